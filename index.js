@@ -1,10 +1,32 @@
 export {
   default as init,
   initSync,
+  Generator,
   Model,
   Tensor,
+  Tokenizer,
 } from "./dist/rten.js";
 
+/**
+ * Return an async iterator over the tokens produced by a `Generator`.
+ *
+ * Control is returned to the event loop after each token, so that the UI can
+ * be updated while generation is in progress.
+ *
+ * @param {import("./dist/rten.js").Generator} generator
+ * @return {AsyncGenerator<number>}
+ */
+export async function* generateTokens(generator) {
+  while (true) {
+    const token = generator.nextToken();
+    if (token === undefined) {
+      return;
+    }
+    yield token;
+    await new Promise((resolve) => setTimeout(resolve, 0));
+  }
+}
+
 /**
  * Return true if the current JS environment supports the SIMD extension for
  * WebAssembly.
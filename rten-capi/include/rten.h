@@ -0,0 +1,122 @@
+/*
+ * C API for RTen.
+ *
+ * See the documentation of the `rten-capi` crate for conventions around
+ * error handling, object lifetimes and buffers.
+ */
+
+#ifndef RTEN_H
+#define RTEN_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Status code returned by fallible functions. */
+typedef enum {
+  RTEN_OK = 0,
+  RTEN_INVALID_ARGUMENT = 1,
+  RTEN_LOAD_FAILED = 2,
+  RTEN_NOT_FOUND = 3,
+  RTEN_RUN_FAILED = 4,
+  RTEN_TOKENIZER_FAILED = 5,
+  RTEN_GENERATOR_FAILED = 6,
+  RTEN_BUFFER_TOO_SMALL = 7,
+  RTEN_PANIC = 8,
+} RtenStatus;
+
+/* Element type of a tensor. */
+typedef enum {
+  RTEN_FLOAT32 = 0,
+  RTEN_INT32 = 1,
+} RtenDType;
+
+typedef struct RtenModel RtenModel;
+typedef struct RtenTensor RtenTensor;
+typedef struct RtenTokenizer RtenTokenizer;
+typedef struct RtenGenerator RtenGenerator;
+
+/* Options for `rten_generator_new`. */
+typedef struct {
+  /* Maximum number of tokens to generate. */
+  size_t max_tokens;
+  /* If non-zero, sample from the `top_k` most likely tokens. Otherwise the
+   * most likely token is always chosen. */
+  size_t top_k;
+  /* Temperature applied to logits when `top_k` is non-zero. */
+  float temperature;
+  /* Tokens which end generation when generated. */
+  const uint32_t *stop_tokens;
+  size_t stop_tokens_len;
+} RtenGeneratorOptions;
+
+/* Return a description of the error from the last failed call on the current
+ * thread, or NULL if the last call succeeded. Valid until the next call. */
+const char *rten_last_error(void);
+
+/* Models */
+
+RtenStatus rten_model_load_file(const char *path, RtenModel **out);
+RtenStatus rten_model_load(const uint8_t *data, size_t len, RtenModel **out);
+void rten_model_free(RtenModel *model);
+RtenStatus rten_model_find_node(const RtenModel *model, const char *name,
+                                size_t *out_id);
+RtenStatus rten_model_input_ids(const RtenModel *model, const size_t **out_ids,
+                                size_t *out_len);
+RtenStatus rten_model_output_ids(const RtenModel *model,
+                                 const size_t **out_ids, size_t *out_len);
+RtenStatus rten_model_run(const RtenModel *model, const size_t *input_ids,
+                          const RtenTensor *const *inputs, size_t n_inputs,
+                          const size_t *output_ids, RtenTensor **outputs,
+                          size_t n_outputs);
+
+/* Tensors */
+
+RtenStatus rten_tensor_from_f32(const size_t *shape, size_t ndim,
+                                const float *data, size_t len,
+                                RtenTensor **out);
+RtenStatus rten_tensor_from_i32(const size_t *shape, size_t ndim,
+                                const int32_t *data, size_t len,
+                                RtenTensor **out);
+void rten_tensor_free(RtenTensor *tensor);
+RtenDType rten_tensor_dtype(const RtenTensor *tensor);
+RtenStatus rten_tensor_shape(const RtenTensor *tensor,
+                             const size_t **out_shape, size_t *out_ndim);
+RtenStatus rten_tensor_data_f32(const RtenTensor *tensor,
+                                const float **out_data, size_t *out_len);
+RtenStatus rten_tensor_data_i32(const RtenTensor *tensor,
+                                const int32_t **out_data, size_t *out_len);
+
+/* Tokenizers */
+
+RtenStatus rten_tokenizer_from_json(const char *json, size_t len,
+                                    RtenTokenizer **out);
+RtenStatus rten_tokenizer_from_file(const char *path, RtenTokenizer **out);
+void rten_tokenizer_free(RtenTokenizer *tokenizer);
+RtenStatus rten_tokenizer_encode(const RtenTokenizer *tokenizer,
+                                 const char *text, size_t text_len,
+                                 uint32_t *buf, size_t capacity,
+                                 size_t *out_len);
+RtenStatus rten_tokenizer_decode(const RtenTokenizer *tokenizer,
+                                 const uint32_t *ids, size_t n_ids, char *buf,
+                                 size_t capacity, size_t *out_len);
+
+/* Generation */
+
+RtenStatus rten_generator_new(const RtenModel *model, const uint32_t *prompt,
+                              size_t prompt_len,
+                              const RtenGeneratorOptions *options,
+                              RtenGenerator **out);
+RtenStatus rten_generator_next(RtenGenerator *generator, uint32_t *out_token,
+                               bool *out_done);
+void rten_generator_free(RtenGenerator *generator);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* RTEN_H */
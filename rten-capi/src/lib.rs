@@ -0,0 +1,1111 @@
+//! C API for embedding RTen in applications written in other languages.
+//!
+//! The API is declared in `include/rten.h`. It exposes models, tensors,
+//! tokenizers and the auto-regressive generation loop from `rten-generate`
+//! using opaque handles.
+//!
+//! ## Conventions
+//!
+//! - Fallible functions return an [RtenStatus]. On failure, a description of
+//!   the error can be obtained using [rten_last_error].
+//! - Objects are created by `rten_<type>_new` or `rten_<type>_load` style
+//!   functions, which return the handle via an out parameter, and must be
+//!   released using the corresponding `rten_<type>_free` function.
+//! - Functions which return variable-length data either return a pointer into
+//!   an object, which is valid until the object is freed, or write into a
+//!   caller-provided buffer. If the buffer is too small,
+//!   [RtenStatus::BufferTooSmall] is returned and the required length is
+//!   written to the length out parameter.
+//! - Panics are caught at the API boundary and reported as
+//!   [RtenStatus::Panic].
+
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::Arc;
+
+use rten::{NodeId, Output};
+use rten_generate::generator::GeneratorItem;
+use rten_generate::model::Model;
+use rten_generate::Generator;
+use rten_tensor::prelude::*;
+use rten_tensor::Tensor;
+use rten_text::tokenizers::{EncodeOptions, Tokenizer};
+
+/// Status code returned by fallible functions.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RtenStatus {
+    /// The operation succeeded.
+    Ok = 0,
+    /// An argument was null or otherwise invalid.
+    InvalidArgument = 1,
+    /// A model or tokenizer failed to load.
+    LoadFailed = 2,
+    /// A model input or output name was not found.
+    NotFound = 3,
+    /// Running the model failed.
+    RunFailed = 4,
+    /// Encoding or decoding text failed.
+    TokenizerFailed = 5,
+    /// A step of the generation loop failed.
+    GeneratorFailed = 6,
+    /// A caller-provided buffer was too small for the result.
+    BufferTooSmall = 7,
+    /// An unexpected internal error occurred.
+    Panic = 8,
+}
+
+/// Element type of a tensor.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RtenDType {
+    Float32 = 0,
+    Int32 = 1,
+}
+
+/// Opaque handle to a loaded model.
+pub struct RtenModel {
+    model: Arc<rten::Model>,
+}
+
+/// Opaque handle to a tensor used as a model input or output.
+///
+/// The elements of tensors created by the API are always contiguous.
+pub struct RtenTensor {
+    output: Output,
+}
+
+/// Opaque handle to a tokenizer.
+pub struct RtenTokenizer {
+    tokenizer: Tokenizer,
+}
+
+/// Opaque handle to the state of an in-progress generation.
+pub struct RtenGenerator {
+    tokens: Box<dyn Iterator<Item = GeneratorItem>>,
+}
+
+/// Options for [rten_generator_new].
+#[repr(C)]
+pub struct RtenGeneratorOptions {
+    /// Maximum number of tokens to generate.
+    pub max_tokens: usize,
+
+    /// If non-zero, sample from the `top_k` most likely tokens. Otherwise the
+    /// most likely token is always chosen.
+    pub top_k: usize,
+
+    /// Temperature applied to logits when `top_k` is non-zero.
+    pub temperature: f32,
+
+    /// Tokens which end generation when generated. May be null if
+    /// `stop_tokens_len` is zero.
+    pub stop_tokens: *const u32,
+    pub stop_tokens_len: usize,
+}
+
+/// Error reported across the API boundary.
+struct Error {
+    status: RtenStatus,
+    message: String,
+}
+
+impl Error {
+    fn new(status: RtenStatus, message: impl ToString) -> Error {
+        Error {
+            status,
+            message: message.to_string(),
+        }
+    }
+
+    fn invalid_arg(message: impl ToString) -> Error {
+        Error::new(RtenStatus::InvalidArgument, message)
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Run the body of an API function, converting errors and panics into a
+/// status code and recording the error message.
+fn ffi_call<F: FnOnce() -> Result<(), Error>>(f: F) -> RtenStatus {
+    let result = catch_unwind(AssertUnwindSafe(f))
+        .unwrap_or_else(|_| Err(Error::new(RtenStatus::Panic, "internal error")));
+    let (status, message) = match result {
+        Ok(()) => (RtenStatus::Ok, None),
+        Err(err) => {
+            // Interior nul bytes are replaced so the message is never lost.
+            let message = CString::new(err.message.replace('\0', " ")).ok();
+            (err.status, message)
+        }
+    };
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+    status
+}
+
+/// Convert a possibly-null pointer to a reference.
+unsafe fn as_ref<'a, T>(ptr: *const T, name: &str) -> Result<&'a T, Error> {
+    ptr.as_ref()
+        .ok_or_else(|| Error::invalid_arg(format!("`{}` is null", name)))
+}
+
+/// Convert a possibly-null pointer to a mutable reference.
+unsafe fn as_mut<'a, T>(ptr: *mut T, name: &str) -> Result<&'a mut T, Error> {
+    ptr.as_mut()
+        .ok_or_else(|| Error::invalid_arg(format!("`{}` is null", name)))
+}
+
+/// Convert a pointer and length to a slice. The pointer may be null if the
+/// length is zero.
+unsafe fn as_slice<'a, T>(ptr: *const T, len: usize, name: &str) -> Result<&'a [T], Error> {
+    if len == 0 {
+        Ok(&[])
+    } else if ptr.is_null() {
+        Err(Error::invalid_arg(format!("`{}` is null", name)))
+    } else {
+        Ok(std::slice::from_raw_parts(ptr, len))
+    }
+}
+
+/// Convert a nul-terminated C string to a `&str`.
+unsafe fn as_str<'a>(ptr: *const c_char, name: &str) -> Result<&'a str, Error> {
+    let cstr = CStr::from_ptr(as_ref(ptr, name)?);
+    cstr.to_str()
+        .map_err(|_| Error::invalid_arg(format!("`{}` is not valid UTF-8", name)))
+}
+
+/// Move `value` to the heap and write the pointer to `out`.
+unsafe fn write_handle<T>(out: *mut *mut T, value: T) -> Result<(), Error> {
+    *as_mut(out, "out")? = Box::into_raw(Box::new(value));
+    Ok(())
+}
+
+/// Copy `src` into the caller-provided buffer `dst` of length `capacity`, and
+/// write the length of `src` to `out_len`.
+unsafe fn write_buffer<T: Copy>(
+    src: &[T],
+    dst: *mut T,
+    capacity: usize,
+    out_len: *mut usize,
+) -> Result<(), Error> {
+    *as_mut(out_len, "out_len")? = src.len();
+    if src.len() > capacity {
+        return Err(Error::new(
+            RtenStatus::BufferTooSmall,
+            format!("buffer needs {} elements but has {}", src.len(), capacity),
+        ));
+    }
+    if !src.is_empty() {
+        if dst.is_null() {
+            return Err(Error::invalid_arg("`buf` is null"));
+        }
+        std::ptr::copy_nonoverlapping(src.as_ptr(), dst, src.len());
+    }
+    Ok(())
+}
+
+/// Return a description of the error from the last failed API call on the
+/// current thread, or null if the last call succeeded.
+///
+/// The returned string is valid until the next API call on this thread.
+#[no_mangle]
+pub extern "C" fn rten_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map(|msg| msg.as_ptr())
+            .unwrap_or(std::ptr::null())
+    })
+}
+
+/// Load a model from a `.rten` file.
+///
+/// # Safety
+///
+/// `path` must be a nul-terminated string and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn rten_model_load_file(
+    path: *const c_char,
+    out: *mut *mut RtenModel,
+) -> RtenStatus {
+    ffi_call(|| {
+        let path = as_str(path, "path")?;
+        let model =
+            rten::Model::load_file(path).map_err(|e| Error::new(RtenStatus::LoadFailed, e))?;
+        write_handle(
+            out,
+            RtenModel {
+                model: Arc::new(model),
+            },
+        )
+    })
+}
+
+/// Load a model from a buffer containing a serialized `.rten` model.
+///
+/// The data is copied, so the buffer can be freed after this returns.
+///
+/// # Safety
+///
+/// `data` must be valid for reads of `len` bytes and `out` must be valid for
+/// writes.
+#[no_mangle]
+pub unsafe extern "C" fn rten_model_load(
+    data: *const u8,
+    len: usize,
+    out: *mut *mut RtenModel,
+) -> RtenStatus {
+    ffi_call(|| {
+        let data = as_slice(data, len, "data")?;
+        let model =
+            rten::Model::load(data.to_vec()).map_err(|e| Error::new(RtenStatus::LoadFailed, e))?;
+        write_handle(
+            out,
+            RtenModel {
+                model: Arc::new(model),
+            },
+        )
+    })
+}
+
+/// Free a model.
+///
+/// Generators created from the model keep it alive until they are freed.
+///
+/// # Safety
+///
+/// `model` must be null or a handle returned by a model loading function
+/// which has not already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn rten_model_free(model: *mut RtenModel) {
+    if !model.is_null() {
+        drop(Box::from_raw(model));
+    }
+}
+
+/// Find the ID of a model input, output or other node by name.
+///
+/// # Safety
+///
+/// `model` must be a valid handle, `name` a nul-terminated string and
+/// `out_id` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn rten_model_find_node(
+    model: *const RtenModel,
+    name: *const c_char,
+    out_id: *mut usize,
+) -> RtenStatus {
+    ffi_call(|| {
+        let model = as_ref(model, "model")?;
+        let name = as_str(name, "name")?;
+        let id = model
+            .model
+            .find_node(name)
+            .ok_or_else(|| Error::new(RtenStatus::NotFound, format!("node not found: {}", name)))?;
+        *as_mut(out_id, "out_id")? = id;
+        Ok(())
+    })
+}
+
+/// Get the IDs of the model's inputs.
+///
+/// The returned array is valid until the model is freed.
+///
+/// # Safety
+///
+/// `model` must be a valid handle and the out parameters valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn rten_model_input_ids(
+    model: *const RtenModel,
+    out_ids: *mut *const usize,
+    out_len: *mut usize,
+) -> RtenStatus {
+    ffi_call(|| {
+        let ids = as_ref(model, "model")?.model.input_ids();
+        *as_mut(out_ids, "out_ids")? = ids.as_ptr();
+        *as_mut(out_len, "out_len")? = ids.len();
+        Ok(())
+    })
+}
+
+/// Get the IDs of the model's outputs.
+///
+/// The returned array is valid until the model is freed.
+///
+/// # Safety
+///
+/// `model` must be a valid handle and the out parameters valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn rten_model_output_ids(
+    model: *const RtenModel,
+    out_ids: *mut *const usize,
+    out_len: *mut usize,
+) -> RtenStatus {
+    ffi_call(|| {
+        let ids = as_ref(model, "model")?.model.output_ids();
+        *as_mut(out_ids, "out_ids")? = ids.as_ptr();
+        *as_mut(out_len, "out_len")? = ids.len();
+        Ok(())
+    })
+}
+
+/// Run a model.
+///
+/// `input_ids` and `inputs` are arrays of length `n_inputs` specifying the
+/// node IDs and values of inputs. `output_ids` and `outputs` are arrays of
+/// length `n_outputs`. On success each entry in `outputs` is set to a new
+/// tensor which must be freed with [rten_tensor_free].
+///
+/// # Safety
+///
+/// All pointers must be valid for reads or writes of the given lengths, and
+/// all tensor handles must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn rten_model_run(
+    model: *const RtenModel,
+    input_ids: *const usize,
+    inputs: *const *const RtenTensor,
+    n_inputs: usize,
+    output_ids: *const usize,
+    outputs: *mut *mut RtenTensor,
+    n_outputs: usize,
+) -> RtenStatus {
+    ffi_call(|| {
+        let model = as_ref(model, "model")?;
+        let input_ids = as_slice(input_ids, n_inputs, "input_ids")?;
+        let input_tensors = as_slice(inputs, n_inputs, "inputs")?;
+        let output_ids: &[NodeId] = as_slice(output_ids, n_outputs, "output_ids")?;
+        if n_outputs > 0 && outputs.is_null() {
+            return Err(Error::invalid_arg("`outputs` is null"));
+        }
+
+        let inputs = input_ids
+            .iter()
+            .zip(input_tensors)
+            .map(|(&id, &tensor)| Ok((id, as_ref(tensor, "inputs")?.output.as_input().into())))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let results = model
+            .model
+            .run(inputs, output_ids, None)
+            .map_err(|e| Error::new(RtenStatus::RunFailed, e))?;
+
+        for (i, output) in results.into_iter().enumerate() {
+            *outputs.add(i) = Box::into_raw(Box::new(RtenTensor::from_output(output)));
+        }
+        Ok(())
+    })
+}
+
+impl RtenTensor {
+    fn from_output(output: Output) -> RtenTensor {
+        let output = match output {
+            Output::FloatTensor(mut t) => {
+                t.make_contiguous();
+                Output::FloatTensor(t)
+            }
+            Output::IntTensor(mut t) => {
+                t.make_contiguous();
+                Output::IntTensor(t)
+            }
+        };
+        RtenTensor { output }
+    }
+}
+
+/// Create a tensor from a shape and elements, which are copied.
+unsafe fn new_tensor<T: Copy>(
+    shape: *const usize,
+    ndim: usize,
+    data: *const T,
+    len: usize,
+) -> Result<Tensor<T>, Error> {
+    let shape = as_slice(shape, ndim, "shape")?;
+    let data = as_slice(data, len, "data")?;
+    Tensor::try_from_data(shape, data.to_vec()).map_err(Error::invalid_arg)
+}
+
+/// Create a float tensor with a given shape from elements in row-major order.
+///
+/// The product of the `ndim` dimensions in `shape` must equal `len`. The data
+/// is copied.
+///
+/// # Safety
+///
+/// `shape` and `data` must be valid for reads of `ndim` and `len` elements
+/// respectively, and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn rten_tensor_from_f32(
+    shape: *const usize,
+    ndim: usize,
+    data: *const f32,
+    len: usize,
+    out: *mut *mut RtenTensor,
+) -> RtenStatus {
+    ffi_call(|| {
+        let tensor = new_tensor(shape, ndim, data, len)?;
+        write_handle(out, RtenTensor::from_output(tensor.into()))
+    })
+}
+
+/// Create an int tensor with a given shape from elements in row-major order.
+///
+/// See [rten_tensor_from_f32].
+///
+/// # Safety
+///
+/// See [rten_tensor_from_f32].
+#[no_mangle]
+pub unsafe extern "C" fn rten_tensor_from_i32(
+    shape: *const usize,
+    ndim: usize,
+    data: *const i32,
+    len: usize,
+    out: *mut *mut RtenTensor,
+) -> RtenStatus {
+    ffi_call(|| {
+        let tensor = new_tensor(shape, ndim, data, len)?;
+        write_handle(out, RtenTensor::from_output(tensor.into()))
+    })
+}
+
+/// Free a tensor.
+///
+/// # Safety
+///
+/// `tensor` must be null or a valid tensor handle which has not already been
+/// freed.
+#[no_mangle]
+pub unsafe extern "C" fn rten_tensor_free(tensor: *mut RtenTensor) {
+    if !tensor.is_null() {
+        drop(Box::from_raw(tensor));
+    }
+}
+
+/// Return the element type of a tensor.
+///
+/// # Safety
+///
+/// `tensor` must be a valid, non-null tensor handle.
+#[no_mangle]
+pub unsafe extern "C" fn rten_tensor_dtype(tensor: *const RtenTensor) -> RtenDType {
+    match (*tensor).output {
+        Output::FloatTensor(_) => RtenDType::Float32,
+        Output::IntTensor(_) => RtenDType::Int32,
+    }
+}
+
+/// Get the shape of a tensor.
+///
+/// The returned array is valid until the tensor is freed.
+///
+/// # Safety
+///
+/// `tensor` must be a valid handle and the out parameters valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn rten_tensor_shape(
+    tensor: *const RtenTensor,
+    out_shape: *mut *const usize,
+    out_ndim: *mut usize,
+) -> RtenStatus {
+    ffi_call(|| {
+        let shape = as_ref(tensor, "tensor")?.output.shape();
+        *as_mut(out_shape, "out_shape")? = shape.as_ptr();
+        *as_mut(out_ndim, "out_ndim")? = shape.len();
+        Ok(())
+    })
+}
+
+/// Get the elements of a float tensor in row-major order.
+///
+/// Returns [RtenStatus::InvalidArgument] if this is not a float tensor. The
+/// returned array is valid until the tensor is freed.
+///
+/// # Safety
+///
+/// `tensor` must be a valid handle and the out parameters valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn rten_tensor_data_f32(
+    tensor: *const RtenTensor,
+    out_data: *mut *const f32,
+    out_len: *mut usize,
+) -> RtenStatus {
+    ffi_call(|| {
+        let Output::FloatTensor(ref t) = as_ref(tensor, "tensor")?.output else {
+            return Err(Error::invalid_arg("tensor is not a float tensor"));
+        };
+        let data = t.data().expect("tensor should be contiguous");
+        *as_mut(out_data, "out_data")? = data.as_ptr();
+        *as_mut(out_len, "out_len")? = data.len();
+        Ok(())
+    })
+}
+
+/// Get the elements of an int tensor in row-major order.
+///
+/// See [rten_tensor_data_f32].
+///
+/// # Safety
+///
+/// See [rten_tensor_data_f32].
+#[no_mangle]
+pub unsafe extern "C" fn rten_tensor_data_i32(
+    tensor: *const RtenTensor,
+    out_data: *mut *const i32,
+    out_len: *mut usize,
+) -> RtenStatus {
+    ffi_call(|| {
+        let Output::IntTensor(ref t) = as_ref(tensor, "tensor")?.output else {
+            return Err(Error::invalid_arg("tensor is not an int tensor"));
+        };
+        let data = t.data().expect("tensor should be contiguous");
+        *as_mut(out_data, "out_data")? = data.as_ptr();
+        *as_mut(out_len, "out_len")? = data.len();
+        Ok(())
+    })
+}
+
+/// Create a tokenizer from the UTF-8 contents of a `tokenizer.json` file in
+/// the Hugging Face Tokenizers format.
+///
+/// # Safety
+///
+/// `json` must be valid for reads of `len` bytes and `out` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn rten_tokenizer_from_json(
+    json: *const c_char,
+    len: usize,
+    out: *mut *mut RtenTokenizer,
+) -> RtenStatus {
+    ffi_call(|| {
+        let json = as_slice(json as *const u8, len, "json")?;
+        let json = std::str::from_utf8(json)
+            .map_err(|_| Error::invalid_arg("`json` is not valid UTF-8"))?;
+        let tokenizer =
+            Tokenizer::from_json(json).map_err(|e| Error::new(RtenStatus::LoadFailed, e))?;
+        write_handle(out, RtenTokenizer { tokenizer })
+    })
+}
+
+/// Load a tokenizer from a `tokenizer.json` file.
+///
+/// # Safety
+///
+/// `path` must be a nul-terminated string and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn rten_tokenizer_from_file(
+    path: *const c_char,
+    out: *mut *mut RtenTokenizer,
+) -> RtenStatus {
+    ffi_call(|| {
+        let path = as_str(path, "path")?;
+        let json =
+            std::fs::read_to_string(path).map_err(|e| Error::new(RtenStatus::LoadFailed, e))?;
+        let tokenizer =
+            Tokenizer::from_json(&json).map_err(|e| Error::new(RtenStatus::LoadFailed, e))?;
+        write_handle(out, RtenTokenizer { tokenizer })
+    })
+}
+
+/// Free a tokenizer.
+///
+/// # Safety
+///
+/// `tokenizer` must be null or a valid tokenizer handle which has not already
+/// been freed.
+#[no_mangle]
+pub unsafe extern "C" fn rten_tokenizer_free(tokenizer: *mut RtenTokenizer) {
+    if !tokenizer.is_null() {
+        drop(Box::from_raw(tokenizer));
+    }
+}
+
+/// Encode UTF-8 text into token IDs.
+///
+/// The token IDs are written to `buf`, which has space for `capacity` IDs,
+/// and the number of IDs is written to `out_len`.
+///
+/// # Safety
+///
+/// `tokenizer` must be a valid handle, `text` valid for reads of `text_len`
+/// bytes, `buf` valid for writes of `capacity` elements and `out_len` valid
+/// for writes.
+#[no_mangle]
+pub unsafe extern "C" fn rten_tokenizer_encode(
+    tokenizer: *const RtenTokenizer,
+    text: *const c_char,
+    text_len: usize,
+    buf: *mut u32,
+    capacity: usize,
+    out_len: *mut usize,
+) -> RtenStatus {
+    ffi_call(|| {
+        let tokenizer = &as_ref(tokenizer, "tokenizer")?.tokenizer;
+        let text = as_slice(text as *const u8, text_len, "text")?;
+        let text = std::str::from_utf8(text)
+            .map_err(|_| Error::invalid_arg("`text` is not valid UTF-8"))?;
+        let encoded = tokenizer
+            .encode(text.into(), EncodeOptions::default())
+            .map_err(|e| Error::new(RtenStatus::TokenizerFailed, e))?;
+        write_buffer(encoded.token_ids(), buf, capacity, out_len)
+    })
+}
+
+/// Decode token IDs into UTF-8 text.
+///
+/// The text is written to `buf`, which has space for `capacity` bytes, and
+/// the length of the text in bytes is written to `out_len`. The text is not
+/// nul-terminated.
+///
+/// # Safety
+///
+/// `tokenizer` must be a valid handle, `ids` valid for reads of `n_ids`
+/// elements, `buf` valid for writes of `capacity` bytes and `out_len` valid
+/// for writes.
+#[no_mangle]
+pub unsafe extern "C" fn rten_tokenizer_decode(
+    tokenizer: *const RtenTokenizer,
+    ids: *const u32,
+    n_ids: usize,
+    buf: *mut c_char,
+    capacity: usize,
+    out_len: *mut usize,
+) -> RtenStatus {
+    ffi_call(|| {
+        let tokenizer = &as_ref(tokenizer, "tokenizer")?.tokenizer;
+        let ids = as_slice(ids, n_ids, "ids")?;
+        let text = tokenizer
+            .decode(ids)
+            .map_err(|e| Error::new(RtenStatus::TokenizerFailed, e))?;
+        write_buffer(text.as_bytes(), buf as *mut u8, capacity, out_len)
+    })
+}
+
+/// Create a generator which produces tokens following `prompt` using a
+/// model.
+///
+/// The model is expected to follow the input and output naming conventions
+/// described in the `rten-generate` crate. The generator keeps the model
+/// alive until it is freed.
+///
+/// # Safety
+///
+/// `model` must be a valid handle, `prompt` valid for reads of `prompt_len`
+/// elements, `options` a valid pointer and `out` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn rten_generator_new(
+    model: *const RtenModel,
+    prompt: *const u32,
+    prompt_len: usize,
+    options: *const RtenGeneratorOptions,
+    out: *mut *mut RtenGenerator,
+) -> RtenStatus {
+    ffi_call(|| {
+        let model: Arc<dyn Model> = as_ref(model, "model")?.model.clone();
+        let prompt = as_slice(prompt, prompt_len, "prompt")?;
+        let options = as_ref(options, "options")?;
+        let stop_tokens = as_slice(
+            options.stop_tokens,
+            options.stop_tokens_len,
+            "options.stop_tokens",
+        )?
+        .to_vec();
+
+        let top_k = (options.top_k > 0).then_some(options.top_k);
+        let tokens = Generator::from_shared_model(model)
+            .map_err(|e| Error::new(RtenStatus::GeneratorFailed, e))?
+            .with_prompt(prompt)
+            .sample_tokens(options.max_tokens, top_k, options.temperature, stop_tokens);
+
+        write_handle(
+            out,
+            RtenGenerator {
+                tokens: Box::new(tokens),
+            },
+        )
+    })
+}
+
+/// Run one step of generation.
+///
+/// On success, `out_done` is set to indicate whether generation has finished.
+/// If it has not, the next token ID is written to `out_token`.
+///
+/// # Safety
+///
+/// `generator` must be a valid handle and the out parameters valid for
+/// writes.
+#[no_mangle]
+pub unsafe extern "C" fn rten_generator_next(
+    generator: *mut RtenGenerator,
+    out_token: *mut u32,
+    out_done: *mut bool,
+) -> RtenStatus {
+    ffi_call(|| {
+        let generator = as_mut(generator, "generator")?;
+        let out_token = as_mut(out_token, "out_token")?;
+        let out_done = as_mut(out_done, "out_done")?;
+        match generator.tokens.next() {
+            Some(Ok(token)) => {
+                *out_token = token;
+                *out_done = false;
+            }
+            Some(Err(err)) => return Err(Error::new(RtenStatus::GeneratorFailed, err)),
+            None => *out_done = true,
+        }
+        Ok(())
+    })
+}
+
+/// Free a generator.
+///
+/// # Safety
+///
+/// `generator` must be null or a valid generator handle which has not
+/// already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn rten_generator_free(generator: *mut RtenGenerator) {
+    if !generator.is_null() {
+        drop(Box::from_raw(generator));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{c_char, CStr};
+    use std::ptr;
+
+    use rten_generate::test_models::{counting_model, relu_model};
+
+    use super::{
+        rten_generator_free, rten_generator_new, rten_generator_next, rten_last_error,
+        rten_model_find_node, rten_model_free, rten_model_load, rten_model_run,
+        rten_tensor_data_f32, rten_tensor_data_i32, rten_tensor_free, rten_tensor_from_f32,
+        rten_tensor_from_i32, rten_tensor_shape, rten_tokenizer_decode, rten_tokenizer_encode,
+        rten_tokenizer_free, rten_tokenizer_from_json, RtenGeneratorOptions, RtenModel, RtenStatus,
+    };
+
+    const TOKENIZER_JSON: &str = r###"{
+        "model": {
+            "type": "WordPiece",
+            "vocab": { "foo": 1, "##bar": 2, "[CLS]": 3, "[SEP]": 4 }
+        }
+    }"###;
+
+    fn load_model(data: &[u8]) -> *mut RtenModel {
+        let mut model = ptr::null_mut();
+        let status = unsafe { rten_model_load(data.as_ptr(), data.len(), &mut model) };
+        assert_eq!(status, RtenStatus::Ok);
+        model
+    }
+
+    fn last_error() -> Option<String> {
+        let msg = rten_last_error();
+        if msg.is_null() {
+            None
+        } else {
+            Some(unsafe { CStr::from_ptr(msg) }.to_str().unwrap().to_string())
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut model = ptr::null_mut();
+        let data = [1u8, 2, 3];
+        let status = unsafe { rten_model_load(data.as_ptr(), data.len(), &mut model) };
+        assert_eq!(status, RtenStatus::LoadFailed);
+        assert!(model.is_null());
+        assert!(last_error().is_some());
+
+        let status = unsafe { rten_model_load(ptr::null(), 10, &mut model) };
+        assert_eq!(status, RtenStatus::InvalidArgument);
+        assert_eq!(last_error().as_deref(), Some("`data` is null"));
+    }
+
+    #[test]
+    fn test_tensor() {
+        let shape = [2, 3];
+        let data = [1., 2., 3., 4., 5., 6.];
+        let mut tensor = ptr::null_mut();
+        let status = unsafe {
+            rten_tensor_from_f32(
+                shape.as_ptr(),
+                shape.len(),
+                data.as_ptr(),
+                data.len(),
+                &mut tensor,
+            )
+        };
+        assert_eq!(status, RtenStatus::Ok);
+        assert!(last_error().is_none());
+
+        let (mut shape_ptr, mut ndim) = (ptr::null(), 0);
+        let status = unsafe { rten_tensor_shape(tensor, &mut shape_ptr, &mut ndim) };
+        assert_eq!(status, RtenStatus::Ok);
+        assert_eq!(
+            unsafe { std::slice::from_raw_parts(shape_ptr, ndim) },
+            shape
+        );
+
+        let (mut data_ptr, mut len) = (ptr::null(), 0);
+        let status = unsafe { rten_tensor_data_f32(tensor, &mut data_ptr, &mut len) };
+        assert_eq!(status, RtenStatus::Ok);
+        assert_eq!(unsafe { std::slice::from_raw_parts(data_ptr, len) }, data);
+
+        let mut int_data = ptr::null();
+        let status = unsafe { rten_tensor_data_i32(tensor, &mut int_data, &mut len) };
+        assert_eq!(status, RtenStatus::InvalidArgument);
+
+        unsafe { rten_tensor_free(tensor) };
+
+        // Mismatched shape and data length.
+        let status =
+            unsafe { rten_tensor_from_f32(shape.as_ptr(), 2, data.as_ptr(), 5, &mut tensor) };
+        assert_eq!(status, RtenStatus::InvalidArgument);
+    }
+
+    #[test]
+    fn test_tokenizer() {
+        let mut tokenizer = ptr::null_mut();
+        let status = unsafe {
+            rten_tokenizer_from_json(
+                TOKENIZER_JSON.as_ptr() as *const c_char,
+                TOKENIZER_JSON.len(),
+                &mut tokenizer,
+            )
+        };
+        assert_eq!(status, RtenStatus::Ok);
+
+        let text = "foobar";
+        let mut ids = [0u32; 4];
+        let mut len = 0;
+
+        // Buffer which is too small reports the required length.
+        let status = unsafe {
+            rten_tokenizer_encode(
+                tokenizer,
+                text.as_ptr() as *const c_char,
+                text.len(),
+                ids.as_mut_ptr(),
+                2,
+                &mut len,
+            )
+        };
+        assert_eq!(status, RtenStatus::BufferTooSmall);
+        assert_eq!(len, 4);
+
+        let status = unsafe {
+            rten_tokenizer_encode(
+                tokenizer,
+                text.as_ptr() as *const c_char,
+                text.len(),
+                ids.as_mut_ptr(),
+                ids.len(),
+                &mut len,
+            )
+        };
+        assert_eq!(status, RtenStatus::Ok);
+        assert_eq!(ids, [3, 1, 2, 4]);
+
+        let mut buf = [0 as c_char; 16];
+        let status = unsafe {
+            rten_tokenizer_decode(
+                tokenizer,
+                ids[1..2].as_ptr(),
+                1,
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut len,
+            )
+        };
+        assert_eq!(status, RtenStatus::Ok);
+        let decoded: Vec<u8> = buf[..len].iter().map(|&c| c as u8).collect();
+        assert_eq!(decoded, b"foo");
+
+        unsafe { rten_tokenizer_free(tokenizer) };
+    }
+
+    #[test]
+    fn test_tokenizer_decoder() {
+        // Tokenizer whose decoder converts byte tokens and `▁` markers back
+        // to the original text.
+        let json = r#"{
+            "model": {
+                "type": "WordLevel",
+                "vocab": { "▁hello": 0, "▁world": 1, "<0x21>": 2 }
+            },
+            "decoder": {
+                "type": "Sequence",
+                "decoders": [
+                    { "type": "ByteFallback" },
+                    { "type": "Metaspace", "replacement": "▁", "prepend_scheme": "always" }
+                ]
+            }
+        }"#;
+        let mut tokenizer = ptr::null_mut();
+        let status = unsafe {
+            rten_tokenizer_from_json(json.as_ptr() as *const c_char, json.len(), &mut tokenizer)
+        };
+        assert_eq!(status, RtenStatus::Ok);
+
+        let ids = [0, 1, 2];
+        let mut buf = [0 as c_char; 32];
+        let mut len = 0;
+        let status = unsafe {
+            rten_tokenizer_decode(
+                tokenizer,
+                ids.as_ptr(),
+                ids.len(),
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut len,
+            )
+        };
+        assert_eq!(status, RtenStatus::Ok);
+        let decoded: Vec<u8> = buf[..len].iter().map(|&c| c as u8).collect();
+        assert_eq!(decoded, b"hello world!");
+
+        unsafe { rten_tokenizer_free(tokenizer) };
+    }
+
+    #[test]
+    fn test_model_run() {
+        let model = load_model(&relu_model());
+
+        let (mut input_id, mut output_id) = (0, 0);
+        let status = unsafe { rten_model_find_node(model, c"input".as_ptr(), &mut input_id) };
+        assert_eq!(status, RtenStatus::Ok);
+        let status = unsafe { rten_model_find_node(model, c"output".as_ptr(), &mut output_id) };
+        assert_eq!(status, RtenStatus::Ok);
+
+        let shape = [2, 2];
+        let data = [1., -2., 3., -4.];
+        let mut input = ptr::null_mut();
+        let status = unsafe {
+            rten_tensor_from_f32(
+                shape.as_ptr(),
+                shape.len(),
+                data.as_ptr(),
+                data.len(),
+                &mut input,
+            )
+        };
+        assert_eq!(status, RtenStatus::Ok);
+
+        let mut output = ptr::null_mut();
+        let status = unsafe {
+            rten_model_run(
+                model,
+                &input_id,
+                &input.cast_const(),
+                1,
+                &output_id,
+                &mut output,
+                1,
+            )
+        };
+        assert_eq!(status, RtenStatus::Ok);
+
+        let (mut data_ptr, mut len) = (ptr::null(), 0);
+        let status = unsafe { rten_tensor_data_f32(output, &mut data_ptr, &mut len) };
+        assert_eq!(status, RtenStatus::Ok);
+        assert_eq!(
+            unsafe { std::slice::from_raw_parts(data_ptr, len) },
+            [1., 0., 3., 0.]
+        );
+        unsafe { rten_tensor_free(output) };
+
+        // Input with a type the model does not support.
+        let int_data = [1, 2, 3, 4];
+        let mut int_input = ptr::null_mut();
+        let status = unsafe {
+            rten_tensor_from_i32(
+                shape.as_ptr(),
+                shape.len(),
+                int_data.as_ptr(),
+                int_data.len(),
+                &mut int_input,
+            )
+        };
+        assert_eq!(status, RtenStatus::Ok);
+        let mut output = ptr::null_mut();
+        let status = unsafe {
+            rten_model_run(
+                model,
+                &input_id,
+                &int_input.cast_const(),
+                1,
+                &output_id,
+                &mut output,
+                1,
+            )
+        };
+        assert_eq!(status, RtenStatus::RunFailed);
+        assert!(output.is_null());
+
+        unsafe {
+            rten_tensor_free(input);
+            rten_tensor_free(int_input);
+            rten_model_free(model);
+        }
+    }
+
+    #[test]
+    fn test_generator() {
+        let model = load_model(&counting_model(10));
+        let prompt = [1, 2];
+        let stop_tokens = [6];
+        let options = RtenGeneratorOptions {
+            max_tokens: 5,
+            top_k: 0,
+            temperature: 1.0,
+            stop_tokens: stop_tokens.as_ptr(),
+            stop_tokens_len: stop_tokens.len(),
+        };
+
+        let mut generator = ptr::null_mut();
+        let status = unsafe {
+            rten_generator_new(
+                model,
+                prompt.as_ptr(),
+                prompt.len(),
+                &options,
+                &mut generator,
+            )
+        };
+        assert_eq!(status, RtenStatus::Ok);
+
+        // The generator keeps the model alive after the model handle is freed.
+        unsafe { rten_model_free(model) };
+
+        let mut tokens = Vec::new();
+        loop {
+            let (mut token, mut done) = (0, false);
+            let status = unsafe { rten_generator_next(generator, &mut token, &mut done) };
+            assert_eq!(status, RtenStatus::Ok);
+            if done {
+                break;
+            }
+            tokens.push(token);
+        }
+        assert_eq!(tokens, [3, 4, 5]);
+        unsafe { rten_generator_free(generator) };
+
+        // Model which does not have the inputs and outputs of a decoder.
+        let model = load_model(&relu_model());
+        let mut generator = ptr::null_mut();
+        let status = unsafe {
+            rten_generator_new(
+                model,
+                prompt.as_ptr(),
+                prompt.len(),
+                &options,
+                &mut generator,
+            )
+        };
+        assert_eq!(status, RtenStatus::GeneratorFailed);
+        assert!(generator.is_null());
+        unsafe { rten_model_free(model) };
+    }
+}
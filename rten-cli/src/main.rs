@@ -1,12 +1,36 @@
 use std::collections::VecDeque;
+use std::env;
 use std::error::Error;
-use std::time::Instant;
+use std::process;
+use std::time::{Duration, Instant};
 
 use rten::{Dimension, InputOrOutput, Model, ModelMetadata, NodeId, Output, RunOptions};
 use rten_tensor::prelude::*;
+use rten_tensor::test_util::{diff_report, ApproxEq};
 use rten_tensor::Tensor;
 
+mod npy;
+
+/// Environment variable set when `bench` re-runs itself with a different
+/// thread count.
+const BENCH_CHILD_VAR: &str = "RTEN_CLI_BENCH_CHILD";
+
+/// Action performed by the CLI.
+#[derive(Copy, Clone, PartialEq)]
+enum Command {
+    /// Print a summary of the model and run it.
+    Run,
+
+    /// Measure model execution time.
+    Bench,
+
+    /// Compare model outputs against reference values.
+    Check,
+}
+
 struct Args {
+    command: Command,
+
     /// Model file to load.
     model: String,
 
@@ -22,8 +46,26 @@ struct Args {
     /// Sizes for dynamic dimensions of inputs.
     input_sizes: Vec<DimSize>,
 
-    /// Number of times to run model.
-    n_iters: u32,
+    /// Number of times to run model. The default depends on the command.
+    n_iters: Option<u32>,
+
+    /// Number of untimed runs before timed runs in `bench`.
+    warmup: u32,
+
+    /// Thread counts to benchmark with.
+    threads: Vec<usize>,
+
+    /// Input values to load from `.npy` files, as `(input_name, path)`.
+    inputs: Vec<(String, String)>,
+
+    /// Reference values for `check`, as `(node_name, path)`.
+    references: Vec<(String, String)>,
+
+    /// Relative tolerance for `check`.
+    rtol: f32,
+
+    /// Absolute tolerance for `check`.
+    atol: f32,
 }
 
 /// Specifies the size for a dynamic input dimension.
@@ -94,26 +136,88 @@ impl DimSize {
     }
 }
 
+/// Parse a `name=path` argument.
+fn parse_named_path(spec: &str) -> Result<(String, String), lexopt::Error> {
+    let (name, path) = spec
+        .split_once('=')
+        .ok_or_else(|| format!("Invalid value \"{}\". Expected name=path", spec))?;
+    Ok((name.to_string(), path.to_string()))
+}
+
 fn parse_args() -> Result<Args, lexopt::Error> {
     use lexopt::prelude::*;
 
     let mut values = VecDeque::new();
 
-    let mut n_iters = 1;
+    let mut command = None;
+    let mut n_iters = None;
     let mut quiet = false;
     let mut timing = false;
     let mut verbose = false;
     let mut input_sizes = Vec::new();
+    let mut warmup = 1;
+    let mut threads = Vec::new();
+    let mut inputs = Vec::new();
+    let mut references = Vec::new();
+    let mut rtol = 1e-4;
+    let mut atol = 1e-5;
 
     let mut parser = lexopt::Parser::from_env();
     while let Some(arg) = parser.next()? {
         match arg {
-            Value(val) => values.push_back(val.string()?),
+            Value(val) => {
+                let val = val.string()?;
+                match val.as_str() {
+                    "bench" if command.is_none() && values.is_empty() => {
+                        command = Some(Command::Bench)
+                    }
+                    "check" if command.is_none() && values.is_empty() => {
+                        command = Some(Command::Check)
+                    }
+                    _ => values.push_back(val),
+                }
+            }
             Short('n') | Long("n_iters") => {
                 let value = parser.value()?.string()?;
-                n_iters = value
+                n_iters = Some(
+                    value
+                        .parse()
+                        .map_err(|_| "Unable to parse `n_iters`".to_string())?,
+                );
+            }
+            Long("warmup") => {
+                let value = parser.value()?.string()?;
+                warmup = value
                     .parse()
-                    .map_err(|_| "Unable to parse `n_iters`".to_string())?;
+                    .map_err(|_| "Unable to parse `warmup`".to_string())?;
+            }
+            Long("threads") => {
+                let value = parser.value()?.string()?;
+                threads = value
+                    .split(',')
+                    .map(|n| n.trim().parse())
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| "Unable to parse `threads`".to_string())?;
+            }
+            Short('i') | Long("input") => {
+                let value = parser.value()?.string()?;
+                inputs.push(parse_named_path(&value)?);
+            }
+            Short('r') | Long("reference") => {
+                let value = parser.value()?.string()?;
+                references.push(parse_named_path(&value)?);
+            }
+            Long("rtol") => {
+                let value = parser.value()?.string()?;
+                rtol = value
+                    .parse()
+                    .map_err(|_| "Unable to parse `rtol`".to_string())?;
+            }
+            Long("atol") => {
+                let value = parser.value()?.string()?;
+                atol = value
+                    .parse()
+                    .map_err(|_| "Unable to parse `atol`".to_string())?;
             }
             Short('q') | Long("quiet") => quiet = true,
             Short('v') | Long("verbose") => verbose = true,
@@ -130,9 +234,14 @@ fn parse_args() -> Result<Args, lexopt::Error> {
             }
             Short('h') | Long("help") => {
                 println!(
-                    "Inspect and run RTen models.
+                    "Inspect, run, benchmark and check RTen models.
 
-Usage: {bin_name} [OPTIONS] <model>
+Usage: {bin_name} [COMMAND] [OPTIONS] <model>
+
+Commands:
+  (none)  Print a summary of the model and run it
+  bench   Measure model execution time
+  check   Compare model outputs against reference values
 
 Args:
   <model>
@@ -140,12 +249,18 @@ Args:
 
 Options:
   -h, --help     Print help
+  -i, --input <name=path>
+                 Load the value for a model input from a `.npy` file. Inputs
+                 which are not specified are generated randomly
+
   -n, --n_iters <n>
-                 Number of times to evaluate model
+                 Number of times to evaluate model. Defaults to 1, or 10 for
+                 `bench`
 
   -q, --quiet    Run model and don't produce other output
 
-  -t, --timing   Output timing info
+  -t, --timing   Output timing info. For `bench`, this reports per-operator
+                 timings for the last run
 
   -s, --size <spec>
                  Specify size for a dynamic dimension in the form `dim_name=size`
@@ -153,6 +268,19 @@ Options:
 
   -v, --verbose  Enable verbose logging
   -V, --version  Display RTen version
+
+Bench options:
+  --threads <n,...>
+                 Comma-separated list of thread counts to benchmark with
+
+  --warmup <n>   Number of untimed runs before timing. Defaults to 1
+
+Check options:
+  -r, --reference <name=path>
+                 Compare the value of a model node against a `.npy` file
+
+  --rtol <tol>   Relative tolerance. Defaults to 1e-4
+  --atol <tol>   Absolute tolerance. Defaults to 1e-5
 ",
                     bin_name = parser.bin_name().unwrap_or("rten")
                 );
@@ -163,14 +291,25 @@ Options:
     }
 
     let model = values.pop_front().ok_or("missing `<model>` arg")?;
+    let command = command.unwrap_or(Command::Run);
+    if command == Command::Check && references.is_empty() {
+        return Err("`check` requires at least one `--reference`".into());
+    }
 
     Ok(Args {
+        command,
         model,
         n_iters,
         quiet,
         timing,
         verbose,
         input_sizes,
+        warmup,
+        threads,
+        inputs,
+        references,
+        rtol,
+        atol,
     })
 }
 
@@ -200,17 +339,17 @@ fn print_metadata(metadata: &ModelMetadata) {
     print_field("Run URL", metadata.run_url());
 }
 
-/// Generate random inputs for `model` using shape metadata and heuristics,
-/// run it, and print details of the output.
+/// Generate inputs for `model` using shape metadata and heuristics.
 ///
-/// `dim_sizes` specifies the sizes for input dimensions with dynamic sizes.
-fn run_with_random_input(
+/// `provided` contains values for inputs which should be used instead of
+/// generating them. `dim_sizes` specifies the sizes for input dimensions with
+/// dynamic sizes.
+fn generate_inputs(
     model: &Model,
     dim_sizes: &[DimSize],
-    run_opts: RunOptions,
-    n_iters: u32,
+    mut provided: Vec<(NodeId, Output)>,
     quiet: bool,
-) -> Result<(), Box<dyn Error>> {
+) -> Result<Vec<(NodeId, Output)>, Box<dyn Error>> {
     let mut rng = fastrand::Rng::new();
 
     // Generate random model inputs. The `Output` type here is used as an
@@ -218,6 +357,11 @@ fn run_with_random_input(
     let inputs: Vec<(NodeId, Output)> = model.input_ids().iter().copied().try_fold(
         Vec::<(NodeId, Output)>::new(),
         |mut inputs, id| {
+            if let Some(pos) = provided.iter().position(|(input_id, _)| *input_id == id) {
+                inputs.push(provided.remove(pos));
+                return Ok(inputs);
+            }
+
             let info = model.node_info(id).ok_or("Unable to get input info")?;
             let name = info.name().unwrap_or("(unnamed input)");
             let shape = info
@@ -272,23 +416,57 @@ fn run_with_random_input(
         },
     )?;
 
+    if !quiet {
+        for (id, input) in inputs.iter() {
+            println!(
+                "  Input \"{}\" shape {:?}",
+                node_name(model, *id),
+                input.shape()
+            );
+        }
+    }
+
+    Ok(inputs)
+}
+
+/// Load values for model inputs from `(input_name, path)` pairs.
+fn load_inputs(
+    model: &Model,
+    inputs: &[(String, String)],
+) -> Result<Vec<(NodeId, Output)>, Box<dyn Error>> {
+    inputs
+        .iter()
+        .map(|(name, path)| {
+            let id = model
+                .find_node(name)
+                .ok_or_else(|| format!("Model has no input named \"{}\"", name))?;
+            Ok((id, npy::read_npy(path)?))
+        })
+        .collect()
+}
+
+/// Return the name of a node, or a placeholder if it is unnamed.
+fn node_name(model: &Model, id: NodeId) -> String {
+    model
+        .node_info(id)
+        .and_then(|ni| ni.name().map(|n| n.to_string()))
+        .unwrap_or("(unnamed)".to_string())
+}
+
+/// Run `model` with the given inputs and print details of the output.
+fn run_model(
+    model: &Model,
+    inputs: &[(NodeId, Output)],
+    run_opts: RunOptions,
+    n_iters: u32,
+    quiet: bool,
+) -> Result<(), Box<dyn Error>> {
     // Convert inputs from `Output` (owned) to `Input` (view).
     let inputs: Vec<(NodeId, InputOrOutput)> = inputs
         .iter()
         .map(|(id, output)| (*id, InputOrOutput::from(output)))
         .collect();
 
-    if !quiet {
-        for (id, input) in inputs.iter() {
-            let info = model.node_info(*id);
-            let name = info
-                .as_ref()
-                .and_then(|ni| ni.name())
-                .unwrap_or("(unnamed)");
-            println!("  Input \"{name}\" generated shape {:?}", input.shape());
-        }
-    }
-
     // Run model and summarize outputs.
     if !quiet {
         println!();
@@ -317,25 +495,15 @@ fn run_with_random_input(
         println!();
     }
 
-    let output_names: Vec<String> = model
-        .output_ids()
-        .iter()
-        .map(|id| {
-            model
-                .node_info(*id)
-                .and_then(|ni| ni.name().map(|n| n.to_string()))
-                .unwrap_or("(unnamed)".to_string())
-        })
-        .collect();
-
     if !quiet {
-        for (i, (output, name)) in outputs.iter().zip(output_names).enumerate() {
+        for (i, (output, &id)) in outputs.iter().zip(model.output_ids()).enumerate() {
             let dtype = match output {
                 Output::FloatTensor(_) => "f32",
                 Output::IntTensor(_) => "i32",
             };
             println!(
-                "  Output {i} \"{name}\" data type {} shape: {:?}",
+                "  Output {i} \"{}\" data type {} shape: {:?}",
+                node_name(model, id),
                 dtype,
                 output.shape()
             );
@@ -345,6 +513,155 @@ fn run_with_random_input(
     Ok(())
 }
 
+/// Run `model` repeatedly and report statistics about execution time.
+///
+/// If `threads` is non-empty, the benchmark is run once for each thread
+/// count, by re-running this process with `RTEN_NUM_THREADS` set. This is
+/// needed because the size of RTen's thread pool is fixed on first use.
+fn bench(
+    model: &Model,
+    inputs: &[(NodeId, Output)],
+    threads: &[usize],
+    run_opts: RunOptions,
+    warmup: u32,
+    n_iters: u32,
+) -> Result<(), Box<dyn Error>> {
+    if !threads.is_empty() && env::var_os(BENCH_CHILD_VAR).is_none() {
+        let exe = env::current_exe()?;
+        for &n_threads in threads {
+            let status = process::Command::new(&exe)
+                .args(env::args_os().skip(1))
+                .env("RTEN_NUM_THREADS", n_threads.to_string())
+                .env(BENCH_CHILD_VAR, "1")
+                .status()?;
+            if !status.success() {
+                return Err(format!("Benchmark with {} threads failed", n_threads).into());
+            }
+        }
+        return Ok(());
+    }
+
+    let inputs: Vec<(NodeId, InputOrOutput)> = inputs
+        .iter()
+        .map(|(id, output)| (*id, InputOrOutput::from(output)))
+        .collect();
+
+    for _ in 0..warmup {
+        model.run(inputs.clone(), model.output_ids(), None)?;
+    }
+
+    let n_iters = n_iters.max(1);
+    let mut durations = Vec::with_capacity(n_iters as usize);
+    for i in 0..n_iters {
+        // Only report operator timings for the last run, to avoid flooding
+        // the output.
+        let mut opts = run_opts.clone();
+        opts.timing = run_opts.timing && i == n_iters - 1;
+
+        let start = Instant::now();
+        model.run(inputs.clone(), model.output_ids(), Some(opts))?;
+        durations.push(start.elapsed());
+    }
+    durations.sort();
+
+    let to_ms = |d: Duration| d.as_secs_f64() * 1000.;
+    let mean = durations.iter().sum::<Duration>() / n_iters;
+    let median = durations[durations.len() / 2];
+    let threads = env::var("RTEN_NUM_THREADS").unwrap_or("default".to_string());
+
+    println!(
+        "Threads: {} Runs: {} Min: {:.2}ms Mean: {:.2}ms Median: {:.2}ms Max: {:.2}ms",
+        threads,
+        n_iters,
+        to_ms(durations[0]),
+        to_ms(mean),
+        to_ms(median),
+        to_ms(durations[durations.len() - 1]),
+    );
+
+    Ok(())
+}
+
+/// Convert a tensor to `f32` values for comparison against a reference.
+fn float_tensor(output: &Output) -> Tensor<f32> {
+    match output {
+        Output::FloatTensor(t) => t.to_tensor(),
+        Output::IntTensor(t) => t.map(|&x| x as f32),
+    }
+}
+
+/// Run `model` and compare the values of nodes against references loaded
+/// from `.npy` files.
+fn check(
+    model: &Model,
+    inputs: &[(NodeId, Output)],
+    references: &[(String, String)],
+    run_opts: RunOptions,
+    rtol: f32,
+    atol: f32,
+) -> Result<(), Box<dyn Error>> {
+    let node_ids = references
+        .iter()
+        .map(|(name, _)| {
+            model
+                .find_node(name)
+                .ok_or_else(|| format!("Model has no node named \"{}\"", name))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let inputs: Vec<(NodeId, InputOrOutput)> = inputs
+        .iter()
+        .map(|(id, output)| (*id, InputOrOutput::from(output)))
+        .collect();
+    let outputs = model.run(inputs, &node_ids, Some(run_opts))?;
+
+    let mut n_failed = 0;
+    for ((name, path), output) in references.iter().zip(outputs) {
+        let expected = npy::read_npy(path)?;
+        if output.shape() != expected.shape() {
+            println!(
+                "  FAIL \"{}\": shape {:?} does not match reference shape {:?}",
+                name,
+                output.shape(),
+                expected.shape()
+            );
+            n_failed += 1;
+            continue;
+        }
+
+        let (actual, expected) = (float_tensor(&output), float_tensor(&expected));
+        let mismatches = actual
+            .iter()
+            .zip(expected.iter())
+            .filter(|(x, y)| !x.approx_eq_with_atol_rtol(y, atol, rtol))
+            .count();
+        let diff = diff_report(&actual, &expected)?;
+        let status = if mismatches == 0 { "OK" } else { "FAIL" };
+        println!(
+            "  {} \"{}\": {}/{} mismatches, {}",
+            status,
+            name,
+            mismatches,
+            actual.len(),
+            diff
+        );
+        if mismatches > 0 {
+            n_failed += 1;
+        }
+    }
+
+    if n_failed > 0 {
+        return Err(format!(
+            "{} of {} nodes did not match their references",
+            n_failed,
+            references.len()
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
 /// Format an input or output shape as a `[dim0, dim1, ...]` string, where each
 /// dimension is represented by its fixed size or symbolic name.
 fn format_shape(shape: &[Dimension]) -> String {
@@ -383,46 +700,90 @@ fn print_input_output_list(model: &Model, node_ids: &[NodeId]) {
 /// cargo run -p rten-cli --release output.rten
 /// ```
 ///
+/// The `bench` command measures execution time, optionally across several
+/// thread counts, and the `check` command compares outputs against reference
+/// values saved from another runtime as `.npy` files:
+///
+/// ```
+/// cargo run -p rten-cli --release bench --threads 1,2,4 output.rten
+/// cargo run -p rten-cli --release check -i input=input.npy -r output=output.npy output.rten
+/// ```
+///
 /// To get detailed timing information set the `RTEN_TIMING` env var before
 /// running. See `docs/profiling.md`.
 fn main() -> Result<(), Box<dyn Error>> {
     let args = parse_args()?;
-    let model = Model::load_file(args.model)?;
+    let model = Model::load_file(&args.model)?;
+    let run_opts = RunOptions {
+        timing: args.timing,
+        verbose: args.verbose,
+        ..Default::default()
+    };
+    let provided_inputs = load_inputs(&model, &args.inputs)?;
+
+    match args.command {
+        Command::Run => {
+            if !args.quiet {
+                println!(
+                    "Model summary: {} inputs, {} outputs, {} params",
+                    model.input_ids().len(),
+                    model.output_ids().len(),
+                    format_param_count(model.total_params()),
+                );
+                println!();
 
-    if !args.quiet {
-        println!(
-            "Model summary: {} inputs, {} outputs, {} params",
-            model.input_ids().len(),
-            model.output_ids().len(),
-            format_param_count(model.total_params()),
-        );
-        println!();
+                println!("Inputs");
+                print_input_output_list(&model, model.input_ids());
+                println!();
 
-        println!("Inputs");
-        print_input_output_list(&model, model.input_ids());
-        println!();
+                println!("Outputs");
+                print_input_output_list(&model, model.output_ids());
+                println!();
 
-        println!("Outputs");
-        print_input_output_list(&model, model.output_ids());
-        println!();
+                print_metadata(model.metadata());
 
-        print_metadata(model.metadata());
+                println!();
+                println!("Running model with random inputs...");
+            }
 
-        println!();
-        println!("Running model with random inputs...");
+            let inputs = generate_inputs(&model, &args.input_sizes, provided_inputs, args.quiet)?;
+            run_model(
+                &model,
+                &inputs,
+                run_opts,
+                args.n_iters.unwrap_or(1),
+                args.quiet,
+            )?;
+        }
+        Command::Bench => {
+            // Only print generated inputs once, rather than for each thread
+            // count.
+            let quiet = args.quiet || env::var_os(BENCH_CHILD_VAR).is_some();
+            let inputs = generate_inputs(&model, &args.input_sizes, provided_inputs, quiet)?;
+            if !quiet {
+                println!();
+            }
+            bench(
+                &model,
+                &inputs,
+                &args.threads,
+                run_opts,
+                args.warmup,
+                args.n_iters.unwrap_or(10),
+            )?;
+        }
+        Command::Check => {
+            let inputs = generate_inputs(&model, &args.input_sizes, provided_inputs, args.quiet)?;
+            check(
+                &model,
+                &inputs,
+                &args.references,
+                run_opts,
+                args.rtol,
+                args.atol,
+            )?;
+        }
     }
 
-    run_with_random_input(
-        &model,
-        &args.input_sizes,
-        RunOptions {
-            timing: args.timing,
-            verbose: args.verbose,
-            ..Default::default()
-        },
-        args.n_iters,
-        args.quiet,
-    )?;
-
     Ok(())
 }
@@ -0,0 +1,265 @@
+//! Reader for tensors stored in NumPy's `.npy` format.
+//!
+//! See <https://numpy.org/doc/stable/reference/generated/numpy.lib.format.html>.
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use rten::Output;
+use rten_tensor::Tensor;
+
+/// Element types supported in `.npy` files.
+///
+/// Integer and 64-bit float arrays are converted to the nearest type that
+/// RTen models support.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum DType {
+    F32,
+    F64,
+    I32,
+    I64,
+    Bool,
+}
+
+impl DType {
+    fn parse(descr: &str) -> Option<DType> {
+        let dtype = match descr {
+            "<f4" => DType::F32,
+            "<f8" => DType::F64,
+            "<i4" => DType::I32,
+            "<i8" => DType::I64,
+            "|b1" => DType::Bool,
+            _ => return None,
+        };
+        Some(dtype)
+    }
+
+    fn size(self) -> usize {
+        match self {
+            DType::F32 | DType::I32 => 4,
+            DType::F64 | DType::I64 => 8,
+            DType::Bool => 1,
+        }
+    }
+}
+
+/// Parsed contents of a `.npy` file header.
+#[derive(Debug, PartialEq)]
+struct Header {
+    dtype: DType,
+    shape: Vec<usize>,
+}
+
+/// Return the value associated with `key` in the Python dict literal that
+/// forms a `.npy` header, eg. `{'descr': '<f4', 'shape': (2, 3), }`.
+fn header_value<'a>(header: &'a str, key: &str) -> Option<&'a str> {
+    let key = format!("'{}':", key);
+    let start = header.find(&key)? + key.len();
+    let value = header[start..].trim_start();
+
+    // Values are either quoted strings, tuples or bare words (`True`/`False`).
+    let end = match value.chars().next()? {
+        '\'' => value[1..].find('\'')? + 2,
+        '(' => value.find(')')? + 1,
+        _ => value.find([',', '}']).unwrap_or(value.len()),
+    };
+    Some(value[..end].trim())
+}
+
+fn parse_header(header: &str) -> Result<Header, String> {
+    let descr = header_value(header, "descr").ok_or("missing `descr` in header")?;
+    let descr = descr.trim_matches('\'');
+    let dtype = DType::parse(descr).ok_or(format!("unsupported dtype \"{}\"", descr))?;
+
+    if header_value(header, "fortran_order") != Some("False") {
+        return Err("Fortran-order arrays are not supported".into());
+    }
+
+    let shape = header_value(header, "shape").ok_or("missing `shape` in header")?;
+    let shape = shape
+        .trim_start_matches('(')
+        .trim_end_matches(')')
+        .split(',')
+        .map(|dim| dim.trim())
+        .filter(|dim| !dim.is_empty())
+        .map(|dim| {
+            dim.parse()
+                .map_err(|_| format!("invalid dimension \"{}\"", dim))
+        })
+        .collect::<Result<Vec<usize>, _>>()?;
+
+    Ok(Header { dtype, shape })
+}
+
+/// Parse the contents of a `.npy` file into a tensor.
+pub fn parse_npy(data: &[u8]) -> Result<Output, Box<dyn Error>> {
+    const MAGIC: &[u8] = b"\x93NUMPY";
+    if !data.starts_with(MAGIC) || data.len() < MAGIC.len() + 2 {
+        return Err("not a .npy file".into());
+    }
+    let major_version = data[MAGIC.len()];
+
+    // Version 1 uses a 2-byte header length. Later versions use 4 bytes.
+    let len_start = MAGIC.len() + 2;
+    let (header_len, header_start) = match major_version {
+        1 => {
+            let len = data
+                .get(len_start..len_start + 2)
+                .ok_or("truncated header")?;
+            (u16::from_le_bytes([len[0], len[1]]) as usize, len_start + 2)
+        }
+        2 | 3 => {
+            let len = data
+                .get(len_start..len_start + 4)
+                .ok_or("truncated header")?;
+            (
+                u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize,
+                len_start + 4,
+            )
+        }
+        _ => return Err(format!("unsupported .npy version {}", major_version).into()),
+    };
+
+    let header = data
+        .get(header_start..header_start + header_len)
+        .ok_or("truncated header")?;
+    let header = std::str::from_utf8(header).map_err(|_| "header is not valid UTF-8")?;
+    let Header { dtype, shape } = parse_header(header)?;
+
+    let body = &data[header_start + header_len..];
+    let size = shape
+        .iter()
+        .try_fold(dtype.size(), |size, &dim| size.checked_mul(dim))
+        .ok_or("shape in header is too large")?;
+    let body = body
+        .get(..size)
+        .ok_or("file is smaller than expected from header")?;
+
+    fn read<const N: usize, T>(body: &[u8], f: impl Fn([u8; N]) -> T) -> Vec<T> {
+        body.chunks_exact(N)
+            .map(|chunk| f(chunk.try_into().unwrap()))
+            .collect()
+    }
+
+    let output = match dtype {
+        DType::F32 => Tensor::from_data(&shape, read(body, f32::from_le_bytes)).into(),
+        DType::F64 => {
+            Tensor::from_data(&shape, read(body, |b| f64::from_le_bytes(b) as f32)).into()
+        }
+        DType::I32 => Tensor::from_data(&shape, read(body, i32::from_le_bytes)).into(),
+        DType::I64 => Tensor::from_data(
+            &shape,
+            read(body, |b| {
+                i64::from_le_bytes(b).clamp(i32::MIN as i64, i32::MAX as i64) as i32
+            }),
+        )
+        .into(),
+        DType::Bool => Tensor::from_data(&shape, read(body, |[b]| b as i32)).into(),
+    };
+
+    Ok(output)
+}
+
+/// Read a tensor from a `.npy` file.
+pub fn read_npy(path: impl AsRef<Path>) -> Result<Output, Box<dyn Error>> {
+    let path = path.as_ref();
+    let data = fs::read(path)?;
+    parse_npy(&data).map_err(|err| format!("failed to read {}: {}", path.display(), err).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use rten::Output;
+    use rten_tensor::prelude::*;
+
+    use super::{parse_header, parse_npy, DType, Header};
+
+    fn make_npy(header: &str, body: &[u8]) -> Vec<u8> {
+        let mut data = b"\x93NUMPY\x01\x00".to_vec();
+        data.extend((header.len() as u16).to_le_bytes());
+        data.extend(header.as_bytes());
+        data.extend(body);
+        data
+    }
+
+    #[test]
+    fn test_parse_header() {
+        let header = parse_header("{'descr': '<f4', 'fortran_order': False, 'shape': (2, 3), }");
+        assert_eq!(
+            header,
+            Ok(Header {
+                dtype: DType::F32,
+                shape: vec![2, 3]
+            })
+        );
+
+        let header = parse_header("{'descr': '<i8', 'fortran_order': False, 'shape': (4,), }");
+        assert_eq!(
+            header,
+            Ok(Header {
+                dtype: DType::I64,
+                shape: vec![4]
+            })
+        );
+
+        let header = parse_header("{'descr': '<f4', 'fortran_order': False, 'shape': (), }");
+        assert_eq!(
+            header,
+            Ok(Header {
+                dtype: DType::F32,
+                shape: vec![]
+            })
+        );
+
+        let header = parse_header("{'descr': '<f4', 'fortran_order': True, 'shape': (2, 3), }");
+        assert_eq!(
+            header,
+            Err("Fortran-order arrays are not supported".to_string())
+        );
+
+        let header = parse_header("{'descr': '<c8', 'fortran_order': False, 'shape': (2, 3), }");
+        assert_eq!(header, Err("unsupported dtype \"<c8\"".to_string()));
+    }
+
+    #[test]
+    fn test_parse_npy() {
+        let body: Vec<u8> = [1.0f32, 2.0, 3.0, 4.0]
+            .iter()
+            .flat_map(|x| x.to_le_bytes())
+            .collect();
+        let data = make_npy(
+            "{'descr': '<f4', 'fortran_order': False, 'shape': (2, 2), }",
+            &body,
+        );
+        let Output::FloatTensor(tensor) = parse_npy(&data).unwrap() else {
+            panic!("expected float tensor");
+        };
+        assert_eq!(tensor.shape(), [2, 2]);
+        assert_eq!(tensor.to_vec(), [1.0, 2.0, 3.0, 4.0]);
+
+        let body: Vec<u8> = [5i64, -6].iter().flat_map(|x| x.to_le_bytes()).collect();
+        let data = make_npy(
+            "{'descr': '<i8', 'fortran_order': False, 'shape': (2,), }",
+            &body,
+        );
+        let Output::IntTensor(tensor) = parse_npy(&data).unwrap() else {
+            panic!("expected int tensor");
+        };
+        assert_eq!(tensor.to_vec(), [5, -6]);
+
+        let data = make_npy(
+            "{'descr': '<f4', 'fortran_order': False, 'shape': (2, 2), }",
+            &body[..4],
+        );
+        let err = parse_npy(&data).err().unwrap();
+        assert_eq!(err.to_string(), "file is smaller than expected from header");
+
+        let data = make_npy(
+            "{'descr': '<f4', 'fortran_order': False, 'shape': (18446744073709551615, 2), }",
+            &[],
+        );
+        let err = parse_npy(&data).err().unwrap();
+        assert_eq!(err.to_string(), "shape in header is too large");
+    }
+}
@@ -4,10 +4,9 @@ use std::fs;
 use std::io::prelude::*;
 
 use rten::{FloatOperators, Model};
-use rten_generate::{Generator, GeneratorUtils};
+use rten_generate::{GeneratorUtils, Seq2SeqGenerator};
 use rten_imageio::read_image;
 use rten_tensor::prelude::*;
-use rten_tensor::NdTensor;
 use rten_text::tokenizers::Tokenizer;
 
 struct Args {
@@ -89,11 +88,7 @@ fn main() -> Result<(), Box<dyn Error>> {
     image.insert_axis(0); // Add batch dim
     let image = image.resize_image([224, 224])?;
 
-    let encoded_image: NdTensor<f32, 3> = encoder_model
-        .run_one(image.view().into(), None)?
-        .try_into()?;
-
-    let encoder_hidden_states_id = decoder_model.node_id("encoder_hidden_states")?;
+    let pixel_values_id = encoder_model.node_id("pixel_values")?;
 
     // `decoder_start_token_id` value from
     // https://huggingface.co/Mozilla/distilvit/blob/main/config.json.
@@ -104,9 +99,8 @@ fn main() -> Result<(), Box<dyn Error>> {
     let max_tokens = 40;
 
     let prompt = vec![bos_token];
-    let generator = Generator::from_model(&decoder_model)?
-        .with_prompt(&prompt)
-        .with_constant_input(encoder_hidden_states_id, encoded_image.view().into())
+    let generator = Seq2SeqGenerator::from_models(&encoder_model, &decoder_model)?
+        .generate(vec![(pixel_values_id, image.view().into())], &prompt)?
         .stop_on_tokens([eos_token])
         .take(max_tokens)
         .decode(&tokenizer);
@@ -1,11 +1,10 @@
 use std::collections::VecDeque;
 use std::error::Error;
 
-use rten::ops::concat;
-use rten::{FloatOperators, InputOrOutput, Model, NodeId, Operators, TensorPool};
+use rten::{FloatOperators, Model};
+use rten_generate::EmbeddingPipeline;
 use rten_tensor::prelude::*;
-use rten_tensor::{NdTensor, Tensor};
-use rten_text::tokenizers::{EncodeOptions, Tokenizer};
+use rten_text::tokenizers::Tokenizer;
 
 struct Args {
     model: String,
@@ -69,102 +68,6 @@ Options:
     Ok(args)
 }
 
-/// Generate embeddings for a slice of sentences.
-///
-/// Returns a `(batch, embed_dim)` tensor where `batch` is equal to `sentences.len()`.
-fn embed_sentence_batch(
-    sentences: &[&str],
-    tokenizer: &Tokenizer,
-    model: &Model,
-    max_seq_len: usize,
-) -> Result<NdTensor<f32, 2>, Box<dyn Error>> {
-    // Tokenize input sequences
-    let mut encoded = Vec::new();
-    for &sentence in sentences {
-        encoded.push(tokenizer.encode(
-            sentence.into(),
-            EncodeOptions {
-                max_chunk_len: Some(max_seq_len),
-                ..Default::default()
-            },
-        )?);
-    }
-
-    // Generate (batch, token_id) input.
-    let max_sequence_len = encoded
-        .iter()
-        .map(|enc| enc.token_ids().len())
-        .max()
-        .unwrap_or(0);
-    let batch = sentences.len();
-    let mut input_ids = Tensor::zeros(&[batch, max_sequence_len]);
-    for (i, encoded) in encoded.iter().enumerate() {
-        let token_ids = encoded.token_ids();
-        for (tid, input_id) in token_ids
-            .iter()
-            .zip(input_ids.slice_mut_dyn((i, ..token_ids.len())).iter_mut())
-        {
-            *input_id = *tid as i32;
-        }
-    }
-
-    // Generate attention mask, set to 1 for non-padding tokens and 0 for
-    // padding tokens.
-    let mut attention_mask = Tensor::zeros(&[batch, max_sequence_len]);
-    for (i, encoded) in encoded.iter().enumerate() {
-        attention_mask
-            .slice_mut::<1, _>((i, ..encoded.token_ids().len()))
-            .fill(1i32);
-    }
-
-    let input_ids_id = model.node_id("input_ids")?;
-    let attention_mask_id = model.node_id("attention_mask")?;
-
-    let mut inputs: Vec<(NodeId, InputOrOutput)> = vec![
-        (input_ids_id, input_ids.view().into()),
-        (attention_mask_id, attention_mask.view().into()),
-    ];
-
-    // Generate token type IDs if this model needs them. These are all zeros
-    // since each item has just one sequence.
-    let type_ids: Tensor<i32>;
-    if let Some(type_ids_id) = model.find_node("token_type_ids") {
-        type_ids = Tensor::zeros(&[batch, max_sequence_len]);
-        inputs.push((type_ids_id, type_ids.view().into()));
-    }
-
-    let output_id = model.node_id("last_hidden_state")?;
-    let [last_hidden_state] = model.run_n(inputs, [output_id], None)?;
-    let last_hidden_state = last_hidden_state.into_float().ok_or("wrong output type")?;
-
-    // Mean pool each item in the batch. We process each batch item separately
-    // since they can have different lengths.
-    let mean_pooled: Vec<_> = last_hidden_state
-        .axis_iter(0)
-        .zip(encoded.iter())
-        .map(|(item, input)| {
-            // Take the mean of the non-padding elements along the sequence
-            // dimension.
-            let seq_len = input.token_ids().len();
-            item.slice_dyn(..seq_len)
-                .reduce_mean(Some(&[0]), false /* keep_dims */)
-                .unwrap()
-        })
-        .collect();
-    let mean_pooled_views: Vec<_> = mean_pooled
-        .iter()
-        .map(|mp| {
-            // Re-add batch dim.
-            let mut view = mp.view();
-            view.insert_axis(0);
-            view
-        })
-        .collect();
-    let pool = TensorPool::new();
-    let mean_pooled: NdTensor<f32, 2> = concat(&pool, &mean_pooled_views, 0)?.try_into()?;
-    Ok(mean_pooled)
-}
-
 /// This example computes the semantic similarity between a query sentence and
 /// a list of sentences in a text file (one per line).
 ///
@@ -218,16 +121,16 @@ fn main() -> Result<(), Box<dyn Error>> {
     // See notes in https://huggingface.co/jinaai/jina-embeddings-v2-base-en.
     let max_sequence_len = 8192;
 
-    // (batch, embed_dim) matrix of embeddings.
-    let embeddings = embed_sentence_batch(&sentences, &tokenizer, &model, max_sequence_len)?;
+    // (batch, embed_dim) matrix of mean-pooled embeddings, normalized to unit
+    // length.
+    let embeddings = EmbeddingPipeline::from_model(&model)?.embed_text(
+        &tokenizer,
+        &sentences,
+        Some(max_sequence_len),
+    )?;
 
-    // Compute cosine similarity of first row in `embeddings` with all rows.
-    //
-    // First normalize embeddings to unit length, then compute the dot product
-    // of the first row with other rows.
-    let embeddings_norm =
-        embeddings.reduce_l2(Some(&[1]) /* axes */, true /* keep_dims */)?;
-    let embeddings: NdTensor<f32, 2> = embeddings.div(embeddings_norm.view())?.try_into()?;
+    // Compute cosine similarity of first row in `embeddings` with all rows,
+    // which is the dot product since the embeddings are normalized.
 
     // (1, embed) @ (embed, batch) => (1, batch)
     let similarities = embeddings
@@ -3,11 +3,13 @@ use std::error::Error;
 use std::fs;
 use std::io;
 use std::io::prelude::*;
+use std::path::Path;
 
 use rten::Model;
 use rten_generate::sampler::TopKSampler;
 use rten_generate::{Generator, GeneratorUtils};
-use rten_text::tokenizers::{Tokenizer, TokenizerError};
+use rten_text::chat_template::{ChatMessage, ChatTemplate, ChatTemplateOptions};
+use rten_text::tokenizers::Tokenizer;
 
 struct Args {
     model: String,
@@ -40,7 +42,8 @@ Usage: {bin_name} [options] <model> <tokenizer>
 Args:
 
   <model>       - Input model
-  <tokenizer>   - `tokenizer.json` file
+  <tokenizer>   - `tokenizer.json` file. The chat template is read from
+                  `tokenizer_config.json` in the same directory.
 
 Options:
 
@@ -70,34 +73,6 @@ Options:
     Ok(args)
 }
 
-enum MessageChunk<'a> {
-    Text(&'a str),
-    Token(u32),
-}
-
-/// Encode a message consisting of a mix of text and special token IDs into a
-/// sequence of token IDs.
-///
-/// Special tokens need to be passed as IDs because `Tokenizer::encode` will not
-/// generate them (eg. it would treat a string such as "<|endoftext|>" as
-/// ordinary text).
-fn encode_message(
-    tokenizer: &Tokenizer,
-    chunks: &[MessageChunk],
-) -> Result<Vec<u32>, TokenizerError> {
-    let mut token_ids = Vec::new();
-    for chunk in chunks {
-        match chunk {
-            MessageChunk::Token(tok_id) => token_ids.push(*tok_id),
-            MessageChunk::Text(text) => {
-                let encoded = tokenizer.encode((*text).into(), Default::default())?;
-                token_ids.extend(encoded.token_ids());
-            }
-        }
-    }
-    Ok(token_ids)
-}
-
 /// Chatbot using Qwen 2 [2].
 ///
 /// To obtain the model from Hugging Face, use Optimum [1], then convert it.
@@ -129,26 +104,25 @@ fn main() -> Result<(), Box<dyn Error>> {
     let tokenizer_config = fs::read_to_string(&args.tokenizer_config)?;
     let tokenizer = Tokenizer::from_json(&tokenizer_config)?;
 
-    let im_start_token = tokenizer.encoder().get_token_id("<|im_start|>")?;
+    let chat_template_path =
+        Path::new(&args.tokenizer_config).with_file_name("tokenizer_config.json");
+    let chat_template =
+        ChatTemplate::from_tokenizer_config(&fs::read_to_string(chat_template_path)?)?;
+
     let im_end_token = tokenizer.encoder().get_token_id("<|im_end|>")?;
     let end_of_text_token = tokenizer.encoder().get_token_id("<|endoftext|>")?;
 
-    // From `chat_template` in tokenizer_config.json.
-    let prompt_tokens = encode_message(
-        &tokenizer,
-        &[
-            MessageChunk::Token(im_start_token),
-            MessageChunk::Text("system\nYou are a helpful assistant."),
-            MessageChunk::Token(im_end_token),
-        ],
-    )?;
+    let mut messages = vec![ChatMessage::new("system", "You are a helpful assistant.")];
+
+    // Length of the rendered conversation which has already been passed to
+    // the model.
+    let mut rendered_len = 0;
 
     // From Qwen2's `generation_config.json`
     let top_k = 20;
 
-    let mut generator = Generator::from_model(&model)?
-        .with_prompt(&prompt_tokens)
-        .with_sampler(TopKSampler::new(top_k, args.temperature));
+    let mut generator =
+        Generator::from_model(&model)?.with_sampler(TopKSampler::new(top_k, args.temperature));
 
     loop {
         print!("> ");
@@ -161,34 +135,37 @@ fn main() -> Result<(), Box<dyn Error>> {
             break;
         }
 
-        // From `chat_template` in tokenizer_config.json.
-        let token_ids = encode_message(
-            &tokenizer,
-            &[
-                MessageChunk::Token(im_start_token),
-                MessageChunk::Text("user\n"),
-                MessageChunk::Text(&user_input),
-                MessageChunk::Token(im_end_token),
-                MessageChunk::Text("\n"),
-                MessageChunk::Token(im_start_token),
-                MessageChunk::Text("assistant\n"),
-            ],
+        // Render the whole conversation and pass the new part to the model.
+        messages.push(ChatMessage::new("user", &user_input));
+        let prompt = chat_template.render(
+            &messages,
+            ChatTemplateOptions {
+                add_generation_prompt: true,
+            },
         )?;
-
-        generator.append_prompt(&token_ids);
+        let encoded = tokenizer.encode(prompt[rendered_len..].into(), Default::default())?;
+        generator.append_prompt(encoded.token_ids());
 
         let decoder = generator
             .by_ref()
             // See `eos_token_id` in `generation_config.json`
             .stop_on_tokens([im_end_token, end_of_text_token])
             .decode(&tokenizer);
+        let mut reply = String::new();
         for token in decoder {
             let token = token?;
             print!("{}", token);
             let _ = std::io::stdout().flush();
+            reply.push_str(&token);
         }
 
         println!();
+
+        // The reply has already been seen by the model, since it generated it.
+        messages.push(ChatMessage::new("assistant", &reply));
+        rendered_len = chat_template
+            .render(&messages, ChatTemplateOptions::default())?
+            .len();
     }
 
     Ok(())
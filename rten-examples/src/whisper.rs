@@ -0,0 +1,481 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs;
+use std::io::prelude::*;
+
+use rten::{Dimension, FloatOperators, Model};
+use rten_generate::logits::{ForceTokens, SuppressTokens};
+use rten_generate::whisper::TimestampRules;
+use rten_generate::{GeneratorUtils, Seq2SeqGenerator};
+use rten_tensor::prelude::*;
+use rten_tensor::NdTensor;
+use rten_text::tokenizers::Tokenizer;
+
+struct Args {
+    encoder_model: String,
+    decoder_model: String,
+    tokenizer_config: String,
+    wav_file: String,
+    language: String,
+    output: Option<String>,
+}
+
+fn parse_args() -> Result<Args, lexopt::Error> {
+    use lexopt::prelude::*;
+
+    let mut values = VecDeque::new();
+    let mut language = "en".to_string();
+    let mut output = None;
+
+    let mut parser = lexopt::Parser::from_env();
+    while let Some(arg) = parser.next()? {
+        match arg {
+            Value(val) => values.push_back(val.string()?),
+            Short('l') | Long("language") => language = parser.value()?.string()?,
+            Short('o') | Long("output") => output = Some(parser.value()?.string()?),
+            Long("help") => {
+                println!(
+                    "Transcribe speech in a .wav file into SRT subtitles.
+
+Usage: {bin_name} [options] <encoder_model> <decoder_model> <tokenizer> <wav_file>
+
+Args:
+
+  <encoder_model>  - Audio encoder model
+  <decoder_model>  - Text decoder model
+  <tokenizer>      - `tokenizer.json` file
+  <wav_file>       - 16 kHz mono .wav file
+
+Options:
+
+  -l, --language <code>  - Language of the speech (default: \"en\")
+  -o, --output <path>    - Write SRT output to a file instead of stdout
+",
+                    bin_name = parser.bin_name().unwrap_or("whisper")
+                );
+                std::process::exit(0);
+            }
+            _ => return Err(arg.unexpected()),
+        }
+    }
+
+    let encoder_model = values.pop_front().ok_or("missing `encoder_model` arg")?;
+    let decoder_model = values.pop_front().ok_or("missing `decoder_model` arg")?;
+    let tokenizer_config = values.pop_front().ok_or("missing `tokenizer` arg")?;
+    let wav_file = values.pop_front().ok_or("missing `wav_file` arg")?;
+
+    let args = Args {
+        encoder_model,
+        decoder_model,
+        tokenizer_config,
+        wav_file,
+        language,
+        output,
+    };
+
+    Ok(args)
+}
+
+/// Sample rate of audio expected by Whisper.
+const SAMPLE_RATE: usize = 16_000;
+
+/// Size of the FFT window used to compute the spectrogram.
+const N_FFT: usize = 400;
+
+/// Number of samples between successive spectrogram frames.
+const HOP_LENGTH: usize = 160;
+
+/// Number of samples in each 30 second chunk of audio processed by the model.
+const N_SAMPLES: usize = 30 * SAMPLE_RATE;
+
+/// Number of spectrogram frames in each chunk.
+const N_FRAMES: usize = N_SAMPLES / HOP_LENGTH;
+
+/// Duration in seconds between successive timestamp tokens.
+const TIME_PRECISION: f32 = 0.02;
+
+/// Maximum number of tokens to generate for each chunk.
+const MAX_TOKENS_PER_CHUNK: usize = 224;
+
+/// Read a .wav audio file into a sequence of samples with values in [1, -1].
+fn read_wav_file(path: &str) -> Result<Vec<f32>, hound::Error> {
+    let mut reader = hound::WavReader::open(path)?;
+
+    let spec = reader.spec();
+    if spec.sample_rate as usize != SAMPLE_RATE {
+        eprintln!(
+            "WARNING: Sample rate is {} kHz, this model expects {} kHz.",
+            spec.sample_rate / 1_000,
+            SAMPLE_RATE / 1_000
+        );
+    }
+
+    let mut samples = Vec::new();
+    for sample in reader.samples::<i16>() {
+        samples.push(sample?);
+    }
+    let float_samples: Vec<f32> = samples
+        .into_iter()
+        .map(|x| (x as f32) / i16::MAX as f32)
+        .collect();
+    Ok(float_samples)
+}
+
+/// Convert a frequency to the mel scale, using the Slaney formula which
+/// is linear below 1 kHz and logarithmic above.
+fn hz_to_mel(freq: f32) -> f32 {
+    let min_log_hz = 1000.;
+    let min_log_mel = 15.;
+    let log_step = 6.4f32.ln() / 27.;
+    if freq >= min_log_hz {
+        min_log_mel + (freq / min_log_hz).ln() / log_step
+    } else {
+        3. * freq / 200.
+    }
+}
+
+/// Inverse of [`hz_to_mel`].
+fn mel_to_hz(mel: f32) -> f32 {
+    let min_log_hz = 1000.;
+    let min_log_mel = 15.;
+    let log_step = 6.4f32.ln() / 27.;
+    if mel >= min_log_mel {
+        min_log_hz * (log_step * (mel - min_log_mel)).exp()
+    } else {
+        200. * mel / 3.
+    }
+}
+
+/// Computes log-mel spectrograms in the format expected by Whisper's encoder.
+///
+/// This matches `log_mel_spectrogram` in the reference implementation [^1].
+///
+/// [^1]: <https://github.com/openai/whisper/blob/main/whisper/audio.py>
+struct MelSpectrogram {
+    /// Real and imaginary DFT coefficients, with the Hann window applied, as
+    /// a `[N_FFT, n_freqs * 2]` matrix.
+    dft: NdTensor<f32, 2>,
+
+    /// Mel filter bank as a `[n_freqs, n_mels]` matrix.
+    filters: NdTensor<f32, 2>,
+}
+
+impl MelSpectrogram {
+    fn new(n_mels: usize) -> MelSpectrogram {
+        let n_freqs = N_FFT / 2 + 1;
+
+        let dft = NdTensor::from_fn([N_FFT, n_freqs * 2], |[n, k]| {
+            let window = 0.5 - 0.5 * (2. * std::f32::consts::PI * n as f32 / N_FFT as f32).cos();
+            let angle = 2. * std::f32::consts::PI * ((k % n_freqs) * n) as f32 / N_FFT as f32;
+            if k < n_freqs {
+                window * angle.cos()
+            } else {
+                -window * angle.sin()
+            }
+        });
+
+        // Triangular filters with centers evenly spaced on the mel scale,
+        // normalized to have constant energy per channel.
+        let max_mel = hz_to_mel(SAMPLE_RATE as f32 / 2.);
+        let mel_freqs: Vec<f32> = (0..n_mels + 2)
+            .map(|i| mel_to_hz(max_mel * i as f32 / (n_mels + 1) as f32))
+            .collect();
+        let filters = NdTensor::from_fn([n_freqs, n_mels], |[k, m]| {
+            let freq = (k * SAMPLE_RATE) as f32 / N_FFT as f32;
+            let (lower, center, upper) = (mel_freqs[m], mel_freqs[m + 1], mel_freqs[m + 2]);
+            let rising = (freq - lower) / (center - lower);
+            let falling = (upper - freq) / (upper - center);
+            rising.min(falling).max(0.) * 2. / (upper - lower)
+        });
+
+        MelSpectrogram { dft, filters }
+    }
+
+    /// Compute the `[n_mels, N_FRAMES]` log-mel spectrogram of up to
+    /// `N_SAMPLES` samples. Shorter inputs are padded with silence.
+    fn compute(&self, samples: &[f32]) -> Result<NdTensor<f32, 2>, Box<dyn Error>> {
+        let mut padded = samples[..samples.len().min(N_SAMPLES)].to_vec();
+        padded.resize(N_SAMPLES, 0.);
+
+        // Split into overlapping frames centered on multiples of
+        // `HOP_LENGTH`, using reflection padding at the edges.
+        let pad = N_FFT / 2;
+        let reflect = |i: isize| -> f32 {
+            let i = if i < 0 {
+                -i
+            } else if i as usize >= N_SAMPLES {
+                2 * (N_SAMPLES as isize - 1) - i
+            } else {
+                i
+            };
+            padded[i as usize]
+        };
+        let frames = NdTensor::from_fn([N_FRAMES, N_FFT], |[frame, n]| {
+            reflect((frame * HOP_LENGTH + n) as isize - pad as isize)
+        });
+
+        // Compute power spectrum using a DFT expressed as a matmul.
+        let n_freqs = N_FFT / 2 + 1;
+        let spectrum: NdTensor<f32, 2> = frames.matmul(self.dft.as_dyn())?.try_into()?;
+        let power = NdTensor::from_fn([N_FRAMES, n_freqs], |[frame, k]| {
+            let re = spectrum[[frame, k]];
+            let im = spectrum[[frame, n_freqs + k]];
+            re * re + im * im
+        });
+
+        let mel: NdTensor<f32, 2> = power.matmul(self.filters.as_dyn())?.try_into()?;
+        let mut log_mel = mel.transposed().map(|x| x.max(1e-10).log10());
+
+        // Limit dynamic range to 80 dB and scale to roughly [-1, 1].
+        let max_val = log_mel.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        log_mel.apply(|x| (x.max(max_val - 8.) + 4.) / 4.);
+
+        Ok(log_mel)
+    }
+}
+
+/// IDs of special tokens which control the Whisper decoder.
+struct SpecialTokens {
+    start_of_transcript: u32,
+    end_of_text: u32,
+    transcribe: u32,
+    language: u32,
+    no_timestamps: u32,
+
+    /// ID of the first timestamp token, `<|0.00|>`. Subsequent IDs
+    /// correspond to increments of `TIME_PRECISION` seconds.
+    timestamp_begin: u32,
+}
+
+impl SpecialTokens {
+    fn new(tokenizer: &Tokenizer, language: &str) -> Result<SpecialTokens, Box<dyn Error>> {
+        let encoder = tokenizer.encoder();
+        let no_timestamps = encoder.get_token_id("<|notimestamps|>")?;
+        Ok(SpecialTokens {
+            start_of_transcript: encoder.get_token_id("<|startoftranscript|>")?,
+            end_of_text: encoder.get_token_id("<|endoftext|>")?,
+            transcribe: encoder.get_token_id("<|transcribe|>")?,
+            language: encoder
+                .get_token_id(&format!("<|{}|>", language))
+                .map_err(|_| format!("unsupported language \"{}\"", language))?,
+            no_timestamps,
+            timestamp_begin: no_timestamps + 1,
+        })
+    }
+
+    fn is_timestamp(&self, token: u32) -> bool {
+        token >= self.timestamp_begin
+    }
+}
+
+/// A segment of transcribed text with start and end times in seconds.
+struct Segment {
+    start: f32,
+    end: f32,
+    text: String,
+}
+
+/// Format a time in seconds as an SRT timestamp (`HH:MM:SS,mmm`).
+fn format_srt_time(seconds: f32) -> String {
+    let millis = (seconds * 1000.).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02},{:03}",
+        millis / 3_600_000,
+        (millis / 60_000) % 60,
+        (millis / 1000) % 60,
+        millis % 1000
+    )
+}
+
+/// Write segments in SubRip (SRT) subtitle format.
+fn write_srt<W: Write>(mut out: W, segments: &[Segment]) -> std::io::Result<()> {
+    for (i, segment) in segments.iter().enumerate() {
+        writeln!(out, "{}", i + 1)?;
+        writeln!(
+            out,
+            "{} --> {}",
+            format_srt_time(segment.start),
+            format_srt_time(segment.end)
+        )?;
+        writeln!(out, "{}\n", segment.text.trim())?;
+    }
+    Ok(())
+}
+
+/// Split the tokens generated for a chunk into timestamped segments.
+///
+/// Returns the segments and the offset in seconds from the start of the
+/// chunk at which the next chunk should begin.
+fn split_segments(
+    tokenizer: &Tokenizer,
+    special_tokens: &SpecialTokens,
+    tokens: &[u32],
+    chunk_start: f32,
+    chunk_duration: f32,
+) -> Result<(Vec<Segment>, f32), Box<dyn Error>> {
+    let to_seconds = |tok: u32| (tok - special_tokens.timestamp_begin) as f32 * TIME_PRECISION;
+
+    let mut segments = Vec::new();
+    let mut start_time = None;
+    let mut text_tokens = Vec::new();
+
+    for &token in tokens {
+        if !special_tokens.is_timestamp(token) {
+            text_tokens.push(token);
+            continue;
+        }
+        match start_time {
+            None => start_time = Some(to_seconds(token)),
+            Some(start) => {
+                if !text_tokens.is_empty() {
+                    segments.push(Segment {
+                        start: chunk_start + start,
+                        end: chunk_start + to_seconds(token),
+                        text: tokenizer.decode(&text_tokens)?,
+                    });
+                    text_tokens.clear();
+                }
+                start_time = None;
+            }
+        }
+    }
+
+    // If the last segment is incomplete, because the chunk ended mid-speech,
+    // resume from the start of that segment in the next chunk. Otherwise
+    // continue after the end of this chunk.
+    let next_offset = match start_time {
+        Some(start) if !text_tokens.is_empty() && start > 0. => start,
+        _ => chunk_duration,
+    };
+
+    Ok((segments, next_offset))
+}
+
+/// Transcribe speech into SRT subtitles using OpenAI's Whisper [^1].
+///
+/// Audio is processed in 30 second chunks. For each chunk a log-mel
+/// spectrogram is computed and passed to the encoder, then the decoder
+/// generates text interleaved with timestamp tokens that mark the start
+/// and end of each segment.
+///
+/// 1. Export the model using Optimum. Any Whisper size should work:
+///
+/// ```sh
+/// optimum-cli export onnx --model openai/whisper-base whisper-base
+/// ```
+///
+/// 2. Convert the encoder and decoder models:
+///
+/// ```sh
+/// rten-convert whisper-base/encoder_model.onnx
+/// rten-convert whisper-base/decoder_model.onnx
+/// ```
+///
+/// 3. Convert the audio to a 16 kHz mono .wav file, eg. using ffmpeg:
+///
+/// ```sh
+/// ffmpeg -i input.m4a -ar 16000 -ac 1 output.wav
+/// ```
+///
+/// 4. Run the example:
+///
+/// ```sh
+/// cargo run --release --bin whisper whisper-base/encoder_model.rten \
+///     whisper-base/decoder_model.rten whisper-base/tokenizer.json output.wav
+/// ```
+///
+/// Note that `decoder_model` does not have key-value cache inputs, so each
+/// decoding step re-processes the full sequence. The
+/// `decoder_with_past_model` export is faster, but it expects the
+/// cross-attention keys and values to be computed by a separate model.
+/// `Generator` can only cache cross-attention keys and values which are
+/// output by the decoder model in its first step.
+///
+/// [^1]: <https://github.com/openai/whisper>
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = parse_args()?;
+    let encoder_model = Model::load_file(args.encoder_model)?;
+    let decoder_model = Model::load_file(args.decoder_model)?;
+    let tokenizer_config = fs::read_to_string(&args.tokenizer_config)?;
+    let tokenizer = Tokenizer::from_json(&tokenizer_config)?;
+    let samples = read_wav_file(&args.wav_file)?;
+
+    let special_tokens = SpecialTokens::new(&tokenizer, &args.language)?;
+
+    // Get the number of mel bins from the encoder's input shape. This is 80
+    // for most models, but 128 for large-v3.
+    let features_id = encoder_model.node_id("input_features")?;
+    let n_mels = match encoder_model
+        .node_info(features_id)
+        .and_then(|info| info.shape())
+        .as_deref()
+    {
+        Some(&[_, Dimension::Fixed(n_mels), _]) => n_mels,
+        _ => 80,
+    };
+    let mel_spectrogram = MelSpectrogram::new(n_mels);
+
+    let seq2seq = Seq2SeqGenerator::from_models(&encoder_model, &decoder_model)?;
+    let prompt = [special_tokens.start_of_transcript];
+
+    // The output starts with the language and task. After that, special
+    // tokens other than end-of-text and timestamps are not generated.
+    let forced_tokens = [special_tokens.language, special_tokens.transcribe];
+    let other_special_tokens: Vec<u32> =
+        (special_tokens.end_of_text + 1..special_tokens.timestamp_begin).collect();
+    let timestamp_rules = TimestampRules::new(
+        special_tokens.timestamp_begin,
+        special_tokens.end_of_text,
+        special_tokens.no_timestamps,
+    )
+    .with_begin_index(forced_tokens.len())
+    .with_max_initial_timestamp((1. / TIME_PRECISION) as u32);
+
+    let mut segments = Vec::new();
+    let mut seek = 0;
+    while seek < samples.len() {
+        let chunk = &samples[seek..];
+        let chunk_start = seek as f32 / SAMPLE_RATE as f32;
+        let chunk_duration = chunk.len().min(N_SAMPLES) as f32 / SAMPLE_RATE as f32;
+
+        let mut mel = mel_spectrogram.compute(chunk)?.into_dyn();
+        mel.insert_axis(0); // Add batch dim
+
+        let tokens = seq2seq
+            .generate(vec![(features_id, mel.view().into())], &prompt)?
+            .with_logits_processor(SuppressTokens::new(&other_special_tokens))
+            .with_logits_processor(timestamp_rules.clone())
+            .with_logits_processor(ForceTokens::prefix(&forced_tokens))
+            .stop_on_tokens([special_tokens.end_of_text])
+            .take(forced_tokens.len() + MAX_TOKENS_PER_CHUNK)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let (chunk_segments, next_offset) = split_segments(
+            &tokenizer,
+            &special_tokens,
+            tokens.get(forced_tokens.len()..).unwrap_or(&[]),
+            chunk_start,
+            chunk_duration,
+        )?;
+        for segment in &chunk_segments {
+            eprintln!(
+                "[{} --> {}] {}",
+                format_srt_time(segment.start),
+                format_srt_time(segment.end),
+                segment.text.trim()
+            );
+        }
+        segments.extend(chunk_segments);
+
+        let next_offset_samples = (next_offset * SAMPLE_RATE as f32) as usize;
+        seek += next_offset_samples.max(1);
+    }
+
+    if let Some(path) = args.output {
+        write_srt(fs::File::create(path)?, &segments)?;
+    } else {
+        write_srt(std::io::stdout().lock(), &segments)?;
+    }
+
+    Ok(())
+}
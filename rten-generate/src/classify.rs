@@ -0,0 +1,359 @@
+//! Pipeline to classify text using sequence classification models.
+
+use rten_tensor::prelude::*;
+use rten_tensor::NdTensor;
+use rten_vecmath::{vec_sigmoid_in_place, vec_softmax_in_place};
+
+#[cfg(feature = "text-decoder")]
+use rten_text::tokenizers::Tokenizer;
+
+use crate::generator::TokenId;
+use crate::model::Model;
+#[cfg(feature = "text-decoder")]
+use crate::pipeline::encode_batch;
+use crate::pipeline::{BatchRunner, PipelineConfig, PipelineError};
+
+/// Function used to convert logits into label scores.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Activation {
+    /// Apply softmax over labels, for models where each input has exactly one
+    /// label.
+    #[default]
+    Softmax,
+
+    /// Apply sigmoid to each label independently, for models where an input
+    /// can have any number of labels.
+    Sigmoid,
+
+    /// Return raw logits.
+    None,
+}
+
+/// Score for one label, returned by [`ClassifyPipeline`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct LabelScore {
+    /// Index of the label in the model's output.
+    pub index: usize,
+
+    /// Name of the label.
+    pub label: String,
+
+    /// Score for the label, after applying the pipeline's [`Activation`].
+    pub score: f32,
+}
+
+/// Classifies sequences using a sequence classification model.
+///
+/// The model is run on batches of sequences, which are padded to the same
+/// length. It is expected to output a `(batch, num_labels)` tensor of logits.
+/// For each sequence, the pipeline returns the score for every label, sorted
+/// in descending order.
+pub struct ClassifyPipeline<'a> {
+    runner: BatchRunner<'a>,
+    labels: Vec<String>,
+    activation: Activation,
+}
+
+impl<'a> ClassifyPipeline<'a> {
+    /// Create a pipeline which uses default input and output names.
+    ///
+    /// The logits are read from the `logits` output. By default, sequences
+    /// are processed in batches of 16, softmax is applied to the logits and
+    /// labels are named `LABEL_{index}`.
+    pub fn from_model(model: &'a dyn Model) -> Result<ClassifyPipeline<'a>, PipelineError> {
+        Self::from_model_config(model, PipelineConfig::new("logits"))
+    }
+
+    /// Create a pipeline with custom input and output names.
+    ///
+    /// The output in `config` contains the `(batch, num_labels)` logits.
+    pub fn from_model_config(
+        model: &'a dyn Model,
+        config: PipelineConfig,
+    ) -> Result<ClassifyPipeline<'a>, PipelineError> {
+        Ok(ClassifyPipeline {
+            runner: BatchRunner::new(model, config)?,
+            labels: Vec::new(),
+            activation: Activation::default(),
+        })
+    }
+
+    /// Set the names of the model's labels, in the order of the model's
+    /// output.
+    pub fn with_labels(mut self, labels: Vec<String>) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    /// Set label names and the activation function from a Hugging Face
+    /// model configuration (`config.json`).
+    ///
+    /// Labels are read from the `id2label` field. The activation is sigmoid
+    /// if `problem_type` is `multi_label_classification` and softmax
+    /// otherwise.
+    pub fn with_hf_config(mut self, config_json: &str) -> Result<Self, PipelineError> {
+        let config: serde_json::Value = serde_json::from_str(config_json)
+            .map_err(|err| PipelineError::InvalidConfig(err.to_string()))?;
+
+        if let Some(id2label) = config.get("id2label") {
+            let id2label = id2label.as_object().ok_or_else(|| {
+                PipelineError::InvalidConfig("`id2label` is not an object".to_string())
+            })?;
+            let mut labels = vec![String::new(); id2label.len()];
+            for (id, label) in id2label {
+                let slot = id
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|id| labels.get_mut(id))
+                    .ok_or_else(|| {
+                        PipelineError::InvalidConfig(format!("invalid label ID \"{}\"", id))
+                    })?;
+                *slot = label
+                    .as_str()
+                    .ok_or_else(|| {
+                        PipelineError::InvalidConfig(format!("label {} is not a string", id))
+                    })?
+                    .to_string();
+            }
+            self.labels = labels;
+        }
+
+        self.activation = match config.get("problem_type").and_then(|pt| pt.as_str()) {
+            Some("multi_label_classification") => Activation::Sigmoid,
+            _ => Activation::Softmax,
+        };
+
+        Ok(self)
+    }
+
+    /// Set the function used to convert logits into scores.
+    pub fn with_activation(mut self, activation: Activation) -> Self {
+        self.activation = activation;
+        self
+    }
+
+    /// Set the maximum number of sequences that are passed to the model in a
+    /// single run.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.runner.set_batch_size(batch_size);
+        self
+    }
+
+    /// Return the name of the label with a given index.
+    pub fn label(&self, index: usize) -> String {
+        self.labels
+            .get(index)
+            .cloned()
+            .unwrap_or_else(|| format!("LABEL_{}", index))
+    }
+
+    /// Classify encoded sequences.
+    ///
+    /// Returns the scores for each sequence, in the same order as the input.
+    /// The scores for each sequence are sorted in descending order.
+    pub fn classify_tokens<S: AsRef<[TokenId]>>(
+        &self,
+        sequences: &[S],
+    ) -> Result<Vec<Vec<LabelScore>>, PipelineError> {
+        let mut results = Vec::with_capacity(sequences.len());
+        self.runner.run_batches(
+            sequences,
+            |_, _| 0,
+            |batch, output| {
+                let logits: NdTensor<f32, 2> = output.try_into().map_err(|_| {
+                    PipelineError::ShapeMismatch(
+                        "expected (batch, num_labels) float logits".to_string(),
+                    )
+                })?;
+                if logits.size(0) != batch.len() {
+                    return Err(PipelineError::ShapeMismatch(format!(
+                        "expected logits for {} sequences but got {:?}",
+                        batch.len(),
+                        logits.shape()
+                    )));
+                }
+                results.extend(
+                    logits
+                        .axis_iter(0)
+                        .map(|item| self.label_scores(item.to_vec())),
+                );
+                Ok(())
+            },
+        )?;
+        Ok(results)
+    }
+
+    /// Tokenize and classify a batch of texts.
+    ///
+    /// If `max_len` is set, each text is truncated to at most that many
+    /// tokens, including any special tokens that the tokenizer adds.
+    #[cfg(feature = "text-decoder")]
+    pub fn classify(
+        &self,
+        tokenizer: &Tokenizer,
+        texts: &[&str],
+        max_len: Option<usize>,
+    ) -> Result<Vec<Vec<LabelScore>>, PipelineError> {
+        let (sequences, _type_ids) = encode_batch(tokenizer, texts, max_len)?;
+        self.classify_tokens(&sequences)
+    }
+
+    /// Apply the activation to the logits for one sequence and return the
+    /// label scores in descending order.
+    fn label_scores(&self, mut scores: Vec<f32>) -> Vec<LabelScore> {
+        match self.activation {
+            Activation::Softmax => vec_softmax_in_place(&mut scores),
+            Activation::Sigmoid => vec_sigmoid_in_place(&mut scores),
+            Activation::None => {}
+        }
+        let mut scores: Vec<_> = scores
+            .into_iter()
+            .enumerate()
+            .map(|(index, score)| LabelScore {
+                index,
+                label: self.label(index),
+                score,
+            })
+            .collect();
+        scores.sort_by(|a, b| b.score.total_cmp(&a.score));
+        scores
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rten::Output;
+    use rten_tensor::prelude::*;
+    use rten_tensor::NdTensor;
+    use rten_text::tokenizers::{Tokenizer, TokenizerOptions, WordPiece};
+
+    use super::{Activation, ClassifyPipeline, LabelScore};
+    use crate::test_util::{BatchRecordingModel, FnModel, FnModelInputs};
+
+    const NUM_LABELS: usize = 3;
+
+    /// Create a fake classification model whose logit for label `i` is the
+    /// number of non-padding tokens in the sequence with ID `i`.
+    fn fake_classify_model() -> BatchRecordingModel<FnModel<impl Fn(&FnModelInputs) -> Vec<Output>>>
+    {
+        BatchRecordingModel::new(FnModel::new(
+            &["input_ids", "attention_mask"],
+            &["logits"],
+            move |inputs: &FnModelInputs| {
+                let ids = inputs.int("input_ids");
+                let mask = inputs.int("attention_mask");
+                let [batch, seq] = ids.shape().try_into().unwrap();
+
+                let logits = NdTensor::<f32, 2>::from_fn([batch, NUM_LABELS], |[b, label]| {
+                    (0..seq)
+                        .filter(|&s| mask[[b, s]] == 1 && ids[[b, s]] == label as i32)
+                        .count() as f32
+                });
+                vec![logits.into_dyn().into()]
+            },
+        ))
+    }
+
+    #[test]
+    fn test_classify_tokens() {
+        let model = fake_classify_model();
+        let sequences: [&[u32]; 3] = [&[0, 0, 1], &[2], &[1, 2, 1, 1]];
+
+        let pipeline = ClassifyPipeline::from_model(&model)
+            .unwrap()
+            .with_batch_size(2)
+            .with_activation(Activation::None)
+            .with_labels(vec!["zero".into(), "one".into()]);
+        let results = pipeline.classify_tokens(&sequences).unwrap();
+        assert_eq!(model.max_batch(), 2);
+
+        let top: Vec<_> = results
+            .iter()
+            .map(|scores| (scores[0].label.as_str(), scores[0].score))
+            .collect();
+        assert_eq!(top, [("zero", 2.), ("LABEL_2", 1.), ("one", 3.)]);
+
+        // Softmax scores should sum to 1.
+        let pipeline = ClassifyPipeline::from_model(&model).unwrap();
+        let results = pipeline.classify_tokens(&sequences).unwrap();
+        for scores in results {
+            let sum: f32 = scores.iter().map(|s| s.score).sum();
+            assert!((sum - 1.).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_with_hf_config() {
+        let model = fake_classify_model();
+        let config = r#"{
+            "id2label": {"0": "negative", "1": "neutral", "2": "positive"},
+            "problem_type": "multi_label_classification"
+        }"#;
+        let pipeline = ClassifyPipeline::from_model(&model)
+            .unwrap()
+            .with_hf_config(config)
+            .unwrap();
+        let sequences: [&[u32]; 1] = [&[2, 2, 0]];
+        let results = pipeline.classify_tokens(&sequences).unwrap();
+
+        let sigmoid = |x: f32| 1. / (1. + (-x).exp());
+        assert_eq!(
+            results[0],
+            [
+                LabelScore {
+                    index: 2,
+                    label: "positive".into(),
+                    score: sigmoid(2.)
+                },
+                LabelScore {
+                    index: 0,
+                    label: "negative".into(),
+                    score: sigmoid(1.)
+                },
+                LabelScore {
+                    index: 1,
+                    label: "neutral".into(),
+                    score: sigmoid(0.)
+                },
+            ]
+        );
+
+        let err = ClassifyPipeline::from_model(&model)
+            .unwrap()
+            .with_hf_config(r#"{"id2label": {"5": "foo"}}"#)
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "invalid config: invalid label ID \"5\"");
+    }
+
+    #[test]
+    fn test_classify() {
+        let vocab = &["[CLS]", "[SEP]", "good", "bad"];
+        let vocab = vocab
+            .iter()
+            .enumerate()
+            .map(|(i, token)| (token.to_string(), i as u32))
+            .collect();
+        let tokenizer = Tokenizer::new(
+            WordPiece::from_vocab(vocab, Default::default()),
+            TokenizerOptions {
+                cls_token: Some("[CLS]"),
+                sep_token: Some("[SEP]"),
+            },
+        );
+
+        let model = fake_classify_model();
+        let pipeline = ClassifyPipeline::from_model(&model)
+            .unwrap()
+            .with_activation(Activation::None);
+        let results = pipeline
+            .classify(&tokenizer, &["good good", "good"], None)
+            .unwrap();
+
+        // Each sequence contains one `[CLS]` (ID 0) and one `[SEP]` (ID 1)
+        // token, plus the "good" (ID 2) tokens.
+        assert_eq!(results[0][0].index, 2);
+        assert_eq!(results[0][0].score, 2.);
+        assert_eq!(results[1][0].score, 1.);
+    }
+}
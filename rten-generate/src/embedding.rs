@@ -0,0 +1,303 @@
+//! Pipeline to compute fixed-size embeddings from sentence-transformer models.
+
+use rten_tensor::prelude::*;
+use rten_tensor::{NdTensor, NdTensorView};
+
+#[cfg(feature = "text-decoder")]
+use rten_text::tokenizers::Tokenizer;
+
+use crate::generator::TokenId;
+use crate::model::Model;
+#[cfg(feature = "text-decoder")]
+use crate::pipeline::encode_batch;
+use crate::pipeline::{BatchRunner, PipelineConfig, PipelineError};
+
+/// Strategy used to combine the per-token hidden states of a model into a
+/// single embedding for each sequence.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Pooling {
+    /// Average the hidden states of non-padding tokens.
+    #[default]
+    Mean,
+
+    /// Use the hidden state of the first token (eg. `[CLS]` in BERT models).
+    Cls,
+}
+
+/// Computes fixed-size embeddings for token sequences using a
+/// sentence-transformer style model.
+///
+/// The model is run on batches of sequences, which are padded to the same
+/// length, and its hidden states are pooled using the attention mask and
+/// then optionally normalized to unit length. The resulting embeddings can
+/// be compared using a dot product to get their cosine similarity.
+pub struct EmbeddingPipeline<'a> {
+    runner: BatchRunner<'a>,
+    pooling: Pooling,
+    normalize: bool,
+}
+
+impl<'a> EmbeddingPipeline<'a> {
+    /// Create a pipeline which uses default input and output names.
+    ///
+    /// The hidden states are read from the `last_hidden_state` output. By
+    /// default, sequences are processed in batches of 16, mean pooling is
+    /// used and embeddings are normalized.
+    pub fn from_model(model: &'a dyn Model) -> Result<EmbeddingPipeline<'a>, PipelineError> {
+        Self::from_model_config(model, PipelineConfig::new("last_hidden_state"))
+    }
+
+    /// Create a pipeline with custom input and output names.
+    ///
+    /// The output in `config` contains the `(batch, sequence, embed_dim)`
+    /// hidden states.
+    pub fn from_model_config(
+        model: &'a dyn Model,
+        config: PipelineConfig,
+    ) -> Result<EmbeddingPipeline<'a>, PipelineError> {
+        Ok(EmbeddingPipeline {
+            runner: BatchRunner::new(model, config)?,
+            pooling: Pooling::default(),
+            normalize: true,
+        })
+    }
+
+    /// Set the strategy used to pool hidden states.
+    pub fn with_pooling(mut self, pooling: Pooling) -> Self {
+        self.pooling = pooling;
+        self
+    }
+
+    /// Set whether embeddings are L2-normalized to unit length.
+    pub fn with_normalize(mut self, normalize: bool) -> Self {
+        self.normalize = normalize;
+        self
+    }
+
+    /// Set the maximum number of sequences that are passed to the model in a
+    /// single run.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.runner.set_batch_size(batch_size);
+        self
+    }
+
+    /// Compute embeddings for a batch of token ID sequences.
+    ///
+    /// Returns a `(batch, embed_dim)` tensor where `batch` is `sequences.len()`.
+    pub fn embed_tokens<S: AsRef<[TokenId]>>(
+        &self,
+        sequences: &[S],
+    ) -> Result<NdTensor<f32, 2>, PipelineError> {
+        let mut embeddings = Vec::new();
+        let mut embed_dim = 0;
+        self.runner.run_batches(
+            sequences,
+            |_, _| 0,
+            |batch, output| {
+                let hidden_states: NdTensor<f32, 3> = output.try_into().map_err(|_| {
+                    PipelineError::ShapeMismatch(
+                        "expected (batch, sequence, embed_dim) float hidden states".to_string(),
+                    )
+                })?;
+                if hidden_states.size(0) != batch.len() {
+                    return Err(PipelineError::ShapeMismatch(format!(
+                        "expected hidden states for {} sequences but got {:?}",
+                        batch.len(),
+                        hidden_states.shape()
+                    )));
+                }
+
+                embed_dim = hidden_states.size(2);
+                for (seq, item) in batch.iter().zip(hidden_states.axis_iter(0)) {
+                    let mut embedding = vec![0.; embed_dim];
+                    pool(item, seq.as_ref().len(), self.pooling, &mut embedding);
+                    if self.normalize {
+                        l2_normalize(&mut embedding);
+                    }
+                    embeddings.extend(embedding);
+                }
+                Ok(())
+            },
+        )?;
+        Ok(NdTensor::from_data(
+            [sequences.len(), embed_dim],
+            embeddings,
+        ))
+    }
+
+    /// Tokenize a batch of strings and compute their embeddings.
+    ///
+    /// If `max_len` is set, sequences are truncated to at most that many
+    /// tokens, including any special tokens that the tokenizer adds. Returns a
+    /// `(batch, embed_dim)` tensor where `batch` is `texts.len()`.
+    #[cfg(feature = "text-decoder")]
+    pub fn embed_text(
+        &self,
+        tokenizer: &Tokenizer,
+        texts: &[&str],
+        max_len: Option<usize>,
+    ) -> Result<NdTensor<f32, 2>, PipelineError> {
+        let (sequences, _type_ids) = encode_batch(tokenizer, texts, max_len)?;
+        self.embed_tokens(&sequences)
+    }
+}
+
+/// Pool the `(sequence, embed_dim)` hidden states of the first `seq_len`
+/// tokens of a sequence into `out`.
+fn pool(hidden_states: NdTensorView<f32, 2>, seq_len: usize, pooling: Pooling, out: &mut [f32]) {
+    match pooling {
+        Pooling::Mean => {
+            for token in hidden_states.axis_iter(0).take(seq_len) {
+                for (acc, x) in out.iter_mut().zip(token.iter()) {
+                    *acc += x;
+                }
+            }
+            let scale = 1. / seq_len.max(1) as f32;
+            out.iter_mut().for_each(|x| *x *= scale);
+        }
+        Pooling::Cls => {
+            if seq_len > 0 {
+                for (y, x) in out.iter_mut().zip(hidden_states.slice::<1, _>(0).iter()) {
+                    *y = *x;
+                }
+            }
+        }
+    }
+}
+
+/// Scale `xs` to have unit L2 norm. Zero vectors are left unchanged.
+fn l2_normalize(xs: &mut [f32]) {
+    let norm = xs.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0. {
+        xs.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rten::Output;
+    use rten_tensor::prelude::*;
+    use rten_tensor::NdTensor;
+    use rten_text::tokenizers::{Tokenizer, TokenizerOptions, WordPiece};
+
+    use super::{EmbeddingPipeline, Pooling};
+    use crate::test_util::{BatchRecordingModel, FnModel, FnModelInputs};
+
+    /// Create a fake model whose hidden state for a token with ID `id` at
+    /// position `pos` is `[id, pos]`, or zero if the attention mask is zero.
+    fn fake_embedding_model(
+        outputs: &[&str],
+    ) -> BatchRecordingModel<FnModel<impl Fn(&FnModelInputs) -> Vec<Output>>> {
+        BatchRecordingModel::new(FnModel::new(
+            &["input_ids", "attention_mask"],
+            outputs,
+            move |inputs: &FnModelInputs| {
+                let ids = inputs.int("input_ids");
+                let mask = inputs.int("attention_mask");
+                let [batch, seq] = ids.shape().try_into().unwrap();
+                let hidden = NdTensor::from_fn([batch, seq, 2], |[b, s, c]| {
+                    if mask[[b, s]] == 0 {
+                        return 0.;
+                    }
+                    if c == 0 {
+                        ids[[b, s]] as f32
+                    } else {
+                        s as f32
+                    }
+                });
+                vec![hidden.into_dyn().into()]
+            },
+        ))
+    }
+
+    #[test]
+    fn test_embed_tokens() {
+        let model = fake_embedding_model(&["last_hidden_state"]);
+        let sequences: [&[u32]; 3] = [&[4, 6, 8], &[3], &[]];
+
+        struct Case {
+            pooling: Pooling,
+            normalize: bool,
+            expected: [[f32; 2]; 3],
+        }
+
+        let cases = [
+            Case {
+                pooling: Pooling::Mean,
+                normalize: false,
+                expected: [[6., 1.], [3., 0.], [0., 0.]],
+            },
+            Case {
+                pooling: Pooling::Cls,
+                normalize: false,
+                expected: [[4., 0.], [3., 0.], [0., 0.]],
+            },
+            Case {
+                pooling: Pooling::Mean,
+                normalize: true,
+                expected: [[6. / 37f32.sqrt(), 1. / 37f32.sqrt()], [1., 0.], [0., 0.]],
+            },
+        ];
+
+        for Case {
+            pooling,
+            normalize,
+            expected,
+        } in cases
+        {
+            let pipeline = EmbeddingPipeline::from_model(&model)
+                .unwrap()
+                .with_pooling(pooling)
+                .with_normalize(normalize)
+                .with_batch_size(2);
+            let embeddings = pipeline.embed_tokens(&sequences).unwrap();
+            assert_eq!(embeddings.shape(), [3, 2]);
+            for (actual, expected) in embeddings.iter().zip(expected.iter().flatten()) {
+                assert!(
+                    (actual - expected).abs() < 1e-6,
+                    "{} != {}",
+                    actual,
+                    expected
+                );
+            }
+        }
+        assert_eq!(model.max_batch(), 2);
+    }
+
+    #[test]
+    fn test_embed_text() {
+        let vocab = &["[CLS]", "[SEP]", "one", "two"];
+        let vocab = vocab
+            .iter()
+            .enumerate()
+            .map(|(i, token)| (token.to_string(), i as u32))
+            .collect();
+        let tokenizer = Tokenizer::new(
+            WordPiece::from_vocab(vocab, Default::default()),
+            TokenizerOptions {
+                cls_token: Some("[CLS]"),
+                sep_token: Some("[SEP]"),
+            },
+        );
+
+        let model = fake_embedding_model(&["last_hidden_state"]);
+        let pipeline = EmbeddingPipeline::from_model(&model)
+            .unwrap()
+            .with_pooling(Pooling::Mean)
+            .with_normalize(false);
+        let embeddings = pipeline
+            .embed_text(&tokenizer, &["one one one", "one"], Some(3))
+            .unwrap();
+
+        // Truncation should keep the final `[SEP]` token, so the sequences
+        // are both `[CLS] one [SEP]`.
+        assert_eq!(embeddings.to_vec(), [1., 1., 1., 1.]);
+    }
+
+    #[test]
+    fn test_missing_output() {
+        let model = fake_embedding_model(&["logits"]);
+        let err = EmbeddingPipeline::from_model(&model).err().unwrap();
+        assert_eq!(err.to_string(), "model output not found: last_hidden_state");
+    }
+}
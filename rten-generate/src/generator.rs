@@ -2,22 +2,35 @@
 
 use std::error::Error;
 use std::fmt;
-use std::ops::Range;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::mem::MaybeUninit;
+use std::ops::{Deref, Range};
+use std::path::Path;
+use std::sync::Arc;
 
 use rten::{Dimension, Input, InputOrOutput, NodeId, Output};
 use rten_tensor::prelude::*;
-use rten_tensor::{NdTensor, Tensor};
+use rten_tensor::{NdTensor, NdTensorView, Tensor};
+use rten_vecmath::{vec_f16_to_f32, vec_f32_to_f16};
 
 #[cfg(feature = "text-decoder")]
 use rten_text::tokenizers::{Tokenizer, TokenizerError};
 
+use crate::logits::{suppress, LogitsProcessor, Temperature};
 use crate::metrics::Metrics;
 use crate::model::Model;
-use crate::sampler::{ArgMaxSampler, Sampler};
+use crate::sampler::{ArgMaxSampler, Sampler, TopKSampler};
+
+#[cfg(feature = "text-decoder")]
+use crate::stop_strings::StopStrings;
 
 #[cfg(feature = "text-decoder")]
 use crate::text_decoder::TextDecoder;
 
+#[cfg(feature = "text-decoder")]
+use crate::tool_call::{ToolCallFormat, ToolCallStream};
+
 /// Errors that occur when creating or running a [`Generator`].
 #[derive(Debug)]
 pub enum GeneratorError {
@@ -33,6 +46,9 @@ pub enum GeneratorError {
     /// An error occurred while generating the next token.
     GenerateError(Box<dyn Error>),
 
+    /// An error occurred while reading or writing generator state.
+    IoError(io::Error),
+
     /// An error occurred while decoding tokens.
     #[cfg(feature = "text-decoder")]
     DecodeError(TokenizerError),
@@ -48,6 +64,7 @@ impl fmt::Display for GeneratorError {
             GeneratorError::OutputNotFound(name) => write!(f, "model output not found: {}", name),
             GeneratorError::ShapeMismatch(err) => write!(f, "shape mismatch: {}", err),
             GeneratorError::GenerateError(err) => write!(f, "generation error: {}", err),
+            GeneratorError::IoError(err) => write!(f, "io error: {}", err),
             #[cfg(feature = "text-decoder")]
             GeneratorError::DecodeError(err) => write!(f, "decode error: {}", err),
         }
@@ -56,14 +73,224 @@ impl fmt::Display for GeneratorError {
 
 impl Error for GeneratorError {}
 
-enum KvCacheData {
+#[derive(Clone)]
+enum KvCacheData<T = f32> {
     /// Key-value cache with shape `[batch, seq_len, channels]`.
     ///
     /// In this configuration the channels for all heads are combined into the
     /// last dimension.
-    BatchSeqChans(NdTensor<f32, 3>),
+    BatchSeqChans(NdTensor<T, 3>),
     /// Key-value cache with shape `[batch, heads, seq_len, channels]`.
-    BatchHeadSeqChans(NdTensor<f32, 4>),
+    BatchHeadSeqChans(NdTensor<T, 4>),
+}
+
+impl<T: Copy> KvCacheData<T> {
+    /// Create a cache from a shape and elements in row-major order. Returns
+    /// `None` if the shape does not have 3 or 4 dims.
+    fn from_data(shape: &[usize], data: Vec<T>) -> Option<Self> {
+        match *shape {
+            [batch, seq, chans] => Some(KvCacheData::BatchSeqChans(NdTensor::from_data(
+                [batch, seq, chans],
+                data,
+            ))),
+            [batch, heads, seq, chans] => Some(KvCacheData::BatchHeadSeqChans(
+                NdTensor::from_data([batch, heads, seq, chans], data),
+            )),
+            _ => None,
+        }
+    }
+
+    fn shape(&self) -> Vec<usize> {
+        match self {
+            KvCacheData::BatchSeqChans(cache) => cache.shape().to_vec(),
+            KvCacheData::BatchHeadSeqChans(cache) => cache.shape().to_vec(),
+        }
+    }
+
+    /// Return the number of sequence positions in the cache.
+    fn seq_len(&self) -> usize {
+        match self {
+            KvCacheData::BatchSeqChans(cache) => cache.size(1),
+            KvCacheData::BatchHeadSeqChans(cache) => cache.size(2),
+        }
+    }
+
+    /// Return the shape of the cache, excluding the sequence dimension.
+    fn fixed_shape(&self) -> Vec<usize> {
+        match self {
+            KvCacheData::BatchSeqChans(cache) => vec![cache.size(0), cache.size(2)],
+            KvCacheData::BatchHeadSeqChans(cache) => {
+                vec![cache.size(0), cache.size(1), cache.size(3)]
+            }
+        }
+    }
+
+    /// Return the elements of the cache in row-major order.
+    fn to_vec(&self) -> Vec<T> {
+        match self {
+            KvCacheData::BatchSeqChans(cache) => cache.to_vec(),
+            KvCacheData::BatchHeadSeqChans(cache) => cache.to_vec(),
+        }
+    }
+
+    /// Convert the elements of the cache to another type using `convert`,
+    /// which must initialize all elements of its output.
+    fn convert<U: Copy>(&self, convert: impl Fn(&[T], &mut [MaybeUninit<U>])) -> KvCacheData<U> {
+        let src = self.to_vec();
+        let mut dst = Vec::with_capacity(src.len());
+        convert(&src, &mut dst.spare_capacity_mut()[..src.len()]);
+
+        // Safety: `convert` initialized all elements up to `src.len()`.
+        unsafe { dst.set_len(src.len()) };
+
+        KvCacheData::from_data(&self.shape(), dst).unwrap()
+    }
+
+    /// Ensure the cache has capacity for `n` more sequence positions, so that
+    /// the model can append to it without re-allocating.
+    ///
+    /// If `amortize` is true, the new capacity is at least double the current
+    /// length, so that the cost of re-allocation is amortized over many
+    /// generation steps.
+    fn reserve(&mut self, n: usize, amortize: bool) {
+        match self {
+            KvCacheData::BatchSeqChans(cache) => reserve_seq(cache, 1, n, amortize),
+            KvCacheData::BatchHeadSeqChans(cache) => reserve_seq(cache, 2, n, amortize),
+        }
+    }
+}
+
+/// Re-allocate `cache` if needed so that `seq_dim` can grow by `n` without
+/// re-allocating. See [`KvCacheData::reserve`].
+fn reserve_seq<T: Copy, const N: usize>(
+    cache: &mut NdTensor<T, N>,
+    seq_dim: usize,
+    n: usize,
+    amortize: bool,
+) {
+    let len = cache.size(seq_dim);
+    if cache.has_capacity(seq_dim, len + n) {
+        return;
+    }
+    let mut shape = cache.shape();
+    shape[seq_dim] = if amortize {
+        (len + n).max(len * 2)
+    } else {
+        len + n
+    };
+    let mut grown = NdTensor::with_capacity(shape, seq_dim);
+    grown.append(seq_dim, cache).expect("should have capacity");
+    *cache = grown;
+}
+
+/// Determine the number of heads and channels of a key-value cache from the
+/// shapes of its model input and output.
+///
+/// Returns `(Some(heads), chans)` for caches with shape `[batch, heads,
+/// seq, chans]` and `(None, chans)` for caches with shape `[batch, seq,
+/// chans]`. The heads and channels are not necessarily the same as those of
+/// the model's attention layers, or the same for keys and values. For example
+/// models using grouped-query attention have fewer key-value heads than
+/// attention heads. Some exports only specify fixed sizes on one of the input
+/// or output, so each size is taken from whichever specifies it. If `output`
+/// is empty, only the input shape is used.
+fn kv_cache_size(input: &[Dimension], output: &[Dimension]) -> Option<(Option<usize>, usize)> {
+    if !output.is_empty() && output.len() != input.len() {
+        return None;
+    }
+    let fixed_size = |dim: usize| {
+        [input.get(dim), output.get(dim)]
+            .into_iter()
+            .flatten()
+            .find_map(|size| match size {
+                Dimension::Fixed(size) => Some(*size),
+                Dimension::Symbolic(_) => None,
+            })
+    };
+    match input.len() {
+        4 => Some((Some(fixed_size(1)?), fixed_size(3)?)),
+        3 => Some((None, fixed_size(2)?)),
+        _ => None,
+    }
+}
+
+/// Data type used to store the key-value cache.
+///
+/// See [`GeneratorConfig::kv_cache_dtype`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum KvCacheDtype {
+    /// Store the cache as 32-bit floats.
+    #[default]
+    F32,
+
+    /// Store the cache as 16-bit floats.
+    ///
+    /// This halves the memory used to hold the cache between model runs,
+    /// including in [`GeneratorState`] snapshots and saved state files. The
+    /// model receives and returns the cache as 32-bit floats, so the cache is
+    /// converted before and after each run, and the 32-bit copy exists
+    /// temporarily while the model runs.
+    ///
+    /// Cross-attention caches are still stored as 32-bit floats, as they
+    /// are reused unchanged in every step after they are computed.
+    F16,
+}
+
+/// Key-value cache for a layer, in the data type used for storage.
+#[derive(Clone)]
+enum KvCacheStorage {
+    F32(KvCacheData<f32>),
+
+    /// Half-precision floats, stored as their bit patterns.
+    F16(KvCacheData<u16>),
+}
+
+impl KvCacheStorage {
+    /// Return the cache as 32-bit floats, with capacity for `n` more
+    /// sequence positions.
+    fn into_f32(self, n: usize) -> KvCacheData<f32> {
+        match self {
+            KvCacheStorage::F32(mut cache) => {
+                cache.reserve(n, true /* amortize */);
+                cache
+            }
+            KvCacheStorage::F16(cache) => {
+                let mut cache = cache.convert(vec_f16_to_f32);
+                cache.reserve(n, false /* amortize */);
+                cache
+            }
+        }
+    }
+
+    fn seq_len(&self) -> usize {
+        match self {
+            KvCacheStorage::F32(cache) => cache.seq_len(),
+            KvCacheStorage::F16(cache) => cache.seq_len(),
+        }
+    }
+
+    fn dtype(&self) -> KvCacheDtype {
+        match self {
+            KvCacheStorage::F32(_) => KvCacheDtype::F32,
+            KvCacheStorage::F16(_) => KvCacheDtype::F16,
+        }
+    }
+
+    /// Return the shape of the cache, excluding the sequence dimension.
+    fn fixed_shape(&self) -> Vec<usize> {
+        match self {
+            KvCacheStorage::F32(cache) => cache.fixed_shape(),
+            KvCacheStorage::F16(cache) => cache.fixed_shape(),
+        }
+    }
+
+    /// Convert a cache returned by the model to the storage type `dtype`.
+    fn from_f32(cache: KvCacheData<f32>, dtype: KvCacheDtype) -> KvCacheStorage {
+        match dtype {
+            KvCacheDtype::F32 => KvCacheStorage::F32(cache),
+            KvCacheDtype::F16 => KvCacheStorage::F16(cache.convert(vec_f32_to_f16)),
+        }
+    }
 }
 
 /// Key-value cache for a single layer of a transformer model.
@@ -76,18 +303,46 @@ struct KvCache {
 
     /// The cached keys and values. This is set to `None` during inference, as
     /// the model temporarily takes ownership of it.
-    cache: Option<KvCacheData>,
+    cache: Option<KvCacheStorage>,
+
+    /// True if this is a cross-attention cache, whose keys and values depend
+    /// only on the encoder output.
+    cross_attention: bool,
+
+    /// Data type used to store the cache between model runs.
+    ///
+    /// Cross-attention caches are always stored as f32, as they are computed
+    /// once and then passed to the model unchanged in every later step.
+    dtype: KvCacheDtype,
+}
+
+impl KvCache {
+    /// Return true if this cache doesn't change in later steps, because it is
+    /// a cross-attention cache that has already been computed.
+    fn is_fixed(&self) -> bool {
+        self.cross_attention && self.cache.as_ref().is_some_and(|cache| cache.seq_len() > 0)
+    }
 }
 
 /// Specifies a pattern for the name of a key-value cache input or output.
 ///
 /// These inputs are expected to have the form `{prefix}{layer_number}{suffix}`,
 /// with one input and output per layer for the key cache and the value cache.
+#[derive(Clone)]
 pub struct KVCachePattern<'a> {
     pub prefix: &'a str,
     pub suffix: &'a str,
 }
 
+impl KVCachePattern<'_> {
+    /// Return true if `name` has the prefix and suffix of this pattern.
+    fn matches(&self, name: &str) -> bool {
+        name.len() >= self.prefix.len() + self.suffix.len()
+            && name.starts_with(self.prefix)
+            && name.ends_with(self.suffix)
+    }
+}
+
 impl<'a> From<(&'a str, &'a str)> for KVCachePattern<'a> {
     /// Construct a [`KVCachePattern`] from a `(prefix, suffix)` tuple.
     fn from(value: (&'a str, &'a str)) -> Self {
@@ -102,6 +357,7 @@ impl<'a> From<(&'a str, &'a str)> for KVCachePattern<'a> {
 /// follow the configuration of Hugging Face's Optimum tool.
 ///
 /// Any inputs that are not present in the model are ignored.
+#[derive(Clone)]
 pub struct ModelInputsConfig<'a> {
     /// Model input that contains the token IDs of the prompt and output
     /// generated so far.
@@ -127,13 +383,61 @@ pub struct ModelInputsConfig<'a> {
 
     /// Pattern for value cache outputs.
     pub value_cache_output: KVCachePattern<'a>,
+
+    /// Pattern for cross-attention key cache inputs in encoder-decoder
+    /// models.
+    ///
+    /// Cross-attention caches depend only on the encoder output, so the
+    /// corresponding output is only computed on the first run of the model.
+    /// The result is then passed unchanged in subsequent steps. Inputs which
+    /// match this pattern are not treated as self-attention caches, even if
+    /// they also match [`key_cache`](Self::key_cache).
+    pub encoder_key_cache: KVCachePattern<'a>,
+
+    /// Pattern for cross-attention key cache outputs.
+    pub encoder_key_cache_output: KVCachePattern<'a>,
+
+    /// Pattern for cross-attention value cache inputs. See
+    /// [`encoder_key_cache`](Self::encoder_key_cache).
+    pub encoder_value_cache: KVCachePattern<'a>,
+
+    /// Pattern for cross-attention value cache outputs.
+    pub encoder_value_cache_output: KVCachePattern<'a>,
 }
 
 /// Contains essential configuration needed for a `Generator` to execute a
 /// model, such as the roles of different inputs and outputs.
+#[derive(Clone)]
 pub struct GeneratorConfig<'a> {
     /// Specifies names and roles of model inputs and outputs.
     pub model_inputs: ModelInputsConfig<'a>,
+
+    /// Number of sequence positions to allocate space for in the key-value
+    /// cache when the generator is created.
+    ///
+    /// If the sequence grows beyond this length, the cache is re-allocated
+    /// with a larger capacity. Setting this to the expected maximum length
+    /// avoids the cost of re-allocation.
+    pub kv_cache_capacity: usize,
+
+    /// Data type used to store the key-value cache.
+    ///
+    /// When this is [`KvCacheDtype::F16`], `kv_cache_capacity` is not used.
+    /// Capacity for each step is reserved when the cache is converted to
+    /// 32-bit floats before the model is run.
+    pub kv_cache_dtype: KvCacheDtype,
+}
+
+impl Default for GeneratorConfig<'_> {
+    /// Return the default configuration, with default model input names and
+    /// a key-value cache capacity of 512 positions.
+    fn default() -> Self {
+        GeneratorConfig {
+            model_inputs: ModelInputsConfig::default(),
+            kv_cache_capacity: 512,
+            kv_cache_dtype: KvCacheDtype::F32,
+        }
+    }
 }
 
 impl<'a> Default for ModelInputsConfig<'a> {
@@ -151,6 +455,27 @@ impl<'a> Default for ModelInputsConfig<'a> {
             key_cache_output: ("present.", ".key").into(),
             value_cache: ("past_key_values.", ".value").into(),
             value_cache_output: ("present.", ".value").into(),
+            encoder_key_cache: ("past_key_values.", ".encoder.key").into(),
+            encoder_key_cache_output: ("present.", ".encoder.key").into(),
+            encoder_value_cache: ("past_key_values.", ".encoder.value").into(),
+            encoder_value_cache_output: ("present.", ".encoder.value").into(),
+        }
+    }
+}
+
+/// Model used by a [`Generator`], which is either borrowed or shared.
+enum ModelRef<'a> {
+    Borrowed(&'a dyn Model),
+    Shared(Arc<dyn Model + 'a>),
+}
+
+impl<'a> Deref for ModelRef<'a> {
+    type Target = dyn Model + 'a;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            ModelRef::Borrowed(model) => *model,
+            ModelRef::Shared(model) => model.as_ref(),
         }
     }
 }
@@ -169,6 +494,16 @@ impl<'a> Default for ModelInputsConfig<'a> {
 /// `generator.take(30)` will return an iterator that stops generation after 30
 /// tokens have been produced).
 ///
+/// ## Output length
+///
+/// The number of generated tokens can be limited using
+/// [`with_max_new_tokens`](Self::with_max_new_tokens). To prevent the model
+/// from ending its output too early, use
+/// [`with_min_new_tokens`](Self::with_min_new_tokens) to suppress end-of-text
+/// tokens until a minimum number of tokens have been generated. Unlike
+/// filtering the output, this removes the end-of-text tokens from the
+/// distribution before sampling, so the sampler picks a different token.
+///
 /// ## Sampling
 ///
 /// The token ID is sampled from the outputs of the model (the "logits") using
@@ -176,6 +511,11 @@ impl<'a> Default for ModelInputsConfig<'a> {
 /// the token with the highest probability. The sampler can be configured using
 /// [`with_sampler`](Self::with_sampler).
 ///
+/// Before sampling, the logits can be modified using [`LogitsProcessor`]s
+/// added with [`with_logits_processor`](Self::with_logits_processor), for
+/// example to prevent certain tokens from being generated, and scaled by a
+/// temperature set using [`with_temperature`](Self::with_temperature).
+///
 /// ## Key-value caches and generation performance
 ///
 /// To enable efficient decoding, the model should have inputs and outputs for
@@ -183,8 +523,22 @@ impl<'a> Default for ModelInputsConfig<'a> {
 /// cache](https://peterchng.com/blog/2024/06/11/what-is-the-transformer-kv-cache/).
 /// The generator will work with models that do not have cache inputs, but
 /// decoding of long output sequences will be much slower.
+///
+/// For encoder-decoder models, cross-attention caches whose names match
+/// [`ModelInputsConfig::encoder_key_cache`] and
+/// [`ModelInputsConfig::encoder_value_cache`] are computed on the first run
+/// of the model and then passed unchanged in later steps.
+///
+/// ## Reusing a prompt prefix
+///
+/// When many generations share a prefix, such as a long system prompt in a
+/// chat application, the prefix only needs to be processed once. Set the
+/// prefix as the prompt, call [`prefill`](Self::prefill) to run the model on
+/// it and [`state`](Self::state) to save the key-value cache. Each subsequent
+/// generation can then call [`restore_state`](Self::restore_state) followed
+/// by [`append_prompt`](Self::append_prompt) to add the rest of its input.
 pub struct Generator<'a> {
-    model: &'a dyn Model,
+    model: ModelRef<'a>,
 
     /// Additional constant model inputs (eg. encoder outputs) passed to the
     /// model at each step.
@@ -212,9 +566,32 @@ pub struct Generator<'a> {
     // Sampler used to get the next token ID from the output logits.
     sampler: Box<dyn Sampler>,
 
+    /// Processors applied to the output logits before sampling.
+    logits_processors: Vec<Box<dyn LogitsProcessor>>,
+
+    /// Temperature applied to the output logits after `logits_processors`.
+    temperature: Option<Temperature>,
+
     /// Length of the sequence generated so far.
     seq_len: u32,
 
+    /// Tokens generated since the prompt was last set or extended.
+    output_ids: Vec<TokenId>,
+
+    /// Minimum number of tokens to generate before any token in
+    /// `eos_tokens` can be sampled.
+    min_new_tokens: usize,
+
+    /// Tokens which are suppressed until `min_new_tokens` tokens have been
+    /// generated.
+    eos_tokens: Vec<TokenId>,
+
+    /// Maximum number of tokens to generate.
+    max_new_tokens: Option<usize>,
+
+    /// Maximum number of input tokens to process in one run of the model.
+    prefill_chunk_size: Option<usize>,
+
     /// Key-value cache.
     kv_cache: Vec<KvCache>,
 }
@@ -251,10 +628,29 @@ impl<'a> Generator<'a> {
     ///  - `present.N.key` - (batch, head, past_seq_len + 1, size) updated key vector cache
     ///  - `present.N.value` - (batch, head, past_seq_len + 1, size) updated value vector cache
     pub fn from_model(model: &'a dyn Model) -> Result<Generator<'a>, GeneratorError> {
-        let config = GeneratorConfig {
-            model_inputs: ModelInputsConfig::default(),
-        };
-        Self::from_model_config(model, config)
+        Self::from_model_config(model, GeneratorConfig::default())
+    }
+
+    /// Create a generator which shares ownership of a model.
+    ///
+    /// This is like [`from_model`](Self::from_model), except that the
+    /// generator keeps the model alive instead of borrowing it. This is
+    /// useful when the generator needs to outlive the scope that loaded the
+    /// model, for example in bindings for other languages.
+    pub fn from_shared_model(model: Arc<dyn Model + 'a>) -> Result<Generator<'a>, GeneratorError> {
+        Self::from_shared_model_config(model, GeneratorConfig::default())
+    }
+
+    /// Create a generator which shares ownership of a model, using custom
+    /// names for model inputs.
+    ///
+    /// See [`from_shared_model`](Self::from_shared_model) and
+    /// [`from_model_config`](Self::from_model_config).
+    pub fn from_shared_model_config(
+        model: Arc<dyn Model + 'a>,
+        config: GeneratorConfig,
+    ) -> Result<Generator<'a>, GeneratorError> {
+        Self::from_model_ref(ModelRef::Shared(model), config)
     }
 
     /// Create a generator that iteratively produces tokens using a model.
@@ -265,6 +661,14 @@ impl<'a> Generator<'a> {
         model: &'a dyn Model,
         config: GeneratorConfig,
     ) -> Result<Generator<'a>, GeneratorError> {
+        Self::from_model_ref(ModelRef::Borrowed(model), config)
+    }
+
+    fn from_model_ref(
+        model_ref: ModelRef<'a>,
+        config: GeneratorConfig,
+    ) -> Result<Generator<'a>, GeneratorError> {
+        let model: &dyn Model = &*model_ref;
         let model_inputs = &config.model_inputs;
 
         let input_ids_input =
@@ -293,30 +697,39 @@ impl<'a> Generator<'a> {
                 )))?;
 
             let name = input_info.name();
-            let is_key_cache = name.starts_with(model_inputs.key_cache.prefix)
-                && name.ends_with(model_inputs.key_cache.suffix);
-            let is_value_cache = name.starts_with(model_inputs.value_cache.prefix)
-                && name.ends_with(model_inputs.value_cache.suffix);
 
-            if !is_key_cache && !is_value_cache {
+            // Cross-attention patterns are checked first, as their names
+            // may also match the self-attention patterns.
+            let patterns = [
+                (
+                    &model_inputs.encoder_key_cache,
+                    &model_inputs.encoder_key_cache_output,
+                    true,
+                ),
+                (
+                    &model_inputs.encoder_value_cache,
+                    &model_inputs.encoder_value_cache_output,
+                    true,
+                ),
+                (
+                    &model_inputs.key_cache,
+                    &model_inputs.key_cache_output,
+                    false,
+                ),
+                (
+                    &model_inputs.value_cache,
+                    &model_inputs.value_cache_output,
+                    false,
+                ),
+            ];
+            let Some((pattern, output_pattern, cross_attention)) = patterns
+                .into_iter()
+                .find(|(pattern, _, _)| pattern.matches(name))
+            else {
                 continue;
-            }
-
-            let (n_heads, size) = match *input_info.shape() {
-                [_, Dimension::Fixed(n_heads), _, Dimension::Fixed(size)] => (Some(n_heads), size),
-                [_, _, Dimension::Fixed(size)] => (None, size),
-                _ => {
-                    return Err(GeneratorError::ShapeMismatch(format!("input \"{}\" has unexpected shape. expected (batch, past_seq_len, chans) or (batch, heads, past_seq_len, chans) where `heads` and `size` are fixed", name)));
-                }
-            };
-
-            let prefix = if is_key_cache {
-                model_inputs.key_cache.prefix
-            } else {
-                model_inputs.value_cache.prefix
             };
 
-            let layer_index_start = prefix.len();
+            let layer_index_start = pattern.prefix.len();
             let layer_index_str: String = name[layer_index_start..]
                 .chars()
                 .take_while(|ch| ch.is_ascii_digit())
@@ -325,45 +738,71 @@ impl<'a> Generator<'a> {
                 continue;
             };
 
-            let (output_prefix, output_suffix) = if is_key_cache {
-                (
-                    model_inputs.key_cache_output.prefix,
-                    model_inputs.key_cache_output.suffix,
-                )
-            } else {
-                (
-                    model_inputs.value_cache_output.prefix,
-                    model_inputs.value_cache_output.suffix,
-                )
-            };
-
-            let output_name = format!("{}{}{}", output_prefix, layer_index, output_suffix);
+            let output_name = format!(
+                "{}{}{}",
+                output_pattern.prefix, layer_index, output_pattern.suffix
+            );
             let output_id = model
                 .find_node(&output_name)
                 .ok_or(GeneratorError::OutputNotFound(output_name))?;
+            let output_info = model.node_info(output_id);
+            let output_shape = output_info.as_ref().map(|info| info.shape()).unwrap_or(&[]);
 
-            // This value should be configurable.
-            let max_seq_len = 512;
+            let Some((n_heads, size)) = kv_cache_size(input_info.shape(), output_shape) else {
+                return Err(GeneratorError::ShapeMismatch(format!("input \"{}\" has unexpected shape. expected (batch, past_seq_len, chans) or (batch, heads, past_seq_len, chans) where `heads` and `chans` are fixed in the input or output", name)));
+            };
 
-            kv_cache.push(KvCache {
-                input_id,
-                output_id,
-                cache: if let Some(n_heads) = n_heads {
-                    Some(KvCacheData::BatchHeadSeqChans(NdTensor::with_capacity(
-                        [batch_size, n_heads, max_seq_len, size],
+            // Cross-attention caches are computed all at once, so no space
+            // is reserved for them to grow.
+            let capacity = if cross_attention {
+                0
+            } else {
+                config.kv_cache_capacity
+            };
+            let dtype = if cross_attention {
+                KvCacheDtype::F32
+            } else {
+                config.kv_cache_dtype
+            };
+            let cache = match (dtype, n_heads) {
+                (KvCacheDtype::F32, Some(n_heads)) => {
+                    KvCacheStorage::F32(KvCacheData::BatchHeadSeqChans(NdTensor::with_capacity(
+                        [batch_size, n_heads, capacity, size],
                         2, /* seq dim */
                     )))
-                } else {
-                    Some(KvCacheData::BatchSeqChans(NdTensor::with_capacity(
-                        [batch_size, max_seq_len, size],
+                }
+                (KvCacheDtype::F32, None) => {
+                    KvCacheStorage::F32(KvCacheData::BatchSeqChans(NdTensor::with_capacity(
+                        [batch_size, capacity, size],
                         1, /* seq dim */
                     )))
-                },
+                }
+                (KvCacheDtype::F16, Some(n_heads)) => {
+                    KvCacheStorage::F16(KvCacheData::BatchHeadSeqChans(NdTensor::zeros([
+                        batch_size, n_heads, 0, size,
+                    ])))
+                }
+                (KvCacheDtype::F16, None) => {
+                    KvCacheStorage::F16(KvCacheData::BatchSeqChans(NdTensor::zeros([
+                        batch_size, 0, size,
+                    ])))
+                }
+            };
+
+            kv_cache.push(KvCache {
+                input_id,
+                output_id,
+                cache: Some(cache),
+                cross_attention,
+                dtype,
             });
         }
 
+        let attention_mask_input = model.find_node(model_inputs.attention_mask);
+        let position_ids_input = model.find_node(model_inputs.position_ids);
+
         let mut generator = Generator {
-            model,
+            model: model_ref,
             constant_inputs: Vec::new(),
             varying_inputs: Vec::new(),
 
@@ -377,10 +816,16 @@ impl<'a> Generator<'a> {
             logits_output,
             kv_cache,
             seq_len: 0,
+            output_ids: Vec::new(),
+            min_new_tokens: 0,
+            eos_tokens: Vec::new(),
+            max_new_tokens: None,
+            prefill_chunk_size: None,
             sampler: Box::new(ArgMaxSampler {}),
+            logits_processors: Vec::new(),
+            temperature: None,
         };
 
-        let attention_mask_input = model.find_node(model_inputs.attention_mask);
         if let Some(attention_mask_input) = attention_mask_input {
             generator = generator
                 .with_varying_input(attention_mask_input, &|batch_size, positions| {
@@ -388,7 +833,6 @@ impl<'a> Generator<'a> {
                 });
         }
 
-        let position_ids_input = model.find_node(model_inputs.position_ids);
         if let Some(position_ids_input) = position_ids_input {
             generator =
                 generator.with_varying_input(position_ids_input, &|batch_size, positions| {
@@ -409,6 +853,7 @@ impl<'a> Generator<'a> {
     /// [`append_prompt`](Self::append_prompt) instead.
     pub fn with_prompt(mut self, prompt: &[TokenId]) -> Self {
         self.input_ids = prompt.to_vec();
+        self.output_ids.clear();
         self
     }
 
@@ -416,8 +861,51 @@ impl<'a> Generator<'a> {
     ///
     /// This is useful in applications such as chat where the model's input
     /// alternates between encoded user input and model-generated output.
+    ///
+    /// This resets the count of generated tokens used by
+    /// [`with_min_new_tokens`](Self::with_min_new_tokens) and
+    /// [`with_max_new_tokens`](Self::with_max_new_tokens), so the limits
+    /// apply to each response.
     pub fn append_prompt(&mut self, prompt: &[TokenId]) {
         self.input_ids.extend(prompt);
+        self.output_ids.clear();
+    }
+
+    /// Prevent tokens in `eos_tokens` from being generated until at least
+    /// `min_new_tokens` tokens have been generated.
+    ///
+    /// The logits for the end-of-text tokens are set to negative infinity
+    /// before sampling, so that the sampler chooses from the other tokens.
+    pub fn with_min_new_tokens(mut self, min_new_tokens: usize, eos_tokens: &[TokenId]) -> Self {
+        self.min_new_tokens = min_new_tokens;
+        self.eos_tokens = eos_tokens.to_vec();
+        self
+    }
+
+    /// Stop generation after `max_new_tokens` tokens have been generated.
+    ///
+    /// Once the limit is reached, the generator returns `None` until the
+    /// prompt is extended using [`append_prompt`](Self::append_prompt).
+    pub fn with_max_new_tokens(mut self, max_new_tokens: usize) -> Self {
+        self.max_new_tokens = Some(max_new_tokens);
+        self
+    }
+
+    /// Process long prompts in chunks of at most `chunk_size` tokens.
+    ///
+    /// By default all pending input tokens are processed in one run of the
+    /// model. For long prompts this requires a lot of memory for the
+    /// intermediate values computed by the model. When a chunk size is set,
+    /// the prompt is instead processed in several runs, each of which
+    /// appends to the key-value cache, before the first token is generated.
+    ///
+    /// This has no effect if the model has no key-value cache inputs.
+    ///
+    /// Panics if `chunk_size` is zero.
+    pub fn with_prefill_chunk_size(mut self, chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "chunk size must be > 0");
+        self.prefill_chunk_size = Some(chunk_size);
+        self
     }
 
     /// Add a constant input which is provided to the model at each iteration.
@@ -430,6 +918,17 @@ impl<'a> Generator<'a> {
         self
     }
 
+    /// Add a constant input which may be owned by the generator.
+    pub(crate) fn with_constant_value(
+        mut self,
+        input_id: NodeId,
+        value: InputOrOutput<'a>,
+    ) -> Self {
+        self.constant_prop_inputs = None;
+        self.constant_inputs.push((input_id, value));
+        self
+    }
+
     /// Add an input which varies with the sequence position.
     ///
     /// `value_fn` receives `(batch_size, sequence_positions)` as input and
@@ -452,48 +951,347 @@ impl<'a> Generator<'a> {
         self
     }
 
-    /// Run the model and generate the next token.
-    fn generate_next_token(&mut self) -> Result<TokenId, GeneratorError> {
-        fn wrap_error<E>(e: E) -> GeneratorError
-        where
-            E: Into<Box<dyn Error>>,
-        {
-            GeneratorError::GenerateError(e.into())
-        }
-
-        let batch_size = 1;
-        let input_ids: NdTensor<i32, 2> = self
-            .input_ids
-            .iter()
-            .map(|id| *id as i32)
-            .collect::<Tensor<_>>()
-            .into_shape([batch_size, self.input_ids.len()]);
+    /// Configure sampling and stop conditions, and return an iterator over
+    /// the generated tokens.
+    ///
+    /// If `top_k` is set, tokens are sampled from the `top_k` most likely
+    /// tokens after applying `temperature`, otherwise the most likely token is
+    /// chosen. Generation stops after `max_tokens` tokens, or when a token in
+    /// `stop_tokens` is generated. The stop token is not included in the
+    /// output.
+    ///
+    /// This covers the options exposed by the C, Python and WebAssembly
+    /// bindings.
+    pub fn sample_tokens(
+        self,
+        max_tokens: usize,
+        top_k: Option<usize>,
+        temperature: f32,
+        stop_tokens: Vec<TokenId>,
+    ) -> impl Iterator<Item = GeneratorItem> + 'a {
+        let generator = match top_k {
+            Some(k) => self.with_sampler(TopKSampler::new(k, temperature)),
+            None => self.with_sampler(ArgMaxSampler::new()),
+        };
+        generator.stop_on_tokens(stop_tokens).take(max_tokens)
+    }
 
-        let seq_range = (self.seq_len as usize)..(self.seq_len as usize + self.input_ids.len());
+    /// Set the temperature used to scale the output logits before sampling.
+    ///
+    /// The logits are divided by the temperature after any processors added
+    /// with [`with_logits_processor`](Self::with_logits_processor) have been
+    /// applied, and before the sampler is called. This allows the
+    /// temperature to be combined with any sampler, in the same way as the
+    /// `temperature`, `top_k` and `top_p` settings in a Hugging Face
+    /// `generation_config.json` file. Samplers which have their own
+    /// temperature setting should then use a temperature of 1.0.
+    ///
+    /// A temperature of zero makes generation deterministic, by keeping only
+    /// the most likely token. Panics if the temperature is negative.
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(Temperature::new(temperature));
+        self
+    }
 
-        let mut model_inputs: Vec<(NodeId, InputOrOutput)> =
-            vec![(self.input_ids_input, input_ids.view().into())];
+    /// Add a processor which modifies the output logits before sampling.
+    ///
+    /// Processors are applied in the order they are added.
+    pub fn with_logits_processor<P: LogitsProcessor + 'static>(mut self, processor: P) -> Self {
+        self.logits_processors.push(Box::new(processor));
+        self
+    }
 
-        // Propagate constants on the first run.
-        if self.constant_prop_inputs.is_none() {
-            let inputs = match self
-                .model
-                .partial_run(self.constant_inputs.clone(), &[self.logits_output])
-            {
-                Ok(inputs) => inputs,
-                Err(err) => {
-                    return Err(wrap_error(err));
-                }
+    /// Compute the log-probabilities of a sequence of tokens under the model.
+    ///
+    /// This runs the model on `tokens` as if they had been generated, and
+    /// returns the natural log of the probability that the model assigns to
+    /// each token, given the prompt and all preceding tokens. The tokens are
+    /// added to the sequence, so a long sequence can be scored incrementally
+    /// by calling this repeatedly with successive chunks, reusing the
+    /// key-value cache. Generation can also continue afterwards.
+    ///
+    /// The first token of a sequence has no context, so it cannot be scored.
+    /// If there is no prompt and no tokens have been generated or scored
+    /// previously, the first token is used only as context and the result
+    /// has one fewer entry than `tokens`.
+    ///
+    /// Use [`Perplexity`] to compute perplexity from the results.
+    pub fn score_tokens(&mut self, tokens: &[TokenId]) -> Result<Vec<f32>, GeneratorError> {
+        let tokens = if self.input_ids.is_empty() && self.seq_len == 0 {
+            let Some((&first, rest)) = tokens.split_first() else {
+                return Ok(Vec::new());
             };
-            self.constant_prop_inputs = Some(inputs);
+            self.input_ids.push(first);
+            rest
+        } else {
+            tokens
+        };
+        let Some((&last, context)) = tokens.split_last() else {
+            return Ok(Vec::new());
+        };
+
+        // The logits at each position predict the token at the next
+        // position, so the last `tokens.len()` positions of the output
+        // contain the predictions for `tokens`.
+        self.input_ids.extend(context);
+        let logits = self.run_model()?;
+        let n_pos = logits.size(1);
+        let log_probs = tokens
+            .iter()
+            .enumerate()
+            .map(|(i, &token)| {
+                let pos_logits = logits.slice::<1, _>((0, n_pos - tokens.len() + i));
+                log_softmax_at(pos_logits.iter().copied(), token as usize)
+            })
+            .collect::<Option<Vec<f32>>>()
+            .ok_or_else(|| {
+                GeneratorError::ShapeMismatch("token ID is outside of vocabulary".to_string())
+            })?;
+        self.advance(last);
+
+        Ok(log_probs)
+    }
+
+    /// Run the model on the pending prompt tokens to fill the key-value
+    /// cache, without generating a token.
+    ///
+    /// The last prompt token is kept as input for the next step, as the
+    /// model's output for it is needed to generate the first token. This has
+    /// no effect if the model has no key-value cache inputs.
+    pub fn prefill(&mut self) -> Result<(), GeneratorError> {
+        self.prefill_chunks()?;
+        if self.kv_cache.is_empty() || self.input_ids.len() < 2 {
+            return Ok(());
         }
+        let last = self.input_ids.pop().unwrap();
+        self.run_model()?;
+        self.advance(last);
+        Ok(())
+    }
 
-        if let Some(constants) = self.constant_prop_inputs.as_ref() {
-            model_inputs.extend(
-                constants
-                    .iter()
-                    .map(|(node_id, output)| (*node_id, output.as_input().into())),
-            );
+    /// Save the current key-value cache, sequence position and pending input
+    /// tokens.
+    ///
+    /// The state can be restored using [`restore_state`](Self::restore_state)
+    /// on this or another generator for the same model.
+    pub fn state(&self) -> GeneratorState {
+        GeneratorState {
+            kv_cache: self
+                .kv_cache
+                .iter()
+                .map(|entry| entry.cache.clone())
+                .collect(),
+            seq_len: self.seq_len,
+            input_ids: self.input_ids.clone(),
+        }
+    }
+
+    /// Restore state saved by [`state`](Self::state).
+    ///
+    /// This replaces the key-value cache, sequence position and pending
+    /// input tokens, and resets the count of generated tokens. Returns an
+    /// error if the state's key-value cache entries do not match the number,
+    /// data type or shape (other than the sequence length) of this
+    /// generator's cache.
+    pub fn restore_state(&mut self, state: &GeneratorState) -> Result<(), GeneratorError> {
+        if state.kv_cache.len() != self.kv_cache.len() {
+            return Err(GeneratorError::ShapeMismatch(format!(
+                "state has {} key-value cache entries but model has {}",
+                state.kv_cache.len(),
+                self.kv_cache.len()
+            )));
+        }
+        for (i, (entry, cache)) in self.kv_cache.iter().zip(&state.kv_cache).enumerate() {
+            let (Some(expected), Some(cache)) = (&entry.cache, cache) else {
+                continue;
+            };
+            if cache.dtype() != expected.dtype() {
+                return Err(GeneratorError::ShapeMismatch(format!(
+                    "key-value cache entry {} has data type {:?} but generator uses {:?}",
+                    i,
+                    cache.dtype(),
+                    expected.dtype()
+                )));
+            }
+            if cache.fixed_shape() != expected.fixed_shape() {
+                return Err(GeneratorError::ShapeMismatch(format!(
+                    "key-value cache entry {} has batch, head and channel sizes {:?} but generator uses {:?}",
+                    i,
+                    cache.fixed_shape(),
+                    expected.fixed_shape()
+                )));
+            }
+        }
+        for (entry, cache) in self.kv_cache.iter_mut().zip(&state.kv_cache) {
+            entry.cache = cache.clone();
+        }
+        self.seq_len = state.seq_len;
+        self.input_ids = state.input_ids.clone();
+        self.output_ids.clear();
+        Ok(())
+    }
+
+    /// Return the tokens generated since the prompt was last set or
+    /// extended.
+    pub fn output_ids(&self) -> &[TokenId] {
+        &self.output_ids
+    }
+
+    /// Save a checkpoint which can be returned to using
+    /// [`rewind`](Self::rewind).
+    ///
+    /// Unlike [`state`](Self::state), the checkpoint also includes the tokens
+    /// generated so far, so that generation can continue from the checkpoint
+    /// as if the tokens generated after it had not been produced. This
+    /// enables retrying generation when the output is rejected, or exploring
+    /// several continuations of the same output.
+    ///
+    /// A checkpoint contains a copy of the key-value cache, so its size grows
+    /// with the length of the sequence.
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            state: self.state(),
+            output_ids: self.output_ids.clone(),
+        }
+    }
+
+    /// Return to a checkpoint saved by [`checkpoint`](Self::checkpoint).
+    ///
+    /// This replaces the key-value cache, sequence position, pending input
+    /// tokens and generated tokens. Returns an error if the checkpoint was
+    /// saved from a generator for a model with a different number of
+    /// key-value cache inputs.
+    pub fn rewind(&mut self, checkpoint: &Checkpoint) -> Result<(), GeneratorError> {
+        self.restore_state(&checkpoint.state)?;
+        self.output_ids = checkpoint.output_ids.clone();
+        Ok(())
+    }
+
+    /// Save the state of the generator to a file.
+    ///
+    /// This writes the same state as [`state`](Self::state). It can be used
+    /// to process a long prompt prefix ahead of time, and load it at startup
+    /// using [`load_state`](Self::load_state).
+    pub fn save_state(&self, path: impl AsRef<Path>) -> Result<(), GeneratorError> {
+        let mut writer = BufWriter::new(File::create(path).map_err(GeneratorError::IoError)?);
+        self.state()
+            .write(&mut writer)
+            .and_then(|_| writer.flush())
+            .map_err(GeneratorError::IoError)
+    }
+
+    /// Load generator state from a file created by
+    /// [`save_state`](Self::save_state).
+    ///
+    /// The file must have been created by a generator for the same model.
+    /// See [`restore_state`](Self::restore_state).
+    pub fn load_state(&mut self, path: impl AsRef<Path>) -> Result<(), GeneratorError> {
+        let reader = BufReader::new(File::open(path).map_err(GeneratorError::IoError)?);
+        let state = GeneratorState::read(reader).map_err(GeneratorError::IoError)?;
+        self.restore_state(&state)
+    }
+
+    /// Return an iterator which yields each generated token together with its
+    /// log-probability and the `top_k` most likely alternatives.
+    ///
+    /// This is useful for estimating the model's confidence in its output,
+    /// or for implementing APIs which return log-probabilities. See
+    /// [`TokenLogprobs`] for details.
+    pub fn logprobs(self, top_k: usize) -> Logprobs<'a> {
+        Logprobs {
+            generator: self,
+            top_k,
+        }
+    }
+
+    /// Return true if the limit set by
+    /// [`with_max_new_tokens`](Self::with_max_new_tokens) has been reached.
+    fn is_finished(&self) -> bool {
+        self.max_new_tokens
+            .is_some_and(|max_new_tokens| self.output_ids.len() >= max_new_tokens)
+    }
+
+    /// Run the model and generate the next token.
+    ///
+    /// If `top_k` is set, this also returns the log-probabilities of the
+    /// token and the `top_k` most likely tokens.
+    fn generate_next_token(
+        &mut self,
+        top_k: Option<usize>,
+    ) -> Result<(TokenId, Option<TokenLogprobs>), GeneratorError> {
+        self.prefill_chunks()?;
+        let logits = self.run_model()?;
+        let last_logits = logits.slice::<1, _>((0, -1));
+        let suppress_eos =
+            self.output_ids.len() < self.min_new_tokens && !self.eos_tokens.is_empty();
+        let next_id =
+            if suppress_eos || !self.logits_processors.is_empty() || self.temperature.is_some() {
+                let mut last_logits = last_logits.to_tensor();
+                if suppress_eos {
+                    let eos_tokens = self.eos_tokens.iter().map(|&id| id as usize);
+                    suppress(&mut last_logits.view_mut(), eos_tokens);
+                }
+                for processor in &self.logits_processors {
+                    processor.process(&self.output_ids, last_logits.view_mut());
+                }
+                if let Some(temperature) = &self.temperature {
+                    temperature.process(&self.output_ids, last_logits.view_mut());
+                }
+                self.sampler.sample(last_logits.view())
+            } else {
+                self.sampler.sample(last_logits)
+            };
+        let logprobs = top_k.map(|top_k| TokenLogprobs::new(last_logits, next_id, top_k));
+        self.advance(next_id);
+        self.output_ids.push(next_id);
+        Ok((next_id, logprobs))
+    }
+
+    /// Run the model on the pending input tokens and update the key-value
+    /// cache.
+    ///
+    /// Returns the logits for each input position.
+    fn run_model(&mut self) -> Result<NdTensor<f32, 3>, GeneratorError> {
+        fn wrap_error<E>(e: E) -> GeneratorError
+        where
+            E: Into<Box<dyn Error>>,
+        {
+            GeneratorError::GenerateError(e.into())
+        }
+
+        let batch_size = 1;
+        let input_ids: NdTensor<i32, 2> = self
+            .input_ids
+            .iter()
+            .map(|id| *id as i32)
+            .collect::<Tensor<_>>()
+            .into_shape([batch_size, self.input_ids.len()]);
+
+        let seq_range = (self.seq_len as usize)..(self.seq_len as usize + self.input_ids.len());
+
+        let mut model_inputs: Vec<(NodeId, InputOrOutput)> =
+            vec![(self.input_ids_input, input_ids.view().into())];
+
+        // Propagate constants on the first run.
+        if self.constant_prop_inputs.is_none() {
+            let inputs = match self
+                .model
+                .partial_run(self.constant_inputs.clone(), &[self.logits_output])
+            {
+                Ok(inputs) => inputs,
+                Err(err) => {
+                    return Err(wrap_error(err));
+                }
+            };
+            self.constant_prop_inputs = Some(inputs);
+        }
+
+        if let Some(constants) = self.constant_prop_inputs.as_ref() {
+            model_inputs.extend(
+                constants
+                    .iter()
+                    .map(|(node_id, output)| (*node_id, output.as_input().into())),
+            );
         }
 
         if !self.varying_inputs.is_empty() {
@@ -507,9 +1305,12 @@ impl<'a> Generator<'a> {
         // Add key-value cache from previous run. The model takes ownership
         // of the KV-cache tensor during the run so it can efficiently append
         // the entry for the current step, without copying the existing buffer.
-        for entry in self.kv_cache.iter_mut() {
+        //
+        // Cross-attention caches which have already been computed are not
+        // modified by the model, so they are passed as views instead.
+        for entry in self.kv_cache.iter_mut().filter(|entry| !entry.is_fixed()) {
             let cache = entry.cache.take();
-            match cache {
+            match cache.map(|cache| cache.into_f32(self.input_ids.len())) {
                 Some(KvCacheData::BatchSeqChans(cache)) => {
                     model_inputs.push((entry.input_id, cache.into()));
                 }
@@ -520,10 +1321,28 @@ impl<'a> Generator<'a> {
             }
         }
 
+        for entry in self.kv_cache.iter().filter(|entry| entry.is_fixed()) {
+            let input = match entry.cache.as_ref() {
+                Some(KvCacheStorage::F32(KvCacheData::BatchSeqChans(cache))) => cache.view().into(),
+                Some(KvCacheStorage::F32(KvCacheData::BatchHeadSeqChans(cache))) => {
+                    cache.view().into()
+                }
+                Some(KvCacheStorage::F16(cache)) => match cache.convert(vec_f16_to_f32) {
+                    KvCacheData::BatchSeqChans(cache) => cache.into(),
+                    KvCacheData::BatchHeadSeqChans(cache) => cache.into(),
+                },
+                None => continue,
+            };
+            model_inputs.push((entry.input_id, input));
+        }
+
         // Run the model and collect outputs and updated KV cache.
+        let updated_caches: Vec<usize> = (0..self.kv_cache.len())
+            .filter(|&i| !self.kv_cache[i].is_fixed())
+            .collect();
         let model_outputs: Vec<NodeId> = [self.logits_output]
             .into_iter()
-            .chain(self.kv_cache.iter().map(|entry| entry.output_id))
+            .chain(updated_caches.iter().map(|&i| self.kv_cache[i].output_id))
             .collect();
 
         let mut outputs = self
@@ -531,16 +1350,15 @@ impl<'a> Generator<'a> {
             .run(model_inputs, &model_outputs)
             .map_err(wrap_error)?;
 
-        // Sample output token.
         let logits: NdTensor<f32, 3> = outputs.remove(0).try_into().map_err(wrap_error)?;
-        let next_id = self.sampler.sample(logits.slice::<1, _>((0, -1)));
 
         // Update the key-value cache.
         //
         // The KV cache tensors returned from the model should be the same as
         // the passed in tensors, but extended by one element along the sequence
         // axis.
-        for cache_entry in self.kv_cache.iter_mut() {
+        for i in updated_caches {
+            let cache_entry = &mut self.kv_cache[i];
             let output = outputs.remove(0);
             let kv_cache = match output.ndim() {
                 3 => KvCacheData::BatchSeqChans(output.try_into().map_err(wrap_error)?),
@@ -549,18 +1367,334 @@ impl<'a> Generator<'a> {
                     return Err(wrap_error("expected KV cache output to have 3 or 4 dims"));
                 }
             };
-            cache_entry.cache = Some(kv_cache);
+            cache_entry.cache = Some(KvCacheStorage::from_f32(kv_cache, cache_entry.dtype));
+        }
+
+        Ok(logits)
+    }
+
+    /// Run the model on chunks of pending input tokens until at most
+    /// [`prefill_chunk_size`](Self::with_prefill_chunk_size) tokens remain.
+    ///
+    /// The outputs for these tokens are discarded, as only the key-value
+    /// cache is needed.
+    fn prefill_chunks(&mut self) -> Result<(), GeneratorError> {
+        let Some(chunk_size) = self.prefill_chunk_size else {
+            return Ok(());
+        };
+        if self.kv_cache.is_empty() {
+            return Ok(());
         }
+        while self.input_ids.len() > chunk_size {
+            let rest = self.input_ids.split_off(chunk_size);
+            self.run_model()?;
+            self.seq_len += self.input_ids.len() as u32;
+            self.input_ids = rest;
+        }
+        Ok(())
+    }
 
-        // Update the token IDs and sequence offset for the next iteration.
+    /// Update the token IDs and sequence offset for the next iteration, after
+    /// the model has been run on the pending input tokens.
+    fn advance(&mut self, next_id: TokenId) {
         if !self.kv_cache.is_empty() {
             self.seq_len += self.input_ids.len() as u32;
             self.input_ids = vec![next_id];
         } else {
             self.input_ids.push(next_id);
         }
+    }
+}
+
+/// Return `ln(sum(exp(x)))` for the logits `x`.
+///
+/// Subtracting the log of the result from a logit gives its log-probability.
+fn log_sum_exp(logits: impl Iterator<Item = f32> + Clone) -> f32 {
+    let max = logits.clone().fold(f32::NEG_INFINITY, f32::max);
+    let sum_exp: f32 = logits.map(|x| (x - max).exp()).sum();
+    sum_exp.ln() + max
+}
+
+/// Return the log of the softmax probability of the `index`th logit, or
+/// `None` if `index` is out of bounds.
+fn log_softmax_at(logits: impl Iterator<Item = f32> + Clone, index: usize) -> Option<f32> {
+    let logit = logits.clone().nth(index)?;
+    Some(logit - log_sum_exp(logits))
+}
+
+/// Checkpoint of a [`Generator`], created using [`Generator::checkpoint`].
+#[derive(Clone)]
+pub struct Checkpoint {
+    state: GeneratorState,
+    output_ids: Vec<TokenId>,
+}
+
+impl Checkpoint {
+    /// Return the tokens which had been generated when the checkpoint was
+    /// saved.
+    pub fn output_ids(&self) -> &[TokenId] {
+        &self.output_ids
+    }
+}
+
+/// Saved state of a [`Generator`], created using [`Generator::state`].
+///
+/// This contains the key-value cache, the position in the sequence and any
+/// input tokens which the model has not processed yet. It can be used to
+/// resume generation from the same point multiple times, for example to
+/// reuse a processed prompt prefix across several generations.
+#[derive(Clone)]
+pub struct GeneratorState {
+    kv_cache: Vec<Option<KvCacheStorage>>,
+    seq_len: u32,
+    input_ids: Vec<TokenId>,
+}
+
+impl GeneratorState {
+    /// Return the number of tokens in the sequence, including those that
+    /// have not been processed by the model yet.
+    pub fn len(&self) -> usize {
+        self.seq_len as usize + self.input_ids.len()
+    }
+
+    /// Return true if the sequence is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Identifies files containing serialized generator state.
+    const MAGIC: &'static [u8; 4] = b"RTGS";
+
+    /// Version of the serialization format.
+    const VERSION: u32 = 2;
+
+    /// Serialize the state to `writer`.
+    ///
+    /// The format is a header followed by the sequence position, pending
+    /// input tokens and key-value cache tensors. Each tensor is stored as its
+    /// number of dims, a data type (0 for f32, 1 for f16), its shape and its
+    /// elements. All values are stored in little-endian order.
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let write_u32 = |writer: &mut W, val: u32| writer.write_all(&val.to_le_bytes());
+
+        writer.write_all(Self::MAGIC)?;
+        write_u32(&mut writer, Self::VERSION)?;
+        write_u32(&mut writer, self.seq_len)?;
+        write_u32(&mut writer, self.input_ids.len() as u32)?;
+        for &id in &self.input_ids {
+            write_u32(&mut writer, id)?;
+        }
+
+        write_u32(&mut writer, self.kv_cache.len() as u32)?;
+        for cache in &self.kv_cache {
+            let (dtype, shape) = match cache {
+                None => (0, Vec::new()),
+                Some(KvCacheStorage::F32(cache)) => (0, cache.shape()),
+                Some(KvCacheStorage::F16(cache)) => (1, cache.shape()),
+            };
+            writer.write_all(&[shape.len() as u8, dtype])?;
+            for size in shape {
+                write_u32(&mut writer, size as u32)?;
+            }
+            match cache {
+                None => {}
+                Some(KvCacheStorage::F32(cache)) => {
+                    for x in cache.to_vec() {
+                        writer.write_all(&x.to_le_bytes())?;
+                    }
+                }
+                Some(KvCacheStorage::F16(cache)) => {
+                    for x in cache.to_vec() {
+                        writer.write_all(&x.to_le_bytes())?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Deserialize state written by [`write`](Self::write).
+    pub fn read<R: Read>(mut reader: R) -> io::Result<GeneratorState> {
+        fn invalid_data(msg: &str) -> io::Error {
+            io::Error::new(io::ErrorKind::InvalidData, msg)
+        }
+        let read_u32 = |reader: &mut R| -> io::Result<u32> {
+            let mut buf = [0; 4];
+            reader.read_exact(&mut buf)?;
+            Ok(u32::from_le_bytes(buf))
+        };
+
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != Self::MAGIC {
+            return Err(invalid_data("not a generator state file"));
+        }
+        if read_u32(&mut reader)? != Self::VERSION {
+            return Err(invalid_data("unsupported generator state version"));
+        }
+
+        let seq_len = read_u32(&mut reader)?;
+        let n_input_ids = read_u32(&mut reader)?;
+        let input_ids = (0..n_input_ids)
+            .map(|_| read_u32(&mut reader))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let n_caches = read_u32(&mut reader)?;
+        let mut kv_cache = Vec::new();
+        for _ in 0..n_caches {
+            let mut header = [0u8; 2];
+            reader.read_exact(&mut header)?;
+            let [ndim, dtype] = header;
+            let shape = (0..ndim)
+                .map(|_| read_u32(&mut reader).map(|size| size as usize))
+                .collect::<io::Result<Vec<_>>>()?;
+            if shape.is_empty() {
+                kv_cache.push(None);
+                continue;
+            }
+
+            let len = shape
+                .iter()
+                .try_fold(1usize, |len, &size| len.checked_mul(size))
+                .ok_or_else(|| invalid_data("tensor is too large"))?;
+            let elem_size = match dtype {
+                0 => std::mem::size_of::<f32>(),
+                1 => std::mem::size_of::<u16>(),
+                _ => return Err(invalid_data("unsupported key-value cache data type")),
+            };
+            let n_bytes = len
+                .checked_mul(elem_size)
+                .ok_or_else(|| invalid_data("tensor is too large"))?;
+
+            // Read incrementally rather than allocating `n_bytes` upfront, so
+            // that a corrupt header can't cause a huge allocation.
+            let mut bytes = Vec::new();
+            let n_read = reader
+                .by_ref()
+                .take(n_bytes as u64)
+                .read_to_end(&mut bytes)?;
+            if n_read != n_bytes {
+                return Err(invalid_data("key-value cache data is truncated"));
+            }
+
+            let cache = if dtype == 0 {
+                let data = bytes
+                    .chunks_exact(elem_size)
+                    .map(|x| f32::from_le_bytes(x.try_into().unwrap()))
+                    .collect();
+                KvCacheData::from_data(&shape, data).map(KvCacheStorage::F32)
+            } else {
+                let data = bytes
+                    .chunks_exact(elem_size)
+                    .map(|x| u16::from_le_bytes(x.try_into().unwrap()))
+                    .collect();
+                KvCacheData::from_data(&shape, data).map(KvCacheStorage::F16)
+            };
+            let cache =
+                cache.ok_or_else(|| invalid_data("key-value cache must have 3 or 4 dims"))?;
+            kv_cache.push(Some(cache));
+        }
+
+        Ok(GeneratorState {
+            kv_cache,
+            seq_len,
+            input_ids,
+        })
+    }
+}
+
+/// Log-probabilities for a generated token, produced by [`Generator::logprobs`].
+///
+/// Log-probabilities are computed from the logits output by the model,
+/// before any adjustments made during sampling, such as temperature scaling
+/// or suppressing end-of-text tokens.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TokenLogprobs {
+    /// ID of the generated token.
+    pub token_id: TokenId,
+
+    /// Natural log of the probability of the generated token.
+    pub logprob: f32,
+
+    /// The most likely tokens and their log-probabilities, ordered from most
+    /// to least likely. This may include the generated token.
+    pub top_logprobs: Vec<(TokenId, f32)>,
+}
+
+impl TokenLogprobs {
+    /// Compute log-probabilities for `token_id` and the `top_k` most likely
+    /// tokens from the logits for one position.
+    fn new(logits: NdTensorView<f32, 1>, token_id: TokenId, top_k: usize) -> TokenLogprobs {
+        let log_sum_exp = log_sum_exp(logits.iter().copied());
+
+        let mut top_logprobs: Vec<(TokenId, f32)> = logits
+            .iter()
+            .enumerate()
+            .map(|(id, logit)| (id as TokenId, logit - log_sum_exp))
+            .collect();
+        let by_prob_desc = |a: &(TokenId, f32), b: &(TokenId, f32)| b.1.total_cmp(&a.1);
+        let logprob = top_logprobs
+            .get(token_id as usize)
+            .map_or(f32::NEG_INFINITY, |(_, logprob)| *logprob);
+
+        let top_k = top_k.min(top_logprobs.len());
+        if top_k > 0 && top_k < top_logprobs.len() {
+            top_logprobs.select_nth_unstable_by(top_k - 1, by_prob_desc);
+        }
+        top_logprobs.truncate(top_k);
+        top_logprobs.sort_by(by_prob_desc);
 
-        Ok(next_id)
+        TokenLogprobs {
+            token_id,
+            logprob,
+            top_logprobs,
+        }
+    }
+}
+
+/// Accumulates token log-probabilities to compute perplexity.
+///
+/// This is used with [`Generator::score_tokens`] to evaluate how well a model
+/// predicts a sequence. Log-probabilities can be added in chunks, so that
+/// long sequences can be evaluated incrementally.
+#[derive(Clone, Debug, Default)]
+pub struct Perplexity {
+    sum_log_prob: f64,
+    count: usize,
+}
+
+impl Perplexity {
+    /// Create an empty accumulator.
+    pub fn new() -> Perplexity {
+        Perplexity::default()
+    }
+
+    /// Add natural log-probabilities for a sequence of tokens.
+    pub fn add(&mut self, log_probs: &[f32]) {
+        self.sum_log_prob += log_probs.iter().map(|&x| x as f64).sum::<f64>();
+        self.count += log_probs.len();
+    }
+
+    /// Return the number of tokens added.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Return the total log-probability of the tokens added.
+    pub fn total_log_prob(&self) -> f64 {
+        self.sum_log_prob
+    }
+
+    /// Return the perplexity, which is the exponent of the mean negative
+    /// log-probability per token.
+    ///
+    /// Returns `None` if no tokens have been added.
+    pub fn value(&self) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        Some((-self.sum_log_prob / self.count as f64).exp())
     }
 }
 
@@ -572,7 +1706,32 @@ impl<'a> Iterator for Generator<'a> {
 
     /// Run the model and generate the next output token.
     fn next(&mut self) -> Option<Self::Item> {
-        Some(self.generate_next_token())
+        if self.is_finished() {
+            return None;
+        }
+        Some(self.generate_next_token(None).map(|(token_id, _)| token_id))
+    }
+}
+
+/// Wraps a [`Generator`] to yield the log-probabilities of each generated
+/// token.
+///
+/// This is created by calling [`Generator::logprobs`].
+pub struct Logprobs<'a> {
+    generator: Generator<'a>,
+    top_k: usize,
+}
+
+impl Iterator for Logprobs<'_> {
+    type Item = Result<TokenLogprobs, GeneratorError>;
+
+    /// Run the model and generate the next output token.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.generator.is_finished() {
+            return None;
+        }
+        let result = self.generator.generate_next_token(Some(self.top_k));
+        Some(result.map(|(_, logprobs)| logprobs.expect("logprobs should be computed")))
     }
 }
 
@@ -593,6 +1752,35 @@ pub trait GeneratorUtils: Iterator<Item = GeneratorItem> + Sized {
         TextDecoder::wrap(self, tokenizer)
     }
 
+    /// Decode the tokens to text using a tokenizer, and separate tool calls
+    /// from the rest of the text.
+    ///
+    /// The returned iterator yields [`ToolStreamEvent`](crate::tool_call::ToolStreamEvent)s
+    /// which are either text or complete tool calls, enclosed in the
+    /// delimiters specified by `format`.
+    #[cfg(feature = "text-decoder")]
+    fn decode_tool_calls(
+        self,
+        tokenizer: &Tokenizer,
+        format: ToolCallFormat,
+    ) -> ToolCallStream<TextDecoder<'_, Self>> {
+        ToolCallStream::wrap(self.decode(tokenizer), format)
+    }
+
+    /// Decode the tokens to text using a tokenizer, and stop generation when
+    /// any string in `stop_strings` is generated.
+    ///
+    /// Stop strings can span multiple tokens. The output ends with the text
+    /// before the stop string, which is not included. See [`StopStrings`].
+    #[cfg(feature = "text-decoder")]
+    fn decode_until<'a, S: AsRef<str>>(
+        self,
+        tokenizer: &'a Tokenizer,
+        stop_strings: &[S],
+    ) -> StopStrings<TextDecoder<'a, Self>> {
+        StopStrings::wrap(self.decode(tokenizer), stop_strings)
+    }
+
     /// Record timing metrics.
     ///
     /// Metrics such as the number of tokens generated per second will be
@@ -632,14 +1820,21 @@ mod tests {
     use std::cell::{Cell, RefCell};
     use std::collections::HashMap;
     use std::error::Error;
+    use std::rc::Rc;
+    use std::sync::Arc;
 
     use rten::{Dimension, InputOrOutput, NodeId, Output};
     use rten_tensor::prelude::*;
-    use rten_tensor::NdTensor;
+    use rten_tensor::{NdTensor, NdTensorView};
 
-    use super::{Generator, GeneratorUtils};
+    use super::{
+        Generator, GeneratorConfig, GeneratorError, GeneratorState, GeneratorUtils, KvCacheData,
+        KvCacheDtype, KvCacheStorage, Perplexity, TokenId, TokenLogprobs,
+    };
+    use crate::logits::{ForceTokens, SuppressTokens};
     use crate::metrics::Metrics;
     use crate::model::{Model, NodeInfo};
+    use crate::sampler::{ArgMaxSampler, Sampler};
 
     struct FakeModel {
         nodes: Vec<NodeInfo>,
@@ -967,6 +2162,67 @@ mod tests {
         test_generator_impl(false /* use_kv_cache */)
     }
 
+    #[test]
+    fn test_generator_from_shared_model() -> Result<(), Box<dyn Error>> {
+        let params = TransformerParams::default();
+        let expected_token_ids = [0, 1, 2, 3];
+        let prompt = [1, 2, 3];
+
+        // The generator owns the model, so it can outlive this scope.
+        let generator = {
+            let model = fake_transformer_model(
+                params,
+                true, /* use_kv_cache */
+                prompt.len(),
+                &expected_token_ids,
+            );
+            Generator::from_shared_model(Arc::new(model))?.with_prompt(&prompt)
+        };
+
+        let output_token_ids: Vec<_> = generator
+            .take(expected_token_ids.len())
+            .map(|id| id.expect("generation failed"))
+            .collect();
+        assert_eq!(output_token_ids, expected_token_ids);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_generator_sample_tokens() -> Result<(), Box<dyn Error>> {
+        let params = TransformerParams::default();
+        let expected_token_ids = [0, 1, 2, 3];
+        let prompt = [1, 2, 3];
+        let make_model = || {
+            fake_transformer_model(
+                params,
+                true, /* use_kv_cache */
+                prompt.len(),
+                &expected_token_ids,
+            )
+        };
+
+        // Generation ends at a stop token.
+        let model = make_model();
+        let tokens: Vec<_> = Generator::from_model(&model)?
+            .with_prompt(&prompt)
+            .sample_tokens(4, None, 1.0, vec![2])
+            .map(|id| id.expect("generation failed"))
+            .collect();
+        assert_eq!(tokens, [0, 1]);
+
+        // Generation ends after `max_tokens` tokens.
+        let model = make_model();
+        let tokens: Vec<_> = Generator::from_model(&model)?
+            .with_prompt(&prompt)
+            .sample_tokens(3, None, 1.0, vec![])
+            .map(|id| id.expect("generation failed"))
+            .collect();
+        assert_eq!(tokens, [0, 1, 2]);
+
+        Ok(())
+    }
+
     #[test]
     fn test_generator_append_prompt() -> Result<(), Box<dyn Error>> {
         let mut params = TransformerParams::default();
@@ -1034,9 +2290,9 @@ mod tests {
     }
 
     #[test]
-    fn test_profile() -> Result<(), Box<dyn Error>> {
+    fn test_min_max_new_tokens() -> Result<(), Box<dyn Error>> {
         let params = TransformerParams::default();
-        let expected_token_ids = [0, 1, 2, 3, 4];
+        let expected_token_ids = [0, 1, 2, 3, 4, 0, 1, 2, 3, 4, 0, 0, 0];
         let prompt = [1, 2, 3, 1, 2, 3];
         let model = fake_transformer_model(
             params,
@@ -1045,19 +2301,829 @@ mod tests {
             &expected_token_ids,
         );
 
-        let generator = Generator::from_model(&model)?;
-        let mut metrics = Metrics::new();
+        let mut generator = Generator::from_model(&model)?
+            .with_prompt(&prompt)
+            .with_min_new_tokens(6, &[4])
+            .with_max_new_tokens(8);
 
+        // The first end-of-text token is suppressed, so the sampler picks
+        // the next most likely token instead. Generation stops after the
+        // maximum number of tokens.
         let output_token_ids: Vec<_> = generator
-            .with_prompt(&prompt)
-            .profile(&mut metrics)
-            .take(expected_token_ids.len())
+            .by_ref()
+            .stop_on_tokens([4])
             .map(|id| id.expect("generation failed"))
             .collect();
+        assert_eq!(output_token_ids, &[0, 1, 2, 3, 3, 0, 1, 2]);
+        assert!(generator.next().is_none());
 
-        assert_eq!(output_token_ids, expected_token_ids);
-        assert!(metrics.warmup_duration().is_some());
-        assert_eq!(metrics.step_durations().len(), output_token_ids.len() - 1);
+        // Extending the prompt resets the count of generated tokens.
+        generator.append_prompt(&[1]);
+        let next_id = generator.next().transpose().expect("generation failed");
+        assert_eq!(next_id, Some(3));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_logits_processor() -> Result<(), Box<dyn Error>> {
+        let params = TransformerParams::default();
+        let expected_token_ids = [0, 1, 2, 3];
+        let prompt = [1, 2, 3, 1, 2, 3];
+        let model = fake_transformer_model(
+            params,
+            true, /* use_kv_cache */
+            prompt.len(),
+            &expected_token_ids,
+        );
+
+        // Processors are applied to the logits before sampling, in order.
+        let output_token_ids: Vec<_> = Generator::from_model(&model)?
+            .with_prompt(&prompt)
+            .with_logits_processor(SuppressTokens::new(&[1, 3]))
+            .with_logits_processor(ForceTokens::new(&[(3, 3)]))
+            .take(expected_token_ids.len())
+            .map(|id| id.expect("generation failed"))
+            .collect();
+        assert_eq!(output_token_ids, [0, 4, 2, 3]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_temperature() -> Result<(), Box<dyn Error>> {
+        /// Sampler which records the logits it receives.
+        struct RecordingSampler {
+            logits: Rc<RefCell<Vec<Vec<f32>>>>,
+        }
+
+        impl Sampler for RecordingSampler {
+            fn sample(&self, logits: NdTensorView<f32, 1>) -> TokenId {
+                self.logits.borrow_mut().push(logits.to_vec());
+                ArgMaxSampler::new().sample(logits)
+            }
+        }
+
+        let params = TransformerParams::default();
+        let expected_token_ids = [0, 1];
+        let prompt = [1, 2, 3, 1, 2, 3];
+        let model = fake_transformer_model(
+            params,
+            true, /* use_kv_cache */
+            prompt.len(),
+            &expected_token_ids,
+        );
+
+        // The temperature is applied after logits processors, before the
+        // sampler.
+        let logits = Rc::new(RefCell::new(Vec::new()));
+        let output_token_ids: Vec<_> = Generator::from_model(&model)?
+            .with_prompt(&prompt)
+            .with_temperature(0.5)
+            .with_logits_processor(SuppressTokens::new(&[4]))
+            .with_sampler(RecordingSampler {
+                logits: logits.clone(),
+            })
+            .take(expected_token_ids.len())
+            .map(|id| id.expect("generation failed"))
+            .collect();
+        assert_eq!(output_token_ids, expected_token_ids);
+        assert_eq!(
+            *logits.borrow(),
+            [
+                [2., 0., 0., 0., f32::NEG_INFINITY],
+                [0., 2., 0., 0., f32::NEG_INFINITY]
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prefill_chunk_size() -> Result<(), Box<dyn Error>> {
+        let params = TransformerParams::default();
+        let prompt = [1, 2, 3, 1, 2, 3];
+        let step_inputs = |model: &FakeModel| -> Vec<(usize, Vec<i32>)> {
+            let input_id = model.find_node("input_ids").unwrap();
+            let position_ids = model.find_node("position_ids").unwrap();
+            (0..)
+                .map_while(|step| {
+                    let input_ids: NdTensor<i32, 2> =
+                        model.get_inputs(step, input_id)?.try_into().unwrap();
+                    let positions: NdTensor<i32, 2> =
+                        model.get_inputs(step, position_ids)?.try_into().unwrap();
+                    Some((input_ids.size(1), positions.to_vec()))
+                })
+                .collect()
+        };
+
+        // The prompt is processed in chunks before the first token is
+        // generated. The outputs for all but the last chunk are discarded.
+        let expected_token_ids = [0, 1, 2, 3];
+        let model = fake_transformer_model(
+            params,
+            true, /* use_kv_cache */
+            prompt.len(),
+            &expected_token_ids,
+        );
+        let output_token_ids: Vec<_> = Generator::from_model(&model)?
+            .with_prompt(&prompt)
+            .with_prefill_chunk_size(4)
+            .take(3)
+            .map(|id| id.expect("generation failed"))
+            .collect();
+        assert_eq!(output_token_ids, [1, 2, 3]);
+        assert_eq!(
+            step_inputs(&model),
+            [
+                (4, vec![0, 1, 2, 3]),
+                (2, vec![4, 5]),
+                (1, vec![6]),
+                (1, vec![7])
+            ]
+        );
+
+        // Chunks are also used by `prefill`.
+        let model = fake_transformer_model(
+            params,
+            true, /* use_kv_cache */
+            prompt.len(),
+            &expected_token_ids,
+        );
+        let mut generator = Generator::from_model(&model)?
+            .with_prompt(&prompt)
+            .with_prefill_chunk_size(2);
+        generator.prefill()?;
+        assert_eq!(
+            step_inputs(&model),
+            [(2, vec![0, 1]), (2, vec![2, 3]), (1, vec![4])]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_logprobs() -> Result<(), Box<dyn Error>> {
+        let params = TransformerParams::default();
+        let expected_token_ids = [0, 1, 2];
+        let prompt = [1, 2, 3, 1, 2, 3];
+        let model = fake_transformer_model(
+            params,
+            true, /* use_kv_cache */
+            prompt.len(),
+            &expected_token_ids,
+        );
+
+        let outputs: Vec<TokenLogprobs> = Generator::from_model(&model)?
+            .with_prompt(&prompt)
+            .with_max_new_tokens(expected_token_ids.len())
+            .logprobs(2)
+            .map(|item| item.expect("generation failed"))
+            .collect();
+
+        // The fake model outputs a logit of 1 for the expected token and 0
+        // for the other tokens.
+        let logits = [1., 0., 0., 0., 0.];
+        let expected_logprobs = log_softmax(&logits);
+        let (top, other) = (expected_logprobs[0], expected_logprobs[1]);
+
+        assert_eq!(outputs.len(), expected_token_ids.len());
+        for (output, &token_id) in outputs.iter().zip(&expected_token_ids) {
+            assert_eq!(output.token_id, token_id);
+            assert!((output.logprob - top).abs() < 1e-6);
+            assert_eq!(output.top_logprobs.len(), 2);
+            assert_eq!(output.top_logprobs[0].0, token_id);
+            assert!((output.top_logprobs[0].1 - top).abs() < 1e-6);
+            assert!((output.top_logprobs[1].1 - other).abs() < 1e-6);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prefill_and_restore_state() -> Result<(), Box<dyn Error>> {
+        let params = TransformerParams::default();
+        // The first output is for the prefill step and is discarded.
+        let expected_token_ids = [0, 1, 2, 3, 4];
+        let prompt = [1, 2, 3, 1, 2, 3];
+        let model = fake_transformer_model(
+            params,
+            true, /* use_kv_cache */
+            prompt.len(),
+            &expected_token_ids,
+        );
+
+        let mut generator = Generator::from_model(&model)?.with_prompt(&prompt);
+        generator.prefill()?;
+        let state = generator.state();
+        assert_eq!(state.len(), prompt.len());
+
+        let first: Vec<_> = generator
+            .by_ref()
+            .take(2)
+            .map(|id| id.expect("generation failed"))
+            .collect();
+        assert_eq!(first, [1, 2]);
+
+        generator.restore_state(&state)?;
+        let second: Vec<_> = generator
+            .take(2)
+            .map(|id| id.expect("generation failed"))
+            .collect();
+        assert_eq!(second, [3, 4]);
+
+        // The prefill step processes all prompt tokens except the last.
+        // After restoring the state, the model resumes from the same
+        // position, with the same key-value cache.
+        let input_id = model.find_node("input_ids").unwrap();
+        let position_ids = model.find_node("position_ids").unwrap();
+        let key_cache = model.find_node("past_key_values.0.key").unwrap();
+        let get_input = |step, node| -> NdTensor<i32, 2> {
+            model.get_inputs(step, node).unwrap().try_into().unwrap()
+        };
+        let get_cache_len = |step| {
+            let cache: NdTensor<f32, 4> = model
+                .get_inputs(step, key_cache)
+                .unwrap()
+                .try_into()
+                .unwrap();
+            cache.size(2)
+        };
+
+        assert_eq!(get_input(0, input_id).size(1), prompt.len() - 1);
+        for step in [1, 3] {
+            assert_eq!(
+                get_input(step, input_id).to_vec(),
+                [prompt[prompt.len() - 1] as i32]
+            );
+            assert_eq!(
+                get_input(step, position_ids).to_vec(),
+                [prompt.len() as i32 - 1]
+            );
+        }
+        assert_eq!(get_cache_len(1), get_cache_len(3));
+
+        // State can only be restored to a generator for a compatible model.
+        let other_model = fake_transformer_model(
+            params,
+            false, /* use_kv_cache */
+            prompt.len(),
+            &expected_token_ids,
+        );
+        let mut other_generator = Generator::from_model(&other_model)?;
+        assert!(matches!(
+            other_generator.restore_state(&state),
+            Err(GeneratorError::ShapeMismatch(_))
+        ));
+
+        // The cache entries must also have the same number of heads and
+        // channels.
+        let other_model = fake_transformer_model(
+            TransformerParams {
+                n_heads: params.n_heads + 1,
+                ..params
+            },
+            true, /* use_kv_cache */
+            prompt.len(),
+            &expected_token_ids,
+        );
+        let mut other_generator = Generator::from_model(&other_model)?;
+        assert!(matches!(
+            other_generator.restore_state(&state),
+            Err(GeneratorError::ShapeMismatch(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_checkpoint_and_rewind() -> Result<(), Box<dyn Error>> {
+        let params = TransformerParams::default();
+        let expected_token_ids = [0, 1, 2, 3, 4, 0];
+        let prompt = [1, 2, 3, 1, 2, 3];
+        let model = fake_transformer_model(
+            params,
+            true, /* use_kv_cache */
+            prompt.len(),
+            &expected_token_ids,
+        );
+
+        let mut generator = Generator::from_model(&model)?
+            .with_prompt(&prompt)
+            .with_max_new_tokens(4);
+        let next_tokens = |generator: &mut Generator, n| -> Vec<TokenId> {
+            generator
+                .by_ref()
+                .take(n)
+                .map(|id| id.expect("generation failed"))
+                .collect()
+        };
+
+        assert_eq!(next_tokens(&mut generator, 2), [0, 1]);
+        let checkpoint = generator.checkpoint();
+        assert_eq!(checkpoint.output_ids(), [0, 1]);
+
+        assert_eq!(next_tokens(&mut generator, 2), [2, 3]);
+        assert_eq!(generator.output_ids(), [0, 1, 2, 3]);
+
+        // After rewinding, generation resumes from the checkpoint with the
+        // same pending input, positions and generated tokens, so the limit on the number of
+        // new tokens still applies.
+        generator.rewind(&checkpoint)?;
+        assert_eq!(generator.output_ids(), [0, 1]);
+        assert_eq!(next_tokens(&mut generator, 3), [4, 0]);
+
+        let input_id = model.find_node("input_ids").unwrap();
+        let position_ids = model.find_node("position_ids").unwrap();
+        let get_input = |step, node| -> Vec<i32> {
+            let input: NdTensor<i32, 2> = model.get_inputs(step, node).unwrap().try_into().unwrap();
+            input.to_vec()
+        };
+        assert_eq!(get_input(2, input_id), get_input(4, input_id));
+        for (step, rewound_step) in [(2, 4), (3, 5)] {
+            assert_eq!(
+                get_input(step, position_ids),
+                get_input(rewound_step, position_ids)
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_and_load_state() -> Result<(), Box<dyn Error>> {
+        let params = TransformerParams::default();
+        let expected_token_ids = [0, 1, 2, 3];
+        let prompt = [1, 2, 3, 1, 2, 3];
+        let model = fake_transformer_model(
+            params,
+            true, /* use_kv_cache */
+            prompt.len(),
+            &expected_token_ids,
+        );
+
+        let mut generator = Generator::from_model(&model)?.with_prompt(&prompt);
+        generator.prefill()?;
+
+        let path = std::env::temp_dir().join(format!(
+            "rten-generate-test-state-{}.bin",
+            std::process::id()
+        ));
+        generator.save_state(&path)?;
+
+        let mut loaded = Generator::from_model(&model)?;
+        let result = loaded.load_state(&path);
+        std::fs::remove_file(&path)?;
+        result?;
+
+        let saved = generator.state();
+        let restored = loaded.state();
+        assert_eq!(restored.seq_len, saved.seq_len);
+        assert_eq!(restored.input_ids, saved.input_ids);
+        assert_eq!(restored.kv_cache.len(), saved.kv_cache.len());
+        for (restored, saved) in restored.kv_cache.iter().zip(&saved.kv_cache) {
+            match (restored, saved) {
+                (
+                    Some(KvCacheStorage::F32(KvCacheData::BatchHeadSeqChans(restored))),
+                    Some(KvCacheStorage::F32(KvCacheData::BatchHeadSeqChans(saved))),
+                ) => assert_eq!(restored, saved),
+                _ => panic!("unexpected cache type"),
+            }
+        }
+
+        // Loading data which is not a state file fails.
+        let err = GeneratorState::read(&b"not a state file"[..])
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        // Loading a state file with truncated cache data fails.
+        let mut buf = Vec::new();
+        saved.write(&mut buf)?;
+        buf.truncate(buf.len() - 1);
+        let err = GeneratorState::read(buf.as_slice()).err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        // A cache header with a huge shape fails without allocating space for
+        // the data upfront.
+        let mut buf = Vec::new();
+        buf.extend(GeneratorState::MAGIC);
+        for val in [GeneratorState::VERSION, 0, 0, 1] {
+            buf.extend(val.to_le_bytes());
+        }
+        buf.extend([4u8, 0]);
+        for size in [1u32, 1 << 16, 1 << 16, 1 << 16] {
+            buf.extend(size.to_le_bytes());
+        }
+        let err = GeneratorState::read(buf.as_slice()).err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_kv_cache_shapes() -> Result<(), Box<dyn Error>> {
+        let batch = || Dimension::Symbolic("batch".to_string());
+        let seq = || Dimension::Symbolic("seq".to_string());
+        let heads = || Dimension::Symbolic("kv_heads".to_string());
+        let chans = || Dimension::Symbolic("head_dim".to_string());
+
+        let create_model = |past_value: &[Dimension], present_value: &[Dimension]| {
+            let key_dims = [batch(), Dimension::Fixed(2), seq(), Dimension::Fixed(8)];
+            let inputs = [
+                NodeInfo::from_name_shape("input_ids", &[]),
+                NodeInfo::from_name_shape("past_key_values.0.key", &key_dims),
+                NodeInfo::from_name_shape("past_key_values.0.value", past_value),
+            ];
+            let outputs = [
+                NodeInfo::from_name_shape("logits", &[]),
+                NodeInfo::from_name_shape("present.0.key", &key_dims),
+                NodeInfo::from_name_shape("present.0.value", present_value),
+            ];
+            FakeModel::with_inputs_and_outputs(&inputs, &outputs)
+        };
+        let cache_shapes = |generator: &Generator| -> Vec<Vec<usize>> {
+            generator
+                .state()
+                .kv_cache
+                .iter()
+                .map(|cache| match cache {
+                    Some(KvCacheStorage::F32(cache)) => cache.shape(),
+                    _ => panic!("unexpected cache type"),
+                })
+                .collect()
+        };
+
+        // Key and value caches with different channel sizes, where the
+        // number of heads and channels for the value cache are only
+        // specified on the output.
+        let model = create_model(
+            &[batch(), heads(), seq(), chans()],
+            &[batch(), Dimension::Fixed(2), seq(), Dimension::Fixed(16)],
+        );
+        let generator = Generator::from_model(&model)?;
+        assert_eq!(cache_shapes(&generator), [[1, 2, 0, 8], [1, 2, 0, 16]]);
+
+        // Sizes which are not fixed in either the input or output.
+        let model = create_model(
+            &[batch(), heads(), seq(), chans()],
+            &[batch(), Dimension::Fixed(2), seq(), chans()],
+        );
+        let result = Generator::from_model(&model);
+        assert!(matches!(result, Err(GeneratorError::ShapeMismatch(_))));
+
+        // Input and output with different ranks.
+        let model = create_model(
+            &[batch(), Dimension::Fixed(2), seq(), Dimension::Fixed(16)],
+            &[batch(), seq(), Dimension::Fixed(32)],
+        );
+        let result = Generator::from_model(&model);
+        assert!(matches!(result, Err(GeneratorError::ShapeMismatch(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cross_attention_kv_cache() -> Result<(), Box<dyn Error>> {
+        let n_vocab = 5;
+        let n_heads = 2;
+        let n_embed = 4;
+        let encoder_len = 7;
+        let dims = [
+            Dimension::Symbolic("batch".to_string()),
+            Dimension::Fixed(n_heads),
+            Dimension::Symbolic("seq".to_string()),
+            Dimension::Fixed(n_embed),
+        ];
+        let cache_names = ["0.key", "0.value", "0.encoder.key", "0.encoder.value"];
+
+        let prompt = [1, 2];
+        let expected_token_ids = [3, 4, 0, 1];
+
+        // Use a value which is not exactly representable as f16, to check
+        // that the cross-attention cache is not converted to f16.
+        let encoder_cache = NdTensor::full([1, n_heads, encoder_len, n_embed], 0.1);
+
+        let make_model = || {
+            let mut inputs = vec![NodeInfo::from_name_shape("input_ids", &[])];
+            let mut outputs = vec![NodeInfo::from_name_shape("logits", &[])];
+            for name in cache_names {
+                inputs.push(NodeInfo::from_name_shape(
+                    &format!("past_key_values.{}", name),
+                    &dims,
+                ));
+                outputs.push(NodeInfo::from_name_shape(
+                    &format!("present.{}", name),
+                    &dims,
+                ));
+            }
+            let mut model = FakeModel::with_inputs_and_outputs(&inputs, &outputs);
+
+            for (step, token_id) in expected_token_ids.iter().copied().enumerate() {
+                let mut step_outputs = HashMap::new();
+                step_outputs.insert(
+                    model.find_node("logits").unwrap(),
+                    Output::FloatTensor(generate_logits(n_vocab, &[token_id]).into()),
+                );
+                for name in cache_names {
+                    let value = if name.contains("encoder") {
+                        // Cross-attention outputs are only available in the
+                        // first step.
+                        if step > 0 {
+                            continue;
+                        }
+                        encoder_cache.clone()
+                    } else {
+                        NdTensor::zeros([1, n_heads, prompt.len() + step, n_embed])
+                    };
+                    step_outputs.insert(
+                        model.find_node(&format!("present.{}", name)).unwrap(),
+                        Output::FloatTensor(value.into()),
+                    );
+                }
+                model.add_outputs(step_outputs);
+            }
+            model
+        };
+
+        for kv_cache_dtype in [KvCacheDtype::F32, KvCacheDtype::F16] {
+            let model = make_model();
+            let config = GeneratorConfig {
+                kv_cache_dtype,
+                ..Default::default()
+            };
+            let mut generator = Generator::from_model_config(&model, config)?.with_prompt(&prompt);
+            let output: Vec<_> = generator
+                .by_ref()
+                .take(expected_token_ids.len())
+                .map(|id| id.expect("generation failed"))
+                .collect();
+            assert_eq!(output, expected_token_ids);
+
+            // The cross-attention cache is empty in the first step, then the
+            // value computed in the first step is passed in later steps.
+            let encoder_key = model.find_node("past_key_values.0.encoder.key").unwrap();
+            let get_encoder_key = |step| -> NdTensor<f32, 4> {
+                model
+                    .get_inputs(step, encoder_key)
+                    .unwrap()
+                    .try_into()
+                    .unwrap()
+            };
+            assert_eq!(get_encoder_key(0).size(2), 0);
+            for step in 1..expected_token_ids.len() {
+                assert_eq!(get_encoder_key(step), encoder_cache);
+            }
+
+            // Only the self-attention caches use the configured data type.
+            // Cross-attention caches are always stored as f32.
+            let dtypes: Vec<_> = generator
+                .state()
+                .kv_cache
+                .iter()
+                .map(|cache| cache.as_ref().unwrap().dtype())
+                .collect();
+            assert_eq!(
+                dtypes,
+                [
+                    kv_cache_dtype,
+                    kv_cache_dtype,
+                    KvCacheDtype::F32,
+                    KvCacheDtype::F32
+                ]
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_f16_kv_cache() -> Result<(), Box<dyn Error>> {
+        let params = TransformerParams::default();
+        let expected_token_ids = [0, 1, 2, 3];
+        let prompt = [1, 2, 3, 1, 2, 3];
+        let model = fake_transformer_model(
+            params,
+            true, /* use_kv_cache */
+            prompt.len(),
+            &expected_token_ids,
+        );
+
+        let config = GeneratorConfig {
+            kv_cache_dtype: KvCacheDtype::F16,
+            ..Default::default()
+        };
+        let mut generator = Generator::from_model_config(&model, config)?.with_prompt(&prompt);
+        let first: Vec<_> = generator
+            .by_ref()
+            .take(2)
+            .map(|id| id.expect("generation failed"))
+            .collect();
+        assert_eq!(first, [0, 1]);
+
+        // The cache is stored as f16 between steps.
+        let state = generator.state();
+        for cache in &state.kv_cache {
+            match cache {
+                Some(KvCacheStorage::F16(KvCacheData::BatchHeadSeqChans(_))) => {}
+                _ => panic!("unexpected cache type"),
+            }
+        }
+
+        // f16 caches are preserved when state is saved and loaded.
+        let mut buf = Vec::new();
+        state.write(&mut buf)?;
+        let loaded = GeneratorState::read(buf.as_slice())?;
+        for (loaded, saved) in loaded.kv_cache.iter().zip(&state.kv_cache) {
+            match (loaded, saved) {
+                (
+                    Some(KvCacheStorage::F16(KvCacheData::BatchHeadSeqChans(loaded))),
+                    Some(KvCacheStorage::F16(KvCacheData::BatchHeadSeqChans(saved))),
+                ) => assert_eq!(loaded, saved),
+                _ => panic!("unexpected cache type"),
+            }
+        }
+
+        // f16 state can't be restored into a generator which uses f32.
+        let mut f32_generator = Generator::from_model(&model)?;
+        assert!(matches!(
+            f32_generator.restore_state(&state),
+            Err(GeneratorError::ShapeMismatch(_))
+        ));
+
+        let rest: Vec<_> = generator
+            .take(2)
+            .map(|id| id.expect("generation failed"))
+            .collect();
+        assert_eq!(rest, [2, 3]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_kv_cache_reserve() {
+        let mut cache = KvCacheData::BatchHeadSeqChans(NdTensor::with_capacity([1, 2, 4, 3], 2));
+        let KvCacheData::BatchHeadSeqChans(tensor) = &mut cache else {
+            unreachable!();
+        };
+        let data = NdTensor::from_fn([1, 2, 3, 3], |[_, h, s, c]| (h * 100 + s * 10 + c) as f32);
+        tensor.append(2, &data).unwrap();
+
+        // Reserving space within the current capacity has no effect.
+        cache.reserve(1, true /* amortize */);
+        let KvCacheData::BatchHeadSeqChans(tensor) = &cache else {
+            unreachable!();
+        };
+        assert!(tensor.has_capacity(2, 4));
+        assert!(!tensor.has_capacity(2, 5));
+
+        // Reserving space beyond the capacity grows the cache and preserves
+        // its contents.
+        cache.reserve(2, true /* amortize */);
+        let KvCacheData::BatchHeadSeqChans(tensor) = &cache else {
+            unreachable!();
+        };
+        assert_eq!(tensor.shape(), [1, 2, 3, 3]);
+        assert_eq!(*tensor, data);
+        assert!(tensor.has_capacity(2, 6));
+    }
+
+    #[test]
+    fn test_profile() -> Result<(), Box<dyn Error>> {
+        let params = TransformerParams::default();
+        let expected_token_ids = [0, 1, 2, 3, 4];
+        let prompt = [1, 2, 3, 1, 2, 3];
+        let model = fake_transformer_model(
+            params,
+            true, /* use_kv_cache */
+            prompt.len(),
+            &expected_token_ids,
+        );
+
+        let generator = Generator::from_model(&model)?;
+        let mut metrics = Metrics::new();
+
+        let output_token_ids: Vec<_> = generator
+            .with_prompt(&prompt)
+            .profile(&mut metrics)
+            .take(expected_token_ids.len())
+            .map(|id| id.expect("generation failed"))
+            .collect();
+
+        assert_eq!(output_token_ids, expected_token_ids);
+        assert!(metrics.warmup_duration().is_some());
+        assert_eq!(metrics.step_durations().len(), output_token_ids.len() - 1);
+
+        Ok(())
+    }
+
+    /// Return the log-softmax of `logits`.
+    fn log_softmax(logits: &[f32]) -> Vec<f32> {
+        let sum_exp: f32 = logits.iter().map(|x| x.exp()).sum();
+        logits.iter().map(|x| x - sum_exp.ln()).collect()
+    }
+
+    fn test_score_tokens_impl(use_kv_cache: bool) -> Result<(), Box<dyn Error>> {
+        let params = TransformerParams {
+            n_layers: 1,
+            n_vocab: 4,
+            ..Default::default()
+        };
+        let model = fake_transformer_model(params, use_kv_cache, 0, &[]);
+        let mut model = FakeModel {
+            outputs: Vec::new(),
+            ..model
+        };
+        let logits_id = model.find_node("logits").unwrap();
+        let kv_output_ids: Vec<_> = ["present.0.key", "present.0.value"]
+            .iter()
+            .filter_map(|name| model.find_node(name))
+            .collect();
+
+        // Logits for each run of the model. Each run's output has one row per
+        // input position. Without a KV cache, the second run includes the
+        // positions from the first run.
+        let step_logits = [
+            NdTensor::from([[[0., 1., 2., 3.], [3., 0., 1., 0.]]]),
+            if use_kv_cache {
+                NdTensor::from([[[1., 1., 4., 1.]]])
+            } else {
+                NdTensor::from([[[0., 1., 2., 3.], [3., 0., 1., 0.], [1., 1., 4., 1.]]])
+            },
+        ];
+        for (step, logits) in step_logits.iter().enumerate() {
+            let mut outputs = HashMap::new();
+            outputs.insert(logits_id, Output::FloatTensor(logits.clone().into_dyn()));
+            for &kv_output_id in &kv_output_ids {
+                let past_len = if step == 0 { 2 } else { 3 };
+                outputs.insert(
+                    kv_output_id,
+                    Output::FloatTensor(
+                        NdTensor::zeros([1, params.n_heads, past_len, params.n_embed]).into(),
+                    ),
+                );
+            }
+            model.add_outputs(outputs);
+        }
+
+        let mut generator = Generator::from_model(&model)?.with_prompt(&[1]);
+
+        // Tokens can be scored incrementally.
+        let mut perplexity = Perplexity::new();
+        let scores = generator.score_tokens(&[3, 0])?;
+        perplexity.add(&scores);
+        let scores_2 = generator.score_tokens(&[2])?;
+        perplexity.add(&scores_2);
+
+        let expected = [
+            log_softmax(&[0., 1., 2., 3.])[3],
+            log_softmax(&[3., 0., 1., 0.])[0],
+            log_softmax(&[1., 1., 4., 1.])[2],
+        ];
+        let actual = [scores, scores_2].concat();
+        assert_eq!(actual.len(), expected.len());
+        for (actual, expected) in actual.iter().zip(expected) {
+            assert!((actual - expected).abs() < 1e-5);
+        }
+        assert_eq!(perplexity.count(), 3);
+        let expected_ppl = (-expected.iter().sum::<f32>() / 3.).exp() as f64;
+        assert!((perplexity.value().unwrap() - expected_ppl).abs() < 1e-4);
+
+        // Check model inputs. The last scored token is not passed to the
+        // model until the next run.
+        let input_id = model.find_node("input_ids").unwrap();
+        let inputs: NdTensor<i32, 2> = model.get_inputs(0, input_id).unwrap().try_into()?;
+        assert_eq!(inputs, NdTensor::from([[1, 3]]));
+        let inputs: NdTensor<i32, 2> = model.get_inputs(1, input_id).unwrap().try_into()?;
+        if use_kv_cache {
+            assert_eq!(inputs, NdTensor::from([[0]]));
+        } else {
+            assert_eq!(inputs, NdTensor::from([[1, 3, 0]]));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_score_tokens() -> Result<(), Box<dyn Error>> {
+        test_score_tokens_impl(true /* use_kv_cache */)
+    }
+
+    #[test]
+    fn test_score_tokens_without_kv_cache() -> Result<(), Box<dyn Error>> {
+        test_score_tokens_impl(false /* use_kv_cache */)
+    }
+
+    #[test]
+    fn test_score_tokens_without_prompt() -> Result<(), Box<dyn Error>> {
+        let params = TransformerParams::default();
+        let model = fake_transformer_model(params, false /* use_kv_cache */, 0, &[2]);
+        let mut generator = Generator::from_model(&model)?;
+
+        // The first token is used only as context.
+        let scores = generator.score_tokens(&[1, 2])?;
+        assert_eq!(scores.len(), 1);
+        assert_eq!(Perplexity::new().value(), None);
 
         Ok(())
     }
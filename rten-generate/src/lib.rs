@@ -1,5 +1,7 @@
 //! Utilities to simplify running auto-regressive [RTen][rten] models such
-//! as transformer decoders.
+//! as transformer decoders, computing embeddings with sentence-transformer
+//! models, classifying text and reranking documents with cross-encoder
+//! models.
 //!
 //! For working examples, see the examples in the [rten-examples][rten-examples]
 //! crate which import `rten_generate`.
@@ -7,14 +9,36 @@
 //! [rten]: https://github.com/robertknight/rten
 //! [rten-examples]: https://github.com/robertknight/rten/tree/main/rten-examples
 
+pub mod classify;
+pub mod embedding;
 pub mod generator;
+pub mod logits;
 pub mod metrics;
 pub mod model;
+pub mod pipeline;
+pub mod rerank;
 pub mod sampler;
+pub mod seq2seq;
+pub mod stop_strings;
+pub mod tool_call;
+pub mod whisper;
 
 #[cfg(feature = "text-decoder")]
 pub mod text_decoder;
 
+#[cfg(test)]
+mod test_util;
+
+// This is exposed for use in tests of the C, Python and WebAssembly bindings.
+#[doc(hidden)]
+pub mod test_models;
+
+pub use classify::{ClassifyPipeline, LabelScore};
+pub use embedding::{EmbeddingPipeline, Pooling};
 pub use generator::{
-    Generator, GeneratorConfig, GeneratorError, GeneratorUtils, ModelInputsConfig,
+    Checkpoint, Generator, GeneratorConfig, GeneratorError, GeneratorState, GeneratorUtils,
+    KvCacheDtype, Logprobs, ModelInputsConfig, Perplexity, TokenLogprobs,
 };
+pub use pipeline::{PipelineConfig, PipelineError};
+pub use rerank::{RerankPipeline, RerankResult};
+pub use seq2seq::{Seq2SeqConfig, Seq2SeqGenerator};
@@ -0,0 +1,225 @@
+//! Processors which modify model outputs before a token is sampled.
+
+use rten_tensor::prelude::*;
+use rten_tensor::NdTensorViewMut;
+
+use crate::generator::TokenId;
+
+/// Logits processors modify the output logits of a model before the next
+/// token is sampled, for example to prevent certain tokens from being
+/// generated.
+///
+/// Processors are added to a generator using
+/// [`Generator::with_logits_processor`](crate::Generator::with_logits_processor).
+pub trait LogitsProcessor {
+    /// Modify the output logits of a model.
+    ///
+    /// `output_ids` contains the tokens generated since the prompt was set
+    /// or extended. `logits` has shape `[n_vocab]`. To prevent a token from
+    /// being sampled, set its logit to negative infinity.
+    fn process(&self, output_ids: &[TokenId], logits: NdTensorViewMut<f32, 1>);
+}
+
+/// Set the logits of tokens in `ids` to negative infinity. IDs that are
+/// outside the vocabulary are ignored.
+pub(crate) fn suppress(logits: &mut NdTensorViewMut<f32, 1>, ids: impl IntoIterator<Item = usize>) {
+    for id in ids {
+        if let Some(logit) = logits.get_mut([id]) {
+            *logit = f32::NEG_INFINITY;
+        }
+    }
+}
+
+/// A [`LogitsProcessor`] which prevents a set of tokens from being generated.
+#[derive(Clone, Debug)]
+pub struct SuppressTokens {
+    tokens: Vec<TokenId>,
+}
+
+impl SuppressTokens {
+    pub fn new(tokens: &[TokenId]) -> SuppressTokens {
+        SuppressTokens {
+            tokens: tokens.to_vec(),
+        }
+    }
+}
+
+impl LogitsProcessor for SuppressTokens {
+    fn process(&self, _output_ids: &[TokenId], mut logits: NdTensorViewMut<f32, 1>) {
+        suppress(&mut logits, self.tokens.iter().map(|&id| id as usize));
+    }
+}
+
+/// A [`LogitsProcessor`] which prevents a set of tokens from being the first
+/// token generated.
+///
+/// This is used for example to prevent a speech recognition model from
+/// generating an end-of-text token or blank output at the start of a
+/// transcript.
+#[derive(Clone, Debug)]
+pub struct SuppressTokensAtBegin {
+    tokens: Vec<TokenId>,
+}
+
+impl SuppressTokensAtBegin {
+    pub fn new(tokens: &[TokenId]) -> SuppressTokensAtBegin {
+        SuppressTokensAtBegin {
+            tokens: tokens.to_vec(),
+        }
+    }
+}
+
+impl LogitsProcessor for SuppressTokensAtBegin {
+    fn process(&self, output_ids: &[TokenId], mut logits: NdTensorViewMut<f32, 1>) {
+        if output_ids.is_empty() {
+            suppress(&mut logits, self.tokens.iter().map(|&id| id as usize));
+        }
+    }
+}
+
+/// A [`LogitsProcessor`] which forces specific tokens to be generated at
+/// given positions in the output.
+///
+/// This is used for example to force a speech recognition model to start its
+/// output with tokens that specify the language and task, instead of
+/// detecting them.
+#[derive(Clone, Debug)]
+pub struct ForceTokens {
+    /// `(index, token_id)` pairs, where `index` is the position in the
+    /// generated output.
+    tokens: Vec<(usize, TokenId)>,
+}
+
+impl ForceTokens {
+    /// Create a processor which forces the `index`th generated token to be
+    /// `token_id` for each `(index, token_id)` pair in `tokens`.
+    pub fn new(tokens: &[(usize, TokenId)]) -> ForceTokens {
+        ForceTokens {
+            tokens: tokens.to_vec(),
+        }
+    }
+
+    /// Create a processor which forces the output to start with `tokens`.
+    pub fn prefix(tokens: &[TokenId]) -> ForceTokens {
+        ForceTokens {
+            tokens: tokens.iter().copied().enumerate().collect(),
+        }
+    }
+}
+
+impl LogitsProcessor for ForceTokens {
+    fn process(&self, output_ids: &[TokenId], mut logits: NdTensorViewMut<f32, 1>) {
+        let index = output_ids.len();
+        let Some(&(_, token_id)) = self.tokens.iter().find(|(i, _)| *i == index) else {
+            return;
+        };
+        for (id, logit) in logits.iter_mut().enumerate() {
+            *logit = if id == token_id as usize {
+                0.
+            } else {
+                f32::NEG_INFINITY
+            };
+        }
+    }
+}
+
+/// A [`LogitsProcessor`] which divides logits by a temperature.
+///
+/// Temperatures below 1.0 make the most likely tokens more likely to be
+/// sampled, and temperatures above 1.0 make the distribution more uniform. A
+/// temperature of zero keeps only the most likely tokens, so that sampling
+/// is equivalent to choosing the token with the highest probability.
+///
+/// This is normally set using
+/// [`Generator::with_temperature`](crate::Generator::with_temperature).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Temperature {
+    temperature: f32,
+}
+
+impl Temperature {
+    /// Create a processor with a given temperature.
+    ///
+    /// Panics if the temperature is negative.
+    pub fn new(temperature: f32) -> Temperature {
+        assert!(temperature >= 0., "temperature must be >= 0");
+        Temperature { temperature }
+    }
+}
+
+impl LogitsProcessor for Temperature {
+    fn process(&self, _output_ids: &[TokenId], mut logits: NdTensorViewMut<f32, 1>) {
+        if self.temperature == 1. {
+            return;
+        }
+        if self.temperature == 0. {
+            let max = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+            for logit in logits.iter_mut() {
+                if *logit < max {
+                    *logit = f32::NEG_INFINITY;
+                }
+            }
+            return;
+        }
+        for logit in logits.iter_mut() {
+            *logit /= self.temperature;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rten_tensor::prelude::*;
+    use rten_tensor::NdTensor;
+
+    use super::{ForceTokens, LogitsProcessor, SuppressTokens, SuppressTokensAtBegin, Temperature};
+    use crate::generator::TokenId;
+
+    const NEG_INF: f32 = f32::NEG_INFINITY;
+
+    fn process(processor: &dyn LogitsProcessor, output_ids: &[TokenId]) -> Vec<f32> {
+        let mut logits = NdTensor::from([1., 2., 3., 4.]);
+        processor.process(output_ids, logits.view_mut());
+        logits.to_vec()
+    }
+
+    #[test]
+    fn test_suppress_tokens() {
+        let processor = SuppressTokens::new(&[1, 3, 10]);
+        assert_eq!(process(&processor, &[]), [1., NEG_INF, 3., NEG_INF]);
+        assert_eq!(process(&processor, &[0, 2]), [1., NEG_INF, 3., NEG_INF]);
+    }
+
+    #[test]
+    fn test_suppress_tokens_at_begin() {
+        let processor = SuppressTokensAtBegin::new(&[0]);
+        assert_eq!(process(&processor, &[]), [NEG_INF, 2., 3., 4.]);
+        assert_eq!(process(&processor, &[2]), [1., 2., 3., 4.]);
+    }
+
+    #[test]
+    fn test_force_tokens() {
+        let processor = ForceTokens::new(&[(0, 2), (2, 1)]);
+        assert_eq!(process(&processor, &[]), [NEG_INF, NEG_INF, 0., NEG_INF]);
+        assert_eq!(process(&processor, &[2]), [1., 2., 3., 4.]);
+        assert_eq!(
+            process(&processor, &[2, 3]),
+            [NEG_INF, 0., NEG_INF, NEG_INF]
+        );
+
+        let processor = ForceTokens::prefix(&[3, 0]);
+        assert_eq!(process(&processor, &[3]), [0., NEG_INF, NEG_INF, NEG_INF]);
+        assert_eq!(process(&processor, &[3, 0]), [1., 2., 3., 4.]);
+    }
+
+    #[test]
+    fn test_temperature() {
+        assert_eq!(process(&Temperature::new(1.), &[]), [1., 2., 3., 4.]);
+        assert_eq!(process(&Temperature::new(0.5), &[]), [2., 4., 6., 8.]);
+        assert_eq!(process(&Temperature::new(2.), &[]), [0.5, 1., 1.5, 2.]);
+        assert_eq!(
+            process(&Temperature::new(0.), &[]),
+            [NEG_INF, NEG_INF, NEG_INF, 4.]
+        );
+    }
+}
@@ -0,0 +1,224 @@
+//! Types shared by pipelines which run encoder models on batches of token
+//! sequences: [`EmbeddingPipeline`](crate::EmbeddingPipeline),
+//! [`ClassifyPipeline`](crate::ClassifyPipeline) and
+//! [`RerankPipeline`](crate::RerankPipeline).
+
+use std::error::Error;
+use std::fmt;
+
+use rten::{InputOrOutput, NodeId, Output};
+use rten_tensor::prelude::*;
+use rten_tensor::NdTensor;
+
+#[cfg(feature = "text-decoder")]
+use rten_text::tokenizers::{EncodeOptions, EncoderInput, Tokenizer, TokenizerError, Truncation};
+
+use crate::generator::TokenId;
+use crate::model::Model;
+
+/// Errors that occur when creating or running a pipeline.
+#[derive(Debug)]
+pub enum PipelineError {
+    /// An expected model input was not found.
+    InputNotFound(String),
+
+    /// An expected model output was not found.
+    OutputNotFound(String),
+
+    /// The inputs or model output did not have the expected shape.
+    ShapeMismatch(String),
+
+    /// The model configuration could not be parsed.
+    InvalidConfig(String),
+
+    /// An error occurred while running the model.
+    RunError(Box<dyn Error>),
+
+    /// An error occurred while encoding text.
+    #[cfg(feature = "text-decoder")]
+    EncodeError(TokenizerError),
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PipelineError::InputNotFound(name) => write!(f, "model input not found: {}", name),
+            PipelineError::OutputNotFound(name) => write!(f, "model output not found: {}", name),
+            PipelineError::ShapeMismatch(err) => write!(f, "shape mismatch: {}", err),
+            PipelineError::InvalidConfig(err) => write!(f, "invalid config: {}", err),
+            PipelineError::RunError(err) => write!(f, "model run failed: {}", err),
+            #[cfg(feature = "text-decoder")]
+            PipelineError::EncodeError(err) => write!(f, "encode error: {}", err),
+        }
+    }
+}
+
+impl Error for PipelineError {}
+
+/// Specifies the names of model inputs and outputs used by a pipeline.
+pub struct PipelineConfig<'a> {
+    /// Model input that contains the `(batch, sequence)` token IDs.
+    pub input_ids: &'a str,
+
+    /// Model input that contains the attention mask. This is optional.
+    pub attention_mask: &'a str,
+
+    /// Model input that contains token type IDs. This is optional.
+    pub token_type_ids: &'a str,
+
+    /// Model output that the pipeline reads, such as the hidden states or
+    /// logits.
+    pub output: &'a str,
+}
+
+impl<'a> PipelineConfig<'a> {
+    /// Create a config with a given output name and input names that follow
+    /// the conventions of models exported with Hugging Face's Optimum tool.
+    pub fn new(output: &'a str) -> Self {
+        PipelineConfig {
+            input_ids: "input_ids",
+            attention_mask: "attention_mask",
+            token_type_ids: "token_type_ids",
+            output,
+        }
+    }
+}
+
+/// Runs a model on batches of token sequences.
+///
+/// Sequences are split into batches of at most `batch_size` items, and the
+/// sequences in each batch are right-padded with zeros to the same length.
+pub(crate) struct BatchRunner<'a> {
+    model: &'a dyn Model,
+    input_ids_input: NodeId,
+    attention_mask_input: Option<NodeId>,
+    token_type_ids_input: Option<NodeId>,
+    output: NodeId,
+    batch_size: usize,
+}
+
+impl<'a> BatchRunner<'a> {
+    /// Look up the inputs and outputs specified by `config` in `model`.
+    ///
+    /// The batch size defaults to 16.
+    pub fn new(model: &'a dyn Model, config: PipelineConfig) -> Result<Self, PipelineError> {
+        let input_ids_input = model
+            .find_node(config.input_ids)
+            .ok_or(PipelineError::InputNotFound(config.input_ids.to_string()))?;
+        let output = model
+            .find_node(config.output)
+            .ok_or(PipelineError::OutputNotFound(config.output.to_string()))?;
+
+        Ok(BatchRunner {
+            model,
+            input_ids_input,
+            attention_mask_input: model.find_node(config.attention_mask),
+            token_type_ids_input: model.find_node(config.token_type_ids),
+            output,
+            batch_size: 16,
+        })
+    }
+
+    /// Set the maximum number of sequences passed to the model in one run.
+    pub fn set_batch_size(&mut self, batch_size: usize) {
+        self.batch_size = batch_size.max(1);
+    }
+
+    /// Run the model on each batch of `sequences` and pass the batch and
+    /// model output to `f`.
+    ///
+    /// `type_id(i, j)` returns the token type ID of token `j` in
+    /// `sequences[i]`. The attention mask is 1 for non-padding tokens and 0
+    /// for padding.
+    pub fn run_batches<S: AsRef<[TokenId]>>(
+        &self,
+        sequences: &[S],
+        type_id: impl Fn(usize, usize) -> usize,
+        mut f: impl FnMut(&[S], Output) -> Result<(), PipelineError>,
+    ) -> Result<(), PipelineError> {
+        for (batch_idx, batch) in sequences.chunks(self.batch_size).enumerate() {
+            let offset = batch_idx * self.batch_size;
+            let output = self.run_batch(batch, |i, j| type_id(offset + i, j))?;
+            f(batch, output)?;
+        }
+        Ok(())
+    }
+
+    fn run_batch<S: AsRef<[TokenId]>>(
+        &self,
+        sequences: &[S],
+        type_id: impl Fn(usize, usize) -> usize,
+    ) -> Result<Output, PipelineError> {
+        let batch = sequences.len();
+        let seq_lens: Vec<usize> = sequences.iter().map(|s| s.as_ref().len()).collect();
+        let max_seq_len = seq_lens.iter().copied().max().unwrap_or(0);
+
+        let input_ids = NdTensor::from_fn([batch, max_seq_len], |[i, j]| {
+            sequences[i].as_ref().get(j).copied().unwrap_or(0) as i32
+        });
+        let attention_mask =
+            NdTensor::from_fn([batch, max_seq_len], |[i, j]| (j < seq_lens[i]) as i32);
+
+        let mut inputs: Vec<(NodeId, InputOrOutput)> =
+            vec![(self.input_ids_input, input_ids.view().into())];
+        if let Some(attention_mask_input) = self.attention_mask_input {
+            inputs.push((attention_mask_input, attention_mask.view().into()));
+        }
+
+        let type_ids: NdTensor<i32, 2>;
+        if let Some(type_ids_input) = self.token_type_ids_input {
+            type_ids = NdTensor::from_fn([batch, max_seq_len], |[i, j]| {
+                if j < seq_lens[i] {
+                    type_id(i, j) as i32
+                } else {
+                    0
+                }
+            });
+            inputs.push((type_ids_input, type_ids.view().into()));
+        }
+
+        let mut outputs = self
+            .model
+            .run(inputs, &[self.output])
+            .map_err(PipelineError::RunError)?;
+        Ok(outputs.remove(0))
+    }
+}
+
+/// Tokenize a batch of inputs and return the token IDs and token type IDs
+/// of each input.
+///
+/// If `max_len` is set, each input is truncated to at most that many tokens,
+/// including any special tokens that the tokenizer adds. Any padding added by
+/// the tokenizer is removed, as sequences are padded when they are batched.
+#[cfg(feature = "text-decoder")]
+#[allow(clippy::type_complexity)]
+pub(crate) fn encode_batch<'a, I: Copy + Into<EncoderInput<'a>> + Sync>(
+    tokenizer: &Tokenizer,
+    inputs: &[I],
+    max_len: Option<usize>,
+) -> Result<(Vec<Vec<TokenId>>, Vec<Vec<usize>>), PipelineError> {
+    let encoded = tokenizer
+        .encode_batch(
+            inputs,
+            EncodeOptions {
+                truncation: max_len.map(Truncation::new),
+                ..Default::default()
+            },
+        )
+        .map_err(PipelineError::EncodeError)?;
+
+    Ok(encoded
+        .iter()
+        .map(|encoded| {
+            encoded
+                .token_ids()
+                .iter()
+                .zip(encoded.token_type_ids())
+                .zip(encoded.attention_mask())
+                .filter(|(_, mask)| *mask == 1)
+                .map(|((id, type_id), _)| (*id, type_id))
+                .unzip::<_, _, Vec<_>, Vec<_>>()
+        })
+        .unzip())
+}
@@ -0,0 +1,280 @@
+//! Pipeline to score the relevance of documents to a query using
+//! cross-encoder (reranker) models.
+
+use rten_tensor::prelude::*;
+use rten_tensor::NdTensor;
+use rten_vecmath::vec_sigmoid_in_place;
+
+#[cfg(feature = "text-decoder")]
+use rten_text::tokenizers::Tokenizer;
+
+use crate::generator::TokenId;
+use crate::model::Model;
+#[cfg(feature = "text-decoder")]
+use crate::pipeline::encode_batch;
+use crate::pipeline::{BatchRunner, PipelineConfig, PipelineError};
+
+/// Relevance score for one document, returned by [`RerankPipeline::rerank`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RerankResult {
+    /// Index of the document in the input.
+    pub index: usize,
+
+    /// Relevance of the document to the query.
+    pub score: f32,
+}
+
+/// Scores `(query, document)` pairs using a cross-encoder model.
+///
+/// The model is run on batches of pairs, which are padded to the same
+/// length. It is expected to output either a single relevance logit per pair,
+/// or two logits for the "not relevant" and "relevant" classes. By default
+/// logits are converted to probabilities in `[0, 1]`, so that scores are
+/// comparable across queries.
+pub struct RerankPipeline<'a> {
+    runner: BatchRunner<'a>,
+    calibrate: bool,
+}
+
+impl<'a> RerankPipeline<'a> {
+    /// Create a pipeline which uses default input and output names.
+    ///
+    /// The logits are read from the `logits` output. By default, pairs are
+    /// processed in batches of 16 and scores are calibrated.
+    pub fn from_model(model: &'a dyn Model) -> Result<RerankPipeline<'a>, PipelineError> {
+        Self::from_model_config(model, PipelineConfig::new("logits"))
+    }
+
+    /// Create a pipeline with custom input and output names.
+    ///
+    /// The output in `config` contains the `(batch, num_labels)` logits.
+    pub fn from_model_config(
+        model: &'a dyn Model,
+        config: PipelineConfig,
+    ) -> Result<RerankPipeline<'a>, PipelineError> {
+        Ok(RerankPipeline {
+            runner: BatchRunner::new(model, config)?,
+            calibrate: true,
+        })
+    }
+
+    /// Set the maximum number of pairs that are passed to the model in a
+    /// single run.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.runner.set_batch_size(batch_size);
+        self
+    }
+
+    /// Set whether logits are converted to probabilities. If false, raw
+    /// logits for the "relevant" class are returned.
+    pub fn with_calibrate(mut self, calibrate: bool) -> Self {
+        self.calibrate = calibrate;
+        self
+    }
+
+    /// Compute relevance scores for encoded `(query, document)` pairs.
+    ///
+    /// `type_ids` contains the token type IDs for each sequence in
+    /// `sequences`, which are 0 for query tokens and 1 for document tokens.
+    /// Returns one score per sequence, in the same order as the input.
+    pub fn score_tokens<S: AsRef<[TokenId]>, T: AsRef<[usize]>>(
+        &self,
+        sequences: &[S],
+        type_ids: &[T],
+    ) -> Result<Vec<f32>, PipelineError> {
+        if sequences.len() != type_ids.len() {
+            return Err(PipelineError::ShapeMismatch(
+                "number of token ID and type ID sequences differ".to_string(),
+            ));
+        }
+        if sequences
+            .iter()
+            .zip(type_ids)
+            .any(|(ids, types)| ids.as_ref().len() != types.as_ref().len())
+        {
+            return Err(PipelineError::ShapeMismatch(
+                "token ID and type ID sequence lengths differ".to_string(),
+            ));
+        }
+
+        let mut scores = Vec::with_capacity(sequences.len());
+        self.runner.run_batches(
+            sequences,
+            |i, j| type_ids[i].as_ref()[j],
+            |batch, output| {
+                let logits: NdTensor<f32, 2> = output.try_into().map_err(|_| {
+                    PipelineError::ShapeMismatch(
+                        "expected (batch, num_labels) float logits".to_string(),
+                    )
+                })?;
+                if logits.size(0) != batch.len() || !matches!(logits.size(1), 1 | 2) {
+                    return Err(PipelineError::ShapeMismatch(format!(
+                        "expected logits with shape ({}, 1) or ({}, 2) but got {:?}",
+                        batch.len(),
+                        batch.len(),
+                        logits.shape()
+                    )));
+                }
+
+                let start = scores.len();
+                scores.extend(logits.axis_iter(0).map(|item| {
+                    // For two-class models, the score is the difference
+                    // between the "relevant" and "not relevant" logits, so
+                    // that the sigmoid of it is the softmax probability of
+                    // the "relevant" class.
+                    if self.calibrate && item.size(0) == 2 {
+                        item[[1]] - item[[0]]
+                    } else {
+                        item[[item.size(0) - 1]]
+                    }
+                }));
+                if self.calibrate {
+                    vec_sigmoid_in_place(&mut scores[start..]);
+                }
+                Ok(())
+            },
+        )?;
+        Ok(scores)
+    }
+
+    /// Tokenize `(query, document)` pairs and return the documents' scores,
+    /// sorted in order of decreasing relevance.
+    ///
+    /// If `max_len` is set, each pair is truncated to at most that many
+    /// tokens, including any special tokens that the tokenizer adds.
+    #[cfg(feature = "text-decoder")]
+    pub fn rerank(
+        &self,
+        tokenizer: &Tokenizer,
+        query: &str,
+        documents: &[&str],
+        max_len: Option<usize>,
+    ) -> Result<Vec<RerankResult>, PipelineError> {
+        let pairs: Vec<(&str, &str)> = documents.iter().map(|&doc| (query, doc)).collect();
+        let (sequences, type_ids) = encode_batch(tokenizer, &pairs, max_len)?;
+        let scores = self.score_tokens(&sequences, &type_ids)?;
+        let mut results: Vec<_> = scores
+            .into_iter()
+            .enumerate()
+            .map(|(index, score)| RerankResult { index, score })
+            .collect();
+        results.sort_by(|a, b| b.score.total_cmp(&a.score));
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rten::Output;
+    use rten_tensor::prelude::*;
+    use rten_tensor::NdTensor;
+    use rten_text::tokenizers::{Tokenizer, TokenizerOptions, WordPiece};
+
+    use super::{RerankPipeline, RerankResult};
+    use crate::test_util::{BatchRecordingModel, FnModel, FnModelInputs};
+
+    /// Create a fake cross-encoder whose logit for a pair is the sum of the
+    /// token IDs in the second sequence minus the sum of those in the first.
+    fn fake_rerank_model() -> BatchRecordingModel<FnModel<impl Fn(&FnModelInputs) -> Vec<Output>>> {
+        BatchRecordingModel::new(FnModel::new(
+            &["input_ids", "attention_mask", "token_type_ids"],
+            &["logits"],
+            move |inputs: &FnModelInputs| {
+                let ids = inputs.int("input_ids");
+                let mask = inputs.int("attention_mask");
+                let type_ids = inputs.int("token_type_ids");
+                let [batch, seq] = ids.shape().try_into().unwrap();
+
+                let logits = NdTensor::<f32, 2>::from_fn([batch, 1], |[b, _]| {
+                    (0..seq)
+                        .map(|s| {
+                            let sign = if type_ids[[b, s]] == 1 { 1 } else { -1 };
+                            (sign * ids[[b, s]] * mask[[b, s]]) as f32
+                        })
+                        .sum()
+                });
+                vec![logits.into_dyn().into()]
+            },
+        ))
+    }
+
+    #[test]
+    fn test_score_tokens() {
+        let model = fake_rerank_model();
+        let sequences: [&[u32]; 3] = [&[1, 2, 3], &[1, 4], &[2, 2, 2, 1]];
+        let type_ids: [&[usize]; 3] = [&[0, 1, 1], &[0, 1], &[0, 0, 1, 1]];
+
+        let pipeline = RerankPipeline::from_model(&model)
+            .unwrap()
+            .with_batch_size(2)
+            .with_calibrate(false);
+        let scores = pipeline.score_tokens(&sequences, &type_ids).unwrap();
+        assert_eq!(scores, [4., 3., -1.]);
+        assert_eq!(model.max_batch(), 2);
+
+        let pipeline = RerankPipeline::from_model(&model).unwrap();
+        let scores = pipeline.score_tokens(&sequences, &type_ids).unwrap();
+        let expected = [4f32, 3., -1.].map(|x| 1. / (1. + (-x).exp()));
+        for (actual, expected) in scores.iter().zip(expected) {
+            assert!((actual - expected).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_score_tokens_length_mismatch() {
+        let model = fake_rerank_model();
+        let pipeline = RerankPipeline::from_model(&model).unwrap();
+        let sequences: [&[u32]; 1] = [&[1, 2, 3]];
+        let type_ids: [&[usize]; 1] = [&[0, 1]];
+        let err = pipeline.score_tokens(&sequences, &type_ids).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "shape mismatch: token ID and type ID sequence lengths differ"
+        );
+    }
+
+    #[test]
+    fn test_rerank() {
+        let vocab = &["[CLS]", "[SEP]", "[UNK]", "query", "near", "far", "farther"];
+        let vocab = vocab
+            .iter()
+            .enumerate()
+            .map(|(i, token)| (token.to_string(), i as u32))
+            .collect();
+        let tokenizer = Tokenizer::new(
+            WordPiece::from_vocab(vocab, Default::default()),
+            TokenizerOptions {
+                cls_token: Some("[CLS]"),
+                sep_token: Some("[SEP]"),
+            },
+        );
+
+        let model = fake_rerank_model();
+        let pipeline = RerankPipeline::from_model(&model)
+            .unwrap()
+            .with_calibrate(false);
+        let results = pipeline
+            .rerank(&tokenizer, "query", &["far", "farther", "near"], None)
+            .unwrap();
+
+        // Scores are the sum of document token IDs (including the final
+        // `[SEP]`) minus the sum of the query token IDs (`[CLS] query [SEP]`).
+        assert_eq!(
+            results,
+            [
+                RerankResult {
+                    index: 1,
+                    score: 3.
+                },
+                RerankResult {
+                    index: 0,
+                    score: 2.
+                },
+                RerankResult {
+                    index: 2,
+                    score: 1.
+                },
+            ]
+        );
+    }
+}
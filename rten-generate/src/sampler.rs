@@ -51,10 +51,23 @@ impl TopKSampler {
     /// temperature.
     ///
     /// The `k` value must be > 0 and temperature must be >= 0.0.
+    ///
+    /// The temperature is applied in addition to any temperature set using
+    /// [`Generator::with_temperature`](crate::Generator::with_temperature).
+    /// When using that method, create the sampler using
+    /// [`with_k`](Self::with_k) instead.
     pub fn new(k: usize, temperature: f32) -> TopKSampler {
         Self::with_rng(fastrand::Rng::new(), k, temperature)
     }
 
+    /// Create a sampler which samples from the top `k` tokens with a
+    /// temperature of 1.0.
+    ///
+    /// The `k` value must be > 0.
+    pub fn with_k(k: usize) -> TopKSampler {
+        Self::new(k, 1.0)
+    }
+
     /// Create a sampler which samples from the top `k` tokens, using a seeded
     /// random number generator.
     pub fn with_rng(rng: fastrand::Rng, k: usize, temperature: f32) -> TopKSampler {
@@ -106,6 +119,111 @@ impl Sampler for TopKSampler {
     }
 }
 
+/// A [`Sampler`] which implements locally typical sampling.
+///
+/// Tokens are ranked by how close their information content (negative
+/// log-probability) is to the entropy of the distribution, and sampling is
+/// restricted to the smallest set of the most typical tokens whose
+/// probabilities sum to at least `p`. This corresponds to the `typical_p`
+/// option in Hugging Face Transformers.
+///
+/// See <https://arxiv.org/abs/2202.00666>.
+pub struct TypicalSampler {
+    p: f32,
+    temperature: f32,
+    rng: RefCell<fastrand::Rng>,
+}
+
+impl TypicalSampler {
+    /// Create a sampler which samples from the most typical tokens whose
+    /// cumulative probability is at least `p`, with a given temperature.
+    ///
+    /// The `p` value must be in `(0, 1]` and temperature must be >= 0.0.
+    ///
+    /// The temperature is applied in addition to any temperature set using
+    /// [`Generator::with_temperature`](crate::Generator::with_temperature).
+    /// When using that method, create the sampler using
+    /// [`with_p`](Self::with_p) instead.
+    pub fn new(p: f32, temperature: f32) -> TypicalSampler {
+        Self::with_rng(fastrand::Rng::new(), p, temperature)
+    }
+
+    /// Create a sampler which samples from the most typical tokens whose
+    /// cumulative probability is at least `p`, with a temperature of 1.0.
+    ///
+    /// The `p` value must be in `(0, 1]`.
+    pub fn with_p(p: f32) -> TypicalSampler {
+        Self::new(p, 1.0)
+    }
+
+    /// Create a sampler which samples from the most typical tokens, using a
+    /// seeded random number generator.
+    pub fn with_rng(rng: fastrand::Rng, p: f32, temperature: f32) -> TypicalSampler {
+        assert!(temperature >= 0.);
+        assert!(p > 0. && p <= 1.);
+
+        TypicalSampler {
+            rng: RefCell::new(rng),
+            p,
+            temperature,
+        }
+    }
+}
+
+impl Sampler for TypicalSampler {
+    fn sample(&self, logits: NdTensorView<f32, 1>) -> TokenId {
+        if self.temperature == 0. {
+            return ArgMaxSampler::new().sample(logits);
+        }
+
+        // Compute log-probabilities from the scaled logits.
+        let scaled: Vec<f32> = logits.iter().map(|x| x / self.temperature).collect();
+        let max = scaled.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        let log_sum = scaled.iter().map(|x| (x - max).exp()).sum::<f32>().ln() + max;
+        let log_probs: Vec<f32> = scaled.iter().map(|x| x - log_sum).collect();
+
+        let entropy: f32 = log_probs
+            .iter()
+            .filter(|lp| lp.is_finite())
+            .map(|lp| -lp * lp.exp())
+            .sum();
+
+        // Rank tokens by the distance of their information content from the
+        // entropy, and keep the most typical tokens until their cumulative
+        // probability reaches `p`.
+        let mut indices: Vec<usize> = (0..log_probs.len()).collect();
+        indices.sort_by(|&a, &b| {
+            let dist_a = (-log_probs[a] - entropy).abs();
+            let dist_b = (-log_probs[b] - entropy).abs();
+            dist_a.total_cmp(&dist_b)
+        });
+
+        let mut keep = 0;
+        let mut cum_prob = 0.;
+        for &idx in &indices {
+            keep += 1;
+            cum_prob += log_probs[idx].exp();
+            if cum_prob >= self.p {
+                break;
+            }
+        }
+        indices.truncate(keep);
+
+        // Renormalize the probabilities of the kept tokens and sample.
+        let probs: Vec<f32> = indices
+            .iter()
+            .map(|&idx| log_probs[idx].exp() / cum_prob)
+            .collect();
+        let index = multinomial(
+            &mut self.rng.borrow_mut(),
+            NdTensorView::from_data([probs.len()], probs.as_slice()),
+        )
+        .unwrap_or(probs.len() - 1);
+
+        indices[index] as TokenId
+    }
+}
+
 /// Sample an item from a vector of probabilities.
 ///
 /// Returns the index of the selected item, or `None` if the vector is empty
@@ -129,7 +247,7 @@ mod tests {
     use rten_tensor::prelude::*;
     use rten_tensor::NdTensor;
 
-    use super::{ArgMaxSampler, Sampler, TopKSampler};
+    use super::{ArgMaxSampler, Sampler, TopKSampler, TypicalSampler};
 
     #[test]
     fn test_argmax_sampler() {
@@ -212,4 +330,68 @@ mod tests {
             assert_eq!(counts[logits.size(vocab_dim) - k..], *expected);
         }
     }
+
+    #[test]
+    fn test_sampler_default_temperature() {
+        assert_eq!(TopKSampler::with_k(3).temperature, 1.0);
+        assert_eq!(TypicalSampler::with_p(0.5).temperature, 1.0);
+    }
+
+    #[test]
+    fn test_typical_sampler() {
+        struct Case<'a> {
+            p: f32,
+            temperature: f32,
+
+            // Number of times each of the top 4 tokens should be sampled,
+            // ordered from least to most probable.
+            expected_counts: &'a [usize],
+        }
+
+        // For logits `[0, 1, ... 9]`, the entropy of the distribution is
+        // closer to the information content of the second most probable
+        // token than the most probable one, so the second token is the most
+        // typical.
+        let cases = [
+            Case {
+                p: 0.2,
+                temperature: 1.0,
+                expected_counts: &[0, 0, 100, 0],
+            },
+            Case {
+                p: 0.8,
+                temperature: 1.0,
+                expected_counts: &[0, 0, 30, 70],
+            },
+            Case {
+                p: 0.9,
+                temperature: 1.0,
+                expected_counts: &[0, 12, 28, 60],
+            },
+            Case {
+                p: 0.9,
+                temperature: 0.,
+                expected_counts: &[0, 0, 0, 100],
+            },
+        ];
+
+        for Case {
+            p,
+            temperature,
+            expected_counts: expected,
+        } in cases
+        {
+            let rng = fastrand::Rng::with_seed(1234);
+            let logits = NdTensor::arange(0., 10., None);
+            let sampler = TypicalSampler::with_rng(rng, p, temperature);
+
+            let mut counts = vec![0; logits.size(0)];
+            for _ in 0..100 {
+                counts[sampler.sample(logits.view()) as usize] += 1;
+            }
+
+            assert_eq!(counts[..6].iter().sum::<usize>(), 0);
+            assert_eq!(counts[6..], *expected);
+        }
+    }
 }
@@ -0,0 +1,236 @@
+//! Generate sequences using encoder-decoder models.
+
+use rten::{InputOrOutput, NodeId};
+use rten_tensor::prelude::*;
+use rten_tensor::NdTensor;
+
+use crate::generator::{Generator, GeneratorConfig, GeneratorError, TokenId};
+use crate::model::Model;
+
+/// Specifies the names of inputs and outputs used by a [`Seq2SeqGenerator`].
+///
+/// The [`Default`] impl returns names that follow the conventions of
+/// encoder-decoder models exported with Hugging Face's Optimum tool, where
+/// the encoder and decoder are separate models.
+#[derive(Clone)]
+pub struct Seq2SeqConfig<'a> {
+    /// Encoder output that contains the `(batch, sequence, embed)` hidden
+    /// states.
+    pub encoder_output: &'a str,
+
+    /// Decoder input which receives the encoder's hidden states.
+    pub encoder_hidden_states: &'a str,
+
+    /// Decoder input that contains the attention mask for the encoder's
+    /// hidden states. This is optional.
+    pub encoder_attention_mask: &'a str,
+
+    /// Configuration for the decoder.
+    ///
+    /// The decoder's token IDs input is specified by
+    /// `decoder.model_inputs.input_ids`. This is `input_ids` by default, but
+    /// some exports which combine the encoder and decoder into one model name
+    /// it `decoder_input_ids`.
+    pub decoder: GeneratorConfig<'a>,
+}
+
+impl Default for Seq2SeqConfig<'_> {
+    fn default() -> Self {
+        Seq2SeqConfig {
+            encoder_output: "last_hidden_state",
+            encoder_hidden_states: "encoder_hidden_states",
+            encoder_attention_mask: "encoder_attention_mask",
+            decoder: GeneratorConfig::default(),
+        }
+    }
+}
+
+/// Generates token sequences using an encoder-decoder model, such as a
+/// translation, summarization or speech recognition model.
+///
+/// The encoder is run once for each input, then a [`Generator`] is created
+/// which runs the decoder auto-regressively, with the encoder's hidden states
+/// as a constant input. The returned generator has the same interface as one
+/// created for a decoder-only model, so it can be configured with a sampler
+/// and used with the methods of [`GeneratorUtils`](crate::GeneratorUtils).
+pub struct Seq2SeqGenerator<'a> {
+    encoder: &'a dyn Model,
+    decoder: &'a dyn Model,
+    encoder_output: NodeId,
+    encoder_hidden_states_input: NodeId,
+    encoder_attention_mask_input: Option<NodeId>,
+    config: GeneratorConfig<'a>,
+}
+
+impl<'a> Seq2SeqGenerator<'a> {
+    /// Create a generator which uses default input and output names.
+    pub fn from_models(
+        encoder: &'a dyn Model,
+        decoder: &'a dyn Model,
+    ) -> Result<Seq2SeqGenerator<'a>, GeneratorError> {
+        Self::from_models_config(encoder, decoder, Seq2SeqConfig::default())
+    }
+
+    /// Create a generator with custom input and output names.
+    pub fn from_models_config(
+        encoder: &'a dyn Model,
+        decoder: &'a dyn Model,
+        config: Seq2SeqConfig<'a>,
+    ) -> Result<Seq2SeqGenerator<'a>, GeneratorError> {
+        let encoder_output = encoder
+            .find_node(config.encoder_output)
+            .ok_or_else(|| GeneratorError::OutputNotFound(config.encoder_output.to_string()))?;
+        let encoder_hidden_states_input = decoder
+            .find_node(config.encoder_hidden_states)
+            .ok_or_else(|| {
+                GeneratorError::InputNotFound(config.encoder_hidden_states.to_string())
+            })?;
+        let encoder_attention_mask_input = decoder.find_node(config.encoder_attention_mask);
+
+        Ok(Seq2SeqGenerator {
+            encoder,
+            decoder,
+            encoder_output,
+            encoder_hidden_states_input,
+            encoder_attention_mask_input,
+            config: config.decoder,
+        })
+    }
+
+    /// Run the encoder on `encoder_inputs` and return a generator which runs
+    /// the decoder on its output.
+    ///
+    /// `decoder_prompt` is the initial input to the decoder. This normally
+    /// starts with the model's `decoder_start_token_id`, which can be found in
+    /// the model's `config.json` file, and may be followed by other tokens
+    /// which control the output, such as a target language.
+    pub fn generate(
+        &self,
+        encoder_inputs: Vec<(NodeId, InputOrOutput)>,
+        decoder_prompt: &[TokenId],
+    ) -> Result<Generator<'a>, GeneratorError> {
+        let hidden_states = self
+            .encoder
+            .run(encoder_inputs, &[self.encoder_output])
+            .map_err(GeneratorError::GenerateError)?
+            .remove(0);
+        let encoder_len = match hidden_states.shape() {
+            [_batch, seq, _embed] => *seq,
+            shape => {
+                return Err(GeneratorError::ShapeMismatch(format!(
+                    "expected encoder output to have shape (batch, seq, embed) but shape is {:?}",
+                    shape
+                )));
+            }
+        };
+
+        let mut generator = Generator::from_model_config(self.decoder, self.config.clone())?
+            .with_prompt(decoder_prompt)
+            .with_constant_value(self.encoder_hidden_states_input, hidden_states.into());
+
+        if let Some(attention_mask_input) = self.encoder_attention_mask_input {
+            let batch_size = 1;
+            let attention_mask = NdTensor::full([batch_size, encoder_len], 1i32);
+            generator = generator
+                .with_constant_value(attention_mask_input, attention_mask.into_dyn().into());
+        }
+
+        Ok(generator)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::error::Error;
+
+    use rten::Output;
+    use rten_tensor::prelude::*;
+    use rten_tensor::NdTensor;
+
+    use super::Seq2SeqGenerator;
+    use crate::model::Model;
+    use crate::test_util::{FnModel, FnModelInputs};
+
+    const N_VOCAB: usize = 8;
+
+    /// Create a fake encoder which outputs one hidden state for each input
+    /// feature.
+    fn fake_encoder() -> FnModel<impl Fn(&FnModelInputs) -> Vec<Output>> {
+        FnModel::new(
+            &["input_features"],
+            &["last_hidden_state"],
+            |inputs: &FnModelInputs| {
+                let features = inputs.float("input_features");
+                let hidden = NdTensor::<f32, 3>::zeros([1, features.size(1), 4]);
+                vec![hidden.into_dyn().into()]
+            },
+        )
+    }
+
+    /// Create a fake decoder which predicts the last input token plus the
+    /// number of encoder hidden states.
+    ///
+    /// The shapes of the encoder attention masks passed to the decoder are
+    /// recorded in `masks`.
+    fn fake_decoder(
+        masks: &RefCell<Vec<Vec<usize>>>,
+    ) -> FnModel<impl Fn(&FnModelInputs) -> Vec<Output> + '_> {
+        FnModel::new(
+            &[
+                "input_ids",
+                "encoder_hidden_states",
+                "encoder_attention_mask",
+            ],
+            &["logits"],
+            move |inputs: &FnModelInputs| {
+                let ids = inputs.int("input_ids");
+                let hidden = inputs.float("encoder_hidden_states");
+                let mask = inputs.int("encoder_attention_mask");
+                masks.borrow_mut().push(mask.shape().to_vec());
+
+                let seq = ids.size(1);
+                let last_id = ids[[0, seq - 1]] as usize;
+                let next_id = (last_id + hidden.size(1)) % N_VOCAB;
+                let logits = NdTensor::<f32, 3>::from_fn([1, seq, N_VOCAB], |[_, _, i]| {
+                    if i == next_id {
+                        1.
+                    } else {
+                        0.
+                    }
+                });
+                vec![logits.into_dyn().into()]
+            },
+        )
+    }
+
+    #[test]
+    fn test_seq2seq_generator() -> Result<(), Box<dyn Error>> {
+        let masks = RefCell::new(Vec::new());
+        let encoder = fake_encoder();
+        let decoder = fake_decoder(&masks);
+        let seq2seq = Seq2SeqGenerator::from_models(&encoder, &decoder)?;
+
+        let features_id = encoder.find_node("input_features").unwrap();
+        let features = NdTensor::<f32, 2>::zeros([1, 3]);
+        let output: Vec<_> = seq2seq
+            .generate(vec![(features_id, features.view().into())], &[1])?
+            .take(3)
+            .map(|id| id.expect("generation failed"))
+            .collect();
+        assert_eq!(output, [4, 7, 2]);
+        assert_eq!(*masks.borrow(), [[1, 3], [1, 3], [1, 3]]);
+
+        // The missing input is reported if the decoder has no input for the
+        // encoder's hidden states.
+        let err = Seq2SeqGenerator::from_models(&encoder, &encoder)
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "model input not found: encoder_hidden_states"
+        );
+
+        Ok(())
+    }
+}
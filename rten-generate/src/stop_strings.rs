@@ -0,0 +1,182 @@
+//! Iterator adapter which stops generation when a stop string is produced.
+
+use crate::generator::GeneratorError;
+use crate::tool_call::partial_match_len;
+
+/// Wraps a stream of decoded text to stop when any of a set of stop strings
+/// is generated.
+///
+/// Unlike [`stop_on_tokens`](crate::GeneratorUtils::stop_on_tokens), this
+/// matches text, so stop strings such as `"\nUser:"` can span several tokens
+/// or start in the middle of a token. Text is held back while it may be the
+/// start of a stop string. When a stop string is found, the text before it
+/// is yielded and the stream ends, without yielding the stop string or
+/// requesting more text from the wrapped iterator.
+///
+/// This is normally created by calling
+/// [`decode_until`](crate::GeneratorUtils::decode_until) on a `Generator`.
+pub struct StopStrings<I: Iterator<Item = Result<String, GeneratorError>>> {
+    text: I,
+    stop_strings: Vec<String>,
+    buf: String,
+
+    /// The stop string that ended the stream, if any.
+    matched: Option<usize>,
+
+    /// True if the stream has ended.
+    done: bool,
+}
+
+impl<I: Iterator<Item = Result<String, GeneratorError>>> StopStrings<I> {
+    /// Wrap a stream of decoded text. Empty stop strings are ignored.
+    pub fn wrap<S: AsRef<str>>(text: I, stop_strings: &[S]) -> StopStrings<I> {
+        StopStrings {
+            text,
+            stop_strings: stop_strings
+                .iter()
+                .map(|s| s.as_ref())
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_string())
+                .collect(),
+            buf: String::new(),
+            matched: None,
+            done: false,
+        }
+    }
+
+    /// Return the stop string which ended the stream, or `None` if no stop
+    /// string has been found.
+    pub fn matched(&self) -> Option<&str> {
+        self.matched.map(|index| self.stop_strings[index].as_str())
+    }
+
+    /// Return the position and index of the earliest stop string in the
+    /// buffered text.
+    fn find_stop(&self) -> Option<(usize, usize)> {
+        self.stop_strings
+            .iter()
+            .enumerate()
+            .filter_map(|(index, stop)| self.buf.find(stop.as_str()).map(|pos| (pos, index)))
+            .min()
+    }
+}
+
+impl<I: Iterator<Item = Result<String, GeneratorError>>> Iterator for StopStrings<I> {
+    /// The next chunk of text before any stop string, or the error that
+    /// occurred during generation or decoding.
+    type Item = Result<String, GeneratorError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            match self.text.next() {
+                Some(Ok(text)) => {
+                    self.buf.push_str(&text);
+                    if let Some((pos, index)) = self.find_stop() {
+                        self.buf.truncate(pos);
+                        self.matched = Some(index);
+                        self.done = true;
+                    } else {
+                        // Hold back any suffix which may be the start of a
+                        // stop string that is completed by later text.
+                        let keep = self
+                            .stop_strings
+                            .iter()
+                            .map(|stop| partial_match_len(&self.buf, stop))
+                            .max()
+                            .unwrap_or(0);
+                        if keep < self.buf.len() {
+                            let text = self.buf.drain(..self.buf.len() - keep).collect();
+                            return Some(Ok(text));
+                        }
+                    }
+                }
+                Some(Err(err)) => return Some(Err(err)),
+                None => self.done = true,
+            }
+        }
+
+        let text = std::mem::take(&mut self.buf);
+        (!text.is_empty()).then_some(Ok(text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StopStrings;
+    use crate::generator::GeneratorError;
+
+    fn run(chunks: &[&str], stop_strings: &[&str]) -> (Vec<String>, Option<String>) {
+        let text = chunks.iter().map(|chunk| Ok(chunk.to_string()));
+        let mut stream = StopStrings::wrap(text, stop_strings);
+        let output = stream.by_ref().map(|chunk| chunk.unwrap()).collect();
+        (output, stream.matched().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn test_stop_strings() {
+        // Stop string split across chunks, starting mid-chunk.
+        let (output, matched) = run(
+            &["Hello", " there.\nUs", "er: hi", "more"],
+            &["\nUser:", "###"],
+        );
+        assert_eq!(output, ["Hello", " there."]);
+        assert_eq!(matched.as_deref(), Some("\nUser:"));
+
+        // Text which is held back because it may start a stop string is
+        // emitted once it no longer matches.
+        let (output, matched) = run(&["a\nU", "b"], &["\nUser:"]);
+        assert_eq!(output, ["a", "\nUb"]);
+        assert_eq!(matched, None);
+
+        // Held back text is emitted at the end of the stream.
+        let (output, _) = run(&["a\nUs"], &["\nUser:"]);
+        assert_eq!(output, ["a", "\nUs"]);
+
+        // If several stop strings match, the earliest is used.
+        let (output, matched) = run(&["one ### two \nUser:"], &["\nUser:", "###"]);
+        assert_eq!(output, ["one "]);
+        assert_eq!(matched.as_deref(), Some("###"));
+
+        // Stop string at the start of the output.
+        let (output, _) = run(&["###", "abc"], &["###"]);
+        assert!(output.is_empty());
+
+        // No stop strings.
+        let (output, _) = run(&["a", "b"], &[]);
+        assert_eq!(output, ["a", "b"]);
+    }
+
+    #[test]
+    fn test_stops_reading_input() {
+        let mut chunks_read = 0;
+        let text = ["a", "b", "STOP", "c", "d"].into_iter().map(|chunk| {
+            chunks_read += 1;
+            Ok(chunk.to_string())
+        });
+        let output: Vec<_> = StopStrings::wrap(text, &["STOP"])
+            .map(|chunk| chunk.unwrap())
+            .collect();
+        assert_eq!(output, ["a", "b"]);
+        assert_eq!(chunks_read, 3);
+    }
+
+    #[test]
+    fn test_error() {
+        let chunks = [
+            Ok("a".to_string()),
+            Err(GeneratorError::GenerateError("oh no".into())),
+            Ok("b".to_string()),
+        ];
+        let output: Vec<_> = StopStrings::wrap(chunks.into_iter(), &["x"])
+            .map(|chunk| chunk.map_err(|err| err.to_string()))
+            .collect();
+        assert_eq!(
+            output,
+            [
+                Ok("a".to_string()),
+                Err("generation error: oh no".to_string()),
+                Ok("b".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,95 @@
+//! Small serialized models for use in tests of crates which wrap this one,
+//! such as the language bindings.
+
+use rten::model_builder::{ModelBuilder, ModelFormat, OpType};
+use rten::ops::Gather;
+use rten::Dimension;
+use rten_tensor::prelude::*;
+use rten_tensor::Tensor;
+
+/// Return a serialized model which computes `output = Relu(input)`.
+pub fn relu_model() -> Vec<u8> {
+    let mut builder = ModelBuilder::new(ModelFormat::V2);
+    let mut graph_builder = builder.graph_builder();
+
+    let input = graph_builder.add_value("input", None);
+    let output = graph_builder.add_value("output", None);
+    graph_builder.add_input(input);
+    graph_builder.add_output(output);
+    graph_builder.add_operator("relu", OpType::Relu, &[Some(input)], &[output]);
+
+    let graph = graph_builder.finish();
+    builder.set_graph(graph);
+    builder.finish()
+}
+
+/// Return a serialized decoder model with an `input_ids` input and `logits`
+/// output, which predicts that token `i` is followed by token `i + 1`.
+///
+/// The model has no KV cache, so it is run on the whole sequence at each
+/// step.
+pub fn counting_model(n_vocab: usize) -> Vec<u8> {
+    let mut builder = ModelBuilder::new(ModelFormat::V2);
+    let mut graph_builder = builder.graph_builder();
+
+    let input_ids = graph_builder.add_value(
+        "input_ids",
+        Some(&[
+            Dimension::Symbolic("batch".to_string()),
+            Dimension::Symbolic("seq".to_string()),
+        ]),
+    );
+    let logits = graph_builder.add_value("logits", None);
+    graph_builder.add_input(input_ids);
+    graph_builder.add_output(logits);
+
+    // Look up the logits for each token in a table, whose rows are one-hot
+    // vectors for the next token ID.
+    let table = Tensor::from_fn(&[n_vocab, n_vocab], |idx| {
+        (idx[1] == idx[0] + 1) as i32 as f32
+    });
+    let table = graph_builder.add_constant(table.view());
+    graph_builder.add_operator(
+        "gather",
+        OpType::Gather(Gather { axis: 0 }),
+        &[Some(table), Some(input_ids)],
+        &[logits],
+    );
+
+    let graph = graph_builder.finish();
+    builder.set_graph(graph);
+    builder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use rten_tensor::prelude::*;
+    use rten_tensor::NdTensor;
+
+    use super::{counting_model, relu_model};
+
+    #[test]
+    fn test_relu_model() {
+        let model = rten::Model::load(relu_model()).unwrap();
+        let input = NdTensor::from([1., -2.]);
+        let output = model
+            .run_one(input.view().into(), None)
+            .unwrap()
+            .into_float()
+            .unwrap();
+        assert_eq!(output.to_vec(), [1., 0.]);
+    }
+
+    #[test]
+    fn test_counting_model() {
+        let model = rten::Model::load(counting_model(4)).unwrap();
+        let input_ids = NdTensor::from([[0, 2]]);
+        let logits = model
+            .run_one(input_ids.view().into(), None)
+            .unwrap()
+            .into_float()
+            .unwrap();
+        assert_eq!(logits.shape(), [1, 2, 4]);
+        assert_eq!(logits.to_vec(), [0., 1., 0., 0., 0., 0., 0., 1.]);
+    }
+}
@@ -0,0 +1,180 @@
+//! Fake models for use in tests.
+
+use std::cell::Cell;
+use std::error::Error;
+
+use rten::{InputOrOutput, NodeId, Output};
+use rten_tensor::prelude::*;
+use rten_tensor::Tensor;
+
+use crate::model::{Model, NodeInfo};
+
+/// Model inputs passed to the function of a [`FnModel`].
+pub struct FnModelInputs<'a> {
+    model: &'a dyn Model,
+    inputs: Vec<(NodeId, Output)>,
+}
+
+impl FnModelInputs<'_> {
+    /// Return the input with a given name, if it was passed to the model.
+    pub fn get(&self, name: &str) -> Option<Output> {
+        let id = self.model.find_node(name)?;
+        self.inputs
+            .iter()
+            .find(|(input_id, _)| *input_id == id)
+            .map(|(_, input)| input.clone())
+    }
+
+    /// Return the int tensor input with a given name.
+    ///
+    /// Panics if the input is missing or has a different type.
+    pub fn int(&self, name: &str) -> Tensor<i32> {
+        self.get(name)
+            .and_then(|input| input.into_int())
+            .unwrap_or_else(|| panic!("missing int input {}", name))
+    }
+
+    /// Return the float tensor input with a given name.
+    ///
+    /// Panics if the input is missing or has a different type.
+    pub fn float(&self, name: &str) -> Tensor<f32> {
+        self.get(name)
+            .and_then(|input| input.into_float())
+            .unwrap_or_else(|| panic!("missing float input {}", name))
+    }
+}
+
+/// Fake model with named inputs and outputs, whose outputs are computed from
+/// its inputs by a function.
+///
+/// The function returns the values of all outputs, in the order they were
+/// specified when the model was created.
+pub struct FnModel<F> {
+    nodes: Vec<NodeInfo>,
+    input_ids: Vec<NodeId>,
+    compute: F,
+}
+
+impl<F: Fn(&FnModelInputs) -> Vec<Output>> FnModel<F> {
+    pub fn new(inputs: &[&str], outputs: &[&str], compute: F) -> FnModel<F> {
+        FnModel {
+            nodes: inputs
+                .iter()
+                .chain(outputs)
+                .map(|name| NodeInfo::from_name_shape(name, &[]))
+                .collect(),
+            input_ids: (0..inputs.len()).collect(),
+            compute,
+        }
+    }
+}
+
+impl<F: Fn(&FnModelInputs) -> Vec<Output>> Model for FnModel<F> {
+    fn find_node(&self, name: &str) -> Option<NodeId> {
+        self.nodes.iter().position(|info| info.name() == name)
+    }
+
+    fn node_info(&self, id: NodeId) -> Option<NodeInfo> {
+        self.nodes.get(id).cloned()
+    }
+
+    fn input_ids(&self) -> &[NodeId] {
+        &self.input_ids
+    }
+
+    fn run(
+        &self,
+        inputs: Vec<(NodeId, InputOrOutput)>,
+        outputs: &[NodeId],
+    ) -> Result<Vec<Output>, Box<dyn Error>> {
+        if let Some((input_id, _)) = inputs.iter().find(|(id, _)| !self.input_ids.contains(id)) {
+            return Err(format!("invalid input ID {}", input_id).into());
+        }
+
+        let inputs = FnModelInputs {
+            model: self,
+            inputs: inputs
+                .into_iter()
+                .map(|(id, input)| (id, input.to_output()))
+                .collect(),
+        };
+        let values = (self.compute)(&inputs);
+        outputs
+            .iter()
+            .map(|&id| {
+                id.checked_sub(self.input_ids.len())
+                    .and_then(|idx| values.get(idx).cloned())
+                    .ok_or_else(|| format!("invalid output ID {}", id).into())
+            })
+            .collect()
+    }
+
+    /// Return the inputs unchanged, as there are no other nodes which can be
+    /// computed from them.
+    fn partial_run(
+        &self,
+        inputs: Vec<(NodeId, InputOrOutput)>,
+        _outputs: &[NodeId],
+    ) -> Result<Vec<(NodeId, Output)>, Box<dyn Error>> {
+        Ok(inputs
+            .into_iter()
+            .map(|(id, input)| (id, input.to_output()))
+            .collect())
+    }
+}
+
+/// Wrapper around a model which records the largest batch size it is run
+/// with, as given by the first dimension of the `input_ids` input.
+pub struct BatchRecordingModel<M> {
+    model: M,
+    max_batch: Cell<usize>,
+}
+
+impl<M: Model> BatchRecordingModel<M> {
+    pub fn new(model: M) -> BatchRecordingModel<M> {
+        BatchRecordingModel {
+            model,
+            max_batch: Cell::new(0),
+        }
+    }
+
+    /// Return the largest batch size seen by the model so far.
+    pub fn max_batch(&self) -> usize {
+        self.max_batch.get()
+    }
+}
+
+impl<M: Model> Model for BatchRecordingModel<M> {
+    fn find_node(&self, name: &str) -> Option<NodeId> {
+        self.model.find_node(name)
+    }
+
+    fn node_info(&self, id: NodeId) -> Option<NodeInfo> {
+        self.model.node_info(id)
+    }
+
+    fn input_ids(&self) -> &[NodeId] {
+        self.model.input_ids()
+    }
+
+    fn run(
+        &self,
+        inputs: Vec<(NodeId, InputOrOutput)>,
+        outputs: &[NodeId],
+    ) -> Result<Vec<Output>, Box<dyn Error>> {
+        let input_ids = self.model.find_node("input_ids");
+        if let Some((_, input)) = inputs.iter().find(|(id, _)| Some(*id) == input_ids) {
+            let batch = input.size(0);
+            self.max_batch.set(self.max_batch.get().max(batch));
+        }
+        self.model.run(inputs, outputs)
+    }
+
+    fn partial_run(
+        &self,
+        inputs: Vec<(NodeId, InputOrOutput)>,
+        outputs: &[NodeId],
+    ) -> Result<Vec<(NodeId, Output)>, Box<dyn Error>> {
+        self.model.partial_run(inputs, outputs)
+    }
+}
@@ -1,17 +1,21 @@
 //! Iterator adapters to decode token IDs into text using `rten-text`.
 
-use rten_text::tokenizers::{Tokenizer, TokenizerError};
+use rten_text::tokenizers::{DecodeStream, Tokenizer};
 
 use crate::generator::{GeneratorError, GeneratorItem};
 
 /// Wraps a [`Generator`](crate::Generator) to decode the output token IDs from
 /// the model into text using a [`Tokenizer`].
 ///
+/// Tokens are decoded using the tokenizer's decoder, so the concatenation of
+/// the returned strings matches [`Tokenizer::decode`] of the whole sequence.
+///
 /// This is normally created by calling [`decode`](crate::GeneratorUtils::decode)
 /// on a `Generator`.
 pub struct TextDecoder<'a, G: Iterator<Item = GeneratorItem>> {
     generator: G,
     tokenizer: &'a Tokenizer,
+    stream: DecodeStream<'a>,
 }
 
 impl<'a, G> TextDecoder<'a, G>
@@ -23,6 +27,7 @@ where
         TextDecoder {
             generator,
             tokenizer,
+            stream: tokenizer.decode_stream(),
         }
     }
 }
@@ -38,27 +43,21 @@ impl<'a, G: Iterator<Item = GeneratorItem>> Iterator for TextDecoder<'a, G> {
     /// occurs during generation or `None` if the end of output has been
     /// reached.
     fn next(&mut self) -> Option<Self::Item> {
-        // Buffer that holds model output tokens until it forms a valid UTF-8
-        // sequence.
-        let mut token_buf = Vec::new();
-
         for token in self.generator.by_ref() {
             let token = match token {
                 Ok(tok) => tok,
                 Err(err) => return Some(Err(err)),
             };
 
-            token_buf.push(token);
-
-            let text = self.tokenizer.encoder().decode(&token_buf);
-            match text {
-                Ok(text) => return Some(Ok(text)),
-                Err(TokenizerError::InvalidUtf8) => {
-                    // If the current token sequence doesn't correspond to a
-                    // complete UTF-8 sequence, add more tokens until it does.
-                    continue;
-                }
+            // If the tokens so far don't produce any new text, for example
+            // because they form an incomplete UTF-8 sequence, add more tokens
+            // until they do.
+            match self.stream.step(token) {
+                Ok(Some(text)) => return Some(Ok(text)),
+                Ok(None) => continue,
                 Err(err) => {
+                    // Discard the tokens which could not be decoded.
+                    self.stream = self.tokenizer.decode_stream();
                     return Some(Err(GeneratorError::DecodeError(err)));
                 }
             }
@@ -103,7 +102,36 @@ mod tests {
             .decode(&tokenizer)
             .map(|tok| tok.map_err(|e| e.to_string()))
             .collect();
-        assert_eq!(tokens, ["one", "two", "three"].map(|s| Ok(s.to_string())));
+        // The WordPiece decoder separates words with spaces.
+        assert_eq!(tokens, ["one", " two", " three"].map(|s| Ok(s.to_string())));
+    }
+
+    #[test]
+    fn test_decode_uses_decoder() {
+        // Tokenizer whose decoder converts byte tokens and `▁` markers back
+        // to the original text.
+        let json = r#"{
+            "model": {
+                "type": "WordLevel",
+                "vocab": { "▁hello": 0, "▁world": 1, "<0x21>": 2 }
+            },
+            "decoder": {
+                "type": "Sequence",
+                "decoders": [
+                    { "type": "ByteFallback" },
+                    { "type": "Metaspace", "replacement": "▁", "prepend_scheme": "always" }
+                ]
+            }
+        }"#;
+        let tokenizer = Tokenizer::from_json(json).unwrap();
+        let generator = [0, 1, 2].into_iter().map(Ok);
+
+        let tokens: Vec<_> = generator
+            .decode(&tokenizer)
+            .map(|tok| tok.map_err(|e| e.to_string()))
+            .collect();
+
+        assert_eq!(tokens, ["hello", " world", "!"].map(|s| Ok(s.to_string())));
     }
 
     #[test]
@@ -145,7 +173,7 @@ mod tests {
             [
                 Ok("one".to_string()),
                 Err("generation error: oh no".to_string()),
-                Ok("three".to_string())
+                Ok(" three".to_string())
             ]
         );
     }
@@ -0,0 +1,414 @@
+//! Iterator adapter which detects tool calls in generated text.
+
+use std::collections::VecDeque;
+
+use crate::generator::GeneratorError;
+
+/// Specifies how tool calls are delimited in a model's output.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ToolCallFormat {
+    /// Tool calls are enclosed by start and end markers, such as
+    /// `<tool_call>` and `</tool_call>`.
+    Delimited { start: String, end: String },
+
+    /// Tool calls are JSON objects. Any `{` outside of a tool call starts a
+    /// new call, which ends at the matching `}`.
+    ///
+    /// This is suitable for models which respond with only a JSON object
+    /// when they call a tool.
+    JsonObject,
+}
+
+impl ToolCallFormat {
+    /// Create a format where tool calls are enclosed by `start` and `end`.
+    pub fn delimited(start: &str, end: &str) -> ToolCallFormat {
+        ToolCallFormat::Delimited {
+            start: start.to_string(),
+            end: end.to_string(),
+        }
+    }
+}
+
+impl Default for ToolCallFormat {
+    /// Return the `<tool_call>...</tool_call>` format used by Hermes, Qwen
+    /// and other models.
+    fn default() -> Self {
+        Self::delimited("<tool_call>", "</tool_call>")
+    }
+}
+
+/// A tool call found in generated text.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ToolCall {
+    /// Text of the tool call, excluding delimiters and surrounding
+    /// whitespace.
+    pub content: String,
+}
+
+impl ToolCall {
+    /// Return the name of the called function, if the content is a JSON
+    /// object with a `name` field.
+    pub fn name(&self) -> Option<String> {
+        let value: serde_json::Value = serde_json::from_str(&self.content).ok()?;
+        value.get("name")?.as_str().map(|name| name.to_string())
+    }
+
+    /// Return the arguments of the call as a JSON string, if the content is a
+    /// JSON object with an `arguments` or `parameters` field.
+    pub fn arguments(&self) -> Option<String> {
+        let value: serde_json::Value = serde_json::from_str(&self.content).ok()?;
+        value
+            .get("arguments")
+            .or_else(|| value.get("parameters"))
+            .map(|args| args.to_string())
+    }
+}
+
+/// Item produced by [`ToolCallStream`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum ToolStreamEvent {
+    /// Text which is not part of a tool call.
+    Text(String),
+
+    /// A complete tool call.
+    ToolCall(ToolCall),
+}
+
+/// Parser state for [`ToolCallStream`].
+enum State {
+    /// Outside of a tool call.
+    Text,
+
+    /// Inside a delimited tool call.
+    Delimited,
+
+    /// Inside a JSON tool call.
+    Json {
+        depth: usize,
+        in_string: bool,
+        escaped: bool,
+    },
+}
+
+/// Wraps a stream of decoded text to separate tool calls from other text.
+///
+/// Text is buffered while it may be the start of a tool call, and the content
+/// of each tool call is buffered until the call is complete. Other text is
+/// yielded as soon as possible.
+///
+/// This is normally created by calling
+/// [`decode_tool_calls`](crate::GeneratorUtils::decode_tool_calls) on a
+/// `Generator`.
+pub struct ToolCallStream<I: Iterator<Item = Result<String, GeneratorError>>> {
+    text: I,
+    format: ToolCallFormat,
+    state: State,
+    buf: String,
+
+    /// Number of bytes at the start of `buf` which have already been
+    /// processed by the JSON parser.
+    scanned: usize,
+
+    events: VecDeque<ToolStreamEvent>,
+}
+
+impl<I: Iterator<Item = Result<String, GeneratorError>>> ToolCallStream<I> {
+    /// Wrap a stream of decoded text.
+    pub fn wrap(text: I, format: ToolCallFormat) -> ToolCallStream<I> {
+        ToolCallStream {
+            text,
+            format,
+            state: State::Text,
+            buf: String::new(),
+            scanned: 0,
+            events: VecDeque::new(),
+        }
+    }
+
+    fn push_text(&mut self, text: String) {
+        if text.is_empty() {
+            return;
+        }
+        // Merge adjacent text chunks, so that text which was held back while
+        // checking for a start delimiter is not split unnecessarily.
+        if let Some(ToolStreamEvent::Text(prev)) = self.events.back_mut() {
+            prev.push_str(&text);
+        } else {
+            self.events.push_back(ToolStreamEvent::Text(text));
+        }
+    }
+
+    fn push_call(&mut self, content: &str) {
+        self.events.push_back(ToolStreamEvent::ToolCall(ToolCall {
+            content: content.trim().to_string(),
+        }));
+    }
+
+    /// Process buffered text and add any completed events to the queue.
+    fn process(&mut self) {
+        match self.format.clone() {
+            ToolCallFormat::Delimited { start, end } => self.process_delimited(&start, &end),
+            ToolCallFormat::JsonObject => self.process_json(),
+        }
+    }
+
+    fn process_delimited(&mut self, start: &str, end: &str) {
+        loop {
+            match self.state {
+                State::Text => {
+                    if let Some(pos) = self.buf.find(start) {
+                        let text = self.buf[..pos].to_string();
+                        self.push_text(text);
+                        self.buf.drain(..pos + start.len());
+                        self.state = State::Delimited;
+                    } else {
+                        // Hold back any suffix which may be the start of a
+                        // delimiter that is completed by later text.
+                        let keep = partial_match_len(&self.buf, start);
+                        let text: String = self.buf.drain(..self.buf.len() - keep).collect();
+                        self.push_text(text);
+                        return;
+                    }
+                }
+                State::Delimited => {
+                    let Some(pos) = self.buf.find(end) else {
+                        return;
+                    };
+                    let content = self.buf[..pos].to_string();
+                    self.push_call(&content);
+                    self.buf.drain(..pos + end.len());
+                    self.state = State::Text;
+                }
+                State::Json { .. } => unreachable!(),
+            }
+        }
+    }
+
+    fn process_json(&mut self) {
+        let buf = std::mem::take(&mut self.buf);
+        let scanned = self.scanned;
+        let mut consumed = 0;
+        for (pos, ch) in buf.char_indices().skip_while(|(pos, _)| *pos < scanned) {
+            match &mut self.state {
+                State::Text => {
+                    if ch == '{' {
+                        self.push_text(buf[consumed..pos].to_string());
+                        consumed = pos;
+                        self.state = State::Json {
+                            depth: 1,
+                            in_string: false,
+                            escaped: false,
+                        };
+                    }
+                }
+                State::Json {
+                    depth,
+                    in_string,
+                    escaped,
+                } => {
+                    if *escaped {
+                        *escaped = false;
+                    } else if *in_string {
+                        match ch {
+                            '\\' => *escaped = true,
+                            '"' => *in_string = false,
+                            _ => {}
+                        }
+                    } else {
+                        match ch {
+                            '"' => *in_string = true,
+                            '{' => *depth += 1,
+                            '}' => *depth -= 1,
+                            _ => {}
+                        }
+                    }
+                    if *depth == 0 {
+                        let end = pos + ch.len_utf8();
+                        self.push_call(&buf[consumed..end]);
+                        consumed = end;
+                        self.state = State::Text;
+                    }
+                }
+                State::Delimited => unreachable!(),
+            }
+        }
+
+        // Text outside of tool calls can be emitted immediately. The content
+        // of incomplete tool calls is kept until the call ends.
+        if let State::Text = self.state {
+            self.push_text(buf[consumed..].to_string());
+            consumed = buf.len();
+        }
+        self.buf = buf[consumed..].to_string();
+        self.scanned = self.buf.len();
+    }
+
+    /// Flush buffered text at the end of the stream.
+    ///
+    /// Incomplete tool calls are emitted as text, including the start
+    /// delimiter, so that no output is lost.
+    fn finish(&mut self) {
+        let mut text = std::mem::take(&mut self.buf);
+        if let (State::Delimited, ToolCallFormat::Delimited { start, .. }) =
+            (&self.state, &self.format)
+        {
+            text.insert_str(0, start);
+        }
+        self.state = State::Text;
+        self.push_text(text);
+    }
+}
+
+impl<I: Iterator<Item = Result<String, GeneratorError>>> Iterator for ToolCallStream<I> {
+    /// The next text chunk or tool call, or the error that occurred during
+    /// generation or decoding.
+    type Item = Result<ToolStreamEvent, GeneratorError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Some(Ok(event));
+            }
+            match self.text.next() {
+                Some(Ok(text)) => {
+                    self.buf.push_str(&text);
+                    self.process();
+                }
+                Some(Err(err)) => return Some(Err(err)),
+                None => {
+                    self.finish();
+                    return self.events.pop_front().map(Ok);
+                }
+            }
+        }
+    }
+}
+
+/// Return the length of the longest suffix of `text` which is a proper
+/// prefix of `pattern`.
+pub(crate) fn partial_match_len(text: &str, pattern: &str) -> usize {
+    (1..pattern.len())
+        .rev()
+        .filter(|&len| pattern.is_char_boundary(len))
+        .find(|&len| text.ends_with(&pattern[..len]))
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ToolCall, ToolCallFormat, ToolCallStream, ToolStreamEvent};
+    use crate::generator::GeneratorError;
+
+    fn parse(chunks: &[&str], format: ToolCallFormat) -> Vec<ToolStreamEvent> {
+        let text = chunks.iter().map(|chunk| Ok(chunk.to_string()));
+        ToolCallStream::wrap(text, format)
+            .map(|event| event.unwrap())
+            .collect()
+    }
+
+    fn text(s: &str) -> ToolStreamEvent {
+        ToolStreamEvent::Text(s.to_string())
+    }
+
+    fn call(s: &str) -> ToolStreamEvent {
+        ToolStreamEvent::ToolCall(ToolCall {
+            content: s.to_string(),
+        })
+    }
+
+    #[test]
+    fn test_delimited() {
+        // Delimiters split across chunks.
+        let events = parse(
+            &[
+                "Let me check.",
+                "<tool",
+                "_call>\n{\"name\": ",
+                "\"weather\"}\n</tool_",
+                "call>Done <",
+                "b>",
+            ],
+            ToolCallFormat::default(),
+        );
+        assert_eq!(
+            events,
+            [
+                text("Let me check."),
+                call("{\"name\": \"weather\"}"),
+                text("Done "),
+                text("<b>"),
+            ]
+        );
+
+        // Text which is held back while checking for a delimiter is emitted
+        // at the end of the stream.
+        let events = parse(&["a <tool"], ToolCallFormat::default());
+        assert_eq!(events, [text("a "), text("<tool")]);
+
+        // Incomplete tool calls are emitted as text.
+        let events = parse(&["<tool_call>{\"na"], ToolCallFormat::default());
+        assert_eq!(events, [text("<tool_call>{\"na")]);
+
+        // Multiple calls in one chunk.
+        let events = parse(&["[A][B]"], ToolCallFormat::delimited("[", "]"));
+        assert_eq!(events, [call("A"), call("B")]);
+    }
+
+    #[test]
+    fn test_json_object() {
+        let events = parse(
+            &[
+                "Calling ",
+                "{\"name\": \"f\", \"arguments\": {\"s\": \"}{\\\"\"}",
+                "} ok",
+            ],
+            ToolCallFormat::JsonObject,
+        );
+        assert_eq!(
+            events,
+            [
+                text("Calling "),
+                call("{\"name\": \"f\", \"arguments\": {\"s\": \"}{\\\"\"}}"),
+                text(" ok"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tool_call_fields() {
+        let tool_call = ToolCall {
+            content: r#"{"name": "get_weather", "arguments": {"city": "Paris"}}"#.to_string(),
+        };
+        assert_eq!(tool_call.name().as_deref(), Some("get_weather"));
+        assert_eq!(
+            tool_call.arguments().as_deref(),
+            Some(r#"{"city":"Paris"}"#)
+        );
+
+        let tool_call = ToolCall {
+            content: "not json".to_string(),
+        };
+        assert_eq!(tool_call.name(), None);
+        assert_eq!(tool_call.arguments(), None);
+    }
+
+    #[test]
+    fn test_error() {
+        let chunks = [
+            Ok("a".to_string()),
+            Err(GeneratorError::GenerateError("oh no".into())),
+            Ok("b".to_string()),
+        ];
+        let events: Vec<_> = ToolCallStream::wrap(chunks.into_iter(), ToolCallFormat::default())
+            .map(|event| event.map_err(|err| err.to_string()))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                Ok(text("a")),
+                Err("generation error: oh no".to_string()),
+                Ok(text("b")),
+            ]
+        );
+    }
+}
@@ -0,0 +1,260 @@
+//! Decoding utilities for Whisper speech recognition models.
+//!
+//! Whisper's decoder is controlled using special tokens. Its output starts
+//! with tokens which specify the language and task, which can be forced using
+//! [`ForceTokens`](crate::logits::ForceTokens), and unwanted tokens are
+//! suppressed using [`SuppressTokens`](crate::logits::SuppressTokens) and
+//! [`SuppressTokensAtBegin`](crate::logits::SuppressTokensAtBegin) with the
+//! `suppress_tokens` and `begin_suppress_tokens` lists from the model's
+//! `generation_config.json` file. When timestamps are enabled,
+//! [`TimestampRules`] constrains where timestamp tokens can appear.
+
+#[cfg(feature = "text-decoder")]
+use rten_text::tokenizers::{Tokenizer, TokenizerError};
+
+use rten_tensor::prelude::*;
+use rten_tensor::NdTensorViewMut;
+
+use crate::generator::TokenId;
+use crate::logits::{suppress, LogitsProcessor};
+
+/// A [`LogitsProcessor`] which constrains the generation of timestamp tokens
+/// by Whisper models.
+///
+/// Whisper represents timestamps as tokens with IDs starting from the
+/// `<|0.00|>` token, in increments of 0.02 seconds. When timestamps are
+/// enabled, each segment of text in the output is preceded by a start
+/// timestamp and followed by an end timestamp. This processor implements the
+/// rules from the reference implementation, which ensure that:
+///
+/// - The `<|notimestamps|>` token is not generated.
+/// - The output starts with a timestamp, which is no later than the
+///   maximum initial timestamp, if set.
+/// - Timestamps appear in pairs, except for the start of the first segment
+///   and the end of the last segment.
+/// - Timestamps do not decrease, and each segment's end timestamp is later
+///   than its start.
+/// - A timestamp is generated if the total probability of all timestamps is
+///   greater than the probability of any other token.
+#[derive(Clone, Debug)]
+pub struct TimestampRules {
+    timestamp_begin: TokenId,
+    end_of_text: TokenId,
+    no_timestamps: TokenId,
+    max_initial_timestamp: Option<u32>,
+    begin_index: usize,
+}
+
+impl TimestampRules {
+    /// Create a processor given the IDs of the `<|0.00|>`, `<|endoftext|>`
+    /// and `<|notimestamps|>` tokens.
+    pub fn new(
+        timestamp_begin: TokenId,
+        end_of_text: TokenId,
+        no_timestamps: TokenId,
+    ) -> TimestampRules {
+        TimestampRules {
+            timestamp_begin,
+            end_of_text,
+            no_timestamps,
+            max_initial_timestamp: None,
+            begin_index: 0,
+        }
+    }
+
+    /// Create a processor using token IDs from a Whisper tokenizer.
+    #[cfg(feature = "text-decoder")]
+    pub fn from_tokenizer(tokenizer: &Tokenizer) -> Result<TimestampRules, TokenizerError> {
+        let encoder = tokenizer.encoder();
+        Ok(Self::new(
+            encoder.get_token_id("<|0.00|>")?,
+            encoder.get_token_id("<|endoftext|>")?,
+            encoder.get_token_id("<|notimestamps|>")?,
+        ))
+    }
+
+    /// Limit the first timestamp to at most `max_initial_timestamp` steps of
+    /// 0.02 seconds. The reference implementation uses a limit of 1 second,
+    /// or 50 steps.
+    pub fn with_max_initial_timestamp(mut self, max_initial_timestamp: u32) -> Self {
+        self.max_initial_timestamp = Some(max_initial_timestamp);
+        self
+    }
+
+    /// Ignore the first `begin_index` tokens of the output.
+    ///
+    /// This is used when the output starts with language and task tokens
+    /// that are forced using [`ForceTokens`](crate::logits::ForceTokens)
+    /// rather than included in the prompt. The rules are applied to the
+    /// output that follows these tokens.
+    pub fn with_begin_index(mut self, begin_index: usize) -> Self {
+        self.begin_index = begin_index;
+        self
+    }
+
+    fn is_timestamp(&self, id: TokenId) -> bool {
+        id >= self.timestamp_begin
+    }
+}
+
+impl LogitsProcessor for TimestampRules {
+    fn process(&self, output_ids: &[TokenId], mut logits: NdTensorViewMut<f32, 1>) {
+        let Some(output_ids) = output_ids.get(self.begin_index..) else {
+            return;
+        };
+        let n_vocab = logits.size(0);
+        let timestamp_begin = self.timestamp_begin as usize;
+        let timestamps = timestamp_begin..n_vocab;
+
+        suppress(&mut logits, [self.no_timestamps as usize]);
+
+        // Timestamps must appear in pairs, except directly at the start.
+        let last_was_timestamp = output_ids.last().is_some_and(|&id| self.is_timestamp(id));
+        let penultimate_was_timestamp =
+            output_ids.len() < 2 || self.is_timestamp(output_ids[output_ids.len() - 2]);
+        if last_was_timestamp {
+            if penultimate_was_timestamp {
+                // Start of a segment. The next token must be text.
+                suppress(&mut logits, timestamps.clone());
+            } else {
+                // End of a segment. The next token must be the start of the
+                // next segment or the end of the output.
+                suppress(&mut logits, 0..self.end_of_text as usize);
+            }
+        }
+
+        // Timestamps must not decrease. A segment's end timestamp can be
+        // the same as its start.
+        if let Some(&last_timestamp) = output_ids.iter().rev().find(|&&id| self.is_timestamp(id)) {
+            let min_timestamp = if last_was_timestamp && !penultimate_was_timestamp {
+                last_timestamp
+            } else {
+                last_timestamp + 1
+            };
+            suppress(&mut logits, timestamp_begin..min_timestamp as usize);
+        }
+
+        // The output must start with a timestamp.
+        if output_ids.is_empty() {
+            suppress(&mut logits, 0..timestamp_begin);
+            if let Some(max) = self.max_initial_timestamp {
+                suppress(&mut logits, timestamp_begin + max as usize + 1..n_vocab);
+            }
+        }
+
+        // Generate a timestamp if the total probability of timestamps is
+        // greater than that of any other token. Both sides are compared as
+        // unnormalized log-probabilities.
+        let max_logit = |ids: std::ops::Range<usize>| {
+            ids.filter_map(|id| logits.get([id]).copied())
+                .fold(f32::NEG_INFINITY, f32::max)
+        };
+        let max_timestamp = max_logit(timestamps.clone());
+        let max_text = max_logit(0..timestamp_begin);
+        if max_timestamp == f32::NEG_INFINITY {
+            return;
+        }
+        let timestamp_sum_exp: f32 = timestamps
+            .clone()
+            .filter_map(|id| logits.get([id]))
+            .map(|&x| (x - max_timestamp).exp())
+            .sum();
+        let timestamp_logsumexp = max_timestamp + timestamp_sum_exp.ln();
+        if timestamp_logsumexp > max_text {
+            suppress(&mut logits, 0..timestamp_begin);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rten_tensor::prelude::*;
+    use rten_tensor::NdTensor;
+
+    use super::TimestampRules;
+    use crate::generator::TokenId;
+    use crate::logits::LogitsProcessor;
+
+    // Test vocabulary with text tokens 0-2, end-of-text 3, no-timestamps 4
+    // and timestamps 5-9.
+    const EOT: TokenId = 3;
+    const NO_TIMESTAMPS: TokenId = 4;
+    const TIMESTAMP_BEGIN: TokenId = 5;
+    const N_VOCAB: usize = 10;
+
+    /// Return the IDs of tokens which are not suppressed.
+    fn allowed(rules: &TimestampRules, output_ids: &[TokenId], logits: &[f32]) -> Vec<TokenId> {
+        let mut logits = NdTensor::from_data([N_VOCAB], logits.to_vec());
+        rules.process(output_ids, logits.view_mut());
+        logits
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| **x != f32::NEG_INFINITY)
+            .map(|(id, _)| id as TokenId)
+            .collect()
+    }
+
+    #[test]
+    fn test_timestamp_rules() {
+        let rules = TimestampRules::new(TIMESTAMP_BEGIN, EOT, NO_TIMESTAMPS);
+
+        // Text tokens are much more likely than timestamps, so that only the
+        // rules below determine whether timestamps are allowed.
+        let mut logits = [0.; N_VOCAB];
+        logits[..TIMESTAMP_BEGIN as usize].fill(10.);
+
+        // The output must start with a timestamp.
+        assert_eq!(allowed(&rules, &[], &logits), [5, 6, 7, 8, 9]);
+        let limited = rules.clone().with_max_initial_timestamp(1);
+        assert_eq!(allowed(&limited, &[], &logits), [5, 6]);
+
+        // After a start timestamp, text must follow.
+        assert_eq!(allowed(&rules, &[6], &logits), [0, 1, 2, 3]);
+
+        // After text, any token except timestamps which are not later than
+        // the start timestamp is allowed, so that segments are not empty.
+        assert_eq!(allowed(&rules, &[6, 1], &logits), [0, 1, 2, 3, 7, 8, 9]);
+
+        // After an end timestamp, the start timestamp of the next segment or
+        // end-of-text must follow.
+        assert_eq!(allowed(&rules, &[6, 1, 7], &logits), [3, 7, 8, 9]);
+
+        // After the start timestamp of the next segment, text must follow.
+        assert_eq!(allowed(&rules, &[6, 1, 7, 7], &logits), [0, 1, 2, 3]);
+
+        // After the text of the next segment, the end timestamp must be
+        // later than the start.
+        assert_eq!(allowed(&rules, &[6, 1, 7, 8, 2], &logits), [0, 1, 2, 3, 9]);
+    }
+
+    #[test]
+    fn test_timestamp_rules_probability() {
+        let rules = TimestampRules::new(TIMESTAMP_BEGIN, EOT, NO_TIMESTAMPS);
+
+        // Each timestamp is less likely than the most likely text token, but
+        // their total probability is greater, so a timestamp is forced.
+        let mut logits = [0.; N_VOCAB];
+        logits[0] = 1.;
+        assert_eq!(allowed(&rules, &[6, 1], &logits), [7, 8, 9]);
+
+        // If the text token is more likely than all timestamps combined,
+        // text is allowed.
+        logits[0] = 3.;
+        assert_eq!(allowed(&rules, &[6, 1], &logits), [0, 1, 2, 3, 7, 8, 9]);
+    }
+
+    #[test]
+    fn test_timestamp_rules_begin_index() {
+        let rules = TimestampRules::new(TIMESTAMP_BEGIN, EOT, NO_TIMESTAMPS).with_begin_index(2);
+        let mut logits = [0.; N_VOCAB];
+        logits[..TIMESTAMP_BEGIN as usize].fill(10.);
+
+        // Tokens before the begin index are not constrained.
+        assert_eq!(allowed(&rules, &[], &logits).len(), N_VOCAB);
+        assert_eq!(allowed(&rules, &[1], &logits).len(), N_VOCAB);
+
+        // The output after the begin index must start with a timestamp.
+        assert_eq!(allowed(&rules, &[1, 2], &logits), [5, 6, 7, 8, 9]);
+        assert_eq!(allowed(&rules, &[1, 2, 6], &logits), [0, 1, 2, 3]);
+    }
+}
@@ -0,0 +1,445 @@
+//! Python bindings for RTen.
+//!
+//! This exposes model loading and execution, tokenization and the
+//! auto-regressive generation loop as a Python module named `rten`.
+//!
+//! The API mirrors the WebAssembly bindings in the rten-wasm crate. Tensors
+//! are exchanged as flat lists of values plus a shape, which can be converted
+//! to/from NumPy arrays using `np.array(t.float_data()).reshape(t.shape())`
+//! and `Tensor.float_tensor(a.shape, a.ravel())`.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use rten::{InputOrOutput, NodeId, Output};
+use rten_generate::Generator as GeneratorImpl;
+use rten_tensor::prelude::*;
+use rten_text::tokenizers::{EncodeOptions, Tokenizer as TokenizerImpl};
+
+/// Convert an error from an RTen crate into a Python `ValueError`.
+fn to_py_err<E: std::fmt::Display>(err: E) -> PyErr {
+    PyValueError::new_err(err.to_string())
+}
+
+/// A multi-dimensional array used as a model input or output.
+#[pyclass(frozen, module = "rten")]
+#[derive(Clone)]
+pub struct Tensor {
+    data: Arc<Output>,
+}
+
+impl Tensor {
+    fn from_output(output: Output) -> Tensor {
+        Tensor {
+            data: Arc::new(output),
+        }
+    }
+
+    fn as_input(&self) -> InputOrOutput<'_> {
+        self.data.as_input().into()
+    }
+}
+
+#[pymethods]
+impl Tensor {
+    /// Construct a float tensor from a shape and elements in row-major order.
+    #[staticmethod]
+    fn float_tensor(shape: Vec<usize>, data: Vec<f32>) -> PyResult<Tensor> {
+        let tensor = rten_tensor::Tensor::try_from_data(&shape, data).map_err(to_py_err)?;
+        Ok(Tensor::from_output(tensor.into()))
+    }
+
+    /// Construct an int tensor from a shape and elements in row-major order.
+    #[staticmethod]
+    fn int_tensor(shape: Vec<usize>, data: Vec<i32>) -> PyResult<Tensor> {
+        let tensor = rten_tensor::Tensor::try_from_data(&shape, data).map_err(to_py_err)?;
+        Ok(Tensor::from_output(tensor.into()))
+    }
+
+    /// Return the shape of the tensor.
+    fn shape(&self) -> Vec<usize> {
+        self.data.shape().into()
+    }
+
+    /// Return the element type of the tensor, as a NumPy-style name.
+    fn dtype(&self) -> &'static str {
+        match *self.data {
+            Output::FloatTensor(_) => "float32",
+            Output::IntTensor(_) => "int32",
+        }
+    }
+
+    /// Return the elements of a float tensor in row-major order, or `None`
+    /// if this is not a float tensor.
+    fn float_data(&self) -> Option<Vec<f32>> {
+        match *self.data {
+            Output::FloatTensor(ref t) => Some(t.to_vec()),
+            _ => None,
+        }
+    }
+
+    /// Return the elements of an int tensor in row-major order, or `None`
+    /// if this is not an int tensor.
+    fn int_data(&self) -> Option<Vec<i32>> {
+        match *self.data {
+            Output::IntTensor(ref t) => Some(t.to_vec()),
+            _ => None,
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Tensor(shape={:?}, dtype={})", self.shape(), self.dtype())
+    }
+}
+
+/// A machine learning model loaded from a `.rten` file.
+#[pyclass(frozen, module = "rten")]
+pub struct Model {
+    model: Arc<rten::Model>,
+}
+
+impl Model {
+    fn node_id(&self, name: &str) -> PyResult<NodeId> {
+        self.model
+            .find_node(name)
+            .ok_or_else(|| PyValueError::new_err(format!("node not found: {}", name)))
+    }
+
+    fn node_names(&self, ids: &[NodeId]) -> Vec<String> {
+        ids.iter()
+            .map(|&id| {
+                self.model
+                    .node_info(id)
+                    .and_then(|info| info.name().map(|name| name.to_string()))
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
+}
+
+#[pymethods]
+impl Model {
+    /// Load a model from a file.
+    #[staticmethod]
+    fn load_file(path: &str) -> PyResult<Model> {
+        let model = rten::Model::load_file(path).map_err(to_py_err)?;
+        Ok(Model {
+            model: Arc::new(model),
+        })
+    }
+
+    /// Load a model from a serialized buffer.
+    #[staticmethod]
+    fn load(data: Vec<u8>) -> PyResult<Model> {
+        let model = rten::Model::load(data).map_err(to_py_err)?;
+        Ok(Model {
+            model: Arc::new(model),
+        })
+    }
+
+    /// Return the names of the model's inputs.
+    fn input_names(&self) -> Vec<String> {
+        self.node_names(self.model.input_ids())
+    }
+
+    /// Return the names of the model's outputs.
+    fn output_names(&self) -> Vec<String> {
+        self.node_names(self.model.output_ids())
+    }
+
+    /// Run the model.
+    ///
+    /// `inputs` maps input names to tensors. `outputs` specifies the names of
+    /// the outputs to compute, and defaults to all of the model's outputs.
+    /// Returns a dict mapping output names to tensors.
+    ///
+    /// The GIL is released while the model runs.
+    #[pyo3(signature = (inputs, outputs=None))]
+    fn run(
+        &self,
+        py: Python<'_>,
+        inputs: HashMap<String, Tensor>,
+        outputs: Option<Vec<String>>,
+    ) -> PyResult<HashMap<String, Tensor>> {
+        let output_names = outputs.unwrap_or_else(|| self.output_names());
+        let output_ids = output_names
+            .iter()
+            .map(|name| self.node_id(name))
+            .collect::<PyResult<Vec<_>>>()?;
+        let inputs = inputs
+            .iter()
+            .map(|(name, tensor)| Ok((self.node_id(name)?, tensor)))
+            .collect::<PyResult<Vec<_>>>()?;
+
+        let results = py
+            .allow_threads(|| {
+                let inputs = inputs
+                    .iter()
+                    .map(|(id, tensor)| (*id, tensor.as_input()))
+                    .collect();
+                self.model.run(inputs, &output_ids, None)
+            })
+            .map_err(to_py_err)?;
+
+        Ok(output_names
+            .into_iter()
+            .zip(results.into_iter().map(Tensor::from_output))
+            .collect())
+    }
+}
+
+/// Tokenizer which converts between text and token IDs.
+///
+/// Tokenizers can only be used from the thread which created them.
+#[pyclass(frozen, unsendable, module = "rten")]
+pub struct Tokenizer {
+    tokenizer: TokenizerImpl,
+}
+
+#[pymethods]
+impl Tokenizer {
+    /// Load a tokenizer from a `tokenizer.json` file in the Hugging Face
+    /// Tokenizers format.
+    #[staticmethod]
+    fn from_file(path: &str) -> PyResult<Tokenizer> {
+        let json = std::fs::read_to_string(path).map_err(to_py_err)?;
+        Tokenizer::from_json(&json)
+    }
+
+    /// Create a tokenizer from the contents of a `tokenizer.json` file.
+    #[staticmethod]
+    fn from_json(json: &str) -> PyResult<Tokenizer> {
+        let tokenizer = TokenizerImpl::from_json(json).map_err(to_py_err)?;
+        Ok(Tokenizer { tokenizer })
+    }
+
+    /// Encode text into a list of token IDs.
+    fn encode(&self, text: &str) -> PyResult<Vec<u32>> {
+        let encoded = self
+            .tokenizer
+            .encode(text.into(), EncodeOptions::default())
+            .map_err(to_py_err)?;
+        Ok(encoded.token_ids().to_vec())
+    }
+
+    /// Decode a list of token IDs into text.
+    fn decode(&self, ids: Vec<u32>) -> PyResult<String> {
+        self.tokenizer.decode(&ids).map_err(to_py_err)
+    }
+}
+
+/// Runs the generation loop for an auto-regressive transformer model.
+///
+/// The model is expected to follow the input and output naming conventions
+/// described in the `rten-generate` crate.
+#[pyclass(frozen, module = "rten")]
+pub struct Generator {
+    model: Arc<rten::Model>,
+    max_tokens: usize,
+    top_k: Option<usize>,
+    temperature: f32,
+    stop_tokens: Vec<u32>,
+}
+
+#[pymethods]
+impl Generator {
+    /// Create a generator for a model.
+    ///
+    /// At most `max_tokens` tokens are generated. If `top_k` is set, tokens
+    /// are sampled from the `top_k` most likely tokens after applying
+    /// `temperature`, otherwise the most likely token is chosen. Generation
+    /// stops early when a token in `stop_tokens` is generated.
+    #[new]
+    #[pyo3(signature = (model, max_tokens, top_k=None, temperature=1.0, stop_tokens=Vec::new()))]
+    fn new(
+        model: &Model,
+        max_tokens: usize,
+        top_k: Option<usize>,
+        temperature: f32,
+        stop_tokens: Vec<u32>,
+    ) -> Generator {
+        Generator {
+            model: model.model.clone(),
+            max_tokens,
+            top_k,
+            temperature,
+            stop_tokens,
+        }
+    }
+
+    /// Generate tokens following `prompt`, a list of token IDs.
+    ///
+    /// If `on_token` is given, it is called with each token ID as it is
+    /// generated, which can be used to stream output. Returns the list of
+    /// generated token IDs, excluding the prompt and any stop token.
+    ///
+    /// The GIL is released while the model runs, and re-acquired after each
+    /// step to call `on_token` and handle signals.
+    #[pyo3(signature = (prompt, on_token=None))]
+    fn generate(
+        &self,
+        py: Python<'_>,
+        prompt: Vec<u32>,
+        on_token: Option<Bound<'_, PyAny>>,
+    ) -> PyResult<Vec<u32>> {
+        if let Some(callback) = &on_token {
+            if !callback.is_callable() {
+                return Err(PyTypeError::new_err("`on_token` must be callable"));
+            }
+        }
+        let on_token = on_token.map(Bound::unbind);
+
+        py.allow_threads(|| {
+            let generator = GeneratorImpl::from_model(self.model.as_ref())
+                .map_err(to_py_err)?
+                .with_prompt(&prompt)
+                .sample_tokens(
+                    self.max_tokens,
+                    self.top_k,
+                    self.temperature,
+                    self.stop_tokens.clone(),
+                );
+
+            let mut tokens = Vec::new();
+            for token in generator {
+                let token = token.map_err(to_py_err)?;
+                tokens.push(token);
+                Python::with_gil(|py| {
+                    if let Some(callback) = &on_token {
+                        callback.call1(py, (token,))?;
+                    }
+                    // Allow Python to handle signals such as Ctrl+C between
+                    // steps.
+                    py.check_signals()
+                })?;
+            }
+            Ok(tokens)
+        })
+    }
+}
+
+/// Python module definition.
+#[pymodule]
+#[pyo3(name = "rten")]
+fn rten_python(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Tensor>()?;
+    m.add_class::<Model>()?;
+    m.add_class::<Tokenizer>()?;
+    m.add_class::<Generator>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use pyo3::exceptions::{PyTypeError, PyZeroDivisionError};
+    use pyo3::prelude::*;
+    use pyo3::types::PyList;
+    use rten_generate::test_models::{counting_model, relu_model};
+
+    use super::{Generator, Model, Tensor, Tokenizer};
+
+    #[test]
+    fn test_tensor() {
+        let tensor = Tensor::float_tensor(vec![2, 2], vec![1., 2., 3., 4.]).unwrap();
+        assert_eq!(tensor.shape(), [2, 2]);
+        assert_eq!(tensor.dtype(), "float32");
+        assert_eq!(tensor.float_data(), Some(vec![1., 2., 3., 4.]));
+        assert_eq!(tensor.int_data(), None);
+        assert_eq!(tensor.__repr__(), "Tensor(shape=[2, 2], dtype=float32)");
+
+        let tensor = Tensor::int_tensor(vec![3], vec![1, 2, 3]).unwrap();
+        assert_eq!(tensor.dtype(), "int32");
+        assert_eq!(tensor.int_data(), Some(vec![1, 2, 3]));
+
+        assert!(Tensor::float_tensor(vec![2, 2], vec![1., 2., 3.]).is_err());
+    }
+
+    #[test]
+    fn test_model_run() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let model = Model::load(relu_model()).unwrap();
+            assert_eq!(model.input_names(), ["input"]);
+            assert_eq!(model.output_names(), ["output"]);
+
+            let input = Tensor::float_tensor(vec![2, 2], vec![1., -2., 3., -4.]).unwrap();
+            let inputs = HashMap::from([("input".to_string(), input.clone())]);
+            let outputs = model.run(py, inputs, None).unwrap();
+            assert_eq!(outputs["output"].float_data(), Some(vec![1., 0., 3., 0.]));
+
+            let inputs = HashMap::from([("not_an_input".to_string(), input)]);
+            let err = model.run(py, inputs, None).err().unwrap();
+            assert_eq!(
+                err.value_bound(py).to_string(),
+                "node not found: not_an_input"
+            );
+        });
+    }
+
+    #[test]
+    fn test_tokenizer() {
+        let json = r###"{
+            "model": {
+                "type": "WordPiece",
+                "vocab": { "foo": 1, "##bar": 2, "[CLS]": 3, "[SEP]": 4 }
+            }
+        }"###;
+        let tokenizer = Tokenizer::from_json(json).unwrap();
+        let ids = tokenizer.encode("foobar").unwrap();
+        assert_eq!(ids, [3, 1, 2, 4]);
+        assert_eq!(tokenizer.decode(ids[1..2].to_vec()).unwrap(), "foo");
+    }
+
+    #[test]
+    fn test_tokenizer_decoder() {
+        let json = r#"{
+            "model": {
+                "type": "WordLevel",
+                "vocab": { "▁hello": 0, "▁world": 1, "<0x21>": 2 }
+            },
+            "decoder": {
+                "type": "Sequence",
+                "decoders": [
+                    { "type": "ByteFallback" },
+                    { "type": "Metaspace", "replacement": "▁", "prepend_scheme": "always" }
+                ]
+            }
+        }"#;
+        let tokenizer = Tokenizer::from_json(json).unwrap();
+        assert_eq!(tokenizer.decode(vec![0, 1, 2]).unwrap(), "hello world!");
+    }
+
+    #[test]
+    fn test_generator() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let model = Model::load(counting_model(10)).unwrap();
+            let generator = Generator::new(&model, 5, None, 1.0, vec![6]);
+
+            let streamed = PyList::empty_bound(py);
+            let on_token = streamed.getattr("append").unwrap();
+            let tokens = generator.generate(py, vec![1, 2], Some(on_token)).unwrap();
+            assert_eq!(tokens, [3, 4, 5]);
+            assert_eq!(streamed.extract::<Vec<u32>>().unwrap(), tokens);
+
+            // Errors raised by the callback stop generation.
+            let on_token = py.eval_bound("lambda token: 1 / 0", None, None).unwrap();
+            let err = generator
+                .generate(py, vec![1], Some(on_token))
+                .err()
+                .unwrap();
+            assert!(err.is_instance_of::<PyZeroDivisionError>(py));
+
+            let on_token = streamed.into_any();
+            let err = generator
+                .generate(py, vec![1], Some(on_token))
+                .err()
+                .unwrap();
+            assert!(err.is_instance_of::<PyTypeError>(py));
+        });
+    }
+}
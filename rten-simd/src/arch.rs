@@ -39,3 +39,276 @@ unsafe fn simd_gather_mask<S: SimdFloat, const LEN: usize>(
     let values: [f32; LEN] = std::array::from_fn(|i| *src.add(offset_array[i] as usize));
     S::splat(0.).blend(S::load(values.as_ptr()), mask)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{SimdFloat, SimdInt, SimdInt16, SimdInt8, SimdVal, MAX_LEN};
+
+    // Maximum number of 8-bit lanes in a vector register across all
+    // supported architectures.
+    const MAX_LEN_I8: usize = MAX_LEN * 4;
+
+    /// Generate `len` values which include the extremes of `i8`.
+    fn test_i8s(len: usize, seed: i32) -> Vec<i8> {
+        (0..len as i32)
+            .map(|i| match i {
+                0 => i8::MIN,
+                1 => i8::MAX,
+                _ => (i * seed + 11) as i8,
+            })
+            .collect()
+    }
+
+    fn test_i16s(len: usize, seed: i32) -> Vec<i16> {
+        (0..len as i32)
+            .map(|i| match i {
+                0 => i16::MIN,
+                1 => i16::MAX,
+                _ => (i * seed * 257 + 11) as i16,
+            })
+            .collect()
+    }
+
+    unsafe fn store_i8<S: SimdInt8>(x: S) -> Vec<i8> {
+        let mut out = [0; MAX_LEN_I8];
+        x.store(out.as_mut_ptr());
+        out[..S::LEN].to_vec()
+    }
+
+    unsafe fn store_i16<S: SimdInt16>(x: S) -> Vec<i16> {
+        let mut out = [0; MAX_LEN_I8 / 2];
+        x.store(out.as_mut_ptr());
+        out[..S::LEN].to_vec()
+    }
+
+    unsafe fn store_i32<S: SimdInt>(x: S) -> Vec<i32> {
+        let mut out = [0; MAX_LEN];
+        x.store(out.as_mut_ptr());
+        out[..S::LEN].to_vec()
+    }
+
+    /// Test a [SimdInt8] implementation and its associated [SimdInt16] type
+    /// against scalar reference implementations.
+    unsafe fn check_simd_int8<S: SimdInt8>() {
+        let xs = test_i8s(S::LEN, 37);
+        let ys = test_i8s(S::LEN, -53);
+        let x = S::load(xs.as_ptr());
+        let y = S::load(ys.as_ptr());
+
+        let map2 = |f: fn(i8, i8) -> i8| -> Vec<i8> {
+            xs.iter().zip(&ys).map(|(x, y)| f(*x, *y)).collect()
+        };
+
+        assert_eq!(store_i8(x), xs);
+        assert_eq!(store_i8(S::splat(-5)), vec![-5; S::LEN]);
+        assert_eq!(store_i8(S::zero()), vec![0; S::LEN]);
+        assert_eq!(store_i8(x.add(y)), map2(i8::wrapping_add));
+        assert_eq!(store_i8(x.sub(y)), map2(i8::wrapping_sub));
+        assert_eq!(store_i8(x.min(y)), map2(i8::min));
+        assert_eq!(store_i8(x.max(y)), map2(i8::max));
+
+        let (lo, hi) = x.widen();
+        let mut widened = store_i16(lo);
+        widened.extend(store_i16(hi));
+        let expected: Vec<i16> = xs.iter().map(|x| *x as i16).collect();
+        assert_eq!(widened, expected);
+        assert_eq!(store_i8(S::narrow_saturate(lo, hi)), xs);
+
+        let wide_lo = S::Int16::load(test_i16s(S::Int16::LEN, 37).as_ptr());
+        let wide_hi = S::Int16::load(test_i16s(S::Int16::LEN, -53).as_ptr());
+        let mut wide = store_i16(wide_lo);
+        wide.extend(store_i16(wide_hi));
+        let expected: Vec<i8> = wide
+            .iter()
+            .map(|x| (*x).clamp(i8::MIN as i16, i8::MAX as i16) as i8)
+            .collect();
+        assert_eq!(store_i8(S::narrow_saturate(wide_lo, wide_hi)), expected);
+
+        let acc_val = 1000;
+        let acc = S::Int32::splat(acc_val);
+        let expected: Vec<i32> = xs
+            .chunks(4)
+            .zip(ys.chunks(4))
+            .map(|(x, y)| {
+                x.iter()
+                    .zip(y)
+                    .fold(acc_val, |acc, (x, y)| acc + *x as i32 * *y as i32)
+            })
+            .collect();
+        assert_eq!(store_i32(x.dot(y, acc)), expected);
+
+        check_simd_int16::<S::Int16>();
+    }
+
+    /// Test a [SimdInt16] implementation against scalar reference
+    /// implementations.
+    unsafe fn check_simd_int16<S: SimdInt16>() {
+        let xs = test_i16s(S::LEN, 37);
+        let ys = test_i16s(S::LEN, -53);
+        let x = S::load(xs.as_ptr());
+        let y = S::load(ys.as_ptr());
+
+        let map2 = |f: fn(i16, i16) -> i16| -> Vec<i16> {
+            xs.iter().zip(&ys).map(|(x, y)| f(*x, *y)).collect()
+        };
+
+        assert_eq!(store_i16(x), xs);
+        assert_eq!(store_i16(S::splat(-300)), vec![-300; S::LEN]);
+        assert_eq!(store_i16(S::zero()), vec![0; S::LEN]);
+        assert_eq!(store_i16(x.add(y)), map2(i16::wrapping_add));
+        assert_eq!(store_i16(x.sub(y)), map2(i16::wrapping_sub));
+        assert_eq!(store_i16(x.min(y)), map2(i16::min));
+        assert_eq!(store_i16(x.max(y)), map2(i16::max));
+
+        let (lo, hi) = x.widen();
+        let mut widened = store_i32(lo);
+        widened.extend(store_i32(hi));
+        let expected: Vec<i32> = xs.iter().map(|x| *x as i32).collect();
+        assert_eq!(widened, expected);
+        assert_eq!(store_i16(S::narrow_saturate(lo, hi)), xs);
+
+        let big = S::Int32::splat(100_000);
+        let small = S::Int32::splat(-100_000);
+        let mut expected = vec![i16::MAX; S::LEN / 2];
+        expected.extend(vec![i16::MIN; S::LEN / 2]);
+        assert_eq!(store_i16(S::narrow_saturate(big, small)), expected);
+
+        let acc_val = -1000;
+        let acc = S::Int32::splat(acc_val);
+        let expected: Vec<i32> = xs
+            .chunks(2)
+            .zip(ys.chunks(2))
+            .map(|(x, y)| {
+                let dot = (x[0] as i32 * y[0] as i32).wrapping_add(x[1] as i32 * y[1] as i32);
+                acc_val.wrapping_add(dot)
+            })
+            .collect();
+        assert_eq!(store_i32(x.dot(y, acc)), expected);
+        assert_eq!(S::Int32::LEN * 2, S::LEN);
+    }
+
+    /// Test horizontal reductions of a [SimdFloat] implementation against
+    /// scalar reference implementations.
+    unsafe fn check_simd_float_reductions<S: SimdFloat>() {
+        let len = S::LEN;
+
+        // Rotate the input so that the minimum and maximum are found in each
+        // lane position.
+        for offset in 0..len {
+            let xs: Vec<f32> = (0..len)
+                .map(|i| ((i + offset) % len) as f32 * 1.5 - 4.)
+                .collect();
+            let ys: Vec<f32> = xs.iter().rev().copied().collect();
+            let x = S::load(xs.as_ptr());
+            let y = S::load(ys.as_ptr());
+
+            let max = xs.iter().fold(f32::NEG_INFINITY, |max, x| max.max(*x));
+            let min = xs.iter().fold(f32::INFINITY, |min, x| min.min(*x));
+            assert_eq!(x.reduce_max(), max);
+            assert_eq!(x.reduce_min(), min);
+            assert_eq!(
+                x.reduce_argmax(),
+                xs.iter().position(|x| *x == max).unwrap()
+            );
+            assert_eq!(
+                x.reduce_argmin(),
+                xs.iter().position(|x| *x == min).unwrap()
+            );
+
+            let mut elementwise = [0.; MAX_LEN];
+            x.min(y).store(elementwise.as_mut_ptr());
+            let expected: Vec<f32> = xs.iter().zip(&ys).map(|(x, y)| x.min(*y)).collect();
+            assert_eq!(elementwise[..len], expected);
+        }
+
+        // Ties resolve to the lowest index.
+        let x = S::splat(2.);
+        assert_eq!(x.reduce_max(), 2.);
+        assert_eq!(x.reduce_min(), 2.);
+        assert_eq!(x.reduce_argmax(), 0);
+        assert_eq!(x.reduce_argmin(), 0);
+
+        // NaN lanes are ignored by arg reductions.
+        let mut xs = vec![1.; len];
+        xs[0] = f32::NAN;
+        xs[len - 1] = 3.;
+        let x = S::load(xs.as_ptr());
+        assert_eq!(x.reduce_argmax(), len - 1);
+        assert_eq!(x.reduce_argmin(), if len > 2 { 1 } else { len - 1 });
+        assert_eq!(S::splat(f32::NAN).reduce_argmax(), 0);
+    }
+
+    #[test]
+    fn test_simd_float_reductions_scalar() {
+        unsafe { check_simd_float_reductions::<f32>() }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_simd_float_reductions_avx2() {
+        if !is_x86_feature_detected!("avx2") {
+            return;
+        }
+        unsafe { check_simd_float_reductions::<std::arch::x86_64::__m256>() }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[cfg(feature = "avx512")]
+    #[test]
+    fn test_simd_float_reductions_avx512() {
+        if !crate::is_avx512_supported() {
+            return;
+        }
+        unsafe { check_simd_float_reductions::<std::arch::x86_64::__m512>() }
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    #[test]
+    fn test_simd_float_reductions_neon() {
+        unsafe { check_simd_float_reductions::<std::arch::aarch64::float32x4_t>() }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    #[cfg(target_feature = "simd128")]
+    #[test]
+    fn test_simd_float_reductions_wasm() {
+        unsafe { check_simd_float_reductions::<super::wasm::v128f>() }
+    }
+
+    #[test]
+    fn test_simd_int8_scalar() {
+        unsafe { check_simd_int8::<[i8; 4]>() }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_simd_int8_avx2() {
+        if !is_x86_feature_detected!("avx2") {
+            return;
+        }
+        unsafe { check_simd_int8::<super::x86_64::m256i8>() }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[cfg(feature = "avx512")]
+    #[test]
+    fn test_simd_int8_avx512() {
+        if !crate::is_avx512_supported() {
+            return;
+        }
+        unsafe { check_simd_int8::<super::x86_64::m512i8>() }
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    #[test]
+    fn test_simd_int8_neon() {
+        unsafe { check_simd_int8::<std::arch::aarch64::int8x16_t>() }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    #[cfg(target_feature = "simd128")]
+    #[test]
+    fn test_simd_int8_wasm() {
+        unsafe { check_simd_int8::<super::wasm::v128i8>() }
+    }
+}
@@ -1,12 +1,17 @@
 use std::arch::aarch64::{
-    float32x4_t, int32x4_t, uint32x4_t, vabsq_f32, vaddq_f32, vaddq_s32, vaddvq_f32, vandq_u32,
-    vbslq_f32, vbslq_s32, vceqq_s32, vcgeq_f32, vcgeq_s32, vcgtq_s32, vcleq_f32, vcleq_s32,
-    vcltq_f32, vcltq_s32, vcvtq_s32_f32, vdivq_f32, vdupq_n_f32, vdupq_n_s32, vfmaq_f32, vld1q_f32,
-    vld1q_s32, vmaxq_f32, vmulq_f32, vreinterpretq_f32_s32, vshlq_n_s32, vst1q_f32, vst1q_s32,
-    vsubq_f32, vsubq_s32,
+    float32x4_t, int16x8_t, int32x4_t, int8x16_t, uint32x4_t, vabsq_f32, vaddq_f32, vaddq_s16,
+    vaddq_s32, vaddq_s8, vaddvq_f32, vandq_u32, vbslq_f32, vbslq_s32, vceqq_s32, vcgeq_f32,
+    vcgeq_s32, vcgtq_s32, vcleq_f32, vcleq_s32, vcltq_f32, vcltq_s32, vcombine_s16, vcombine_s8,
+    vcvtq_s32_f32, vdivq_f32, vdupq_n_f32, vdupq_n_s16, vdupq_n_s32, vdupq_n_s8, vfmaq_f32,
+    vget_low_s16, vget_low_s8, vld1q_f32, vld1q_s16, vld1q_s32, vld1q_s8, vmaxq_f32, vmaxq_s16,
+    vmaxq_s32, vmaxq_s8, vmaxvq_f32, vminq_f32, vminq_s16, vminq_s32, vminq_s8, vminvq_f32,
+    vmovl_high_s16, vmovl_high_s8, vmovl_s16, vmovl_s8, vmull_high_s16, vmull_high_s8, vmull_s16,
+    vmull_s8, vmulq_f32, vpaddlq_s16, vpaddq_s32, vqmovn_s16, vqmovn_s32, vreinterpretq_f32_s32,
+    vreinterpretq_s32_f32, vshlq_n_s32, vshrq_n_s32, vst1q_f32, vst1q_s16, vst1q_s32, vst1q_s8,
+    vsubq_f32, vsubq_s16, vsubq_s32, vsubq_s8,
 };
 
-use crate::{SimdFloat, SimdInt, SimdMask, SimdVal};
+use crate::{SimdFloat, SimdInt, SimdInt16, SimdInt8, SimdMask, SimdVal};
 
 impl SimdMask for uint32x4_t {
     #[inline]
@@ -23,6 +28,7 @@ impl SimdVal for int32x4_t {
 
 impl SimdInt for int32x4_t {
     type Float = float32x4_t;
+    type Int8 = int8x16_t;
 
     #[inline]
     unsafe fn zero() -> Self {
@@ -74,11 +80,26 @@ impl SimdInt for int32x4_t {
         vsubq_s32(self, rhs)
     }
 
+    #[inline]
+    unsafe fn min(self, rhs: Self) -> Self {
+        vminq_s32(self, rhs)
+    }
+
+    #[inline]
+    unsafe fn max(self, rhs: Self) -> Self {
+        vmaxq_s32(self, rhs)
+    }
+
     #[inline]
     unsafe fn shl<const COUNT: i32>(self) -> Self {
         vshlq_n_s32(self, COUNT)
     }
 
+    #[inline]
+    unsafe fn shr<const COUNT: i32>(self) -> Self {
+        vshrq_n_s32(self, COUNT)
+    }
+
     #[inline]
     unsafe fn reinterpret_as_float(self) -> Self::Float {
         vreinterpretq_f32_s32(self)
@@ -95,6 +116,128 @@ impl SimdInt for int32x4_t {
     }
 }
 
+impl SimdInt8 for int8x16_t {
+    const LEN: usize = 16;
+
+    type Int16 = int16x8_t;
+    type Int32 = int32x4_t;
+
+    #[inline]
+    unsafe fn splat(val: i8) -> Self {
+        vdupq_n_s8(val)
+    }
+
+    #[inline]
+    unsafe fn add(self, rhs: Self) -> Self {
+        vaddq_s8(self, rhs)
+    }
+
+    #[inline]
+    unsafe fn sub(self, rhs: Self) -> Self {
+        vsubq_s8(self, rhs)
+    }
+
+    #[inline]
+    unsafe fn min(self, rhs: Self) -> Self {
+        vminq_s8(self, rhs)
+    }
+
+    #[inline]
+    unsafe fn max(self, rhs: Self) -> Self {
+        vmaxq_s8(self, rhs)
+    }
+
+    #[inline]
+    unsafe fn widen(self) -> (int16x8_t, int16x8_t) {
+        (vmovl_s8(vget_low_s8(self)), vmovl_high_s8(self))
+    }
+
+    #[inline]
+    unsafe fn narrow_saturate(lo: int16x8_t, hi: int16x8_t) -> Self {
+        vcombine_s8(vqmovn_s16(lo), vqmovn_s16(hi))
+    }
+
+    #[inline]
+    unsafe fn dot(self, rhs: Self, acc: int32x4_t) -> int32x4_t {
+        // The `sdot` instruction requires the "dotprod" extension, which is
+        // not available on all Arm v8 CPUs, so this uses widening multiplies
+        // and pairwise additions instead.
+        let prod_lo = vmull_s8(vget_low_s8(self), vget_low_s8(rhs));
+        let prod_hi = vmull_high_s8(self, rhs);
+        let pairs_lo = vpaddlq_s16(prod_lo);
+        let pairs_hi = vpaddlq_s16(prod_hi);
+        vaddq_s32(acc, vpaddq_s32(pairs_lo, pairs_hi))
+    }
+
+    #[inline]
+    unsafe fn load(ptr: *const i8) -> Self {
+        vld1q_s8(ptr)
+    }
+
+    #[inline]
+    unsafe fn store(self, ptr: *mut i8) {
+        vst1q_s8(ptr, self)
+    }
+}
+
+impl SimdInt16 for int16x8_t {
+    const LEN: usize = 8;
+
+    type Int32 = int32x4_t;
+
+    #[inline]
+    unsafe fn splat(val: i16) -> Self {
+        vdupq_n_s16(val)
+    }
+
+    #[inline]
+    unsafe fn add(self, rhs: Self) -> Self {
+        vaddq_s16(self, rhs)
+    }
+
+    #[inline]
+    unsafe fn sub(self, rhs: Self) -> Self {
+        vsubq_s16(self, rhs)
+    }
+
+    #[inline]
+    unsafe fn min(self, rhs: Self) -> Self {
+        vminq_s16(self, rhs)
+    }
+
+    #[inline]
+    unsafe fn max(self, rhs: Self) -> Self {
+        vmaxq_s16(self, rhs)
+    }
+
+    #[inline]
+    unsafe fn widen(self) -> (int32x4_t, int32x4_t) {
+        (vmovl_s16(vget_low_s16(self)), vmovl_high_s16(self))
+    }
+
+    #[inline]
+    unsafe fn narrow_saturate(lo: int32x4_t, hi: int32x4_t) -> Self {
+        vcombine_s16(vqmovn_s32(lo), vqmovn_s32(hi))
+    }
+
+    #[inline]
+    unsafe fn dot(self, rhs: Self, acc: int32x4_t) -> int32x4_t {
+        let prod_lo = vmull_s16(vget_low_s16(self), vget_low_s16(rhs));
+        let prod_hi = vmull_high_s16(self, rhs);
+        vaddq_s32(acc, vpaddq_s32(prod_lo, prod_hi))
+    }
+
+    #[inline]
+    unsafe fn load(ptr: *const i16) -> Self {
+        vld1q_s16(ptr)
+    }
+
+    #[inline]
+    unsafe fn store(self, ptr: *mut i16) {
+        vst1q_s16(ptr, self)
+    }
+}
+
 impl SimdVal for float32x4_t {
     const LEN: usize = 4;
 
@@ -134,6 +277,11 @@ impl SimdFloat for float32x4_t {
         vcvtq_s32_f32(self)
     }
 
+    #[inline]
+    unsafe fn reinterpret_as_int(self) -> Self::Int {
+        vreinterpretq_s32_f32(self)
+    }
+
     #[inline]
     unsafe fn mul(self, rhs: Self) -> Self {
         vmulq_f32(self, rhs)
@@ -159,6 +307,11 @@ impl SimdFloat for float32x4_t {
         vcltq_f32(self, rhs)
     }
 
+    #[inline]
+    unsafe fn min(self, rhs: Self) -> Self {
+        vminq_f32(self, rhs)
+    }
+
     #[inline]
     unsafe fn max(self, rhs: Self) -> Self {
         vmaxq_f32(self, rhs)
@@ -188,4 +341,14 @@ impl SimdFloat for float32x4_t {
     unsafe fn sum(self) -> f32 {
         vaddvq_f32(self)
     }
+
+    #[inline]
+    unsafe fn reduce_max(self) -> f32 {
+        vmaxvq_f32(self)
+    }
+
+    #[inline]
+    unsafe fn reduce_min(self) -> f32 {
+        vminvq_f32(self)
+    }
 }
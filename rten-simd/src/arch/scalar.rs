@@ -1,4 +1,4 @@
-use crate::{SimdFloat, SimdInt, SimdMask, SimdVal};
+use crate::{SimdFloat, SimdInt, SimdInt16, SimdInt8, SimdMask, SimdVal};
 
 impl SimdMask for bool {
     #[inline]
@@ -16,6 +16,7 @@ impl SimdVal for i32 {
 /// Treat an `i32` as a single-lane SIMD "vector".
 impl SimdInt for i32 {
     type Float = f32;
+    type Int8 = [i8; 4];
 
     #[inline]
     unsafe fn zero() -> Self {
@@ -63,12 +64,22 @@ impl SimdInt for i32 {
 
     #[inline]
     unsafe fn add(self, rhs: Self) -> Self {
-        self + rhs
+        self.wrapping_add(rhs)
     }
 
     #[inline]
     unsafe fn sub(self, rhs: Self) -> Self {
-        self - rhs
+        self.wrapping_sub(rhs)
+    }
+
+    #[inline]
+    unsafe fn min(self, rhs: Self) -> Self {
+        Ord::min(self, rhs)
+    }
+
+    #[inline]
+    unsafe fn max(self, rhs: Self) -> Self {
+        Ord::max(self, rhs)
     }
 
     #[inline]
@@ -76,6 +87,11 @@ impl SimdInt for i32 {
         self << COUNT
     }
 
+    #[inline]
+    unsafe fn shr<const COUNT: i32>(self) -> Self {
+        self >> COUNT
+    }
+
     #[inline]
     unsafe fn reinterpret_as_float(self) -> Self::Float {
         f32::from_bits(self as u32)
@@ -92,6 +108,134 @@ impl SimdInt for i32 {
     }
 }
 
+/// Treat four `i8`s, packed into the same space as an `i32`, as a SIMD vector.
+impl SimdInt8 for [i8; 4] {
+    const LEN: usize = 4;
+
+    type Int16 = [i16; 2];
+    type Int32 = i32;
+
+    #[inline]
+    unsafe fn splat(val: i8) -> Self {
+        [val; 4]
+    }
+
+    #[inline]
+    unsafe fn add(self, rhs: Self) -> Self {
+        std::array::from_fn(|i| self[i].wrapping_add(rhs[i]))
+    }
+
+    #[inline]
+    unsafe fn sub(self, rhs: Self) -> Self {
+        std::array::from_fn(|i| self[i].wrapping_sub(rhs[i]))
+    }
+
+    #[inline]
+    unsafe fn min(self, rhs: Self) -> Self {
+        std::array::from_fn(|i| self[i].min(rhs[i]))
+    }
+
+    #[inline]
+    unsafe fn max(self, rhs: Self) -> Self {
+        std::array::from_fn(|i| self[i].max(rhs[i]))
+    }
+
+    #[inline]
+    unsafe fn widen(self) -> ([i16; 2], [i16; 2]) {
+        (
+            [self[0] as i16, self[1] as i16],
+            [self[2] as i16, self[3] as i16],
+        )
+    }
+
+    #[inline]
+    unsafe fn narrow_saturate(lo: [i16; 2], hi: [i16; 2]) -> Self {
+        let narrow = |x: i16| x.clamp(i8::MIN as i16, i8::MAX as i16) as i8;
+        [narrow(lo[0]), narrow(lo[1]), narrow(hi[0]), narrow(hi[1])]
+    }
+
+    #[inline]
+    unsafe fn dot(self, rhs: Self, acc: i32) -> i32 {
+        self.iter()
+            .zip(rhs)
+            .fold(acc, |acc, (x, y)| acc.wrapping_add(*x as i32 * y as i32))
+    }
+
+    #[inline]
+    unsafe fn load(ptr: *const i8) -> Self {
+        std::array::from_fn(|i| *ptr.add(i))
+    }
+
+    #[inline]
+    unsafe fn store(self, ptr: *mut i8) {
+        for i in 0..4 {
+            *ptr.add(i) = self[i];
+        }
+    }
+}
+
+/// Treat two `i16`s, packed into the same space as an `i32`, as a SIMD vector.
+impl SimdInt16 for [i16; 2] {
+    const LEN: usize = 2;
+
+    type Int32 = i32;
+
+    #[inline]
+    unsafe fn splat(val: i16) -> Self {
+        [val; 2]
+    }
+
+    #[inline]
+    unsafe fn add(self, rhs: Self) -> Self {
+        std::array::from_fn(|i| self[i].wrapping_add(rhs[i]))
+    }
+
+    #[inline]
+    unsafe fn sub(self, rhs: Self) -> Self {
+        std::array::from_fn(|i| self[i].wrapping_sub(rhs[i]))
+    }
+
+    #[inline]
+    unsafe fn min(self, rhs: Self) -> Self {
+        std::array::from_fn(|i| self[i].min(rhs[i]))
+    }
+
+    #[inline]
+    unsafe fn max(self, rhs: Self) -> Self {
+        std::array::from_fn(|i| self[i].max(rhs[i]))
+    }
+
+    #[inline]
+    unsafe fn widen(self) -> (i32, i32) {
+        (self[0] as i32, self[1] as i32)
+    }
+
+    #[inline]
+    unsafe fn narrow_saturate(lo: i32, hi: i32) -> Self {
+        let narrow = |x: i32| x.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+        [narrow(lo), narrow(hi)]
+    }
+
+    #[inline]
+    unsafe fn dot(self, rhs: Self, acc: i32) -> i32 {
+        let prod_0 = self[0] as i32 * rhs[0] as i32;
+        let prod_1 = self[1] as i32 * rhs[1] as i32;
+        acc.wrapping_add(prod_0.wrapping_add(prod_1))
+    }
+
+    #[inline]
+    unsafe fn load(ptr: *const i16) -> Self {
+        std::array::from_fn(|i| *ptr.add(i))
+    }
+
+    #[inline]
+    unsafe fn store(self, ptr: *mut i16) {
+        for i in 0..2 {
+            *ptr.add(i) = self[i];
+        }
+    }
+}
+
 impl SimdVal for f32 {
     const LEN: usize = 1;
 
@@ -142,6 +286,11 @@ impl SimdFloat for f32 {
         self as i32
     }
 
+    #[inline]
+    unsafe fn reinterpret_as_int(self) -> Self::Int {
+        self.to_bits() as i32
+    }
+
     #[inline]
     unsafe fn mul(self, rhs: Self) -> Self {
         self * rhs
@@ -167,6 +316,11 @@ impl SimdFloat for f32 {
         self < rhs
     }
 
+    #[inline]
+    unsafe fn min(self, rhs: Self) -> Self {
+        f32::min(self, rhs)
+    }
+
     #[inline]
     unsafe fn max(self, rhs: Self) -> Self {
         f32::max(self, rhs)
@@ -204,4 +358,24 @@ impl SimdFloat for f32 {
     unsafe fn sum(self) -> f32 {
         self
     }
+
+    #[inline]
+    unsafe fn reduce_max(self) -> f32 {
+        self
+    }
+
+    #[inline]
+    unsafe fn reduce_min(self) -> f32 {
+        self
+    }
+
+    #[inline]
+    unsafe fn reduce_argmax(self) -> usize {
+        0
+    }
+
+    #[inline]
+    unsafe fn reduce_argmin(self) -> usize {
+        0
+    }
 }
@@ -1,17 +1,32 @@
 use std::arch::wasm32::{
     f32x4_abs, f32x4_add, f32x4_div, f32x4_extract_lane, f32x4_ge, f32x4_le, f32x4_lt, f32x4_max,
-    f32x4_mul, f32x4_splat, f32x4_sub, i32x4_add, i32x4_eq, i32x4_ge, i32x4_gt, i32x4_le, i32x4_lt,
-    i32x4_shl, i32x4_shuffle, i32x4_splat, i32x4_sub, i32x4_trunc_sat_f32x4, v128, v128_and,
-    v128_bitselect, v128_load, v128_store,
+    f32x4_min, f32x4_mul, f32x4_splat, f32x4_sub, i16x8_add, i16x8_extend_high_i8x16,
+    i16x8_extend_low_i8x16, i16x8_extmul_high_i8x16, i16x8_extmul_low_i8x16, i16x8_max, i16x8_min,
+    i16x8_narrow_i32x4, i16x8_splat, i16x8_sub, i32x4_add, i32x4_dot_i16x8, i32x4_eq,
+    i32x4_extadd_pairwise_i16x8, i32x4_extend_high_i16x8, i32x4_extend_low_i16x8, i32x4_ge,
+    i32x4_gt, i32x4_le, i32x4_lt, i32x4_max, i32x4_min, i32x4_shl, i32x4_shr, i32x4_shuffle,
+    i32x4_splat, i32x4_sub, i32x4_trunc_sat_f32x4, i8x16_add, i8x16_max, i8x16_min,
+    i8x16_narrow_i16x8, i8x16_splat, i8x16_sub, v128, v128_and, v128_bitselect, v128_load,
+    v128_store,
 };
 
-use crate::{SimdFloat, SimdInt, SimdMask, SimdVal};
+use crate::{SimdFloat, SimdInt, SimdInt16, SimdInt8, SimdMask, SimdVal};
 
 /// Wrapper around a WASM v128 type that marks it as containing integers.
 #[allow(non_camel_case_types)]
 #[derive(Copy, Clone, Debug)]
 pub struct v128i(v128);
 
+/// Wrapper around a WASM v128 type that marks it as containing 8-bit integers.
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, Debug)]
+pub struct v128i8(v128);
+
+/// Wrapper around a WASM v128 type that marks it as containing 16-bit integers.
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, Debug)]
+pub struct v128i16(v128);
+
 /// Wrapper around a WASM v128 type that marks it as containing floats.
 #[allow(non_camel_case_types)]
 #[derive(Copy, Clone, Debug)]
@@ -32,6 +47,7 @@ impl SimdVal for v128i {
 
 impl SimdInt for v128i {
     type Float = v128f;
+    type Int8 = v128i8;
 
     #[inline]
     unsafe fn splat(val: i32) -> Self {
@@ -78,11 +94,26 @@ impl SimdInt for v128i {
         Self(i32x4_sub(self.0, rhs.0))
     }
 
+    #[inline]
+    unsafe fn min(self, rhs: Self) -> Self {
+        Self(i32x4_min(self.0, rhs.0))
+    }
+
+    #[inline]
+    unsafe fn max(self, rhs: Self) -> Self {
+        Self(i32x4_max(self.0, rhs.0))
+    }
+
     #[inline]
     unsafe fn shl<const COUNT: i32>(self) -> Self {
         Self(i32x4_shl(self.0, COUNT as u32))
     }
 
+    #[inline]
+    unsafe fn shr<const COUNT: i32>(self) -> Self {
+        Self(i32x4_shr(self.0, COUNT as u32))
+    }
+
     #[inline]
     unsafe fn reinterpret_as_float(self) -> Self::Float {
         v128f(self.0)
@@ -99,6 +130,129 @@ impl SimdInt for v128i {
     }
 }
 
+impl SimdInt8 for v128i8 {
+    const LEN: usize = 16;
+
+    type Int16 = v128i16;
+    type Int32 = v128i;
+
+    #[inline]
+    unsafe fn splat(val: i8) -> Self {
+        Self(i8x16_splat(val))
+    }
+
+    #[inline]
+    unsafe fn add(self, rhs: Self) -> Self {
+        Self(i8x16_add(self.0, rhs.0))
+    }
+
+    #[inline]
+    unsafe fn sub(self, rhs: Self) -> Self {
+        Self(i8x16_sub(self.0, rhs.0))
+    }
+
+    #[inline]
+    unsafe fn min(self, rhs: Self) -> Self {
+        Self(i8x16_min(self.0, rhs.0))
+    }
+
+    #[inline]
+    unsafe fn max(self, rhs: Self) -> Self {
+        Self(i8x16_max(self.0, rhs.0))
+    }
+
+    #[inline]
+    unsafe fn widen(self) -> (v128i16, v128i16) {
+        (
+            v128i16(i16x8_extend_low_i8x16(self.0)),
+            v128i16(i16x8_extend_high_i8x16(self.0)),
+        )
+    }
+
+    #[inline]
+    unsafe fn narrow_saturate(lo: v128i16, hi: v128i16) -> Self {
+        Self(i8x16_narrow_i16x8(lo.0, hi.0))
+    }
+
+    #[inline]
+    unsafe fn dot(self, rhs: Self, acc: v128i) -> v128i {
+        let pairs_lo = i32x4_extadd_pairwise_i16x8(i16x8_extmul_low_i8x16(self.0, rhs.0));
+        let pairs_hi = i32x4_extadd_pairwise_i16x8(i16x8_extmul_high_i8x16(self.0, rhs.0));
+        let even = i32x4_shuffle::<0, 2, 4, 6>(pairs_lo, pairs_hi);
+        let odd = i32x4_shuffle::<1, 3, 5, 7>(pairs_lo, pairs_hi);
+        v128i(i32x4_add(acc.0, i32x4_add(even, odd)))
+    }
+
+    #[inline]
+    unsafe fn load(ptr: *const i8) -> Self {
+        Self(v128_load(ptr as *const v128))
+    }
+
+    #[inline]
+    unsafe fn store(self, ptr: *mut i8) {
+        v128_store(ptr as *mut v128, self.0)
+    }
+}
+
+impl SimdInt16 for v128i16 {
+    const LEN: usize = 8;
+
+    type Int32 = v128i;
+
+    #[inline]
+    unsafe fn splat(val: i16) -> Self {
+        Self(i16x8_splat(val))
+    }
+
+    #[inline]
+    unsafe fn add(self, rhs: Self) -> Self {
+        Self(i16x8_add(self.0, rhs.0))
+    }
+
+    #[inline]
+    unsafe fn sub(self, rhs: Self) -> Self {
+        Self(i16x8_sub(self.0, rhs.0))
+    }
+
+    #[inline]
+    unsafe fn min(self, rhs: Self) -> Self {
+        Self(i16x8_min(self.0, rhs.0))
+    }
+
+    #[inline]
+    unsafe fn max(self, rhs: Self) -> Self {
+        Self(i16x8_max(self.0, rhs.0))
+    }
+
+    #[inline]
+    unsafe fn widen(self) -> (v128i, v128i) {
+        (
+            v128i(i32x4_extend_low_i16x8(self.0)),
+            v128i(i32x4_extend_high_i16x8(self.0)),
+        )
+    }
+
+    #[inline]
+    unsafe fn narrow_saturate(lo: v128i, hi: v128i) -> Self {
+        Self(i16x8_narrow_i32x4(lo.0, hi.0))
+    }
+
+    #[inline]
+    unsafe fn dot(self, rhs: Self, acc: v128i) -> v128i {
+        v128i(i32x4_add(acc.0, i32x4_dot_i16x8(self.0, rhs.0)))
+    }
+
+    #[inline]
+    unsafe fn load(ptr: *const i16) -> Self {
+        Self(v128_load(ptr as *const v128))
+    }
+
+    #[inline]
+    unsafe fn store(self, ptr: *mut i16) {
+        v128_store(ptr as *mut v128, self.0)
+    }
+}
+
 impl SimdVal for v128f {
     const LEN: usize = 4;
 
@@ -138,6 +292,11 @@ impl SimdFloat for v128f {
         v128i(i32x4_trunc_sat_f32x4(self.0))
     }
 
+    #[inline]
+    unsafe fn reinterpret_as_int(self) -> Self::Int {
+        v128i(self.0)
+    }
+
     #[inline]
     unsafe fn mul(self, rhs: Self) -> Self {
         Self(f32x4_mul(self.0, rhs.0))
@@ -163,6 +322,11 @@ impl SimdFloat for v128f {
         v128i(f32x4_lt(self.0, rhs.0))
     }
 
+    #[inline]
+    unsafe fn min(self, rhs: Self) -> Self {
+        Self(f32x4_min(self.0, rhs.0))
+    }
+
     #[inline]
     unsafe fn max(self, rhs: Self) -> Self {
         Self(f32x4_max(self.0, rhs.0))
@@ -199,4 +363,20 @@ impl SimdFloat for v128f {
         let sum = f32x4_add(lo, hi);
         f32x4_extract_lane::<0>(sum)
     }
+
+    #[inline]
+    unsafe fn reduce_max(self) -> f32 {
+        let hi_2 = i32x4_shuffle::<2, 3, 0, 0>(self.0, self.0);
+        let max_2 = f32x4_max(self.0, hi_2);
+        let hi = i32x4_shuffle::<1, 0, 0, 0>(max_2, max_2);
+        f32x4_extract_lane::<0>(f32x4_max(max_2, hi))
+    }
+
+    #[inline]
+    unsafe fn reduce_min(self) -> f32 {
+        let hi_2 = i32x4_shuffle::<2, 3, 0, 0>(self.0, self.0);
+        let min_2 = f32x4_min(self.0, hi_2);
+        let hi = i32x4_shuffle::<1, 0, 0, 0>(min_2, min_2);
+        f32x4_extract_lane::<0>(f32x4_min(min_2, hi))
+    }
 }
@@ -1,16 +1,22 @@
 use std::arch::x86_64::{
-    __m256, __m256i, _mm256_add_epi32, _mm256_add_ps, _mm256_and_si256, _mm256_andnot_ps,
-    _mm256_blendv_epi8, _mm256_blendv_ps, _mm256_castps256_ps128, _mm256_castsi256_ps,
-    _mm256_cmp_ps, _mm256_cmpeq_epi32, _mm256_cmpgt_epi32, _mm256_cvttps_epi32, _mm256_div_ps,
-    _mm256_extractf128_ps, _mm256_fmadd_ps, _mm256_loadu_ps, _mm256_loadu_si256, _mm256_max_ps,
-    _mm256_mul_ps, _mm256_or_si256, _mm256_set1_epi32, _mm256_set1_ps, _mm256_setzero_si256,
-    _mm256_slli_epi32, _mm256_storeu_ps, _mm256_storeu_si256, _mm256_sub_epi32, _mm256_sub_ps,
-    _mm_add_ps, _mm_cvtss_f32, _mm_movehl_ps, _mm_prefetch, _mm_shuffle_ps, _CMP_GE_OQ, _CMP_LE_OQ,
-    _CMP_LT_OQ, _MM_HINT_ET0, _MM_HINT_T0,
+    __m256, __m256i, _mm256_add_epi16, _mm256_add_epi32, _mm256_add_epi8, _mm256_add_ps,
+    _mm256_and_si256, _mm256_andnot_ps, _mm256_blendv_epi8, _mm256_blendv_ps,
+    _mm256_castps256_ps128, _mm256_castps_si256, _mm256_castsi256_ps, _mm256_castsi256_si128,
+    _mm256_cmp_ps, _mm256_cmpeq_epi32, _mm256_cmpgt_epi32, _mm256_cvtepi16_epi32,
+    _mm256_cvtepi8_epi16, _mm256_cvttps_epi32, _mm256_div_ps, _mm256_extractf128_ps,
+    _mm256_extracti128_si256, _mm256_fmadd_ps, _mm256_hadd_epi32, _mm256_loadu_ps,
+    _mm256_loadu_si256, _mm256_madd_epi16, _mm256_max_epi16, _mm256_max_epi32, _mm256_max_epi8,
+    _mm256_max_ps, _mm256_min_epi16, _mm256_min_epi32, _mm256_min_epi8, _mm256_min_ps,
+    _mm256_mul_ps, _mm256_or_si256, _mm256_packs_epi16, _mm256_packs_epi32,
+    _mm256_permute4x64_epi64, _mm256_set1_epi16, _mm256_set1_epi32, _mm256_set1_epi8,
+    _mm256_set1_ps, _mm256_setzero_si256, _mm256_slli_epi32, _mm256_srai_epi32, _mm256_storeu_ps,
+    _mm256_storeu_si256, _mm256_sub_epi16, _mm256_sub_epi32, _mm256_sub_epi8, _mm256_sub_ps,
+    _mm_add_ps, _mm_cvtss_f32, _mm_max_ps, _mm_max_ss, _mm_min_ps, _mm_min_ss, _mm_movehl_ps,
+    _mm_prefetch, _mm_shuffle_ps, _CMP_GE_OQ, _CMP_LE_OQ, _CMP_LT_OQ, _MM_HINT_ET0, _MM_HINT_T0,
 };
 use std::mem::transmute;
 
-use crate::{SimdFloat, SimdInt, SimdMask, SimdVal};
+use crate::{SimdFloat, SimdInt, SimdInt16, SimdInt8, SimdMask, SimdVal};
 
 impl SimdMask for __m256i {
     #[inline]
@@ -28,6 +34,7 @@ impl SimdVal for __m256i {
 
 impl SimdInt for __m256i {
     type Float = __m256;
+    type Int8 = m256i8;
 
     #[inline]
     #[target_feature(enable = "avx2")]
@@ -89,12 +96,30 @@ impl SimdInt for __m256i {
         _mm256_sub_epi32(self, rhs)
     }
 
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn min(self, rhs: Self) -> Self {
+        _mm256_min_epi32(self, rhs)
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn max(self, rhs: Self) -> Self {
+        _mm256_max_epi32(self, rhs)
+    }
+
     #[inline]
     #[target_feature(enable = "avx2")]
     unsafe fn shl<const COUNT: i32>(self) -> Self {
         _mm256_slli_epi32(self, COUNT)
     }
 
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn shr<const COUNT: i32>(self) -> Self {
+        _mm256_srai_epi32(self, COUNT)
+    }
+
     #[inline]
     #[target_feature(enable = "avx2")]
     unsafe fn reinterpret_as_float(self) -> Self::Float {
@@ -116,6 +141,172 @@ impl SimdInt for __m256i {
     }
 }
 
+/// Wrapper around an AVX2 `__m256i` type that marks it as containing 8-bit
+/// integers.
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, Debug)]
+pub struct m256i8(__m256i);
+
+impl SimdInt8 for m256i8 {
+    const LEN: usize = 32;
+
+    type Int16 = m256i16;
+    type Int32 = __m256i;
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn splat(val: i8) -> Self {
+        Self(_mm256_set1_epi8(val))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn add(self, rhs: Self) -> Self {
+        Self(_mm256_add_epi8(self.0, rhs.0))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn sub(self, rhs: Self) -> Self {
+        Self(_mm256_sub_epi8(self.0, rhs.0))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn min(self, rhs: Self) -> Self {
+        Self(_mm256_min_epi8(self.0, rhs.0))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn max(self, rhs: Self) -> Self {
+        Self(_mm256_max_epi8(self.0, rhs.0))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn widen(self) -> (m256i16, m256i16) {
+        let lo = _mm256_cvtepi8_epi16(_mm256_castsi256_si128(self.0));
+        let hi = _mm256_cvtepi8_epi16(_mm256_extracti128_si256::<1>(self.0));
+        (m256i16(lo), m256i16(hi))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn narrow_saturate(lo: m256i16, hi: m256i16) -> Self {
+        // `packs` operates within 128-bit lanes, so the result contains
+        // 64-bit chunks [lo0, hi0, lo1, hi1]. Permute to restore the order.
+        let packed = _mm256_packs_epi16(lo.0, hi.0);
+        Self(_mm256_permute4x64_epi64::<0b11_01_10_00>(packed))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn dot(self, rhs: Self, acc: __m256i) -> __m256i {
+        // AVX2 has no signed 8-bit multiply, so widen to 16 bits and use
+        // `madd` to compute sums of adjacent pairs.
+        let (x_lo, x_hi) = self.widen();
+        let (y_lo, y_hi) = rhs.widen();
+        let pairs_lo = _mm256_madd_epi16(x_lo.0, y_lo.0);
+        let pairs_hi = _mm256_madd_epi16(x_hi.0, y_hi.0);
+
+        // `hadd` operates within 128-bit lanes, so the result contains sums
+        // for groups [0, 1, 4, 5, 2, 3, 6, 7] in 64-bit chunks. Permute to
+        // restore the order.
+        let quads = _mm256_hadd_epi32(pairs_lo, pairs_hi);
+        let quads = _mm256_permute4x64_epi64::<0b11_01_10_00>(quads);
+        _mm256_add_epi32(acc, quads)
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn load(ptr: *const i8) -> Self {
+        Self(_mm256_loadu_si256(ptr as *const __m256i))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn store(self, ptr: *mut i8) {
+        _mm256_storeu_si256(ptr as *mut __m256i, self.0)
+    }
+}
+
+/// Wrapper around an AVX2 `__m256i` type that marks it as containing 16-bit
+/// integers.
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, Debug)]
+pub struct m256i16(__m256i);
+
+impl SimdInt16 for m256i16 {
+    const LEN: usize = 16;
+
+    type Int32 = __m256i;
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn splat(val: i16) -> Self {
+        Self(_mm256_set1_epi16(val))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn add(self, rhs: Self) -> Self {
+        Self(_mm256_add_epi16(self.0, rhs.0))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn sub(self, rhs: Self) -> Self {
+        Self(_mm256_sub_epi16(self.0, rhs.0))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn min(self, rhs: Self) -> Self {
+        Self(_mm256_min_epi16(self.0, rhs.0))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn max(self, rhs: Self) -> Self {
+        Self(_mm256_max_epi16(self.0, rhs.0))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn widen(self) -> (__m256i, __m256i) {
+        let lo = _mm256_cvtepi16_epi32(_mm256_castsi256_si128(self.0));
+        let hi = _mm256_cvtepi16_epi32(_mm256_extracti128_si256::<1>(self.0));
+        (lo, hi)
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn narrow_saturate(lo: __m256i, hi: __m256i) -> Self {
+        // See notes in `m256i8::narrow_saturate`.
+        let packed = _mm256_packs_epi32(lo, hi);
+        Self(_mm256_permute4x64_epi64::<0b11_01_10_00>(packed))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn dot(self, rhs: Self, acc: __m256i) -> __m256i {
+        _mm256_add_epi32(acc, _mm256_madd_epi16(self.0, rhs.0))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn load(ptr: *const i16) -> Self {
+        Self(_mm256_loadu_si256(ptr as *const __m256i))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn store(self, ptr: *mut i16) {
+        _mm256_storeu_si256(ptr as *mut __m256i, self.0)
+    }
+}
+
 impl SimdVal for __m256 {
     const LEN: usize = 8;
 
@@ -163,6 +354,12 @@ impl SimdFloat for __m256 {
         _mm256_cvttps_epi32(self)
     }
 
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn reinterpret_as_int(self) -> Self::Int {
+        _mm256_castps_si256(self)
+    }
+
     #[inline]
     #[target_feature(enable = "avx2")]
     unsafe fn mul(self, rhs: Self) -> Self {
@@ -193,6 +390,12 @@ impl SimdFloat for __m256 {
         transmute(_mm256_cmp_ps(self, rhs, _CMP_LT_OQ))
     }
 
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn min(self, rhs: Self) -> Self {
+        _mm256_min_ps(self, rhs)
+    }
+
     #[inline]
     #[target_feature(enable = "avx2")]
     unsafe fn max(self, rhs: Self) -> Self {
@@ -247,6 +450,28 @@ impl SimdFloat for __m256 {
         _mm_cvtss_f32(sum)
     }
 
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn reduce_max(self) -> f32 {
+        let hi_4 = _mm256_extractf128_ps(self, 1);
+        let lo_4 = _mm256_castps256_ps128(self);
+        let max_4 = _mm_max_ps(lo_4, hi_4);
+        let max_2 = _mm_max_ps(max_4, _mm_movehl_ps(max_4, max_4));
+        let max = _mm_max_ss(max_2, _mm_shuffle_ps(max_2, max_2, 0x1));
+        _mm_cvtss_f32(max)
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn reduce_min(self) -> f32 {
+        let hi_4 = _mm256_extractf128_ps(self, 1);
+        let lo_4 = _mm256_castps256_ps128(self);
+        let min_4 = _mm_min_ps(lo_4, hi_4);
+        let min_2 = _mm_min_ps(min_4, _mm_movehl_ps(min_4, min_4));
+        let min = _mm_min_ss(min_2, _mm_shuffle_ps(min_2, min_2, 0x1));
+        _mm_cvtss_f32(min)
+    }
+
     /// Prefetch the cache line containing `data`, for reading.
     #[inline]
     unsafe fn prefetch(data: *const f32) {
@@ -263,12 +488,19 @@ impl SimdFloat for __m256 {
 #[cfg(feature = "avx512")]
 use std::arch::x86_64::{
     __m512, __m512i, __mmask16, _mm512_abs_ps, _mm512_add_epi32, _mm512_add_ps,
-    _mm512_castsi512_ps, _mm512_cmp_epi32_mask, _mm512_cmp_ps_mask, _mm512_cvttps_epi32,
-    _mm512_div_ps, _mm512_fmadd_ps, _mm512_loadu_ps, _mm512_loadu_si512, _mm512_mask_blend_epi32,
-    _mm512_mask_blend_ps, _mm512_mask_i32gather_ps, _mm512_max_ps, _mm512_mul_ps,
-    _mm512_reduce_add_ps, _mm512_set1_epi32, _mm512_set1_ps, _mm512_setzero_si512,
-    _mm512_sllv_epi32, _mm512_storeu_ps, _mm512_storeu_si512, _mm512_sub_epi32, _mm512_sub_ps,
-    _MM_CMPINT_EQ, _MM_CMPINT_LE, _MM_CMPINT_LT,
+    _mm512_castps_si512, _mm512_castsi512_ps, _mm512_cmp_epi32_mask, _mm512_cmp_ps_mask,
+    _mm512_cvttps_epi32, _mm512_div_ps, _mm512_fmadd_ps, _mm512_loadu_ps, _mm512_loadu_si512,
+    _mm512_mask_blend_epi32, _mm512_mask_blend_ps, _mm512_mask_i32gather_ps, _mm512_max_ps,
+    _mm512_mul_ps, _mm512_reduce_add_ps, _mm512_set1_epi32, _mm512_set1_ps, _mm512_setzero_si512,
+    _mm512_sllv_epi32, _mm512_srav_epi32, _mm512_storeu_ps, _mm512_storeu_si512, _mm512_sub_epi32,
+    _mm512_sub_ps, _MM_CMPINT_EQ, _MM_CMPINT_LE, _MM_CMPINT_LT,
+};
+
+#[cfg(feature = "avx512")]
+use std::arch::x86_64::{
+    _mm512_castsi256_si512, _mm512_castsi512_si256, _mm512_cvtepi16_epi32,
+    _mm512_extracti64x4_epi64, _mm512_inserti64x4, _mm512_max_epi32, _mm512_min_epi32,
+    _mm512_min_ps, _mm512_reduce_max_ps, _mm512_reduce_min_ps,
 };
 
 #[cfg(feature = "avx512")]
@@ -290,6 +522,7 @@ impl SimdVal for __m512i {
 #[cfg(feature = "avx512")]
 impl SimdInt for __m512i {
     type Float = __m512;
+    type Int8 = m512i8;
 
     #[inline]
     #[target_feature(enable = "avx512f")]
@@ -351,6 +584,18 @@ impl SimdInt for __m512i {
         _mm512_sub_epi32(self, rhs)
     }
 
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn min(self, rhs: Self) -> Self {
+        _mm512_min_epi32(self, rhs)
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn max(self, rhs: Self) -> Self {
+        _mm512_max_epi32(self, rhs)
+    }
+
     #[inline]
     #[target_feature(enable = "avx512f")]
     unsafe fn shl<const COUNT: i32>(self) -> Self {
@@ -358,6 +603,13 @@ impl SimdInt for __m512i {
         _mm512_sllv_epi32(self, count)
     }
 
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn shr<const COUNT: i32>(self) -> Self {
+        let count = Self::splat(COUNT);
+        _mm512_srav_epi32(self, count)
+    }
+
     #[inline]
     #[target_feature(enable = "avx512f")]
     unsafe fn reinterpret_as_float(self) -> Self::Float {
@@ -377,6 +629,239 @@ impl SimdInt for __m512i {
     }
 }
 
+/// Split a 512-bit vector into low and high 256-bit halves.
+#[cfg(feature = "avx512")]
+#[inline]
+#[target_feature(enable = "avx512f")]
+unsafe fn split_m512i(x: __m512i) -> (__m256i, __m256i) {
+    (_mm512_castsi512_si256(x), _mm512_extracti64x4_epi64::<1>(x))
+}
+
+/// Combine low and high 256-bit halves into a 512-bit vector.
+#[cfg(feature = "avx512")]
+#[inline]
+#[target_feature(enable = "avx512f")]
+unsafe fn join_m512i(lo: __m256i, hi: __m256i) -> __m512i {
+    _mm512_inserti64x4::<1>(_mm512_castsi256_si512(lo), hi)
+}
+
+/// Wrapper around an AVX-512 `__m512i` type that marks it as containing 8-bit
+/// integers.
+///
+/// 8 and 16-bit integer operations on 512-bit vectors require AVX-512 BW,
+/// which is not implied by the features that enable AVX-512 dispatch. Hence
+/// these operations are implemented using AVX2 instructions on each half of
+/// the vector.
+#[cfg(feature = "avx512")]
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, Debug)]
+pub struct m512i8(__m512i);
+
+#[cfg(feature = "avx512")]
+impl m512i8 {
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn halves(self) -> (m256i8, m256i8) {
+        let (lo, hi) = split_m512i(self.0);
+        (m256i8(lo), m256i8(hi))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn from_halves(lo: m256i8, hi: m256i8) -> Self {
+        Self(join_m512i(lo.0, hi.0))
+    }
+}
+
+#[cfg(feature = "avx512")]
+impl SimdInt8 for m512i8 {
+    const LEN: usize = 64;
+
+    type Int16 = m512i16;
+    type Int32 = __m512i;
+
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn splat(val: i8) -> Self {
+        let half = m256i8::splat(val);
+        Self::from_halves(half, half)
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn add(self, rhs: Self) -> Self {
+        let ((x_lo, x_hi), (y_lo, y_hi)) = (self.halves(), rhs.halves());
+        Self::from_halves(x_lo.add(y_lo), x_hi.add(y_hi))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn sub(self, rhs: Self) -> Self {
+        let ((x_lo, x_hi), (y_lo, y_hi)) = (self.halves(), rhs.halves());
+        Self::from_halves(x_lo.sub(y_lo), x_hi.sub(y_hi))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn min(self, rhs: Self) -> Self {
+        let ((x_lo, x_hi), (y_lo, y_hi)) = (self.halves(), rhs.halves());
+        Self::from_halves(x_lo.min(y_lo), x_hi.min(y_hi))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn max(self, rhs: Self) -> Self {
+        let ((x_lo, x_hi), (y_lo, y_hi)) = (self.halves(), rhs.halves());
+        Self::from_halves(x_lo.max(y_lo), x_hi.max(y_hi))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn widen(self) -> (m512i16, m512i16) {
+        let (lo, hi) = self.halves();
+        let (lo_lo, lo_hi) = lo.widen();
+        let (hi_lo, hi_hi) = hi.widen();
+        (
+            m512i16(join_m512i(lo_lo.0, lo_hi.0)),
+            m512i16(join_m512i(hi_lo.0, hi_hi.0)),
+        )
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn narrow_saturate(lo: m512i16, hi: m512i16) -> Self {
+        let ((lo_lo, lo_hi), (hi_lo, hi_hi)) = (lo.halves(), hi.halves());
+        Self::from_halves(
+            m256i8::narrow_saturate(lo_lo, lo_hi),
+            m256i8::narrow_saturate(hi_lo, hi_hi),
+        )
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn dot(self, rhs: Self, acc: __m512i) -> __m512i {
+        let ((x_lo, x_hi), (y_lo, y_hi)) = (self.halves(), rhs.halves());
+        let (acc_lo, acc_hi) = split_m512i(acc);
+        join_m512i(x_lo.dot(y_lo, acc_lo), x_hi.dot(y_hi, acc_hi))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn load(ptr: *const i8) -> Self {
+        Self(_mm512_loadu_si512(ptr as *const i32))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn store(self, ptr: *mut i8) {
+        _mm512_storeu_si512(ptr as *mut i32, self.0)
+    }
+}
+
+/// Wrapper around an AVX-512 `__m512i` type that marks it as containing
+/// 16-bit integers.
+///
+/// See notes in [m512i8] about the implementation.
+#[cfg(feature = "avx512")]
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, Debug)]
+pub struct m512i16(__m512i);
+
+#[cfg(feature = "avx512")]
+impl m512i16 {
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn halves(self) -> (m256i16, m256i16) {
+        let (lo, hi) = split_m512i(self.0);
+        (m256i16(lo), m256i16(hi))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn from_halves(lo: m256i16, hi: m256i16) -> Self {
+        Self(join_m512i(lo.0, hi.0))
+    }
+}
+
+#[cfg(feature = "avx512")]
+impl SimdInt16 for m512i16 {
+    const LEN: usize = 32;
+
+    type Int32 = __m512i;
+
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn splat(val: i16) -> Self {
+        let half = m256i16::splat(val);
+        Self::from_halves(half, half)
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn add(self, rhs: Self) -> Self {
+        let ((x_lo, x_hi), (y_lo, y_hi)) = (self.halves(), rhs.halves());
+        Self::from_halves(x_lo.add(y_lo), x_hi.add(y_hi))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn sub(self, rhs: Self) -> Self {
+        let ((x_lo, x_hi), (y_lo, y_hi)) = (self.halves(), rhs.halves());
+        Self::from_halves(x_lo.sub(y_lo), x_hi.sub(y_hi))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn min(self, rhs: Self) -> Self {
+        let ((x_lo, x_hi), (y_lo, y_hi)) = (self.halves(), rhs.halves());
+        Self::from_halves(x_lo.min(y_lo), x_hi.min(y_hi))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn max(self, rhs: Self) -> Self {
+        let ((x_lo, x_hi), (y_lo, y_hi)) = (self.halves(), rhs.halves());
+        Self::from_halves(x_lo.max(y_lo), x_hi.max(y_hi))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn widen(self) -> (__m512i, __m512i) {
+        let (lo, hi) = split_m512i(self.0);
+        (_mm512_cvtepi16_epi32(lo), _mm512_cvtepi16_epi32(hi))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn narrow_saturate(lo: __m512i, hi: __m512i) -> Self {
+        let ((lo_lo, lo_hi), (hi_lo, hi_hi)) = (split_m512i(lo), split_m512i(hi));
+        Self::from_halves(
+            m256i16::narrow_saturate(lo_lo, lo_hi),
+            m256i16::narrow_saturate(hi_lo, hi_hi),
+        )
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn dot(self, rhs: Self, acc: __m512i) -> __m512i {
+        let ((x_lo, x_hi), (y_lo, y_hi)) = (self.halves(), rhs.halves());
+        let (acc_lo, acc_hi) = split_m512i(acc);
+        join_m512i(x_lo.dot(y_lo, acc_lo), x_hi.dot(y_hi, acc_hi))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn load(ptr: *const i16) -> Self {
+        Self(_mm512_loadu_si512(ptr as *const i32))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn store(self, ptr: *mut i16) {
+        _mm512_storeu_si512(ptr as *mut i32, self.0)
+    }
+}
+
 #[cfg(feature = "avx512")]
 impl SimdVal for __m512 {
     const LEN: usize = 16;
@@ -424,6 +909,12 @@ impl SimdFloat for __m512 {
         _mm512_cvttps_epi32(self)
     }
 
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn reinterpret_as_int(self) -> Self::Int {
+        _mm512_castps_si512(self)
+    }
+
     #[inline]
     #[target_feature(enable = "avx512f")]
     unsafe fn mul(self, rhs: Self) -> Self {
@@ -454,6 +945,12 @@ impl SimdFloat for __m512 {
         _mm512_cmp_ps_mask(self, rhs, _CMP_LT_OQ)
     }
 
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn min(self, rhs: Self) -> Self {
+        _mm512_min_ps(self, rhs)
+    }
+
     #[inline]
     #[target_feature(enable = "avx512f")]
     unsafe fn max(self, rhs: Self) -> Self {
@@ -499,4 +996,16 @@ impl SimdFloat for __m512 {
     unsafe fn sum(self) -> f32 {
         _mm512_reduce_add_ps(self)
     }
+
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn reduce_max(self) -> f32 {
+        _mm512_reduce_max_ps(self)
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn reduce_min(self) -> f32 {
+        _mm512_reduce_min_ps(self)
+    }
 }
@@ -7,16 +7,71 @@ use crate::functional::simd_map;
 use crate::span::{MutPtrLen, PtrLen};
 use crate::SimdFloat;
 
+/// Instruction set used to evaluate SIMD operations.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Isa {
+    #[cfg(feature = "avx512")]
+    #[cfg(target_arch = "x86_64")]
+    Avx512,
+
+    #[cfg(target_arch = "x86_64")]
+    Avx2Fma,
+
+    /// The instruction set chosen at compile time. This is WASM SIMD or Arm
+    /// Neon if available, or the generic fallback otherwise.
+    Default,
+}
+
+/// Return the preferred instruction set for the current system.
+///
+/// On platforms which require runtime detection, the result is computed on
+/// the first call and cached.
+fn detect_isa() -> Isa {
+    #[cfg(target_arch = "x86_64")]
+    {
+        use std::sync::OnceLock;
+
+        static ISA: OnceLock<Isa> = OnceLock::new();
+        *ISA.get_or_init(|| {
+            #[cfg(feature = "avx512")]
+            if crate::is_avx512_supported() {
+                return Isa::Avx512;
+            }
+            if is_x86_feature_detected!("fma") && is_x86_feature_detected!("avx2") {
+                Isa::Avx2Fma
+            } else {
+                Isa::Default
+            }
+        })
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    Isa::Default
+}
+
 /// Dispatches SIMD operations using the preferred SIMD types for the current
 /// platform.
-#[derive(Default)]
-pub struct SimdDispatcher {}
+///
+/// The instruction set is resolved when the dispatcher is created, and runtime
+/// feature detection is only performed once per process. Callers which
+/// dispatch many small operations can create a dispatcher once and reuse it to
+/// avoid repeating even the cached lookup.
+#[derive(Copy, Clone, Debug)]
+pub struct SimdDispatcher {
+    isa: Isa,
+}
+
+impl Default for SimdDispatcher {
+    fn default() -> Self {
+        SimdDispatcher { isa: detect_isa() }
+    }
+}
 
 impl SimdDispatcher {
     /// Evaluate `op` using the preferred SIMD instruction set for the current
     /// system.
     #[allow(unused_imports)]
-    #[allow(unreachable_code)] // Ignore fallback, if unused
+    #[inline]
     pub fn dispatch<Op: SimdOp>(&self, op: Op) {
         #[cfg(feature = "avx512")]
         #[cfg(target_arch = "x86_64")]
@@ -35,41 +90,42 @@ impl SimdDispatcher {
             op.eval::<__m256>();
         }
 
-        #[cfg(target_arch = "x86_64")]
-        {
-            #[cfg(feature = "avx512")]
-            if crate::is_avx512_supported() {
-                unsafe { simd_op_avx512(op) };
+        #[allow(unreachable_code)] // Ignore fallback, if unused
+        fn simd_op_default<Op: SimdOp>(op: Op) {
+            #[cfg(target_arch = "wasm32")]
+            #[cfg(target_feature = "simd128")]
+            {
+                use crate::arch::wasm::v128f;
+
+                // Safety: The WASM runtime will have verified SIMD instructions
+                // are accepted when loading the binary.
+                unsafe { op.eval::<v128f>() };
                 return;
             }
 
-            if is_x86_feature_detected!("fma") && is_x86_feature_detected!("avx2") {
-                // Safety: We've checked that AVX2 + FMA are available.
-                unsafe { simd_op_avx(op) };
+            #[cfg(target_arch = "aarch64")]
+            {
+                use std::arch::aarch64::float32x4_t;
+                unsafe { op.eval::<float32x4_t>() };
                 return;
             }
+
+            // Generic fallback.
+            unsafe { op.eval::<f32>() };
         }
 
-        #[cfg(target_arch = "wasm32")]
-        #[cfg(target_feature = "simd128")]
-        {
-            use crate::arch::wasm::v128f;
+        match self.isa {
+            // Safety: `detect_isa` checked that AVX-512 is available.
+            #[cfg(feature = "avx512")]
+            #[cfg(target_arch = "x86_64")]
+            Isa::Avx512 => unsafe { simd_op_avx512(op) },
 
-            // Safety: The WASM runtime will have verified SIMD instructions
-            // are accepted when loading the binary.
-            unsafe { op.eval::<v128f>() };
-            return;
-        }
+            // Safety: `detect_isa` checked that AVX2 + FMA are available.
+            #[cfg(target_arch = "x86_64")]
+            Isa::Avx2Fma => unsafe { simd_op_avx(op) },
 
-        #[cfg(target_arch = "aarch64")]
-        {
-            use std::arch::aarch64::float32x4_t;
-            unsafe { op.eval::<float32x4_t>() };
-            return;
+            Isa::Default => simd_op_default(op),
         }
-
-        // Generic fallback.
-        unsafe { op.eval::<f32>() };
     }
 }
 
@@ -33,8 +33,6 @@ pub unsafe fn simd_map<S: SimdFloat, Op: FnMut(S) -> S>(
     let mut in_ptr = input.ptr();
     let mut out_ptr = output.ptr();
 
-    // S::LEN can't be used as the array size due to const generics limitations.
-    const MAX_LEN: usize = 16;
     assert!(S::LEN <= MAX_LEN);
     let mut remainder = [pad; MAX_LEN];
 
@@ -65,6 +63,70 @@ pub unsafe fn simd_map<S: SimdFloat, Op: FnMut(S) -> S>(
     }
 }
 
+/// Apply a binary operation to each pair of elements in `a` and `b` and store
+/// the results in `output`.
+///
+/// When evaluated, all elements in `output` will be initialized.
+///
+/// This is like [simd_map], except that `op` is called with SIMD vectors
+/// containing corresponding groups of elements from both inputs. If the final
+/// group has a size that is smaller than the SIMD vector width, both vectors
+/// will be padded with `pad`.
+///
+/// # Safety
+///
+/// The caller must ensure that `S` is a supported SIMD vector type on the
+/// current system.
+#[inline(always)]
+pub unsafe fn simd_map_binary<S: SimdFloat, Op: FnMut(S, S) -> S>(
+    a: PtrLen<f32>,
+    b: PtrLen<f32>,
+    output: MutPtrLen<MaybeUninit<f32>>,
+    mut op: Op,
+    pad: f32,
+) {
+    assert!(a.len() == b.len());
+    assert!(a.len() == output.len());
+
+    let mut n = a.len();
+    let mut a_ptr = a.ptr();
+    let mut b_ptr = b.ptr();
+    let mut out_ptr = output.ptr();
+
+    // S::LEN can't be used as the array size due to const generics limitations.
+    assert!(S::LEN <= MAX_LEN);
+    let mut a_remainder = [pad; MAX_LEN];
+    let mut b_remainder = [pad; MAX_LEN];
+
+    // Main loop over full vectors.
+    while n >= S::LEN {
+        let x = S::load(a_ptr);
+        let y = S::load(b_ptr);
+        op(x, y).store(out_ptr as *mut f32);
+
+        n -= S::LEN;
+        a_ptr = a_ptr.add(S::LEN);
+        b_ptr = b_ptr.add(S::LEN);
+        out_ptr = out_ptr.add(S::LEN);
+    }
+
+    // Handle remainder with padded vectors.
+    if n > 0 {
+        for i in 0..n {
+            a_remainder[i] = *a_ptr.add(i);
+            b_remainder[i] = *b_ptr.add(i);
+        }
+
+        let x = S::load(a_remainder.as_ptr());
+        let y = S::load(b_remainder.as_ptr());
+        op(x, y).store(a_remainder.as_mut_ptr());
+
+        for i in 0..n {
+            out_ptr.add(i).write(MaybeUninit::new(a_remainder[i]));
+        }
+    }
+}
+
 /// Apply a vectorized fold operation over `xs`. If the length of `xs` is not
 /// a multiple of `S::LEN` then the final update will use a vector padded
 /// with `pad`.
@@ -73,12 +73,20 @@ pub trait SimdMask: Copy {
 }
 
 /// Trait for SIMD vectors containing 32-bit integers.
+///
+/// This is part of a family of integer vector traits along with [SimdInt8]
+/// and [SimdInt16]. Vectors of the associated types occupy a register of the
+/// same size, and thus have 4x and 2x as many lanes respectively.
 #[allow(clippy::missing_safety_doc)]
 pub trait SimdInt: SimdVal {
     /// The type produced by an operation that converts each element in this
     /// vector to a float.
     type Float: SimdFloat<Int = Self, Mask = Self::Mask>;
 
+    /// The type of a vector of 8-bit integers with the same size as this
+    /// vector.
+    type Int8: SimdInt8<Int32 = Self>;
+
     /// Return a new vector with all elements set to zero.
     #[inline]
     unsafe fn zero() -> Self {
@@ -118,9 +126,19 @@ pub trait SimdInt: SimdVal {
     /// Compute `self - rhs`.
     unsafe fn sub(self, rhs: Self) -> Self;
 
+    /// Compute the minimum of `self` and `rhs`.
+    unsafe fn min(self, rhs: Self) -> Self;
+
+    /// Compute the maximum of `self` and `rhs`.
+    unsafe fn max(self, rhs: Self) -> Self;
+
     /// Shift the bits in each element left by `count`.
     unsafe fn shl<const COUNT: i32>(self) -> Self;
 
+    /// Shift the bits in each element right by `count`, filling with the
+    /// sign bit.
+    unsafe fn shr<const COUNT: i32>(self) -> Self;
+
     /// Reinterpret the bits of each element as a float.
     unsafe fn reinterpret_as_float(self) -> Self::Float;
 
@@ -141,6 +159,142 @@ pub trait SimdInt: SimdVal {
     unsafe fn store(self, ptr: *mut i32);
 }
 
+/// Trait for SIMD vectors containing 8-bit signed integers.
+///
+/// Arithmetic operations wrap on overflow.
+#[allow(clippy::missing_safety_doc)]
+pub trait SimdInt8: Copy + Sized {
+    /// The number of elements in the SIMD vector.
+    const LEN: usize;
+
+    /// The type of a vector of 16-bit integers with the same size as this
+    /// vector.
+    type Int16: SimdInt16<Int32 = Self::Int32>;
+
+    /// The type of a vector of 32-bit integers with the same size as this
+    /// vector.
+    type Int32: SimdInt;
+
+    /// Return a new vector with all elements set to zero.
+    #[inline]
+    unsafe fn zero() -> Self {
+        Self::splat(0)
+    }
+
+    /// Broadcast `val` to all elements in a new vector.
+    unsafe fn splat(val: i8) -> Self;
+
+    /// Compute `self + rhs`.
+    unsafe fn add(self, rhs: Self) -> Self;
+
+    /// Compute `self - rhs`.
+    unsafe fn sub(self, rhs: Self) -> Self;
+
+    /// Compute the minimum of `self` and `rhs`.
+    unsafe fn min(self, rhs: Self) -> Self;
+
+    /// Compute the maximum of `self` and `rhs`.
+    unsafe fn max(self, rhs: Self) -> Self;
+
+    /// Sign-extend the elements in the low and high halves of this vector
+    /// to 16 bits.
+    unsafe fn widen(self) -> (Self::Int16, Self::Int16);
+
+    /// Narrow the elements of `lo` and `hi` to 8 bits, saturating values
+    /// which are out of range, and concatenate the results.
+    ///
+    /// This is the inverse of [`widen`](SimdInt8::widen).
+    unsafe fn narrow_saturate(lo: Self::Int16, hi: Self::Int16) -> Self;
+
+    /// Compute the dot product of each group of 4 adjacent elements in `self`
+    /// and `rhs`, and add the result to the corresponding element of `acc`.
+    ///
+    /// ie. `acc[i] + sum(self[4 * i + k] * rhs[4 * i + k] for k in 0..4)`.
+    unsafe fn dot(self, rhs: Self, acc: Self::Int32) -> Self::Int32;
+
+    /// Load `Self::LEN` values from the memory address at `ptr`.
+    ///
+    /// Implementations must not require `ptr` to be aligned.
+    ///
+    /// Safety: The caller must ensure `ptr` points to at least `Self::LEN`
+    /// values.
+    unsafe fn load(ptr: *const i8) -> Self;
+
+    /// Store `Self::LEN` values to the memory address at `ptr`.
+    ///
+    /// Implementations must not require `ptr` to be aligned.
+    ///
+    /// Safety: The caller must ensure `ptr` points to a buffer with space for
+    /// at least `Self::LEN` values.
+    unsafe fn store(self, ptr: *mut i8);
+}
+
+/// Trait for SIMD vectors containing 16-bit signed integers.
+///
+/// Arithmetic operations wrap on overflow.
+#[allow(clippy::missing_safety_doc)]
+pub trait SimdInt16: Copy + Sized {
+    /// The number of elements in the SIMD vector.
+    const LEN: usize;
+
+    /// The type of a vector of 32-bit integers with the same size as this
+    /// vector.
+    type Int32: SimdInt;
+
+    /// Return a new vector with all elements set to zero.
+    #[inline]
+    unsafe fn zero() -> Self {
+        Self::splat(0)
+    }
+
+    /// Broadcast `val` to all elements in a new vector.
+    unsafe fn splat(val: i16) -> Self;
+
+    /// Compute `self + rhs`.
+    unsafe fn add(self, rhs: Self) -> Self;
+
+    /// Compute `self - rhs`.
+    unsafe fn sub(self, rhs: Self) -> Self;
+
+    /// Compute the minimum of `self` and `rhs`.
+    unsafe fn min(self, rhs: Self) -> Self;
+
+    /// Compute the maximum of `self` and `rhs`.
+    unsafe fn max(self, rhs: Self) -> Self;
+
+    /// Sign-extend the elements in the low and high halves of this vector
+    /// to 32 bits.
+    unsafe fn widen(self) -> (Self::Int32, Self::Int32);
+
+    /// Narrow the elements of `lo` and `hi` to 16 bits, saturating values
+    /// which are out of range, and concatenate the results.
+    ///
+    /// This is the inverse of [`widen`](SimdInt16::widen).
+    unsafe fn narrow_saturate(lo: Self::Int32, hi: Self::Int32) -> Self;
+
+    /// Compute the dot product of each pair of adjacent elements in `self`
+    /// and `rhs`, and add the result to the corresponding element of `acc`.
+    ///
+    /// ie. `acc[i] + self[2 * i] * rhs[2 * i] + self[2 * i + 1] * rhs[2 * i + 1]`.
+    unsafe fn dot(self, rhs: Self, acc: Self::Int32) -> Self::Int32;
+
+    /// Load `Self::LEN` values from the memory address at `ptr`.
+    ///
+    /// Implementations must not require `ptr` to be aligned.
+    ///
+    /// Safety: The caller must ensure `ptr` points to at least `Self::LEN`
+    /// values.
+    unsafe fn load(ptr: *const i16) -> Self;
+
+    /// Store `Self::LEN` values to the memory address at `ptr`.
+    ///
+    /// Implementations must not require `ptr` to be aligned.
+    ///
+    /// Safety: The caller must ensure `ptr` points to a buffer with space for
+    /// at least `Self::LEN` values.
+    unsafe fn store(self, ptr: *mut i16);
+}
+
 /// Trait for SIMD vectors containing single-precision floats.
 #[allow(clippy::missing_safety_doc)]
 pub trait SimdFloat: SimdVal {
@@ -190,6 +344,9 @@ pub trait SimdFloat: SimdVal {
     /// Convert this float to an int with truncation.
     unsafe fn to_int_trunc(self) -> Self::Int;
 
+    /// Reinterpret the bits of each element as an int.
+    unsafe fn reinterpret_as_int(self) -> Self::Int;
+
     /// Compute `self * rhs`.
     unsafe fn mul(self, rhs: Self) -> Self;
 
@@ -205,6 +362,9 @@ pub trait SimdFloat: SimdVal {
     /// Compute a mask containing `self < rhs`.
     unsafe fn lt(self, rhs: Self) -> Self::Mask;
 
+    /// Compute the minimum of `self` and `rhs`.
+    unsafe fn min(self, rhs: Self) -> Self;
+
     /// Compute the maximum of `self` and `rhs`.
     unsafe fn max(self, rhs: Self) -> Self;
 
@@ -232,6 +392,55 @@ pub trait SimdFloat: SimdVal {
     /// differences in results depending on the architecture.
     unsafe fn sum(self) -> f32;
 
+    /// Return the maximum value of all the lanes in this vector.
+    ///
+    /// The result is unspecified if any lane is NaN.
+    #[inline]
+    unsafe fn reduce_max(self) -> f32 {
+        let mut elements = [0.; MAX_LEN];
+        self.store(elements.as_mut_ptr());
+        elements[..Self::LEN]
+            .iter()
+            .fold(f32::NEG_INFINITY, |max, x| max.max(*x))
+    }
+
+    /// Return the minimum value of all the lanes in this vector.
+    ///
+    /// The result is unspecified if any lane is NaN.
+    #[inline]
+    unsafe fn reduce_min(self) -> f32 {
+        let mut elements = [0.; MAX_LEN];
+        self.store(elements.as_mut_ptr());
+        elements[..Self::LEN]
+            .iter()
+            .fold(f32::INFINITY, |min, x| min.min(*x))
+    }
+
+    /// Return the index of the lane containing the maximum value in this
+    /// vector.
+    ///
+    /// If multiple lanes contain the maximum value, the lowest index is
+    /// returned. NaN lanes are ignored, unless all lanes are NaN, in which
+    /// case zero is returned.
+    #[inline]
+    unsafe fn reduce_argmax(self) -> usize {
+        let mut elements = [0.; MAX_LEN];
+        self.store(elements.as_mut_ptr());
+        reduce_arg_by(&elements[..Self::LEN], |x, best| x > best)
+    }
+
+    /// Return the index of the lane containing the minimum value in this
+    /// vector.
+    ///
+    /// See [`reduce_argmax`](SimdFloat::reduce_argmax) for handling of ties
+    /// and NaNs.
+    #[inline]
+    unsafe fn reduce_argmin(self) -> usize {
+        let mut elements = [0.; MAX_LEN];
+        self.store(elements.as_mut_ptr());
+        reduce_arg_by(&elements[..Self::LEN], |x, best| x < best)
+    }
+
     /// Load `Self::LEN` floats from the memory address at `ptr`.
     ///
     /// Implementations must not require `ptr` to be aligned.
@@ -283,3 +492,23 @@ pub trait SimdFloat: SimdVal {
         // Noop
     }
 }
+
+/// Return the index of the best non-NaN element in `xs`, where `better(x,
+/// best)` returns true if `x` should replace the current best value.
+///
+/// Returns zero if all elements are NaN.
+#[inline(always)]
+fn reduce_arg_by<F: Fn(f32, f32) -> bool>(xs: &[f32], better: F) -> usize {
+    let mut best: Option<(usize, f32)> = None;
+    for (i, &x) in xs.iter().enumerate() {
+        let is_better = match best {
+            _ if x.is_nan() => false,
+            Some((_, best_val)) => better(x, best_val),
+            None => true,
+        };
+        if is_better {
+            best = Some((i, x));
+        }
+    }
+    best.map(|(i, _)| i).unwrap_or(0)
+}
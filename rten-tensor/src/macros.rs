@@ -15,14 +15,28 @@
 /// tensor!([1, 2, 3]);
 /// Tensor::from([1, 2, 3]);
 ///
+/// // Create a 2D tensor with shape [2, 3] from nested array literals.
+/// tensor!([[1, 2, 3], [4, 5, 6]]);
+/// Tensor::from([[1, 2, 3], [4, 5, 6]]);
+///
 /// // Create a 3D tensor with shape [1, 2, 2] and elements [1, 2, 3, 4].
 /// tensor!((1, 2, 2); [1, 2, 3, 4]);
+/// tensor!([[[1, 2], [3, 4]]]);
 /// Tensor::from([[[1, 2], [3, 4]]]);
 /// Tensor::from([1, 2, 3, 4]).into_shape([1, 2, 2].as_slice());
 /// ```
 #[macro_export]
 #[deprecated(note = "Use `Tensor::from` or `Tensor::from_data` instead")]
 macro_rules! tensor {
+    // Nested array literal. The shape is inferred from the nesting, up to
+    // the maximum rank supported by `Tensor::from`.
+    [[$([$($row:tt)*]),+ $(,)?]] => {
+        {
+            use $crate::Tensor;
+            Tensor::from([$([$($row)*]),+])
+        }
+    };
+
     [[$($elem:expr),*]] => {
         {
             use $crate::Tensor;
@@ -75,14 +89,28 @@ macro_rules! tensor {
 /// ndtensor!([1, 2, 3]);
 /// NdTensor::from([1, 2, 3]);
 ///
+/// // Create a 2D tensor with shape [2, 3] from nested array literals.
+/// ndtensor!([[1, 2, 3], [4, 5, 6]]);
+/// NdTensor::from([[1, 2, 3], [4, 5, 6]]);
+///
 /// // Create a 3D tensor with shape [1, 2, 2] and elements [1, 2, 3, 4].
 /// ndtensor!((1, 2, 2); [1, 2, 3, 4]);
+/// ndtensor!([[[1, 2], [3, 4]]]);
 /// NdTensor::from([[[1, 2], [3, 4]]]);
 /// NdTensor::from([1, 2, 3, 4]).into_shape([1, 2, 2]);
 /// ```
 #[macro_export]
 #[deprecated(note = "Use `NdTensor::from` or `NdTensor::from_data` instead")]
 macro_rules! ndtensor {
+    // Nested array literal. The shape is inferred from the nesting, up to
+    // the maximum rank supported by `NdTensor::from`.
+    [[$([$($row:tt)*]),+ $(,)?]] => {
+        {
+            use $crate::NdTensor;
+            NdTensor::from([$([$($row)*]),+])
+        }
+    };
+
     [[$($elem:expr),*]] => {
         {
             use $crate::NdTensor;
@@ -149,6 +177,25 @@ mod tests {
         assert_eq!(x, Tensor::from_data(&[1, 2, 2], vec![1, 2, 3, 4]));
     }
 
+    #[test]
+    fn test_tensor_nested() {
+        let x = tensor!([[1, 2, 3], [4, 5, 6]]);
+        assert_eq!(x, Tensor::from_data(&[2, 3], vec![1, 2, 3, 4, 5, 6]));
+
+        // As above, but with trailing commas.
+        let x = tensor!([[1, 2, 3,], [4, 5, 6,],]);
+        assert_eq!(x, Tensor::from_data(&[2, 3], vec![1, 2, 3, 4, 5, 6]));
+
+        let x = tensor!([[[1, 2], [3, 4]], [[5, 6], [7, 8]]]);
+        assert_eq!(
+            x,
+            Tensor::from_data(&[2, 2, 2], (1..=8).collect::<Vec<_>>())
+        );
+
+        let x = tensor!([[[[1.], [2.]]]]);
+        assert_eq!(x, Tensor::from_data(&[1, 1, 2, 1], vec![1., 2.]));
+    }
+
     #[test]
     fn test_ndtensor_scalar() {
         let x = ndtensor!(5.);
@@ -170,4 +217,19 @@ mod tests {
         let x = ndtensor!((1, 2, 2); [1, 2, 3, 4,]);
         assert_eq!(x, NdTensor::from_data([1, 2, 2], vec![1, 2, 3, 4]));
     }
+
+    #[test]
+    fn test_ndtensor_nested() {
+        let x = ndtensor!([[1, 2, 3], [4, 5, 6]]);
+        assert_eq!(x, NdTensor::from_data([2, 3], vec![1, 2, 3, 4, 5, 6]));
+
+        let x = ndtensor!([[[1, 2], [3, 4]], [[5, 6], [7, 8]]]);
+        assert_eq!(
+            x,
+            NdTensor::from_data([2, 2, 2], (1..=8).collect::<Vec<_>>())
+        );
+
+        let x = ndtensor!([[[[1.], [2.]]]]);
+        assert_eq!(x, NdTensor::from_data([1, 1, 2, 1], vec![1., 2.]));
+    }
 }
@@ -30,6 +30,48 @@ impl XorShiftRng {
         let val = self.next_u64() >> (64 - n_bits);
         (val as f32) * scale
     }
+
+    /// Return a random value from a normal distribution with a given mean and
+    /// standard deviation.
+    ///
+    /// This uses the Box-Muller transform.
+    pub fn next_normal(&mut self, mean: f32, std_dev: f32) -> f32 {
+        // Map the first sample to (0, 1] to avoid taking the log of zero.
+        let u1 = 1.0 - self.next_f32().min(1.0 - f32::EPSILON);
+        let u2 = self.next_f32();
+        let z = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f32::consts::PI * u2).cos();
+        mean + std_dev * z
+    }
+
+    /// Choose a random index from `weights`, where the probability of
+    /// choosing index `i` is proportional to `weights[i]`.
+    ///
+    /// The weights do not need to sum to one. Returns `None` if `weights` is
+    /// empty or does not have a positive sum.
+    pub fn next_categorical(&mut self, weights: &[f32]) -> Option<usize> {
+        let total: f32 = weights.iter().sum();
+        if total.is_nan() || total <= 0. {
+            return None;
+        }
+
+        let threshold = self.next_f32() * total;
+        let mut cum_sum = 0.;
+        let mut last_nonzero = None;
+        for (i, &weight) in weights.iter().enumerate() {
+            if weight <= 0. {
+                continue;
+            }
+            cum_sum += weight;
+            last_nonzero = Some(i);
+            if threshold < cum_sum {
+                return Some(i);
+            }
+        }
+
+        // Rounding errors in the cumulative sum can leave `threshold` just
+        // above the final value.
+        last_nonzero
+    }
 }
 
 impl RandomSource<f32> for XorShiftRng {
@@ -37,3 +79,40 @@ impl RandomSource<f32> for XorShiftRng {
         self.next_f32()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::XorShiftRng;
+
+    #[test]
+    fn test_next_normal() {
+        let mut rng = XorShiftRng::new(1234);
+        let n = 10_000;
+        let samples: Vec<f32> = (0..n).map(|_| rng.next_normal(2., 0.5)).collect();
+
+        let mean = samples.iter().sum::<f32>() / n as f32;
+        let var = samples.iter().map(|x| (x - mean).powi(2)).sum::<f32>() / n as f32;
+
+        assert!((mean - 2.).abs() < 0.05, "mean {mean}");
+        assert!((var.sqrt() - 0.5).abs() < 0.05, "std dev {}", var.sqrt());
+        assert!(samples.iter().all(|x| x.is_finite()));
+    }
+
+    #[test]
+    fn test_next_categorical() {
+        let mut rng = XorShiftRng::new(1234);
+
+        assert_eq!(rng.next_categorical(&[]), None);
+        assert_eq!(rng.next_categorical(&[0., 0.]), None);
+        assert_eq!(rng.next_categorical(&[0., 3., 0.]), Some(1));
+
+        let weights = [1., 0., 3.];
+        let mut counts = [0; 3];
+        for _ in 0..4000 {
+            counts[rng.next_categorical(&weights).unwrap()] += 1;
+        }
+        assert_eq!(counts[1], 0);
+        let ratio = counts[2] as f32 / counts[0] as f32;
+        assert!((ratio - 3.).abs() < 0.4, "ratio {ratio}");
+    }
+}
@@ -116,6 +116,18 @@ pub trait AsView: Layout {
         self.view().broadcast(shape)
     }
 
+    /// Variant of [`broadcast`](AsView::broadcast) which returns an error
+    /// instead of panicking if this view cannot be broadcast to `shape`.
+    fn try_broadcast<S: IntoLayout>(
+        &self,
+        shape: S,
+    ) -> Result<TensorBase<ViewData<Self::Elem>, S::Layout>, ExpandError>
+    where
+        Self::Layout: BroadcastLayout<S::Layout>,
+    {
+        self.view().try_broadcast(shape)
+    }
+
     /// Return the layout of this tensor as a slice, if it is contiguous.
     fn data(&self) -> Option<&[Self::Elem]>;
 
@@ -1031,6 +1043,53 @@ impl<T, L: Clone + MutLayout> TensorBase<Vec<T>, L> {
         TensorBase::from_data(shape, data)
     }
 
+    /// Create a new 2D `n x n` identity matrix, with ones on the diagonal and
+    /// zeros elsewhere.
+    pub fn eye(n: usize) -> TensorBase<Vec<T>, L>
+    where
+        T: Clone + Default + From<bool>,
+        [usize; 2]: AsIndex<L>,
+    {
+        let mut data = vec![T::default(); n * n];
+        for i in 0..n {
+            data[i * n + i] = true.into();
+        }
+        TensorBase::from_data([n, n].as_index(), data)
+    }
+
+    /// Create a new 1D tensor with `steps` values evenly spaced over the
+    /// closed interval `[start, end]`.
+    ///
+    /// If `steps` is 1, the result contains only `start`.
+    pub fn linspace(start: T, end: T, steps: usize) -> TensorBase<Vec<T>, L>
+    where
+        T: Copy
+            + From<f32>
+            + std::ops::Add<Output = T>
+            + std::ops::Sub<Output = T>
+            + std::ops::Mul<Output = T>,
+        [usize; 1]: AsIndex<L>,
+    {
+        let data: Vec<T> = match steps {
+            0 => Vec::new(),
+            1 => vec![start],
+            _ => {
+                let range = end - start;
+                let denom = (steps - 1) as f32;
+                (0..steps)
+                    .map(|i| {
+                        if i == steps - 1 {
+                            end
+                        } else {
+                            start + range * T::from(i as f32 / denom)
+                        }
+                    })
+                    .collect()
+            }
+        };
+        TensorBase::from_data([data.len()].as_index(), data)
+    }
+
     /// Make the underlying data in this tensor contiguous.
     ///
     /// This means that after calling `make_contiguous`, the elements are
@@ -1280,6 +1339,23 @@ impl<'a, T, L: Clone + MutLayout> TensorBase<ViewData<'a, T>, L> {
         }
     }
 
+    /// Broadcast this view to another shape, or return an error if the
+    /// shapes are not compatible.
+    ///
+    /// See [AsView::try_broadcast].
+    pub fn try_broadcast<S: IntoLayout>(
+        &self,
+        shape: S,
+    ) -> Result<TensorBase<ViewData<'a, T>, S::Layout>, ExpandError>
+    where
+        L: BroadcastLayout<S::Layout>,
+    {
+        if !self.layout.can_broadcast_to(shape.as_ref()) {
+            return Err(ExpandError::ShapeMismatch);
+        }
+        Ok(self.broadcast(shape))
+    }
+
     /// Return the data in this tensor as a slice if it is contiguous, ie.
     /// the order of elements in the slice is the same as the logical order
     /// yielded by `iter`, and there are no gaps.
@@ -1982,6 +2058,79 @@ impl<T, S: Storage<Elem = T>> TensorBase<S, NdLayout<1>> {
     }
 }
 
+impl<T, S: Storage<Elem = T>> TensorBase<S, NdLayout<2>> {
+    /// Return a 1D view of the elements on a diagonal of this matrix.
+    ///
+    /// `offset` selects the diagonal. `0` is the main diagonal, positive
+    /// values select diagonals above it and negative values select diagonals
+    /// below it. If the offset is outside the matrix, the view is empty.
+    pub fn diagonal(&self, offset: isize) -> NdTensorView<'_, T, 1> {
+        let [rows, cols] = self.shape();
+        let [row_stride, col_stride] = self.strides();
+        let (row, col) = if offset >= 0 {
+            (0, offset as usize)
+        } else {
+            (offset.unsigned_abs(), 0)
+        };
+        let len = if row < rows && col < cols {
+            (rows - row).min(cols - col)
+        } else {
+            0
+        };
+        let start = if len > 0 {
+            self.layout.offset([row, col])
+        } else {
+            0
+        };
+        let layout = NdLayout::try_from_shape_and_strides(
+            [len],
+            [row_stride + col_stride],
+            OverlapPolicy::AllowOverlap,
+        )
+        .expect("invalid layout");
+        TensorBase {
+            data: self.data.slice(start..start + layout.min_data_len()),
+            layout,
+        }
+    }
+
+    /// Return a copy of this matrix with elements above the `offset`-th
+    /// diagonal set to zero.
+    ///
+    /// See [`diagonal`](TensorBase::diagonal) for the meaning of `offset`.
+    pub fn tril(&self, offset: isize) -> NdTensor<T, 2>
+    where
+        T: Clone + Default,
+    {
+        self.mask_triangle(|y, x| x as isize - y as isize <= offset)
+    }
+
+    /// Return a copy of this matrix with elements below the `offset`-th
+    /// diagonal set to zero.
+    ///
+    /// See [`diagonal`](TensorBase::diagonal) for the meaning of `offset`.
+    pub fn triu(&self, offset: isize) -> NdTensor<T, 2>
+    where
+        T: Clone + Default,
+    {
+        self.mask_triangle(|y, x| x as isize - y as isize >= offset)
+    }
+
+    fn mask_triangle<F: Fn(usize, usize) -> bool>(&self, keep: F) -> NdTensor<T, 2>
+    where
+        T: Clone + Default,
+    {
+        NdTensor::from_fn(self.shape(), |[y, x]| {
+            if keep(y, x) {
+                // Safety: `from_fn` only yields valid indices for our shape.
+                unsafe { self.get_unchecked([y, x]).clone() }
+            } else {
+                T::default()
+            }
+        })
+    }
+}
+
 impl<T, S: StorageMut<Elem = T>> TensorBase<S, NdLayout<1>> {
     /// Fill this vector with values from a static array of length `M`.
     ///
@@ -2175,6 +2324,28 @@ where
     }
 }
 
+impl<
+        T: Clone + Scalar,
+        L: MutLayout,
+        const D0: usize,
+        const D1: usize,
+        const D2: usize,
+        const D3: usize,
+    > From<[[[[T; D3]; D2]; D1]; D0]> for TensorBase<Vec<T>, L>
+where
+    [usize; 4]: AsIndex<L>,
+{
+    /// Construct a 4D tensor from a nested array.
+    fn from(value: [[[[T; D3]; D2]; D1]; D0]) -> Self {
+        let data: Vec<_> = value
+            .iter()
+            .flat_map(|y| y.iter().flat_map(|z| z.iter().flat_map(|w| w.iter())))
+            .cloned()
+            .collect();
+        Self::from_data([D0, D1, D2, D3].as_index(), data)
+    }
+}
+
 /// A view of a tensor which does "weak" checking when indexing via
 /// `view[<index>]`. This means that it does not bounds-check individual
 /// dimensions, but does bounds-check the computed offset.
@@ -2455,6 +2626,23 @@ mod tests {
         assert_eq!(view.to_vec(), expected_data);
     }
 
+    #[test]
+    fn test_try_broadcast() {
+        let tensor = NdTensor::from_data([1, 3], vec![1, 2, 3]);
+
+        let view = tensor.try_broadcast([2, 3]).unwrap();
+        assert_eq!(view.shape(), [2, 3]);
+        assert_eq!(view.strides(), [0, 1]);
+        assert_eq!(view.to_vec(), [1, 2, 3, 1, 2, 3]);
+
+        let view = tensor.try_broadcast([4, 2, 3].as_slice()).unwrap();
+        assert_eq!(view.shape(), [4, 2, 3]);
+        assert!(view.is_broadcast());
+
+        let err = tensor.try_broadcast([2, 2]);
+        assert_eq!(err.err(), Some(ExpandError::ShapeMismatch));
+    }
+
     #[test]
     fn test_clip_dim() {
         let mut tensor = NdTensor::arange(0, 9, None).into_shape([3, 3]);
@@ -2566,6 +2754,11 @@ mod tests {
         assert_eq!(x.shape(), [2, 2, 2]);
         assert_eq!(x.data(), Some([1, 2, 3, 4, 5, 6, 7, 8].as_slice()));
 
+        // 4D
+        let x = NdTensor::from([[[[1, 2], [3, 4]]], [[[5, 6], [7, 8]]]]);
+        assert_eq!(x.shape(), [2, 1, 2, 2]);
+        assert_eq!(x.data(), Some([1, 2, 3, 4, 5, 6, 7, 8].as_slice()));
+
         // Float
         let x = NdTensor::from([1., 2., 3.]);
         assert_eq!(x.shape(), [3]);
@@ -2609,6 +2802,42 @@ mod tests {
         assert_eq!(x.data(), Some([1, 2, 3].as_slice()));
     }
 
+    #[test]
+    fn test_diagonal() {
+        let x = NdTensor::from([[1, 2, 3], [4, 5, 6]]);
+
+        assert_eq!(x.diagonal(0).to_vec(), [1, 5]);
+        assert_eq!(x.diagonal(1).to_vec(), [2, 6]);
+        assert_eq!(x.diagonal(2).to_vec(), [3]);
+        assert_eq!(x.diagonal(3).shape(), [0]);
+        assert_eq!(x.diagonal(-1).to_vec(), [4]);
+        assert_eq!(x.diagonal(-2).shape(), [0]);
+
+        // Non-contiguous input.
+        let xt = x.transposed();
+        assert_eq!(xt.diagonal(0).to_vec(), [1, 5]);
+        assert_eq!(xt.diagonal(-1).to_vec(), [2, 6]);
+    }
+
+    #[test]
+    fn test_tril_triu() {
+        let x = NdTensor::from([[1, 2, 3], [4, 5, 6], [7, 8, 9]]);
+
+        assert_eq!(x.tril(0), NdTensor::from([[1, 0, 0], [4, 5, 0], [7, 8, 9]]));
+        assert_eq!(x.tril(1), NdTensor::from([[1, 2, 0], [4, 5, 6], [7, 8, 9]]));
+        assert_eq!(
+            x.tril(-1),
+            NdTensor::from([[0, 0, 0], [4, 0, 0], [7, 8, 0]])
+        );
+
+        assert_eq!(x.triu(0), NdTensor::from([[1, 2, 3], [0, 5, 6], [0, 0, 9]]));
+        assert_eq!(x.triu(1), NdTensor::from([[0, 2, 3], [0, 0, 6], [0, 0, 0]]));
+        assert_eq!(
+            x.triu(-1),
+            NdTensor::from([[1, 2, 3], [4, 5, 6], [0, 8, 9]])
+        );
+    }
+
     #[test]
     fn test_dyn_tensor_from_nd_tensor() {
         let x = NdTensor::from_data([2, 2], vec![1, 2, 3, 4]);
@@ -2703,6 +2932,38 @@ mod tests {
         assert_eq!(x.data(), Some([1, 2, 3, 4].as_slice()));
     }
 
+    #[test]
+    fn test_eye() {
+        let x = NdTensor::<f32, 2>::eye(3);
+        assert_eq!(x.shape(), [3, 3]);
+        assert_eq!(
+            x.data(),
+            Some([1., 0., 0., 0., 1., 0., 0., 0., 1.].as_slice())
+        );
+
+        let x = Tensor::<i32>::eye(2);
+        assert_eq!(x.shape(), [2, 2]);
+        assert_eq!(x.data(), Some([1, 0, 0, 1].as_slice()));
+
+        let x = NdTensor::<f32, 2>::eye(0);
+        assert_eq!(x.shape(), [0, 0]);
+    }
+
+    #[test]
+    fn test_linspace() {
+        let x = NdTensor::<f32, 1>::linspace(0., 1., 5);
+        assert_eq!(x.data(), Some([0., 0.25, 0.5, 0.75, 1.].as_slice()));
+
+        let x = Tensor::<f64>::linspace(2., -2., 3);
+        assert_eq!(x.data(), Some([2., 0., -2.].as_slice()));
+
+        let x = NdTensor::<f32, 1>::linspace(3., 5., 1);
+        assert_eq!(x.data(), Some([3.].as_slice()));
+
+        let x = NdTensor::<f32, 1>::linspace(3., 5., 0);
+        assert_eq!(x.shape(), [0]);
+    }
+
     #[test]
     fn test_full() {
         let tensor = NdTensor::full([2, 2], 2.);
@@ -144,13 +144,7 @@ pub fn expect_equal_with_tolerance<V: AsView>(
 where
     V::Elem: Clone + Debug + ApproxEq,
 {
-    if x.shape() != y.shape() {
-        return Err(ExpectEqualError::ShapeMismatch(format!(
-            "Tensors have different shapes. {:?} vs. {:?}",
-            x.shape(),
-            y.shape()
-        )));
-    }
+    check_same_shape(x, y)?;
 
     let mismatches: Vec<_> = zip(x.iter(), y.iter())
         .enumerate()
@@ -181,6 +175,101 @@ where
     }
 }
 
+fn check_same_shape<V: AsView>(x: &V, y: &V) -> Result<(), ExpectEqualError> {
+    if x.shape() != y.shape() {
+        return Err(ExpectEqualError::ShapeMismatch(format!(
+            "Tensors have different shapes. {:?} vs. {:?}",
+            x.shape(),
+            y.shape()
+        )));
+    }
+    Ok(())
+}
+
+/// Return true if `x` and `y` have the same shape and all elements satisfy
+/// [`ApproxEq::approx_eq_with_atol_rtol`].
+///
+/// This matches the behavior of `torch.allclose` and `np.allclose`.
+pub fn allclose<V: AsView>(x: &V, y: &V, rtol: V::Elem, atol: V::Elem) -> bool
+where
+    V::Elem: Clone + ApproxEq,
+{
+    x.shape() == y.shape()
+        && zip(x.iter(), y.iter())
+            .all(|(xi, yi)| xi.approx_eq_with_atol_rtol(yi, atol.clone(), rtol.clone()))
+}
+
+/// Summary of the differences between two float tensors, produced by
+/// [diff_report].
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiffReport {
+    /// Maximum absolute difference between corresponding elements.
+    pub max_abs_diff: f32,
+
+    /// Index of the element with the largest absolute difference.
+    pub max_abs_diff_index: Vec<usize>,
+
+    /// Maximum difference relative to the magnitude of the element in the
+    /// second tensor. Elements where the second tensor is zero are skipped.
+    pub max_rel_diff: f32,
+
+    /// Index of the element with the largest relative difference.
+    pub max_rel_diff_index: Vec<usize>,
+}
+
+impl Display for DiffReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "max abs diff {} at {:?}, max rel diff {} at {:?}",
+            self.max_abs_diff, self.max_abs_diff_index, self.max_rel_diff, self.max_rel_diff_index
+        )
+    }
+}
+
+/// Compare two float tensors and report the largest absolute and relative
+/// differences between them.
+///
+/// This is useful to explain a failed [allclose] check. NaN values in either
+/// tensor are treated as having an infinite difference.
+pub fn diff_report<V: AsView<Elem = f32>>(x: &V, y: &V) -> Result<DiffReport, ExpectEqualError> {
+    check_same_shape(x, y)?;
+
+    let mut max_abs = (0., 0);
+    let mut max_rel = (0., 0);
+    for (i, (&xi, &yi)) in zip(x.iter(), y.iter()).enumerate() {
+        let abs_diff = if xi.is_nan() || yi.is_nan() {
+            f32::INFINITY
+        } else {
+            (xi - yi).abs()
+        };
+        if abs_diff > max_abs.0 {
+            max_abs = (abs_diff, i);
+        }
+        if yi != 0. {
+            let rel_diff = abs_diff / yi.abs();
+            if rel_diff > max_rel.0 {
+                max_rel = (rel_diff, i);
+            }
+        }
+    }
+
+    let index = |lin_index| {
+        if x.is_empty() {
+            Vec::new()
+        } else {
+            index_from_linear_index(x.shape().as_ref(), lin_index)
+        }
+    };
+
+    Ok(DiffReport {
+        max_abs_diff: max_abs.0,
+        max_abs_diff_index: index(max_abs.1),
+        max_rel_diff: max_rel.0,
+        max_rel_diff_index: index(max_rel.1),
+    })
+}
+
 // Return true if `a` and `b` have the same shape and data, treating NaN
 // values as equal.
 pub fn eq_with_nans(a: TensorView, b: TensorView) -> bool {
@@ -193,7 +282,45 @@ pub fn eq_with_nans(a: TensorView, b: TensorView) -> bool {
 
 #[cfg(test)]
 mod tests {
-    use super::ApproxEq;
+    use super::{allclose, diff_report, ApproxEq, DiffReport};
+    use crate::NdTensor;
+
+    #[test]
+    fn test_allclose() {
+        let x = NdTensor::from([1., 2., 3.]);
+        let y = NdTensor::from([1., 2.001, 3.]);
+
+        assert!(allclose(&x, &x, 1e-5, 1e-8));
+        assert!(!allclose(&x, &y, 1e-5, 1e-8));
+        assert!(allclose(&x, &y, 1e-5, 1e-2));
+        assert!(allclose(&x, &y, 1e-3, 0.));
+
+        let z = NdTensor::from([1., 2.]);
+        assert!(!allclose(&x, &z, 1e-5, 1e-8));
+    }
+
+    #[test]
+    fn test_diff_report() {
+        let x = NdTensor::from([[1., 2.], [3., 4.]]);
+        let y = NdTensor::from([[1., 2.5], [2., 4.]]);
+
+        let report = diff_report(&x, &y).unwrap();
+        assert_eq!(
+            report,
+            DiffReport {
+                max_abs_diff: 1.,
+                max_abs_diff_index: vec![1, 0],
+                max_rel_diff: 0.5,
+                max_rel_diff_index: vec![1, 0],
+            }
+        );
+
+        let report = diff_report(&x, &x).unwrap();
+        assert_eq!(report.max_abs_diff, 0.);
+
+        let z = NdTensor::from([[1., 2.]]);
+        assert!(diff_report(&x, &z).is_err());
+    }
 
     #[test]
     fn test_approx_eq_i32() {
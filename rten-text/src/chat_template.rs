@@ -0,0 +1,1237 @@
+//! Chat templates which format a conversation into a prompt for a model.
+//!
+//! Chat models are trained with prompts that use a particular format to mark
+//! up the messages in a conversation. Hugging Face models specify this format
+//! using a [Jinja](https://jinja.palletsprojects.com/) template in the
+//! `chat_template` field of `tokenizer_config.json`. [ChatTemplate] supports
+//! the subset of Jinja that is used by these templates.
+
+use std::error::Error;
+use std::fmt;
+
+mod parse;
+mod value;
+
+use parse::{parse_template, Args, BinaryOp, Expr, Node};
+use value::Value;
+
+/// Errors that can occur when parsing or rendering a [ChatTemplate].
+#[derive(Debug)]
+pub enum ChatTemplateError {
+    /// There was an error decoding the JSON data.
+    JsonError(serde_json::Error),
+
+    /// The tokenizer configuration does not contain a chat template.
+    MissingTemplate,
+
+    /// The template has invalid syntax or uses unsupported features.
+    ParseError(String),
+
+    /// An error occurred while rendering the template, such as an operation
+    /// on values of the wrong type.
+    RenderError(String),
+
+    /// The template raised an error using `raise_exception`. This usually
+    /// means the messages are not valid for the model, eg. because roles do
+    /// not alternate between user and assistant.
+    Exception(String),
+}
+
+impl fmt::Display for ChatTemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::JsonError(err) => write!(f, "JSON error {}", err),
+            Self::MissingTemplate => write!(f, "missing chat template"),
+            Self::ParseError(msg) => write!(f, "template parse error: {}", msg),
+            Self::RenderError(msg) => write!(f, "template render error: {}", msg),
+            Self::Exception(msg) => write!(f, "template raised exception: {}", msg),
+        }
+    }
+}
+
+impl Error for ChatTemplateError {}
+
+/// Maximum number of items produced by `range`, as in Jinja's sandbox.
+const MAX_RANGE_LEN: usize = 100_000;
+
+/// Maximum length in bytes of a string produced by repetition (`str * n`).
+const MAX_REPEAT_LEN: usize = 1 << 20;
+
+fn render_error(msg: impl Into<String>) -> ChatTemplateError {
+    ChatTemplateError::RenderError(msg.into())
+}
+
+/// A message in a conversation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChatMessage {
+    /// The author of the message, eg. "system", "user" or "assistant".
+    pub role: String,
+
+    /// The text of the message.
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn new(role: &str, content: &str) -> ChatMessage {
+        ChatMessage {
+            role: role.to_string(),
+            content: content.to_string(),
+        }
+    }
+}
+
+/// Options for [ChatTemplate::render].
+#[derive(Clone, Debug, Default)]
+pub struct ChatTemplateOptions {
+    /// Add the tokens that start a response from the assistant to the end of
+    /// the prompt.
+    ///
+    /// This should be set when generating a response, so that the model
+    /// replies as the assistant rather than continuing the last message.
+    pub add_generation_prompt: bool,
+}
+
+/// Template which formats a list of messages into a prompt for a chat model.
+///
+/// The template is evaluated with the variables `messages`, a list of maps
+/// with `role` and `content` keys, and `add_generation_prompt`. Additional
+/// variables such as `bos_token` can be set using
+/// [ChatTemplate::with_variable].
+///
+/// Templates are rendered using the `trim_blocks` and `lstrip_blocks`
+/// settings that Hugging Face Transformers uses. The supported subset of
+/// Jinja includes `if`, `for` and `set` statements, `break` and `continue`,
+/// Python-like expressions and method calls on strings and mappings, common
+/// filters and tests, and the `raise_exception` and `namespace` functions.
+#[derive(Clone, Debug)]
+pub struct ChatTemplate {
+    nodes: Vec<Node>,
+    variables: Vec<(String, Value)>,
+}
+
+impl ChatTemplate {
+    /// Parse a chat template.
+    pub fn new(template: &str) -> Result<ChatTemplate, ChatTemplateError> {
+        Ok(ChatTemplate {
+            nodes: parse_template(template)?,
+            variables: Vec::new(),
+        })
+    }
+
+    /// Load the chat template from the contents of a Hugging Face
+    /// `tokenizer_config.json` file.
+    ///
+    /// Special tokens in the configuration, such as `bos_token` and
+    /// `eos_token`, are made available to the template as variables. If the
+    /// configuration contains several named templates, the one named
+    /// "default" is used.
+    pub fn from_tokenizer_config(json: &str) -> Result<ChatTemplate, ChatTemplateError> {
+        let config: serde_json::Value =
+            serde_json::from_str(json).map_err(ChatTemplateError::JsonError)?;
+
+        let template = match config.get("chat_template") {
+            Some(serde_json::Value::String(template)) => Some(template.as_str()),
+            Some(serde_json::Value::Array(templates)) => templates
+                .iter()
+                .find(|t| t.get("name").and_then(|n| n.as_str()) == Some("default"))
+                .and_then(|t| t.get("template"))
+                .and_then(|t| t.as_str()),
+            _ => None,
+        }
+        .ok_or(ChatTemplateError::MissingTemplate)?;
+
+        let mut chat_template = ChatTemplate::new(template)?;
+        for name in [
+            "bos_token",
+            "eos_token",
+            "unk_token",
+            "pad_token",
+            "cls_token",
+            "sep_token",
+            "mask_token",
+        ] {
+            // Tokens are either strings or objects with a `content` field.
+            let token = config.get(name).and_then(|token| match token {
+                serde_json::Value::String(content) => Some(content.as_str()),
+                serde_json::Value::Object(_) => token.get("content").and_then(|c| c.as_str()),
+                _ => None,
+            });
+            if let Some(token) = token {
+                chat_template = chat_template.with_variable(name, token);
+            }
+        }
+        Ok(chat_template)
+    }
+
+    /// Set a string variable which is available to the template, such as
+    /// `bos_token`.
+    pub fn with_variable(mut self, name: &str, value: &str) -> ChatTemplate {
+        self.variables.retain(|(n, _)| n != name);
+        self.variables.push((name.to_string(), value.into()));
+        self
+    }
+
+    /// Format a list of messages into a prompt.
+    ///
+    /// The result can be converted to token IDs using
+    /// [Tokenizer::encode](crate::tokenizers::Tokenizer::encode). Since chat
+    /// templates include special tokens as text, the tokenizer should be
+    /// configured with the model's added tokens, as it is when loaded using
+    /// [Tokenizer::from_json](crate::tokenizers::Tokenizer::from_json).
+    pub fn render(
+        &self,
+        messages: &[ChatMessage],
+        options: ChatTemplateOptions,
+    ) -> Result<String, ChatTemplateError> {
+        let messages = Value::List(
+            messages
+                .iter()
+                .map(|msg| {
+                    Value::map([
+                        ("role", msg.role.as_str().into()),
+                        ("content", msg.content.as_str().into()),
+                    ])
+                })
+                .collect(),
+        );
+
+        let mut globals = self.variables.clone();
+        globals.push(("messages".to_string(), messages));
+        globals.push((
+            "add_generation_prompt".to_string(),
+            options.add_generation_prompt.into(),
+        ));
+
+        let mut renderer = Renderer {
+            scopes: vec![globals],
+        };
+        let mut output = String::new();
+        renderer.render(&self.nodes, &mut output)?;
+        Ok(output)
+    }
+}
+
+/// Control flow result of rendering a list of nodes.
+enum Flow {
+    Normal,
+    Break,
+    Continue,
+}
+
+/// Evaluates a template's syntax tree.
+struct Renderer {
+    /// Stack of variable scopes. Each `for` loop iteration has its own scope.
+    scopes: Vec<Vec<(String, Value)>>,
+}
+
+impl Renderer {
+    fn render(&mut self, nodes: &[Node], out: &mut String) -> Result<Flow, ChatTemplateError> {
+        for node in nodes {
+            match node {
+                Node::Text(text) => out.push_str(text),
+                Node::Output(expr) => {
+                    let value = self.eval(expr)?;
+                    out.push_str(&value.to_string());
+                }
+                Node::If {
+                    branches,
+                    otherwise,
+                } => {
+                    let mut body = otherwise;
+                    for (cond, branch_body) in branches {
+                        if self.eval(cond)?.is_true() {
+                            body = branch_body;
+                            break;
+                        }
+                    }
+                    match self.render(body, out)? {
+                        Flow::Normal => {}
+                        flow => return Ok(flow),
+                    }
+                }
+                Node::For {
+                    targets,
+                    iter,
+                    filter,
+                    body,
+                    otherwise,
+                } => {
+                    let iter = self.eval(iter)?;
+                    let mut items = iter.iter_items().ok_or_else(|| {
+                        render_error(format!("cannot iterate over {}", iter.type_name()))
+                    })?;
+                    if let Some(filter) = filter {
+                        let mut filtered = Vec::new();
+                        for item in items {
+                            self.scopes.push(bind_targets(targets, item.clone())?);
+                            let keep = self.eval(filter);
+                            self.scopes.pop();
+                            if keep?.is_true() {
+                                filtered.push(item);
+                            }
+                        }
+                        items = filtered;
+                    }
+
+                    if items.is_empty() {
+                        self.render(otherwise, out)?;
+                        continue;
+                    }
+
+                    let len = items.len();
+                    for (i, item) in items.iter().enumerate() {
+                        let loop_var = Value::map([
+                            ("index", Value::Int(i as i64 + 1)),
+                            ("index0", Value::Int(i as i64)),
+                            ("revindex", Value::Int((len - i) as i64)),
+                            ("revindex0", Value::Int((len - i - 1) as i64)),
+                            ("first", Value::Bool(i == 0)),
+                            ("last", Value::Bool(i == len - 1)),
+                            ("length", Value::Int(len as i64)),
+                            (
+                                "previtem",
+                                i.checked_sub(1)
+                                    .map_or(Value::Undefined, |prev| items[prev].clone()),
+                            ),
+                            (
+                                "nextitem",
+                                items.get(i + 1).cloned().unwrap_or(Value::Undefined),
+                            ),
+                        ]);
+                        let mut scope = bind_targets(targets, item.clone())?;
+                        scope.push(("loop".to_string(), loop_var));
+
+                        self.scopes.push(scope);
+                        let flow = self.render(body, out);
+                        self.scopes.pop();
+
+                        match flow? {
+                            Flow::Break => break,
+                            Flow::Normal | Flow::Continue => {}
+                        }
+                    }
+                }
+                Node::Set { name, attr, value } => {
+                    let value = self.eval(value)?;
+                    match attr {
+                        None => {
+                            let scope = self.scopes.last_mut().expect("scope stack is empty");
+                            scope.retain(|(n, _)| n != name);
+                            scope.push((name.clone(), value));
+                        }
+                        Some(attr) => {
+                            let target = self
+                                .scopes
+                                .iter_mut()
+                                .rev()
+                                .find_map(|scope| {
+                                    scope.iter_mut().find(|(n, _)| n == name).map(|(_, v)| v)
+                                })
+                                .and_then(|target| target.get_mut(attr))
+                                .ok_or_else(|| {
+                                    render_error(format!("cannot set attribute of {}", name))
+                                })?;
+                            *target = value;
+                        }
+                    }
+                }
+                Node::Break => return Ok(Flow::Break),
+                Node::Continue => return Ok(Flow::Continue),
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn lookup(&self, name: &str) -> Value {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.iter().find(|(n, _)| n == name))
+            .map(|(_, value)| value.clone())
+            .unwrap_or(Value::Undefined)
+    }
+
+    fn eval(&self, expr: &Expr) -> Result<Value, ChatTemplateError> {
+        let value = match expr {
+            Expr::Literal(value) => value.clone(),
+            Expr::List(items) => Value::List(
+                items
+                    .iter()
+                    .map(|item| self.eval(item))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Expr::Map(entries) => {
+                let mut map = Vec::with_capacity(entries.len());
+                for (key, value) in entries {
+                    let Value::String(key) = self.eval(key)? else {
+                        return Err(render_error("mapping keys must be strings"));
+                    };
+                    map.push((key, self.eval(value)?));
+                }
+                Value::Map(map)
+            }
+            Expr::Var(name) => self.lookup(name),
+            Expr::Attr(value, attr) => {
+                let value = self.eval(value)?;
+                get_item(&value, &Value::String(attr.clone()))
+            }
+            Expr::Index(value, index) => get_item(&self.eval(value)?, &self.eval(index)?),
+            Expr::Slice {
+                value,
+                start,
+                end,
+                step,
+            } => {
+                let eval_int = |expr: &Option<Box<Expr>>| -> Result<_, ChatTemplateError> {
+                    match expr {
+                        Some(expr) => match self.eval(expr)? {
+                            Value::None => Ok(None),
+                            value => value
+                                .as_i64()
+                                .map(Some)
+                                .ok_or_else(|| render_error("slice indices must be integers")),
+                        },
+                        None => Ok(None),
+                    }
+                };
+                let (start, end, step) = (eval_int(start)?, eval_int(end)?, eval_int(step)?);
+                slice(&self.eval(value)?, start, end, step.unwrap_or(1))?
+            }
+            Expr::Call(callee, args) => match callee.as_ref() {
+                Expr::Attr(value, method) => {
+                    let value = self.eval(value)?;
+                    let args = self.eval_args(args)?;
+                    call_method(&value, method, &args)?
+                }
+                Expr::Var(name) => {
+                    let args = self.eval_args(args)?;
+                    call_function(name, &args)?
+                }
+                _ => return Err(render_error("expression is not callable")),
+            },
+            Expr::Filter(value, name, args) => {
+                let value = self.eval(value)?;
+                let args = self.eval_args(args)?;
+                apply_filter(value, name, &args)?
+            }
+            Expr::Test {
+                value,
+                name,
+                args,
+                negated,
+            } => {
+                let value = self.eval(value)?;
+                let args: Vec<Value> = args
+                    .iter()
+                    .map(|arg| self.eval(arg))
+                    .collect::<Result<_, _>>()?;
+                Value::Bool(apply_test(&value, name, &args)? != *negated)
+            }
+            Expr::Not(value) => Value::Bool(!self.eval(value)?.is_true()),
+            Expr::Neg(value) => match self.eval(value)? {
+                Value::Int(i) => Value::Int(-i),
+                Value::Float(f) => Value::Float(-f),
+                value => return Err(render_error(format!("cannot negate {}", value.type_name()))),
+            },
+            Expr::And(left, right) => {
+                let left = self.eval(left)?;
+                if left.is_true() {
+                    self.eval(right)?
+                } else {
+                    left
+                }
+            }
+            Expr::Or(left, right) => {
+                let left = self.eval(left)?;
+                if left.is_true() {
+                    left
+                } else {
+                    self.eval(right)?
+                }
+            }
+            Expr::Binary(op, left, right) => binary_op(*op, self.eval(left)?, self.eval(right)?)?,
+            Expr::Conditional {
+                cond,
+                then,
+                otherwise,
+            } => {
+                if self.eval(cond)?.is_true() {
+                    self.eval(then)?
+                } else if let Some(otherwise) = otherwise {
+                    self.eval(otherwise)?
+                } else {
+                    Value::Undefined
+                }
+            }
+        };
+        Ok(value)
+    }
+
+    fn eval_args(&self, args: &Args) -> Result<CallArgs, ChatTemplateError> {
+        Ok(CallArgs {
+            positional: args
+                .positional
+                .iter()
+                .map(|arg| self.eval(arg))
+                .collect::<Result<_, _>>()?,
+            keyword: args
+                .keyword
+                .iter()
+                .map(|(name, arg)| Ok((name.clone(), self.eval(arg)?)))
+                .collect::<Result<_, ChatTemplateError>>()?,
+        })
+    }
+}
+
+/// Evaluated arguments for a function, method or filter call.
+#[derive(Default)]
+struct CallArgs {
+    positional: Vec<Value>,
+    keyword: Vec<(String, Value)>,
+}
+
+impl CallArgs {
+    /// Get an argument by position or keyword.
+    fn get(&self, index: usize, name: &str) -> Option<&Value> {
+        self.positional.get(index).or_else(|| {
+            self.keyword
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, value)| value)
+        })
+    }
+
+    /// Get a string argument by position or keyword.
+    fn get_str(&self, index: usize, name: &str) -> Result<Option<&str>, ChatTemplateError> {
+        match self.get(index, name) {
+            None | Some(Value::None) => Ok(None),
+            Some(Value::String(s)) => Ok(Some(s)),
+            Some(value) => Err(render_error(format!(
+                "expected string argument but got {}",
+                value.type_name()
+            ))),
+        }
+    }
+}
+
+/// Bind the loop variables of a `for` statement to an item.
+fn bind_targets(
+    targets: &[String],
+    item: Value,
+) -> Result<Vec<(String, Value)>, ChatTemplateError> {
+    if let [target] = targets {
+        return Ok(vec![(target.clone(), item)]);
+    }
+    match item {
+        Value::List(items) if items.len() == targets.len() => {
+            Ok(targets.iter().cloned().zip(items).collect())
+        }
+        _ => Err(render_error("cannot unpack loop item")),
+    }
+}
+
+/// Resolve a negative index relative to the end of a sequence.
+fn resolve_index(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 { len as i64 + index } else { index };
+    (index >= 0 && (index as usize) < len).then_some(index as usize)
+}
+
+/// Look up an attribute or item of a value. Returns `Undefined` if it does
+/// not exist.
+fn get_item(value: &Value, key: &Value) -> Value {
+    let item = match (value, key) {
+        (Value::Map(_), Value::String(key)) => value.get(key).cloned(),
+        (Value::List(items), Value::Int(index)) => {
+            resolve_index(*index, items.len()).map(|i| items[i].clone())
+        }
+        (Value::String(s), Value::Int(index)) => {
+            let chars: Vec<char> = s.chars().collect();
+            resolve_index(*index, chars.len()).map(|i| Value::String(chars[i].into()))
+        }
+        _ => None,
+    };
+    item.unwrap_or(Value::Undefined)
+}
+
+/// Slice a list or string, as in Python.
+fn slice(
+    value: &Value,
+    start: Option<i64>,
+    end: Option<i64>,
+    step: i64,
+) -> Result<Value, ChatTemplateError> {
+    if step == 0 {
+        return Err(render_error("slice step cannot be zero"));
+    }
+
+    let slice_indices = |len: usize| -> Vec<usize> {
+        let len = len as i64;
+        let clamp = |index: i64, min: i64, max: i64| {
+            let index = if index < 0 { index + len } else { index };
+            index.clamp(min, max)
+        };
+        if step > 0 {
+            let start = start.map_or(0, |s| clamp(s, 0, len));
+            let end = end.map_or(len, |e| clamp(e, 0, len));
+            (start..end.max(start))
+                .step_by(step as usize)
+                .map(|i| i as usize)
+                .collect()
+        } else {
+            let start = start.map_or(len - 1, |s| clamp(s, -1, len - 1));
+            let end = end.map_or(-1, |e| clamp(e, -1, len - 1));
+            let mut indices = Vec::new();
+            let mut i = start;
+            while i > end {
+                indices.push(i as usize);
+                i += step;
+            }
+            indices
+        }
+    };
+
+    match value {
+        Value::List(items) => Ok(Value::List(
+            slice_indices(items.len())
+                .into_iter()
+                .map(|i| items[i].clone())
+                .collect(),
+        )),
+        Value::String(s) => {
+            let chars: Vec<char> = s.chars().collect();
+            Ok(Value::String(
+                slice_indices(chars.len())
+                    .into_iter()
+                    .map(|i| chars[i])
+                    .collect(),
+            ))
+        }
+        Value::Undefined => Ok(Value::Undefined),
+        _ => Err(render_error(format!("cannot slice {}", value.type_name()))),
+    }
+}
+
+fn binary_op(op: BinaryOp, left: Value, right: Value) -> Result<Value, ChatTemplateError> {
+    let type_error = |left: &Value, right: &Value| {
+        render_error(format!(
+            "unsupported operand types for {:?}: {} and {}",
+            op,
+            left.type_name(),
+            right.type_name()
+        ))
+    };
+
+    // Apply an arithmetic operation to integers, or floats if either operand
+    // is a float.
+    let arith = |int_op: fn(i64, i64) -> Option<i64>, float_op: fn(f64, f64) -> f64| {
+        if let (Value::Int(a), Value::Int(b)) = (&left, &right) {
+            return int_op(*a, *b)
+                .map(Value::Int)
+                .ok_or_else(|| render_error("integer overflow or division by zero"));
+        }
+        match (left.as_f64(), right.as_f64()) {
+            (Some(a), Some(b)) => Ok(Value::Float(float_op(a, b))),
+            _ => Err(type_error(&left, &right)),
+        }
+    };
+
+    let compare = |pred: fn(std::cmp::Ordering) -> bool| {
+        left.compare(&right)
+            .map(|ord| Value::Bool(pred(ord)))
+            .ok_or_else(|| type_error(&left, &right))
+    };
+
+    match op {
+        BinaryOp::Add => match (&left, &right) {
+            (Value::String(a), Value::String(b)) => Ok(Value::String(format!("{}{}", a, b))),
+            (Value::List(a), Value::List(b)) => {
+                Ok(Value::List(a.iter().chain(b).cloned().collect()))
+            }
+            _ => arith(i64::checked_add, |a, b| a + b),
+        },
+        BinaryOp::Sub => arith(i64::checked_sub, |a, b| a - b),
+        BinaryOp::Mul => match (&left, &right) {
+            (Value::String(s), Value::Int(n)) | (Value::Int(n), Value::String(s)) => {
+                let n = usize::try_from((*n).max(0)).unwrap_or(usize::MAX);
+                match s.len().checked_mul(n) {
+                    Some(len) if len <= MAX_REPEAT_LEN => Ok(Value::String(s.repeat(n))),
+                    _ => Err(render_error("repeated string is too long")),
+                }
+            }
+            _ => arith(i64::checked_mul, |a, b| a * b),
+        },
+        BinaryOp::Div => match (left.as_f64(), right.as_f64()) {
+            (Some(_), Some(0.)) => Err(render_error("division by zero")),
+            (Some(a), Some(b)) => Ok(Value::Float(a / b)),
+            _ => Err(type_error(&left, &right)),
+        },
+        BinaryOp::FloorDiv => arith(
+            |a, b| {
+                let quotient = a.checked_div(b)?;
+                let round_down = a % b != 0 && (a < 0) != (b < 0);
+                Some(quotient - i64::from(round_down))
+            },
+            |a, b| (a / b).floor(),
+        ),
+        BinaryOp::Mod => arith(
+            |a, b| Some((a.checked_rem(b)? + b) % b),
+            |a, b| a - b * (a / b).floor(),
+        ),
+        BinaryOp::Concat => Ok(Value::String(format!("{}{}", left, right))),
+        BinaryOp::Eq => Ok(Value::Bool(left.loose_eq(&right))),
+        BinaryOp::Ne => Ok(Value::Bool(!left.loose_eq(&right))),
+        BinaryOp::Lt => compare(|ord| ord.is_lt()),
+        BinaryOp::Le => compare(|ord| ord.is_le()),
+        BinaryOp::Gt => compare(|ord| ord.is_gt()),
+        BinaryOp::Ge => compare(|ord| ord.is_ge()),
+        BinaryOp::In | BinaryOp::NotIn => {
+            let contains = right
+                .contains(&left)
+                .ok_or_else(|| type_error(&left, &right))?;
+            Ok(Value::Bool(contains == (op == BinaryOp::In)))
+        }
+    }
+}
+
+/// Call a global function.
+fn call_function(name: &str, args: &CallArgs) -> Result<Value, ChatTemplateError> {
+    match name {
+        "raise_exception" => {
+            let msg = args.get(0, "message").map(|msg| msg.to_string());
+            Err(ChatTemplateError::Exception(msg.unwrap_or_default()))
+        }
+        "namespace" => Ok(Value::Map(args.keyword.clone())),
+        "range" => {
+            let ints: Vec<i64> = args
+                .positional
+                .iter()
+                .map(|arg| arg.as_i64())
+                .collect::<Option<_>>()
+                .ok_or_else(|| render_error("range arguments must be integers"))?;
+            let (start, end, step) = match ints[..] {
+                [end] => (0, end, 1),
+                [start, end] => (start, end, 1),
+                [start, end, step] if step != 0 => (start, end, step),
+                _ => return Err(render_error("invalid range arguments")),
+            };
+            let mut items = Vec::new();
+            let mut i = start;
+            while (step > 0 && i < end) || (step < 0 && i > end) {
+                if items.len() >= MAX_RANGE_LEN {
+                    return Err(render_error("range is too large"));
+                }
+                items.push(Value::Int(i));
+                // Overflow means the next value would be past the end.
+                let Some(next) = i.checked_add(step) else {
+                    break;
+                };
+                i = next;
+            }
+            Ok(Value::List(items))
+        }
+        _ => Err(render_error(format!("unknown function \"{}\"", name))),
+    }
+}
+
+/// Call a method of a string or mapping.
+fn call_method(value: &Value, method: &str, args: &CallArgs) -> Result<Value, ChatTemplateError> {
+    let unknown_method = || {
+        render_error(format!(
+            "unknown method \"{}\" for {}",
+            method,
+            value.type_name()
+        ))
+    };
+
+    match value {
+        Value::String(s) => {
+            let result: Value = match method {
+                "strip" | "lstrip" | "rstrip" => {
+                    let chars = args.get_str(0, "chars")?;
+                    let is_strip_char = |ch: char| match chars {
+                        Some(chars) => chars.contains(ch),
+                        None => ch.is_whitespace(),
+                    };
+                    match method {
+                        "strip" => s.trim_matches(is_strip_char),
+                        "lstrip" => s.trim_start_matches(is_strip_char),
+                        _ => s.trim_end_matches(is_strip_char),
+                    }
+                    .into()
+                }
+                "upper" => s.to_uppercase().into(),
+                "lower" => s.to_lowercase().into(),
+                "title" => title_case(s).into(),
+                "capitalize" => capitalize(s).into(),
+                "startswith" | "endswith" => {
+                    let affixes = match args.get(0, "prefix") {
+                        Some(Value::String(affix)) => vec![affix.clone()],
+                        Some(Value::List(items)) => items.iter().map(|i| i.to_string()).collect(),
+                        _ => return Err(render_error(format!("invalid argument for {}", method))),
+                    };
+                    let matches = affixes.iter().any(|affix| {
+                        if method == "startswith" {
+                            s.starts_with(affix.as_str())
+                        } else {
+                            s.ends_with(affix.as_str())
+                        }
+                    });
+                    matches.into()
+                }
+                "split" => {
+                    let max_split = match args.get(1, "maxsplit") {
+                        Some(Value::Int(n)) if *n >= 0 => Some(*n as usize),
+                        _ => None,
+                    };
+                    let parts: Vec<&str> = match (args.get_str(0, "sep")?, max_split) {
+                        (Some(sep), Some(n)) => s.splitn(n + 1, sep).collect(),
+                        (Some(sep), None) => s.split(sep).collect(),
+                        (None, Some(n)) => {
+                            let mut parts = Vec::new();
+                            let mut rest = s.trim_start();
+                            while parts.len() < n && !rest.is_empty() {
+                                match rest.find(char::is_whitespace) {
+                                    Some(pos) => {
+                                        parts.push(&rest[..pos]);
+                                        rest = rest[pos..].trim_start();
+                                    }
+                                    None => {
+                                        parts.push(rest);
+                                        rest = "";
+                                    }
+                                }
+                            }
+                            if !rest.is_empty() {
+                                parts.push(rest);
+                            }
+                            parts
+                        }
+                        (None, None) => s.split_whitespace().collect(),
+                    };
+                    Value::List(parts.into_iter().map(Value::from).collect())
+                }
+                "replace" => {
+                    let (Some(old), Some(new)) = (args.get_str(0, "old")?, args.get_str(1, "new")?)
+                    else {
+                        return Err(render_error("replace requires two string arguments"));
+                    };
+                    match args.get(2, "count").and_then(|c| c.as_i64()) {
+                        Some(count) if count >= 0 => s.replacen(old, new, count as usize).into(),
+                        _ => s.replace(old, new).into(),
+                    }
+                }
+                "join" => {
+                    let items = args
+                        .get(0, "iterable")
+                        .and_then(|items| items.iter_items())
+                        .ok_or_else(|| render_error("join requires an iterable argument"))?;
+                    let items: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+                    items.join(s).into()
+                }
+                _ => return Err(unknown_method()),
+            };
+            Ok(result)
+        }
+        Value::Map(entries) => match method {
+            "items" => Ok(Value::List(
+                entries
+                    .iter()
+                    .map(|(k, v)| Value::List(vec![k.as_str().into(), v.clone()]))
+                    .collect(),
+            )),
+            "keys" => Ok(Value::List(
+                entries.iter().map(|(k, _)| k.as_str().into()).collect(),
+            )),
+            "values" => Ok(Value::List(
+                entries.iter().map(|(_, v)| v.clone()).collect(),
+            )),
+            "get" => {
+                let key = args
+                    .get(0, "key")
+                    .ok_or_else(|| render_error("get requires a key"))?;
+                let default = args.get(1, "default").cloned().unwrap_or(Value::None);
+                Ok(match get_item(value, key) {
+                    Value::Undefined => default,
+                    item => item,
+                })
+            }
+            _ => Err(unknown_method()),
+        },
+        _ => Err(unknown_method()),
+    }
+}
+
+/// Apply a filter (`value | name(args)`).
+fn apply_filter(value: Value, name: &str, args: &CallArgs) -> Result<Value, ChatTemplateError> {
+    let type_error = |value: &Value| {
+        render_error(format!(
+            "filter \"{}\" cannot be applied to {}",
+            name,
+            value.type_name()
+        ))
+    };
+    let items = |value: &Value| value.iter_items().ok_or_else(|| type_error(value));
+
+    let result = match name {
+        "trim" => match &value {
+            Value::String(_) => call_method(&value, "strip", args)?,
+            _ => return Err(type_error(&value)),
+        },
+        "length" | "count" => {
+            let len = value.len().ok_or_else(|| type_error(&value))?;
+            Value::Int(len as i64)
+        }
+        "tojson" => {
+            let indent = args
+                .get(0, "indent")
+                .and_then(|i| i.as_i64())
+                .map(|i| i.max(0) as usize);
+            Value::String(value.to_json(indent))
+        }
+        "string" => Value::String(value.to_string()),
+        "upper" | "lower" | "title" | "capitalize" => call_method(
+            &Value::String(value.to_string()),
+            name,
+            &CallArgs::default(),
+        )?,
+        "int" => match &value {
+            Value::Int(_) => value,
+            Value::Float(f) => Value::Int(*f as i64),
+            Value::Bool(b) => Value::Int(*b as i64),
+            Value::String(s) => Value::Int(s.trim().parse().unwrap_or(0)),
+            _ => Value::Int(0),
+        },
+        "float" => match &value {
+            Value::String(s) => Value::Float(s.trim().parse().unwrap_or(0.)),
+            _ => Value::Float(value.as_f64().unwrap_or(0.)),
+        },
+        "abs" => match value {
+            Value::Int(i) => Value::Int(i.abs()),
+            Value::Float(f) => Value::Float(f.abs()),
+            _ => return Err(type_error(&value)),
+        },
+        "default" | "d" => {
+            let default = args.get(0, "default_value").cloned().unwrap_or_default();
+            let boolean = args.get(1, "boolean").is_some_and(|b| b.is_true());
+            let use_default = match value {
+                Value::Undefined => true,
+                _ => boolean && !value.is_true(),
+            };
+            if use_default {
+                default
+            } else {
+                value
+            }
+        }
+        "first" => items(&value)?.into_iter().next().unwrap_or_default(),
+        "last" => items(&value)?.pop().unwrap_or_default(),
+        "list" => Value::List(items(&value)?),
+        "reverse" => match value {
+            Value::String(s) => Value::String(s.chars().rev().collect()),
+            _ => Value::List(items(&value)?.into_iter().rev().collect()),
+        },
+        "items" => call_method(&value, "items", &CallArgs::default())?,
+        "join" => {
+            let sep = args.get_str(0, "d")?.unwrap_or("");
+            let items: Vec<String> = items(&value)?.iter().map(|i| i.to_string()).collect();
+            Value::String(items.join(sep))
+        }
+        "replace" => call_method(&Value::String(value.to_string()), "replace", args)?,
+        "map" => {
+            let attr = args
+                .keyword
+                .iter()
+                .find(|(name, _)| name == "attribute")
+                .map(|(_, attr)| attr.clone())
+                .ok_or_else(|| render_error("map requires an \"attribute\" argument"))?;
+            Value::List(
+                items(&value)?
+                    .iter()
+                    .map(|item| get_item(item, &attr))
+                    .collect(),
+            )
+        }
+        "selectattr" | "rejectattr" => {
+            let attr = args
+                .get_str(0, "attr")?
+                .ok_or_else(|| render_error(format!("{} requires an attribute name", name)))?;
+            let attr = Value::String(attr.to_string());
+            let test = args.get_str(1, "test")?;
+            let test_args = args.positional.get(2..).unwrap_or_default();
+
+            let mut selected = Vec::new();
+            for item in items(&value)? {
+                let attr_value = get_item(&item, &attr);
+                let passed = match test {
+                    Some(test) => apply_test(&attr_value, test, test_args)?,
+                    None => attr_value.is_true(),
+                };
+                if passed == (name == "selectattr") {
+                    selected.push(item);
+                }
+            }
+            Value::List(selected)
+        }
+        "safe" => value,
+        _ => return Err(render_error(format!("unknown filter \"{}\"", name))),
+    };
+    Ok(result)
+}
+
+/// Apply a test (`value is name(args)`).
+fn apply_test(value: &Value, name: &str, args: &[Value]) -> Result<bool, ChatTemplateError> {
+    let arg = || {
+        args.first()
+            .ok_or_else(|| render_error(format!("test \"{}\" requires an argument", name)))
+    };
+
+    let result = match name {
+        "defined" => !matches!(value, Value::Undefined),
+        "undefined" => matches!(value, Value::Undefined),
+        "none" => matches!(value, Value::None),
+        "boolean" => matches!(value, Value::Bool(_)),
+        "true" => matches!(value, Value::Bool(true)),
+        "false" => matches!(value, Value::Bool(false)),
+        "string" => matches!(value, Value::String(_)),
+        "number" => matches!(value, Value::Int(_) | Value::Float(_)),
+        "integer" => matches!(value, Value::Int(_)),
+        "float" => matches!(value, Value::Float(_)),
+        "mapping" => matches!(value, Value::Map(_)),
+        "sequence" | "iterable" => {
+            matches!(value, Value::List(_) | Value::String(_) | Value::Map(_))
+        }
+        "even" | "odd" => {
+            let i = value
+                .as_i64()
+                .ok_or_else(|| render_error(format!("test \"{}\" requires an integer", name)))?;
+            (i % 2 == 0) == (name == "even")
+        }
+        "divisibleby" => match (value.as_i64(), arg()?.as_i64()) {
+            (Some(_), Some(0)) => return Err(render_error("division by zero")),
+            (Some(a), Some(b)) => a % b == 0,
+            _ => return Err(render_error("divisibleby requires integers")),
+        },
+        "eq" | "equalto" | "==" => value.loose_eq(arg()?),
+        "ne" | "!=" => !value.loose_eq(arg()?),
+        "in" => arg()?.contains(value).unwrap_or(false),
+        "lower" => matches!(value, Value::String(s) if s.to_lowercase() == *s),
+        "upper" => matches!(value, Value::String(s) if s.to_uppercase() == *s),
+        _ => return Err(render_error(format!("unknown test \"{}\"", name))),
+    };
+    Ok(result)
+}
+
+/// Uppercase the first character of a string and lowercase the rest.
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(first) => first
+            .to_uppercase()
+            .chain(chars.flat_map(char::to_lowercase))
+            .collect(),
+        None => String::new(),
+    }
+}
+
+/// Uppercase the first character of each word and lowercase the rest.
+fn title_case(s: &str) -> String {
+    let mut prev_alpha = false;
+    s.chars()
+        .flat_map(|ch| {
+            let mapped: Vec<char> = if prev_alpha {
+                ch.to_lowercase().collect()
+            } else {
+                ch.to_uppercase().collect()
+            };
+            prev_alpha = ch.is_alphanumeric();
+            mapped
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChatMessage, ChatTemplate, ChatTemplateError, ChatTemplateOptions};
+
+    fn render(template: &str, messages: &[ChatMessage]) -> Result<String, ChatTemplateError> {
+        ChatTemplate::new(template)?.render(messages, ChatTemplateOptions::default())
+    }
+
+    #[test]
+    fn test_render_qwen2() {
+        let config = r#"{
+            "chat_template": "{% for message in messages %}{% if loop.first and messages[0]['role'] != 'system' %}{{ '<|im_start|>system\nYou are a helpful assistant.<|im_end|>\n' }}{% endif %}{{'<|im_start|>' + message['role'] + '\n' + message['content'] + '<|im_end|>' + '\n'}}{% endfor %}{% if add_generation_prompt %}{{ '<|im_start|>assistant\n' }}{% endif %}",
+            "eos_token": "<|im_end|>"
+        }"#;
+        let template = ChatTemplate::from_tokenizer_config(config).unwrap();
+        let messages = [ChatMessage::new("user", "Hello")];
+
+        let prompt = template
+            .render(&messages, ChatTemplateOptions::default())
+            .unwrap();
+        assert_eq!(
+            prompt,
+            "<|im_start|>system\nYou are a helpful assistant.<|im_end|>\n<|im_start|>user\nHello<|im_end|>\n"
+        );
+
+        let prompt = template
+            .render(
+                &messages,
+                ChatTemplateOptions {
+                    add_generation_prompt: true,
+                },
+            )
+            .unwrap();
+        assert!(prompt.ends_with("<|im_end|>\n<|im_start|>assistant\n"));
+    }
+
+    #[test]
+    fn test_render_llama2() {
+        // Template which uses a system message, `raise_exception`, `strip`
+        // and special token variables.
+        let template = r#"
+{%- if messages[0]['role'] == 'system' -%}
+    {%- set loop_messages = messages[1:] -%}
+    {%- set system_message = messages[0]['content'] -%}
+{%- else -%}
+    {%- set loop_messages = messages -%}
+    {%- set system_message = false -%}
+{%- endif -%}
+{%- for message in loop_messages -%}
+    {%- if (message['role'] == 'user') != (loop.index0 % 2 == 0) -%}
+        {{- raise_exception('Conversation roles must alternate user/assistant/user/assistant/...') -}}
+    {%- endif -%}
+    {%- if loop.index0 == 0 and system_message != false -%}
+        {%- set content = '<<SYS>>\n' + system_message + '\n<</SYS>>\n\n' + message['content'] -%}
+    {%- else -%}
+        {%- set content = message['content'] -%}
+    {%- endif -%}
+    {%- if message['role'] == 'user' -%}
+        {{- bos_token + '[INST] ' + content.strip() + ' [/INST]' -}}
+    {%- elif message['role'] == 'assistant' -%}
+        {{- ' '  + content.strip() + ' ' + eos_token -}}
+    {%- endif -%}
+{%- endfor -%}
+"#;
+        let template = ChatTemplate::new(template)
+            .unwrap()
+            .with_variable("bos_token", "<s>")
+            .with_variable("eos_token", "</s>");
+
+        let messages = [
+            ChatMessage::new("system", "Be brief."),
+            ChatMessage::new("user", "Hi"),
+            ChatMessage::new("assistant", "Hello!"),
+            ChatMessage::new("user", " Bye "),
+        ];
+        let prompt = template
+            .render(&messages, ChatTemplateOptions::default())
+            .unwrap();
+        assert_eq!(
+            prompt,
+            "<s>[INST] <<SYS>>\nBe brief.\n<</SYS>>\n\nHi [/INST] Hello! </s><s>[INST] Bye [/INST]"
+        );
+
+        let messages = [
+            ChatMessage::new("user", "Hi"),
+            ChatMessage::new("user", "Hi again"),
+        ];
+        let result = template.render(&messages, ChatTemplateOptions::default());
+        assert!(
+            matches!(result, Err(ChatTemplateError::Exception(msg)) if msg.starts_with("Conversation roles"))
+        );
+    }
+
+    #[test]
+    fn test_expressions() {
+        let messages = [
+            ChatMessage::new("system", "sys"),
+            ChatMessage::new("user", "a"),
+            ChatMessage::new("assistant", "b"),
+        ];
+
+        let cases = [
+            ("{{ 1 + 2 * 3 }}", "7"),
+            ("{{ 7 // 2 }} {{ -7 // 2 }} {{ 7 % 3 }} {{ 1 / 2 }}", "3 -4 1 0.5"),
+            ("{{ 'a' ~ 1 ~ none }}", "a1None"),
+            ("{{ 'x' if true else 'y' }}{{ 'z' if false }}", "x"),
+            ("{{ not 1 == 2 and 'b' in 'abc' }}", "True"),
+            ("{{ 'd' not in ['a', 'b'] }}", "True"),
+            ("{{ messages | length }}", "3"),
+            ("{{ messages[-1].content }}", "b"),
+            ("{{ messages[::-1] | map(attribute='role') | join(',') }}", "assistant,user,system"),
+            ("{{ (messages | selectattr('role', 'equalto', 'user') | first).content }}", "a"),
+            ("{{ messages[0].missing is defined }} {{ undefined_var is undefined }}", "False True"),
+            ("{{ {'a': [1, 'x'], 'b': none} | tojson }}", r#"{"a": [1, "x"], "b": null}"#),
+            ("{{ [1, 'x'] }} {{ 1.0 }}", "[1, 'x'] 1.0"),
+            ("{{ '  Hi  ' | trim | upper }}", "HI"),
+            ("{{ 'a,b,c'.split(',')[1:] }}", "['b', 'c']"),
+            ("{{ 'hello world'.title() }} {{ 'x'.startswith(('y', 'x')) }}", "Hello World True"),
+            ("{{ {'k': 'v'}.get('k') }}{{ {'k': 'v'}.get('z', 'd') }}", "vd"),
+            ("{{ undefined_var | default('fallback') }}", "fallback"),
+            (
+                "{% set ns = namespace(found=false) %}{% for m in messages %}{% if m.role == 'user' %}{% set ns.found = true %}{% endif %}{% endfor %}{{ ns.found }}",
+                "True",
+            ),
+            (
+                "{% for m in messages if m.role != 'system' %}{{ loop.index }}{{ m.content }}{% endfor %}",
+                "1a2b",
+            ),
+            (
+                "{% for m in messages %}{% if loop.index0 == 2 %}{% break %}{% endif %}{% if loop.first %}{% continue %}{% endif %}{{ m.role }}{% endfor %}",
+                "user",
+            ),
+            ("{% for x in [] %}x{% else %}empty{% endfor %}", "empty"),
+            ("{% for k, v in {'a': 1, 'b': 2}.items() %}{{ k }}={{ v }};{% endfor %}", "a=1;b=2;"),
+            ("{% for i in range(3) %}{{ i }}{% endfor %}", "012"),
+            ("{{ range(9223372036854775806, 9223372036854775807, 5) }}", "[9223372036854775806]"),
+            ("{{ 'ab' * 3 }}{{ 'x' * -1 }}", "ababab"),
+            // Variables set inside a loop are scoped to the loop.
+            ("{% set x = 1 %}{% for m in messages %}{% set x = 2 %}{% endfor %}{{ x }}", "1"),
+        ];
+
+        for (template, expected) in cases {
+            let output = render(template, &messages)
+                .unwrap_or_else(|err| panic!("failed to render \"{}\": {}", template, err));
+            assert_eq!(output, expected, "mismatch for \"{}\"", template);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let parse_errors = [
+            "{% if true %}",
+            "{{ 1 + }}",
+            "{% macro foo() %}{% endmacro %}",
+            "{{ 'unterminated }}",
+            "{% for x in %}{% endfor %}",
+        ];
+        for template in parse_errors {
+            let result = ChatTemplate::new(template);
+            assert!(
+                matches!(result, Err(ChatTemplateError::ParseError(_))),
+                "expected parse error for \"{}\"",
+                template
+            );
+        }
+
+        let render_errors = [
+            "{{ 1 + 'a' }}",
+            "{{ unknown_function() }}",
+            "{{ 1 | nope }}",
+            "{{ range(1000000000) }}",
+            "{{ 'ab' * 9223372036854775807 }}",
+        ];
+        for template in render_errors {
+            let result = render(template, &[]);
+            assert!(
+                matches!(result, Err(ChatTemplateError::RenderError(_))),
+                "expected render error for \"{}\"",
+                template
+            );
+        }
+
+        let result = ChatTemplate::from_tokenizer_config("{}");
+        assert!(matches!(result, Err(ChatTemplateError::MissingTemplate)));
+    }
+}
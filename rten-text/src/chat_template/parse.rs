@@ -0,0 +1,939 @@
+use super::value::Value;
+use super::ChatTemplateError;
+
+/// Node in the syntax tree of a template.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Node {
+    /// Literal text.
+    Text(String),
+
+    /// `{{ expr }}`
+    Output(Expr),
+
+    /// `{% if cond %}...{% elif cond %}...{% else %}...{% endif %}`
+    If {
+        branches: Vec<(Expr, Vec<Node>)>,
+        otherwise: Vec<Node>,
+    },
+
+    /// `{% for targets in iter if filter %}...{% else %}...{% endfor %}`
+    For {
+        targets: Vec<String>,
+        iter: Expr,
+        filter: Option<Expr>,
+        body: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+
+    /// `{% set name = value %}` or `{% set name.attr = value %}`
+    Set {
+        name: String,
+        attr: Option<String>,
+        value: Expr,
+    },
+
+    /// `{% break %}`
+    Break,
+
+    /// `{% continue %}`
+    Continue,
+}
+
+/// Binary operator in an [Expr].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    FloorDiv,
+    Mod,
+    Concat,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    In,
+    NotIn,
+}
+
+/// Arguments for a function, method, filter or test call.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Args {
+    pub positional: Vec<Expr>,
+    pub keyword: Vec<(String, Expr)>,
+}
+
+/// Expression in a template.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Expr {
+    Literal(Value),
+    List(Vec<Expr>),
+    Map(Vec<(Expr, Expr)>),
+    Var(String),
+
+    /// `value.attr`
+    Attr(Box<Expr>, String),
+
+    /// `value[index]`
+    Index(Box<Expr>, Box<Expr>),
+
+    /// `value[start:end:step]`
+    Slice {
+        value: Box<Expr>,
+        start: Option<Box<Expr>>,
+        end: Option<Box<Expr>>,
+        step: Option<Box<Expr>>,
+    },
+
+    /// `func(args)` or `value.method(args)`
+    Call(Box<Expr>, Args),
+
+    /// `value | filter(args)`
+    Filter(Box<Expr>, String, Args),
+
+    /// `value is [not] test(args)`
+    Test {
+        value: Box<Expr>,
+        name: String,
+        args: Vec<Expr>,
+        negated: bool,
+    },
+
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+
+    /// `then if cond else otherwise`
+    Conditional {
+        cond: Box<Expr>,
+        then: Box<Expr>,
+        otherwise: Option<Box<Expr>>,
+    },
+}
+
+fn parse_error(msg: impl Into<String>) -> ChatTemplateError {
+    ChatTemplateError::ParseError(msg.into())
+}
+
+/// Section of a template source, before statements are parsed.
+#[derive(Debug, PartialEq)]
+enum Segment<'a> {
+    Text(&'a str),
+    Expr(&'a str),
+    Stmt(&'a str),
+}
+
+/// How to trim the text following a tag.
+#[derive(Clone, Copy, PartialEq)]
+enum Trim {
+    None,
+    Newline,
+    Whitespace,
+}
+
+/// Split a template into text, `{{ expression }}` and `{% statement %}`
+/// segments and remove comments.
+///
+/// This applies whitespace control, using the `trim_blocks` and
+/// `lstrip_blocks` settings that Hugging Face Transformers uses for chat
+/// templates. This means that a newline after a block tag is removed, as
+/// are spaces and tabs before a block tag at the start of a line.
+fn split_template(source: &str) -> Result<Vec<Segment<'_>>, ChatTemplateError> {
+    let mut segments = Vec::new();
+    let mut pos = 0;
+    let mut trim = Trim::None;
+
+    loop {
+        let mut text_start = pos;
+        match trim {
+            Trim::None => {}
+            Trim::Newline => {
+                if source[pos..].starts_with("\r\n") {
+                    text_start += 2;
+                } else if source[pos..].starts_with('\n') {
+                    text_start += 1;
+                }
+            }
+            Trim::Whitespace => {
+                text_start = source.len() - source[pos..].trim_start().len();
+            }
+        }
+        let line_start = text_start == 0 || source[..text_start].ends_with('\n');
+
+        let tag_start = ["{{", "{%", "{#"]
+            .iter()
+            .filter_map(|open| source[text_start..].find(open))
+            .min()
+            .map(|offset| text_start + offset);
+        let Some(tag_start) = tag_start else {
+            if text_start < source.len() {
+                segments.push(Segment::Text(&source[text_start..]));
+            }
+            break;
+        };
+
+        let kind = source.as_bytes()[tag_start + 1];
+        let is_block = kind != b'{';
+        let mut inner_start = tag_start + 2;
+        let mut text = &source[text_start..tag_start];
+
+        if source[inner_start..].starts_with('-') {
+            text = text.trim_end();
+            inner_start += 1;
+        } else if source[inner_start..].starts_with('+') {
+            inner_start += 1;
+        } else if is_block {
+            let line_pos = text.rfind('\n').map(|pos| pos + 1);
+            if line_pos.is_some() || line_start {
+                let line_pos = line_pos.unwrap_or(0);
+                if text[line_pos..].chars().all(|ch| ch == ' ' || ch == '\t') {
+                    text = &text[..line_pos];
+                }
+            }
+        }
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+
+        let tag_end = match kind {
+            b'#' => source[inner_start..]
+                .find("#}")
+                .map(|pos| inner_start + pos),
+            b'%' => find_tag_end(source, inner_start, "%}"),
+            _ => find_tag_end(source, inner_start, "}}"),
+        }
+        .ok_or_else(|| parse_error("unclosed tag"))?;
+
+        let mut inner = &source[inner_start..tag_end];
+        if let Some(stripped) = inner.strip_suffix('-') {
+            inner = stripped;
+            trim = Trim::Whitespace;
+        } else if is_block {
+            trim = Trim::Newline;
+        } else {
+            trim = Trim::None;
+        }
+
+        match kind {
+            b'{' => segments.push(Segment::Expr(inner)),
+            b'%' => segments.push(Segment::Stmt(inner)),
+            _ => {}
+        }
+        pos = tag_end + 2;
+    }
+
+    Ok(segments)
+}
+
+/// Find the position of the `close` delimiter for a tag, skipping over
+/// string literals.
+fn find_tag_end(source: &str, start: usize, close: &str) -> Option<usize> {
+    let mut quote = None;
+    let mut escaped = false;
+    for (pos, ch) in source[start..].char_indices() {
+        let pos = start + pos;
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if ch == '\\' => escaped = true,
+            Some(q) if ch == q => quote = None,
+            Some(_) => {}
+            None if ch == '\'' || ch == '"' => quote = Some(ch),
+            None if source[pos..].starts_with(close) => return Some(pos),
+            None => {}
+        }
+    }
+    None
+}
+
+/// Token in a template expression or statement.
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Name(String),
+    Str(String),
+    Int(i64),
+    Float(f64),
+    Op(&'static str),
+}
+
+/// Operators, ordered such that longer operators come before any operators
+/// which are a prefix of them.
+const OPERATORS: &[&str] = &[
+    "==", "!=", "<=", ">=", "//", "<", ">", "+", "-", "*", "/", "%", "~", "|", ".", ",", ":", "(",
+    ")", "[", "]", "{", "}", "=",
+];
+
+/// Split the source of an expression or statement into tokens.
+fn tokenize(source: &str) -> Result<Vec<Token>, ChatTemplateError> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+
+    while let Some(&(pos, ch)) = chars.peek() {
+        if ch.is_whitespace() {
+            chars.next();
+        } else if ch.is_alphabetic() || ch == '_' {
+            let mut end = pos;
+            while let Some(&(pos, ch)) = chars.peek() {
+                if !ch.is_alphanumeric() && ch != '_' {
+                    break;
+                }
+                end = pos + ch.len_utf8();
+                chars.next();
+            }
+            tokens.push(Token::Name(source[pos..end].to_string()));
+        } else if ch.is_ascii_digit() {
+            let mut end = pos;
+            let mut is_float = false;
+            while let Some(&(pos, ch)) = chars.peek() {
+                let is_decimal_point = ch == '.'
+                    && !is_float
+                    && source[pos + 1..].starts_with(|ch: char| ch.is_ascii_digit());
+                if !ch.is_ascii_digit() && !is_decimal_point {
+                    break;
+                }
+                is_float |= is_decimal_point;
+                end = pos + 1;
+                chars.next();
+            }
+            let num = &source[pos..end];
+            let token = if is_float {
+                num.parse().map(Token::Float).ok()
+            } else {
+                num.parse().map(Token::Int).ok()
+            };
+            tokens.push(token.ok_or_else(|| parse_error(format!("invalid number {}", num)))?);
+        } else if ch == '\'' || ch == '"' {
+            chars.next();
+            let mut value = String::new();
+            let mut closed = false;
+            while let Some((_, c)) = chars.next() {
+                match c {
+                    '\\' => match chars.next().map(|(_, c)| c) {
+                        Some('n') => value.push('\n'),
+                        Some('t') => value.push('\t'),
+                        Some('r') => value.push('\r'),
+                        Some(c @ ('\\' | '\'' | '"')) => value.push(c),
+                        Some(c) => {
+                            value.push('\\');
+                            value.push(c);
+                        }
+                        None => break,
+                    },
+                    c if c == ch => {
+                        closed = true;
+                        break;
+                    }
+                    c => value.push(c),
+                }
+            }
+            if !closed {
+                return Err(parse_error("unterminated string"));
+            }
+            tokens.push(Token::Str(value));
+        } else {
+            let op = OPERATORS
+                .iter()
+                .find(|op| source[pos..].starts_with(**op))
+                .ok_or_else(|| parse_error(format!("unexpected character {:?}", ch)))?;
+            for _ in 0..op.len() {
+                chars.next();
+            }
+            tokens.push(Token::Op(op));
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Parser for the tokens of an expression or statement.
+struct ExprParser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl ExprParser {
+    fn new(source: &str) -> Result<ExprParser, ChatTemplateError> {
+        Ok(ExprParser {
+            tokens: tokenize(source)?,
+            pos: 0,
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn is_op(&self, op: &str) -> bool {
+        matches!(self.peek(), Some(Token::Op(o)) if *o == op)
+    }
+
+    fn is_name(&self, name: &str) -> bool {
+        matches!(self.peek(), Some(Token::Name(n)) if n == name)
+    }
+
+    fn eat_op(&mut self, op: &str) -> bool {
+        let found = self.is_op(op);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn eat_name(&mut self, name: &str) -> bool {
+        let found = self.is_name(name);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect_op(&mut self, op: &str) -> Result<(), ChatTemplateError> {
+        if self.eat_op(op) {
+            Ok(())
+        } else {
+            Err(parse_error(format!("expected \"{}\"", op)))
+        }
+    }
+
+    fn expect_name(&mut self) -> Result<String, ChatTemplateError> {
+        match self.next() {
+            Some(Token::Name(name)) => Ok(name),
+            _ => Err(parse_error("expected name")),
+        }
+    }
+
+    /// Return an error if there are unparsed tokens.
+    fn expect_end(&self) -> Result<(), ChatTemplateError> {
+        match self.peek() {
+            None => Ok(()),
+            Some(token) => Err(parse_error(format!("unexpected token {:?}", token))),
+        }
+    }
+
+    /// Parse a full expression, including conditional expressions.
+    fn parse_expr(&mut self) -> Result<Expr, ChatTemplateError> {
+        let then = self.parse_or()?;
+        if !self.eat_name("if") {
+            return Ok(then);
+        }
+        let cond = self.parse_or()?;
+        let otherwise = if self.eat_name("else") {
+            Some(Box::new(self.parse_expr()?))
+        } else {
+            None
+        };
+        Ok(Expr::Conditional {
+            cond: Box::new(cond),
+            then: Box::new(then),
+            otherwise,
+        })
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, ChatTemplateError> {
+        let mut left = self.parse_and()?;
+        while self.eat_name("or") {
+            left = Expr::Or(Box::new(left), Box::new(self.parse_and()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, ChatTemplateError> {
+        let mut left = self.parse_not()?;
+        while self.eat_name("and") {
+            left = Expr::And(Box::new(left), Box::new(self.parse_not()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr, ChatTemplateError> {
+        if self.eat_name("not") {
+            return Ok(Expr::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_compare()
+    }
+
+    fn parse_compare(&mut self) -> Result<Expr, ChatTemplateError> {
+        let mut left = self.parse_math1()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Op("==")) => BinaryOp::Eq,
+                Some(Token::Op("!=")) => BinaryOp::Ne,
+                Some(Token::Op("<")) => BinaryOp::Lt,
+                Some(Token::Op("<=")) => BinaryOp::Le,
+                Some(Token::Op(">")) => BinaryOp::Gt,
+                Some(Token::Op(">=")) => BinaryOp::Ge,
+                Some(Token::Name(name)) if name == "in" => BinaryOp::In,
+                Some(Token::Name(name))
+                    if name == "not"
+                        && matches!(self.tokens.get(self.pos + 1), Some(Token::Name(n)) if n == "in") =>
+                {
+                    self.pos += 1;
+                    BinaryOp::NotIn
+                }
+                _ => break,
+            };
+            self.pos += 1;
+            left = Expr::Binary(op, Box::new(left), Box::new(self.parse_math1()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_math1(&mut self) -> Result<Expr, ChatTemplateError> {
+        let mut left = self.parse_concat()?;
+        loop {
+            let op = if self.eat_op("+") {
+                BinaryOp::Add
+            } else if self.eat_op("-") {
+                BinaryOp::Sub
+            } else {
+                break;
+            };
+            left = Expr::Binary(op, Box::new(left), Box::new(self.parse_concat()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_concat(&mut self) -> Result<Expr, ChatTemplateError> {
+        let mut left = self.parse_math2()?;
+        while self.eat_op("~") {
+            left = Expr::Binary(
+                BinaryOp::Concat,
+                Box::new(left),
+                Box::new(self.parse_math2()?),
+            );
+        }
+        Ok(left)
+    }
+
+    fn parse_math2(&mut self) -> Result<Expr, ChatTemplateError> {
+        let mut left = self.parse_unary()?;
+        loop {
+            let op = if self.eat_op("*") {
+                BinaryOp::Mul
+            } else if self.eat_op("/") {
+                BinaryOp::Div
+            } else if self.eat_op("//") {
+                BinaryOp::FloorDiv
+            } else if self.eat_op("%") {
+                BinaryOp::Mod
+            } else {
+                break;
+            };
+            left = Expr::Binary(op, Box::new(left), Box::new(self.parse_unary()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ChatTemplateError> {
+        let expr = if self.eat_op("-") {
+            Expr::Neg(Box::new(self.parse_unary()?))
+        } else if self.eat_op("+") {
+            self.parse_unary()?
+        } else {
+            let primary = self.parse_primary()?;
+            self.parse_postfix(primary)?
+        };
+        self.parse_filters(expr)
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, ChatTemplateError> {
+        let expr = match self.next() {
+            Some(Token::Name(name)) => match name.as_str() {
+                "true" | "True" => Expr::Literal(Value::Bool(true)),
+                "false" | "False" => Expr::Literal(Value::Bool(false)),
+                "none" | "None" => Expr::Literal(Value::None),
+                _ => Expr::Var(name),
+            },
+            Some(Token::Str(mut s)) => {
+                // Adjacent string literals are concatenated.
+                while let Some(Token::Str(next)) = self.peek() {
+                    s.push_str(next);
+                    self.pos += 1;
+                }
+                Expr::Literal(Value::String(s))
+            }
+            Some(Token::Int(i)) => Expr::Literal(Value::Int(i)),
+            Some(Token::Float(f)) => Expr::Literal(Value::Float(f)),
+            Some(Token::Op("(")) => {
+                let expr = self.parse_expr()?;
+                if self.is_op(",") {
+                    // Tuples are treated as lists.
+                    let mut items = vec![expr];
+                    while self.eat_op(",") && !self.is_op(")") {
+                        items.push(self.parse_expr()?);
+                    }
+                    self.expect_op(")")?;
+                    Expr::List(items)
+                } else {
+                    self.expect_op(")")?;
+                    expr
+                }
+            }
+            Some(Token::Op("[")) => {
+                let mut items = Vec::new();
+                while !self.eat_op("]") {
+                    items.push(self.parse_expr()?);
+                    if !self.eat_op(",") {
+                        self.expect_op("]")?;
+                        break;
+                    }
+                }
+                Expr::List(items)
+            }
+            Some(Token::Op("{")) => {
+                let mut entries = Vec::new();
+                while !self.eat_op("}") {
+                    let key = self.parse_expr()?;
+                    self.expect_op(":")?;
+                    entries.push((key, self.parse_expr()?));
+                    if !self.eat_op(",") {
+                        self.expect_op("}")?;
+                        break;
+                    }
+                }
+                Expr::Map(entries)
+            }
+            Some(token) => return Err(parse_error(format!("unexpected token {:?}", token))),
+            None => return Err(parse_error("unexpected end of expression")),
+        };
+        Ok(expr)
+    }
+
+    /// Parse attribute accesses, subscripts and calls after an expression.
+    fn parse_postfix(&mut self, mut expr: Expr) -> Result<Expr, ChatTemplateError> {
+        loop {
+            if self.eat_op(".") {
+                let attr = match self.next() {
+                    Some(Token::Name(name)) => name,
+                    Some(Token::Int(i)) => i.to_string(),
+                    _ => return Err(parse_error("expected attribute name")),
+                };
+                expr = Expr::Attr(Box::new(expr), attr);
+            } else if self.eat_op("[") {
+                expr = self.parse_subscript(expr)?;
+            } else if self.is_op("(") {
+                expr = Expr::Call(Box::new(expr), self.parse_args()?);
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    /// Parse an index or slice expression, after the opening `[`.
+    fn parse_subscript(&mut self, value: Expr) -> Result<Expr, ChatTemplateError> {
+        let mut parts: [Option<Box<Expr>>; 3] = [None, None, None];
+        let mut n_colons = 0;
+        loop {
+            if self.eat_op("]") {
+                break;
+            } else if self.eat_op(":") {
+                n_colons += 1;
+                if n_colons > 2 {
+                    return Err(parse_error("invalid slice"));
+                }
+            } else if parts[n_colons].is_none() {
+                parts[n_colons] = Some(Box::new(self.parse_expr()?));
+            } else {
+                return Err(parse_error("expected \"]\""));
+            }
+        }
+
+        let [start, end, step] = parts;
+        if n_colons == 0 {
+            let index = start.ok_or_else(|| parse_error("expected index"))?;
+            Ok(Expr::Index(Box::new(value), index))
+        } else {
+            Ok(Expr::Slice {
+                value: Box::new(value),
+                start,
+                end,
+                step,
+            })
+        }
+    }
+
+    /// Parse a parenthesized argument list.
+    fn parse_args(&mut self) -> Result<Args, ChatTemplateError> {
+        self.expect_op("(")?;
+        let mut args = Args::default();
+        while !self.eat_op(")") {
+            let is_keyword = matches!(self.peek(), Some(Token::Name(_)))
+                && matches!(self.tokens.get(self.pos + 1), Some(Token::Op("=")));
+            if is_keyword {
+                let name = self.expect_name()?;
+                self.expect_op("=")?;
+                args.keyword.push((name, self.parse_expr()?));
+            } else {
+                args.positional.push(self.parse_expr()?);
+            }
+            if !self.eat_op(",") {
+                self.expect_op(")")?;
+                break;
+            }
+        }
+        Ok(args)
+    }
+
+    /// Parse filters (`value | filter`) and tests (`value is test`) applied
+    /// to an expression.
+    fn parse_filters(&mut self, mut expr: Expr) -> Result<Expr, ChatTemplateError> {
+        loop {
+            if self.eat_op("|") {
+                let name = self.expect_name()?;
+                let args = if self.is_op("(") {
+                    self.parse_args()?
+                } else {
+                    Args::default()
+                };
+                expr = Expr::Filter(Box::new(expr), name, args);
+            } else if self.eat_name("is") {
+                let negated = self.eat_name("not");
+                let name = self.expect_name()?;
+                let args = match self.peek() {
+                    Some(Token::Op("(")) => self.parse_args()?.positional,
+                    Some(Token::Str(_) | Token::Int(_) | Token::Float(_)) => {
+                        vec![self.parse_primary()?]
+                    }
+                    _ => Vec::new(),
+                };
+                expr = Expr::Test {
+                    value: Box::new(expr),
+                    name,
+                    args,
+                    negated,
+                };
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+}
+
+/// Parse a template into a syntax tree.
+pub(crate) fn parse_template(source: &str) -> Result<Vec<Node>, ChatTemplateError> {
+    let segments = split_template(source)?;
+    let mut segments = segments.into_iter();
+    let (nodes, end) = parse_block(&mut segments, &[])?;
+    debug_assert!(end.is_none());
+    Ok(nodes)
+}
+
+/// Parse nodes until a statement with a keyword in `end_tags` is reached.
+///
+/// Returns the parsed nodes and, if an end tag was found, a parser for the
+/// statement which ended the block, positioned after the keyword.
+#[allow(clippy::type_complexity)]
+fn parse_block<'a>(
+    segments: &mut impl Iterator<Item = Segment<'a>>,
+    end_tags: &[&str],
+) -> Result<(Vec<Node>, Option<(String, ExprParser)>), ChatTemplateError> {
+    let mut nodes = Vec::new();
+
+    while let Some(segment) = segments.next() {
+        let stmt = match segment {
+            Segment::Text(text) => {
+                nodes.push(Node::Text(text.to_string()));
+                continue;
+            }
+            Segment::Expr(source) => {
+                let mut parser = ExprParser::new(source)?;
+                let expr = parser.parse_expr()?;
+                parser.expect_end()?;
+                nodes.push(Node::Output(expr));
+                continue;
+            }
+            Segment::Stmt(stmt) => stmt,
+        };
+
+        let mut parser = ExprParser::new(stmt)?;
+        let keyword = parser.expect_name()?;
+        if end_tags.contains(&keyword.as_str()) {
+            return Ok((nodes, Some((keyword, parser))));
+        }
+
+        let node = match keyword.as_str() {
+            "if" => parse_if(segments, parser)?,
+            "for" => parse_for(segments, parser)?,
+            "set" => {
+                let name = parser.expect_name()?;
+                let attr = if parser.eat_op(".") {
+                    Some(parser.expect_name()?)
+                } else {
+                    None
+                };
+                if !parser.eat_op("=") {
+                    return Err(parse_error("block assignments are not supported"));
+                }
+                let value = parser.parse_expr()?;
+                parser.expect_end()?;
+                Node::Set { name, attr, value }
+            }
+            "break" => {
+                parser.expect_end()?;
+                Node::Break
+            }
+            "continue" => {
+                parser.expect_end()?;
+                Node::Continue
+            }
+            _ => return Err(parse_error(format!("unsupported tag \"{}\"", keyword))),
+        };
+        nodes.push(node);
+    }
+
+    if let Some(tag) = end_tags.last() {
+        return Err(parse_error(format!("missing \"{}\" tag", tag)));
+    }
+    Ok((nodes, None))
+}
+
+/// Parse an `if` statement, after the `if` keyword.
+fn parse_if<'a>(
+    segments: &mut impl Iterator<Item = Segment<'a>>,
+    mut parser: ExprParser,
+) -> Result<Node, ChatTemplateError> {
+    let mut branches = Vec::new();
+    let mut otherwise = Vec::new();
+
+    loop {
+        let cond = parser.parse_expr()?;
+        parser.expect_end()?;
+
+        let (body, end) = parse_block(segments, &["elif", "else", "endif"])?;
+        branches.push((cond, body));
+
+        let Some((keyword, end_parser)) = end else {
+            unreachable!("parse_block returns end tag or error");
+        };
+        parser = end_parser;
+        match keyword.as_str() {
+            "elif" => continue,
+            "else" => {
+                parser.expect_end()?;
+                let (body, end) = parse_block(segments, &["endif"])?;
+                otherwise = body;
+                if let Some((_, parser)) = end {
+                    parser.expect_end()?;
+                }
+                break;
+            }
+            _ => {
+                parser.expect_end()?;
+                break;
+            }
+        }
+    }
+
+    Ok(Node::If {
+        branches,
+        otherwise,
+    })
+}
+
+/// Parse a `for` statement, after the `for` keyword.
+fn parse_for<'a>(
+    segments: &mut impl Iterator<Item = Segment<'a>>,
+    mut parser: ExprParser,
+) -> Result<Node, ChatTemplateError> {
+    let mut targets = vec![parser.expect_name()?];
+    while parser.eat_op(",") {
+        targets.push(parser.expect_name()?);
+    }
+    if !parser.eat_name("in") {
+        return Err(parse_error("expected \"in\""));
+    }
+    // The iterable is parsed without conditional expressions, so that an
+    // `if` that follows is treated as a filter.
+    let iter = parser.parse_or()?;
+    let filter = if parser.eat_name("if") {
+        Some(parser.parse_or()?)
+    } else {
+        None
+    };
+    parser.expect_end()?;
+
+    let (body, end) = parse_block(segments, &["else", "endfor"])?;
+    let mut otherwise = Vec::new();
+    if let Some((keyword, parser)) = end {
+        parser.expect_end()?;
+        if keyword == "else" {
+            let (body, end) = parse_block(segments, &["endfor"])?;
+            otherwise = body;
+            if let Some((_, parser)) = end {
+                parser.expect_end()?;
+            }
+        }
+    }
+
+    Ok(Node::For {
+        targets,
+        iter,
+        filter,
+        body,
+        otherwise,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{split_template, Segment};
+
+    #[test]
+    fn test_split_template() {
+        struct Case<'a> {
+            template: &'a str,
+            segments: Vec<Segment<'a>>,
+        }
+
+        let cases = [
+            Case {
+                template: "Hello {{ name }}!",
+                segments: vec![
+                    Segment::Text("Hello "),
+                    Segment::Expr(" name "),
+                    Segment::Text("!"),
+                ],
+            },
+            // Whitespace control.
+            Case {
+                template: "a  {{- x -}}\n b",
+                segments: vec![Segment::Text("a"), Segment::Expr(" x "), Segment::Text("b")],
+            },
+            // Comments are removed.
+            Case {
+                template: "a{# comment #}b",
+                segments: vec![Segment::Text("a"), Segment::Text("b")],
+            },
+            // A newline after a block tag is removed, as are spaces before a
+            // block tag at the start of a line.
+            Case {
+                template: "{% if x %}\n  a\n  {% endif %}\nb",
+                segments: vec![
+                    Segment::Stmt(" if x "),
+                    Segment::Text("  a\n"),
+                    Segment::Stmt(" endif "),
+                    Segment::Text("b"),
+                ],
+            },
+            // Delimiters inside strings are ignored.
+            Case {
+                template: "{{ '}}' }}",
+                segments: vec![Segment::Expr(" '}}' ")],
+            },
+        ];
+
+        for Case { template, segments } in cases {
+            assert_eq!(split_template(template).unwrap(), segments);
+        }
+    }
+}
@@ -0,0 +1,353 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+/// A value produced when evaluating a template expression.
+///
+/// This follows the semantics of Python values in Jinja templates, since
+/// that is what chat templates are written for.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) enum Value {
+    /// Result of looking up a variable, attribute or item which doesn't
+    /// exist.
+    #[default]
+    Undefined,
+    None,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    List(Vec<Value>),
+
+    /// Mapping from keys to values, in insertion order.
+    Map(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Create a map value from a list of entries.
+    pub fn map<'a>(entries: impl IntoIterator<Item = (&'a str, Value)>) -> Value {
+        Value::Map(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    /// Return the truthiness of the value, as in Python.
+    pub fn is_true(&self) -> bool {
+        match self {
+            Value::Undefined | Value::None => false,
+            Value::Bool(b) => *b,
+            Value::Int(i) => *i != 0,
+            Value::Float(f) => *f != 0.,
+            Value::String(s) => !s.is_empty(),
+            Value::List(items) => !items.is_empty(),
+            Value::Map(entries) => !entries.is_empty(),
+        }
+    }
+
+    /// Return the name of the value's type, for use in error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Undefined => "undefined",
+            Value::None => "none",
+            Value::Bool(_) => "boolean",
+            Value::Int(_) => "integer",
+            Value::Float(_) => "float",
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+            Value::Map(_) => "mapping",
+        }
+    }
+
+    /// Look up an entry in a map.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Map(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Look up an entry in a map for modification, adding it if not present.
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Value> {
+        let Value::Map(entries) = self else {
+            return None;
+        };
+        let idx = match entries.iter().position(|(k, _)| k == key) {
+            Some(idx) => idx,
+            None => {
+                entries.push((key.to_string(), Value::Undefined));
+                entries.len() - 1
+            }
+        };
+        Some(&mut entries[idx].1)
+    }
+
+    /// Return the value as a float, if it is a number.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Bool(b) => Some(*b as i64 as f64),
+            Value::Int(i) => Some(*i as f64),
+            Value::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    /// Return the value as an integer, if it is an integer or boolean.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Bool(b) => Some(*b as i64),
+            Value::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// Return the items which are produced by iterating over the value in a
+    /// `for` loop.
+    ///
+    /// Iterating over a map yields its keys and iterating over a string
+    /// yields its characters.
+    pub fn iter_items(&self) -> Option<Vec<Value>> {
+        match self {
+            Value::Undefined => Some(Vec::new()),
+            Value::List(items) => Some(items.clone()),
+            Value::Map(entries) => Some(
+                entries
+                    .iter()
+                    .map(|(k, _)| Value::String(k.clone()))
+                    .collect(),
+            ),
+            Value::String(s) => Some(s.chars().map(|ch| Value::String(ch.into())).collect()),
+            _ => None,
+        }
+    }
+
+    /// Return the number of items in a string, list or map.
+    pub fn len(&self) -> Option<usize> {
+        match self {
+            Value::String(s) => Some(s.chars().count()),
+            Value::List(items) => Some(items.len()),
+            Value::Map(entries) => Some(entries.len()),
+            _ => None,
+        }
+    }
+
+    /// Return true if `item` is contained in this value, as with Python's
+    /// `in` operator.
+    pub fn contains(&self, item: &Value) -> Option<bool> {
+        match (self, item) {
+            (Value::String(s), Value::String(sub)) => Some(s.contains(sub.as_str())),
+            (Value::List(items), _) => Some(items.iter().any(|x| x.loose_eq(item))),
+            (Value::Map(_), Value::String(key)) => Some(self.get(key).is_some()),
+            (Value::Map(_), _) => Some(false),
+            (Value::Undefined, _) => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Compare values for equality, treating integers and floats with the
+    /// same value as equal.
+    pub fn loose_eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::List(a), Value::List(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(x, y)| x.loose_eq(y))
+            }
+            (Value::Map(a), Value::Map(b)) => {
+                a.len() == b.len()
+                    && a.iter()
+                        .all(|(k, v)| other.get(k).is_some_and(|other_v| v.loose_eq(other_v)))
+            }
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::None, Value::None) | (Value::Undefined, Value::Undefined) => true,
+            _ => match (self.as_f64(), other.as_f64()) {
+                (Some(a), Some(b)) => a == b,
+                _ => false,
+            },
+        }
+    }
+
+    /// Compare values for ordering. Returns `None` if the values cannot be
+    /// compared.
+    pub fn compare(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+            (Value::List(a), Value::List(b)) => {
+                for (x, y) in a.iter().zip(b) {
+                    match x.compare(y)? {
+                        Ordering::Equal => {}
+                        ord => return Some(ord),
+                    }
+                }
+                Some(a.len().cmp(&b.len()))
+            }
+            _ => self.as_f64()?.partial_cmp(&other.as_f64()?),
+        }
+    }
+
+    /// Format the value as Python's `repr` would.
+    ///
+    /// This is used for values nested inside lists and maps.
+    fn fmt_repr(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::String(s) => {
+                write!(f, "'")?;
+                for ch in s.chars() {
+                    match ch {
+                        '\'' => write!(f, "\\'")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '\n' => write!(f, "\\n")?,
+                        '\t' => write!(f, "\\t")?,
+                        '\r' => write!(f, "\\r")?,
+                        _ => write!(f, "{}", ch)?,
+                    }
+                }
+                write!(f, "'")
+            }
+            _ => write!(f, "{}", self),
+        }
+    }
+
+    /// Serialize the value as JSON, using the same formatting as Python's
+    /// `json.dumps`.
+    pub fn to_json(&self, indent: Option<usize>) -> String {
+        let mut out = String::new();
+        self.write_json(&mut out, indent, 0);
+        out
+    }
+
+    fn write_json(&self, out: &mut String, indent: Option<usize>, depth: usize) {
+        let newline = |out: &mut String, depth: usize| {
+            if let Some(indent) = indent {
+                out.push('\n');
+                out.extend(std::iter::repeat_n(' ', indent * depth));
+            }
+        };
+        let separator = if indent.is_some() { "," } else { ", " };
+
+        match self {
+            Value::Undefined | Value::None => out.push_str("null"),
+            Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+            Value::Int(_) | Value::Float(_) => out.push_str(&self.to_string()),
+            Value::String(s) => {
+                out.push_str(&serde_json::to_string(s).unwrap_or_default());
+            }
+            Value::List(items) => {
+                if items.is_empty() {
+                    out.push_str("[]");
+                    return;
+                }
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(separator);
+                    }
+                    newline(out, depth + 1);
+                    item.write_json(out, indent, depth + 1);
+                }
+                newline(out, depth);
+                out.push(']');
+            }
+            Value::Map(entries) => {
+                if entries.is_empty() {
+                    out.push_str("{}");
+                    return;
+                }
+                out.push('{');
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(separator);
+                    }
+                    newline(out, depth + 1);
+                    out.push_str(&serde_json::to_string(key).unwrap_or_default());
+                    out.push_str(": ");
+                    value.write_json(out, indent, depth + 1);
+                }
+                newline(out, depth);
+                out.push('}');
+            }
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    /// Format the value as it appears in rendered template output.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Undefined => Ok(()),
+            Value::None => write!(f, "None"),
+            Value::Bool(true) => write!(f, "True"),
+            Value::Bool(false) => write!(f, "False"),
+            Value::Int(i) => write!(f, "{}", i),
+            Value::Float(x) if x.is_finite() && x.fract() == 0. => write!(f, "{:.1}", x),
+            Value::Float(x) => write!(f, "{}", x),
+            Value::String(s) => write!(f, "{}", s),
+            Value::List(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    item.fmt_repr(f)?;
+                }
+                write!(f, "]")
+            }
+            Value::Map(entries) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    Value::String(key.clone()).fmt_repr(f)?;
+                    write!(f, ": ")?;
+                    value.fmt_repr(f)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(val: &str) -> Value {
+        Value::String(val.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(val: String) -> Value {
+        Value::String(val)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(val: bool) -> Value {
+        Value::Bool(val)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(val: i64) -> Value {
+        Value::Int(val)
+    }
+}
+
+impl From<&serde_json::Value> for Value {
+    fn from(val: &serde_json::Value) -> Value {
+        match val {
+            serde_json::Value::Null => Value::None,
+            serde_json::Value::Bool(b) => Value::Bool(*b),
+            serde_json::Value::Number(n) => match n.as_i64() {
+                Some(i) => Value::Int(i),
+                None => Value::Float(n.as_f64().unwrap_or(f64::NAN)),
+            },
+            serde_json::Value::String(s) => Value::String(s.clone()),
+            serde_json::Value::Array(items) => Value::List(items.iter().map(Value::from).collect()),
+            serde_json::Value::Object(entries) => Value::Map(
+                entries
+                    .iter()
+                    .map(|(k, v)| (k.clone(), Value::from(v)))
+                    .collect(),
+            ),
+        }
+    }
+}
@@ -1,7 +1,16 @@
 //! Tools for performing string normalization prior to tokenization.
 
+use std::borrow::Cow;
+use std::fmt;
+use std::iter::repeat_n;
+use std::ops::Range;
+use std::sync::Arc;
+
+use fancy_regex::Regex;
 use unicode_categories::UnicodeCategories;
-use unicode_normalization::char::decompose_canonical;
+use unicode_normalization::char::{
+    canonical_combining_class, compose, decompose_canonical, decompose_compatible,
+};
 
 struct CharNormalizer {
     normalized: Vec<char>,
@@ -26,10 +35,16 @@ impl CharNormalizer {
     }
 
     /// Lowercase the normalized characters.
-    fn lower_case(&mut self) {
-        for ch in &self.normalized {
-            for lower_ch in ch.to_lowercase() {
-                self.tmp.push(lower_ch);
+    ///
+    /// If `case_fold` is true, full Unicode case folding is applied instead.
+    /// If `turkic` is true, "I" and "İ" are mapped to "ı" and "i".
+    fn lower_case(&mut self, case_fold: bool, turkic: bool) {
+        for &ch in &self.normalized {
+            match ch {
+                'I' if turkic => self.tmp.push('ı'),
+                'İ' if turkic => self.tmp.push('i'),
+                _ if case_fold => case_fold_char(ch, &mut self.tmp),
+                _ => self.tmp.extend(ch.to_lowercase()),
             }
         }
         self.update_normalized_from_tmp();
@@ -60,6 +75,51 @@ impl CharNormalizer {
     }
 }
 
+/// Append the full Unicode case folding of `ch` to `out`.
+///
+/// This is the mapping used for caseless matching, which differs from
+/// lowercasing for characters such as "ß" (folded to "ss") and "ς" (folded to
+/// "σ"). See <https://www.unicode.org/Public/UCD/latest/ucd/CaseFolding.txt>.
+fn case_fold_char(ch: char, out: &mut Vec<char>) {
+    // Cherokee is folded to uppercase, since the uppercase letters were
+    // encoded first.
+    let cherokee = match ch as u32 {
+        0x13F8..=0x13FD => char::from_u32(ch as u32 - 8),
+        0xAB70..=0xABBF => char::from_u32(ch as u32 - 0xAB70 + 0x13A0),
+        0x13A0..=0x13F5 => Some(ch),
+        _ => None,
+    };
+
+    // For other characters, full case folding is equivalent to
+    // lowercasing, uppercasing and then lowercasing again. The first step
+    // handles characters whose lowercase form expands when uppercased
+    // (eg. "ẞ" -> "ß" -> "SS"). Dotless "ı" is the exception, as it has no
+    // case folding.
+    match cherokee {
+        Some(ch) => out.push(ch),
+        None if ch == 'ı' => out.push(ch),
+        None => out.extend(
+            ch.to_lowercase()
+                .flat_map(char::to_uppercase)
+                .flat_map(char::to_lowercase),
+        ),
+    }
+}
+
+/// Trait for custom normalization steps.
+///
+/// Implementations can be combined with the built-in normalizers and used by
+/// encoders by wrapping them with [Normalizer::custom].
+pub trait Normalize: Send + Sync {
+    /// Apply normalization to a string.
+    ///
+    /// Returns a tuple of `(normalized_string, offset_map)`, where
+    /// `offset_map` maps each byte in the normalized string to the byte
+    /// offset of the character in `text` that produced it. See
+    /// [Normalizer::normalize].
+    fn normalize(&self, text: &str) -> (String, Vec<usize>);
+}
+
 /// Normalizer applies normalization such as Unicode normalization and
 /// lower-casing to strings.
 ///
@@ -67,28 +127,182 @@ impl CharNormalizer {
 /// from positions in the normalized string back to the original string. This
 /// is useful for post-processing in NLP tasks to map machine learning model
 /// outputs back to the location in the original text.
+///
+/// Normalizers which perform a single step, such as
+/// [strip_accents](Normalizer::strip_accents), can be chained together using
+/// [sequence](Normalizer::sequence). These mirror the normalizers in the
+/// `normalizer` section of Hugging Face `tokenizer.json` files.
 #[derive(Clone, Debug)]
 pub struct Normalizer {
-    lowercase: bool,
-    strip_accents: bool,
+    kind: NormalizerKind,
+}
+
+#[derive(Clone, Debug)]
+enum NormalizerKind {
+    /// Normalizer configured using [NormalizerOptions].
+    Options(NormalizerOptions),
+
+    /// Convert text to a Unicode normalization form.
+    Unicode(NormalizationForm),
+
+    /// Replace occurrences of a pattern.
+    Replace {
+        pattern: ReplacePattern,
+        content: String,
+    },
+
+    /// Remove combining marks.
+    StripAccents,
+
+    /// Apply several normalizers in order.
+    Sequence(Vec<Normalizer>),
+
+    /// Normalizer implemented outside of this crate.
+    Custom(CustomNormalizer),
+}
+
+#[derive(Clone)]
+struct CustomNormalizer(Arc<dyn Normalize>);
+
+impl fmt::Debug for CustomNormalizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CustomNormalizer")
+    }
 }
 
 /// Configuration for a [Normalizer].
 #[derive(Clone, Debug, Default)]
 pub struct NormalizerOptions {
     /// If true, convert all text to lowercase using [char::to_lowercase].
+    ///
+    /// The conversion can be customized using
+    /// [case_fold](NormalizerOptions::case_fold) and
+    /// [turkic_lowercase](NormalizerOptions::turkic_lowercase).
     pub lowercase: bool,
 
+    /// If true, [lowercase](NormalizerOptions::lowercase) applies full
+    /// Unicode case folding instead of lowercasing.
+    ///
+    /// Case folding maps text which differs only in case to the same string,
+    /// including characters which expand to several characters (eg. "ß" is
+    /// folded to "ss"). Offsets for the expanded characters all map to the
+    /// source character.
+    pub case_fold: bool,
+
+    /// If true, [lowercase](NormalizerOptions::lowercase) uses the rules for
+    /// Turkish and Azerbaijani, where "I" is lowercased to dotless "ı" and
+    /// dotted "İ" is lowercased to "i".
+    pub turkic_lowercase: bool,
+
     /// Whether to strip accents when tokenizing. An "accent" is defined as
     /// any unicode character in the Nonspacing Mark ("Mn") category.
     pub strip_accents: bool,
+
+    /// If true, remove leading and trailing spaces and replace runs of
+    /// spaces with a single space.
+    pub remove_extra_whitespace: bool,
+
+    /// Replace spaces with this character. SentencePiece models use `▁`
+    /// (U+2581) so that spaces are visible in tokens.
+    pub replace_space: Option<char>,
+
+    /// String to add to the start of non-empty text, after other
+    /// normalization has been applied.
+    pub prepend: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+enum ReplacePattern {
+    String(String),
+    Regex(Regex),
+}
+
+/// Unicode normalization forms.
+///
+/// See <https://unicode.org/reports/tr15/> for details of what each form
+/// does.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NormalizationForm {
+    /// Canonical decomposition followed by canonical composition.
+    Nfc,
+    /// Canonical decomposition.
+    Nfd,
+    /// Compatibility decomposition followed by canonical composition.
+    Nfkc,
+    /// Compatibility decomposition.
+    Nfkd,
 }
 
 impl Normalizer {
     pub fn new(opts: NormalizerOptions) -> Normalizer {
         Normalizer {
-            lowercase: opts.lowercase,
-            strip_accents: opts.strip_accents,
+            kind: NormalizerKind::Options(opts),
+        }
+    }
+
+    /// Create a normalizer which removes combining marks, such as accents,
+    /// from the text.
+    ///
+    /// Unlike [NormalizerOptions::strip_accents], this does not decompose
+    /// characters first, so precomposed characters such as "é" are not
+    /// changed. This matches the `StripAccents` normalizer in Hugging Face
+    /// Tokenizers, which is used after an NFD normalization step (see
+    /// [Normalizer::unicode]).
+    pub fn strip_accents() -> Normalizer {
+        Normalizer {
+            kind: NormalizerKind::StripAccents,
+        }
+    }
+
+    /// Create a normalizer which converts text to a Unicode normalization
+    /// form.
+    pub fn unicode(form: NormalizationForm) -> Normalizer {
+        Normalizer {
+            kind: NormalizerKind::Unicode(form),
+        }
+    }
+
+    /// Create a normalizer which replaces occurrences of the string `pattern`
+    /// with `content`.
+    pub fn replace(pattern: &str, content: &str) -> Normalizer {
+        Normalizer {
+            kind: NormalizerKind::Replace {
+                pattern: ReplacePattern::String(pattern.to_string()),
+                content: content.to_string(),
+            },
+        }
+    }
+
+    /// Create a normalizer which replaces matches of the regex `pattern` with
+    /// `content`.
+    ///
+    /// Empty matches are ignored. If matching fails, for example because the
+    /// regex exceeds its backtracking limit, the rest of the text is left
+    /// unchanged.
+    pub fn replace_regex(
+        pattern: &str,
+        content: &str,
+    ) -> Result<Normalizer, Box<fancy_regex::Error>> {
+        let regex = Regex::new(pattern)?;
+        Ok(Normalizer {
+            kind: NormalizerKind::Replace {
+                pattern: ReplacePattern::Regex(regex),
+                content: content.to_string(),
+            },
+        })
+    }
+
+    /// Create a normalizer which applies each of `normalizers` in turn.
+    pub fn sequence(normalizers: Vec<Normalizer>) -> Normalizer {
+        Normalizer {
+            kind: NormalizerKind::Sequence(normalizers),
+        }
+    }
+
+    /// Create a normalizer which uses a custom [Normalize] implementation.
+    pub fn custom<N: Normalize + 'static>(normalizer: N) -> Normalizer {
+        Normalizer {
+            kind: NormalizerKind::Custom(CustomNormalizer(Arc::new(normalizer))),
         }
     }
 
@@ -103,41 +317,381 @@ impl Normalizer {
             return (text.to_string(), offsets);
         }
 
-        let mut normalized = String::with_capacity(text.len());
-        let mut offsets = Vec::with_capacity(text.len());
-        let mut char_normalizer = CharNormalizer::new();
-
-        for (offset, ch) in text.char_indices() {
-            char_normalizer.set_char(ch);
-
-            if self.strip_accents {
-                char_normalizer.strip_accents();
+        match &self.kind {
+            NormalizerKind::Options(opts) => normalize_with_options(text, opts),
+            NormalizerKind::Unicode(form) => normalize_unicode(text, *form),
+            NormalizerKind::Replace { pattern, content } => {
+                let matches: Vec<Range<usize>> = match pattern {
+                    ReplacePattern::String(pattern) => text
+                        .match_indices(pattern.as_str())
+                        .map(|(start, m)| start..start + m.len())
+                        .collect(),
+                    ReplacePattern::Regex(regex) => regex
+                        .find_iter(text)
+                        .map_while(|m| m.ok())
+                        .map(|m| m.range())
+                        .collect(),
+                };
+                replace_ranges(text, &matches, content)
             }
+            NormalizerKind::StripAccents => {
+                let mut normalized = String::with_capacity(text.len());
+                let mut offsets = Vec::with_capacity(text.len());
+                for (offset, ch) in text.char_indices() {
+                    if !ch.is_mark() {
+                        normalized.push(ch);
+                        offsets.extend(repeat_n(offset, ch.len_utf8()));
+                    }
+                }
+                (normalized, offsets)
+            }
+            NormalizerKind::Sequence(normalizers) => normalizers
+                .iter()
+                .fold(NormalizedText::new(text), |text, normalizer| {
+                    text.normalize(normalizer)
+                })
+                .into_parts(),
+            NormalizerKind::Custom(CustomNormalizer(normalizer)) => normalizer.normalize(text),
+        }
+    }
 
-            if self.lowercase {
-                char_normalizer.lower_case();
+    /// Return true if this normalizer doesn't alter its input.
+    fn is_noop(&self) -> bool {
+        match &self.kind {
+            NormalizerKind::Options(opts) => {
+                !opts.lowercase
+                    && !opts.strip_accents
+                    && !opts.remove_extra_whitespace
+                    && opts.replace_space.is_none()
+                    && opts.prepend.is_none()
+            }
+            NormalizerKind::Replace { pattern, .. } => {
+                matches!(pattern, ReplacePattern::String(pattern) if pattern.is_empty())
             }
+            NormalizerKind::Unicode(_)
+            | NormalizerKind::StripAccents
+            | NormalizerKind::Custom(_) => false,
+            NormalizerKind::Sequence(normalizers) => normalizers.iter().all(|n| n.is_noop()),
+        }
+    }
+}
+
+impl Normalize for Normalizer {
+    fn normalize(&self, text: &str) -> (String, Vec<usize>) {
+        Normalizer::normalize(self, text)
+    }
+}
+
+/// Replace non-empty `ranges` of `text` with `content`.
+///
+/// The first byte of each replacement is mapped to the start of the range it
+/// replaced, and any other bytes are mapped to the last character in the
+/// range. This means that a token which covers the whole of a multi-byte
+/// replacement maps to the whole of the replaced text.
+fn replace_ranges(text: &str, ranges: &[Range<usize>], content: &str) -> (String, Vec<usize>) {
+    let mut normalized = String::with_capacity(text.len());
+    let mut offsets = Vec::with_capacity(text.len());
+    let mut last_end = 0;
+    for range in ranges.iter().filter(|r| !r.is_empty()) {
+        normalized.push_str(&text[last_end..range.start]);
+        offsets.extend(last_end..range.start);
+        normalized.push_str(content);
+        if !content.is_empty() {
+            let last_char = text[..range.end]
+                .char_indices()
+                .next_back()
+                .map_or(range.start, |(i, _)| i);
+            offsets.push(range.start);
+            offsets.extend(repeat_n(last_char, content.len() - 1));
+        }
+        last_end = range.end;
+    }
+    normalized.push_str(&text[last_end..]);
+    offsets.extend(last_end..text.len());
+    (normalized, offsets)
+}
+
+/// Convert a string to a Unicode normalization form.
+///
+/// This follows the algorithm in <https://unicode.org/reports/tr15/>, while
+/// keeping track of which source character each output character came from.
+/// Characters produced by composition are mapped to the offset of the first
+/// character that was combined.
+fn normalize_unicode(text: &str, form: NormalizationForm) -> (String, Vec<usize>) {
+    if text.is_ascii() {
+        return (text.to_string(), (0..text.len()).collect());
+    }
+
+    // Decompose each character, recording the offset of its source.
+    let mut chars: Vec<(char, usize)> = Vec::with_capacity(text.len());
+    for (offset, ch) in text.char_indices() {
+        let push = |decomposed| chars.push((decomposed, offset));
+        match form {
+            NormalizationForm::Nfc | NormalizationForm::Nfd => decompose_canonical(ch, push),
+            NormalizationForm::Nfkc | NormalizationForm::Nfkd => decompose_compatible(ch, push),
+        }
+    }
+
+    // Sort runs of non-starters by their combining class.
+    let mut start = 0;
+    while start < chars.len() {
+        if canonical_combining_class(chars[start].0) == 0 {
+            start += 1;
+            continue;
+        }
+        let len = chars[start..]
+            .iter()
+            .position(|(ch, _)| canonical_combining_class(*ch) == 0)
+            .unwrap_or(chars.len() - start);
+        chars[start..start + len].sort_by_key(|(ch, _)| canonical_combining_class(*ch));
+        start += len;
+    }
+
+    if matches!(form, NormalizationForm::Nfc | NormalizationForm::Nfkc) {
+        chars = compose_chars(chars);
+    }
+
+    let mut normalized = String::with_capacity(text.len());
+    let mut offsets = Vec::with_capacity(text.len());
+    for (ch, offset) in chars {
+        normalized.push(ch);
+        offsets.extend(repeat_n(offset, ch.len_utf8()));
+    }
+    (normalized, offsets)
+}
 
-            for ch in char_normalizer.normalized() {
-                normalized.push(*ch);
-                for _ in 0..ch.len_utf8() {
-                    offsets.push(offset);
+/// Apply canonical composition to a sequence of `(char, source_offset)`
+/// tuples which are in canonical order.
+fn compose_chars(chars: Vec<(char, usize)>) -> Vec<(char, usize)> {
+    let mut composed: Vec<(char, usize)> = Vec::with_capacity(chars.len());
+
+    // Index in `composed` of the last starter, and the combining class of the
+    // last character after it.
+    let mut starter: Option<usize> = None;
+    let mut last_class: Option<u8> = None;
+
+    for (ch, offset) in chars {
+        let class = canonical_combining_class(ch);
+        if let Some(starter) = starter {
+            // A character can combine with the last starter unless there is
+            // a character in between with a class that is zero or not lower.
+            let blocked = last_class.is_some_and(|last| last == 0 || last >= class);
+            if !blocked {
+                if let Some(combined) = compose(composed[starter].0, ch) {
+                    composed[starter].0 = combined;
+                    continue;
                 }
             }
         }
+        if class == 0 {
+            starter = Some(composed.len());
+            last_class = None;
+        } else {
+            last_class = Some(class);
+        }
+        composed.push((ch, offset));
+    }
 
-        (normalized, offsets)
+    composed
+}
+
+/// Apply normalization configured using [NormalizerOptions] to a string.
+///
+/// See [Normalizer::normalize].
+fn normalize_with_options(text: &str, opts: &NormalizerOptions) -> (String, Vec<usize>) {
+    let mut normalized = String::with_capacity(text.len());
+    let mut offsets = Vec::with_capacity(text.len());
+    let mut char_normalizer = CharNormalizer::new();
+
+    let mut source_text = text;
+    let mut source_start = 0;
+    if opts.remove_extra_whitespace {
+        let trimmed = text.trim_start_matches(' ');
+        source_start = text.len() - trimmed.len();
+        source_text = trimmed.trim_end_matches(' ');
     }
+    let mut prev_char = None;
 
-    /// Return true if this normalizer doesn't alter its input.
-    fn is_noop(&self) -> bool {
-        !self.lowercase && !self.strip_accents
+    for (offset, ch) in source_text.char_indices() {
+        let offset = source_start + offset;
+        if opts.remove_extra_whitespace && ch == ' ' && prev_char == Some(' ') {
+            continue;
+        }
+        prev_char = Some(ch);
+
+        char_normalizer.set_char(ch);
+
+        if opts.strip_accents {
+            char_normalizer.strip_accents();
+        }
+
+        if opts.lowercase {
+            char_normalizer.lower_case(opts.case_fold, opts.turkic_lowercase);
+        }
+
+        for &ch in char_normalizer.normalized() {
+            let ch = match opts.replace_space {
+                Some(replacement) if ch == ' ' => replacement,
+                _ => ch,
+            };
+            normalized.push(ch);
+            for _ in 0..ch.len_utf8() {
+                offsets.push(offset);
+            }
+        }
+    }
+
+    if let Some(prefix) = opts.prepend.as_deref() {
+        if !normalized.is_empty() {
+            normalized.insert_str(0, prefix);
+            offsets.splice(0..0, repeat_n(offsets[0], prefix.len()));
+        }
+    }
+
+    (normalized, offsets)
+}
+
+/// Text which has been transformed by zero or more normalizers, together
+/// with the alignment between the normalized and original text.
+///
+/// This is similar to the `NormalizedString` type in Hugging Face
+/// Tokenizers. Each call to [NormalizedText::normalize] applies a normalizer
+/// to the current text and composes the resulting offset map with the
+/// existing one, so that positions in the final normalized text can be mapped
+/// back to the caller's original string.
+///
+/// ```
+/// use rten_text::normalizer::{NormalizationForm, NormalizedText, Normalizer};
+///
+/// let text = NormalizedText::new("Café au lait")
+///     .normalize(&Normalizer::unicode(NormalizationForm::Nfd))
+///     .normalize(&Normalizer::strip_accents())
+///     .normalize(&Normalizer::replace(" ", "_"));
+/// assert_eq!(text.normalized(), "Cafe_au_lait");
+/// assert_eq!(text.original_range(5..7), 6..8);
+/// assert_eq!(text.normalized_range(0..5), 0..4);
+/// ```
+#[derive(Clone, Debug)]
+pub struct NormalizedText<'a> {
+    original: &'a str,
+    normalized: Cow<'a, str>,
+
+    /// Map from byte offsets in `normalized` to byte offsets in `original`,
+    /// or `None` if the text has not been changed.
+    offsets: Option<Vec<usize>>,
+}
+
+impl<'a> NormalizedText<'a> {
+    /// Create a `NormalizedText` which has not yet been normalized.
+    pub fn new(original: &'a str) -> NormalizedText<'a> {
+        NormalizedText {
+            original,
+            normalized: Cow::Borrowed(original),
+            offsets: None,
+        }
+    }
+
+    /// Apply `normalizer` to the normalized text.
+    pub fn normalize(self, normalizer: &Normalizer) -> NormalizedText<'a> {
+        if normalizer.is_noop() {
+            return self;
+        }
+        let (normalized, offsets) = normalizer.normalize(&self.normalized);
+        let offsets = match self.offsets {
+            Some(prev_offsets) => offsets.into_iter().map(|i| prev_offsets[i]).collect(),
+            None => offsets,
+        };
+        NormalizedText {
+            original: self.original,
+            normalized: Cow::Owned(normalized),
+            offsets: Some(offsets),
+        }
+    }
+
+    /// Return the text before normalization.
+    pub fn original(&self) -> &'a str {
+        self.original
+    }
+
+    /// Return the normalized text.
+    pub fn normalized(&self) -> &str {
+        &self.normalized
+    }
+
+    /// Map a byte offset in the normalized text to the offset of the
+    /// character in the original text which produced it.
+    ///
+    /// Offsets at or past the end of the normalized text map to the end of
+    /// the original text.
+    pub fn original_offset(&self, offset: usize) -> usize {
+        match &self.offsets {
+            Some(offsets) => offsets.get(offset).copied().unwrap_or(self.original.len()),
+            None => offset.min(self.original.len()),
+        }
+    }
+
+    /// Map a byte range in the normalized text to the range of the original
+    /// text which produced it.
+    pub fn original_range(&self, range: Range<usize>) -> Range<usize> {
+        match &self.offsets {
+            Some(offsets) => map_range(self.original, offsets, range),
+            None => range,
+        }
+    }
+
+    /// Map a byte range in the original text to the range of the normalized
+    /// text which was produced from it.
+    ///
+    /// The result is empty if all of the characters in `range` were removed
+    /// by normalization.
+    pub fn normalized_range(&self, range: Range<usize>) -> Range<usize> {
+        let Some(offsets) = &self.offsets else {
+            return range;
+        };
+        let start = offsets
+            .iter()
+            .position(|&offset| offset >= range.start)
+            .unwrap_or(offsets.len());
+        let mut end = offsets
+            .iter()
+            .rposition(|&offset| offset < range.end)
+            .map_or(start, |last| last + 1);
+        while !self.normalized.is_char_boundary(end) {
+            end += 1;
+        }
+        start..end.max(start)
+    }
+
+    /// Return the normalized text and the map from byte offsets in the
+    /// normalized text to byte offsets in the original text.
+    pub fn into_parts(self) -> (String, Vec<usize>) {
+        let offsets = self
+            .offsets
+            .unwrap_or_else(|| (0..self.original.len()).collect());
+        (self.normalized.into_owned(), offsets)
     }
 }
 
+/// Map a byte range in a normalized string to the corresponding range in the
+/// `source` string, using an `offset_map` returned by [Normalizer::normalize].
+///
+/// The end of the returned range is the end of the source character that
+/// produced the last byte in `range`.
+pub(crate) fn map_range(source: &str, offset_map: &[usize], range: Range<usize>) -> Range<usize> {
+    let start = offset_map.get(range.start).copied().unwrap_or(source.len());
+    if range.is_empty() {
+        return start..start;
+    }
+    let last = offset_map[range.end - 1];
+    let end = last + source[last..].chars().next().map_or(0, |ch| ch.len_utf8());
+    start..end
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{Normalizer, NormalizerOptions};
+    use unicode_normalization::UnicodeNormalization;
+
+    use super::{NormalizationForm, Normalize, NormalizedText, Normalizer, NormalizerOptions};
 
     #[test]
     fn test_normalizer_noop() {
@@ -199,6 +753,88 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_normalizer_case_fold() {
+        struct Case<'a> {
+            input: &'a str,
+            case_fold: bool,
+            turkic_lowercase: bool,
+            expected: &'a str,
+            expected_offsets: Vec<usize>,
+        }
+
+        let cases = [
+            // Lowercasing leaves "ß" and "ς" unchanged.
+            Case {
+                input: "Straße Σς",
+                case_fold: false,
+                turkic_lowercase: false,
+                expected: "straße σς",
+                expected_offsets: vec![0, 1, 2, 3, 4, 4, 6, 7, 8, 8, 10, 10],
+            },
+            // Case folding expands "ß" into "ss". Both characters map to the
+            // source character.
+            Case {
+                input: "Straße Σς",
+                case_fold: true,
+                turkic_lowercase: false,
+                expected: "strasse σσ",
+                expected_offsets: vec![0, 1, 2, 3, 4, 4, 6, 7, 8, 8, 10, 10],
+            },
+            // Capital sharp S, ligatures and Cherokee, which folds to
+            // uppercase.
+            Case {
+                input: "ẞﬁꭰ",
+                case_fold: true,
+                turkic_lowercase: false,
+                expected: "ssfiᎠ",
+                expected_offsets: vec![0, 0, 3, 3, 6, 6, 6],
+            },
+            // Dotless "ı" has no case folding.
+            Case {
+                input: "Iı",
+                case_fold: true,
+                turkic_lowercase: false,
+                expected: "iı",
+                expected_offsets: vec![0, 1, 1],
+            },
+            // Turkish dotted and dotless I.
+            Case {
+                input: "IİAı",
+                case_fold: false,
+                turkic_lowercase: true,
+                expected: "ıiaı",
+                expected_offsets: vec![0, 0, 1, 3, 4, 4],
+            },
+            Case {
+                input: "Iİß",
+                case_fold: true,
+                turkic_lowercase: true,
+                expected: "ıiss",
+                expected_offsets: vec![0, 0, 1, 3, 3],
+            },
+        ];
+
+        for Case {
+            input,
+            case_fold,
+            turkic_lowercase,
+            expected,
+            expected_offsets,
+        } in cases
+        {
+            let normalizer = Normalizer::new(NormalizerOptions {
+                lowercase: true,
+                case_fold,
+                turkic_lowercase,
+                ..Default::default()
+            });
+            let (normalized, offsets) = normalizer.normalize(input);
+            assert_eq!(normalized, expected);
+            assert_eq!(offsets, expected_offsets);
+        }
+    }
+
     #[test]
     fn test_normalizer_strip_accepts() {
         struct Case<'a> {
@@ -247,4 +883,260 @@ mod tests {
             assert_eq!(offsets, expected_offsets);
         }
     }
+
+    #[test]
+    fn test_normalizer_whitespace() {
+        let normalizer = Normalizer::new(NormalizerOptions {
+            remove_extra_whitespace: true,
+            replace_space: Some('▁'),
+            prepend: Some("▁".to_string()),
+            ..Default::default()
+        });
+
+        let (normalized, offsets) = normalizer.normalize("  Hello   world ");
+        assert_eq!(normalized, "▁Hello▁world");
+
+        // The prefix maps to the start of the first non-space character.
+        assert_eq!(&offsets[..3], &[2, 2, 2]);
+        assert_eq!(offsets[3], 2);
+        // Offsets of the collapsed space map to the first space in the run.
+        assert_eq!(&offsets[8..11], &[7, 7, 7]);
+        assert_eq!(offsets[11], 10);
+
+        // Nothing is prepended to empty text.
+        let (normalized, offsets) = normalizer.normalize("   ");
+        assert_eq!(normalized, "");
+        assert!(offsets.is_empty());
+    }
+
+    #[test]
+    fn test_normalizer_strip_accents_standalone() {
+        let normalizer = Normalizer::strip_accents();
+
+        let (normalized, offsets) = normalizer.normalize("Mote\u{308}rhead");
+        assert_eq!(normalized, "Moterhead");
+        assert_eq!(offsets, &[0, 1, 2, 3, 6, 7, 8, 9, 10]);
+
+        // Precomposed characters are not decomposed.
+        let (normalized, _) = normalizer.normalize("Motörhead");
+        assert_eq!(normalized, "Motörhead");
+    }
+
+    #[test]
+    fn test_normalizer_sequence() {
+        let normalizer = Normalizer::sequence(vec![
+            Normalizer::new(NormalizerOptions {
+                lowercase: true,
+                ..Default::default()
+            }),
+            Normalizer::strip_accents(),
+            Normalizer::new(NormalizerOptions {
+                prepend: Some("_".to_string()),
+                ..Default::default()
+            }),
+        ]);
+
+        let (normalized, offsets) = normalizer.normalize("İA\u{301}B");
+        assert_eq!(normalized, "_iab");
+
+        // Offsets are mapped through each step back to the original text.
+        assert_eq!(offsets, &[0, 0, 2, 5]);
+
+        let noop = Normalizer::sequence(vec![Normalizer::new(NormalizerOptions::default())]);
+        assert_eq!(noop.normalize("Abc").0, "Abc");
+    }
+
+    #[test]
+    fn test_normalized_text() {
+        // Text which has not been normalized.
+        let text = NormalizedText::new("Héllo");
+        assert_eq!(text.normalized(), "Héllo");
+        assert_eq!(text.original_range(1..3), 1..3);
+        assert_eq!(text.normalized_range(1..3), 1..3);
+        assert_eq!(
+            text.into_parts(),
+            ("Héllo".to_string(), vec![0, 1, 2, 3, 4, 5])
+        );
+
+        // Offsets are tracked through each normalization step.
+        let original = "ÉCOLE  d'Été";
+        let text = NormalizedText::new(original)
+            .normalize(&Normalizer::new(NormalizerOptions {
+                lowercase: true,
+                ..Default::default()
+            }))
+            .normalize(&Normalizer::unicode(NormalizationForm::Nfd))
+            .normalize(&Normalizer::strip_accents())
+            .normalize(&Normalizer::replace("  ", "▁"));
+        assert_eq!(text.original(), original);
+        assert_eq!(text.normalized(), "ecole▁d'ete");
+
+        // "ecole" => "ÉCOLE"
+        assert_eq!(text.original_range(0..5), 0..6);
+        assert_eq!(&original[text.original_range(0..5)], "ÉCOLE");
+        // "▁" => "  "
+        assert_eq!(&original[text.original_range(5..8)], "  ");
+        // "ete" => "Été"
+        assert_eq!(&original[text.original_range(10..13)], "Été");
+
+        assert_eq!(text.original_offset(0), 0);
+        assert_eq!(text.original_offset(1), 2);
+        assert_eq!(text.original_offset(100), original.len());
+
+        assert_eq!(text.normalized_range(0..6), 0..5);
+        assert_eq!(&text.normalized()[text.normalized_range(10..14)], "ete");
+        assert_eq!(text.normalized_range(0..0), 0..0);
+
+        let (normalized, offsets) = text.into_parts();
+        assert_eq!(normalized, "ecole▁d'ete");
+        assert_eq!(offsets.len(), normalized.len());
+    }
+
+    #[test]
+    fn test_normalizer_unicode() {
+        struct Case<'a> {
+            input: &'a str,
+            form: NormalizationForm,
+            expected: &'a str,
+            expected_offsets: Vec<usize>,
+        }
+
+        let cases = [
+            // Composition of a base character and combining mark.
+            Case {
+                input: "cafe\u{301}!",
+                form: NormalizationForm::Nfc,
+                expected: "café!",
+                expected_offsets: vec![0, 1, 2, 3, 3, 6],
+            },
+            // Decomposition of a precomposed character.
+            Case {
+                input: "café",
+                form: NormalizationForm::Nfd,
+                expected: "cafe\u{301}",
+                expected_offsets: vec![0, 1, 2, 3, 3, 3],
+            },
+            // Reordering of combining marks. U+0323 (class 220) comes before
+            // U+0301 (class 230).
+            Case {
+                input: "a\u{301}\u{323}",
+                form: NormalizationForm::Nfd,
+                expected: "a\u{323}\u{301}",
+                expected_offsets: vec![0, 3, 3, 1, 1],
+            },
+            // Composition after reordering.
+            Case {
+                input: "a\u{301}\u{323}",
+                form: NormalizationForm::Nfc,
+                expected: "\u{1ea1}\u{301}",
+                expected_offsets: vec![0, 0, 0, 1, 1],
+            },
+            // Compatibility decomposition.
+            Case {
+                input: "ﬁx",
+                form: NormalizationForm::Nfkd,
+                expected: "fix",
+                expected_offsets: vec![0, 0, 3],
+            },
+            // Compatibility decomposition is not applied by NFC.
+            Case {
+                input: "ﬁ",
+                form: NormalizationForm::Nfc,
+                expected: "ﬁ",
+                expected_offsets: vec![0, 0, 0],
+            },
+            // Hangul syllable composed from jamo.
+            Case {
+                input: "\u{1100}\u{1161}\u{11a8}",
+                form: NormalizationForm::Nfkc,
+                expected: "\u{ac01}",
+                expected_offsets: vec![0, 0, 0],
+            },
+        ];
+
+        for Case {
+            input,
+            form,
+            expected,
+            expected_offsets,
+        } in cases
+        {
+            let (normalized, offsets) = Normalizer::unicode(form).normalize(input);
+            assert_eq!(normalized, expected);
+            assert_eq!(offsets, expected_offsets);
+        }
+
+        // Compare against the `unicode-normalization` iterators.
+        let text = "Ǆemal Ⅻ ﬃ Å Å 한국어 \u{1100}\u{1161} e\u{302}\u{323}";
+        for (form, expected) in [
+            (NormalizationForm::Nfc, text.nfc().collect::<String>()),
+            (NormalizationForm::Nfd, text.nfd().collect()),
+            (NormalizationForm::Nfkc, text.nfkc().collect()),
+            (NormalizationForm::Nfkd, text.nfkd().collect()),
+        ] {
+            let (normalized, offsets) = Normalizer::unicode(form).normalize(text);
+            assert_eq!(normalized, expected);
+            assert_eq!(offsets.len(), normalized.len());
+        }
+    }
+
+    #[test]
+    fn test_normalizer_replace() {
+        let normalizer = Normalizer::replace(" ", "▁");
+        let (normalized, offsets) = normalizer.normalize("a b");
+        assert_eq!(normalized, "a▁b");
+        assert_eq!(offsets, &[0, 1, 1, 1, 2]);
+
+        // Regex replacement, where matches have a different length to the
+        // replacement.
+        let normalizer = Normalizer::replace_regex(r"\s+", " ").unwrap();
+        let (normalized, offsets) = normalizer.normalize("foo \t\n bar  baz");
+        assert_eq!(normalized, "foo bar baz");
+        assert_eq!(offsets, &[0, 1, 2, 3, 7, 8, 9, 10, 12, 13, 14]);
+
+        // Empty matches are ignored.
+        let normalizer = Normalizer::replace_regex(r"(?<=\d)(?=(\d{3})+$)", ",").unwrap();
+        let (normalized, _) = normalizer.normalize("1234567");
+        assert_eq!(normalized, "1234567");
+
+        // Regex replacement with lookaround.
+        let normalizer = Normalizer::replace_regex(r"(?<=[a-z])X", "").unwrap();
+        let (normalized, offsets) = normalizer.normalize("aXbX X");
+        assert_eq!(normalized, "ab X");
+        assert_eq!(offsets, &[0, 2, 4, 5]);
+
+        assert!(Normalizer::replace_regex("(", "").is_err());
+    }
+
+    #[test]
+    fn test_normalizer_custom() {
+        /// Normalizer which removes ASCII digits.
+        struct RemoveDigits;
+
+        impl Normalize for RemoveDigits {
+            fn normalize(&self, text: &str) -> (String, Vec<usize>) {
+                let mut normalized = String::new();
+                let mut offsets = Vec::new();
+                for (offset, ch) in text.char_indices() {
+                    if !ch.is_ascii_digit() {
+                        normalized.push(ch);
+                        offsets.extend(std::iter::repeat_n(offset, ch.len_utf8()));
+                    }
+                }
+                (normalized, offsets)
+            }
+        }
+
+        // Custom normalizers can be combined with built-in ones.
+        let normalizer = Normalizer::sequence(vec![
+            Normalizer::custom(RemoveDigits),
+            Normalizer::new(NormalizerOptions {
+                lowercase: true,
+                ..Default::default()
+            }),
+        ]);
+        let (normalized, offsets) = normalizer.normalize("A1b2C");
+        assert_eq!(normalized, "abc");
+        assert_eq!(offsets, &[0, 2, 4]);
+    }
 }
@@ -1,6 +1,10 @@
 use std::iter::StepBy;
+use std::ops::Range;
 use std::slice::Windows;
 
+use regex_automata::meta;
+use regex_automata::{Anchored, Input};
+
 /// Iterator over chunks of a slice, with an overlap between each chunk and
 /// the next.
 pub struct OverlappingChunks<'a, T> {
@@ -57,47 +61,98 @@ impl<T> SliceExt for [T] {
     }
 }
 
-/// Iterator returned by [SplitExt::split_keep_delimeters].
+/// Result of a pure delimiter predicate for each ASCII char.
+struct AsciiDelims {
+    is_delim: [bool; 128],
+}
+
+impl AsciiDelims {
+    fn new<P: FnMut(char) -> bool>(predicate: &mut P) -> AsciiDelims {
+        let mut is_delim = [false; 128];
+        for (byte, is_delim) in is_delim.iter_mut().enumerate() {
+            *is_delim = predicate(byte as u8 as char);
+        }
+        AsciiDelims { is_delim }
+    }
+}
+
+/// Iterator returned by [SplitExt::split_keep_delimeters] and
+/// [SplitExt::split_keep_delimeters_pure].
 pub struct SplitKeepDelim<'a, P: FnMut(char) -> bool> {
     remainder: &'a str,
     predicate: P,
+
+    /// Table used to classify ASCII chars without calling `predicate`. This
+    /// is only set if the predicate is known to be pure.
+    ascii_delims: Option<AsciiDelims>,
+}
+
+impl<'a, P: FnMut(char) -> bool> SplitKeepDelim<'a, P> {
+    /// Return the byte index and length of the next delimiter, using the
+    /// ASCII lookup table.
+    fn find_delim_ascii(&mut self, ascii_delims: &AsciiDelims) -> Option<(usize, usize)> {
+        // Scan bytes rather than chars, so that ASCII text can be classified
+        // without decoding.
+        let bytes = self.remainder.as_bytes();
+        let mut index = 0;
+        while index < bytes.len() {
+            let byte = bytes[index];
+            let (is_delim, char_len) = if byte.is_ascii() {
+                (ascii_delims.is_delim[byte as usize], 1)
+            } else {
+                let ch = self.remainder[index..].chars().next().unwrap();
+                ((self.predicate)(ch), ch.len_utf8())
+            };
+            if is_delim {
+                return Some((index, char_len));
+            }
+            index += char_len;
+        }
+        None
+    }
+
+    /// Return the byte index and length of the next delimiter, calling the
+    /// predicate for each char in turn.
+    fn find_delim(&mut self) -> Option<(usize, usize)> {
+        self.remainder
+            .char_indices()
+            .find(|&(_, ch)| (self.predicate)(ch))
+            .map(|(index, ch)| (index, ch.len_utf8()))
+    }
 }
 
 impl<'a, P: FnMut(char) -> bool> Iterator for SplitKeepDelim<'a, P> {
     type Item = &'a str;
 
     fn next(&mut self) -> Option<&'a str> {
-        for (index, ch) in self.remainder.char_indices() {
-            if !(self.predicate)(ch) {
-                continue;
-            }
-            if index == 0 {
-                let mut next_index = 1;
-                while !self.remainder.is_char_boundary(next_index) {
-                    next_index += 1;
-                }
-                let substr = &self.remainder[..next_index];
-                self.remainder = &self.remainder[next_index..];
-                return Some(substr);
-            } else {
-                let substr = &self.remainder[..index];
-                self.remainder = &self.remainder[index..];
-                return Some(substr);
-            }
-        }
-        if !self.remainder.is_empty() {
-            let substr = self.remainder;
-            self.remainder = "";
-            Some(substr)
-        } else {
-            None
+        if self.remainder.is_empty() {
+            return None;
         }
+
+        let ascii_delims = self.ascii_delims.take();
+        let delim = match &ascii_delims {
+            Some(ascii_delims) => self.find_delim_ascii(ascii_delims),
+            None => self.find_delim(),
+        };
+        self.ascii_delims = ascii_delims;
+
+        let end = match delim {
+            Some((0, char_len)) => char_len,
+            Some((index, _)) => index,
+            None => self.remainder.len(),
+        };
+        let (substr, remainder) = self.remainder.split_at(end);
+        self.remainder = remainder;
+        Some(substr)
     }
 }
 
 pub trait SplitExt<'a> {
     /// Split a string but retain the delimeters.
     ///
+    /// `predicate` is called for each char in order, until a delimiter is
+    /// found.
+    ///
     /// ```text
     /// use crate::split::SplitExt;
     ///
@@ -105,7 +160,16 @@ pub trait SplitExt<'a> {
     /// let tokens: Vec<_> = str.split_keep_delimeters(|ch| ch.is_ascii_punctuation()).collect();
     /// assert_eq!(tokens, &["foo", ".", "bar"]);
     /// ```
+    #[allow(dead_code)] // The tokenizers use `split_keep_delimeters_pure`.
     fn split_keep_delimeters<P: FnMut(char) -> bool>(self, predicate: P) -> SplitKeepDelim<'a, P>;
+
+    /// Variant of [split_keep_delimeters](SplitExt::split_keep_delimeters)
+    /// for predicates which always return the same result for a given char.
+    ///
+    /// The predicate is evaluated for all ASCII chars up front, so that ASCII
+    /// text can be scanned without calling it for each char.
+    fn split_keep_delimeters_pure<P: Fn(char) -> bool>(self, predicate: P)
+        -> SplitKeepDelim<'a, P>;
 }
 
 impl<'a> SplitExt<'a> for &'a str {
@@ -113,13 +177,190 @@ impl<'a> SplitExt<'a> for &'a str {
         SplitKeepDelim {
             remainder: self,
             predicate,
+            ascii_delims: None,
+        }
+    }
+
+    fn split_keep_delimeters_pure<P: Fn(char) -> bool>(
+        self,
+        mut predicate: P,
+    ) -> SplitKeepDelim<'a, P> {
+        SplitKeepDelim {
+            remainder: self,
+            ascii_delims: Some(AsciiDelims::new(&mut predicate)),
+            predicate,
         }
     }
 }
 
+/// Suffix of GPT-2 style patterns which matches whitespace that is not
+/// followed by a non-whitespace character, or any remaining whitespace.
+const TRAILING_WHITESPACE_ALTS: &str = r"|\s+(?!\S)|\s+";
+
+#[derive(Clone, Debug)]
+enum SplitRegexKind {
+    /// Pattern ending with [TRAILING_WHITESPACE_ALTS], compiled to finite
+    /// automata.
+    ///
+    /// `regex` is the pattern with the suffix replaced by `|\s+` and
+    /// `prefix` is the pattern without the suffix.
+    Automata {
+        regex: meta::Regex,
+        prefix: meta::Regex,
+    },
+
+    /// Any other pattern.
+    Fancy(fancy_regex::Regex),
+}
+
+/// Regex used to split text into pieces prior to tokenization.
+///
+/// Patterns are matched using [fancy_regex](https://crates.io/crates/fancy-regex),
+/// which uses a finite automaton for patterns that don't need backtracking.
+/// GPT-2 style patterns end with `\s+(?!\S)|\s+`, and the look-ahead
+/// would otherwise force the whole pattern to use backtracking, which is
+/// much slower. For these patterns the look-ahead is emulated by trimming
+/// matches, so the rest of the pattern can be compiled to an automaton using
+/// [regex_automata](https://crates.io/crates/regex-automata).
+#[derive(Clone, Debug)]
+pub struct SplitRegex {
+    kind: SplitRegexKind,
+}
+
+impl SplitRegex {
+    pub fn new(pattern: &str) -> Result<SplitRegex, Box<fancy_regex::Error>> {
+        let fancy = fancy_regex::Regex::new(pattern)?;
+
+        let automata = pattern
+            .strip_suffix(TRAILING_WHITESPACE_ALTS)
+            .filter(|prefix| {
+                fancy_regex::Expr::parse_tree(prefix)
+                    .is_ok_and(|tree| !needs_backtracking(&tree.expr))
+            })
+            .and_then(|prefix| {
+                let regex = meta::Regex::new(&format!(r"(?:{prefix})|\s+")).ok()?;
+                let prefix = meta::Regex::new(prefix).ok()?;
+                Some((regex, prefix))
+            });
+
+        let kind = match automata {
+            Some((regex, prefix)) => SplitRegexKind::Automata { regex, prefix },
+            None => SplitRegexKind::Fancy(fancy),
+        };
+        Ok(SplitRegex { kind })
+    }
+
+    /// Return an iterator over the byte ranges of non-overlapping matches of
+    /// the pattern in `text`.
+    ///
+    /// Matching fails if a pattern which requires backtracking exceeds the
+    /// backtracking limit.
+    pub fn find_iter<'a>(
+        &'a self,
+        text: &'a str,
+    ) -> impl Iterator<Item = Result<Range<usize>, Box<fancy_regex::Error>>> + 'a {
+        let matches: Box<dyn Iterator<Item = _>> = match &self.kind {
+            SplitRegexKind::Automata { regex, prefix } => Box::new(EmulatedLookaheadMatches {
+                regex,
+                prefix,
+                text,
+                pos: 0,
+            }),
+            SplitRegexKind::Fancy(regex) => Box::new(
+                regex
+                    .find_iter(text)
+                    .map(|m| m.map(|m| m.range()).map_err(Box::new)),
+            ),
+        };
+        matches
+    }
+}
+
+/// Return true if `expr` uses features which fancy_regex implements using
+/// backtracking, such as look-around assertions, backreferences and
+/// possessive quantifiers.
+fn needs_backtracking(expr: &fancy_regex::Expr) -> bool {
+    use fancy_regex::Expr;
+
+    match expr {
+        Expr::Empty | Expr::Any { .. } | Expr::Assertion(_) | Expr::Literal { .. } => false,
+        Expr::Delegate { .. } => false,
+        Expr::Concat(children) | Expr::Alt(children) => children.iter().any(needs_backtracking),
+        Expr::Group(child) => needs_backtracking(child),
+        Expr::Repeat { child, .. } => needs_backtracking(child),
+        Expr::LookAround(..)
+        | Expr::Backref(_)
+        | Expr::AtomicGroup(_)
+        | Expr::KeepOut
+        | Expr::ContinueFromPreviousMatchEnd
+        | Expr::BackrefExistsCondition(_)
+        | Expr::Conditional { .. } => true,
+    }
+}
+
+/// Iterator over matches of a pattern where `\s+(?!\S)|\s+` has been
+/// replaced by `\s+`.
+///
+/// The look-ahead only changes the result when the whitespace is followed by
+/// a non-whitespace character, in which case the match excludes the last
+/// whitespace character, unless that would leave it empty. Matches which
+/// could have come from the rest of the pattern are not changed.
+struct EmulatedLookaheadMatches<'a> {
+    regex: &'a meta::Regex,
+    prefix: &'a meta::Regex,
+    text: &'a str,
+    pos: usize,
+}
+
+impl Iterator for EmulatedLookaheadMatches<'_> {
+    type Item = Result<Range<usize>, Box<fancy_regex::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos > self.text.len() {
+            return None;
+        }
+        let input = Input::new(self.text).range(self.pos..);
+        let mut range = self.regex.search(&input)?.range();
+
+        let matched = &self.text[range.clone()];
+        let followed_by_non_space = self.text[range.end..]
+            .chars()
+            .next()
+            .is_some_and(|ch| !ch.is_whitespace());
+        if followed_by_non_space
+            && matched.chars().nth(1).is_some()
+            && matched.chars().all(char::is_whitespace)
+        {
+            // Alternatives are tried in order, so the match came from `\s+`
+            // only if the rest of the pattern does not match here.
+            let prefix_input = Input::new(self.text)
+                .range(range.start..)
+                .anchored(Anchored::Yes);
+            if self.prefix.search(&prefix_input).is_none() {
+                let last_char = matched.chars().next_back().unwrap();
+                range.end -= last_char.len_utf8();
+            }
+        }
+
+        self.pos = if range.is_empty() {
+            range.end
+                + self.text[range.end..]
+                    .chars()
+                    .next()
+                    .map_or(1, |ch| ch.len_utf8())
+        } else {
+            range.end
+        };
+        Some(Ok(range))
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{SliceExt, SplitExt};
+    use unicode_categories::UnicodeCategories;
+
+    use super::{SliceExt, SplitExt, SplitRegex, SplitRegexKind};
+    use crate::tokenizers::patterns;
 
     #[test]
     fn test_chunks_overlap() {
@@ -230,4 +471,148 @@ mod tests {
             assert_eq!(words_and_puncs, expected,);
         }
     }
+
+    #[test]
+    fn test_split_keep_delimeters_long_text() {
+        // Reference implementation which classifies each char in turn.
+        fn reference_split<P: Fn(char) -> bool>(text: &str, predicate: P) -> Vec<&str> {
+            let mut tokens = Vec::new();
+            let mut start = 0;
+            for (index, ch) in text.char_indices() {
+                if predicate(ch) {
+                    if index > start {
+                        tokens.push(&text[start..index]);
+                    }
+                    tokens.push(&text[index..index + ch.len_utf8()]);
+                    start = index + ch.len_utf8();
+                }
+            }
+            if start < text.len() {
+                tokens.push(&text[start..]);
+            }
+            tokens
+        }
+
+        // Include ASCII and non-ASCII letters and punctuation.
+        let text = "Antidisestablishmentarianism, naïve café—résumé! \
+            abcdefghijklmnop.qrstuvwxyz0123456789 a.bc.def.ghij.klmno";
+
+        let predicates: [fn(char) -> bool; 3] = [
+            |c| c.is_ascii_punctuation() || c.is_whitespace(),
+            |c| !c.is_alphanumeric(),
+            // Predicate where ASCII letters are delimiters.
+            |c| c == 'e' || c == ' ',
+        ];
+        for predicate in predicates {
+            let expected = reference_split(text, predicate);
+            let tokens: Vec<_> = text.split_keep_delimeters(predicate).collect();
+            assert_eq!(tokens, expected);
+            let tokens: Vec<_> = text.split_keep_delimeters_pure(predicate).collect();
+            assert_eq!(tokens, expected);
+        }
+    }
+
+    #[test]
+    fn test_split_keep_delimeters_lazy_predicate() {
+        // The predicate is only called for chars up to the end of the
+        // returned token.
+        let mut calls = Vec::new();
+        let first = "ab.cd"
+            .split_keep_delimeters(|ch| {
+                calls.push(ch);
+                ch == '.'
+            })
+            .next();
+        assert_eq!(first, Some("ab"));
+        assert_eq!(calls, ['a', 'b', '.']);
+    }
+
+    #[test]
+    fn test_split_regex() {
+        let texts = [
+            "",
+            "Hello world!",
+            "  leading and trailing spaces  ",
+            "multiple   spaces\tand\ttabs\n\nnewlines \n x",
+            "it's 2024, isn't it? Ça va\u{3000}très bien\u{3000}\u{3000}!",
+            "code:\n    if x:\n        return y\n",
+        ];
+
+        let patterns = [
+            (patterns::GPT2, true),
+            // Pattern where other alternatives can match whitespace.
+            (r"\s*[\r\n]+|\p{L}+|\s+(?!\S)|\s+", true),
+            // Possessive quantifiers require backtracking.
+            (patterns::CL100K, false),
+            // Patterns without look-around use fancy_regex, which compiles
+            // them to an automaton itself.
+            (r"\w+|[^\w\s]+", false),
+            (r"\s+", false),
+            // Escaped `|` is part of the previous alternative.
+            (r"a\|\s+(?!\S)|\s+", false),
+        ];
+
+        for (pattern, uses_automata) in patterns {
+            let regex = SplitRegex::new(pattern).unwrap();
+            assert_eq!(
+                matches!(regex.kind, SplitRegexKind::Automata { .. }),
+                uses_automata,
+                "pattern {pattern}"
+            );
+
+            let reference = fancy_regex::Regex::new(pattern).unwrap();
+            for text in texts {
+                let matches: Vec<_> = regex.find_iter(text).map(|m| m.unwrap()).collect();
+                let expected: Vec<_> = reference
+                    .find_iter(text)
+                    .map(|m| m.unwrap().range())
+                    .collect();
+                assert_eq!(matches, expected, "pattern {pattern} text {text:?}");
+            }
+        }
+
+        assert!(SplitRegex::new("(").is_err());
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_split_keep_delimeters() {
+        use rten_bench::run_bench;
+
+        let text = "The quick brown fox jumps over the lazy dog. Antidisestablishmentarianism, \
+            it's 2024 and the dog isn't lazy any more!\n"
+            .repeat(2000);
+        // Predicate used by the WordPiece and WordLevel tokenizers.
+        let is_punc_or_space =
+            |ch: char| ch.is_ascii_punctuation() || ch.is_punctuation() || ch.is_whitespace();
+
+        run_bench(10, Some("split_keep_delimeters"), || {
+            assert!(text.split_keep_delimeters(is_punc_or_space).count() > 0);
+        });
+        run_bench(10, Some("split_keep_delimeters_pure"), || {
+            assert!(text.split_keep_delimeters_pure(is_punc_or_space).count() > 0);
+        });
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_split_regex() {
+        use rten_bench::run_bench;
+
+        let text = "The quick brown fox jumps over the lazy dog.  It's 2024, \
+            and   the dog\tisn't lazy   any more!\n\n"
+            .repeat(2000);
+
+        for (name, pattern) in [("GPT2", patterns::GPT2), ("CL100K", patterns::CL100K)] {
+            let regex = SplitRegex::new(pattern).unwrap();
+            let reference = fancy_regex::Regex::new(pattern).unwrap();
+
+            run_bench(10, Some(&format!("{name} split regex")), || {
+                assert!(regex.find_iter(&text).count() > 0);
+            });
+            run_bench(10, Some(&format!("{name} fancy-regex")), || {
+                assert!(reference.find_iter(&text).count() > 0);
+            });
+        }
+    }
 }
@@ -1,28 +1,51 @@
 //! Tokenizers for converting text into sequences of token IDs.
 //!
-//! There are two ways to construct a tokenizer:
+//! There are three ways to construct a tokenizer:
 //!
 //! 1. Load a preconfigured tokenizer from JSON, using [Tokenizer::from_json].
 //!    This crate supports a subset of the `tokenizer.json` format that
 //!    Hugging Face Tokenizers generates.
 //!
-//! 2. Manually configure a [Tokenizer] by creating an [Encoder] implementation,
-//!    such as [WordPiece] and then wrap it with a tokenizer using
+//! 2. Load a SentencePiece `.model` file, using
+//!    [Tokenizer::from_sentencepiece].
+//!
+//! 3. Manually configure a [Tokenizer] by creating an [Encoder] implementation,
+//!    such as [WordPiece] or [WordLevel] and then wrap it with a tokenizer using
 //!    [Tokenizer::new].
 
+use std::cmp::Reverse;
 use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
-use std::iter::repeat;
+use std::iter::repeat_n;
 use std::ops::Range;
 
-use crate::normalizer::{Normalizer, NormalizerOptions};
+use crate::normalizer::{map_range, NormalizationForm, Normalizer, NormalizerOptions};
 use crate::split::SliceExt;
+use decoders::Decoder;
+use pre_tokenizers::{PreToken, PreTokenizer};
 
+mod added_tokens;
 mod bpe;
+pub mod decoders;
 mod json;
+pub mod pre_tokenizers;
+mod prune;
+mod registry;
+mod sentencepiece;
+mod template;
+mod unigram;
+mod wordlevel;
 mod wordpiece;
+use added_tokens::AddedTokenMatcher;
 pub use bpe::{patterns, Bpe, BpeError};
+use prune::PrunedEncoder;
+pub use prune::VocabMapping;
+pub use registry::{ComponentRegistry, CustomComponentError};
+use template::{SequenceId, TemplatePiece, TemplateToken};
+pub use template::{Template, TemplateError};
+pub use unigram::{Unigram, UnigramOptions};
+pub use wordlevel::{WordLevel, WordLevelOptions};
 pub use wordpiece::{WordPiece, WordPieceOptions};
 
 /// Input sequences for [Tokenizer::encode].
@@ -34,6 +57,38 @@ pub enum EncoderInput<'a> {
     /// Encoder input with a pair of sequences. Used in tasks such as extractive
     /// question answering, where the sequence is `(query, context)`.
     Pair((&'a str, &'a str)),
+
+    /// Encoder input with a single sequence which has already been split
+    /// into words. Used in tasks such as token classification, where the
+    /// word boundaries are defined outside of the tokenizer.
+    ///
+    /// The tokenizer's pre-tokenizer is not applied to this input. Each word
+    /// is passed to the model separately, after matching any [AddedToken]s,
+    /// and the tokens produced from it have the word's index as their word
+    /// ID. Special tokens are added using the tokenizer's [Template] as for
+    /// [EncoderInput::Item].
+    Words(&'a [&'a str]),
+}
+
+impl<'a> EncoderInput<'a> {
+    /// Return the total length of the input sequences in bytes.
+    fn text_len(&self) -> usize {
+        match self {
+            EncoderInput::Item(item) => item.len(),
+            EncoderInput::Pair((first, second)) => first.len() + second.len(),
+            EncoderInput::Words(words) => words.iter().map(|word| word.len()).sum(),
+        }
+    }
+
+    /// Return the texts that make up the input. These are the input
+    /// sequences, or the words of a pre-split input.
+    fn texts(&self) -> Vec<&'a str> {
+        match *self {
+            EncoderInput::Item(item) => vec![item],
+            EncoderInput::Pair((first, second)) => vec![first, second],
+            EncoderInput::Words(words) => words.to_vec(),
+        }
+    }
 }
 
 /// Construct a tokenizer input with a single sequence.
@@ -50,6 +105,14 @@ impl<'a> From<(&'a str, &'a str)> for EncoderInput<'a> {
     }
 }
 
+/// Construct a tokenizer input from a sequence that is already split into
+/// words.
+impl<'a> From<&'a [&'a str]> for EncoderInput<'a> {
+    fn from(val: &'a [&'a str]) -> EncoderInput<'a> {
+        EncoderInput::Words(val)
+    }
+}
+
 /// Integer type used to represent token IDs.
 pub type TokenId = u32;
 
@@ -63,30 +126,86 @@ pub struct Encoded<'a> {
     input: EncoderInput<'a>,
     token_ids: Vec<TokenId>,
 
-    /// Number of tokens in `token_ids` that were generated from the first
-    /// sequence in the input. This includes the `[CLS]` and `[SEP]` tokens
-    /// which come before and after the sequence respectively.
-    first_seq_tokens: usize,
+    /// Token type IDs for the tokens in `token_ids`, excluding padding.
+    type_ids: Vec<usize>,
+
+    /// Word indices for the tokens in `token_ids`, excluding padding. This
+    /// is `None` for special tokens.
+    word_ids: Vec<Option<usize>>,
 
     /// Offsets of text corresponding to tokens in the input string. When the
     /// input contains two sentences, the offsets are relative to the string
     /// that a particular input that a token comes from.
     token_offsets: Vec<usize>,
+
+    /// Byte ranges of the input text that each token was produced from.
+    /// Offsets are assigned in the same way as `token_offsets`. The ranges
+    /// for special and padding tokens are empty.
+    token_spans: Vec<Range<usize>>,
+
+    /// Offset of the end of the text corresponding to the last token from
+    /// the input.
+    end_offset: usize,
+
+    /// Number of padding tokens at the start of `token_ids`.
+    pad_left: usize,
+
+    /// Number of padding tokens at the end of `token_ids`.
+    pad_right: usize,
+
+    /// Token type ID used for padding tokens.
+    pad_type_id: usize,
+
+    /// Units of the offsets returned by [Encoded::offset_mapping].
+    offset_type: OffsetType,
 }
 
 impl<'a> Encoded<'a> {
     fn new(
         input: EncoderInput<'a>,
         ids: Vec<TokenId>,
-        offsets: Vec<usize>,
-        first_seq_tokens: usize,
+        spans: Vec<Range<usize>>,
+        end_offset: usize,
+        type_ids: Vec<usize>,
+        word_ids: Vec<Option<usize>>,
     ) -> Encoded<'a> {
         Encoded {
             input,
             token_ids: ids,
-            token_offsets: offsets,
-            first_seq_tokens,
+            token_offsets: spans.iter().map(|span| span.start).collect(),
+            token_spans: spans,
+            end_offset,
+            type_ids,
+            word_ids,
+            pad_left: 0,
+            pad_right: 0,
+            pad_type_id: 0,
+            offset_type: OffsetType::default(),
+        }
+    }
+
+    /// Add padding tokens until the output has at least `len` tokens.
+    fn pad(&mut self, len: usize, padding: &Padding) {
+        let n_pad = len.saturating_sub(self.token_ids.len());
+        if n_pad == 0 {
+            return;
+        }
+        match padding.direction {
+            PaddingDirection::Left => {
+                self.token_ids.splice(0..0, repeat_n(padding.pad_id, n_pad));
+                self.token_offsets.splice(0..0, repeat_n(0, n_pad));
+                self.token_spans.splice(0..0, repeat_n(0..0, n_pad));
+                self.pad_left += n_pad;
+            }
+            PaddingDirection::Right => {
+                self.token_ids.extend(repeat_n(padding.pad_id, n_pad));
+                self.token_offsets.extend(repeat_n(self.end_offset, n_pad));
+                self.token_spans
+                    .extend(repeat_n(self.end_offset..self.end_offset, n_pad));
+                self.pad_right += n_pad;
+            }
         }
+        self.pad_type_id = padding.pad_type_id;
     }
 
     /// Return the sequence of token IDs that the input was tokenized into.
@@ -94,34 +213,162 @@ impl<'a> Encoded<'a> {
         &self.token_ids
     }
 
+    /// Return the token strings for the token IDs in this encoding.
+    ///
+    /// `tokenizer` must be the tokenizer that produced this encoding. The
+    /// strings are the canonical representations of the tokens, as returned
+    /// by [Encoder::get_token_str], including any special and padding tokens.
+    /// This is useful for displaying the tokens, eg. in debugging tools.
+    pub fn tokens(&self, tokenizer: &Tokenizer) -> Result<Vec<String>, TokenizerError> {
+        tokenizer.encoder().get_tokens(&self.token_ids)
+    }
+
     /// Return the byte offsets of the start of each token in the input
     /// sequence. If the input contained two sequences, the offsets are assigned
-    /// as if the two sequences were concatenated.
+    /// as if the two sequences were concatenated. The same applies to the
+    /// words of an [EncoderInput::Words] input.
     pub fn token_offsets(&self) -> &[usize] {
         &self.token_offsets
     }
 
+    /// Return the `(start, end)` offsets of the text in the input that each
+    /// token was produced from.
+    ///
+    /// Offsets are character offsets by default, or byte offsets if
+    /// [EncodeOptions::offset_type] is [OffsetType::Byte]. They refer to the
+    /// original input, before any normalization was applied. If the input
+    /// contained two sequences, offsets are relative to the sequence that a
+    /// token came from. If the input was split into words, offsets are
+    /// relative to the word that a token came from. Special tokens and
+    /// padding have offsets of `(0, 0)`.
+    pub fn offset_mapping(&self) -> Vec<(usize, usize)> {
+        let texts = self.input.texts();
+        let text_offsets: Vec<Option<Vec<usize>>> = texts
+            .iter()
+            .map(|text| match self.offset_type {
+                OffsetType::Byte => None,
+                OffsetType::Char => Some(char_offsets(text)),
+            })
+            .collect();
+
+        self.token_spans
+            .iter()
+            .map(|span| {
+                if span.is_empty() {
+                    return (0, 0);
+                }
+                let (index, base) = locate_text(&texts, span.start);
+                let map_offset = |offset: usize| match &text_offsets[index] {
+                    Some(offsets) => offsets[offset - base],
+                    None => offset - base,
+                };
+                (map_offset(span.start), map_offset(span.end))
+            })
+            .collect()
+    }
+
     /// Return an iterator of the inputs for the `token_type_ids` input field
     /// in the model, if it has one.
-    pub fn token_type_ids(&self) -> impl Iterator<Item = usize> {
-        let second_seq_tokens = self.token_ids.len() - self.first_seq_tokens;
-        repeat(0)
-            .take(self.first_seq_tokens)
-            .chain(repeat(1).take(second_seq_tokens))
+    ///
+    /// The type IDs are assigned by the tokenizer's [Template]. By default
+    /// tokens from the first sequence have type 0 and tokens from the second
+    /// sequence have type 1.
+    pub fn token_type_ids(&self) -> impl Iterator<Item = usize> + '_ {
+        repeat_n(self.pad_type_id, self.pad_left)
+            .chain(self.type_ids.iter().copied())
+            .chain(repeat_n(self.pad_type_id, self.pad_right))
+    }
+
+    /// Return the index of the word that each token was produced from.
+    ///
+    /// Words are the pieces that the tokenizer's pre-tokenizer splits the
+    /// input into, plus any [AddedToken]s in the input. If the tokenizer has
+    /// no pre-tokenizer, each span of text between added tokens is a single
+    /// word. If the input was already split into words (see
+    /// [EncoderInput::Words]), the word index is the index of the input word.
+    /// Word indices start at zero for each input sequence. Special tokens
+    /// added by the [Template] and padding have a word index of `None`.
+    ///
+    /// This matches `Encoding.word_ids` in Hugging Face Tokenizers.
+    pub fn word_ids(&self) -> impl Iterator<Item = Option<usize>> + '_ {
+        repeat_n(None, self.pad_left)
+            .chain(self.word_ids.iter().copied())
+            .chain(repeat_n(None, self.pad_right))
+    }
+
+    /// Return the inputs for the `attention_mask` input field in the model.
+    ///
+    /// The mask has one entry per token, which is 0 for padding tokens and 1
+    /// otherwise.
+    pub fn attention_mask(&self) -> Vec<usize> {
+        let n_tokens = self.token_ids.len() - self.pad_left - self.pad_right;
+        repeat_n(0, self.pad_left)
+            .chain(repeat_n(1, n_tokens))
+            .chain(repeat_n(0, self.pad_right))
+            .collect()
+    }
+
+    /// Convert a byte offset in `text` to the units of [Encoded::offset_mapping].
+    fn to_offset_units(&self, text: &str, offset: usize) -> usize {
+        match self.offset_type {
+            OffsetType::Byte => offset,
+            OffsetType::Char => text[..offset].chars().count(),
+        }
+    }
+
+    /// Return the index of the token that contains the character at
+    /// `offset` in sequence `sequence` of the input.
+    ///
+    /// `sequence` is 0 for the first input sequence and 1 for the second. If
+    /// the input was split into words, it is the index of the word instead.
+    /// `offset` uses the same units as [Encoded::offset_mapping]. Returns
+    /// `None` if the offset is out of bounds or the character is not part of
+    /// any token, for example because it is whitespace that was discarded
+    /// during tokenization, or was truncated.
+    pub fn char_to_token(&self, offset: usize, sequence: usize) -> Option<usize> {
+        let texts = self.input.texts();
+        let text = *texts.get(sequence)?;
+        let base: usize = texts[..sequence].iter().map(|text| text.len()).sum();
+        let byte_offset = match self.offset_type {
+            OffsetType::Byte => offset,
+            OffsetType::Char => text.char_indices().nth(offset)?.0,
+        };
+        if byte_offset >= text.len() {
+            return None;
+        }
+        let byte_offset = base + byte_offset;
+        self.token_spans
+            .iter()
+            .position(|span| span.contains(&byte_offset))
+    }
+
+    /// Return the range of the input sequence that the token at `index`
+    /// was produced from.
+    ///
+    /// The range uses the same units as [Encoded::offset_mapping] and is
+    /// relative to the sequence or word that the token came from. Returns `None` if
+    /// `index` is out of bounds or refers to a special or padding token.
+    pub fn token_to_chars(&self, index: usize) -> Option<Range<usize>> {
+        let span = self.token_spans.get(index)?;
+        if span.is_empty() {
+            return None;
+        }
+        let texts = self.input.texts();
+        let (index, base) = locate_text(&texts, span.start);
+        let text = texts[index];
+        let start = self.to_offset_units(text, span.start - base);
+        let end = self.to_offset_units(text, span.end - base);
+        Some(start..end)
     }
 
     /// Return the text from the input sequence(s) that corresponds to a range
-    /// of token indices. If the input contained two sequences, the range must
-    /// lie entirely within one of them.
+    /// of token indices. If the input contained two sequences or was split
+    /// into words, the range must lie entirely within one of them.
     pub fn text_for_token_range(&self, range: Range<usize>) -> Option<&'a str> {
         let start_offset = self.token_offsets.get(range.start).copied()?;
-        let input_len = match self.input {
-            EncoderInput::Item(item) => item.len(),
-            EncoderInput::Pair((query, context)) => query.len() + context.len(),
-        };
 
         let end_offset = if range.end == self.token_offsets.len() {
-            input_len
+            self.end_offset
         } else {
             self.token_offsets.get(range.end).copied()?
         };
@@ -136,13 +383,63 @@ impl<'a> Encoded<'a> {
                     context.get(start_offset - offset..end_offset - offset)
                 }
             }
+            EncoderInput::Words(words) => {
+                let (index, base) = locate_text(words, start_offset);
+                words
+                    .get(index)?
+                    .get(start_offset - base..end_offset.saturating_sub(base))
+            }
         }
     }
 }
 
+/// Return the index of the text in `texts` which contains the byte `offset`,
+/// and the offset of the start of that text, where offsets are assigned as
+/// if the texts were concatenated.
+///
+/// Offsets at or after the end of the last text are assigned to the last
+/// text.
+fn locate_text(texts: &[&str], offset: usize) -> (usize, usize) {
+    let mut base = 0;
+    for (index, text) in texts.iter().enumerate() {
+        if offset < base + text.len() {
+            return (index, base);
+        }
+        base += text.len();
+    }
+    let last = texts.len().saturating_sub(1);
+    (last, base - texts.get(last).map_or(0, |text| text.len()))
+}
+
+/// Return a mapping from byte offsets in `text` to character offsets.
+///
+/// The result has an entry for each byte in `text`, plus one for the end of
+/// the string.
+fn char_offsets(text: &str) -> Vec<usize> {
+    let mut offsets = Vec::with_capacity(text.len() + 1);
+    let mut n_chars = 0;
+    for ch in text.chars() {
+        offsets.extend(repeat_n(n_chars, ch.len_utf8()));
+        n_chars += 1;
+    }
+    offsets.push(n_chars);
+    offsets
+}
+
+/// Return true if the text in `range` is not preceded or followed by a word
+/// character.
+fn is_single_word(text: &str, range: Range<usize>) -> bool {
+    let is_word_char = |ch: char| ch.is_alphanumeric() || ch == '_';
+    !text[..range.start]
+        .chars()
+        .next_back()
+        .is_some_and(is_word_char)
+        && !text[range.end..].chars().next().is_some_and(is_word_char)
+}
+
 /// Options that control chunking and truncation by [Tokenizer::encode] and
 /// [Tokenizer::encode_chunks].
-#[derive(Clone, Default)]
+#[derive(Clone)]
 pub struct EncodeOptions {
     /// Maximum number of tokens in each chunk, including any special tokens
     /// (eg. `[CLS]`, `[SEP]`) that are added.
@@ -150,6 +447,286 @@ pub struct EncodeOptions {
 
     /// The number of tokens that a chunk will overlap with the previous chunk.
     pub overlap: usize,
+
+    /// Truncate the output of [Tokenizer::encode] to a maximum length.
+    ///
+    /// This overrides the tokenizer's default truncation settings (see
+    /// [Tokenizer::with_truncation]). It is not used by
+    /// [Tokenizer::encode_chunks].
+    pub truncation: Option<Truncation>,
+
+    /// Pad outputs to a minimum length.
+    ///
+    /// This overrides the tokenizer's default padding settings (see
+    /// [Tokenizer::with_padding]).
+    pub padding: Option<Padding>,
+
+    /// Units of the offsets returned by [Encoded::offset_mapping].
+    pub offset_type: OffsetType,
+
+    /// Encode special tokens which appear in the input as ordinary text.
+    ///
+    /// By default, any [AddedToken]s in the input are encoded as their
+    /// single token IDs. If this is set, added tokens which are marked as
+    /// special (eg. `<|endoftext|>`) are instead passed to the [Encoder] like
+    /// the rest of the text. This is useful when encoding untrusted input.
+    pub split_special_tokens: bool,
+
+    /// Add the special tokens from the tokenizer's [Template] (eg. `[CLS]`,
+    /// `[SEP]`) around the input. This is `true` by default.
+    ///
+    /// Set this to `false` to get only the token IDs for the input text, eg.
+    /// when assembling a chat prompt from separately encoded parts.
+    pub add_special_tokens: bool,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        EncodeOptions {
+            max_chunk_len: None,
+            overlap: 0,
+            truncation: None,
+            padding: None,
+            offset_type: OffsetType::default(),
+            split_special_tokens: false,
+            add_special_tokens: true,
+        }
+    }
+}
+
+/// Options that control decoding by [Tokenizer::decode_with_options].
+#[derive(Clone, Debug, Default)]
+pub struct DecodeOptions {
+    /// Omit special tokens (eg. `[CLS]`, `<|im_start|>`) from the output.
+    ///
+    /// Special tokens are added tokens which are marked as special and
+    /// tokens returned by [Tokenizer::special_tokens].
+    pub skip_special_tokens: bool,
+
+    /// Remove spaces before punctuation and English contractions (eg.
+    /// `"do n't ."` becomes `"don't."`).
+    ///
+    /// This matches the `clean_up_tokenization_spaces` option in Hugging Face
+    /// Transformers, and is useful for models such as BERT whose tokenizers
+    /// separate all tokens with spaces when decoding.
+    pub clean_up_tokenization_spaces: bool,
+}
+
+/// Encoded prompt for use with token healing, returned by
+/// [Tokenizer::heal_prompt].
+///
+/// Token healing avoids biasing generation by the way a prompt is split into
+/// tokens. For example if a prompt ends with `"https:"`, a model will rarely
+/// have seen the token for `":"` followed by one for `"//"`, as in its
+/// training data the text would be encoded using a single `"://"` token.
+/// Token healing removes the final token from the prompt, then constrains the
+/// first generated token to those which start with the removed text.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TokenHealing {
+    /// Token IDs for the prompt, with the final token removed.
+    pub token_ids: Vec<TokenId>,
+
+    /// Text of the token that was removed from the end of the prompt.
+    pub prefix: String,
+
+    /// IDs of tokens whose text starts with [`prefix`](Self::prefix). This
+    /// includes the removed token. The first generated token should be
+    /// chosen from these.
+    pub candidates: Vec<TokenId>,
+}
+
+/// Specifies the units of token offsets returned by [Encoded::offset_mapping].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OffsetType {
+    /// Offsets are byte offsets into the UTF-8 input string. These can be
+    /// used to slice the input `str`.
+    Byte,
+
+    /// Offsets are counts of Unicode characters (`char`s) from the start of
+    /// the input. This matches the offsets returned by the Python API of
+    /// Hugging Face Tokenizers.
+    #[default]
+    Char,
+}
+
+/// Specifies the length that encoded outputs are padded to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PaddingLength {
+    /// Pad each output in a batch to the length of the longest output.
+    ///
+    /// For [Tokenizer::encode] the batch is a single output, and for
+    /// [Tokenizer::encode_chunks] it is the list of chunks.
+    BatchLongest,
+
+    /// Pad outputs to a fixed length. Outputs which are already longer are
+    /// not modified.
+    Fixed(usize),
+}
+
+/// Specifies which end of a sequence padding tokens are added to.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum PaddingDirection {
+    /// Add padding tokens at the start of the sequence.
+    Left,
+
+    /// Add padding tokens at the end of the sequence.
+    #[default]
+    Right,
+}
+
+/// Configuration for padding encoded sequences to a minimum length.
+///
+/// Padding is used to create inputs for models that have a fixed input
+/// length, or to combine several inputs into a batch.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Padding {
+    pub length: PaddingLength,
+    pub direction: PaddingDirection,
+
+    /// ID of the token used for padding (eg. `[PAD]`).
+    pub pad_id: TokenId,
+
+    /// Value returned by [Encoded::token_type_ids] for padding tokens.
+    pub pad_type_id: usize,
+
+    /// If set, round the padded length up to a multiple of this value.
+    pub pad_to_multiple_of: Option<usize>,
+}
+
+impl Padding {
+    /// Create a padding configuration which adds `pad_id` tokens to the end
+    /// of outputs.
+    pub fn new(length: PaddingLength, pad_id: TokenId) -> Padding {
+        Padding {
+            length,
+            direction: PaddingDirection::default(),
+            pad_id,
+            pad_type_id: 0,
+            pad_to_multiple_of: None,
+        }
+    }
+
+    /// Pad a batch of encoded outputs.
+    fn apply(&self, outputs: &mut [Encoded]) {
+        let longest = outputs
+            .iter()
+            .map(|output| output.token_ids.len())
+            .max()
+            .unwrap_or(0);
+        let len = match self.length {
+            PaddingLength::BatchLongest => longest,
+            PaddingLength::Fixed(len) => len,
+        };
+        for output in outputs {
+            let len = len.max(output.token_ids.len());
+            let len = match self.pad_to_multiple_of {
+                Some(multiple) if multiple > 0 => len.next_multiple_of(multiple),
+                _ => len,
+            };
+            output.pad(len, self);
+        }
+    }
+}
+
+/// Specifies which end of a sequence tokens are removed from when truncating.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TruncationDirection {
+    /// Remove tokens from the start of the sequence.
+    ///
+    /// This keeps the end of the input, such as the most recent turns of a
+    /// conversation in a chat prompt. It corresponds to a `direction` of
+    /// `"Left"` in `tokenizer.json` files.
+    Left,
+
+    /// Remove tokens from the end of the sequence.
+    #[default]
+    Right,
+}
+
+/// Specifies which sequences are truncated when the input is a pair.
+///
+/// For inputs containing a single sequence, that sequence is always truncated.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TruncationStrategy {
+    /// Remove tokens from the longest sequence until both fit, splitting the
+    /// available space evenly if both sequences are long.
+    #[default]
+    LongestFirst,
+
+    /// Only truncate the first sequence.
+    OnlyFirst,
+
+    /// Only truncate the second sequence.
+    OnlySecond,
+}
+
+/// Configuration for truncating encoded sequences to a maximum length.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Truncation {
+    /// Maximum number of tokens in the output, including any special tokens
+    /// (eg. `[CLS]`, `[SEP]`) that are added.
+    pub max_length: usize,
+
+    pub direction: TruncationDirection,
+    pub strategy: TruncationStrategy,
+}
+
+impl Truncation {
+    /// Create a truncation configuration which removes tokens from the end of
+    /// the longest sequence.
+    pub fn new(max_length: usize) -> Truncation {
+        Truncation {
+            max_length,
+            direction: TruncationDirection::default(),
+            strategy: TruncationStrategy::default(),
+        }
+    }
+
+    /// Return the number of tokens to keep from two sequences of length
+    /// `first` and `second`, so that their combined length is at most
+    /// `max_len`.
+    fn keep_lengths(
+        &self,
+        first: usize,
+        second: usize,
+        max_len: usize,
+    ) -> Result<(usize, usize), TokenizerError> {
+        if first + second <= max_len {
+            return Ok((first, second));
+        }
+        match self.strategy {
+            TruncationStrategy::LongestFirst => {
+                let short = first.min(second);
+                let (keep_short, keep_long) = if short <= max_len - short {
+                    (short, max_len - short)
+                } else {
+                    (max_len / 2, max_len - max_len / 2)
+                };
+                if first <= second {
+                    Ok((keep_short, keep_long))
+                } else {
+                    Ok((keep_long, keep_short))
+                }
+            }
+            TruncationStrategy::OnlyFirst => max_len
+                .checked_sub(second)
+                .map(|keep_first| (keep_first, second))
+                .ok_or(TokenizerError::TruncationFailed),
+            TruncationStrategy::OnlySecond => max_len
+                .checked_sub(first)
+                .map(|keep_second| (first, keep_second))
+                .ok_or(TokenizerError::TruncationFailed),
+        }
+    }
+
+    /// Return the range of indices to keep from a sequence of length `len`
+    /// when keeping `keep` tokens.
+    fn keep_range(&self, len: usize, keep: usize) -> Range<usize> {
+        match self.direction {
+            TruncationDirection::Left => len - keep..len,
+            TruncationDirection::Right => 0..keep,
+        }
+    }
 }
 
 /// An Encoder implements a specific method of converting strings into token IDs
@@ -157,7 +734,7 @@ pub struct EncodeOptions {
 ///
 /// Encoders are not generally used directly but instead via a wrapping
 /// [Tokenizer].
-pub trait Encoder {
+pub trait Encoder: Send + Sync {
     /// Look up the numeric ID for a token given its canonical string
     /// representation. This is used eg. for looking up the IDs of special
     /// tokens.
@@ -176,6 +753,12 @@ pub trait Encoder {
     /// [`decode`](Self::decode) instead.
     fn get_token_str(&self, id: TokenId) -> Result<String, TokenizerError>;
 
+    /// Return the size of the vocabulary.
+    ///
+    /// This is one more than the largest token ID. Token IDs in
+    /// `0..vocab_size()` are not guaranteed to all be valid.
+    fn vocab_size(&self) -> usize;
+
     /// Return the canonical strings that correspond to a sequence of token IDs.
     ///
     /// See [`get_token_str`](Self::get_token_str) for notes on what the
@@ -191,12 +774,14 @@ pub trait Encoder {
 
     /// Encode a string into a sequence of token IDs with source offsets.
     ///
-    /// `on_token` is a callback with `(offset, token_id)` arguments that should
-    /// be invoked for each token produced.
+    /// `on_token` is a callback with `(byte_range, token_id)` arguments that
+    /// should be invoked for each token produced. `byte_range` is the range of
+    /// `text` that the token was produced from. The range must start and end
+    /// on character boundaries.
     fn encode_with_offsets(
         &self,
         text: &str,
-        on_token: &mut dyn FnMut(usize, TokenId),
+        on_token: &mut dyn FnMut(Range<usize>, TokenId),
     ) -> Result<(), TokenizerError>;
 
     /// Encode a string into a sequence of token IDs.
@@ -206,7 +791,7 @@ pub trait Encoder {
     /// source offsets are not needed.
     fn encode(&self, text: &str) -> Result<Vec<TokenId>, TokenizerError> {
         let mut token_ids = Vec::new();
-        self.encode_with_offsets(text, &mut |_offset, token_id| token_ids.push(token_id))?;
+        self.encode_with_offsets(text, &mut |_range, token_id| token_ids.push(token_id))?;
         Ok(token_ids)
     }
 
@@ -220,6 +805,123 @@ pub trait Encoder {
     /// Special tokens are decoded into their canonical string representations
     /// as returned by [`get_token_str`](Self::get_token_str).
     fn decode(&self, ids: &[TokenId]) -> Result<String, TokenizerError>;
+
+    /// Return the normalizer that this encoder applies to its input, if any.
+    ///
+    /// This is used to match [AddedToken]s that have the
+    /// [normalized](AddedToken::normalized) flag set.
+    fn normalizer(&self) -> Option<&Normalizer> {
+        None
+    }
+}
+
+/// IDs and type IDs of the special tokens that a [Template] adds around the
+/// input.
+struct TemplateTokenIds {
+    prefix: Vec<(TokenId, usize)>,
+    middle: Vec<(TokenId, usize)>,
+    suffix: Vec<(TokenId, usize)>,
+
+    /// Token type IDs for the first and second sequences.
+    sequence_type_ids: [usize; 2],
+}
+
+/// Tokens and offsets from one of the input sequences.
+#[derive(Clone, Copy)]
+struct SequenceTokens<'t> {
+    tokens: &'t [TokenId],
+    offsets: &'t [Range<usize>],
+    words: &'t [usize],
+
+    /// Offset of the end of the text for the last token in `tokens`.
+    end_offset: usize,
+}
+
+impl<'t> SequenceTokens<'t> {
+    /// Return the part of a sequence in `range`. The end offset is the offset
+    /// of the next token, or `seq_end` if there are no more tokens.
+    fn slice(
+        tokens: &'t [TokenId],
+        offsets: &'t [Range<usize>],
+        words: &'t [usize],
+        range: Range<usize>,
+        seq_end: usize,
+    ) -> SequenceTokens<'t> {
+        SequenceTokens {
+            tokens: &tokens[range.clone()],
+            offsets: &offsets[range.clone()],
+            words: &words[range.clone()],
+            end_offset: offsets.get(range.end).map_or(seq_end, |r| r.start),
+        }
+    }
+}
+
+impl TemplateTokenIds {
+    /// Return the total number of special tokens.
+    fn len(&self) -> usize {
+        self.prefix.len() + self.middle.len() + self.suffix.len()
+    }
+
+    /// Combine the tokens from one or two sequences with the special tokens.
+    fn apply<'a>(
+        &self,
+        input: EncoderInput<'a>,
+        first: SequenceTokens,
+        second: Option<SequenceTokens>,
+    ) -> Encoded<'a> {
+        let mut tokens = Vec::new();
+        let mut offsets = Vec::new();
+        let mut type_ids = Vec::new();
+        let mut word_ids = Vec::new();
+
+        fn ids(special: &[(TokenId, usize)]) -> impl Iterator<Item = TokenId> + '_ {
+            special.iter().map(|(id, _)| *id)
+        }
+        fn types(special: &[(TokenId, usize)]) -> impl Iterator<Item = usize> + '_ {
+            special.iter().map(|(_, type_id)| *type_id)
+        }
+
+        let prefix_offset = match input {
+            EncoderInput::Item(_) | EncoderInput::Words(_) => {
+                first.offsets.first().map_or(first.end_offset, |r| r.start)
+            }
+            EncoderInput::Pair(_) => 0,
+        };
+        tokens.extend(ids(&self.prefix));
+        offsets.extend(repeat_n(prefix_offset..prefix_offset, self.prefix.len()));
+        type_ids.extend(types(&self.prefix));
+        word_ids.extend(repeat_n(None, self.prefix.len()));
+
+        tokens.extend_from_slice(first.tokens);
+        offsets.extend_from_slice(first.offsets);
+        type_ids.extend(repeat_n(self.sequence_type_ids[0], first.tokens.len()));
+        word_ids.extend(first.words.iter().copied().map(Some));
+
+        let mut end_offset = first.end_offset;
+
+        if let Some(second) = second {
+            tokens.extend(ids(&self.middle));
+            offsets.extend(repeat_n(
+                first.end_offset..first.end_offset,
+                self.middle.len(),
+            ));
+            type_ids.extend(types(&self.middle));
+            word_ids.extend(repeat_n(None, self.middle.len()));
+
+            tokens.extend_from_slice(second.tokens);
+            offsets.extend_from_slice(second.offsets);
+            type_ids.extend(repeat_n(self.sequence_type_ids[1], second.tokens.len()));
+            word_ids.extend(second.words.iter().copied().map(Some));
+            end_offset = second.end_offset;
+        }
+
+        tokens.extend(ids(&self.suffix));
+        offsets.extend(repeat_n(end_offset..end_offset, self.suffix.len()));
+        type_ids.extend(types(&self.suffix));
+        word_ids.extend(repeat_n(None, self.suffix.len()));
+
+        Encoded::new(input, tokens, offsets, end_offset, type_ids, word_ids)
+    }
 }
 
 /// Errors returned by [Tokenizer::from_json].
@@ -231,6 +933,17 @@ pub enum FromJsonError {
     JsonError(serde_json::Error),
     /// The model type isn't supported by this crate.
     UnsupportedModel,
+    /// A regex in the tokenizer configuration is invalid.
+    RegexError(Box<fancy_regex::Error>),
+    /// The post-processor template is invalid.
+    TemplateError(TemplateError),
+    /// A special token is not in the vocabulary.
+    MissingToken(String),
+    /// A custom component from a [ComponentRegistry] could not be created.
+    CustomComponentError(CustomComponentError),
+    /// The configuration uses normalizers, pre-tokenizers, post-processors
+    /// or decoders which are not supported by this crate.
+    UnsupportedComponents(Vec<UnsupportedComponent>),
 }
 
 impl fmt::Display for FromJsonError {
@@ -239,12 +952,85 @@ impl fmt::Display for FromJsonError {
             Self::BpeError(err) => write!(f, "BPE tokenizer error: {}", err),
             Self::JsonError(err) => write!(f, "JSON error {}", err),
             Self::UnsupportedModel => write!(f, "unsupported model type"),
+            Self::RegexError(err) => write!(f, "invalid regex {}", err),
+            Self::TemplateError(err) => write!(f, "invalid template {}", err),
+            Self::MissingToken(token) => write!(f, "special token {} not in vocabulary", token),
+            Self::CustomComponentError(err) => write!(f, "custom component error: {}", err),
+            Self::UnsupportedComponents(components) => {
+                write!(f, "unsupported components: ")?;
+                for (i, component) in components.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", component)?;
+                }
+                Ok(())
+            }
         }
     }
 }
 
 impl Error for FromJsonError {}
 
+/// Kinds of processing step in a `tokenizer.json` file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ComponentKind {
+    Normalizer,
+    PreTokenizer,
+    PostProcessor,
+    Decoder,
+}
+
+impl fmt::Display for ComponentKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Normalizer => "normalizer",
+            Self::PreTokenizer => "pre-tokenizer",
+            Self::PostProcessor => "post-processor",
+            Self::Decoder => "decoder",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A component in a `tokenizer.json` file whose type isn't supported.
+///
+/// See [FromJsonError::UnsupportedComponents].
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnsupportedComponent {
+    /// The section of the file that the component is in.
+    pub kind: ComponentKind,
+
+    /// The value of the component's `type` field, eg. `Precompiled`.
+    pub type_name: String,
+}
+
+impl fmt::Display for UnsupportedComponent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} \"{}\"", self.kind, self.type_name)
+    }
+}
+
+/// Errors returned by [Tokenizer::from_sentencepiece].
+#[derive(Debug)]
+pub enum FromSentencePieceError {
+    /// The data is not a valid SentencePiece model.
+    InvalidModel(String),
+    /// The model type isn't supported by this crate.
+    UnsupportedModel,
+}
+
+impl fmt::Display for FromSentencePieceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidModel(err) => write!(f, "invalid SentencePiece model: {}", err),
+            Self::UnsupportedModel => write!(f, "unsupported model type"),
+        }
+    }
+}
+
+impl Error for FromSentencePieceError {}
+
 /// Tokenizes text inputs into sequences of token IDs that can be fed to a
 /// machine learning model.
 ///
@@ -252,60 +1038,529 @@ impl Error for FromJsonError {}
 /// individual sequences (eg. WordPiece, Byte Pair Encoding, Unigram) and adds
 /// common functionality such as injecting special tokens, splitting sequences
 /// into overlapping chunks and truncating long sequences.
+///
+/// ## Thread safety
+///
+/// `Tokenizer` is `Send + Sync`, and all encoding and decoding methods take
+/// `&self`. A single tokenizer can therefore be shared between the worker
+/// threads of a server using an `Arc<Tokenizer>`, without any locking by the
+/// caller. Internal state which is updated during encoding, such as the
+/// cache of encoded pieces in [Bpe], is split into independently locked
+/// shards so that concurrent calls rarely contend with each other.
 pub struct Tokenizer {
     encoder: Box<dyn Encoder>,
 
-    /// Token added at start of output.
-    cls_token: Option<String>,
+    /// Special tokens added around the input sequences.
+    template: Template,
+
+    /// Splits input text into pieces before it is passed to the encoder.
+    pre_tokenizer: Option<Box<dyn PreTokenizer>>,
+
+    /// Decoder used by [Tokenizer::decode]. If not set, decoding is handled
+    /// by the encoder.
+    decoder: Option<Box<dyn Decoder>>,
+
+    /// Default truncation settings for [Tokenizer::encode].
+    truncation: Option<Truncation>,
+
+    /// Default padding settings.
+    padding: Option<Padding>,
+
+    /// Special tokens used by the model.
+    special_tokens: SpecialTokens,
 
-    /// Token added after end of each sequence.
-    sep_token: Option<String>,
+    /// Tokens which are matched in the input before it is passed to the
+    /// encoder.
+    added_tokens: Vec<AddedToken>,
+
+    /// Matcher for `added_tokens`.
+    added_token_matcher: AddedTokenMatcher,
 }
 
-/// Configuration for a [Tokenizer].
-#[derive(Clone, Default)]
-pub struct TokenizerOptions<'a> {
-    /// Token added at the start of the output. For BERT models, this is the
-    /// `[CLS]` token.
-    pub cls_token: Option<&'a str>,
+/// A token which is matched in the input text before the rest of the text is
+/// passed to the [Encoder].
+///
+/// Added tokens are used for special tokens such as `[CLS]` or
+/// `<|im_start|>`, which would otherwise be split into several tokens, as
+/// well as for words that were added to a model's vocabulary after it was
+/// trained. See [Tokenizer::with_added_tokens].
+#[derive(Clone, Debug, PartialEq)]
+pub struct AddedToken {
+    /// Text which is replaced by the token.
+    pub content: String,
 
-    /// Token added after each encoded sequence in the output. For BERT models,
-    /// this is the `[SEP]` token.
-    pub sep_token: Option<&'a str>,
+    /// The token's ID.
+    pub id: TokenId,
+
+    /// Whether this is a special token. See
+    /// [EncodeOptions::split_special_tokens].
+    pub special: bool,
+
+    /// Whether the token consumes any whitespace to its left.
+    pub lstrip: bool,
+
+    /// Whether the token consumes any whitespace to its right.
+    pub rstrip: bool,
+
+    /// Whether the token only matches whole words. If set, the token is not
+    /// matched if it is preceded or followed by a letter, digit or underscore.
+    pub single_word: bool,
+
+    /// Whether the token is matched against the normalized input rather than
+    /// the original text. See [Encoder::normalizer].
+    pub normalized: bool,
 }
 
-impl Tokenizer {
-    /// Create a new tokenizer which wraps the given encoder.
-    pub fn new<E: Encoder + 'static>(encoder: E, options: TokenizerOptions) -> Tokenizer {
-        Tokenizer {
-            encoder: Box::new(encoder),
-            cls_token: options.cls_token.map(|t| t.to_string()),
-            sep_token: options.sep_token.map(|t| t.to_string()),
+impl AddedToken {
+    /// Create an added token which is not marked as special.
+    ///
+    /// The token is matched against the normalized input.
+    pub fn new(content: &str, id: TokenId) -> AddedToken {
+        AddedToken {
+            content: content.to_string(),
+            id,
+            special: false,
+            lstrip: false,
+            rstrip: false,
+            single_word: false,
+            normalized: true,
         }
     }
 
-    /// Load a tokenizer from the contents of a Hugging Face `tokenizer.json`
-    /// file.
-    pub fn from_json(json: &str) -> Result<Tokenizer, FromJsonError> {
-        let tokenizer_json = json::from_json(json).map_err(FromJsonError::JsonError)?;
-        Self::from_parsed_json(tokenizer_json)
+    /// Create an added token which is marked as special.
+    ///
+    /// The token is matched against the original input.
+    pub fn special(content: &str, id: TokenId) -> AddedToken {
+        AddedToken {
+            special: true,
+            normalized: false,
+            ..AddedToken::new(content, id)
+        }
     }
+}
 
-    fn from_parsed_json(json: json::TokenizerJson) -> Result<Tokenizer, FromJsonError> {
-        let normalizer = json.normalizer.map(|normalizer| match normalizer {
-            json::Normalizer::Bert(bert_norm) => Normalizer::new(NormalizerOptions {
-                lowercase: bert_norm.lowercase,
-                strip_accents: bert_norm.strip_accents.unwrap_or(bert_norm.lowercase),
-            }),
+/// A special token, such as the start-of-sequence or padding token.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpecialToken {
+    /// The canonical string for the token, eg. `[CLS]` or `<|endoftext|>`.
+    pub content: String,
 
-            // Dummy implementation of NFC normalization.
-            json::Normalizer::Nfc => Normalizer::new(NormalizerOptions {
-                lowercase: false,
-                strip_accents: false,
-            }),
+    /// The token's ID.
+    pub id: TokenId,
+}
+
+/// Special tokens used by a model, identified by their role.
+///
+/// See [Tokenizer::special_tokens].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SpecialTokens {
+    /// Token at the start of a sequence.
+    pub bos: Option<SpecialToken>,
+
+    /// Token at the end of a sequence. Generation usually stops when the model
+    /// outputs this token.
+    pub eos: Option<SpecialToken>,
+
+    /// Token used for text that is not in the vocabulary.
+    pub unk: Option<SpecialToken>,
+
+    /// Token used for padding.
+    pub pad: Option<SpecialToken>,
+
+    /// Classification token, added at the start of the input by BERT models.
+    pub cls: Option<SpecialToken>,
+
+    /// Separator token, added after each sequence by BERT models.
+    pub sep: Option<SpecialToken>,
+
+    /// Token used for masked language modeling.
+    pub mask: Option<SpecialToken>,
+
+    /// All tokens which are marked as special in the tokenizer configuration,
+    /// including those without a specific role (eg. `<|im_start|>` in chat
+    /// models).
+    pub all: Vec<SpecialToken>,
+}
+
+impl SpecialTokens {
+    /// Add a token to [SpecialTokens::all] if it is not already present.
+    fn add(&mut self, token: SpecialToken) {
+        if !self.all.contains(&token) {
+            self.all.push(token);
+        }
+    }
+}
+
+/// Configuration for a [Tokenizer].
+#[derive(Clone, Default)]
+pub struct TokenizerOptions<'a> {
+    /// Token added at the start of the output. For BERT models, this is the
+    /// `[CLS]` token.
+    pub cls_token: Option<&'a str>,
+
+    /// Token added after each encoded sequence in the output. For BERT models,
+    /// this is the `[SEP]` token.
+    pub sep_token: Option<&'a str>,
+}
+
+impl Tokenizer {
+    /// Create a new tokenizer which wraps the given encoder.
+    pub fn new<E: Encoder + 'static>(encoder: E, options: TokenizerOptions) -> Tokenizer {
+        let mut tokenizer = Tokenizer {
+            encoder: Box::new(encoder),
+            template: Template::from_cls_sep(options.cls_token, options.sep_token),
+            pre_tokenizer: None,
+            decoder: None,
+            truncation: None,
+            padding: None,
+            special_tokens: SpecialTokens::default(),
+            added_tokens: Vec::new(),
+            added_token_matcher: AddedTokenMatcher::default(),
+        };
+        tokenizer.special_tokens.cls = options
+            .cls_token
+            .and_then(|token| tokenizer.lookup_special_token(token));
+        tokenizer.special_tokens.sep = options
+            .sep_token
+            .and_then(|token| tokenizer.lookup_special_token(token));
+        tokenizer
+    }
+
+    /// Set the special tokens which are added around input sequences.
+    ///
+    /// This replaces the `cls_token` and `sep_token` from the
+    /// [TokenizerOptions] passed to [Tokenizer::new].
+    pub fn with_template(mut self, template: Template) -> Tokenizer {
+        self.template = template;
+        self
+    }
+
+    /// Set the pre-tokenizer which splits input text into pieces before
+    /// they are passed to the encoder.
+    ///
+    /// The pre-tokenizer is applied to the text on either side of any
+    /// [added tokens](Tokenizer::with_added_tokens).
+    pub fn with_pre_tokenizer<P: PreTokenizer + 'static>(mut self, pre_tokenizer: P) -> Tokenizer {
+        self.pre_tokenizer = Some(Box::new(pre_tokenizer));
+        self
+    }
+
+    /// Set the decoder used to convert token IDs back into text.
+    ///
+    /// See [Tokenizer::decode].
+    pub fn with_decoder<D: Decoder + 'static>(mut self, decoder: D) -> Tokenizer {
+        self.decoder = Some(Box::new(decoder));
+        self
+    }
+
+    /// Set the default truncation settings for [Tokenizer::encode].
+    ///
+    /// These can be overridden using [EncodeOptions::truncation].
+    pub fn with_truncation(mut self, truncation: Truncation) -> Tokenizer {
+        self.truncation = Some(truncation);
+        self
+    }
+
+    /// Set the default padding settings for [Tokenizer::encode],
+    /// [Tokenizer::encode_chunks] and [Tokenizer::encode_batch].
+    ///
+    /// These can be overridden using [EncodeOptions::padding].
+    pub fn with_padding(mut self, padding: Padding) -> Tokenizer {
+        self.padding = Some(padding);
+        self
+    }
+
+    /// Set the special tokens used by the model.
+    ///
+    /// See [Tokenizer::special_tokens].
+    pub fn with_special_tokens(mut self, special_tokens: SpecialTokens) -> Tokenizer {
+        self.special_tokens = special_tokens;
+        self
+    }
+
+    /// Update the special tokens using the contents of a Hugging Face
+    /// `special_tokens_map.json` file.
+    ///
+    /// Tokens in the file replace any existing tokens with the same role.
+    /// Returns an error if a token is not in the vocabulary.
+    pub fn with_special_tokens_map(mut self, json: &str) -> Result<Tokenizer, FromJsonError> {
+        let map = json::special_tokens_map_from_json(json).map_err(FromJsonError::JsonError)?;
+        let lookup = |value: &json::SpecialTokenValue| {
+            self.lookup_special_token(value.content())
+                .ok_or_else(|| FromJsonError::MissingToken(value.content().to_string()))
+        };
+
+        let mut special_tokens = self.special_tokens.clone();
+        for (role, value) in [
+            (&mut special_tokens.bos, map.bos_token),
+            (&mut special_tokens.eos, map.eos_token),
+            (&mut special_tokens.unk, map.unk_token),
+            (&mut special_tokens.pad, map.pad_token),
+            (&mut special_tokens.cls, map.cls_token),
+            (&mut special_tokens.sep, map.sep_token),
+            (&mut special_tokens.mask, map.mask_token),
+        ] {
+            if let Some(value) = value {
+                *role = Some(lookup(&value)?);
+            }
+        }
+        for value in &map.additional_special_tokens {
+            special_tokens.add(lookup(value)?);
+        }
+
+        self.special_tokens = special_tokens;
+        Ok(self)
+    }
+
+    /// Set the tokens which are matched in the input text before it is
+    /// passed to the encoder.
+    ///
+    /// Where the input contains the content of an added token, the token's
+    /// ID is emitted and the text on either side is encoded separately. If
+    /// several added tokens match at the same position, the longest one is
+    /// used.
+    pub fn with_added_tokens(mut self, added_tokens: Vec<AddedToken>) -> Tokenizer {
+        self.added_token_matcher = AddedTokenMatcher::new(&added_tokens, self.encoder.normalizer());
+        self.added_tokens = added_tokens;
+        self
+    }
+
+    /// Return the tokens which are matched in the input text before it is
+    /// passed to the encoder.
+    ///
+    /// See [Tokenizer::with_added_tokens].
+    pub fn added_tokens(&self) -> &[AddedToken] {
+        &self.added_tokens
+    }
+
+    /// Return the special tokens used by the model, such as the end-of-sequence
+    /// and padding tokens.
+    ///
+    /// When a tokenizer is loaded using [Tokenizer::from_json], this includes
+    /// all tokens marked as special in the `added_tokens` list. Roles are
+    /// assigned to tokens where the `tokenizer.json` file specifies them, eg.
+    /// the unknown token for the model and `[CLS]` and `[SEP]` tokens used by
+    /// the post-processor. Other roles, such as the end-of-sequence token, are
+    /// usually specified in a separate `special_tokens_map.json` file that can
+    /// be loaded using [Tokenizer::with_special_tokens_map].
+    pub fn special_tokens(&self) -> &SpecialTokens {
+        &self.special_tokens
+    }
+
+    /// Look up the ID of a special token, returning `None` if it is not in
+    /// the vocabulary.
+    fn lookup_special_token(&self, content: &str) -> Option<SpecialToken> {
+        self.encoder
+            .get_token_id(content)
+            .ok()
+            .map(|id| SpecialToken {
+                content: content.to_string(),
+                id,
+            })
+    }
+
+    /// Load a tokenizer from the contents of a Hugging Face `tokenizer.json`
+    /// file.
+    ///
+    /// If the file uses normalizers, pre-tokenizers, post-processors or
+    /// decoders which are not supported, this returns
+    /// [FromJsonError::UnsupportedComponents] listing all of them.
+    pub fn from_json(json: &str) -> Result<Tokenizer, FromJsonError> {
+        Self::from_json_with_registry(json, &ComponentRegistry::default())
+    }
+
+    /// Load a tokenizer from the contents of a Hugging Face `tokenizer.json`
+    /// file, as UTF-8 bytes.
+    ///
+    /// This avoids a separate conversion to `str` when the file is embedded
+    /// in the binary using `include_bytes!` or fetched over the network.
+    /// See [Tokenizer::from_json].
+    pub fn from_bytes(json: &[u8]) -> Result<Tokenizer, FromJsonError> {
+        let tokenizer_json = json::from_slice(json).map_err(FromJsonError::JsonError)?;
+        Self::from_parsed_json(tokenizer_json, &ComponentRegistry::default())
+    }
+
+    /// Load a tokenizer from a reader which returns the contents of a Hugging
+    /// Face `tokenizer.json` file.
+    ///
+    /// The reader is not buffered, so it should be wrapped in a
+    /// [BufReader](std::io::BufReader) if it reads from a file or socket
+    /// directly. Errors from the reader are returned as
+    /// [FromJsonError::JsonError]. See [Tokenizer::from_json].
+    pub fn from_reader<R: std::io::Read>(reader: R) -> Result<Tokenizer, FromJsonError> {
+        let tokenizer_json = json::from_reader(reader).map_err(FromJsonError::JsonError)?;
+        Self::from_parsed_json(tokenizer_json, &ComponentRegistry::default())
+    }
+
+    /// Load a tokenizer from the contents of a Hugging Face `tokenizer.json`
+    /// file, which may use custom normalizers and pre-tokenizers from
+    /// `registry`.
+    pub fn from_json_with_registry(
+        json: &str,
+        registry: &ComponentRegistry,
+    ) -> Result<Tokenizer, FromJsonError> {
+        let tokenizer_json = json::from_json(json).map_err(FromJsonError::JsonError)?;
+        Self::from_parsed_json(tokenizer_json, registry)
+    }
+
+    /// Load a tokenizer from the contents of a SentencePiece `.model` file.
+    ///
+    /// Unigram and BPE models are supported. The SentencePiece whitespace
+    /// handling options are applied, but other normalization rules in the
+    /// model are not. Control and user-defined symbols (eg. `<s>`) are
+    /// registered as [added tokens](Tokenizer::added_tokens) and the
+    /// unknown, start, end and padding tokens are set as
+    /// [special tokens](Tokenizer::special_tokens).
+    ///
+    /// As with SentencePiece, no start or end of sequence tokens are added
+    /// to the output. Use [Tokenizer::with_template] to add them.
+    pub fn from_sentencepiece(model: &[u8]) -> Result<Tokenizer, FromSentencePieceError> {
+        let model =
+            sentencepiece::from_bytes(model).map_err(FromSentencePieceError::InvalidModel)?;
+        let normalizer_spec = &model.normalizer_spec;
+        let trainer_spec = &model.trainer_spec;
+        let space = if normalizer_spec.escape_whitespaces {
+            '▁'
+        } else {
+            ' '
+        };
+        let normalizer = Normalizer::new(NormalizerOptions {
+            remove_extra_whitespace: normalizer_spec.remove_extra_whitespaces,
+            replace_space: normalizer_spec.escape_whitespaces.then_some(space),
+            prepend: normalizer_spec.add_dummy_prefix.then(|| space.to_string()),
+            ..Default::default()
         });
 
-        match json.model {
+        let token = |id: i32| {
+            let piece = model.pieces.get(usize::try_from(id).ok()?)?;
+            Some(SpecialToken {
+                content: piece.piece.clone(),
+                id: id as TokenId,
+            })
+        };
+        let unk = token(trainer_spec.unk_id);
+        let unk_id = unk.as_ref().map(|token| token.id);
+        let vocab: Vec<(String, f32)> = model
+            .pieces
+            .iter()
+            .map(|piece| (piece.piece.clone(), piece.score))
+            .collect();
+
+        let mut tokenizer = match trainer_spec.model_type {
+            sentencepiece::ModelType::Unigram => {
+                let encoder = Unigram::from_vocab(
+                    vocab,
+                    UnigramOptions {
+                        normalizer: Some(normalizer),
+                        unk_id,
+                        byte_fallback: trainer_spec.byte_fallback,
+                    },
+                );
+                Tokenizer::new(encoder, TokenizerOptions::default())
+            }
+            sentencepiece::ModelType::Bpe => {
+                let encoder = sentencepiece::SentencePieceBpe::new(
+                    vocab,
+                    Some(normalizer),
+                    unk_id,
+                    trainer_spec.byte_fallback,
+                );
+                Tokenizer::new(encoder, TokenizerOptions::default())
+            }
+            sentencepiece::ModelType::Word | sentencepiece::ModelType::Char => {
+                return Err(FromSentencePieceError::UnsupportedModel);
+            }
+        };
+
+        let mut special_tokens = SpecialTokens {
+            bos: token(trainer_spec.bos_id),
+            eos: token(trainer_spec.eos_id),
+            unk,
+            pad: token(trainer_spec.pad_id),
+            ..Default::default()
+        };
+        let mut added_tokens = Vec::new();
+        for (id, piece) in model.pieces.iter().enumerate() {
+            let id = id as TokenId;
+            match piece.kind {
+                sentencepiece::PieceType::Control | sentencepiece::PieceType::Unknown => {
+                    added_tokens.push(AddedToken::special(&piece.piece, id));
+                    special_tokens.add(SpecialToken {
+                        content: piece.piece.clone(),
+                        id,
+                    });
+                }
+                sentencepiece::PieceType::UserDefined => {
+                    added_tokens.push(AddedToken {
+                        normalized: false,
+                        ..AddedToken::new(&piece.piece, id)
+                    });
+                }
+                _ => {}
+            }
+        }
+        tokenizer.special_tokens = special_tokens;
+        let mut tokenizer = tokenizer.with_added_tokens(added_tokens);
+
+        let mut decoders: Vec<Box<dyn Decoder>> = vec![
+            Box::new(decoders::Replace::new(&space.to_string(), " ")),
+            Box::new(decoders::ByteFallback::new()),
+            Box::new(decoders::Fuse::new()),
+        ];
+        if normalizer_spec.add_dummy_prefix {
+            decoders.push(Box::new(decoders::Strip::new(' ', 1, 0)));
+        }
+        tokenizer.decoder = Some(Box::new(decoders::Sequence::new(decoders)));
+
+        Ok(tokenizer)
+    }
+
+    fn from_parsed_json(
+        json: json::TokenizerJson,
+        registry: &ComponentRegistry,
+    ) -> Result<Tokenizer, FromJsonError> {
+        // Unsupported components found in the configuration. These are
+        // collected so that they can all be reported at once.
+        let mut unsupported = Vec::new();
+
+        let normalizer = json
+            .normalizer
+            .map(|normalizer| Self::normalizer_from_json(normalizer, registry, &mut unsupported))
+            .transpose()?;
+
+        let decoder = json
+            .decoder
+            .map(|decoder| Self::decoder_from_json(decoder, &mut unsupported))
+            .transpose()?
+            .flatten();
+        let pre_tokenizer = json
+            .pre_tokenizer
+            .map(|pre_tokenizer| {
+                Self::pre_tokenizer_from_json(pre_tokenizer, registry, &mut unsupported)
+            })
+            .transpose()?
+            .flatten();
+
+        let mut special_tokens = SpecialTokens::default();
+        for token in json.added_tokens.iter().flatten() {
+            if token.special {
+                special_tokens.add(SpecialToken {
+                    content: token.content.clone(),
+                    id: token.id,
+                });
+            }
+        }
+        let unk_token = match &json.model {
+            json::Model::Bpe(model) => model.unk_token.clone(),
+            json::Model::Unigram(model) => model
+                .unk_id
+                .and_then(|id| model.vocab.get(id as usize))
+                .map(|(token, _score)| token.clone()),
+            json::Model::WordLevel(model) => model.unk_token.clone(),
+            json::Model::WordPiece(model) => model.unk_token.clone(),
+        };
+
+        let mut tokenizer = match json.model {
             json::Model::Bpe(model) => {
                 let added_tokens: HashMap<TokenId, String> = json
                     .added_tokens
@@ -318,788 +1573,3636 @@ impl Tokenizer {
                     })
                     .unwrap_or_default();
                 let merges: Vec<_> = model.merges.iter().map(|s| s.as_str()).collect();
-                let encoder = Bpe::new(
-                    &merges,
-                    bpe::patterns::GPT2,
-                    Some(model.vocab),
-                    added_tokens,
-                )
-                .map_err(FromJsonError::BpeError)?;
-                let tokenizer = Tokenizer::new(
+                // If the text is split by a pre-tokenizer, the encoder
+                // doesn't need to split it again.
+                let pattern = if pre_tokenizer.is_some() {
+                    ""
+                } else {
+                    bpe::patterns::GPT2
+                };
+                let encoder = Bpe::new(&merges, pattern, Some(model.vocab), added_tokens)
+                    .map_err(FromJsonError::BpeError)?;
+                Tokenizer::new(
                     encoder,
                     TokenizerOptions {
                         cls_token: None,
                         sep_token: None,
                     },
-                );
+                )
+            }
+            json::Model::Unigram(model) => {
+                let encoder_opts = UnigramOptions {
+                    normalizer,
+                    unk_id: model.unk_id,
+                    byte_fallback: model.byte_fallback,
+                };
+                let encoder = Unigram::from_vocab(model.vocab, encoder_opts);
+                Tokenizer::new(encoder, TokenizerOptions::default())
+            }
+            json::Model::WordLevel(model) => {
+                let encoder_opts = WordLevelOptions {
+                    normalizer,
+                    unk_token: model.unk_token,
+                };
 
-                Ok(tokenizer)
+                let encoder = WordLevel::from_vocab(model.vocab, encoder_opts);
+                Tokenizer::new(
+                    encoder,
+                    TokenizerOptions {
+                        cls_token: None,
+                        sep_token: None,
+                    },
+                )
             }
             json::Model::WordPiece(model) => {
                 let encoder_opts = WordPieceOptions {
                     normalizer,
-                    ..Default::default()
+                    max_word_len: model.max_input_chars_per_word,
+                    continuing_subword_prefix: model.continuing_subword_prefix,
+                    unk_token: model.unk_token,
                 };
 
                 let encoder = WordPiece::from_vocab(model.vocab, encoder_opts);
-                let tokenizer = Tokenizer::new(
+                Tokenizer::new(
                     encoder,
                     TokenizerOptions {
                         cls_token: Some("[CLS]"),
                         sep_token: Some("[SEP]"),
                     },
-                );
-
-                Ok(tokenizer)
+                )
             }
-        }
-    }
-
-    /// Return the wrapped encoder.
-    pub fn encoder(&self) -> &dyn Encoder {
-        self.encoder.as_ref()
-    }
-
-    fn cls_token(&self) -> Result<Option<TokenId>, TokenizerError> {
-        self.cls_token
-            .as_ref()
-            .map(|cls| self.encoder.get_token_id(cls.as_str()))
-            .transpose()
-    }
-
-    fn sep_token(&self) -> Result<Option<TokenId>, TokenizerError> {
-        self.sep_token
-            .as_ref()
-            .map(|sep| self.encoder.get_token_id(sep.as_str()))
-            .transpose()
-    }
-
-    /// Encode one or two sequences into a sequence of tokens.
-    pub fn encode<'a>(
-        &self,
-        input: EncoderInput<'a>,
-        options: EncodeOptions,
-    ) -> Result<Encoded<'a>, TokenizerError> {
-        let cls_token = self.cls_token()?;
-        let sep_token = self.sep_token()?;
+        };
+        tokenizer.decoder = decoder;
+        tokenizer.pre_tokenizer = pre_tokenizer;
 
-        // To simplify the implementation, we tokenize the whole input and
-        // just discard all chunks except the first. This could be optimized
-        // to only generate one chunk.
-        let chunks = self.encode_chunks(input, options)?;
+        special_tokens.cls = tokenizer.special_tokens.cls.take();
+        special_tokens.sep = tokenizer.special_tokens.sep.take();
+        special_tokens.unk = unk_token.and_then(|token| tokenizer.lookup_special_token(&token));
+        if let Some(padding) = &json.padding {
+            special_tokens.pad = padding.pad_token.as_ref().map(|token| SpecialToken {
+                content: token.clone(),
+                id: padding.pad_id,
+            });
+        }
 
-        let chunk = chunks.into_iter().next().unwrap_or_else(|| {
-            // If the input is empty after tokenization, generate a single
-            // empty chunk.
-            let mut tokens = Vec::new();
-            let mut offsets = Vec::new();
-            let mut first_seq_tokens = 0;
-
-            if let Some(cls_token) = cls_token {
-                tokens.push(cls_token);
-                offsets.push(0);
-                first_seq_tokens += 1;
+        if let Some(post_processor) = json.post_processor {
+            if let Some((cls, sep)) = Self::cls_sep_from_json(&post_processor.config) {
+                special_tokens.cls = Some(cls);
+                special_tokens.sep = Some(sep);
             }
-            if let Some(sep_token) = sep_token {
-                tokens.push(sep_token);
-                offsets.push(0);
-                first_seq_tokens += 1;
-
-                if matches!(input, EncoderInput::Pair(_)) {
-                    tokens.push(sep_token);
-                    offsets.push(0);
-                }
+            if let Some(template) = Self::template_from_json(post_processor, &mut unsupported)? {
+                tokenizer.template = template;
             }
+        }
 
-            Encoded::new(input, tokens, offsets, first_seq_tokens)
+        if !unsupported.is_empty() {
+            return Err(FromJsonError::UnsupportedComponents(unsupported));
+        }
+        tokenizer.special_tokens = special_tokens;
+        let added_tokens = json
+            .added_tokens
+            .iter()
+            .flatten()
+            .map(|token| AddedToken {
+                content: token.content.clone(),
+                id: token.id,
+                special: token.special,
+                lstrip: token.lstrip,
+                rstrip: token.rstrip,
+                single_word: token.single_word,
+                normalized: token.normalized.unwrap_or(!token.special),
+            })
+            .collect();
+        let mut tokenizer = tokenizer.with_added_tokens(added_tokens);
+        tokenizer.truncation = json.truncation.map(|truncation| Truncation {
+            max_length: truncation.max_length,
+            direction: match truncation.direction {
+                json::TruncationDirection::Left => TruncationDirection::Left,
+                json::TruncationDirection::Right => TruncationDirection::Right,
+            },
+            strategy: match truncation.strategy {
+                json::TruncationStrategy::LongestFirst => TruncationStrategy::LongestFirst,
+                json::TruncationStrategy::OnlyFirst => TruncationStrategy::OnlyFirst,
+                json::TruncationStrategy::OnlySecond => TruncationStrategy::OnlySecond,
+            },
+        });
+        tokenizer.padding = json.padding.map(|padding| Padding {
+            length: match padding.strategy {
+                json::PaddingStrategy::BatchLongest => PaddingLength::BatchLongest,
+                json::PaddingStrategy::Fixed(len) => PaddingLength::Fixed(len),
+            },
+            direction: match padding.direction {
+                json::PaddingDirection::Left => PaddingDirection::Left,
+                json::PaddingDirection::Right => PaddingDirection::Right,
+            },
+            pad_id: padding.pad_id,
+            pad_type_id: padding.pad_type_id,
+            pad_to_multiple_of: padding.pad_to_multiple_of,
         });
 
-        Ok(chunk)
+        Ok(tokenizer)
     }
 
-    /// Encode one or two sequences into a sequence of tokens.
+    /// Convert a normalizer configuration from a `tokenizer.json` file.
     ///
-    /// The output is split into chunks such that the number of tokens in
-    /// each chunk is less than the limit specified in [EncodeOptions].
-    pub fn encode_chunks<'a>(
-        &self,
-        input: EncoderInput<'a>,
-        options: EncodeOptions,
-    ) -> Result<Vec<Encoded<'a>>, TokenizerError> {
-        let cls_token = self.cls_token()?;
-        let sep_token = self.sep_token()?;
-
-        let has_cls = cls_token.is_some() as usize;
-        let has_sep = sep_token.is_some() as usize;
-
-        // Number of non-content tokens added to each chunk.
-        let non_content_tokens_per_chunk = has_cls
-            + match input {
-                EncoderInput::Item(_) => has_sep,     // [CLS] .. [SEP]
-                EncoderInput::Pair(_) => has_sep * 2, // [CLS] .. [SEP] .. [SEP]
-            };
+    /// Unsupported normalizers are added to `unsupported`.
+    fn normalizer_from_json(
+        config: serde_json::Value,
+        registry: &ComponentRegistry,
+        unsupported: &mut Vec<UnsupportedComponent>,
+    ) -> Result<Normalizer, FromJsonError> {
+        if let Some(normalizer) = registry.create_normalizer(&config) {
+            return normalizer.map_err(FromJsonError::CustomComponentError);
+        }
+        let type_name = json::component_type(&config)
+            .unwrap_or_default()
+            .to_string();
 
-        // Encode the full input sequences.
-        let mut tokens = Vec::new();
-        let mut offsets = Vec::new();
-        let (first_seq, second_seq) = match input {
-            EncoderInput::Item(first) => (first, None),
-            EncoderInput::Pair((first, second)) => (first, Some(second)),
+        let normalizer = match serde_json::from_value(config).map_err(FromJsonError::JsonError)? {
+            json::Normalizer::Bert(bert_norm) => Normalizer::new(NormalizerOptions {
+                lowercase: bert_norm.lowercase,
+                strip_accents: bert_norm.strip_accents.unwrap_or(bert_norm.lowercase),
+                ..Default::default()
+            }),
+            json::Normalizer::Lowercase => Normalizer::new(NormalizerOptions {
+                lowercase: true,
+                ..Default::default()
+            }),
+            json::Normalizer::Nfc => Normalizer::unicode(NormalizationForm::Nfc),
+            json::Normalizer::Nfd => Normalizer::unicode(NormalizationForm::Nfd),
+            json::Normalizer::Nfkc => Normalizer::unicode(NormalizationForm::Nfkc),
+            json::Normalizer::Nfkd => Normalizer::unicode(NormalizationForm::Nfkd),
+            json::Normalizer::Replace(replace) => match replace.pattern {
+                json::Pattern::String(pattern) => Normalizer::replace(&pattern, &replace.content),
+                json::Pattern::Regex(pattern) => {
+                    Normalizer::replace_regex(&pattern, &replace.content)
+                        .map_err(FromJsonError::RegexError)?
+                }
+            },
+            json::Normalizer::Sequence(sequence) => Normalizer::sequence(
+                sequence
+                    .normalizers
+                    .into_iter()
+                    .map(|normalizer| Self::normalizer_from_json(normalizer, registry, unsupported))
+                    .collect::<Result<_, _>>()?,
+            ),
+            json::Normalizer::StripAccents => Normalizer::strip_accents(),
+            json::Normalizer::Unsupported => {
+                unsupported.push(UnsupportedComponent {
+                    kind: ComponentKind::Normalizer,
+                    type_name,
+                });
+                Normalizer::sequence(Vec::new())
+            }
         };
+        Ok(normalizer)
+    }
 
-        self.encoder
-            .encode_with_offsets(first_seq, &mut |offset, token| {
-                offsets.push(offset);
-                tokens.push(token);
-            })?;
-        let first_seq_tokens = tokens.len();
-
-        if let Some(second_seq) = second_seq {
-            self.encoder
-                .encode_with_offsets(second_seq, &mut |offset, token| {
-                    offsets.push(offset + first_seq.len());
-                    tokens.push(token);
-                })?;
+    /// Get the `[CLS]` and `[SEP]` tokens from a post-processor configuration
+    /// which specifies them.
+    fn cls_sep_from_json(
+        post_processor: &json::PostProcessor,
+    ) -> Option<(SpecialToken, SpecialToken)> {
+        let token = |(content, id): &(String, TokenId)| SpecialToken {
+            content: content.clone(),
+            id: *id,
+        };
+        match post_processor {
+            json::PostProcessor::BertProcessing(processor)
+            | json::PostProcessor::RobertaProcessing(processor) => {
+                Some((token(&processor.cls), token(&processor.sep)))
+            }
+            json::PostProcessor::Sequence(sequence) => sequence
+                .processors
+                .iter()
+                .rev()
+                .find_map(|processor| Self::cls_sep_from_json(&processor.config)),
+            json::PostProcessor::TemplateProcessing(_)
+            | json::PostProcessor::ByteLevel
+            | json::PostProcessor::Unsupported => None,
         }
+    }
 
-        let max_tokens_per_chunk = options
-            .max_chunk_len
-            .unwrap_or(tokens.len() + non_content_tokens_per_chunk)
-            .saturating_sub(non_content_tokens_per_chunk);
+    /// Convert a post-processor configuration from a `tokenizer.json` file
+    /// into a template.
+    ///
+    /// Returns `None` if the post-processor doesn't add special tokens.
+    /// Unsupported post-processors are added to `unsupported`.
+    fn template_from_json(
+        post_processor: json::WithType<json::PostProcessor>,
+        unsupported: &mut Vec<UnsupportedComponent>,
+    ) -> Result<Option<Template>, FromJsonError> {
+        let special = |token: &str| TemplatePiece::SpecialToken(TemplateToken::new(token, 0));
+        let seq_a = TemplatePiece::Sequence(SequenceId::A, 0);
+        let seq_b = TemplatePiece::Sequence(SequenceId::B, 0);
 
-        if max_tokens_per_chunk == 0 {
-            // We can't "consume" tokens from the input in each chunk, so just
-            // return an empty output.
-            return Ok(vec![]);
+        let template = match post_processor.config {
+            json::PostProcessor::BertProcessing(json::ClsSepProcessing {
+                cls: (cls, _),
+                sep: (sep, _),
+            }) => Template::from_cls_sep(Some(&cls), Some(&sep)),
+            json::PostProcessor::RobertaProcessing(json::ClsSepProcessing {
+                cls: (cls, _),
+                sep: (sep, _),
+            }) => Template::from_pieces(
+                [special(&cls), seq_a.clone(), special(&sep)],
+                [
+                    special(&cls),
+                    seq_a,
+                    special(&sep),
+                    special(&sep),
+                    seq_b,
+                    special(&sep),
+                ],
+            )
+            .map_err(FromJsonError::TemplateError)?,
+            json::PostProcessor::TemplateProcessing(template) => {
+                let convert = |piece: json::TemplatePiece| match piece {
+                    json::TemplatePiece::Sequence(seq) => TemplatePiece::Sequence(
+                        match seq.id {
+                            json::SequenceId::A => SequenceId::A,
+                            json::SequenceId::B => SequenceId::B,
+                        },
+                        seq.type_id,
+                    ),
+                    json::TemplatePiece::SpecialToken(token) => {
+                        TemplatePiece::SpecialToken(TemplateToken {
+                            token: token.id,
+                            type_id: token.type_id,
+                        })
+                    }
+                };
+                Template::from_pieces(
+                    template.single.into_iter().map(convert),
+                    template.pair.into_iter().map(convert),
+                )
+                .map_err(FromJsonError::TemplateError)?
+            }
+            json::PostProcessor::Sequence(sequence) => {
+                // Use the last post-processor which adds special tokens.
+                let mut template = None;
+                for processor in sequence.processors {
+                    if let Some(processor_template) =
+                        Self::template_from_json(processor, unsupported)?
+                    {
+                        template = Some(processor_template);
+                    }
+                }
+                return Ok(template);
+            }
+            json::PostProcessor::ByteLevel => return Ok(None),
+            json::PostProcessor::Unsupported => {
+                unsupported.push(UnsupportedComponent {
+                    kind: ComponentKind::PostProcessor,
+                    type_name: post_processor.type_name,
+                });
+                return Ok(None);
+            }
+        };
+        Ok(Some(template))
+    }
+
+    /// Convert a pre-tokenizer configuration from a `tokenizer.json` file.
+    ///
+    /// Returns `None` if the configuration uses pre-tokenizers which are not
+    /// supported, after adding them to `unsupported`.
+    fn pre_tokenizer_from_json(
+        config: serde_json::Value,
+        registry: &ComponentRegistry,
+        unsupported: &mut Vec<UnsupportedComponent>,
+    ) -> Result<Option<Box<dyn PreTokenizer>>, FromJsonError> {
+        if let Some(pre_tokenizer) = registry.create_pre_tokenizer(&config) {
+            return pre_tokenizer
+                .map(Some)
+                .map_err(FromJsonError::CustomComponentError);
         }
+        let type_name = json::component_type(&config)
+            .unwrap_or_default()
+            .to_string();
 
-        // Split into chunks.
-        let mut chunks = Vec::new();
-
-        match input {
-            // For single sequence inputs, create chunks with a maximum of
-            // `max_seq_len` tokens each.
-            EncoderInput::Item(item) => {
-                let all_offsets = &offsets;
-                for (chunk_idx, (tokens_chunk, offsets_chunk)) in tokens
-                    .chunks_with_overlap(max_tokens_per_chunk, options.overlap)
-                    .zip(offsets.chunks_with_overlap(max_tokens_per_chunk, options.overlap))
-                    .enumerate()
-                {
-                    let mut tokens = Vec::new();
-                    let mut offsets = Vec::new();
-
-                    if let Some(cls_token) = cls_token {
-                        tokens.push(cls_token);
-                        offsets.push(offsets_chunk.first().copied().unwrap());
+        let pre_tokenizer: Box<dyn PreTokenizer> = match serde_json::from_value(config)
+            .map_err(FromJsonError::JsonError)?
+        {
+            json::PreTokenizer::Bert => {
+                // Split on whitespace, and then isolate punctuation
+                // characters. This matches how the WordPiece encoder splits
+                // text.
+                let split = |pattern, behavior| {
+                    pre_tokenizers::Split::regex(pattern, behavior)
+                        .map(|split| Box::new(split) as Box<dyn PreTokenizer>)
+                        .map_err(FromJsonError::RegexError)
+                };
+                Box::new(pre_tokenizers::Sequence::new(vec![
+                    split(r"\s+", pre_tokenizers::SplitDelimiterBehavior::Removed)?,
+                    split(
+                        r"[[:punct:]\p{P}]",
+                        pre_tokenizers::SplitDelimiterBehavior::Isolated,
+                    )?,
+                ]))
+            }
+            json::PreTokenizer::ByteLevel(byte_level) => {
+                // The mapping of bytes to characters is done by the BPE
+                // encoder, so only the prefix space and splitting are
+                // handled here.
+                let mut steps: Vec<Box<dyn PreTokenizer>> = Vec::new();
+                if byte_level.add_prefix_space.unwrap_or(true) {
+                    steps.push(Box::new(pre_tokenizers::Metaspace::new(
+                        ' ',
+                        pre_tokenizers::PrependScheme::Always,
+                        false,
+                    )));
+                }
+                if byte_level.use_regex.unwrap_or(true) {
+                    steps.push(Box::new(
+                        pre_tokenizers::Split::regex(
+                            bpe::patterns::GPT2,
+                            pre_tokenizers::SplitDelimiterBehavior::Isolated,
+                        )
+                        .map_err(FromJsonError::RegexError)?,
+                    ));
+                }
+                Box::new(pre_tokenizers::Sequence::new(steps))
+            }
+            json::PreTokenizer::Metaspace(metaspace) => {
+                let prepend_scheme = match metaspace.prepend_scheme.as_deref() {
+                    Some("first") => pre_tokenizers::PrependScheme::First,
+                    Some("never") => pre_tokenizers::PrependScheme::Never,
+                    Some(_) => pre_tokenizers::PrependScheme::Always,
+                    None => match metaspace.add_prefix_space.unwrap_or(true) {
+                        true => pre_tokenizers::PrependScheme::Always,
+                        false => pre_tokenizers::PrependScheme::Never,
+                    },
+                };
+                Box::new(pre_tokenizers::Metaspace::new(
+                    metaspace.replacement,
+                    prepend_scheme,
+                    metaspace.split.unwrap_or(true),
+                ))
+            }
+            json::PreTokenizer::Sequence(sequence) => {
+                // Convert all entries, so that every unsupported one is
+                // reported.
+                let mut pre_tokenizers = Vec::with_capacity(sequence.pretokenizers.len());
+                for pre_tokenizer in sequence.pretokenizers {
+                    pre_tokenizers.push(Self::pre_tokenizer_from_json(
+                        pre_tokenizer,
+                        registry,
+                        unsupported,
+                    )?);
+                }
+                let Some(pre_tokenizers) = pre_tokenizers.into_iter().collect() else {
+                    return Ok(None);
+                };
+                Box::new(pre_tokenizers::Sequence::new(pre_tokenizers))
+            }
+            json::PreTokenizer::Split(split) => {
+                let behavior = match split.behavior {
+                    json::SplitDelimiterBehavior::Removed => {
+                        pre_tokenizers::SplitDelimiterBehavior::Removed
                     }
-
-                    tokens.extend_from_slice(tokens_chunk);
-                    offsets.extend_from_slice(offsets_chunk);
-
-                    if let Some(sep_token) = sep_token {
-                        tokens.push(sep_token);
+                    json::SplitDelimiterBehavior::Isolated => {
+                        pre_tokenizers::SplitDelimiterBehavior::Isolated
+                    }
+                    json::SplitDelimiterBehavior::MergedWithPrevious => {
+                        pre_tokenizers::SplitDelimiterBehavior::MergedWithPrevious
+                    }
+                    json::SplitDelimiterBehavior::MergedWithNext => {
+                        pre_tokenizers::SplitDelimiterBehavior::MergedWithNext
+                    }
+                    json::SplitDelimiterBehavior::Contiguous => {
+                        pre_tokenizers::SplitDelimiterBehavior::Contiguous
                     }
+                };
+                let invert = split.invert;
+                let split = match split.pattern {
+                    json::Pattern::String(pattern) => {
+                        pre_tokenizers::Split::new(&pattern, behavior)
+                    }
+                    json::Pattern::Regex(pattern) => {
+                        pre_tokenizers::Split::regex(&pattern, behavior)
+                            .map_err(FromJsonError::RegexError)?
+                    }
+                };
+                Box::new(split.with_invert(invert))
+            }
+            json::PreTokenizer::UnicodeScripts => Box::new(pre_tokenizers::UnicodeScripts::new()),
+            json::PreTokenizer::Whitespace => Box::new(
+                pre_tokenizers::Split::regex(
+                    r"\w+|[^\w\s]+",
+                    pre_tokenizers::SplitDelimiterBehavior::Removed,
+                )
+                .map_err(FromJsonError::RegexError)?
+                .with_invert(true),
+            ),
+            json::PreTokenizer::WhitespaceSplit => Box::new(
+                pre_tokenizers::Split::regex(
+                    r"\s+",
+                    pre_tokenizers::SplitDelimiterBehavior::Removed,
+                )
+                .map_err(FromJsonError::RegexError)?,
+            ),
+            json::PreTokenizer::Unsupported => {
+                unsupported.push(UnsupportedComponent {
+                    kind: ComponentKind::PreTokenizer,
+                    type_name,
+                });
+                return Ok(None);
+            }
+        };
+        Ok(Some(pre_tokenizer))
+    }
 
-                    // The offset for the final token is the offset of the first
-                    // token in the next chunk, or the input length if this
-                    // is the final chunk.
-                    let chunk_start = chunk_idx * max_tokens_per_chunk;
-                    offsets.push(
-                        all_offsets
-                            .get(chunk_start + offsets_chunk.len())
-                            .copied()
-                            .unwrap_or(item.len()),
-                    );
-
-                    let n_tokens = tokens.len();
-                    chunks.push(Encoded::new(input, tokens, offsets, n_tokens));
+    /// Convert a decoder configuration from a `tokenizer.json` file.
+    ///
+    /// Returns `None` if the configuration uses decoders which are not
+    /// supported, after adding them to `unsupported`.
+    fn decoder_from_json(
+        decoder: json::WithType<json::Decoder>,
+        unsupported: &mut Vec<UnsupportedComponent>,
+    ) -> Result<Option<Box<dyn Decoder>>, FromJsonError> {
+        let decoder: Box<dyn Decoder> = match decoder.config {
+            json::Decoder::ByteFallback => Box::new(decoders::ByteFallback::new()),
+            json::Decoder::ByteLevel => Box::new(decoders::ByteLevel::new()),
+            json::Decoder::Fuse => Box::new(decoders::Fuse::new()),
+            json::Decoder::Metaspace(metaspace) => {
+                let strip_prefix_space = match metaspace.prepend_scheme.as_deref() {
+                    Some(scheme) => scheme != "never",
+                    None => metaspace.add_prefix_space.unwrap_or(true),
+                };
+                Box::new(decoders::Metaspace::new(
+                    metaspace.replacement,
+                    strip_prefix_space,
+                ))
+            }
+            json::Decoder::Replace(replace) => match replace.pattern {
+                json::Pattern::String(pattern) => {
+                    Box::new(decoders::Replace::new(&pattern, &replace.content))
+                }
+                json::Pattern::Regex(pattern) => Box::new(
+                    decoders::Replace::regex(&pattern, &replace.content)
+                        .map_err(FromJsonError::RegexError)?,
+                ),
+            },
+            json::Decoder::Sequence(sequence) => {
+                let mut decoders = Vec::with_capacity(sequence.decoders.len());
+                for decoder in sequence.decoders {
+                    decoders.push(Self::decoder_from_json(decoder, unsupported)?);
                 }
+                let Some(decoders) = decoders.into_iter().collect() else {
+                    return Ok(None);
+                };
+                Box::new(decoders::Sequence::new(decoders))
+            }
+            json::Decoder::Strip(strip) => {
+                Box::new(decoders::Strip::new(strip.content, strip.start, strip.stop))
             }
+            json::Decoder::WordPiece(wordpiece) => Box::new(decoders::WordPiece::new(
+                &wordpiece.prefix,
+                wordpiece.cleanup,
+            )),
+            json::Decoder::Unsupported => {
+                unsupported.push(UnsupportedComponent {
+                    kind: ComponentKind::Decoder,
+                    type_name: decoder.type_name,
+                });
+                return Ok(None);
+            }
+        };
+        Ok(Some(decoder))
+    }
 
-            // For input sequence pairs, create chunks where the first part is
-            // the same for each chunk and has a maximum of `max_seq_len` tokens,
-            // and the second part contains chunks of the second sequence,
-            // taking up the remaining available space in the chunk.
-            EncoderInput::Pair((first, second)) => {
-                let (first_tokens, second_tokens) = tokens.split_at(first_seq_tokens);
-                let (first_offsets, second_offsets) = offsets.split_at(first_seq_tokens);
+    /// Return the wrapped encoder.
+    pub fn encoder(&self) -> &dyn Encoder {
+        self.encoder.as_ref()
+    }
 
-                let first_len = first_tokens.len().min(max_tokens_per_chunk);
-                let second_len = second_tokens.len().min(max_tokens_per_chunk - first_len);
+    /// Reduce the vocabulary to the tokens in `keep`.
+    ///
+    /// Returns a tokenizer which uses the reduced vocabulary, and the mapping
+    /// between the original and new token IDs. Tokens in the reduced
+    /// vocabulary are numbered from zero. This is useful for models which
+    /// are only used with a subset of the vocabulary, as the embedding and
+    /// output matrices can be reduced to match (see
+    /// [VocabMapping::new_to_old]).
+    ///
+    /// Special tokens, added tokens, the tokens added by the [Template] and
+    /// the padding token are always kept. If the encoder produces a token
+    /// which was removed, it is replaced by the [unknown
+    /// token](SpecialTokens::unk) if there is one, or encoding fails with
+    /// [TokenizerError::MissingToken] otherwise.
+    pub fn prune_vocab(
+        mut self,
+        keep: &[TokenId],
+    ) -> Result<(Tokenizer, VocabMapping), TokenizerError> {
+        for &id in keep {
+            self.encoder.get_token_str(id)?;
+        }
 
-                if second_len == 0 {
-                    // We can't "consume" tokens from the second sequence in
-                    // each chunk, so just return an empty output.
-                    return Ok(vec![]);
-                }
+        let SpecialTokens {
+            bos,
+            eos,
+            unk,
+            pad,
+            cls,
+            sep,
+            mask,
+            all,
+        } = &self.special_tokens;
+        let special_ids = [bos, eos, unk, pad, cls, sep, mask]
+            .into_iter()
+            .flatten()
+            .chain(all)
+            .map(|token| token.id);
+        let added_ids = self.added_tokens.iter().map(|token| token.id);
+        let template_ids = [&self.template.single, &self.template.pair]
+            .into_iter()
+            .flat_map(|parts| [&parts.prefix, &parts.middle, &parts.suffix])
+            .flatten()
+            .filter_map(|token| self.encoder.get_token_id(&token.token).ok());
+        let pad_id = self.padding.as_ref().map(|padding| padding.pad_id);
 
-                for (chunk_idx, (tokens_chunk, offsets_chunk)) in second_tokens
-                    .chunks_with_overlap(second_len, options.overlap)
-                    .zip(second_offsets.chunks_with_overlap(second_len, options.overlap))
-                    .enumerate()
-                {
-                    let mut tokens = Vec::new();
-                    let mut offsets = Vec::new();
+        let mapping = VocabMapping::new(
+            keep.iter()
+                .copied()
+                .chain(special_ids)
+                .chain(added_ids)
+                .chain(template_ids)
+                .chain(pad_id),
+        );
 
-                    // Add the first sequence. This is the same for every chunk.
-                    if let Some(cls_token) = cls_token {
-                        tokens.push(cls_token);
-                        offsets.push(0);
-                    }
+        // All of the IDs below were added to the mapping.
+        let map_id = |id: TokenId| mapping.old_to_new(id).unwrap();
+        let special_tokens = &mut self.special_tokens;
+        for token in [
+            &mut special_tokens.bos,
+            &mut special_tokens.eos,
+            &mut special_tokens.unk,
+            &mut special_tokens.pad,
+            &mut special_tokens.cls,
+            &mut special_tokens.sep,
+            &mut special_tokens.mask,
+        ]
+        .into_iter()
+        .flatten()
+        .chain(&mut special_tokens.all)
+        {
+            token.id = map_id(token.id);
+        }
+        for token in &mut self.added_tokens {
+            token.id = map_id(token.id);
+        }
+        if let Some(padding) = &mut self.padding {
+            padding.pad_id = map_id(padding.pad_id);
+        }
 
-                    tokens.extend_from_slice(&first_tokens[..first_len]);
-                    offsets.extend_from_slice(&first_offsets[..first_len]);
+        let unk_id = self.special_tokens.unk.as_ref().map(|token| token.id);
+        self.encoder = Box::new(PrunedEncoder::new(self.encoder, mapping.clone(), unk_id));
 
-                    if let Some(sep_token) = sep_token {
-                        tokens.push(sep_token);
-                        offsets.push(first.len());
-                    }
+        Ok((self, mapping))
+    }
 
-                    let first_seq_len = tokens.len();
+    /// Reduce the vocabulary to the tokens needed to encode the texts in
+    /// `corpus`.
+    ///
+    /// This is a convenience wrapper around [Tokenizer::prune_vocab] which
+    /// keeps every token produced by encoding the texts with the default
+    /// options.
+    pub fn prune_vocab_for_corpus(
+        self,
+        corpus: &[&str],
+    ) -> Result<(Tokenizer, VocabMapping), TokenizerError> {
+        let mut keep = Vec::new();
+        let options = EncodeOptions::default();
+        for text in corpus {
+            self.encode_sequence(
+                text,
+                &options,
+                self.pre_tokenizer.as_deref(),
+                &mut |_range, id, _word| keep.push(id),
+            )?;
+        }
+        self.prune_vocab(&keep)
+    }
 
-                    // Add the second sequence, which changes in each chunk.
-                    tokens.extend_from_slice(tokens_chunk);
-                    offsets.extend_from_slice(offsets_chunk);
+    /// Encode a prompt for generation with token healing.
+    ///
+    /// Returns the token IDs for `prompt` with the final token removed, the
+    /// text of the removed token and the IDs of vocabulary tokens whose text
+    /// starts with it. See [TokenHealing] for an explanation.
+    ///
+    /// Special tokens from the [Template] are not added. If the prompt is
+    /// empty or ends with an [AddedToken], no token is removed and the list
+    /// of candidates is empty.
+    ///
+    /// This decodes every token in the vocabulary, so callers should avoid
+    /// calling it repeatedly for the same prompt.
+    pub fn heal_prompt(&self, prompt: &str) -> Result<TokenHealing, TokenizerError> {
+        let mut token_ids = Vec::new();
+        let mut offsets = Vec::new();
+        let options = EncodeOptions::default();
+        let pre_tokenizer = self.pre_tokenizer.as_deref();
+        self.encode_sequence(prompt, &options, pre_tokenizer, &mut |range, id, _word| {
+            token_ids.push(id);
+            offsets.push(range);
+        })?;
 
-                    // The offset for the final token is the offset of the first
-                    // token from the second sequence in the next chunk, or
-                    // the concatenated input length if this is the final chunk.
-                    if let Some(sep_token) = sep_token {
-                        tokens.push(sep_token);
-                    }
-                    let chunk_start = chunk_idx * second_len;
-                    offsets.push(
-                        second_offsets
-                            .get(chunk_start + offsets_chunk.len())
-                            .copied()
-                            .unwrap_or(first.len() + second.len()),
-                    );
-
-                    chunks.push(Encoded::new(input, tokens, offsets, first_seq_len));
-                }
-            }
+        let Some(last) = offsets.last().cloned() else {
+            return Ok(TokenHealing::default());
+        };
+        let last_id = *token_ids.last().unwrap();
+        if self.added_tokens.iter().any(|token| token.id == last_id) {
+            return Ok(TokenHealing {
+                token_ids,
+                ..Default::default()
+            });
         }
 
-        Ok(chunks)
-    }
-}
+        // Remove all trailing tokens for the final piece of text. There can be
+        // several if byte fallback was used.
+        let n_removed = offsets
+            .iter()
+            .rev()
+            .take_while(|range| range.start == last.start)
+            .count();
+        let removed_ids = token_ids.split_off(token_ids.len() - n_removed);
+        let prefix = self
+            .decode(&removed_ids)
+            .unwrap_or_else(|_| prompt[last.start..].to_string());
 
-/// Error type returned when tokenizing a string.
-#[derive(Clone, Debug)]
-pub enum TokenizerError {
-    /// A token was not found in the vocabulary.
-    MissingToken(String),
+        // Tokens are compared using their decoded text rather than canonical
+        // strings, as the latter may encode bytes or spaces differently.
+        let candidates = (0..self.encoder.vocab_size() as TokenId)
+            .filter(|&id| !self.is_special_token(id))
+            .filter(|&id| {
+                self.decode(&[id])
+                    .is_ok_and(|text| !text.is_empty() && text.starts_with(&prefix))
+            })
+            .collect();
 
-    /// No token with a given ID exists in the vocabulary.
-    InvalidTokenId(TokenId),
+        Ok(TokenHealing {
+            token_ids,
+            prefix,
+            candidates,
+        })
+    }
 
-    /// Splitting the input with a regex failed.
-    RegexSplitFailed(Box<fancy_regex::Error>),
+    /// Decode a sequence of token IDs into a text string.
+    ///
+    /// If the tokenizer has a [Decoder], the token IDs are converted to their
+    /// canonical strings, which are then transformed by the decoder.
+    /// Otherwise this is equivalent to [Encoder::decode].
+    pub fn decode(&self, ids: &[TokenId]) -> Result<String, TokenizerError> {
+        match &self.decoder {
+            Some(decoder) => decoder.decode(self.encoder.get_tokens(ids)?),
+            None => self.encoder.decode(ids),
+        }
+    }
 
-    /// There was an error parsing a byte sequence as a UTF-8 string.
+    /// Decode a sequence of token IDs into a text string, using the given
+    /// options.
     ///
-    /// This can arise when working with tokenizers like [Bpe] where
-    /// individual tokens do not always represent whole characters.
-    InvalidUtf8,
-}
+    /// See [Tokenizer::decode].
+    pub fn decode_with_options(
+        &self,
+        ids: &[TokenId],
+        options: DecodeOptions,
+    ) -> Result<String, TokenizerError> {
+        let text = if options.skip_special_tokens {
+            let ids: Vec<TokenId> = ids
+                .iter()
+                .copied()
+                .filter(|id| !self.is_special_token(*id))
+                .collect();
+            self.decode(&ids)?
+        } else {
+            self.decode(ids)?
+        };
+        if options.clean_up_tokenization_spaces {
+            Ok(decoders::cleanup_tokenization(&text))
+        } else {
+            Ok(text)
+        }
+    }
 
-impl fmt::Display for TokenizerError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Self::MissingToken(ref token) => write!(f, "missing vocab token {}", token),
-            Self::InvalidTokenId(id) => write!(f, "unknown token id {}", id),
-            Self::RegexSplitFailed(err) => write!(f, "regex failed {}", err),
-            Self::InvalidUtf8 => write!(f, "UTF-8 decode failed"),
+    /// Return true if `id` is an added token marked as special, or one of the
+    /// tokens returned by [Tokenizer::special_tokens].
+    fn is_special_token(&self, id: TokenId) -> bool {
+        let SpecialTokens {
+            bos,
+            eos,
+            unk,
+            pad,
+            cls,
+            sep,
+            mask,
+            all,
+        } = &self.special_tokens;
+        self.added_tokens
+            .iter()
+            .any(|token| token.special && token.id == id)
+            || [bos, eos, unk, pad, cls, sep, mask]
+                .into_iter()
+                .flatten()
+                .chain(all)
+                .any(|token| token.id == id)
+    }
+
+    /// Create a [DecodeStream] which decodes token IDs one at a time.
+    pub fn decode_stream(&self) -> DecodeStream<'_> {
+        DecodeStream {
+            tokenizer: self,
+            ids: Vec::new(),
+            prefix_tokens: 0,
+            prefix: String::new(),
         }
     }
-}
 
-impl Error for TokenizerError {}
+    /// Decode a batch of token ID sequences into text strings.
+    ///
+    /// This is equivalent to calling [Tokenizer::decode] for each sequence.
+    pub fn decode_batch<S: AsRef<[TokenId]>>(
+        &self,
+        sequences: &[S],
+    ) -> Result<Vec<String>, TokenizerError> {
+        sequences
+            .iter()
+            .map(|ids| self.decode(ids.as_ref()))
+            .collect()
+    }
 
-#[cfg(test)]
-mod tests {
-    use std::collections::HashMap;
-    use std::error::Error;
-    use std::fs::read_to_string;
-    use std::ops::Range;
-    use std::path::PathBuf;
+    /// Look up the IDs of the special tokens added around `input`.
+    ///
+    /// If [`add_special_tokens`](EncodeOptions::add_special_tokens) is false,
+    /// no tokens are added, but the sequences keep their token type IDs.
+    fn template_token_ids(
+        &self,
+        input: EncoderInput,
+        options: &EncodeOptions,
+    ) -> Result<TemplateTokenIds, TokenizerError> {
+        let parts = match input {
+            EncoderInput::Item(_) | EncoderInput::Words(_) => &self.template.single,
+            EncoderInput::Pair(_) => &self.template.pair,
+        };
+        let lookup = |tokens: &[TemplateToken]| -> Result<Vec<(TokenId, usize)>, TokenizerError> {
+            if !options.add_special_tokens {
+                return Ok(Vec::new());
+            }
+            tokens
+                .iter()
+                .map(|token| Ok((self.encoder.get_token_id(&token.token)?, token.type_id)))
+                .collect()
+        };
+        Ok(TemplateTokenIds {
+            prefix: lookup(&parts.prefix)?,
+            middle: lookup(&parts.middle)?,
+            suffix: lookup(&parts.suffix)?,
+            sequence_type_ids: parts.sequence_type_ids,
+        })
+    }
 
-    use super::{EncodeOptions, EncoderInput, TokenId, Tokenizer, TokenizerOptions, WordPiece};
-    use serde::Deserialize;
+    /// Return the padding settings to use for an encoding operation.
+    fn padding<'a>(&'a self, options: &'a EncodeOptions) -> Option<&'a Padding> {
+        options.padding.as_ref().or(self.padding.as_ref())
+    }
 
-    fn make_wordpiece(vocab: &[&str]) -> WordPiece {
-        let vocab: HashMap<_, _> = vocab
-            .iter()
-            .enumerate()
-            .map(|(i, token)| (token.to_string(), i as u32))
-            .collect();
-        WordPiece::from_vocab(vocab, Default::default())
+    /// Encode one or two sequences into a sequence of tokens.
+    pub fn encode<'a>(
+        &self,
+        input: EncoderInput<'a>,
+        options: EncodeOptions,
+    ) -> Result<Encoded<'a>, TokenizerError> {
+        let padding = self.padding(&options).cloned();
+        let offset_type = options.offset_type;
+        let mut encoded = self.encode_unpadded(input, options)?;
+        if let Some(padding) = padding {
+            padding.apply(std::slice::from_mut(&mut encoded));
+        }
+        encoded.offset_type = offset_type;
+        Ok(encoded)
     }
 
-    // The tests below use the WordPiece encoder to exercise common Tokenizer
-    // functionality. This is convenient as WordPiece is simple.
+    /// Encode a pair of sequences into a sequence of tokens.
+    ///
+    /// This is a convenience wrapper around [Tokenizer::encode] for tasks such
+    /// as question answering, natural language inference or reranking. The
+    /// sequences are combined using the tokenizer's [Template].
+    pub fn encode_pair<'a>(
+        &self,
+        first: &'a str,
+        second: &'a str,
+        options: EncodeOptions,
+    ) -> Result<Encoded<'a>, TokenizerError> {
+        self.encode(EncoderInput::Pair((first, second)), options)
+    }
 
-    #[test]
-    fn test_encode_two_sequences() {
-        let vocab = &[
-            "[CLS]", "[SEP]", "[UNK]", "This", "is", "a", "test", "sequence",
-        ];
-        let encoder = make_wordpiece(vocab);
-        let tokenizer = Tokenizer::new(
-            encoder,
-            TokenizerOptions {
-                cls_token: Some("[CLS]"),
-                sep_token: Some("[SEP]"),
-            },
-        );
+    /// Encode a sequence which has already been split into words.
+    ///
+    /// This is a convenience wrapper around [Tokenizer::encode] for tasks such
+    /// as token classification, where labels are assigned to words defined
+    /// outside of the tokenizer. The pre-tokenizer is skipped, but the model
+    /// and [Template] are applied as usual. Use [Encoded::word_ids] to map
+    /// tokens back to the index of the word in `words`.
+    pub fn encode_words<'a>(
+        &self,
+        words: &'a [&'a str],
+        options: EncodeOptions,
+    ) -> Result<Encoded<'a>, TokenizerError> {
+        self.encode(EncoderInput::Words(words), options)
+    }
 
-        // Two sequences, no subwords.
-        let encoded = tokenizer
-            .encode(
-                ("This is", "a test sequence").into(),
-                EncodeOptions::default(),
-            )
-            .unwrap();
-        assert_eq!(
-            tokenizer.encoder().get_tokens(encoded.token_ids()).unwrap(),
-            &["[CLS]", "This", "is", "[SEP]", "a", "test", "sequence", "[SEP]"]
-        );
+    /// Encode a batch of inputs.
+    ///
+    /// `inputs` can be a slice of strings, or of [EncoderInput]s to encode
+    /// sequence pairs. This is equivalent to calling [Tokenizer::encode] for
+    /// each input, except that [PaddingLength::BatchLongest] pads each output
+    /// to the length of the longest output in the batch.
+    ///
+    /// If the `rayon` crate feature is enabled, inputs are encoded in
+    /// parallel.
+    pub fn encode_batch<'a, I: Copy + Into<EncoderInput<'a>> + Sync>(
+        &self,
+        inputs: &[I],
+        options: EncodeOptions,
+    ) -> Result<Vec<Encoded<'a>>, TokenizerError> {
+        let encode = |input: &I| self.encode_unpadded((*input).into(), options.clone());
 
-        let token_type_ids: Vec<_> = encoded.token_type_ids().collect();
-        assert_eq!(token_type_ids, &[0, 0, 0, 0, 1, 1, 1, 1]);
+        #[cfg(feature = "rayon")]
+        let mut outputs = {
+            use rayon::prelude::*;
+            inputs
+                .par_iter()
+                .map(encode)
+                .collect::<Result<Vec<_>, _>>()?
+        };
+
+        #[cfg(not(feature = "rayon"))]
+        let mut outputs = inputs.iter().map(encode).collect::<Result<Vec<_>, _>>()?;
+
+        if let Some(padding) = self.padding(&options) {
+            padding.apply(&mut outputs);
+        }
+        for output in &mut outputs {
+            output.offset_type = options.offset_type;
+        }
+        Ok(outputs)
     }
 
-    #[test]
-    fn test_text_for_token_range() {
-        struct Case<'a> {
-            input: EncoderInput<'a>,
-            range: Range<usize>,
-            expected: Option<&'a str>,
+    fn encode_unpadded<'a>(
+        &self,
+        input: EncoderInput<'a>,
+        options: EncodeOptions,
+    ) -> Result<Encoded<'a>, TokenizerError> {
+        if let Some(truncation) = options.truncation.as_ref().or(self.truncation.as_ref()) {
+            return self.encode_truncated(input, truncation, &options);
         }
 
-        let vocab = &[
-            "[CLS]", "[SEP]", "[UNK]", "This", "is", "a", "test", "sequence", "Word", "##Piece",
-            "Piece", "of", "pie", ".", "!", "?", "Hey", "Hello",
-        ];
+        let special_tokens = self.template_token_ids(input, &options)?;
 
-        let cases = [
-            // Part of a single sequence
-            Case {
-                input: "This is a test sequence".into(),
-                range: 4..6,
-                expected: Some("test sequence"),
-            },
-            // Whole of a single sentence
-            Case {
-                input: "This is a test sequence".into(),
-                range: 1..6,
-                expected: Some("This is a test sequence"),
-            },
-            // Part of first item in a pair
-            Case {
-                input: ("This is a test sequence", "Hey Hello").into(),
-                range: 4..6,
-                expected: Some("test sequence"),
-            },
-            // Whole of first item in a pair
+        // To simplify the implementation, we tokenize the whole input and
+        // just discard all chunks except the first. This could be optimized
+        // to only generate one chunk.
+        let chunks = self.encode_chunks_unpadded(input, options)?;
+
+        let chunk = chunks.into_iter().next().unwrap_or_else(|| {
+            // If the input is empty after tokenization, generate a single
+            // empty chunk.
+            let empty = SequenceTokens {
+                tokens: &[],
+                offsets: &[],
+                words: &[],
+                end_offset: 0,
+            };
+            let second = matches!(input, EncoderInput::Pair(_)).then_some(empty);
+            special_tokens.apply(input, empty, second)
+        });
+
+        Ok(chunk)
+    }
+
+    /// Encode the sequences in `input` without adding special tokens.
+    ///
+    /// Returns `(token_ids, offsets, words, first_seq_tokens)` where
+    /// `offsets` are the byte ranges that each token was produced from,
+    /// `words` are the word indices of each token and `first_seq_tokens` is
+    /// the number of tokens from the first sequence. Offsets for the second
+    /// sequence, or for the words of a pre-split input, are relative to the
+    /// start of the first.
+    #[allow(clippy::type_complexity)]
+    fn encode_sequences(
+        &self,
+        input: EncoderInput,
+        options: &EncodeOptions,
+    ) -> Result<(Vec<TokenId>, Vec<Range<usize>>, Vec<usize>, usize), TokenizerError> {
+        let mut tokens = Vec::new();
+        let mut offsets = Vec::new();
+        let mut words = Vec::new();
+        let pre_tokenizer = self.pre_tokenizer.as_deref();
+        let (first_seq, second_seq) = match input {
+            EncoderInput::Item(first) => (first, None),
+            EncoderInput::Pair((first, second)) => (first, Some(second)),
+            EncoderInput::Words(input_words) => {
+                // Each word is encoded without pre-tokenization, and all of
+                // its tokens are assigned the word's index.
+                let mut base = 0;
+                for (word_index, word) in input_words.iter().enumerate() {
+                    self.encode_sequence(word, options, None, &mut |range, token, _| {
+                        offsets.push(range.start + base..range.end + base);
+                        tokens.push(token);
+                        words.push(word_index);
+                    })?;
+                    base += word.len();
+                }
+                let n_tokens = tokens.len();
+                return Ok((tokens, offsets, words, n_tokens));
+            }
+        };
+
+        self.encode_sequence(
+            first_seq,
+            options,
+            pre_tokenizer,
+            &mut |range, token, word| {
+                offsets.push(range);
+                tokens.push(token);
+                words.push(word);
+            },
+        )?;
+        let first_seq_tokens = tokens.len();
+
+        if let Some(second_seq) = second_seq {
+            self.encode_sequence(
+                second_seq,
+                options,
+                pre_tokenizer,
+                &mut |range, token, word| {
+                    offsets.push(range.start + first_seq.len()..range.end + first_seq.len());
+                    tokens.push(token);
+                    words.push(word);
+                },
+            )?;
+        }
+
+        Ok((tokens, offsets, words, first_seq_tokens))
+    }
+
+    /// Encode a single sequence without adding special tokens.
+    ///
+    /// Added tokens are matched in `text` first, and the text between them is
+    /// split into words using `pre_tokenizer`, if given, and passed to the
+    /// encoder. `on_token` is called with the byte range, ID and word index of
+    /// each token.
+    fn encode_sequence(
+        &self,
+        text: &str,
+        options: &EncodeOptions,
+        pre_tokenizer: Option<&dyn PreTokenizer>,
+        on_token: &mut dyn FnMut(Range<usize>, TokenId, usize),
+    ) -> Result<(), TokenizerError> {
+        let encode_segment =
+            |range: Range<usize>,
+             word: &mut usize,
+             on_token: &mut dyn FnMut(Range<usize>, TokenId, usize)| {
+                if range.is_empty() {
+                    return Ok(());
+                }
+                let Some(pre_tokenizer) = pre_tokenizer else {
+                    let start = range.start;
+                    let segment_word = *word;
+                    *word += 1;
+                    return self
+                        .encoder
+                        .encode_with_offsets(&text[range], &mut |range, token| {
+                            on_token(range.start + start..range.end + start, token, segment_word)
+                        });
+                };
+
+                let pieces = pre_tokenizer
+                    .pre_tokenize_chain(vec![PreToken::new(&text[range.clone()], range.start)])?;
+                for piece in pieces {
+                    let piece_word = *word;
+                    *word += 1;
+                    self.encoder
+                        .encode_with_offsets(&piece.text, &mut |range, token| {
+                            on_token(map_range(text, &piece.offsets, range), token, piece_word)
+                        })?;
+                }
+                Ok(())
+            };
+
+        let mut pos = 0;
+        let mut word = 0;
+        for (range, id) in self.find_added_tokens(text, options) {
+            encode_segment(pos..range.start, &mut word, on_token)?;
+            on_token(range.clone(), id, word);
+            word += 1;
+            pos = range.end;
+        }
+        encode_segment(pos..text.len(), &mut word, on_token)
+    }
+
+    /// Find non-overlapping occurrences of added tokens in `text`.
+    ///
+    /// Returns the byte range of each match, including any whitespace that
+    /// the token strips, and the token ID. If several tokens match at the
+    /// same position, the longest is used.
+    fn find_added_tokens(
+        &self,
+        text: &str,
+        options: &EncodeOptions,
+    ) -> Vec<(Range<usize>, TokenId)> {
+        // Candidate matches as `(range, range_with_whitespace, token_index)`.
+        let mut candidates = Vec::new();
+
+        for (range, index) in self
+            .added_token_matcher
+            .find(text, self.encoder.normalizer())
+        {
+            let token = &self.added_tokens[index];
+            if token.special && options.split_special_tokens {
+                continue;
+            }
+            if token.single_word && !is_single_word(text, range.clone()) {
+                continue;
+            }
+            let mut span = range.clone();
+            if token.lstrip {
+                span.start = text[..span.start].trim_end().len();
+            }
+            if token.rstrip {
+                span.end = text.len() - text[span.end..].trim_start().len();
+            }
+            candidates.push((range, span, index));
+        }
+        candidates.sort_by_key(|(range, _, index)| (range.start, Reverse(range.end), *index));
+
+        let mut matches = Vec::new();
+        let mut end = 0;
+        for (range, span, index) in candidates {
+            if range.start < end {
+                continue;
+            }
+            matches.push((span.start.max(end)..span.end, self.added_tokens[index].id));
+            end = span.end;
+        }
+        matches
+    }
+
+    /// Encode one or two sequences and truncate the result to fit within
+    /// the maximum length specified by `truncation`.
+    fn encode_truncated<'a>(
+        &self,
+        input: EncoderInput<'a>,
+        truncation: &Truncation,
+        options: &EncodeOptions,
+    ) -> Result<Encoded<'a>, TokenizerError> {
+        let special_tokens = self.template_token_ids(input, options)?;
+
+        let (tokens, offsets, words, first_seq_tokens) = self.encode_sequences(input, options)?;
+        let (first_tokens, second_tokens) = tokens.split_at(first_seq_tokens);
+        let (first_offsets, second_offsets) = offsets.split_at(first_seq_tokens);
+        let (first_words, second_words) = words.split_at(first_seq_tokens);
+
+        let max_content_len = truncation.max_length.saturating_sub(special_tokens.len());
+
+        let (keep_first, keep_second) = match input {
+            EncoderInput::Item(_) | EncoderInput::Words(_) => {
+                (first_tokens.len().min(max_content_len), 0)
+            }
+            EncoderInput::Pair(_) => {
+                truncation.keep_lengths(first_tokens.len(), second_tokens.len(), max_content_len)?
+            }
+        };
+        let first_range = truncation.keep_range(first_tokens.len(), keep_first);
+        let second_range = truncation.keep_range(second_tokens.len(), keep_second);
+
+        let encoded = match input {
+            EncoderInput::Item(_) | EncoderInput::Words(_) => special_tokens.apply(
+                input,
+                SequenceTokens::slice(
+                    first_tokens,
+                    first_offsets,
+                    first_words,
+                    first_range,
+                    input.text_len(),
+                ),
+                None,
+            ),
+            EncoderInput::Pair((first, second)) => special_tokens.apply(
+                input,
+                SequenceTokens::slice(
+                    first_tokens,
+                    first_offsets,
+                    first_words,
+                    first_range,
+                    first.len(),
+                ),
+                Some(SequenceTokens::slice(
+                    second_tokens,
+                    second_offsets,
+                    second_words,
+                    second_range,
+                    first.len() + second.len(),
+                )),
+            ),
+        };
+        Ok(encoded)
+    }
+
+    /// Encode one or two sequences into a sequence of tokens.
+    ///
+    /// The output is split into chunks such that the number of tokens in
+    /// each chunk is less than the limit specified in [EncodeOptions].
+    pub fn encode_chunks<'a>(
+        &self,
+        input: EncoderInput<'a>,
+        options: EncodeOptions,
+    ) -> Result<Vec<Encoded<'a>>, TokenizerError> {
+        let padding = self.padding(&options).cloned();
+        let offset_type = options.offset_type;
+        let mut chunks = self.encode_chunks_unpadded(input, options)?;
+        if let Some(padding) = padding {
+            padding.apply(&mut chunks);
+        }
+        for chunk in &mut chunks {
+            chunk.offset_type = offset_type;
+        }
+        Ok(chunks)
+    }
+
+    fn encode_chunks_unpadded<'a>(
+        &self,
+        input: EncoderInput<'a>,
+        options: EncodeOptions,
+    ) -> Result<Vec<Encoded<'a>>, TokenizerError> {
+        let special_tokens = self.template_token_ids(input, &options)?;
+
+        // Number of non-content tokens added to each chunk.
+        let non_content_tokens_per_chunk = special_tokens.len();
+
+        // Encode the full input sequences.
+        let (tokens, offsets, words, first_seq_tokens) = self.encode_sequences(input, &options)?;
+
+        let max_tokens_per_chunk = options
+            .max_chunk_len
+            .unwrap_or(tokens.len() + non_content_tokens_per_chunk)
+            .saturating_sub(non_content_tokens_per_chunk);
+
+        if max_tokens_per_chunk == 0 {
+            // We can't "consume" tokens from the input in each chunk, so just
+            // return an empty output.
+            return Ok(vec![]);
+        }
+
+        // Split into chunks.
+        let mut chunks = Vec::new();
+
+        match input {
+            // For single sequence inputs, create chunks with a maximum of
+            // `max_seq_len` tokens each.
+            EncoderInput::Item(_) | EncoderInput::Words(_) => {
+                let all_offsets = &offsets;
+                for (chunk_idx, ((tokens_chunk, offsets_chunk), words_chunk)) in tokens
+                    .chunks_with_overlap(max_tokens_per_chunk, options.overlap)
+                    .zip(offsets.chunks_with_overlap(max_tokens_per_chunk, options.overlap))
+                    .zip(words.chunks_with_overlap(max_tokens_per_chunk, options.overlap))
+                    .enumerate()
+                {
+                    // The end offset is the offset of the first token in the
+                    // next chunk, or the input length if this is the final
+                    // chunk.
+                    let chunk_start = chunk_idx * max_tokens_per_chunk;
+                    let end_offset = all_offsets
+                        .get(chunk_start + offsets_chunk.len())
+                        .map_or(input.text_len(), |r| r.start);
+
+                    chunks.push(special_tokens.apply(
+                        input,
+                        SequenceTokens {
+                            tokens: tokens_chunk,
+                            offsets: offsets_chunk,
+                            words: words_chunk,
+                            end_offset,
+                        },
+                        None,
+                    ));
+                }
+            }
+
+            // For input sequence pairs, create chunks where the first part is
+            // the same for each chunk and has a maximum of `max_seq_len` tokens,
+            // and the second part contains chunks of the second sequence,
+            // taking up the remaining available space in the chunk.
+            EncoderInput::Pair((first, second)) => {
+                let (first_tokens, second_tokens) = tokens.split_at(first_seq_tokens);
+                let (first_offsets, second_offsets) = offsets.split_at(first_seq_tokens);
+                let (first_words, second_words) = words.split_at(first_seq_tokens);
+
+                let first_len = first_tokens.len().min(max_tokens_per_chunk);
+                let second_len = second_tokens.len().min(max_tokens_per_chunk - first_len);
+
+                if second_len == 0 {
+                    // We can't "consume" tokens from the second sequence in
+                    // each chunk, so just return an empty output.
+                    return Ok(vec![]);
+                }
+
+                // The first sequence is the same for every chunk.
+                let first_seq = SequenceTokens {
+                    tokens: &first_tokens[..first_len],
+                    offsets: &first_offsets[..first_len],
+                    words: &first_words[..first_len],
+                    end_offset: first.len(),
+                };
+
+                for (chunk_idx, ((tokens_chunk, offsets_chunk), words_chunk)) in second_tokens
+                    .chunks_with_overlap(second_len, options.overlap)
+                    .zip(second_offsets.chunks_with_overlap(second_len, options.overlap))
+                    .zip(second_words.chunks_with_overlap(second_len, options.overlap))
+                    .enumerate()
+                {
+                    // The end offset is the offset of the first token from
+                    // the second sequence in the next chunk, or the
+                    // concatenated input length if this is the final chunk.
+                    let chunk_start = chunk_idx * second_len;
+                    let end_offset = second_offsets
+                        .get(chunk_start + offsets_chunk.len())
+                        .map_or(first.len() + second.len(), |r| r.start);
+
+                    chunks.push(special_tokens.apply(
+                        input,
+                        first_seq,
+                        Some(SequenceTokens {
+                            tokens: tokens_chunk,
+                            offsets: offsets_chunk,
+                            words: words_chunk,
+                            end_offset,
+                        }),
+                    ));
+                }
+            }
+        }
+
+        Ok(chunks)
+    }
+}
+
+/// Incrementally decodes token IDs into text.
+///
+/// This is used to display the output of a model as it is generated. Token
+/// IDs are added one at a time using [DecodeStream::step], which returns any
+/// text that has been completed by the new token.
+///
+/// Tokens are decoded together with the preceding tokens, so that decoders
+/// which depend on context (eg. to remove the space at the start of the
+/// output) produce the same text as [Tokenizer::decode] for the whole
+/// sequence. Tokens which do not yet form a complete UTF-8 sequence are
+/// buffered until they do.
+///
+/// Use [Tokenizer::decode_stream] to create a decode stream.
+pub struct DecodeStream<'a> {
+    tokenizer: &'a Tokenizer,
+
+    /// Tokens from the last returned chunk of text, followed by tokens that
+    /// have not been decoded yet.
+    ids: Vec<TokenId>,
+
+    /// Number of tokens at the start of `ids` from the last returned chunk.
+    prefix_tokens: usize,
+
+    /// Decoded text for the first `prefix_tokens` tokens in `ids`.
+    prefix: String,
+}
+
+impl DecodeStream<'_> {
+    /// Add a token ID to the stream.
+    ///
+    /// Returns the text completed by this token, or `None` if more tokens are
+    /// needed to produce new text.
+    pub fn step(&mut self, id: TokenId) -> Result<Option<String>, TokenizerError> {
+        self.ids.push(id);
+
+        let text = match self.tokenizer.decode(&self.ids) {
+            Ok(text) => text,
+            Err(TokenizerError::InvalidUtf8) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        // Wait for more tokens if the text ends with an incomplete character
+        // which the decoder replaced with U+FFFD.
+        if text.len() <= self.prefix.len() || text.ends_with(char::REPLACEMENT_CHARACTER) {
+            return Ok(None);
+        }
+        let new_text = text
+            .strip_prefix(&self.prefix)
+            .ok_or(TokenizerError::DecodeStreamFailed)?
+            .to_string();
+
+        self.ids.drain(..self.prefix_tokens);
+        self.prefix = self.tokenizer.decode(&self.ids)?;
+        self.prefix_tokens = self.ids.len();
+
+        Ok(Some(new_text))
+    }
+}
+
+/// Error type returned when tokenizing a string.
+#[derive(Clone, Debug)]
+pub enum TokenizerError {
+    /// A token was not found in the vocabulary.
+    MissingToken(String),
+
+    /// No token with a given ID exists in the vocabulary.
+    InvalidTokenId(TokenId),
+
+    /// Splitting the input with a regex failed.
+    RegexSplitFailed(Box<fancy_regex::Error>),
+
+    /// There was an error parsing a byte sequence as a UTF-8 string.
+    ///
+    /// This can arise when working with tokenizers like [Bpe] where
+    /// individual tokens do not always represent whole characters.
+    InvalidUtf8,
+
+    /// The input could not be truncated to the maximum length using the
+    /// selected [TruncationStrategy], because the sequence which is not
+    /// truncated is too long.
+    TruncationFailed,
+
+    /// A [DecodeStream] could not produce new text because decoding the
+    /// latest tokens changed previously returned text.
+    DecodeStreamFailed,
+}
+
+impl fmt::Display for TokenizerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingToken(ref token) => write!(f, "missing vocab token {}", token),
+            Self::InvalidTokenId(id) => write!(f, "unknown token id {}", id),
+            Self::RegexSplitFailed(err) => write!(f, "regex failed {}", err),
+            Self::InvalidUtf8 => write!(f, "UTF-8 decode failed"),
+            Self::TruncationFailed => write!(f, "sequence too long to truncate to max length"),
+            Self::DecodeStreamFailed => write!(f, "decoded text does not extend previous text"),
+        }
+    }
+}
+
+impl Error for TokenizerError {}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::error::Error;
+    use std::fs::read_to_string;
+    use std::ops::Range;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use super::bpe::char_to_byte;
+    use super::pre_tokenizers::{PreToken, PreTokenizer, Split, SplitDelimiterBehavior};
+    use super::sentencepiece::tests::create_model;
+    use super::{
+        decoders, patterns, AddedToken, Bpe, ComponentKind, ComponentRegistry, DecodeOptions,
+        EncodeOptions, EncoderInput, FromJsonError, FromSentencePieceError, OffsetType, Padding,
+        PaddingDirection, PaddingLength, SpecialToken, Template, TokenHealing, TokenId, Tokenizer,
+        TokenizerError, TokenizerOptions, Truncation, TruncationDirection, TruncationStrategy,
+        UnsupportedComponent, WordPiece, WordPieceOptions,
+    };
+    use crate::normalizer::{Normalizer, NormalizerOptions};
+    use serde::Deserialize;
+
+    fn make_wordpiece(vocab: &[&str]) -> WordPiece {
+        let vocab: HashMap<_, _> = vocab
+            .iter()
+            .enumerate()
+            .map(|(i, token)| (token.to_string(), i as u32))
+            .collect();
+        WordPiece::from_vocab(vocab, Default::default())
+    }
+
+    // The tests below use the WordPiece encoder to exercise common Tokenizer
+    // functionality. This is convenient as WordPiece is simple.
+
+    #[test]
+    fn test_encode_two_sequences() {
+        let vocab = &[
+            "[CLS]", "[SEP]", "[UNK]", "This", "is", "a", "test", "sequence",
+        ];
+        let encoder = make_wordpiece(vocab);
+        let tokenizer = Tokenizer::new(
+            encoder,
+            TokenizerOptions {
+                cls_token: Some("[CLS]"),
+                sep_token: Some("[SEP]"),
+            },
+        );
+
+        // Two sequences, no subwords.
+        let encoded = tokenizer
+            .encode(
+                ("This is", "a test sequence").into(),
+                EncodeOptions::default(),
+            )
+            .unwrap();
+        assert_eq!(
+            tokenizer.encoder().get_tokens(encoded.token_ids()).unwrap(),
+            &["[CLS]", "This", "is", "[SEP]", "a", "test", "sequence", "[SEP]"]
+        );
+
+        let token_type_ids: Vec<_> = encoded.token_type_ids().collect();
+        assert_eq!(token_type_ids, &[0, 0, 0, 0, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_encode_truncation() {
+        struct Case<'a> {
+            input: EncoderInput<'a>,
+            truncation: Truncation,
+            tokens: &'a [&'a str],
+            token_type_ids: &'a [usize],
+        }
+
+        let vocab = &[
+            "[CLS]", "[SEP]", "[UNK]", "This", "is", "a", "test", "sequence", "short",
+        ];
+        let tokenizer = Tokenizer::new(
+            make_wordpiece(vocab),
+            TokenizerOptions {
+                cls_token: Some("[CLS]"),
+                sep_token: Some("[SEP]"),
+            },
+        );
+        let truncation = |max_length, direction, strategy| Truncation {
+            max_length,
+            direction,
+            strategy,
+        };
+        use TruncationDirection::{Left, Right};
+        use TruncationStrategy::{LongestFirst, OnlyFirst, OnlySecond};
+
+        let cases = [
+            // Single sequence which fits.
+            Case {
+                input: "This is a test".into(),
+                truncation: Truncation::new(10),
+                tokens: &["[CLS]", "This", "is", "a", "test", "[SEP]"],
+                token_type_ids: &[0, 0, 0, 0, 0, 0],
+            },
+            // Single sequence, truncated from the right and left.
+            Case {
+                input: "This is a test".into(),
+                truncation: Truncation::new(4),
+                tokens: &["[CLS]", "This", "is", "[SEP]"],
+                token_type_ids: &[0, 0, 0, 0],
+            },
+            Case {
+                input: "This is a test".into(),
+                truncation: truncation(4, Left, LongestFirst),
+                tokens: &["[CLS]", "a", "test", "[SEP]"],
+                token_type_ids: &[0, 0, 0, 0],
+            },
+            // Max length is shorter than the special tokens.
+            Case {
+                input: "This is a test".into(),
+                truncation: Truncation::new(1),
+                tokens: &["[CLS]", "[SEP]"],
+                token_type_ids: &[0, 0],
+            },
+            // Pair where the longer sequence is truncated.
+            Case {
+                input: ("short", "This is a test sequence").into(),
+                truncation: Truncation::new(7),
+                tokens: &["[CLS]", "short", "[SEP]", "This", "is", "a", "[SEP]"],
+                token_type_ids: &[0, 0, 0, 1, 1, 1, 1],
+            },
+            // Pair where both sequences are truncated. The longer sequence
+            // gets the extra token if the space can't be split evenly.
+            Case {
+                input: ("This is a test", "a test sequence").into(),
+                truncation: Truncation::new(8),
+                tokens: &["[CLS]", "This", "is", "a", "[SEP]", "a", "test", "[SEP]"],
+                token_type_ids: &[0, 0, 0, 0, 0, 1, 1, 1],
+            },
+            Case {
+                input: ("This is a test", "a test sequence").into(),
+                truncation: truncation(7, Right, OnlyFirst),
+                tokens: &["[CLS]", "This", "[SEP]", "a", "test", "sequence", "[SEP]"],
+                token_type_ids: &[0, 0, 0, 1, 1, 1, 1],
+            },
+            Case {
+                input: ("This is a test", "a test sequence").into(),
+                truncation: truncation(8, Left, OnlySecond),
+                tokens: &[
+                    "[CLS]", "This", "is", "a", "test", "[SEP]", "sequence", "[SEP]",
+                ],
+                token_type_ids: &[0, 0, 0, 0, 0, 0, 1, 1],
+            },
+        ];
+
+        for Case {
+            input,
+            truncation,
+            tokens,
+            token_type_ids,
+        } in cases
+        {
+            let options = EncodeOptions {
+                truncation: Some(truncation.clone()),
+                ..Default::default()
+            };
+            let encoded = tokenizer.encode(input, options).unwrap();
+            assert_eq!(
+                tokenizer.encoder().get_tokens(encoded.token_ids()).unwrap(),
+                tokens
+            );
+            assert_eq!(encoded.token_type_ids().collect::<Vec<_>>(), token_type_ids);
+            assert_eq!(encoded.token_offsets().len(), tokens.len());
+        }
+
+        // Sequence which is not truncated is too long.
+        let options = EncodeOptions {
+            truncation: Some(truncation(6, Right, OnlySecond)),
+            ..Default::default()
+        };
+        let result = tokenizer.encode(("This is a test", "a test sequence").into(), options);
+        assert!(matches!(result, Err(TokenizerError::TruncationFailed)));
+
+        // Default truncation settings for the tokenizer.
+        let tokenizer = tokenizer.with_truncation(Truncation::new(3));
+        let encoded = tokenizer
+            .encode("This is a test".into(), Default::default())
+            .unwrap();
+        assert_eq!(
+            tokenizer.encoder().get_tokens(encoded.token_ids()).unwrap(),
+            &["[CLS]", "This", "[SEP]"]
+        );
+        assert_eq!(encoded.text_for_token_range(1..2), Some("This "));
+    }
+
+    #[test]
+    fn test_encode_padding() {
+        let vocab = &[
+            "[PAD]", "[CLS]", "[SEP]", "[UNK]", "This", "is", "a", "test", "sequence",
+        ];
+        let tokenizer = Tokenizer::new(
+            make_wordpiece(vocab),
+            TokenizerOptions {
+                cls_token: Some("[CLS]"),
+                sep_token: Some("[SEP]"),
+            },
+        );
+        let encode = |tokenizer: &Tokenizer, input: EncoderInput, padding: Padding| {
+            let options = EncodeOptions {
+                padding: Some(padding),
+                ..Default::default()
+            };
+            let encoded = tokenizer.encode(input, options).unwrap();
+            let tokens = tokenizer.encoder().get_tokens(encoded.token_ids()).unwrap();
+            let token_type_ids: Vec<_> = encoded.token_type_ids().collect();
+            (tokens, token_type_ids)
+        };
+
+        // Pad to fixed length on the right.
+        let (tokens, type_ids) = encode(
+            &tokenizer,
+            ("This is", "a test").into(),
+            Padding::new(PaddingLength::Fixed(8), 0),
+        );
+        assert_eq!(
+            tokens,
+            &["[CLS]", "This", "is", "[SEP]", "a", "test", "[SEP]", "[PAD]"]
+        );
+        assert_eq!(type_ids, &[0, 0, 0, 0, 1, 1, 1, 0]);
+
+        // Pad to fixed length on the left.
+        let (tokens, type_ids) = encode(
+            &tokenizer,
+            ("This is", "a test").into(),
+            Padding {
+                direction: PaddingDirection::Left,
+                pad_type_id: 2,
+                ..Padding::new(PaddingLength::Fixed(9), 0)
+            },
+        );
+        assert_eq!(
+            tokens,
+            &["[PAD]", "[PAD]", "[CLS]", "This", "is", "[SEP]", "a", "test", "[SEP]"]
+        );
+        assert_eq!(type_ids, &[2, 2, 0, 0, 0, 0, 1, 1, 1]);
+
+        // Outputs longer than the fixed length are not changed.
+        let (tokens, _) = encode(
+            &tokenizer,
+            "This is a test".into(),
+            Padding::new(PaddingLength::Fixed(3), 0),
+        );
+        assert_eq!(tokens, &["[CLS]", "This", "is", "a", "test", "[SEP]"]);
+
+        // Pad to a multiple of a given length.
+        let (tokens, _) = encode(
+            &tokenizer,
+            "This is a".into(),
+            Padding {
+                pad_to_multiple_of: Some(4),
+                ..Padding::new(PaddingLength::BatchLongest, 0)
+            },
+        );
+        assert_eq!(
+            tokens,
+            &["[CLS]", "This", "is", "a", "[SEP]", "[PAD]", "[PAD]", "[PAD]"]
+        );
+
+        // Padding doesn't affect the mapping of tokens to text.
+        let options = EncodeOptions {
+            padding: Some(Padding::new(PaddingLength::Fixed(8), 0)),
+            ..Default::default()
+        };
+        let encoded = tokenizer.encode("This is".into(), options).unwrap();
+        assert_eq!(encoded.text_for_token_range(1..3), Some("This is"));
+    }
+
+    #[test]
+    fn test_offset_mapping() {
+        let vocab = &[
+            "[PAD]", "[CLS]", "[SEP]", "[UNK]", "this", "is", "cafe", "test", "##s",
+        ];
+        let vocab: HashMap<_, _> = vocab
+            .iter()
+            .enumerate()
+            .map(|(i, token)| (token.to_string(), i as u32))
+            .collect();
+        let encoder = WordPiece::from_vocab(
+            vocab,
+            WordPieceOptions {
+                normalizer: Some(Normalizer::new(NormalizerOptions {
+                    lowercase: true,
+                    strip_accents: true,
+                    ..Default::default()
+                })),
+                ..Default::default()
+            },
+        );
+        let tokenizer = Tokenizer::new(
+            encoder,
+            TokenizerOptions {
+                cls_token: Some("[CLS]"),
+                sep_token: Some("[SEP]"),
+            },
+        );
+
+        // Offsets refer to the source text before normalization, and are in
+        // characters rather than bytes.
+        let encoded = tokenizer
+            .encode("This Café".into(), Default::default())
+            .unwrap();
+        assert_eq!(
+            tokenizer.encoder().get_tokens(encoded.token_ids()).unwrap(),
+            &["[CLS]", "this", "cafe", "[SEP]"]
+        );
+        assert_eq!(encoded.offset_mapping(), &[(0, 0), (0, 4), (5, 9), (0, 0)]);
+
+        // Offsets for each sequence in a pair are relative to that sequence.
+        // Subword and unknown tokens have their own offsets.
+        let options = EncodeOptions {
+            padding: Some(Padding::new(PaddingLength::Fixed(9), 0)),
+            ..Default::default()
+        };
+        let encoded = tokenizer
+            .encode(("Café ", "tests ñ").into(), options)
+            .unwrap();
+        assert_eq!(
+            tokenizer.encoder().get_tokens(encoded.token_ids()).unwrap(),
+            &["[CLS]", "cafe", "[SEP]", "test", "##s", "[UNK]", "[SEP]", "[PAD]", "[PAD]"]
+        );
+        assert_eq!(
+            encoded.offset_mapping(),
+            &[
+                (0, 0),
+                (0, 4),
+                (0, 0),
+                (0, 4),
+                (4, 5),
+                (6, 7),
+                (0, 0),
+                (0, 0),
+                (0, 0)
+            ]
+        );
+
+        // Byte offsets.
+        let options = EncodeOptions {
+            offset_type: OffsetType::Byte,
+            ..Default::default()
+        };
+        let encoded = tokenizer.encode(("Café", "ñ").into(), options).unwrap();
+        assert_eq!(
+            encoded.offset_mapping(),
+            &[(0, 0), (0, 5), (0, 0), (0, 2), (0, 0)]
+        );
+        let (start, end) = encoded.offset_mapping()[1];
+        assert_eq!(&"Café"[start..end], "Café");
+    }
+
+    #[test]
+    fn test_attention_mask() {
+        let vocab = &[
+            "[PAD]", "[CLS]", "[SEP]", "[UNK]", "This", "is", "a", "test",
+        ];
+        let tokenizer = Tokenizer::new(
+            make_wordpiece(vocab),
+            TokenizerOptions {
+                cls_token: Some("[CLS]"),
+                sep_token: Some("[SEP]"),
+            },
+        );
+
+        // No padding.
+        let encoded = tokenizer
+            .encode("This is".into(), Default::default())
+            .unwrap();
+        assert_eq!(encoded.attention_mask(), &[1, 1, 1, 1]);
+
+        // Padding on the right.
+        let options = EncodeOptions {
+            padding: Some(Padding::new(PaddingLength::Fixed(6), 0)),
+            ..Default::default()
+        };
+        let encoded = tokenizer.encode("This is".into(), options).unwrap();
+        assert_eq!(encoded.attention_mask(), &[1, 1, 1, 1, 0, 0]);
+
+        // Padding on the left.
+        let options = EncodeOptions {
+            padding: Some(Padding {
+                direction: PaddingDirection::Left,
+                ..Padding::new(PaddingLength::Fixed(8), 0)
+            }),
+            ..Default::default()
+        };
+        let encoded = tokenizer.encode(("This", "a").into(), options).unwrap();
+        assert_eq!(encoded.attention_mask(), &[0, 0, 0, 1, 1, 1, 1, 1]);
+        assert_eq!(encoded.attention_mask().len(), encoded.token_ids().len());
+    }
+
+    #[test]
+    fn test_encoded_tokens() {
+        let vocab = &[
+            "[PAD]", "[CLS]", "[SEP]", "[UNK]", "this", "is", "test", "##s",
+        ];
+        let tokenizer = Tokenizer::new(
+            make_wordpiece(vocab),
+            TokenizerOptions {
+                cls_token: Some("[CLS]"),
+                sep_token: Some("[SEP]"),
+            },
+        );
+        let options = EncodeOptions {
+            padding: Some(Padding::new(PaddingLength::Fixed(8), 0)),
+            ..Default::default()
+        };
+        let encoded = tokenizer.encode("this is tests!".into(), options).unwrap();
+        assert_eq!(
+            encoded.tokens(&tokenizer).unwrap(),
+            &["[CLS]", "this", "is", "test", "##s", "[UNK]", "[SEP]", "[PAD]"]
+        );
+    }
+
+    #[test]
+    fn test_word_ids() {
+        let vocab = &[
+            "[PAD]", "[CLS]", "[SEP]", "[UNK]", "<mask>", "this", "is", "a", "test", "##s", ".",
+        ];
+        let tokenizer = Tokenizer::new(
+            make_wordpiece(vocab),
+            TokenizerOptions {
+                cls_token: Some("[CLS]"),
+                sep_token: Some("[SEP]"),
+            },
+        )
+        .with_added_tokens(vec![AddedToken::new("<mask>", 4)]);
+
+        // Without a pre-tokenizer, the text between added tokens is one word.
+        let encoded = tokenizer
+            .encode("this is<mask>tests".into(), Default::default())
+            .unwrap();
+        assert_eq!(
+            tokenizer.encoder().get_tokens(encoded.token_ids()).unwrap(),
+            &["[CLS]", "this", "is", "<mask>", "test", "##s", "[SEP]"]
+        );
+        assert_eq!(
+            encoded.word_ids().collect::<Vec<_>>(),
+            &[None, Some(0), Some(0), Some(1), Some(2), Some(2), None]
+        );
+
+        // With a pre-tokenizer, each piece is a word. Word indices restart
+        // for the second sequence, and padding has no word index.
+        let pre_tokenizer = Split::regex(r"\w+|[^\w\s]+", SplitDelimiterBehavior::Removed)
+            .unwrap()
+            .with_invert(true);
+        let tokenizer = tokenizer.with_pre_tokenizer(pre_tokenizer);
+        let options = EncodeOptions {
+            padding: Some(Padding::new(PaddingLength::Fixed(12), 0)),
+            ..Default::default()
+        };
+        let encoded = tokenizer
+            .encode(("this is a <mask>.", "tests").into(), options)
+            .unwrap();
+        assert_eq!(
+            tokenizer.encoder().get_tokens(encoded.token_ids()).unwrap(),
+            &[
+                "[CLS]", "this", "is", "a", "<mask>", ".", "[SEP]", "test", "##s", "[SEP]",
+                "[PAD]", "[PAD]"
+            ]
+        );
+        assert_eq!(
+            encoded.word_ids().collect::<Vec<_>>(),
+            &[
+                None,
+                Some(0),
+                Some(1),
+                Some(2),
+                Some(3),
+                Some(4),
+                None,
+                Some(0),
+                Some(0),
+                None,
+                None,
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn test_encode_words() {
+        let vocab = &[
+            "[PAD]", "[CLS]", "[SEP]", "[UNK]", "<mask>", "this", "is", "a", "test", "##s", ".",
+        ];
+        let pre_tokenizer = Split::regex(r"\w+|[^\w\s]+", SplitDelimiterBehavior::Removed)
+            .unwrap()
+            .with_invert(true);
+        let tokenizer = Tokenizer::new(
+            make_wordpiece(vocab),
+            TokenizerOptions {
+                cls_token: Some("[CLS]"),
+                sep_token: Some("[SEP]"),
+            },
+        )
+        .with_added_tokens(vec![AddedToken::new("<mask>", 4)])
+        .with_pre_tokenizer(pre_tokenizer);
+
+        // Each input word is encoded separately, and its tokens share the
+        // word's index. Added tokens are matched within words.
+        let words = ["this", "is", "tests", "<mask>."];
+        let encoded = tokenizer.encode_words(&words, Default::default()).unwrap();
+        assert_eq!(
+            tokenizer.encoder().get_tokens(encoded.token_ids()).unwrap(),
+            &["[CLS]", "this", "is", "test", "##s", "<mask>", ".", "[SEP]"]
+        );
+        assert_eq!(
+            encoded.word_ids().collect::<Vec<_>>(),
+            &[
+                None,
+                Some(0),
+                Some(1),
+                Some(2),
+                Some(2),
+                Some(3),
+                Some(3),
+                None
+            ]
+        );
+
+        // Offsets are relative to the word that each token came from.
+        assert_eq!(
+            encoded.offset_mapping(),
+            &[
+                (0, 0),
+                (0, 4),
+                (0, 2),
+                (0, 4),
+                (4, 5),
+                (0, 6),
+                (6, 7),
+                (0, 0)
+            ]
+        );
+        assert_eq!(encoded.text_for_token_range(3..5), Some("tests"));
+        assert_eq!(encoded.char_to_token(4, 2), Some(4));
+        assert_eq!(encoded.char_to_token(0, 4), None);
+        assert_eq!(encoded.token_to_chars(6), Some(6..7));
+
+        // Truncation applies to the tokens from all words.
+        let options = EncodeOptions {
+            truncation: Some(Truncation::new(5)),
+            ..Default::default()
+        };
+        let encoded = tokenizer.encode_words(&words, options).unwrap();
+        assert_eq!(
+            tokenizer.encoder().get_tokens(encoded.token_ids()).unwrap(),
+            &["[CLS]", "this", "is", "test", "[SEP]"]
+        );
+    }
+
+    #[test]
+    fn test_char_to_token() {
+        let vocab = &[
+            "[PAD]", "[CLS]", "[SEP]", "[UNK]", "this", "is", "cafe", "test", "##s",
+        ];
+        let vocab: HashMap<_, _> = vocab
+            .iter()
+            .enumerate()
+            .map(|(i, token)| (token.to_string(), i as u32))
+            .collect();
+        let encoder = WordPiece::from_vocab(
+            vocab,
+            WordPieceOptions {
+                normalizer: Some(Normalizer::new(NormalizerOptions {
+                    lowercase: true,
+                    strip_accents: true,
+                    ..Default::default()
+                })),
+                ..Default::default()
+            },
+        );
+        let tokenizer = Tokenizer::new(
+            encoder,
+            TokenizerOptions {
+                cls_token: Some("[CLS]"),
+                sep_token: Some("[SEP]"),
+            },
+        );
+        let options = EncodeOptions {
+            padding: Some(Padding::new(PaddingLength::Fixed(8), 0)),
+            ..Default::default()
+        };
+        let encoded = tokenizer
+            .encode(("Café is", "tests").into(), options.clone())
+            .unwrap();
+        assert_eq!(
+            tokenizer.encoder().get_tokens(encoded.token_ids()).unwrap(),
+            &["[CLS]", "cafe", "is", "[SEP]", "test", "##s", "[SEP]", "[PAD]"]
+        );
+
+        // Offsets are in characters by default.
+        assert_eq!(encoded.char_to_token(0, 0), Some(1));
+        assert_eq!(encoded.char_to_token(3, 0), Some(1));
+        assert_eq!(encoded.char_to_token(4, 0), None); // Whitespace
+        assert_eq!(encoded.char_to_token(6, 0), Some(2));
+        assert_eq!(encoded.char_to_token(7, 0), None); // Out of bounds
+        assert_eq!(encoded.char_to_token(0, 1), Some(4));
+        assert_eq!(encoded.char_to_token(4, 1), Some(5));
+        assert_eq!(encoded.char_to_token(0, 2), None); // No such sequence
+
+        assert_eq!(encoded.token_to_chars(0), None); // Special token
+        assert_eq!(encoded.token_to_chars(1), Some(0..4));
+        assert_eq!(encoded.token_to_chars(2), Some(5..7));
+        assert_eq!(encoded.token_to_chars(4), Some(0..4));
+        assert_eq!(encoded.token_to_chars(5), Some(4..5));
+        assert_eq!(encoded.token_to_chars(7), None); // Padding
+        assert_eq!(encoded.token_to_chars(8), None); // Out of bounds
+
+        // The two lookups are inverses of each other for content tokens.
+        for (i, span) in encoded.offset_mapping().into_iter().enumerate() {
+            if let Some(range) = encoded.token_to_chars(i) {
+                assert_eq!(range, span.0..span.1);
+            }
+        }
+
+        // Byte offsets.
+        let options = EncodeOptions {
+            offset_type: OffsetType::Byte,
+            ..options
+        };
+        let encoded = tokenizer.encode("Café is".into(), options).unwrap();
+        assert_eq!(encoded.char_to_token(3, 0), Some(1));
+        assert_eq!(encoded.char_to_token(7, 0), Some(2));
+        assert_eq!(encoded.token_to_chars(1), Some(0..5));
+        assert_eq!(encoded.token_to_chars(2), Some(6..8));
+    }
+
+    #[test]
+    fn test_prune_vocab() {
+        let vocab = &[
+            "[PAD]", "[CLS]", "[SEP]", "[UNK]", "the", "cat", "dog", "sat", "on", "mat", "##s",
+            "<mask>",
+        ];
+        let make_tokenizer = || {
+            Tokenizer::new(
+                make_wordpiece(vocab),
+                TokenizerOptions {
+                    cls_token: Some("[CLS]"),
+                    sep_token: Some("[SEP]"),
+                },
+            )
+            .with_added_tokens(vec![AddedToken::special("<mask>", 11)])
+        };
+        let tokenizer = make_tokenizer()
+            .with_special_tokens_map(r#"{"unk_token": "[UNK]", "pad_token": "[PAD]"}"#)
+            .unwrap();
+
+        let (tokenizer, mapping) = tokenizer
+            .prune_vocab_for_corpus(&["the cat sat", "the cats"])
+            .unwrap();
+
+        // Tokens from the corpus and special tokens are kept.
+        assert_eq!(mapping.len(), 9);
+        assert_eq!(mapping.new_to_old(), &[0, 1, 2, 3, 4, 5, 7, 10, 11]);
+        assert_eq!(
+            mapping.old_to_new_table(),
+            &[0, 1, 2, 3, 4, 5, -1, 6, -1, -1, 7, 8]
+        );
+        assert_eq!(mapping.old_to_new(7), Some(6));
+        assert_eq!(mapping.old_to_new(6), None);
+
+        // Removed tokens are replaced by the unknown token.
+        let encoded = tokenizer
+            .encode("the dog sat<mask>".into(), Default::default())
+            .unwrap();
+        assert_eq!(encoded.token_ids(), &[1, 4, 3, 6, 8, 2]);
+        assert_eq!(
+            tokenizer.encoder().get_tokens(encoded.token_ids()).unwrap(),
+            &["[CLS]", "the", "[UNK]", "sat", "<mask>", "[SEP]"]
+        );
+        assert_eq!(tokenizer.special_tokens().unk.as_ref().unwrap().id, 3);
+        assert_eq!(tokenizer.special_tokens().pad.as_ref().unwrap().id, 0);
+        assert_eq!(tokenizer.added_tokens()[0].id, 8);
+        assert_eq!(tokenizer.encoder().get_token_id("sat").unwrap(), 6);
+        assert!(tokenizer.encoder().get_token_id("dog").is_err());
+        assert!(tokenizer.encoder().get_token_str(9).is_err());
+
+        // Without an unknown token, encoding removed tokens fails.
+        let (tokenizer, mapping) = make_tokenizer().prune_vocab(&[4, 5]).unwrap();
+        assert_eq!(mapping.new_to_old(), &[1, 2, 4, 5, 11]);
+        let encoded = tokenizer
+            .encode("cat the".into(), Default::default())
+            .unwrap();
+        assert_eq!(encoded.token_ids(), &[0, 3, 2, 1]);
+        let result = tokenizer.encode("the dog".into(), Default::default());
+        assert!(matches!(result, Err(TokenizerError::MissingToken(token)) if token == "dog"));
+
+        // Tokens to keep must be in the vocabulary.
+        assert!(make_tokenizer().prune_vocab(&[100]).is_err());
+    }
+
+    #[test]
+    fn test_heal_prompt() {
+        let merges = ["h t", "ht t", "htt p", "http s", ": /", ":/ /"];
+        let added_tokens = HashMap::from([(262, "<|end|>".to_string())]);
+        let encoder = Bpe::new(&merges, patterns::GPT2, None, added_tokens).unwrap();
+        let tokenizer = Tokenizer::new(encoder, TokenizerOptions::default())
+            .with_added_tokens(vec![AddedToken::special("<|end|>", 262)]);
+
+        let healing = tokenizer.heal_prompt("see https:").unwrap();
+        assert_eq!(tokenizer.decode(&healing.token_ids).unwrap(), "see https");
+        assert_eq!(healing.prefix, ":");
+        let mut candidates: Vec<_> = healing
+            .candidates
+            .iter()
+            .map(|&id| tokenizer.decode(&[id]).unwrap())
+            .collect();
+        candidates.sort();
+        assert_eq!(candidates, &[":", ":/", "://"]);
+
+        // Prompts ending with an added token are not changed.
+        let healing = tokenizer.heal_prompt("https<|end|>").unwrap();
+        assert_eq!(
+            healing.token_ids,
+            tokenizer
+                .encoder()
+                .encode("https")
+                .unwrap()
+                .into_iter()
+                .chain([262])
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(healing.prefix, "");
+        assert!(healing.candidates.is_empty());
+
+        assert_eq!(tokenizer.heal_prompt("").unwrap(), TokenHealing::default());
+    }
+
+    #[test]
+    fn test_tokenizer_shared_between_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Tokenizer>();
+
+        let merges = ["t h", "th e", "Ġ c", "Ġc a", "Ġca t"];
+        let encoder = Bpe::new(&merges, patterns::GPT2, None, Default::default())
+            .unwrap()
+            .with_cache_size(512);
+        let tokenizer = Arc::new(Tokenizer::new(encoder, TokenizerOptions::default()));
+
+        let texts: Vec<String> = (0..32)
+            .map(|i| format!("the cat {} sat on the {} mat", i, i * 7))
+            .collect();
+        let expected: Vec<Vec<TokenId>> = texts
+            .iter()
+            .map(|text| tokenizer.encoder().encode(text).unwrap())
+            .collect();
+
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                let tokenizer = tokenizer.clone();
+                let texts = &texts;
+                let expected = &expected;
+                scope.spawn(move || {
+                    for _ in 0..10 {
+                        for (text, expected) in texts.iter().zip(expected) {
+                            let encoded = tokenizer
+                                .encode(text.as_str().into(), Default::default())
+                                .unwrap();
+                            assert_eq!(encoded.token_ids(), expected);
+                        }
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn test_encode_batch() {
+        let vocab = &[
+            "[PAD]", "[CLS]", "[SEP]", "[UNK]", "This", "is", "a", "test", "sequence",
+        ];
+        let tokenizer = Tokenizer::new(
+            make_wordpiece(vocab),
+            TokenizerOptions {
+                cls_token: Some("[CLS]"),
+                sep_token: Some("[SEP]"),
+            },
+        )
+        .with_padding(Padding::new(PaddingLength::BatchLongest, 0));
+
+        let inputs = ["This is a test", "a", ""];
+        let outputs = tokenizer.encode_batch(&inputs, Default::default()).unwrap();
+        let tokens: Vec<_> = outputs
+            .iter()
+            .map(|output| tokenizer.encoder().get_tokens(output.token_ids()).unwrap())
+            .collect();
+        assert_eq!(
+            tokens,
+            &[
+                vec!["[CLS]", "This", "is", "a", "test", "[SEP]"],
+                vec!["[CLS]", "a", "[SEP]", "[PAD]", "[PAD]", "[PAD]"],
+                vec!["[CLS]", "[SEP]", "[PAD]", "[PAD]", "[PAD]", "[PAD]"],
+            ]
+        );
+
+        // Batch of sequence pairs.
+        let inputs: [EncoderInput; 2] = [("This", "a test").into(), ("a", "sequence").into()];
+        let outputs = tokenizer.encode_batch(&inputs, Default::default()).unwrap();
+        let tokens: Vec<_> = outputs
+            .iter()
+            .map(|output| tokenizer.encoder().get_tokens(output.token_ids()).unwrap())
+            .collect();
+        assert_eq!(
+            tokens,
+            &[
+                vec!["[CLS]", "This", "[SEP]", "a", "test", "[SEP]"],
+                vec!["[CLS]", "a", "[SEP]", "sequence", "[SEP]", "[PAD]"],
+            ]
+        );
+
+        // Chunks are padded to the length of the longest chunk.
+        let options = EncodeOptions {
+            max_chunk_len: Some(5),
+            ..Default::default()
+        };
+        let chunks = tokenizer
+            .encode_chunks("This is a test sequence".into(), options)
+            .unwrap();
+        let tokens: Vec<_> = chunks
+            .iter()
+            .map(|chunk| tokenizer.encoder().get_tokens(chunk.token_ids()).unwrap())
+            .collect();
+        assert_eq!(
+            tokens,
+            &[
+                vec!["[CLS]", "This", "is", "a", "[SEP]"],
+                vec!["[CLS]", "test", "sequence", "[SEP]", "[PAD]"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_encode_pair_with_template() {
+        let vocab = &[
+            "<s>", "</s>", "[UNK]", "This", "is", "a", "test", "sequence",
+        ];
+        let tokenizer = Tokenizer::new(make_wordpiece(vocab), TokenizerOptions::default())
+            .with_template(Template::new("<s> $A </s>", "<s> $A </s> </s> $B:1 </s>:1").unwrap());
+
+        let encoded = tokenizer
+            .encode("This is".into(), Default::default())
+            .unwrap();
+        assert_eq!(
+            tokenizer.encoder().get_tokens(encoded.token_ids()).unwrap(),
+            &["<s>", "This", "is", "</s>"]
+        );
+
+        let encoded = tokenizer
+            .encode_pair("This is", "a test", Default::default())
+            .unwrap();
+        assert_eq!(
+            tokenizer.encoder().get_tokens(encoded.token_ids()).unwrap(),
+            &["<s>", "This", "is", "</s>", "</s>", "a", "test", "</s>"]
+        );
+        let token_type_ids: Vec<_> = encoded.token_type_ids().collect();
+        assert_eq!(token_type_ids, &[0, 0, 0, 0, 0, 1, 1, 1]);
+        assert_eq!(encoded.text_for_token_range(1..3), Some("This is"));
+        assert_eq!(encoded.text_for_token_range(5..7), Some("a test"));
+
+        // Chunks and truncation account for all special tokens.
+        let options = EncodeOptions {
+            max_chunk_len: Some(7),
+            ..Default::default()
+        };
+        let chunks = tokenizer
+            .encode_chunks(("This", "a test sequence").into(), options)
+            .unwrap();
+        let chunk_tokens: Vec<_> = chunks
+            .iter()
+            .map(|chunk| tokenizer.encoder().get_tokens(chunk.token_ids()).unwrap())
+            .collect();
+        assert_eq!(
+            chunk_tokens,
+            &[
+                vec!["<s>", "This", "</s>", "</s>", "a", "test", "</s>"],
+                vec!["<s>", "This", "</s>", "</s>", "sequence", "</s>"],
+            ]
+        );
+
+        let options = EncodeOptions {
+            truncation: Some(Truncation::new(6)),
+            ..Default::default()
+        };
+        let encoded = tokenizer
+            .encode_pair("This", "a test sequence", options)
+            .unwrap();
+        assert_eq!(
+            tokenizer.encoder().get_tokens(encoded.token_ids()).unwrap(),
+            &["<s>", "This", "</s>", "</s>", "a", "</s>"]
+        );
+
+        // Special tokens from the template can be omitted.
+        let options = EncodeOptions {
+            add_special_tokens: false,
+            truncation: Some(Truncation::new(2)),
+            ..Default::default()
+        };
+        let encoded = tokenizer
+            .encode_pair("This is", "a test", options.clone())
+            .unwrap();
+        assert_eq!(
+            tokenizer.encoder().get_tokens(encoded.token_ids()).unwrap(),
+            &["This", "a"]
+        );
+        let token_type_ids: Vec<_> = encoded.token_type_ids().collect();
+        assert_eq!(token_type_ids, &[0, 1]);
+        let options = EncodeOptions {
+            truncation: None,
+            ..options
+        };
+        let encoded = tokenizer.encode("This is".into(), options).unwrap();
+        assert_eq!(
+            tokenizer.encoder().get_tokens(encoded.token_ids()).unwrap(),
+            &["This", "is"]
+        );
+    }
+
+    #[test]
+    fn test_text_for_token_range() {
+        struct Case<'a> {
+            input: EncoderInput<'a>,
+            range: Range<usize>,
+            expected: Option<&'a str>,
+        }
+
+        let vocab = &[
+            "[CLS]", "[SEP]", "[UNK]", "This", "is", "a", "test", "sequence", "Word", "##Piece",
+            "Piece", "of", "pie", ".", "!", "?", "Hey", "Hello",
+        ];
+
+        let cases = [
+            // Part of a single sequence
+            Case {
+                input: "This is a test sequence".into(),
+                range: 4..6,
+                expected: Some("test sequence"),
+            },
+            // Whole of a single sentence
+            Case {
+                input: "This is a test sequence".into(),
+                range: 1..6,
+                expected: Some("This is a test sequence"),
+            },
+            // Part of first item in a pair
+            Case {
+                input: ("This is a test sequence", "Hey Hello").into(),
+                range: 4..6,
+                expected: Some("test sequence"),
+            },
+            // Whole of first item in a pair
+            Case {
+                input: "This is a test sequence".into(),
+                range: 1..6,
+                expected: Some("This is a test sequence"),
+            },
+            // Part of second item in a pair
+            Case {
+                input: ("This is a test sequence", "Hey Hello").into(),
+                range: 8..9,
+                expected: Some("Hello"),
+            },
+            // Whole of second item in a pair
+            Case {
+                input: ("This is a test sequence", "Hey Hello").into(),
+                range: 7..9,
+                expected: Some("Hey Hello"),
+            },
+            // Out of bounds range for a single sequence
+            Case {
+                input: "This is a test sequence".into(),
+                range: 4..8,
+                expected: None,
+            },
+            // Out of bounds range for a pair
+            Case {
+                input: ("This is a test sequence", "Hey Hello").into(),
+                range: 7..12,
+                expected: None,
+            },
+            // Range that spans first and second sequences in a pair
+            Case {
+                input: "This is a test sequence".into(),
+                range: 1..8,
+                expected: None,
+            },
+            // Range that intersects special tokens
+            Case {
+                input: "This is a test sequence".into(),
+                range: 0..7,
+                expected: Some("This is a test sequence"),
+            },
+        ];
+
+        let encoder = make_wordpiece(vocab);
+        let tokenizer = Tokenizer::new(
+            encoder,
+            TokenizerOptions {
+                cls_token: Some("[CLS]"),
+                sep_token: Some("[SEP]"),
+            },
+        );
+
+        for Case {
+            input,
+            range,
+            expected,
+        } in cases
+        {
+            let encoded = tokenizer.encode(input, EncodeOptions::default()).unwrap();
+            let text = encoded.text_for_token_range(range.clone());
+            assert_eq!(
+                text, expected,
+                "mismatch for input {:?} with range {:?}",
+                input, range
+            );
+        }
+    }
+
+    #[test]
+    fn test_encode_chunks_single_sequence() {
+        let vocab = &[
+            "[CLS]", "[SEP]", "[UNK]", "This", "is", "a", "test", "sequence",
+        ];
+
+        struct Case<'a> {
+            text: &'a str,
+            max_chunk_len: Option<usize>,
+            overlap: usize,
+            tokens: Vec<&'a [&'a str]>,
+            use_cls_sep: bool,
+        }
+
+        let cases = [
+            // Unbounded chunk size
+            Case {
+                text: "This is a test sequence",
+                max_chunk_len: None,
+                overlap: 0,
+                tokens: vec![&["[CLS]", "This", "is", "a", "test", "sequence", "[SEP]"]],
+                use_cls_sep: true,
+            },
+            // Two chunks
+            Case {
+                text: "This is a test sequence",
+                max_chunk_len: Some(5),
+                overlap: 0,
+                tokens: vec![
+                    &["[CLS]", "This", "is", "a", "[SEP]"],
+                    &["[CLS]", "test", "sequence", "[SEP]"],
+                ],
+                use_cls_sep: true,
+            },
+            // Three chunks
+            Case {
+                text: "This is a test sequence",
+                max_chunk_len: Some(4),
+                overlap: 0,
+                tokens: vec![
+                    &["[CLS]", "This", "is", "[SEP]"],
+                    &["[CLS]", "a", "test", "[SEP]"],
+                    &["[CLS]", "sequence", "[SEP]"],
+                ],
+                use_cls_sep: true,
+            },
+            // Chunk size that is small enough that there is no room for
+            // any content tokens in each chunk.
+            Case {
+                text: "This is a test sequence",
+                max_chunk_len: Some(0),
+                overlap: 0,
+                tokens: vec![],
+                use_cls_sep: true,
+            },
+            // Overlap between chunks
+            Case {
+                text: "This is a test sequence",
+                max_chunk_len: Some(5),
+                overlap: 2,
+                tokens: vec![
+                    &["[CLS]", "This", "is", "a", "[SEP]"],
+                    &["[CLS]", "is", "a", "test", "[SEP]"],
+                    &["[CLS]", "a", "test", "sequence", "[SEP]"],
+                ],
+                use_cls_sep: true,
+            },
+            // No special tokens
+            Case {
+                text: "This is a test sequence",
+                max_chunk_len: None,
+                overlap: 0,
+                tokens: vec![&["This", "is", "a", "test", "sequence"]],
+                use_cls_sep: false,
+            },
+        ];
+
+        let encoder = make_wordpiece(vocab);
+
+        for Case {
+            text,
+            max_chunk_len,
+            overlap,
+            tokens,
+            use_cls_sep,
+        } in cases
+        {
+            let tokenizer = Tokenizer::new(
+                encoder.clone(),
+                TokenizerOptions {
+                    cls_token: use_cls_sep.then_some("[CLS]"),
+                    sep_token: use_cls_sep.then_some("[SEP]"),
+                },
+            );
+            let options = EncodeOptions {
+                max_chunk_len,
+                overlap,
+                ..Default::default()
+            };
+            let chunks = tokenizer.encode_chunks(text.into(), options).unwrap();
+            let chunk_tokens: Vec<_> = chunks
+                .into_iter()
+                .map(|c| tokenizer.encoder().get_tokens(c.token_ids()).unwrap())
+                .collect();
+            assert_eq!(chunk_tokens, tokens);
+        }
+    }
+
+    #[test]
+    fn test_encode_chunks_sequence_pair() {
+        let vocab = &[
+            "[CLS]",
+            "[SEP]",
+            "[UNK]",
+            "What",
+            "is",
+            "Rust",
+            "?",
+            "a",
+            "programming",
+            "language",
+            ".",
+            "Its",
+            "mascot",
+            "is",
+            "Ferris",
+        ];
+
+        let encoder = make_wordpiece(vocab);
+
+        struct Case<'a> {
+            query: &'a str,
+            context: &'a str,
+            max_chunk_len: Option<usize>,
+            overlap: usize,
+            tokens: Vec<&'a [&'a str]>,
+            use_sep_cls: bool,
+        }
+
+        let cases = [
+            // Unbounded chunk size
             Case {
-                input: "This is a test sequence".into(),
-                range: 1..6,
-                expected: Some("This is a test sequence"),
+                query: "What is Rust?",
+                context: "Rust is a programming language",
+                max_chunk_len: None,
+                overlap: 0,
+                use_sep_cls: true,
+                tokens: vec![&[
+                    "[CLS]",
+                    "What",
+                    "is",
+                    "Rust",
+                    "?",
+                    "[SEP]",
+                    "Rust",
+                    "is",
+                    "a",
+                    "programming",
+                    "language",
+                    "[SEP]",
+                ]],
             },
-            // Part of second item in a pair
+            // Multiple chunks, no overlap
             Case {
-                input: ("This is a test sequence", "Hey Hello").into(),
-                range: 8..9,
-                expected: Some("Hello"),
+                query: "What is Rust?",
+                context: "Rust is a programming language. Its mascot is Ferris.",
+                max_chunk_len: Some(13),
+                overlap: 0,
+                use_sep_cls: true,
+                tokens: vec![
+                    &[
+                        "[CLS]",
+                        "What",
+                        "is",
+                        "Rust",
+                        "?",
+                        "[SEP]",
+                        "Rust",
+                        "is",
+                        "a",
+                        "programming",
+                        "language",
+                        ".",
+                        "[SEP]",
+                    ],
+                    &[
+                        "[CLS]", "What", "is", "Rust", "?", "[SEP]", "Its", "mascot", "is",
+                        "Ferris", ".", "[SEP]",
+                    ],
+                ],
             },
-            // Whole of second item in a pair
+            // Multiple chunks with overlap
             Case {
-                input: ("This is a test sequence", "Hey Hello").into(),
-                range: 7..9,
-                expected: Some("Hey Hello"),
+                query: "What is Rust?",
+                context: "Rust is a programming language. Its mascot is Ferris",
+                max_chunk_len: Some(13),
+                overlap: 2,
+                use_sep_cls: true,
+                tokens: vec![
+                    &[
+                        "[CLS]",
+                        "What",
+                        "is",
+                        "Rust",
+                        "?",
+                        "[SEP]",
+                        "Rust",
+                        "is",
+                        "a",
+                        "programming",
+                        "language",
+                        ".",
+                        "[SEP]",
+                    ],
+                    &[
+                        "[CLS]", "What", "is", "Rust", "?", "[SEP]", "language", ".", "Its",
+                        "mascot", "is", "Ferris", "[SEP]",
+                    ],
+                ],
             },
-            // Out of bounds range for a single sequence
+            // Chunk size too small for any tokens from the second sequence
+            Case {
+                query: "What is Rust?",
+                context: "Rust is a programming language",
+                max_chunk_len: Some(7), // Tokens in query + special tokens (3)
+                overlap: 0,
+                use_sep_cls: true,
+                tokens: vec![],
+            },
+            // No special tokens
+            Case {
+                query: "What is Rust?",
+                context: "Rust is a programming language",
+                max_chunk_len: None,
+                overlap: 0,
+                use_sep_cls: false,
+                tokens: vec![&[
+                    "What",
+                    "is",
+                    "Rust",
+                    "?",
+                    "Rust",
+                    "is",
+                    "a",
+                    "programming",
+                    "language",
+                ]],
+            },
+        ];
+
+        for Case {
+            query,
+            context,
+            max_chunk_len,
+            overlap,
+            tokens,
+            use_sep_cls,
+        } in cases
+        {
+            let tokenizer = Tokenizer::new(
+                encoder.clone(),
+                TokenizerOptions {
+                    cls_token: use_sep_cls.then_some("[CLS]"),
+                    sep_token: use_sep_cls.then_some("[SEP]"),
+                },
+            );
+            let options = EncodeOptions {
+                max_chunk_len,
+                overlap,
+                ..Default::default()
+            };
+            let chunks = tokenizer
+                .encode_chunks((query, context).into(), options)
+                .unwrap();
+            let chunk_tokens: Vec<_> = chunks
+                .iter()
+                .map(|c| tokenizer.encoder().get_tokens(c.token_ids()).unwrap())
+                .collect();
+            assert_eq!(chunk_tokens, tokens);
+
+            // Check that the generated offsets are correct. Since none of the
+            // tokens are subwords, and no normalization is being applied, the
+            // source text for every token index should be the same as the
+            // token's canonical string.
+            for (chunk, chunk_tokens) in chunks.iter().zip(chunk_tokens.into_iter()) {
+                for (i, token) in chunk_tokens.into_iter().enumerate() {
+                    if !token.starts_with("[") {
+                        let text = chunk.text_for_token_range(i..i + 1).map(|t| t.trim());
+                        assert_eq!(text, Some(token).as_deref());
+                    }
+                }
+            }
+        }
+    }
+
+    #[derive(Deserialize)]
+    struct TokenizerJsonCase {
+        text: String,
+        token_ids: Vec<TokenId>,
+    }
+
+    #[derive(Deserialize)]
+    struct TokenizerJsonTest {
+        tokenizer: super::json::TokenizerJson,
+        cases: Vec<TokenizerJsonCase>,
+    }
+
+    fn read_test_json(path: &str) -> Result<TokenizerJsonTest, Box<dyn Error>> {
+        let mut abs_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        abs_path.push("test-data/tokenizer-json/");
+        abs_path.push(path);
+        let content = read_to_string(abs_path)?;
+        let json = serde_json::from_str(&content)?;
+        Ok(json)
+    }
+
+    #[test]
+    fn test_from_json() {
+        let paths = [
+            "wordlevel.json",
+            "wordpiece.json",
+            "wordpiece-lower.json",
+            "wordpiece-options.json",
+            "wordpiece-truncation.json",
+            "wordpiece-padding.json",
+        ];
+
+        for path in paths.iter() {
+            let config = read_test_json(path).unwrap();
+
+            let tokenizer =
+                Tokenizer::from_parsed_json(config.tokenizer, &Default::default()).unwrap();
+            for case in config.cases {
+                let encoded = tokenizer
+                    .encode(case.text.as_str().into(), Default::default())
+                    .unwrap();
+                assert_eq!(encoded.token_ids(), case.token_ids);
+            }
+        }
+    }
+
+    #[test]
+    fn test_from_bytes_and_reader() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("test-data/tokenizer-json/wordpiece.json");
+        let config: serde_json::Value =
+            serde_json::from_str(&read_to_string(path).unwrap()).unwrap();
+        let json = serde_json::to_vec(&config["tokenizer"]).unwrap();
+        let text = config["cases"][0]["text"].as_str().unwrap();
+        let expected = Tokenizer::from_json(std::str::from_utf8(&json).unwrap())
+            .unwrap()
+            .encode(text.into(), Default::default())
+            .unwrap()
+            .token_ids()
+            .to_vec();
+
+        let tokenizers = [
+            Tokenizer::from_bytes(&json).unwrap(),
+            Tokenizer::from_reader(json.as_slice()).unwrap(),
+        ];
+        for tokenizer in tokenizers {
+            let encoded = tokenizer.encode(text.into(), Default::default()).unwrap();
+            assert_eq!(encoded.token_ids(), expected);
+        }
+
+        let result = Tokenizer::from_bytes(b"{\"model\": ");
+        assert!(matches!(result, Err(FromJsonError::JsonError(_))));
+        let result = Tokenizer::from_bytes(b"\xff");
+        assert!(matches!(result, Err(FromJsonError::JsonError(_))));
+    }
+
+    #[test]
+    fn test_decoder_from_json() {
+        struct Case<'a> {
+            decoder: &'a str,
+            expected: &'a str,
+        }
+
+        let cases = [
+            // No decoder. Decoding is handled by the encoder.
             Case {
-                input: "This is a test sequence".into(),
-                range: 4..8,
-                expected: None,
+                decoder: "null",
+                expected: "[CLS] foo ##bar [SEP]",
             },
-            // Out of bounds range for a pair
             Case {
-                input: ("This is a test sequence", "Hey Hello").into(),
-                range: 7..12,
-                expected: None,
+                decoder: r###"{"type": "WordPiece", "prefix": "##", "cleanup": true}"###,
+                expected: "[CLS] foobar [SEP]",
             },
-            // Range that spans first and second sequences in a pair
             Case {
-                input: "This is a test sequence".into(),
-                range: 1..8,
-                expected: None,
+                decoder: r###"{
+                    "type": "Sequence",
+                    "decoders": [
+                        {"type": "Replace", "pattern": {"String": "#"}, "content": ""},
+                        {"type": "Fuse"}
+                    ]
+                }"###,
+                expected: "[CLS]foobar[SEP]",
             },
-            // Range that intersects special tokens
-            Case {
-                input: "This is a test sequence".into(),
-                range: 0..7,
-                expected: Some("This is a test sequence"),
+        ];
+
+        for Case { decoder, expected } in cases {
+            let json = format!(
+                r###"{{
+                    "model": {{
+                        "type": "WordPiece",
+                        "vocab": {{"foo": 1, "##bar": 2, "[CLS]": 3, "[SEP]": 4}}
+                    }},
+                    "decoder": {}
+                }}"###,
+                decoder
+            );
+            let tokenizer = Tokenizer::from_json(&json).unwrap();
+            let encoded = tokenizer
+                .encode("foobar".into(), Default::default())
+                .unwrap();
+            let decoded = tokenizer.decode(encoded.token_ids()).unwrap();
+            assert_eq!(decoded, expected);
+        }
+    }
+
+    #[test]
+    fn test_decode_batch() {
+        let vocab = &["[CLS]", "[SEP]", "[UNK]", "This", "is", "a", "test"];
+        let tokenizer = Tokenizer::new(make_wordpiece(vocab), TokenizerOptions::default());
+
+        let sequences: [&[TokenId]; 3] = [&[3, 4], &[], &[5, 6]];
+        let decoded = tokenizer.decode_batch(&sequences).unwrap();
+        assert_eq!(decoded, &["This is", "", "a test"]);
+
+        let result = tokenizer.decode_batch(&[vec![3], vec![100]]);
+        assert!(matches!(result, Err(TokenizerError::InvalidTokenId(100))));
+    }
+
+    #[test]
+    fn test_from_sentencepiece() {
+        // Piece types from `sentencepiece_model.proto`.
+        const NORMAL: i64 = 1;
+        const UNKNOWN: i64 = 2;
+        const CONTROL: i64 = 3;
+        const BYTE: i64 = 6;
+
+        let mut pieces = vec![
+            ("<unk>", 0., UNKNOWN),
+            ("<s>", 0., CONTROL),
+            ("</s>", 0., CONTROL),
+        ];
+        let byte_tokens: Vec<String> = (0..=255u8).map(|b| format!("<0x{:02X}>", b)).collect();
+        pieces.extend(byte_tokens.iter().map(|t| (t.as_str(), 0., BYTE)));
+        let words = [
+            ("▁", -1.),
+            ("h", -2.),
+            ("i", -2.),
+            ("▁h", -3.),
+            ("▁hi", -4.),
+        ];
+        pieces.extend(words.iter().map(|(t, score)| (*t, *score, NORMAL)));
+        let token_id = |token: &str| pieces.iter().position(|p| p.0 == token).unwrap() as TokenId;
+
+        // Model types from `sentencepiece_model.proto`.
+        const UNIGRAM: i64 = 1;
+        const BPE: i64 = 2;
+
+        for model_type in [UNIGRAM, BPE] {
+            let model = create_model(&pieces, model_type, true /* byte_fallback */, true);
+            let tokenizer = Tokenizer::from_sentencepiece(&model).unwrap();
+
+            let special = tokenizer.special_tokens();
+            assert_eq!(special.unk.as_ref().map(|t| t.id), Some(0));
+            assert_eq!(
+                special.bos.as_ref().map(|t| t.content.as_str()),
+                Some("<s>")
+            );
+            assert_eq!(
+                special.eos.as_ref().map(|t| t.content.as_str()),
+                Some("</s>")
+            );
+            assert!(special.pad.is_none());
+
+            let text = "<s>hi  hé";
+            let encoded = tokenizer.encode(text.into(), Default::default()).unwrap();
+            assert_eq!(
+                encoded.token_ids(),
+                &[
+                    token_id("<s>"),
+                    token_id("▁hi"),
+                    token_id("▁h"),
+                    token_id("<0xC3>"),
+                    token_id("<0xA9>"),
+                ]
+            );
+            assert_eq!(
+                encoded.offset_mapping(),
+                &[(0, 3), (3, 5), (5, 8), (8, 9), (8, 9)]
+            );
+            assert_eq!(tokenizer.decode(encoded.token_ids()).unwrap(), "<s> hi hé");
+            let decoded = tokenizer
+                .decode_with_options(
+                    encoded.token_ids(),
+                    DecodeOptions {
+                        skip_special_tokens: true,
+                        ..Default::default()
+                    },
+                )
+                .unwrap();
+            assert_eq!(decoded, "hi hé");
+        }
+
+        let result = Tokenizer::from_sentencepiece(&[0xff]);
+        assert!(matches!(
+            result,
+            Err(FromSentencePieceError::InvalidModel(_))
+        ));
+    }
+
+    #[test]
+    fn test_decode_with_options() {
+        let vocab = &["[CLS]", "[SEP]", "hello", "world", "<x>", "<y>"];
+        let tokenizer = Tokenizer::new(
+            make_wordpiece(vocab),
+            TokenizerOptions {
+                cls_token: Some("[CLS]"),
+                sep_token: Some("[SEP]"),
             },
+        )
+        .with_added_tokens(vec![
+            AddedToken::special("<x>", 4),
+            AddedToken::new("<y>", 5),
+        ]);
+        let ids = &[0, 2, 4, 3, 5, 1];
+
+        let text = tokenizer
+            .decode_with_options(ids, DecodeOptions::default())
+            .unwrap();
+        assert_eq!(text, "[CLS] hello <x> world <y> [SEP]");
+
+        let text = tokenizer
+            .decode_with_options(
+                ids,
+                DecodeOptions {
+                    skip_special_tokens: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(text, "hello world <y>");
+
+        let vocab = &[
+            "[UNK]", "i", "do", "not", "don", "'", "t", "n't", "it", ",", "ok", ".",
         ];
+        let tokenizer = Tokenizer::new(make_wordpiece(vocab), TokenizerOptions::default());
+        let ids = &[1, 2, 3, 4, 5, 6, 8, 9, 10, 11];
+        assert_eq!(tokenizer.decode(ids).unwrap(), "i do not don ' t it , ok .");
+        let text = tokenizer
+            .decode_with_options(
+                ids,
+                DecodeOptions {
+                    clean_up_tokenization_spaces: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(text, "i don't don't it, ok.");
+    }
 
-        let encoder = make_wordpiece(vocab);
+    #[test]
+    fn test_decode_stream() {
+        let decode_stream = |tokenizer: &Tokenizer, ids: &[TokenId]| {
+            let mut stream = tokenizer.decode_stream();
+            ids.iter()
+                .map(|id| stream.step(*id).unwrap())
+                .collect::<Vec<_>>()
+        };
+        let some = |s: &str| Some(s.to_string());
+
+        // Decoder which joins subwords and adds spaces between words.
+        let vocab = &["[UNK]", "foo", "##bar", "baz"];
+        let tokenizer = Tokenizer::new(make_wordpiece(vocab), TokenizerOptions::default())
+            .with_decoder(decoders::WordPiece::new("##", true));
+        assert_eq!(
+            decode_stream(&tokenizer, &[1, 2, 3]),
+            &[some("foo"), some("bar"), some(" baz")]
+        );
+
+        // Decoder which removes the space at the start of the output.
+        let vocab = &["[UNK]", "▁Hello", "▁world"];
+        let tokenizer = Tokenizer::new(make_wordpiece(vocab), TokenizerOptions::default())
+            .with_decoder(decoders::Metaspace::new('▁', true));
+        assert_eq!(
+            decode_stream(&tokenizer, &[1, 2, 2]),
+            &[some("Hello"), some(" world"), some(" world")]
+        );
+
+        // Characters which span multiple tokens.
+        let encoder = Bpe::new(&[], patterns::GPT2, None, Default::default()).unwrap();
+        let tokenizer = Tokenizer::new(encoder, TokenizerOptions::default());
+        let ids = tokenizer.encoder().encode("a😊b").unwrap();
+        assert_eq!(
+            decode_stream(&tokenizer, &ids),
+            &[some("a"), None, None, None, some("😊"), some("b")]
+        );
+    }
+
+    #[test]
+    fn test_special_tokens() {
+        let token = |content: &str, id| {
+            Some(SpecialToken {
+                content: content.to_string(),
+                id,
+            })
+        };
+
+        // Tokenizer created with `cls_token` and `sep_token` options.
+        let vocab = &["[CLS]", "[SEP]", "[UNK]", "foo"];
         let tokenizer = Tokenizer::new(
-            encoder,
+            make_wordpiece(vocab),
             TokenizerOptions {
                 cls_token: Some("[CLS]"),
                 sep_token: Some("[SEP]"),
             },
         );
+        let special_tokens = tokenizer.special_tokens();
+        assert_eq!(special_tokens.cls, token("[CLS]", 0));
+        assert_eq!(special_tokens.sep, token("[SEP]", 1));
+        assert_eq!(special_tokens.eos, None);
 
-        for Case {
-            input,
-            range,
-            expected,
-        } in cases
-        {
-            let encoded = tokenizer.encode(input, EncodeOptions::default()).unwrap();
-            let text = encoded.text_for_token_range(range.clone());
-            assert_eq!(
-                text, expected,
-                "mismatch for input {:?} with range {:?}",
-                input, range
-            );
-        }
+        // Tokenizer loaded from JSON.
+        let json = r#"{
+            "added_tokens": [
+                {"id": 0, "content": "<s>", "special": true},
+                {"id": 1, "content": "</s>", "special": true},
+                {"id": 2, "content": "<unk>", "special": true},
+                {"id": 3, "content": "<pad>", "special": true},
+                {"id": 4, "content": "foo", "special": false}
+            ],
+            "model": {
+                "type": "WordPiece",
+                "unk_token": "<unk>",
+                "vocab": {"<s>": 0, "</s>": 1, "<unk>": 2, "<pad>": 3, "foo": 4, "<|im_end|>": 5}
+            },
+            "padding": {
+                "strategy": "BatchLongest",
+                "pad_id": 3,
+                "pad_token": "<pad>"
+            },
+            "post_processor": {
+                "type": "RobertaProcessing",
+                "sep": ["</s>", 1],
+                "cls": ["<s>", 0]
+            }
+        }"#;
+        let tokenizer = Tokenizer::from_json(json).unwrap();
+        let special_tokens = tokenizer.special_tokens();
+        assert_eq!(special_tokens.cls, token("<s>", 0));
+        assert_eq!(special_tokens.sep, token("</s>", 1));
+        assert_eq!(special_tokens.unk, token("<unk>", 2));
+        assert_eq!(special_tokens.pad, token("<pad>", 3));
+        assert_eq!(special_tokens.bos, None);
+        assert_eq!(special_tokens.eos, None);
+        assert_eq!(
+            special_tokens.all,
+            [
+                token("<s>", 0),
+                token("</s>", 1),
+                token("<unk>", 2),
+                token("<pad>", 3)
+            ]
+            .map(Option::unwrap)
+        );
+
+        // Roles from `special_tokens_map.json`.
+        let special_tokens_map = r#"{
+            "bos_token": "<s>",
+            "eos_token": {"content": "</s>", "lstrip": false},
+            "additional_special_tokens": ["<|im_end|>"]
+        }"#;
+        let tokenizer = tokenizer
+            .with_special_tokens_map(special_tokens_map)
+            .unwrap();
+        let special_tokens = tokenizer.special_tokens();
+        assert_eq!(special_tokens.bos, token("<s>", 0));
+        assert_eq!(special_tokens.eos, token("</s>", 1));
+        assert_eq!(special_tokens.unk, token("<unk>", 2));
+        assert_eq!(special_tokens.all.last().cloned(), token("<|im_end|>", 5));
+
+        let result = tokenizer.with_special_tokens_map(r#"{"mask_token": "<mask>"}"#);
+        assert!(matches!(result, Err(FromJsonError::MissingToken(tok)) if tok == "<mask>"));
     }
 
     #[test]
-    fn test_encode_chunks_single_sequence() {
+    fn test_added_tokens() {
         let vocab = &[
-            "[CLS]", "[SEP]", "[UNK]", "This", "is", "a", "test", "sequence",
+            "[UNK]",
+            "hello",
+            "world",
+            "<|im_start|>",
+            "<|im_end|>",
+            "<",
+            "|",
+            ">",
+            "im",
+            "_",
+            "end",
+            "new_word",
         ];
+        let tokenizer = Tokenizer::new(make_wordpiece(vocab), TokenizerOptions::default())
+            .with_added_tokens(vec![
+                AddedToken::special("<|im_start|>", 3),
+                AddedToken::special("<|im_end|>", 4),
+                AddedToken::special("<|im", 0),
+                AddedToken::new("new word", 11),
+            ]);
+
+        // Added tokens are matched anywhere in the input. The longest token
+        // is used if several match at the same position.
+        let text = "<|im_start|>hello<|im_end|> new wordworld";
+        let encoded = tokenizer.encode(text.into(), Default::default()).unwrap();
+        assert_eq!(encoded.token_ids(), &[3, 1, 4, 11, 2]);
+        assert_eq!(
+            encoded.offset_mapping(),
+            &[(0, 12), (12, 17), (17, 27), (28, 36), (36, 41)]
+        );
+
+        // Special tokens can be encoded as ordinary text.
+        let encoded = tokenizer
+            .encode(
+                text.into(),
+                EncodeOptions {
+                    split_special_tokens: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            encoded.token_ids(),
+            &[5, 6, 8, 9, 0, 6, 7, 1, 5, 6, 8, 9, 10, 6, 7, 11, 2]
+        );
+    }
+
+    #[test]
+    fn test_added_token_flags() {
+        let json = r#"{
+            "added_tokens": [
+                {"id": 3, "content": "<mask>", "special": true, "lstrip": true},
+                {"id": 4, "content": "[X]", "special": true, "rstrip": true},
+                {"id": 5, "content": "Hello", "special": false, "single_word": true},
+                {"id": 6, "content": "Foo", "special": false, "normalized": false}
+            ],
+            "normalizer": {
+                "type": "BertNormalizer",
+                "lowercase": true,
+                "strip_accents": null
+            },
+            "model": {
+                "type": "WordPiece",
+                "vocab": {
+                    "[UNK]": 0, "a": 1, "b": 2, "<mask>": 3, "[X]": 4, "hello": 5,
+                    "Foo": 6, "foo": 7, "hellos": 8, "[CLS]": 9, "[SEP]": 10
+                }
+            }
+        }"#;
+        let tokenizer = Tokenizer::from_json(json).unwrap();
 
         struct Case<'a> {
             text: &'a str,
-            max_chunk_len: Option<usize>,
-            overlap: usize,
-            tokens: Vec<&'a [&'a str]>,
-            use_cls_sep: bool,
+            token_ids: &'a [TokenId],
+            offsets: &'a [(usize, usize)],
         }
 
         let cases = [
-            // Unbounded chunk size
+            // `lstrip` consumes whitespace before the token.
             Case {
-                text: "This is a test sequence",
-                max_chunk_len: None,
-                overlap: 0,
-                tokens: vec![&["[CLS]", "This", "is", "a", "test", "sequence", "[SEP]"]],
-                use_cls_sep: true,
+                text: "a  <mask> b",
+                token_ids: &[1, 3, 2],
+                offsets: &[(0, 1), (1, 9), (10, 11)],
             },
-            // Two chunks
+            // `rstrip` consumes whitespace after the token.
             Case {
-                text: "This is a test sequence",
-                max_chunk_len: Some(5),
-                overlap: 0,
-                tokens: vec![
-                    &["[CLS]", "This", "is", "a", "[SEP]"],
-                    &["[CLS]", "test", "sequence", "[SEP]"],
-                ],
-                use_cls_sep: true,
+                text: "[X]  a",
+                token_ids: &[4, 1],
+                offsets: &[(0, 5), (5, 6)],
             },
-            // Three chunks
+            // `single_word` tokens don't match inside words, and `normalized`
+            // tokens match the normalized text.
             Case {
-                text: "This is a test sequence",
-                max_chunk_len: Some(4),
-                overlap: 0,
-                tokens: vec![
-                    &["[CLS]", "This", "is", "[SEP]"],
-                    &["[CLS]", "a", "test", "[SEP]"],
-                    &["[CLS]", "sequence", "[SEP]"],
-                ],
-                use_cls_sep: true,
+                text: "HELLO hellos",
+                token_ids: &[5, 8],
+                offsets: &[(0, 5), (6, 12)],
             },
-            // Chunk size that is small enough that there is no room for
-            // any content tokens in each chunk.
+            // Tokens which are not `normalized` match the original text.
             Case {
-                text: "This is a test sequence",
-                max_chunk_len: Some(0),
-                overlap: 0,
-                tokens: vec![],
-                use_cls_sep: true,
+                text: "Foo FOO",
+                token_ids: &[6, 7],
+                offsets: &[(0, 3), (4, 7)],
             },
-            // Overlap between chunks
-            Case {
-                text: "This is a test sequence",
-                max_chunk_len: Some(5),
-                overlap: 2,
-                tokens: vec![
-                    &["[CLS]", "This", "is", "a", "[SEP]"],
-                    &["[CLS]", "is", "a", "test", "[SEP]"],
-                    &["[CLS]", "a", "test", "sequence", "[SEP]"],
-                ],
-                use_cls_sep: true,
+        ];
+
+        for Case {
+            text,
+            token_ids,
+            offsets,
+        } in cases
+        {
+            let encoded = tokenizer.encode(text.into(), Default::default()).unwrap();
+            let n_tokens = encoded.token_ids().len();
+            assert_eq!(encoded.token_ids()[1..n_tokens - 1], *token_ids);
+            assert_eq!(encoded.offset_mapping()[1..n_tokens - 1], *offsets);
+        }
+    }
+
+    #[test]
+    fn test_normalizer_from_json() {
+        let json = r#"{
+            "normalizer": {
+                "type": "Sequence",
+                "normalizers": [
+                    {"type": "Lowercase"},
+                    {"type": "StripAccents"}
+                ]
             },
-            // No special tokens
-            Case {
-                text: "This is a test sequence",
-                max_chunk_len: None,
-                overlap: 0,
-                tokens: vec![&["This", "is", "a", "test", "sequence"]],
-                use_cls_sep: false,
+            "model": {
+                "type": "WordPiece",
+                "vocab": {"[UNK]": 0, "[CLS]": 1, "[SEP]": 2, "cafe": 3, "café": 4}
+            }
+        }"#;
+        let tokenizer = Tokenizer::from_json(json).unwrap();
+
+        // Combining marks are removed, but precomposed characters are left
+        // unchanged, since `StripAccents` expects to follow an NFD step.
+        let text = "CAFE\u{301} Café";
+        let encoded = tokenizer.encode(text.into(), Default::default()).unwrap();
+        assert_eq!(encoded.token_ids(), &[1, 3, 4, 2]);
+        assert_eq!(encoded.offset_mapping()[1..3], [(0, 4), (6, 10)]);
+
+        let json = r#"{
+            "normalizer": {
+                "type": "Replace",
+                "pattern": {"Regex": "c[aeiou]+fe"},
+                "content": "cafe"
+            },
+            "model": {
+                "type": "WordPiece",
+                "vocab": {"[UNK]": 0, "[CLS]": 1, "[SEP]": 2, "cafe": 3}
+            }
+        }"#;
+        let tokenizer = Tokenizer::from_json(json).unwrap();
+        let encoded = tokenizer
+            .encode("caafe cuife".into(), Default::default())
+            .unwrap();
+        assert_eq!(encoded.token_ids(), &[1, 3, 3, 2]);
+        assert_eq!(encoded.offset_mapping()[1..3], [(0, 5), (6, 11)]);
+    }
+
+    #[test]
+    fn test_pre_tokenizer_from_json() {
+        let json = r#"{
+            "pre_tokenizer": {
+                "type": "Metaspace",
+                "replacement": "▁",
+                "prepend_scheme": "always",
+                "split": true
+            },
+            "model": {
+                "type": "Unigram",
+                "unk_id": 0,
+                "vocab": [
+                    ["<unk>", 0.0],
+                    ["▁", -2.0],
+                    ["▁hello", -1.0],
+                    ["▁world", -1.0],
+                    ["wor", -1.5],
+                    ["ld", -1.5]
+                ]
+            }
+        }"#;
+        let tokenizer = Tokenizer::from_json(json).unwrap();
+
+        let encoded = tokenizer
+            .encode("hello world".into(), Default::default())
+            .unwrap();
+        assert_eq!(encoded.token_ids(), &[2, 3]);
+        assert_eq!(encoded.offset_mapping(), [(0, 5), (5, 11)]);
+
+        // Without splitting, pieces can span words.
+        let json = json.replace(r#""split": true"#, r#""split": false"#);
+        let tokenizer = Tokenizer::from_json(&json).unwrap();
+        let encoded = tokenizer
+            .encode("hello  world".into(), Default::default())
+            .unwrap();
+        assert_eq!(encoded.token_ids(), &[2, 1, 3]);
+        assert_eq!(
+            tokenizer.encoder().get_tokens(encoded.token_ids()).unwrap(),
+            ["▁hello", "▁", "▁world"]
+        );
+
+        let json = r#"{
+            "pre_tokenizer": {
+                "type": "UnicodeScripts"
+            },
+            "model": {
+                "type": "WordLevel",
+                "vocab": {"[UNK]": 0, "hello": 1, "世界": 2},
+                "unk_token": "[UNK]"
+            }
+        }"#;
+        let tokenizer = Tokenizer::from_json(json).unwrap();
+        let encoded = tokenizer
+            .encode("hello世界".into(), Default::default())
+            .unwrap();
+        assert_eq!(encoded.token_ids(), &[1, 2]);
+        assert_eq!(encoded.offset_mapping(), [(0, 5), (5, 7)]);
+
+        for pre_tokenizer in ["BertPreTokenizer", "Whitespace"] {
+            let json = format!(
+                r#"{{
+                    "pre_tokenizer": {{"type": "{}"}},
+                    "model": {{
+                        "type": "WordPiece",
+                        "vocab": {{
+                            "[UNK]": 0, "hello": 1, ",": 2, "world": 3, "!": 4,
+                            "[CLS]": 5, "[SEP]": 6
+                        }}
+                    }}
+                }}"#,
+                pre_tokenizer
+            );
+            let tokenizer = Tokenizer::from_json(&json).unwrap();
+            let encoded = tokenizer
+                .encode("hello,  world!".into(), Default::default())
+                .unwrap();
+            assert_eq!(encoded.token_ids(), &[5, 1, 2, 3, 4, 6]);
+            assert_eq!(
+                encoded.offset_mapping()[1..5],
+                [(0, 5), (5, 6), (8, 13), (13, 14)]
+            );
+        }
+    }
+
+    #[test]
+    fn test_split_pre_tokenizer_from_json() {
+        // Byte-level vocabulary with merges that span the boundaries between
+        // the pieces produced by the GPT-2 pattern, but not the Llama 3 one.
+        let merges = ["' h", "'h i", "1 2", "12 3", "123 4"];
+        let mut vocab: HashMap<String, TokenId> = char_to_byte()
+            .keys()
+            .enumerate()
+            .map(|(i, ch)| (ch.to_string(), i as TokenId))
+            .collect();
+        for merge in merges {
+            let id = vocab.len() as TokenId;
+            vocab.insert(merge.replace(' ', ""), id);
+        }
+
+        let json = serde_json::json!({
+            "pre_tokenizer": {
+                "type": "Sequence",
+                "pretokenizers": [
+                    {
+                        "type": "Split",
+                        "pattern": {
+                            "Regex": r"(?i:'s|'t|'re|'ve|'m|'ll|'d)|[^\r\n\p{L}\p{N}]?\p{L}+|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n]*|\s*[\r\n]+|\s+(?!\S)|\s+"
+                        },
+                        "behavior": "Isolated",
+                        "invert": false
+                    },
+                    {
+                        "type": "ByteLevel",
+                        "add_prefix_space": false,
+                        "trim_offsets": true,
+                        "use_regex": false
+                    }
+                ]
+            },
+            "model": {
+                "type": "BPE",
+                "vocab": vocab,
+                "merges": merges,
+            }
+        });
+        let tokenizer = Tokenizer::from_json(&json.to_string()).unwrap();
+
+        let encoded = tokenizer
+            .encode("'hi  1234".into(), Default::default())
+            .unwrap();
+        assert_eq!(
+            tokenizer.encoder().get_tokens(encoded.token_ids()).unwrap(),
+            ["'hi", "Ġ", "Ġ", "123", "4"]
+        );
+        assert_eq!(
+            encoded.offset_mapping(),
+            [(0, 3), (3, 4), (4, 5), (5, 8), (8, 9)]
+        );
+    }
+
+    #[test]
+    fn test_from_json_with_registry() {
+        let json = r#"{
+            "normalizer": {
+                "type": "Sequence",
+                "normalizers": [
+                    {"type": "Lowercase"},
+                    {"type": "ExpandAmpersand", "content": "and"}
+                ]
             },
-        ];
+            "pre_tokenizer": {"type": "SplitOn", "delimiter": "/"},
+            "model": {
+                "type": "WordLevel",
+                "vocab": {"[UNK]": 0, "cats": 1, "and": 2, "dogs": 3, "/": 4},
+                "unk_token": "[UNK]"
+            }
+        }"#;
 
-        let encoder = make_wordpiece(vocab);
+        let registry = ComponentRegistry::new()
+            .with_normalizer("ExpandAmpersand", |config| {
+                let content = config["content"].as_str().ok_or("missing content")?;
+                Ok(Normalizer::replace("&", &format!(" {} ", content)))
+            })
+            .with_pre_tokenizer("SplitOn", |config| {
+                let delimiter = config["delimiter"].as_str().ok_or("missing delimiter")?;
+                Ok(Box::new(Split::new(
+                    delimiter,
+                    SplitDelimiterBehavior::Isolated,
+                )))
+            });
+        let tokenizer = Tokenizer::from_json_with_registry(json, &registry).unwrap();
 
-        for Case {
-            text,
-            max_chunk_len,
-            overlap,
-            tokens,
-            use_cls_sep,
-        } in cases
-        {
-            let tokenizer = Tokenizer::new(
-                encoder.clone(),
-                TokenizerOptions {
-                    cls_token: use_cls_sep.then_some("[CLS]"),
-                    sep_token: use_cls_sep.then_some("[SEP]"),
-                },
-            );
-            let options = EncodeOptions {
-                max_chunk_len,
-                overlap,
-            };
-            let chunks = tokenizer.encode_chunks(text.into(), options).unwrap();
-            let chunk_tokens: Vec<_> = chunks
-                .into_iter()
-                .map(|c| tokenizer.encoder().get_tokens(c.token_ids()).unwrap())
-                .collect();
-            assert_eq!(chunk_tokens, tokens);
+        let encoded = tokenizer
+            .encode("Cats&Dogs/cats".into(), Default::default())
+            .unwrap();
+        assert_eq!(encoded.token_ids(), &[1, 2, 3, 4, 1]);
+        assert_eq!(
+            encoded.offset_mapping(),
+            [(0, 4), (4, 5), (5, 9), (9, 10), (10, 14)]
+        );
+
+        // Unregistered custom components are reported as unsupported.
+        let err = Tokenizer::from_json(json).err().unwrap();
+        assert!(matches!(err, FromJsonError::UnsupportedComponents(c) if c.len() == 2));
+
+        // Errors from factories are propagated.
+        let registry = ComponentRegistry::new()
+            .with_normalizer("ExpandAmpersand", |_config| Err("invalid config".into()));
+        let err = Tokenizer::from_json_with_registry(json, &registry)
+            .err()
+            .unwrap();
+        assert!(matches!(err, FromJsonError::CustomComponentError(_)));
+        assert_eq!(err.to_string(), "custom component error: invalid config");
+
+        // Registered types take precedence over built-in types.
+        struct Reverse;
+        impl PreTokenizer for Reverse {
+            fn pre_tokenize_chain(
+                &self,
+                mut pieces: Vec<PreToken>,
+            ) -> Result<Vec<PreToken>, TokenizerError> {
+                pieces.reverse();
+                Ok(pieces)
+            }
         }
+        let json = r#"{
+            "pre_tokenizer": {
+                "type": "Sequence",
+                "pretokenizers": [
+                    {"type": "Split", "pattern": {"String": " "}, "behavior": "Removed"},
+                    {"type": "Reverse"}
+                ]
+            },
+            "model": {
+                "type": "WordLevel",
+                "vocab": {"[UNK]": 0, "a": 1, "b": 2}
+            }
+        }"#;
+        let registry =
+            ComponentRegistry::new().with_pre_tokenizer("Reverse", |_| Ok(Box::new(Reverse)));
+        let tokenizer = Tokenizer::from_json_with_registry(json, &registry).unwrap();
+        let encoded = tokenizer.encode("a b".into(), Default::default()).unwrap();
+        assert_eq!(encoded.token_ids(), &[2, 1]);
     }
 
     #[test]
-    fn test_encode_chunks_sequence_pair() {
-        let vocab = &[
-            "[CLS]",
-            "[SEP]",
-            "[UNK]",
-            "What",
-            "is",
-            "Rust",
-            "?",
-            "a",
-            "programming",
-            "language",
-            ".",
-            "Its",
-            "mascot",
-            "is",
-            "Ferris",
-        ];
+    fn test_unsupported_components_from_json() {
+        let json = r#"{
+            "normalizer": {
+                "type": "Sequence",
+                "normalizers": [{"type": "Precompiled"}, {"type": "NFKC"}]
+            },
+            "pre_tokenizer": {
+                "type": "Sequence",
+                "pretokenizers": [
+                    {"type": "Digits", "individual_digits": true},
+                    {"type": "Metaspace", "replacement": "▁"},
+                    {"type": "Punctuation"}
+                ]
+            },
+            "post_processor": {"type": "CustomProcessing"},
+            "decoder": {
+                "type": "Sequence",
+                "decoders": [{"type": "Fuse"}, {"type": "CTC"}]
+            },
+            "model": {
+                "type": "WordPiece",
+                "vocab": {"[UNK]": 0}
+            }
+        }"#;
 
-        let encoder = make_wordpiece(vocab);
+        let err = Tokenizer::from_json(json).err().unwrap();
+        let FromJsonError::UnsupportedComponents(components) = &err else {
+            panic!("unexpected error {:?}", err);
+        };
+        let unsupported = |kind, type_name: &str| UnsupportedComponent {
+            kind,
+            type_name: type_name.to_string(),
+        };
+        assert_eq!(
+            components,
+            &[
+                unsupported(ComponentKind::Normalizer, "Precompiled"),
+                unsupported(ComponentKind::Decoder, "CTC"),
+                unsupported(ComponentKind::PreTokenizer, "Digits"),
+                unsupported(ComponentKind::PreTokenizer, "Punctuation"),
+                unsupported(ComponentKind::PostProcessor, "CustomProcessing"),
+            ]
+        );
+        assert_eq!(
+            err.to_string(),
+            "unsupported components: normalizer \"Precompiled\", decoder \"CTC\", \
+            pre-tokenizer \"Digits\", pre-tokenizer \"Punctuation\", \
+            post-processor \"CustomProcessing\""
+        );
+
+        // Errors in the configuration of supported components are reported
+        // as JSON errors.
+        let json = r#"{
+            "normalizer": {"type": "Replace", "pattern": {"String": "a"}},
+            "model": {"type": "WordPiece", "vocab": {"[UNK]": 0}}
+        }"#;
+        let err = Tokenizer::from_json(json).err().unwrap();
+        assert!(matches!(err, FromJsonError::JsonError(_)));
+    }
 
+    #[test]
+    fn test_post_processor_from_json() {
         struct Case<'a> {
-            query: &'a str,
-            context: &'a str,
-            max_chunk_len: Option<usize>,
-            overlap: usize,
-            tokens: Vec<&'a [&'a str]>,
-            use_sep_cls: bool,
+            post_processor: &'a str,
+            expected: &'a [&'a str],
+            expected_type_ids: &'a [usize],
         }
 
         let cases = [
-            // Unbounded chunk size
+            // No post-processor. The model's default special tokens are used.
             Case {
-                query: "What is Rust?",
-                context: "Rust is a programming language",
-                max_chunk_len: None,
-                overlap: 0,
-                use_sep_cls: true,
-                tokens: vec![&[
-                    "[CLS]",
-                    "What",
-                    "is",
-                    "Rust",
-                    "?",
-                    "[SEP]",
-                    "Rust",
-                    "is",
-                    "a",
-                    "programming",
-                    "language",
-                    "[SEP]",
-                ]],
+                post_processor: "null",
+                expected: &["[CLS]", "foo", "[SEP]", "bar", "[SEP]"],
+                expected_type_ids: &[0, 0, 0, 1, 1],
             },
-            // Multiple chunks, no overlap
             Case {
-                query: "What is Rust?",
-                context: "Rust is a programming language. Its mascot is Ferris.",
-                max_chunk_len: Some(13),
-                overlap: 0,
-                use_sep_cls: true,
-                tokens: vec![
-                    &[
-                        "[CLS]",
-                        "What",
-                        "is",
-                        "Rust",
-                        "?",
-                        "[SEP]",
-                        "Rust",
-                        "is",
-                        "a",
-                        "programming",
-                        "language",
-                        ".",
-                        "[SEP]",
-                    ],
-                    &[
-                        "[CLS]", "What", "is", "Rust", "?", "[SEP]", "Its", "mascot", "is",
-                        "Ferris", ".", "[SEP]",
-                    ],
-                ],
+                post_processor: r#"{"type": "ByteLevel", "trim_offsets": true}"#,
+                expected: &["[CLS]", "foo", "[SEP]", "bar", "[SEP]"],
+                expected_type_ids: &[0, 0, 0, 1, 1],
             },
-            // Multiple chunks with overlap
             Case {
-                query: "What is Rust?",
-                context: "Rust is a programming language. Its mascot is Ferris",
-                max_chunk_len: Some(13),
-                overlap: 2,
-                use_sep_cls: true,
-                tokens: vec![
-                    &[
-                        "[CLS]",
-                        "What",
-                        "is",
-                        "Rust",
-                        "?",
-                        "[SEP]",
-                        "Rust",
-                        "is",
-                        "a",
-                        "programming",
-                        "language",
-                        ".",
-                        "[SEP]",
-                    ],
-                    &[
-                        "[CLS]", "What", "is", "Rust", "?", "[SEP]", "language", ".", "Its",
-                        "mascot", "is", "Ferris", "[SEP]",
-                    ],
-                ],
+                post_processor: r#"{
+                    "type": "BertProcessing",
+                    "sep": ["</s>", 2],
+                    "cls": ["<s>", 1]
+                }"#,
+                expected: &["<s>", "foo", "</s>", "bar", "</s>"],
+                expected_type_ids: &[0, 0, 0, 1, 1],
             },
-            // Chunk size too small for any tokens from the second sequence
             Case {
-                query: "What is Rust?",
-                context: "Rust is a programming language",
-                max_chunk_len: Some(7), // Tokens in query + special tokens (3)
-                overlap: 0,
-                use_sep_cls: true,
-                tokens: vec![],
+                post_processor: r#"{
+                    "type": "RobertaProcessing",
+                    "sep": ["</s>", 2],
+                    "cls": ["<s>", 1],
+                    "trim_offsets": true,
+                    "add_prefix_space": false
+                }"#,
+                expected: &["<s>", "foo", "</s>", "</s>", "bar", "</s>"],
+                expected_type_ids: &[0, 0, 0, 0, 0, 0],
             },
-            // No special tokens
             Case {
-                query: "What is Rust?",
-                context: "Rust is a programming language",
-                max_chunk_len: None,
-                overlap: 0,
-                use_sep_cls: false,
-                tokens: vec![&[
-                    "What",
-                    "is",
-                    "Rust",
-                    "?",
-                    "Rust",
-                    "is",
-                    "a",
-                    "programming",
-                    "language",
-                ]],
+                post_processor: r#"{
+                    "type": "Sequence",
+                    "processors": [
+                        {"type": "ByteLevel", "trim_offsets": false},
+                        {
+                            "type": "TemplateProcessing",
+                            "single": [
+                                {"SpecialToken": {"id": "<s>", "type_id": 0}},
+                                {"Sequence": {"id": "A", "type_id": 0}}
+                            ],
+                            "pair": [
+                                {"SpecialToken": {"id": "<s>", "type_id": 0}},
+                                {"Sequence": {"id": "A", "type_id": 0}},
+                                {"SpecialToken": {"id": "<s>", "type_id": 1}},
+                                {"Sequence": {"id": "B", "type_id": 1}}
+                            ],
+                            "special_tokens": {}
+                        }
+                    ]
+                }"#,
+                expected: &["<s>", "foo", "<s>", "bar"],
+                expected_type_ids: &[0, 0, 1, 1],
             },
         ];
 
         for Case {
-            query,
-            context,
-            max_chunk_len,
-            overlap,
-            tokens,
-            use_sep_cls,
+            post_processor,
+            expected,
+            expected_type_ids,
         } in cases
         {
-            let tokenizer = Tokenizer::new(
-                encoder.clone(),
-                TokenizerOptions {
-                    cls_token: use_sep_cls.then_some("[CLS]"),
-                    sep_token: use_sep_cls.then_some("[SEP]"),
-                },
+            let json = format!(
+                r#"{{
+                    "model": {{
+                        "type": "WordPiece",
+                        "vocab": {{"<s>": 1, "</s>": 2, "[CLS]": 3, "[SEP]": 4, "foo": 5, "bar": 6}}
+                    }},
+                    "post_processor": {}
+                }}"#,
+                post_processor
             );
-            let options = EncodeOptions {
-                max_chunk_len,
-                overlap,
-                ..Default::default()
-            };
-            let chunks = tokenizer
-                .encode_chunks((query, context).into(), options)
+            let tokenizer = Tokenizer::from_json(&json).unwrap();
+            let encoded = tokenizer
+                .encode_pair("foo", "bar", Default::default())
                 .unwrap();
-            let chunk_tokens: Vec<_> = chunks
-                .iter()
-                .map(|c| tokenizer.encoder().get_tokens(c.token_ids()).unwrap())
-                .collect();
-            assert_eq!(chunk_tokens, tokens);
-
-            // Check that the generated offsets are correct. Since none of the
-            // tokens are subwords, and no normalization is being applied, the
-            // source text for every token index should be the same as the
-            // token's canonical string.
-            for (chunk, chunk_tokens) in chunks.iter().zip(chunk_tokens.into_iter()) {
-                for (i, token) in chunk_tokens.into_iter().enumerate() {
-                    if !token.starts_with("[") {
-                        let text = chunk.text_for_token_range(i..i + 1).map(|t| t.trim());
-                        assert_eq!(text, Some(token).as_deref());
-                    }
-                }
-            }
-        }
-    }
-
-    #[derive(Deserialize)]
-    struct TokenizerJsonCase {
-        text: String,
-        token_ids: Vec<TokenId>,
-    }
-
-    #[derive(Deserialize)]
-    struct TokenizerJsonTest {
-        tokenizer: super::json::TokenizerJson,
-        cases: Vec<TokenizerJsonCase>,
-    }
-
-    fn read_test_json(path: &str) -> Result<TokenizerJsonTest, Box<dyn Error>> {
-        let mut abs_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-        abs_path.push("test-data/tokenizer-json/");
-        abs_path.push(path);
-        let content = read_to_string(abs_path)?;
-        let json = serde_json::from_str(&content)?;
-        Ok(json)
-    }
-
-    #[test]
-    fn test_from_json() {
-        let paths = ["wordpiece.json", "wordpiece-lower.json"];
-
-        for path in paths.iter() {
-            let config = read_test_json(path).unwrap();
-
-            let tokenizer = Tokenizer::from_parsed_json(config.tokenizer).unwrap();
-            for case in config.cases {
-                let encoded = tokenizer
-                    .encode(case.text.as_str().into(), Default::default())
-                    .unwrap();
-                assert_eq!(encoded.token_ids(), case.token_ids);
-            }
+            assert_eq!(
+                tokenizer.encoder().get_tokens(encoded.token_ids()).unwrap(),
+                expected
+            );
+            assert_eq!(
+                encoded.token_type_ids().collect::<Vec<_>>(),
+                expected_type_ids
+            );
         }
     }
 }
@@ -0,0 +1,169 @@
+use std::ops::Range;
+
+use aho_corasick::AhoCorasick;
+
+use super::AddedToken;
+use crate::normalizer::{NormalizedText, Normalizer};
+
+/// Automaton which finds occurrences of a set of strings.
+#[derive(Clone, Default)]
+struct Patterns {
+    /// Automaton which matches the strings. `None` if there are none.
+    automaton: Option<AhoCorasick>,
+
+    /// Index of the added token which each pattern corresponds to.
+    token_indices: Vec<usize>,
+}
+
+impl Patterns {
+    /// Create an automaton which matches `(token_index, content)` patterns.
+    /// Empty patterns are ignored.
+    fn new(patterns: impl Iterator<Item = (usize, String)>) -> Patterns {
+        let (token_indices, contents): (Vec<usize>, Vec<String>) =
+            patterns.filter(|(_, content)| !content.is_empty()).unzip();
+        let automaton = (!contents.is_empty())
+            .then(|| AhoCorasick::new(&contents).expect("should build added token automaton"));
+        Patterns {
+            automaton,
+            token_indices,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.automaton.is_none()
+    }
+
+    /// Find all occurrences of the patterns in `text`, including overlapping
+    /// ones. Returns the byte range and token index of each match.
+    fn find<'a>(&'a self, text: &'a str) -> impl Iterator<Item = (Range<usize>, usize)> + 'a {
+        self.automaton
+            .iter()
+            .flat_map(move |automaton| automaton.find_overlapping_iter(text))
+            .map(|m| (m.range(), self.token_indices[m.pattern().as_usize()]))
+    }
+}
+
+/// Finds occurrences of [AddedToken]s in text.
+///
+/// The contents of all tokens are matched in a single pass using an
+/// Aho-Corasick automaton, which is built when the tokens are set. This keeps
+/// encoding fast for tokenizers with many added tokens.
+#[derive(Clone, Default)]
+pub(crate) struct AddedTokenMatcher {
+    /// Tokens which are matched against the input text.
+    raw: Patterns,
+
+    /// Tokens which are matched against the normalized input text.
+    normalized: Patterns,
+}
+
+impl AddedTokenMatcher {
+    /// Create a matcher for `tokens`.
+    ///
+    /// `normalizer` is the normalizer used to match tokens which have the
+    /// [normalized](AddedToken::normalized) flag set. It must be the same
+    /// normalizer that is later passed to [AddedTokenMatcher::find].
+    pub fn new(tokens: &[AddedToken], normalizer: Option<&Normalizer>) -> AddedTokenMatcher {
+        let is_normalized = |token: &AddedToken| normalizer.is_some() && token.normalized;
+        let raw = Patterns::new(
+            tokens
+                .iter()
+                .enumerate()
+                .filter(|(_, token)| !is_normalized(token))
+                .map(|(i, token)| (i, token.content.clone())),
+        );
+        let normalized = match normalizer {
+            Some(normalizer) => Patterns::new(
+                tokens
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, token)| is_normalized(token))
+                    .map(|(i, token)| (i, normalizer.normalize(&token.content).0)),
+            ),
+            None => Patterns::default(),
+        };
+        AddedTokenMatcher { raw, normalized }
+    }
+
+    /// Find all occurrences of added tokens in `text`, including overlapping
+    /// ones.
+    ///
+    /// Returns the byte range of each match in `text` and the index of the
+    /// token in the list passed to [AddedTokenMatcher::new].
+    pub fn find(&self, text: &str, normalizer: Option<&Normalizer>) -> Vec<(Range<usize>, usize)> {
+        let mut matches: Vec<_> = self.raw.find(text).collect();
+        if let Some(normalizer) = normalizer.filter(|_| !self.normalized.is_empty()) {
+            let normalized = NormalizedText::new(text).normalize(normalizer);
+            matches.extend(
+                self.normalized
+                    .find(normalized.normalized())
+                    .map(|(range, index)| (normalized.original_range(range), index)),
+            );
+        }
+        matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AddedTokenMatcher;
+    use crate::normalizer::{Normalizer, NormalizerOptions};
+    use crate::tokenizers::{AddedToken, EncodeOptions, Tokenizer, WordLevel, WordLevelOptions};
+
+    #[test]
+    fn test_find() {
+        let normalizer = Normalizer::new(NormalizerOptions {
+            lowercase: true,
+            ..Default::default()
+        });
+        let tokens = [
+            AddedToken::special("<|end|>", 0),
+            AddedToken::new("Foo", 1),
+            AddedToken::new("foobar", 2),
+            AddedToken::new("", 3),
+        ];
+
+        // Without a normalizer, all tokens are matched against the input.
+        let matcher = AddedTokenMatcher::new(&tokens, None);
+        let mut matches = matcher.find("FOOBAR Foo<|end|>", None);
+        matches.sort_by_key(|(range, _)| range.start);
+        assert_eq!(matches, &[(7..10, 1), (10..17, 0)]);
+
+        // With a normalizer, tokens with the `normalized` flag are matched
+        // against the normalized input. Matches can overlap.
+        let matcher = AddedTokenMatcher::new(&tokens, Some(&normalizer));
+        let mut matches = matcher.find("FOOBAR Foo<|end|>", Some(&normalizer));
+        matches.sort_by_key(|(range, index)| (range.start, *index));
+        assert_eq!(matches, &[(0..3, 1), (0..6, 2), (7..10, 1), (10..17, 0)]);
+
+        let matcher = AddedTokenMatcher::new(&[], None);
+        assert!(matcher.find("foo", None).is_empty());
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_find_added_tokens() {
+        use rten_bench::run_bench;
+
+        // Tokenizer with many special tokens, similar to Qwen models.
+        let added_tokens: Vec<_> = (0..500)
+            .map(|i| AddedToken::special(&format!("<|extra_{}|>", i), i + 1))
+            .collect();
+        let encoder = WordLevel::from_vocab(
+            [("[UNK]".to_string(), 0)].into(),
+            WordLevelOptions {
+                unk_token: Some("[UNK]".to_string()),
+                ..Default::default()
+            },
+        );
+        let tokenizer = Tokenizer::new(encoder, Default::default()).with_added_tokens(added_tokens);
+        let text = "The quick brown fox <|extra_42|> jumps over the lazy dog. ".repeat(1000);
+
+        run_bench(10, Some("find added tokens"), || {
+            let encoded = tokenizer
+                .encode(text.as_str().into(), EncodeOptions::default())
+                .unwrap();
+            assert!(!encoded.token_ids().is_empty());
+        });
+    }
+}
@@ -1,10 +1,13 @@
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
 use std::error::Error;
 use std::fmt;
 use std::fmt::{Debug, Display};
+use std::hash::{BuildHasher, RandomState};
+use std::ops::Range;
+use std::sync::Mutex;
 
-use fancy_regex::Regex;
-
+use crate::split::SplitRegex;
 use crate::tokenizers::{Encoder, TokenId, TokenizerError};
 
 /// Errors that can occur when building a [Bpe] tokenizer or encoding or
@@ -87,7 +90,7 @@ fn byte_to_rank() -> [Rank; 256] {
 ///
 /// Based on the `bytes_to_unicode` function in the original GPT-2 encoder -
 /// https://github.com/openai/gpt-2/blob/master/src/encoder.py.
-fn char_to_byte() -> HashMap<char, u8> {
+pub(crate) fn char_to_byte() -> HashMap<char, u8> {
     let mut n = 0;
     (0..=255u8)
         .map(|b| {
@@ -106,37 +109,107 @@ fn char_to_byte() -> HashMap<char, u8> {
 /// Iteratively merge pairs of tokens in `tokens`, using the mappings in `ranks`,
 /// until no more merges are possible.
 ///
+/// `tokens` is a list of `(rank, byte_len)` pairs, where `byte_len` is the
+/// number of bytes of input that the token corresponds to.
+///
+/// Merges are applied in order of rank, and from left to right for pairs with
+/// the same rank. The tokens are kept in a linked list and candidate merges in
+/// a priority queue, so the cost is `O(n log n)` in the number of tokens.
+///
+/// If `dropout` is set, each merge is skipped with probability `p`, using
+/// the given random number generator (see [`Bpe::with_dropout`]). Skipped
+/// merges become candidates again after the next merge that is applied.
+///
 /// Returns the number of merged tokens.
-fn bpe_merge(tokens: &mut Vec<Rank>, ranks: &HashMap<(Rank, Rank), Rank>) -> usize {
-    loop {
-        // Find the pair of tokens with the lowest rank and merge all occurences
-        // of the pair.
-        let min_pair: Option<((Rank, Rank), Rank)> = tokens
-            .windows(2)
-            .filter_map(|pair| {
-                let [first, second] = pair.try_into().unwrap();
-                ranks
-                    .get(&(first, second))
-                    .map(|&rank| ((first, second), rank))
-            })
-            .min_by_key(|((_first, _second), rank)| *rank);
+fn bpe_merge(
+    tokens: &mut Vec<(Rank, usize)>,
+    ranks: &HashMap<(Rank, Rank), Rank>,
+    mut dropout: Option<(f32, &mut fastrand::Rng)>,
+) -> usize {
+    let n = tokens.len();
+    if n < 2 {
+        return n;
+    }
+
+    // Links between tokens that have not been merged into their predecessor.
+    let mut prev: Vec<Option<usize>> = (0..n).map(|i| i.checked_sub(1)).collect();
+    let mut next: Vec<Option<usize>> = (1..=n).map(|i| (i < n).then_some(i)).collect();
+    let mut removed = vec![false; n];
+
+    // Candidate merges as `(rank, index of first token)`. Entries become
+    // stale when either token is merged with a different neighbor, and are
+    // skipped when popped.
+    let mut queue: BinaryHeap<Reverse<(Rank, usize)>> = tokens
+        .windows(2)
+        .enumerate()
+        .filter_map(|(i, pair)| {
+            let [(first, _), (second, _)] = pair.try_into().unwrap();
+            ranks.get(&(first, second)).map(|&rank| Reverse((rank, i)))
+        })
+        .collect();
+    let mut skipped = Vec::new();
 
-        let Some(((first, second), rank)) = min_pair else {
-            break;
+    while let Some(Reverse((rank, i))) = queue.pop() {
+        if removed[i] {
+            continue;
+        }
+        let Some(j) = next[i] else {
+            continue;
         };
+        if ranks.get(&(tokens[i].0, tokens[j].0)) != Some(&rank) {
+            continue;
+        }
+
+        if let Some((p, rng)) = dropout.as_mut() {
+            if rng.f32() < *p {
+                skipped.push(Reverse((rank, i)));
+                continue;
+            }
+            queue.extend(skipped.drain(..));
+        }
+
+        // Merge token `j` into token `i`.
+        tokens[i] = (rank, tokens[i].1 + tokens[j].1);
+        removed[j] = true;
+        next[i] = next[j];
+        if let Some(k) = next[j] {
+            prev[k] = Some(i);
+        }
 
-        let mut i = 0;
-        while i < tokens.len() - 1 {
-            if tokens[i] == first && tokens[i + 1] == second {
-                tokens[i] = rank;
-                tokens.remove(i + 1);
+        // Add merges of the new token with its neighbors.
+        if let Some(h) = prev[i] {
+            if let Some(&new_rank) = ranks.get(&(tokens[h].0, rank)) {
+                queue.push(Reverse((new_rank, h)));
+            }
+        }
+        if let Some(k) = next[i] {
+            if let Some(&new_rank) = ranks.get(&(rank, tokens[k].0)) {
+                queue.push(Reverse((new_rank, i)));
             }
-            i += 1;
         }
     }
+
+    let mut removed = removed.into_iter();
+    tokens.retain(|_| !removed.next().unwrap());
     tokens.len()
 }
 
+/// Return the largest character boundary in `text` that is `<= offset`.
+fn floor_char_boundary(text: &str, mut offset: usize) -> usize {
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
+
+/// Return the smallest character boundary in `text` that is `>= offset`.
+fn ceil_char_boundary(text: &str, mut offset: usize) -> usize {
+    while !text.is_char_boundary(offset) {
+        offset += 1;
+    }
+    offset
+}
+
 struct BpeBuilder {
     /// See [ByteLevelBpe::merges].
     ranks: HashMap<(Rank, Rank), Rank>,
@@ -202,6 +275,116 @@ impl BpeBuilder {
     }
 }
 
+/// Cache of BPE encoding results for individual pieces of text.
+///
+/// Natural language text contains many repeated words, so caching the result
+/// of merging the bytes of each piece avoids a lot of repeated work.
+///
+/// The cache approximates least-recently-used eviction using two generations
+/// of entries. New entries are added to the current generation. When it
+/// fills up, it replaces the previous generation, whose entries are
+/// discarded. Entries found in the previous generation are promoted to the
+/// current one when accessed.
+struct WordCache {
+    /// Maximum number of entries in each generation.
+    generation_size: usize,
+    current: HashMap<String, Vec<(TokenId, usize)>>,
+    previous: HashMap<String, Vec<(TokenId, usize)>>,
+}
+
+impl WordCache {
+    /// Create a cache which holds up to `capacity` entries.
+    fn new(capacity: usize) -> WordCache {
+        WordCache {
+            generation_size: capacity.div_ceil(2),
+            current: HashMap::new(),
+            previous: HashMap::new(),
+        }
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.current.len() + self.previous.len()
+    }
+
+    fn get(&mut self, piece: &str) -> Option<Vec<(TokenId, usize)>> {
+        if let Some(tokens) = self.current.get(piece) {
+            return Some(tokens.clone());
+        }
+        let tokens = self.previous.remove(piece)?;
+        self.insert(piece, tokens.clone());
+        Some(tokens)
+    }
+
+    fn insert(&mut self, piece: &str, tokens: Vec<(TokenId, usize)>) {
+        if self.generation_size == 0 {
+            return;
+        }
+        if self.current.len() >= self.generation_size {
+            self.previous = std::mem::take(&mut self.current);
+        }
+        self.current.insert(piece.to_string(), tokens);
+    }
+}
+
+/// [WordCache] which is split into independently locked shards, so that
+/// threads encoding text concurrently rarely wait for each other.
+struct ShardedWordCache {
+    /// Maximum number of entries across all shards.
+    capacity: usize,
+    shards: Vec<Mutex<WordCache>>,
+    hasher: RandomState,
+}
+
+impl ShardedWordCache {
+    /// Maximum number of shards.
+    const MAX_SHARDS: usize = 16;
+
+    /// Minimum number of entries in each shard.
+    const MIN_SHARD_CAPACITY: usize = 256;
+
+    /// Create a cache which holds up to `capacity` entries.
+    fn new(capacity: usize) -> ShardedWordCache {
+        let n_shards = (capacity / Self::MIN_SHARD_CAPACITY).clamp(1, Self::MAX_SHARDS);
+        let shards = (0..n_shards)
+            .map(|_| Mutex::new(WordCache::new(capacity / n_shards)))
+            .collect();
+        ShardedWordCache {
+            capacity,
+            shards,
+            hasher: RandomState::new(),
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.lock().unwrap().len())
+            .sum()
+    }
+
+    /// Return the shard that contains `piece`.
+    fn shard(&self, piece: &str) -> &Mutex<WordCache> {
+        let index = self.hasher.hash_one(piece) as usize % self.shards.len();
+        &self.shards[index]
+    }
+
+    fn get(&self, piece: &str) -> Option<Vec<(TokenId, usize)>> {
+        self.shard(piece).lock().ok()?.get(piece)
+    }
+
+    fn insert(&self, piece: &str, tokens: Vec<(TokenId, usize)>) {
+        if let Ok(mut shard) = self.shard(piece).lock() {
+            shard.insert(piece, tokens);
+        }
+    }
+}
+
 /// Regex patterns used by popular tokenizer models.
 ///
 /// Some models (eg. GPT-2) use a regex to split input text into pieces prior
@@ -213,6 +396,12 @@ pub mod patterns {
     /// See <https://github.com/openai/tiktoken/blob/main/tiktoken_ext/openai_public.py>.
     pub const GPT2: &str =
         r"'s|'t|'re|'ve|'m|'ll|'d| ?\p{L}+| ?\p{N}+| ?[^\s\p{L}\p{N}]+|\s+(?!\S)|\s+";
+
+    /// Tokenization regex used by GPT-3.5 and GPT-4 (the `cl100k_base`
+    /// encoding).
+    ///
+    /// See <https://github.com/openai/tiktoken/blob/main/tiktoken_ext/openai_public.py>.
+    pub const CL100K: &str = r"'(?i:[sdmt]|ll|ve|re)|[^\r\n\p{L}\p{N}]?+\p{L}+|\p{N}{1,3}| ?[^\s\p{L}\p{N}]++[\r\n]*|\s*[\r\n]|\s+(?!\S)|\s+";
 }
 
 /// Byte Pair Encoding tokenizer used by GPT-2 [^1] and subsequently used by
@@ -256,11 +445,18 @@ pub struct Bpe {
     token_id_to_encoded_bytes: Option<HashMap<TokenId, EncodedBytes>>,
 
     /// Pattern used to split the text into pieces prior to applying BPE
-    /// tokenization.
-    splitter: Regex,
+    /// tokenization. If `None`, the text is encoded as a single piece.
+    splitter: Option<SplitRegex>,
 
     /// Map from token ID to content for special tokens (eg. end-of-string).
     added_tokens: HashMap<TokenId, String>,
+
+    /// Cache of encoded pieces. See [`Bpe::with_cache_size`].
+    cache: ShardedWordCache,
+
+    /// Probability of skipping each merge and the random number generator
+    /// used to decide. See [`Bpe::with_dropout`].
+    dropout: Option<(f32, Mutex<fastrand::Rng>)>,
 }
 
 impl Bpe {
@@ -272,7 +468,9 @@ impl Bpe {
     /// `pattern` is a regex used to split input text into pieces before BPE
     /// encoding is applied. The supported syntax is that supported by the
     /// [fancy_regex](https://crates.io/crates/fancy-regex) crate. The
-    /// [patterns] module contains patterns used by popular models.
+    /// [patterns] module contains patterns used by popular models. If
+    /// `pattern` is empty, the text is not split. This is useful when the
+    /// splitting is done by a [pre-tokenizer](super::pre_tokenizers) instead.
     ///
     /// `vocab` is a mapping between token strings and IDs. If not provided, the
     /// ID of a token is 256 + the index of the pair in the merge list which
@@ -289,7 +487,11 @@ impl Bpe {
         vocab: Option<HashMap<EncodedBytes, TokenId>>,
         added_tokens: HashMap<TokenId, String>,
     ) -> Result<Bpe, BpeError> {
-        let splitter = Regex::new(pattern).map_err(|err| BpeError::InvalidPattern(err.into()))?;
+        let splitter = if pattern.is_empty() {
+            None
+        } else {
+            Some(SplitRegex::new(pattern).map_err(BpeError::InvalidPattern)?)
+        };
 
         let mut builder = BpeBuilder::new();
         builder.add_merges(merges)?;
@@ -318,9 +520,57 @@ impl Bpe {
             splitter,
             added_tokens,
             token_id_to_encoded_bytes,
+            cache: ShardedWordCache::new(Self::DEFAULT_CACHE_SIZE),
+            dropout: None,
         })
     }
 
+    /// Default maximum number of entries in the cache of encoded pieces.
+    pub const DEFAULT_CACHE_SIZE: usize = 10_000;
+
+    /// Pieces longer than this many bytes are not cached.
+    const MAX_CACHED_PIECE_LEN: usize = 256;
+
+    /// Set the maximum number of entries in the cache of encoded pieces.
+    ///
+    /// The encoder caches the tokens produced for each piece of text that
+    /// results from splitting the input with the `pattern` regex, so that
+    /// repeated words are only encoded once. A size of zero disables the
+    /// cache. The default size is [`Bpe::DEFAULT_CACHE_SIZE`].
+    pub fn with_cache_size(self, size: usize) -> Bpe {
+        Bpe {
+            cache: ShardedWordCache::new(size),
+            ..self
+        }
+    }
+
+    /// Return the maximum number of entries in the cache of encoded pieces.
+    pub fn cache_size(&self) -> usize {
+        self.cache.capacity()
+    }
+
+    /// Enable [BPE-dropout](https://arxiv.org/abs/1910.13267).
+    ///
+    /// When enabled, each merge is skipped with probability `p` during
+    /// encoding, so the same text can be split into different, usually more
+    /// fine-grained, tokens each time it is encoded. This is useful for data
+    /// augmentation or for evaluating a model's robustness to alternative
+    /// segmentations. `seed` initializes the random number generator, so
+    /// that encoding a sequence of inputs is reproducible.
+    ///
+    /// A `p` of zero disables dropout. A `p` of one disables all merges. The
+    /// cache of encoded pieces is not used while dropout is enabled.
+    pub fn with_dropout(self, p: f32, seed: u64) -> Bpe {
+        let dropout = (p > 0.).then(|| (p.min(1.), Mutex::new(fastrand::Rng::with_seed(seed))));
+        Bpe { dropout, ..self }
+    }
+
+    /// Return the probability of skipping each merge, if BPE-dropout is
+    /// enabled. See [`Bpe::with_dropout`].
+    pub fn dropout(&self) -> Option<f32> {
+        self.dropout.as_ref().map(|(p, _rng)| *p)
+    }
+
     /// Decode a token ID to a byte sequence. Be aware that the returned bytes
     /// may end in the middle of a UTF-8 character.
     fn get_token_bytes(&self, id: TokenId) -> Option<Vec<u8>> {
@@ -347,28 +597,50 @@ impl Bpe {
     }
 
     /// Encode a string as a sequence of tokens.
-    fn encode_piece(&self, piece: &str) -> Vec<TokenId> {
+    ///
+    /// Returns a list of `(token_id, byte_len)` pairs, where `byte_len` is
+    /// the number of bytes of `piece` that the token corresponds to.
+    fn encode_piece(&self, piece: &str) -> Vec<(TokenId, usize)> {
         // Start with one token per byte.
-        let mut tokens: Vec<Rank> = piece
+        let mut tokens: Vec<(Rank, usize)> = piece
             .as_bytes()
             .iter()
-            .map(|&b| self.byte_to_rank[b as usize])
+            .map(|&b| (self.byte_to_rank[b as usize], 1))
             .collect();
 
         // Iteratively merge tokens together until no more are possible.
-        bpe_merge(&mut tokens, &self.merges);
+        if let Some((p, rng)) = self.dropout.as_ref() {
+            let mut rng = rng.lock().unwrap_or_else(|err| err.into_inner());
+            bpe_merge(&mut tokens, &self.merges, Some((*p, &mut *rng)));
+        } else {
+            bpe_merge(&mut tokens, &self.merges, None);
+        }
 
         // Convert ranks to token IDs.
         let unknown_token_id = 0;
         if let Some(id_map) = self.rank_to_token_id.as_ref() {
             tokens
                 .into_iter()
-                .map(|rank| id_map.get(&rank).copied().unwrap_or(unknown_token_id))
+                .map(|(rank, len)| (id_map.get(&rank).copied().unwrap_or(unknown_token_id), len))
                 .collect()
         } else {
             tokens
         }
     }
+
+    /// Encode a piece of text, using the cache if possible.
+    fn encode_piece_cached(&self, piece: &str) -> Vec<(TokenId, usize)> {
+        if piece.len() > Self::MAX_CACHED_PIECE_LEN || self.dropout.is_some() {
+            return self.encode_piece(piece);
+        }
+
+        if let Some(tokens) = self.cache.get(piece) {
+            return tokens;
+        }
+        let tokens = self.encode_piece(piece);
+        self.cache.insert(piece, tokens.clone());
+        tokens
+    }
 }
 
 impl Encoder for Bpe {
@@ -409,6 +681,15 @@ impl Encoder for Bpe {
         Ok(token_str)
     }
 
+    fn vocab_size(&self) -> usize {
+        let max_added_id = self.added_tokens.keys().max().map(|&id| id as usize + 1);
+        let max_id = match &self.token_id_to_encoded_bytes {
+            Some(vocab) => vocab.keys().max().map_or(0, |&id| id as usize + 1),
+            None => 256 + self.merges.len(),
+        };
+        max_id.max(max_added_id.unwrap_or(0))
+    }
+
     fn get_token_id(&self, text: &str) -> Result<TokenId, TokenizerError> {
         if let Some((&id, _str)) = self.added_tokens.iter().find(|(_id, str)| *str == text) {
             return Ok(id);
@@ -416,7 +697,7 @@ impl Encoder for Bpe {
 
         let tokens = self.encode_piece(text);
         if tokens.len() == 1 {
-            Ok(tokens[0])
+            Ok(tokens[0].0)
         } else {
             Err(TokenizerError::MissingToken(text.to_string()))
         }
@@ -425,18 +706,33 @@ impl Encoder for Bpe {
     fn encode_with_offsets(
         &self,
         text: &str,
-        on_token: &mut dyn FnMut(usize, TokenId),
+        on_token: &mut dyn FnMut(Range<usize>, TokenId),
     ) -> Result<(), TokenizerError> {
-        for piece in self.splitter.find_iter(text) {
-            let piece = piece.map_err(|err| TokenizerError::RegexSplitFailed(err.into()))?;
-            if piece.range().is_empty() {
-                continue;
+        let mut encode_piece = |start: usize, piece: &str| {
+            let mut offset = start;
+            for (token, len) in self.encode_piece_cached(piece) {
+                // Tokens may start or end in the middle of a multi-byte
+                // character. Expand the range to include the whole character.
+                let start = floor_char_boundary(text, offset);
+                let end = ceil_char_boundary(text, offset + len);
+                on_token(start..end, token);
+                offset += len;
+            }
+        };
+
+        let Some(splitter) = &self.splitter else {
+            if !text.is_empty() {
+                encode_piece(0, text);
             }
+            return Ok(());
+        };
 
-            let piece_str = piece.as_str();
-            for token in self.encode_piece(piece_str) {
-                on_token(piece.start(), token)
+        for piece in splitter.find_iter(text) {
+            let piece = piece.map_err(TokenizerError::RegexSplitFailed)?;
+            if piece.is_empty() {
+                continue;
             }
+            encode_piece(piece.start, &text[piece]);
         }
 
         Ok(())
@@ -475,8 +771,8 @@ mod tests {
     use std::collections::HashMap;
 
     use super::patterns::GPT2 as GPT2_SPLIT_PATTERN;
-    use super::{Bpe, EncodedBytes};
-    use crate::tokenizers::{TokenId, Tokenizer};
+    use super::{bpe_merge, Bpe, BpeBuilder, EncodedBytes, Rank};
+    use crate::tokenizers::{Encoder, TokenId, Tokenizer};
 
     // The first ~25 lines of the merge list from GPT 2.
     const MINI_GPT2: &str = "
@@ -566,6 +862,13 @@ in g";
 -------- --------
 ",
             },
+            // Overlapping occurrences of a pair are merged from left to
+            // right.
+            Case {
+                text: "aaaaa",
+                tokens: &["aa", "aa", "a"],
+                merges: "a a",
+            },
         ];
 
         for Case {
@@ -585,6 +888,92 @@ in g";
         }
     }
 
+    /// Reference implementation of [bpe_merge] which repeatedly scans for the
+    /// lowest-ranked pair and merges all occurrences of it.
+    fn bpe_merge_reference(
+        tokens: &mut Vec<(Rank, usize)>,
+        ranks: &HashMap<(Rank, Rank), Rank>,
+    ) -> usize {
+        loop {
+            let min_pair = tokens
+                .windows(2)
+                .filter_map(|pair| ranks.get(&(pair[0].0, pair[1].0)).map(|&r| (pair, r)))
+                .min_by_key(|(_, rank)| *rank)
+                .map(|(pair, rank)| ((pair[0].0, pair[1].0), rank));
+            let Some(((first, second), rank)) = min_pair else {
+                break;
+            };
+            let mut i = 0;
+            while i < tokens.len() - 1 {
+                if tokens[i].0 == first && tokens[i + 1].0 == second {
+                    let (_, second_len) = tokens.remove(i + 1);
+                    tokens[i] = (rank, tokens[i].1 + second_len);
+                }
+                i += 1;
+            }
+        }
+        tokens.len()
+    }
+
+    #[test]
+    fn test_bpe_merge() {
+        let merges: Vec<&str> = MINI_GPT2
+            .lines()
+            .chain(["- -", "-- --", "-- -", "---- ----"])
+            .collect();
+        let mut builder = BpeBuilder::new();
+        builder.add_merges(&merges).unwrap();
+
+        let texts = [
+            "",
+            "a",
+            "the cat is in the bed",
+            "theatre interests thinking",
+            "-------",
+            &"-".repeat(1001),
+            &"the string in the ".repeat(100),
+        ];
+        for text in texts {
+            let text = text.replace(' ', "Ġ");
+            let initial: Vec<(Rank, usize)> = text
+                .chars()
+                .map(|ch| (builder.get_token_rank(&ch.to_string()).unwrap(), 1))
+                .collect();
+
+            let mut expected = initial.clone();
+            bpe_merge_reference(&mut expected, &builder.ranks);
+
+            let mut actual = initial;
+            let len = bpe_merge(&mut actual, &builder.ranks, None);
+
+            assert_eq!(actual, expected);
+            assert_eq!(len, actual.len());
+        }
+    }
+
+    #[test]
+    fn test_offset_mapping() {
+        let merges: Vec<&str> = MINI_GPT2.lines().collect();
+        let encoder = Bpe::new(&merges, GPT2_SPLIT_PATTERN, None, HashMap::new()).unwrap();
+        let tokenizer = Tokenizer::new(encoder, Default::default());
+
+        let encoded = tokenizer
+            .encode("the cat é".into(), Default::default())
+            .unwrap();
+        assert_eq!(
+            tokenizer.encoder().get_tokens(encoded.token_ids()).unwrap(),
+            &["t", "he", "Ġc", "at", "Ġ", "Ã", "©"]
+        );
+
+        // Tokens which correspond to part of a multi-byte character are
+        // mapped to the whole character.
+        assert_eq!(
+            encoded.offset_mapping(),
+            &[(0, 1), (1, 3), (3, 5), (5, 7), (7, 8), (8, 9), (8, 9)]
+        );
+        assert_eq!(encoded.text_for_token_range(1..4), Some("he cat"));
+    }
+
     #[test]
     fn test_get_token_str() {
         struct Case<'a> {
@@ -681,4 +1070,71 @@ in g";
             assert_eq!(decoded, expected);
         }
     }
+
+    #[test]
+    fn test_cache() {
+        let merges: Vec<&str> = MINI_GPT2.lines().collect();
+        let text = "the cat is in the bed and the cat is on the mat";
+
+        let uncached = Bpe::new(&merges, GPT2_SPLIT_PATTERN, None, HashMap::new())
+            .unwrap()
+            .with_cache_size(0);
+        assert_eq!(uncached.cache_size(), 0);
+        let expected = uncached.encode(text).unwrap();
+        assert_eq!(uncached.cache.len(), 0);
+
+        // Encoding with a cache should produce the same tokens, whether or
+        // not the pieces are already cached.
+        let cached = Bpe::new(&merges, GPT2_SPLIT_PATTERN, None, HashMap::new()).unwrap();
+        assert_eq!(cached.cache_size(), Bpe::DEFAULT_CACHE_SIZE);
+        for _ in 0..2 {
+            assert_eq!(cached.encode(text).unwrap(), expected);
+        }
+        let unique_pieces = [
+            "the", " cat", " is", " in", " the", " bed", " and", " on", " mat",
+        ];
+        assert_eq!(cached.cache.len(), unique_pieces.len());
+
+        // The number of cached pieces should not exceed the cache size.
+        let small_cache = Bpe::new(&merges, GPT2_SPLIT_PATTERN, None, HashMap::new())
+            .unwrap()
+            .with_cache_size(4);
+        assert_eq!(small_cache.encode(text).unwrap(), expected);
+        assert!(small_cache.cache.len() <= 4);
+    }
+
+    #[test]
+    fn test_dropout() {
+        let merges: Vec<&str> = MINI_GPT2.lines().collect();
+        let text = "the cat is in the bed and the cat is on the mat";
+        let new_bpe = || Bpe::new(&merges, GPT2_SPLIT_PATTERN, None, HashMap::new()).unwrap();
+        let expected = new_bpe().encode(text).unwrap();
+
+        // A dropout probability of zero disables dropout.
+        let bpe = new_bpe().with_dropout(0., 1234);
+        assert_eq!(bpe.dropout(), None);
+        assert_eq!(bpe.encode(text).unwrap(), expected);
+
+        // A dropout probability of one skips all merges.
+        let bpe = new_bpe().with_dropout(1., 1234);
+        assert_eq!(bpe.dropout(), Some(1.));
+        assert_eq!(bpe.encode(text).unwrap().len(), text.len());
+
+        // Intermediate probabilities produce more tokens than without dropout.
+        // The output is deterministic for a given seed.
+        let encode_with_seed = |seed| {
+            let bpe = new_bpe().with_dropout(0.5, seed);
+            (0..5)
+                .map(|_| bpe.encode(text).unwrap())
+                .collect::<Vec<_>>()
+        };
+        let outputs = encode_with_seed(1234);
+        assert_eq!(outputs, encode_with_seed(1234));
+        for tokens in &outputs {
+            assert!(tokens.len() > expected.len());
+            assert!(tokens.len() < text.len());
+            assert_eq!(new_bpe().decode(tokens).unwrap(), text);
+        }
+        assert!(outputs.iter().any(|tokens| *tokens != outputs[0]));
+    }
 }
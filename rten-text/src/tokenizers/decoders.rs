@@ -0,0 +1,478 @@
+//! Decoders which convert token strings back into text.
+//!
+//! Decoders are the final stage of [Tokenizer::decode](super::Tokenizer::decode).
+//! They take the canonical strings for a sequence of token IDs (see
+//! [Encoder::get_token_str](super::Encoder::get_token_str)) and undo
+//! transformations that were applied during encoding, such as mapping bytes to
+//! printable characters or marking the start of words.
+//!
+//! These mirror the decoders in the `decoder` section of Hugging Face
+//! `tokenizer.json` files.
+
+use fancy_regex::Regex;
+
+use super::bpe::char_to_byte;
+use super::TokenizerError;
+
+/// A Decoder transforms a sequence of token strings into text.
+///
+/// Decoders operate on a list of strings rather than producing a single
+/// string, so that they can be chained together using [Sequence].
+pub trait Decoder: Send + Sync {
+    /// Transform a sequence of token strings.
+    fn decode_chain(&self, tokens: Vec<String>) -> Result<Vec<String>, TokenizerError>;
+
+    /// Transform a sequence of token strings and join the results.
+    fn decode(&self, tokens: Vec<String>) -> Result<String, TokenizerError> {
+        Ok(self.decode_chain(tokens)?.concat())
+    }
+}
+
+/// Decoder for byte-level tokenizers such as [Bpe](super::Bpe), which
+/// converts the printable characters used to represent bytes back into
+/// bytes, then decodes the bytes as UTF-8.
+///
+/// Decoding fails with [TokenizerError::InvalidUtf8] if the bytes do not form
+/// a complete UTF-8 sequence.
+#[derive(Clone, Debug, Default)]
+pub struct ByteLevel {}
+
+impl ByteLevel {
+    pub fn new() -> ByteLevel {
+        ByteLevel {}
+    }
+}
+
+impl Decoder for ByteLevel {
+    fn decode_chain(&self, tokens: Vec<String>) -> Result<Vec<String>, TokenizerError> {
+        let char_to_byte = char_to_byte();
+        let mut bytes = Vec::new();
+        for token in tokens {
+            // Tokens which contain characters outside of the byte mapping,
+            // such as some added tokens, are used as-is.
+            let token_bytes: Option<Vec<u8>> = token
+                .chars()
+                .map(|ch| char_to_byte.get(&ch).copied())
+                .collect();
+            match token_bytes {
+                Some(token_bytes) => bytes.extend(token_bytes),
+                None => bytes.extend(token.as_bytes()),
+            }
+        }
+        let text = String::from_utf8(bytes).map_err(|_| TokenizerError::InvalidUtf8)?;
+        Ok(vec![text])
+    }
+}
+
+/// Decoder for [WordPiece](super::WordPiece) tokenizers, which joins
+/// subwords and separates words with spaces.
+#[derive(Clone, Debug)]
+pub struct WordPiece {
+    prefix: String,
+    cleanup: bool,
+}
+
+impl WordPiece {
+    /// Create a WordPiece decoder.
+    ///
+    /// `prefix` is the marker at the start of tokens which continue a word
+    /// (usually `##`). If `cleanup` is true, spaces before punctuation and
+    /// English contractions are removed.
+    pub fn new(prefix: &str, cleanup: bool) -> WordPiece {
+        WordPiece {
+            prefix: prefix.to_string(),
+            cleanup,
+        }
+    }
+}
+
+impl Default for WordPiece {
+    fn default() -> Self {
+        WordPiece::new("##", true)
+    }
+}
+
+/// Remove spaces that a word-based tokenizer inserts before punctuation and
+/// English contractions.
+pub(crate) fn cleanup_tokenization(text: &str) -> String {
+    text.replace(" .", ".")
+        .replace(" ?", "?")
+        .replace(" !", "!")
+        .replace(" ,", ",")
+        .replace(" ' ", "'")
+        .replace(" n't", "n't")
+        .replace(" 'm", "'m")
+        .replace(" do not", " don't")
+        .replace(" 's", "'s")
+        .replace(" 've", "'ve")
+        .replace(" 're", "'re")
+}
+
+impl Decoder for WordPiece {
+    fn decode_chain(&self, tokens: Vec<String>) -> Result<Vec<String>, TokenizerError> {
+        let tokens = tokens
+            .into_iter()
+            .enumerate()
+            .map(|(i, token)| {
+                let token = if i == 0 {
+                    token
+                } else if let Some(suffix) = token.strip_prefix(&self.prefix) {
+                    suffix.to_string()
+                } else {
+                    format!(" {}", token)
+                };
+                if self.cleanup {
+                    cleanup_tokenization(&token)
+                } else {
+                    token
+                }
+            })
+            .collect();
+        Ok(tokens)
+    }
+}
+
+/// Decoder for tokenizers which replace spaces with a marker character
+/// (usually `▁`), such as SentencePiece models.
+#[derive(Clone, Debug)]
+pub struct Metaspace {
+    replacement: char,
+    strip_prefix_space: bool,
+}
+
+impl Metaspace {
+    /// Create a Metaspace decoder which replaces `replacement` with spaces.
+    ///
+    /// If `strip_prefix_space` is true, the space at the start of the first
+    /// token is removed. This undoes the space which is added to the start
+    /// of the input during encoding.
+    pub fn new(replacement: char, strip_prefix_space: bool) -> Metaspace {
+        Metaspace {
+            replacement,
+            strip_prefix_space,
+        }
+    }
+}
+
+impl Default for Metaspace {
+    fn default() -> Self {
+        Metaspace::new('▁', true)
+    }
+}
+
+impl Decoder for Metaspace {
+    fn decode_chain(&self, tokens: Vec<String>) -> Result<Vec<String>, TokenizerError> {
+        let tokens = tokens
+            .into_iter()
+            .enumerate()
+            .map(|(i, token)| {
+                let token = token.replace(self.replacement, " ");
+                match token.strip_prefix(' ') {
+                    Some(rest) if i == 0 && self.strip_prefix_space => rest.to_string(),
+                    _ => token,
+                }
+            })
+            .collect();
+        Ok(tokens)
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Pattern {
+    String(String),
+    Regex(Regex),
+}
+
+/// Decoder which replaces a pattern in each token.
+#[derive(Clone, Debug)]
+pub struct Replace {
+    pattern: Pattern,
+    content: String,
+}
+
+impl Replace {
+    /// Create a decoder which replaces occurrences of the string `pattern`
+    /// with `content`.
+    pub fn new(pattern: &str, content: &str) -> Replace {
+        Replace {
+            pattern: Pattern::String(pattern.to_string()),
+            content: content.to_string(),
+        }
+    }
+
+    /// Create a decoder which replaces matches of the regex `pattern` with
+    /// `content`.
+    pub fn regex(pattern: &str, content: &str) -> Result<Replace, Box<fancy_regex::Error>> {
+        let regex = Regex::new(pattern)?;
+        Ok(Replace {
+            pattern: Pattern::Regex(regex),
+            content: content.to_string(),
+        })
+    }
+}
+
+impl Decoder for Replace {
+    fn decode_chain(&self, tokens: Vec<String>) -> Result<Vec<String>, TokenizerError> {
+        tokens
+            .into_iter()
+            .map(|token| match &self.pattern {
+                Pattern::String(pattern) => Ok(token.replace(pattern.as_str(), &self.content)),
+                Pattern::Regex(regex) => {
+                    let mut replaced = String::with_capacity(token.len());
+                    let mut last_end = 0;
+                    for m in regex.find_iter(&token) {
+                        let m = m.map_err(|err| TokenizerError::RegexSplitFailed(err.into()))?;
+                        replaced.push_str(&token[last_end..m.start()]);
+                        replaced.push_str(&self.content);
+                        last_end = m.end();
+                    }
+                    replaced.push_str(&token[last_end..]);
+                    Ok(replaced)
+                }
+            })
+            .collect()
+    }
+}
+
+/// Decoder which removes up to a given number of occurrences of a character
+/// from the start and end of each token.
+#[derive(Clone, Debug)]
+pub struct Strip {
+    content: char,
+    start: usize,
+    stop: usize,
+}
+
+impl Strip {
+    /// Create a decoder which removes up to `start` occurrences of `content`
+    /// from the start of each token and up to `stop` occurrences from the end.
+    pub fn new(content: char, start: usize, stop: usize) -> Strip {
+        Strip {
+            content,
+            start,
+            stop,
+        }
+    }
+}
+
+impl Decoder for Strip {
+    fn decode_chain(&self, tokens: Vec<String>) -> Result<Vec<String>, TokenizerError> {
+        let tokens = tokens
+            .into_iter()
+            .map(|token| {
+                let mut text = token.as_str();
+                for _ in 0..self.start {
+                    let Some(rest) = text.strip_prefix(self.content) else {
+                        break;
+                    };
+                    text = rest;
+                }
+                for _ in 0..self.stop {
+                    let Some(rest) = text.strip_suffix(self.content) else {
+                        break;
+                    };
+                    text = rest;
+                }
+                text.to_string()
+            })
+            .collect();
+        Ok(tokens)
+    }
+}
+
+/// Decoder which converts tokens of the form `<0xXX>` into bytes.
+///
+/// This is used by SentencePiece models which fall back to encoding
+/// individual bytes for characters that are not in the vocabulary. Bytes
+/// which do not form valid UTF-8 are replaced with `U+FFFD`.
+#[derive(Clone, Debug, Default)]
+pub struct ByteFallback {}
+
+impl ByteFallback {
+    pub fn new() -> ByteFallback {
+        ByteFallback {}
+    }
+}
+
+/// Parse a token of the form `<0xXX>` into a byte.
+pub(super) fn parse_byte_token(token: &str) -> Option<u8> {
+    let hex = token.strip_prefix("<0x")?.strip_suffix('>')?;
+    if hex.len() != 2 {
+        return None;
+    }
+    u8::from_str_radix(hex, 16).ok()
+}
+
+impl Decoder for ByteFallback {
+    fn decode_chain(&self, tokens: Vec<String>) -> Result<Vec<String>, TokenizerError> {
+        let mut output = Vec::with_capacity(tokens.len());
+        let mut bytes = Vec::new();
+
+        let flush_bytes = |bytes: &mut Vec<u8>, output: &mut Vec<String>| {
+            if bytes.is_empty() {
+                return;
+            }
+            match String::from_utf8(std::mem::take(bytes)) {
+                Ok(text) => output.push(text),
+                Err(err) => {
+                    for _ in err.as_bytes() {
+                        output.push(char::REPLACEMENT_CHARACTER.to_string());
+                    }
+                }
+            }
+        };
+
+        for token in tokens {
+            if let Some(byte) = parse_byte_token(&token) {
+                bytes.push(byte);
+            } else {
+                flush_bytes(&mut bytes, &mut output);
+                output.push(token);
+            }
+        }
+        flush_bytes(&mut bytes, &mut output);
+
+        Ok(output)
+    }
+}
+
+/// Decoder which joins all tokens into a single string.
+#[derive(Clone, Debug, Default)]
+pub struct Fuse {}
+
+impl Fuse {
+    pub fn new() -> Fuse {
+        Fuse {}
+    }
+}
+
+impl Decoder for Fuse {
+    fn decode_chain(&self, tokens: Vec<String>) -> Result<Vec<String>, TokenizerError> {
+        Ok(vec![tokens.concat()])
+    }
+}
+
+/// Decoder which applies a sequence of decoders in order.
+#[derive(Default)]
+pub struct Sequence {
+    decoders: Vec<Box<dyn Decoder>>,
+}
+
+impl Sequence {
+    pub fn new(decoders: Vec<Box<dyn Decoder>>) -> Sequence {
+        Sequence { decoders }
+    }
+}
+
+impl Decoder for Sequence {
+    fn decode_chain(&self, tokens: Vec<String>) -> Result<Vec<String>, TokenizerError> {
+        self.decoders
+            .iter()
+            .try_fold(tokens, |tokens, decoder| decoder.decode_chain(tokens))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        ByteFallback, ByteLevel, Decoder, Fuse, Metaspace, Replace, Sequence, Strip, WordPiece,
+    };
+    use crate::tokenizers::TokenizerError;
+
+    fn tokens(tokens: &[&str]) -> Vec<String> {
+        tokens.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn test_byte_level() {
+        let decoder = ByteLevel::new();
+        assert_eq!(
+            decoder
+                .decode(tokens(&["Hello", "Ġworld", "<|endoftext|>"]))
+                .unwrap(),
+            "Hello world<|endoftext|>"
+        );
+
+        // "é" is encoded as two bytes, each mapped to a separate character.
+        assert_eq!(decoder.decode(tokens(&["Ã", "©"])).unwrap(), "é");
+        assert!(matches!(
+            decoder.decode(tokens(&["Ã"])),
+            Err(TokenizerError::InvalidUtf8)
+        ));
+    }
+
+    #[test]
+    fn test_wordpiece() {
+        let decoder = WordPiece::default();
+        assert_eq!(
+            decoder
+                .decode(tokens(&["hello", "wor", "##ld", "!", "it", "'s", "fine"]))
+                .unwrap(),
+            "hello world! it's fine"
+        );
+
+        let decoder = WordPiece::new("##", false);
+        assert_eq!(
+            decoder.decode(tokens(&["hello", "##s", "!"])).unwrap(),
+            "hellos !"
+        );
+    }
+
+    #[test]
+    fn test_metaspace() {
+        let decoder = Metaspace::default();
+        assert_eq!(
+            decoder.decode(tokens(&["▁Hello", "▁wor", "ld"])).unwrap(),
+            "Hello world"
+        );
+
+        let decoder = Metaspace::new('▁', false);
+        assert_eq!(decoder.decode(tokens(&["▁Hello"])).unwrap(), " Hello");
+    }
+
+    #[test]
+    fn test_replace_and_strip() {
+        let decoder = Replace::new("▁", " ");
+        assert_eq!(
+            decoder.decode_chain(tokens(&["▁a▁b", "c"])).unwrap(),
+            tokens(&[" a b", "c"])
+        );
+
+        let decoder = Replace::regex("[0-9]+", "#").unwrap();
+        assert_eq!(decoder.decode(tokens(&["a12b3"])).unwrap(), "a#b#");
+
+        let decoder = Strip::new(' ', 1, 0);
+        assert_eq!(
+            decoder.decode_chain(tokens(&["  a ", "b"])).unwrap(),
+            tokens(&[" a ", "b"])
+        );
+    }
+
+    #[test]
+    fn test_byte_fallback() {
+        let decoder = ByteFallback::new();
+        assert_eq!(
+            decoder
+                .decode_chain(tokens(&["a", "<0xC3>", "<0xA9>", "<0x41", "<0xFF>"]))
+                .unwrap(),
+            tokens(&["a", "é", "<0x41", "\u{FFFD}"])
+        );
+    }
+
+    #[test]
+    fn test_sequence() {
+        // The decoder sequence used by Llama models.
+        let decoder = Sequence::new(vec![
+            Box::new(Replace::new("▁", " ")),
+            Box::new(ByteFallback::new()),
+            Box::new(Fuse::new()),
+            Box::new(Strip::new(' ', 1, 0)),
+        ]);
+        assert_eq!(
+            decoder
+                .decode_chain(tokens(&["▁Hello", "▁w", "<0xC3>", "<0xB6>", "rld"]))
+                .unwrap(),
+            tokens(&["Hello wörld"])
+        );
+    }
+}
@@ -4,12 +4,45 @@
 use std::collections::HashMap;
 
 use super::TokenId;
-use serde::Deserialize;
+use serde::de::{DeserializeOwned, Error as _};
+use serde::{Deserialize, Deserializer};
+
+/// Return the value of the `type` field of a component's configuration.
+pub(crate) fn component_type(config: &serde_json::Value) -> Option<&str> {
+    config.get("type")?.as_str()
+}
+
+/// A component configuration together with the value of its `type` field.
+///
+/// This is used to report the names of component types that are not
+/// supported, which are otherwise discarded by `#[serde(other)]`.
+pub(crate) struct WithType<T> {
+    pub type_name: String,
+    pub config: T,
+}
+
+impl<'de, T: DeserializeOwned> Deserialize<'de> for WithType<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        let type_name = component_type(&value).unwrap_or_default().to_string();
+        let config = T::deserialize(value).map_err(D::Error::custom)?;
+        Ok(WithType { type_name, config })
+    }
+}
 
 #[derive(Deserialize)]
 pub(crate) struct AddedToken {
     pub content: String,
     pub id: TokenId,
+    #[serde(default)]
+    pub special: bool,
+    #[serde(default)]
+    pub lstrip: bool,
+    #[serde(default)]
+    pub rstrip: bool,
+    #[serde(default)]
+    pub single_word: bool,
+    pub normalized: Option<bool>,
 }
 
 #[derive(Deserialize)]
@@ -18,19 +51,176 @@ pub(crate) struct BertNormalizer {
     pub strip_accents: Option<bool>,
 }
 
+#[derive(Deserialize)]
+pub(crate) struct SequenceNormalizer {
+    /// Configurations of the normalizers in the sequence. These are parsed
+    /// after custom types have been looked up. See [Normalizer].
+    pub normalizers: Vec<serde_json::Value>,
+}
+
 #[derive(Deserialize)]
 #[serde(tag = "type")]
 pub(crate) enum Normalizer {
     #[serde(rename = "BertNormalizer")]
     Bert(BertNormalizer),
+    Lowercase,
     #[serde(rename = "NFC")]
     Nfc,
+    #[serde(rename = "NFD")]
+    Nfd,
+    #[serde(rename = "NFKC")]
+    Nfkc,
+    #[serde(rename = "NFKD")]
+    Nfkd,
+    Replace(Replace),
+    Sequence(SequenceNormalizer),
+    StripAccents,
+
+    /// A normalizer type which isn't supported by this crate.
+    #[serde(other)]
+    Unsupported,
+}
+
+#[derive(Deserialize)]
+pub(crate) enum Pattern {
+    String(String),
+    Regex(String),
+}
+
+/// Configuration for `Replace` normalizers and decoders.
+#[derive(Deserialize)]
+pub(crate) struct Replace {
+    pub pattern: Pattern,
+    pub content: String,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct WordPieceDecoder {
+    pub prefix: String,
+    pub cleanup: bool,
+}
+
+/// Configuration for `Metaspace` pre-tokenizers and decoders.
+#[derive(Deserialize)]
+pub(crate) struct Metaspace {
+    pub replacement: char,
+
+    /// Whether a space was added to the start of the input. Used by older
+    /// versions of Hugging Face Tokenizers.
+    pub add_prefix_space: Option<bool>,
+
+    /// One of "always", "never" or "first". Replaces `add_prefix_space` in
+    /// newer versions of Hugging Face Tokenizers.
+    pub prepend_scheme: Option<String>,
+
+    /// Whether the pre-tokenizer splits text into words. Defaults to true.
+    pub split: Option<bool>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct StripDecoder {
+    pub content: char,
+    pub start: usize,
+    pub stop: usize,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct SequenceDecoder {
+    pub decoders: Vec<WithType<Decoder>>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+pub(crate) enum Decoder {
+    ByteFallback,
+    ByteLevel,
+    Fuse,
+    Metaspace(Metaspace),
+    Replace(Replace),
+    Sequence(SequenceDecoder),
+    Strip(StripDecoder),
+    WordPiece(WordPieceDecoder),
+
+    /// A decoder type which isn't supported by this crate.
+    #[serde(other)]
+    Unsupported,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ByteLevelPreTokenizer {
+    /// Whether to add a space to the start of the input if it doesn't
+    /// already start with one. Defaults to true.
+    pub add_prefix_space: Option<bool>,
+
+    /// Whether to split the input using the GPT-2 regex. Defaults to true.
+    pub use_regex: Option<bool>,
+}
+
+#[derive(Deserialize)]
+pub(crate) enum SplitDelimiterBehavior {
+    Removed,
+    Isolated,
+    MergedWithPrevious,
+    MergedWithNext,
+    Contiguous,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct SplitPreTokenizer {
+    pub pattern: Pattern,
+    pub behavior: SplitDelimiterBehavior,
+    #[serde(default)]
+    pub invert: bool,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct SequencePreTokenizer {
+    /// Configurations of the pre-tokenizers in the sequence. These are parsed
+    /// after custom types have been looked up. See [PreTokenizer].
+    pub pretokenizers: Vec<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+pub(crate) enum PreTokenizer {
+    #[serde(rename = "BertPreTokenizer")]
+    Bert,
+    ByteLevel(ByteLevelPreTokenizer),
+    Metaspace(Metaspace),
+    Sequence(SequencePreTokenizer),
+    Split(SplitPreTokenizer),
+    UnicodeScripts,
+    Whitespace,
+    WhitespaceSplit,
+
+    /// A pre-tokenizer type which isn't supported by this crate.
+    #[serde(other)]
+    Unsupported,
 }
 
 #[derive(Deserialize)]
 pub(crate) struct WordPieceModel {
     /// Mapping from token text to token ID.
     pub vocab: HashMap<String, TokenId>,
+
+    /// Token used for words that are not in the vocabulary.
+    pub unk_token: Option<String>,
+
+    /// Prefix of tokens which continue a word (usually `##`).
+    pub continuing_subword_prefix: Option<String>,
+
+    /// Maximum length of a word in characters. Longer words are replaced by
+    /// `unk_token`.
+    pub max_input_chars_per_word: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct WordLevelModel {
+    /// Mapping from token text to token ID.
+    pub vocab: HashMap<String, TokenId>,
+
+    /// Token used for words that are not in the vocabulary.
+    pub unk_token: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -40,6 +230,21 @@ pub(crate) struct BpeModel {
 
     /// List of `<token_a> [SPACE] <token_b>` containing tokens to merge.
     pub merges: Vec<String>,
+
+    /// Token used for inputs that are not in the vocabulary.
+    pub unk_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct UnigramModel {
+    /// List of `(token, score)` entries, where the index is the token ID.
+    pub vocab: Vec<(String, f32)>,
+
+    /// ID of the token used for inputs that are not in the vocabulary.
+    pub unk_id: Option<TokenId>,
+
+    #[serde(default)]
+    pub byte_fallback: bool,
 }
 
 #[derive(Deserialize)]
@@ -47,9 +252,122 @@ pub(crate) struct BpeModel {
 pub(crate) enum Model {
     #[serde(rename = "BPE")]
     Bpe(BpeModel),
+    Unigram(UnigramModel),
+    WordLevel(WordLevelModel),
     WordPiece(WordPieceModel),
 }
 
+#[derive(Deserialize, Default)]
+pub(crate) enum TruncationDirection {
+    Left,
+    #[default]
+    Right,
+}
+
+#[derive(Deserialize)]
+pub(crate) enum TruncationStrategy {
+    LongestFirst,
+    OnlyFirst,
+    OnlySecond,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct Truncation {
+    #[serde(default)]
+    pub direction: TruncationDirection,
+    pub max_length: usize,
+    pub strategy: TruncationStrategy,
+}
+
+#[derive(Deserialize)]
+pub(crate) enum PaddingStrategy {
+    BatchLongest,
+    Fixed(usize),
+}
+
+#[derive(Deserialize, Default)]
+pub(crate) enum PaddingDirection {
+    Left,
+    #[default]
+    Right,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct Padding {
+    pub strategy: PaddingStrategy,
+    #[serde(default)]
+    pub direction: PaddingDirection,
+    pub pad_to_multiple_of: Option<usize>,
+    pub pad_id: TokenId,
+    #[serde(default)]
+    pub pad_type_id: usize,
+    pub pad_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub(crate) enum SequenceId {
+    A,
+    B,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct TemplateSequence {
+    pub id: SequenceId,
+    #[serde(default)]
+    pub type_id: usize,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct TemplateSpecialToken {
+    pub id: String,
+    #[serde(default)]
+    pub type_id: usize,
+}
+
+#[derive(Deserialize)]
+pub(crate) enum TemplatePiece {
+    Sequence(TemplateSequence),
+    SpecialToken(TemplateSpecialToken),
+}
+
+#[derive(Deserialize)]
+pub(crate) struct TemplateProcessing {
+    pub single: Vec<TemplatePiece>,
+    pub pair: Vec<TemplatePiece>,
+}
+
+/// Configuration for post-processors which add `cls` before the input and
+/// `sep` after each sequence.
+#[derive(Deserialize)]
+pub(crate) struct ClsSepProcessing {
+    /// Tuple of `(token, id)`.
+    pub sep: (String, TokenId),
+
+    /// Tuple of `(token, id)`.
+    pub cls: (String, TokenId),
+}
+
+#[derive(Deserialize)]
+pub(crate) struct SequenceProcessing {
+    pub processors: Vec<WithType<PostProcessor>>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+pub(crate) enum PostProcessor {
+    BertProcessing(ClsSepProcessing),
+    RobertaProcessing(ClsSepProcessing),
+    TemplateProcessing(TemplateProcessing),
+    Sequence(SequenceProcessing),
+
+    /// Post-processor which adjusts offsets. This doesn't add special tokens.
+    ByteLevel,
+
+    /// A post-processor type which isn't supported by this crate.
+    #[serde(other)]
+    Unsupported,
+}
+
 /// Structure of the `tokenizers.json` files generated by Hugging Face
 /// tokenizers [^1].
 ///
@@ -57,11 +375,71 @@ pub(crate) enum Model {
 #[derive(Deserialize)]
 pub(crate) struct TokenizerJson {
     pub added_tokens: Option<Vec<AddedToken>>,
-    pub normalizer: Option<Normalizer>,
+    /// Normalizer configuration. This is kept as JSON so that it can be
+    /// matched against custom normalizer types before being parsed as a
+    /// [Normalizer].
+    pub normalizer: Option<serde_json::Value>,
+
+    /// Pre-tokenizer configuration. This is kept as JSON so that it can be
+    /// matched against custom pre-tokenizer types before being parsed as a
+    /// [PreTokenizer].
+    pub pre_tokenizer: Option<serde_json::Value>,
+
     pub model: Model,
+    pub decoder: Option<WithType<Decoder>>,
+    pub truncation: Option<Truncation>,
+    pub padding: Option<Padding>,
+    pub post_processor: Option<WithType<PostProcessor>>,
 }
 
 /// Deserialize a `tokenizer.json` file.
 pub fn from_json(json: &str) -> Result<TokenizerJson, serde_json::Error> {
     serde_json::from_str(json)
 }
+
+/// Deserialize a `tokenizer.json` file from UTF-8 bytes.
+pub fn from_slice(json: &[u8]) -> Result<TokenizerJson, serde_json::Error> {
+    serde_json::from_slice(json)
+}
+
+/// Deserialize a `tokenizer.json` file from a reader.
+pub fn from_reader<R: std::io::Read>(reader: R) -> Result<TokenizerJson, serde_json::Error> {
+    serde_json::from_reader(reader)
+}
+
+/// Entry in a `special_tokens_map.json` file. This is either the token
+/// string or an object with the token string in a `content` field.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub(crate) enum SpecialTokenValue {
+    Content(String),
+    Token { content: String },
+}
+
+impl SpecialTokenValue {
+    pub fn content(&self) -> &str {
+        match self {
+            Self::Content(content) => content,
+            Self::Token { content } => content,
+        }
+    }
+}
+
+/// Structure of the `special_tokens_map.json` files generated by Hugging
+/// Face Transformers.
+#[derive(Deserialize)]
+pub(crate) struct SpecialTokensMap {
+    pub bos_token: Option<SpecialTokenValue>,
+    pub eos_token: Option<SpecialTokenValue>,
+    pub unk_token: Option<SpecialTokenValue>,
+    pub pad_token: Option<SpecialTokenValue>,
+    pub cls_token: Option<SpecialTokenValue>,
+    pub sep_token: Option<SpecialTokenValue>,
+    pub mask_token: Option<SpecialTokenValue>,
+    #[serde(default)]
+    pub additional_special_tokens: Vec<SpecialTokenValue>,
+}
+
+pub fn special_tokens_map_from_json(json: &str) -> Result<SpecialTokensMap, serde_json::Error> {
+    serde_json::from_str(json)
+}
@@ -0,0 +1,849 @@
+//! Pre-tokenizers which split text into pieces before encoding.
+//!
+//! Pre-tokenizers run after added tokens have been matched in the input and
+//! before the [Encoder](super::Encoder). They split the text into pieces,
+//! such as words, which are encoded separately, and may also transform the
+//! text, for example by marking the start of words.
+//!
+//! These mirror the pre-tokenizers in the `pre_tokenizer` section of Hugging
+//! Face `tokenizer.json` files.
+
+use std::ops::Range;
+use std::sync::OnceLock;
+
+use regex_syntax::hir::{Class, HirKind};
+
+use super::TokenizerError;
+use crate::split::SplitRegex;
+
+/// A piece of text produced by a [PreTokenizer].
+#[derive(Clone, Debug, PartialEq)]
+pub struct PreToken {
+    /// Text of the piece. This may differ from the corresponding text in the
+    /// input if the pre-tokenizer transforms it.
+    pub text: String,
+
+    /// Byte offset in the input of the character which produced each byte
+    /// of `text`.
+    pub offsets: Vec<usize>,
+}
+
+impl PreToken {
+    /// Create a piece which is the unmodified text at byte offset `start` in
+    /// the input.
+    pub fn new(text: &str, start: usize) -> PreToken {
+        PreToken {
+            text: text.to_string(),
+            offsets: text
+                .char_indices()
+                .flat_map(|(i, ch)| std::iter::repeat_n(start + i, ch.len_utf8()))
+                .collect(),
+        }
+    }
+
+    /// Return the part of this piece within a byte range of [text](Self::text).
+    pub fn slice(&self, range: Range<usize>) -> PreToken {
+        PreToken {
+            text: self.text[range.clone()].to_string(),
+            offsets: self.offsets[range].to_vec(),
+        }
+    }
+}
+
+/// A PreTokenizer splits text into pieces which are encoded separately.
+///
+/// Pre-tokenizers operate on a list of pieces rather than a single string,
+/// so that they can be chained together using [Sequence].
+pub trait PreTokenizer: Send + Sync {
+    /// Split each piece in `pieces` into zero or more smaller pieces.
+    fn pre_tokenize_chain(&self, pieces: Vec<PreToken>) -> Result<Vec<PreToken>, TokenizerError>;
+
+    /// Split a string into pieces.
+    fn pre_tokenize(&self, text: &str) -> Result<Vec<PreToken>, TokenizerError> {
+        self.pre_tokenize_chain(vec![PreToken::new(text, 0)])
+    }
+}
+
+/// Specifies when [Metaspace] adds a replacement character to the start of
+/// text.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum PrependScheme {
+    /// Add the replacement character to the start of every piece of text
+    /// that is pre-tokenized.
+    #[default]
+    Always,
+
+    /// Only add the replacement character to the start of the input, not
+    /// to text that follows an added token.
+    First,
+
+    /// Never add the replacement character.
+    Never,
+}
+
+/// Pre-tokenizer which replaces spaces with a visible character and splits
+/// text into words which start with that character.
+///
+/// This is used by models which use SentencePiece tokenizers, such as T5 and
+/// Llama, where the replacement character is `▁` (U+2581).
+#[derive(Clone, Debug)]
+pub struct Metaspace {
+    replacement: char,
+    prepend_scheme: PrependScheme,
+    split: bool,
+}
+
+impl Metaspace {
+    /// Create a pre-tokenizer which replaces spaces with `replacement`.
+    ///
+    /// `prepend_scheme` specifies when `replacement` is added to the start of
+    /// the text, if it is not already present. If `split` is true, the text
+    /// is split before each occurrence of `replacement`.
+    pub fn new(replacement: char, prepend_scheme: PrependScheme, split: bool) -> Metaspace {
+        Metaspace {
+            replacement,
+            prepend_scheme,
+            split,
+        }
+    }
+}
+
+impl Default for Metaspace {
+    fn default() -> Metaspace {
+        Metaspace::new('▁', PrependScheme::Always, true)
+    }
+}
+
+impl PreTokenizer for Metaspace {
+    fn pre_tokenize_chain(&self, pieces: Vec<PreToken>) -> Result<Vec<PreToken>, TokenizerError> {
+        let mut output = Vec::with_capacity(pieces.len());
+        let replacement_len = self.replacement.len_utf8();
+
+        for piece in pieces {
+            if piece.text.is_empty() {
+                continue;
+            }
+
+            let mut text = String::with_capacity(piece.text.len());
+            let mut offsets = Vec::with_capacity(piece.offsets.len());
+
+            let prepend = match self.prepend_scheme {
+                PrependScheme::Always => true,
+                PrependScheme::First => piece.offsets.first() == Some(&0),
+                PrependScheme::Never => false,
+            };
+            if prepend && !piece.text.starts_with([' ', self.replacement]) {
+                text.push(self.replacement);
+                offsets.extend(std::iter::repeat_n(piece.offsets[0], replacement_len));
+            }
+
+            for (i, ch) in piece.text.char_indices() {
+                let ch = if ch == ' ' { self.replacement } else { ch };
+                text.push(ch);
+                offsets.extend(std::iter::repeat_n(piece.offsets[i], ch.len_utf8()));
+            }
+            let piece = PreToken { text, offsets };
+
+            if !self.split {
+                output.push(piece);
+                continue;
+            }
+
+            let mut word_start = 0;
+            for (i, _) in piece.text.match_indices(self.replacement) {
+                if i > word_start {
+                    output.push(piece.slice(word_start..i));
+                }
+                word_start = i;
+            }
+            output.push(piece.slice(word_start..piece.text.len()));
+        }
+
+        Ok(output)
+    }
+}
+
+/// Specifies how [Split] handles the matches of its pattern.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SplitDelimiterBehavior {
+    /// Remove matches from the output.
+    Removed,
+
+    /// Output each match as a separate piece.
+    #[default]
+    Isolated,
+
+    /// Append each match to the preceding piece.
+    MergedWithPrevious,
+
+    /// Prepend each match to the following piece.
+    MergedWithNext,
+
+    /// Output each run of consecutive matches as a single piece.
+    Contiguous,
+}
+
+#[derive(Clone, Debug)]
+enum Pattern {
+    String(String),
+    Regex(SplitRegex),
+}
+
+/// Pre-tokenizer which splits text using a string or regex pattern.
+///
+/// Regex patterns use the syntax supported by
+/// [fancy_regex](https://crates.io/crates/fancy-regex). This includes
+/// look-around assertions and possessive quantifiers, which are used by the
+/// patterns of GPT-4, Llama 3 and other models (see
+/// [patterns](super::patterns)). Patterns that only use look-around for the
+/// `\s+(?!\S)` idiom of GPT-2 style patterns are matched without
+/// backtracking, which is much faster.
+#[derive(Clone, Debug)]
+pub struct Split {
+    pattern: Pattern,
+    behavior: SplitDelimiterBehavior,
+    invert: bool,
+}
+
+impl Split {
+    /// Create a pre-tokenizer which splits text at occurrences of the string
+    /// `pattern`.
+    pub fn new(pattern: &str, behavior: SplitDelimiterBehavior) -> Split {
+        Split {
+            pattern: Pattern::String(pattern.to_string()),
+            behavior,
+            invert: false,
+        }
+    }
+
+    /// Create a pre-tokenizer which splits text at matches of the regex
+    /// `pattern`.
+    pub fn regex(
+        pattern: &str,
+        behavior: SplitDelimiterBehavior,
+    ) -> Result<Split, Box<fancy_regex::Error>> {
+        let regex = SplitRegex::new(pattern)?;
+        Ok(Split {
+            pattern: Pattern::Regex(regex),
+            behavior,
+            invert: false,
+        })
+    }
+
+    /// Swap the roles of matches and the text between them, so that the
+    /// text between matches is treated as the delimiter.
+    pub fn with_invert(mut self, invert: bool) -> Split {
+        self.invert = invert;
+        self
+    }
+
+    /// Split `text` into ranges, each of which is labeled with whether it
+    /// matches the pattern.
+    fn find_matches(&self, text: &str) -> Result<Vec<(Range<usize>, bool)>, TokenizerError> {
+        let mut ranges = Vec::new();
+        let mut last_end = 0;
+        let mut push_match = |range: Range<usize>| {
+            if range.is_empty() {
+                return;
+            }
+            if range.start > last_end {
+                ranges.push((last_end..range.start, self.invert));
+            }
+            last_end = range.end;
+            ranges.push((range, !self.invert));
+        };
+
+        match &self.pattern {
+            Pattern::String(pattern) if pattern.is_empty() => {}
+            Pattern::String(pattern) => {
+                for (start, matched) in text.match_indices(pattern.as_str()) {
+                    push_match(start..start + matched.len());
+                }
+            }
+            Pattern::Regex(regex) => {
+                for m in regex.find_iter(text) {
+                    push_match(m.map_err(TokenizerError::RegexSplitFailed)?);
+                }
+            }
+        }
+
+        if last_end < text.len() {
+            ranges.push((last_end..text.len(), self.invert));
+        }
+        Ok(ranges)
+    }
+}
+
+impl PreTokenizer for Split {
+    fn pre_tokenize_chain(&self, pieces: Vec<PreToken>) -> Result<Vec<PreToken>, TokenizerError> {
+        let mut output = Vec::with_capacity(pieces.len());
+
+        for piece in pieces {
+            let mut ranges: Vec<Range<usize>> = Vec::new();
+            let mut prev_match = false;
+
+            for (range, is_match) in self.find_matches(&piece.text)? {
+                let merge = match self.behavior {
+                    SplitDelimiterBehavior::Removed if is_match => continue,
+                    SplitDelimiterBehavior::Removed | SplitDelimiterBehavior::Isolated => false,
+                    SplitDelimiterBehavior::MergedWithPrevious => is_match && !prev_match,
+                    SplitDelimiterBehavior::MergedWithNext => !is_match && prev_match,
+                    SplitDelimiterBehavior::Contiguous => is_match && prev_match,
+                };
+                match ranges.last_mut() {
+                    Some(prev) if merge => prev.end = range.end,
+                    _ => ranges.push(range),
+                }
+                prev_match = is_match;
+            }
+
+            output.extend(ranges.into_iter().map(|range| piece.slice(range)));
+        }
+
+        Ok(output)
+    }
+}
+
+/// Pre-tokenizer which splits text where the Unicode script changes, such as
+/// between Latin and Chinese text.
+///
+/// Hiragana and Katakana are treated as part of the Han script, so that
+/// Japanese text is not split between kana and kanji. Spaces are compatible
+/// with any script and are kept with the preceding text.
+#[derive(Clone, Debug, Default)]
+pub struct UnicodeScripts {}
+
+impl UnicodeScripts {
+    pub fn new() -> UnicodeScripts {
+        UnicodeScripts {}
+    }
+}
+
+impl PreTokenizer for UnicodeScripts {
+    fn pre_tokenize_chain(&self, pieces: Vec<PreToken>) -> Result<Vec<PreToken>, TokenizerError> {
+        let scripts = ScriptTable::get();
+        let mut output = Vec::with_capacity(pieces.len());
+
+        for piece in pieces {
+            let mut prev_script = None;
+            let mut start = 0;
+            for (i, ch) in piece.text.char_indices() {
+                let Some(script) = scripts.script(ch) else {
+                    continue;
+                };
+                if prev_script.is_some_and(|prev| prev != script) {
+                    output.push(piece.slice(start..i));
+                    start = i;
+                }
+                prev_script = Some(script);
+            }
+            if start < piece.text.len() {
+                output.push(piece.slice(start..piece.text.len()));
+            }
+        }
+
+        Ok(output)
+    }
+}
+
+/// Names of Unicode scripts, as accepted by `\p{Script=...}` in regexes.
+const SCRIPT_NAMES: &[&str] = &[
+    "Adlam",
+    "Ahom",
+    "Anatolian_Hieroglyphs",
+    "Arabic",
+    "Armenian",
+    "Avestan",
+    "Balinese",
+    "Bamum",
+    "Bassa_Vah",
+    "Batak",
+    "Bengali",
+    "Bhaiksuki",
+    "Bopomofo",
+    "Brahmi",
+    "Braille",
+    "Buginese",
+    "Buhid",
+    "Canadian_Aboriginal",
+    "Carian",
+    "Caucasian_Albanian",
+    "Chakma",
+    "Cham",
+    "Cherokee",
+    "Chorasmian",
+    "Common",
+    "Coptic",
+    "Cuneiform",
+    "Cypriot",
+    "Cypro_Minoan",
+    "Cyrillic",
+    "Deseret",
+    "Devanagari",
+    "Dives_Akuru",
+    "Dogra",
+    "Duployan",
+    "Egyptian_Hieroglyphs",
+    "Elbasan",
+    "Elymaic",
+    "Ethiopic",
+    "Georgian",
+    "Glagolitic",
+    "Gothic",
+    "Grantha",
+    "Greek",
+    "Gujarati",
+    "Gunjala_Gondi",
+    "Gurmukhi",
+    "Han",
+    "Hangul",
+    "Hanifi_Rohingya",
+    "Hanunoo",
+    "Hatran",
+    "Hebrew",
+    "Hiragana",
+    "Imperial_Aramaic",
+    "Inherited",
+    "Inscriptional_Pahlavi",
+    "Inscriptional_Parthian",
+    "Javanese",
+    "Kaithi",
+    "Kannada",
+    "Katakana",
+    "Kawi",
+    "Kayah_Li",
+    "Kharoshthi",
+    "Khitan_Small_Script",
+    "Khmer",
+    "Khojki",
+    "Khudawadi",
+    "Lao",
+    "Latin",
+    "Lepcha",
+    "Limbu",
+    "Linear_A",
+    "Linear_B",
+    "Lisu",
+    "Lycian",
+    "Lydian",
+    "Mahajani",
+    "Makasar",
+    "Malayalam",
+    "Mandaic",
+    "Manichaean",
+    "Marchen",
+    "Masaram_Gondi",
+    "Medefaidrin",
+    "Meetei_Mayek",
+    "Mende_Kikakui",
+    "Meroitic_Cursive",
+    "Meroitic_Hieroglyphs",
+    "Miao",
+    "Modi",
+    "Mongolian",
+    "Mro",
+    "Multani",
+    "Myanmar",
+    "Nabataean",
+    "Nag_Mundari",
+    "Nandinagari",
+    "New_Tai_Lue",
+    "Newa",
+    "Nko",
+    "Nushu",
+    "Nyiakeng_Puachue_Hmong",
+    "Ogham",
+    "Ol_Chiki",
+    "Old_Hungarian",
+    "Old_Italic",
+    "Old_North_Arabian",
+    "Old_Permic",
+    "Old_Persian",
+    "Old_Sogdian",
+    "Old_South_Arabian",
+    "Old_Turkic",
+    "Old_Uyghur",
+    "Oriya",
+    "Osage",
+    "Osmanya",
+    "Pahawh_Hmong",
+    "Palmyrene",
+    "Pau_Cin_Hau",
+    "Phags_Pa",
+    "Phoenician",
+    "Psalter_Pahlavi",
+    "Rejang",
+    "Runic",
+    "Samaritan",
+    "Saurashtra",
+    "Sharada",
+    "Shavian",
+    "Siddham",
+    "SignWriting",
+    "Sinhala",
+    "Sogdian",
+    "Sora_Sompeng",
+    "Soyombo",
+    "Sundanese",
+    "Syloti_Nagri",
+    "Syriac",
+    "Tagalog",
+    "Tagbanwa",
+    "Tai_Le",
+    "Tai_Tham",
+    "Tai_Viet",
+    "Takri",
+    "Tamil",
+    "Tangsa",
+    "Tangut",
+    "Telugu",
+    "Thaana",
+    "Thai",
+    "Tibetan",
+    "Tifinagh",
+    "Tirhuta",
+    "Toto",
+    "Ugaritic",
+    "Vai",
+    "Vithkuqi",
+    "Wancho",
+    "Warang_Citi",
+    "Yezidi",
+    "Yi",
+    "Zanabazar_Square",
+];
+
+/// Index into [SCRIPT_NAMES] used for characters which have no assigned
+/// script.
+const UNKNOWN_SCRIPT: u8 = SCRIPT_NAMES.len() as u8;
+
+/// Lookup table mapping characters to Unicode scripts.
+///
+/// The script data is taken from the Unicode tables in `regex-syntax`, which
+/// `fancy-regex` already depends on.
+struct ScriptTable {
+    /// Non-overlapping character ranges sorted by start, and the index in
+    /// [SCRIPT_NAMES] of the script they belong to.
+    ranges: Vec<(char, char, u8)>,
+
+    /// Index of the Han script.
+    han: u8,
+}
+
+impl ScriptTable {
+    /// Return the shared script table, building it on first use.
+    fn get() -> &'static ScriptTable {
+        static TABLE: OnceLock<ScriptTable> = OnceLock::new();
+        TABLE.get_or_init(ScriptTable::new)
+    }
+
+    fn new() -> ScriptTable {
+        let script_index = |name| {
+            SCRIPT_NAMES
+                .iter()
+                .position(|n| *n == name)
+                .expect("script should be listed") as u8
+        };
+        let han = script_index("Han");
+
+        // Kana are merged with Han so that Japanese text is not split.
+        let (hiragana, katakana) = (script_index("Hiragana"), script_index("Katakana"));
+
+        let mut ranges = Vec::new();
+        for (i, name) in SCRIPT_NAMES.iter().enumerate() {
+            let mut index = i as u8;
+            if index == hiragana || index == katakana {
+                index = han;
+            }
+
+            let hir = regex_syntax::Parser::new()
+                .parse(&format!(r"\p{{Script={}}}", name))
+                .expect("script name should be valid");
+            let HirKind::Class(Class::Unicode(class)) = hir.kind() else {
+                panic!("script {} should parse as a class", name);
+            };
+            ranges.extend(class.iter().map(|r| (r.start(), r.end(), index)));
+        }
+        ranges.sort_by_key(|(start, _end, _script)| *start);
+
+        ScriptTable { ranges, han }
+    }
+
+    /// Return the index of the script that `ch` belongs to, or `None` if
+    /// it is compatible with all scripts.
+    fn script(&self, ch: char) -> Option<u8> {
+        match ch {
+            ' ' => return None,
+            // Katakana-Hiragana prolonged sound mark. This is in the "Common"
+            // script but is only used in Japanese text.
+            '\u{30FC}' => return Some(self.han),
+            _ => {}
+        }
+
+        let idx = self
+            .ranges
+            .partition_point(|(start, _end, _script)| *start <= ch);
+        let script = idx
+            .checked_sub(1)
+            .map(|idx| self.ranges[idx])
+            .filter(|(_start, end, _script)| ch <= *end)
+            .map(|(_start, _end, script)| script)
+            .unwrap_or(UNKNOWN_SCRIPT);
+        Some(script)
+    }
+}
+
+/// Pre-tokenizer which applies a sequence of pre-tokenizers in order.
+pub struct Sequence {
+    pre_tokenizers: Vec<Box<dyn PreTokenizer>>,
+}
+
+impl Sequence {
+    pub fn new(pre_tokenizers: Vec<Box<dyn PreTokenizer>>) -> Sequence {
+        Sequence { pre_tokenizers }
+    }
+}
+
+impl PreTokenizer for Sequence {
+    fn pre_tokenize_chain(
+        &self,
+        mut pieces: Vec<PreToken>,
+    ) -> Result<Vec<PreToken>, TokenizerError> {
+        for pre_tokenizer in &self.pre_tokenizers {
+            pieces = pre_tokenizer.pre_tokenize_chain(pieces)?;
+        }
+        Ok(pieces)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Metaspace, PreToken, PreTokenizer, PrependScheme, Sequence, Split, SplitDelimiterBehavior,
+        UnicodeScripts,
+    };
+    use crate::tokenizers::patterns;
+
+    fn texts(pieces: &[PreToken]) -> Vec<&str> {
+        pieces.iter().map(|p| p.text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_metaspace() {
+        struct Case<'a> {
+            text: &'a str,
+            prepend_scheme: PrependScheme,
+            split: bool,
+            expected: &'a [&'a str],
+        }
+
+        let cases = [
+            Case {
+                text: "Hello world",
+                prepend_scheme: PrependScheme::Always,
+                split: true,
+                expected: &["▁Hello", "▁world"],
+            },
+            Case {
+                text: "Hello  world ",
+                prepend_scheme: PrependScheme::Never,
+                split: true,
+                expected: &["Hello", "▁", "▁world", "▁"],
+            },
+            Case {
+                text: "Hello world",
+                prepend_scheme: PrependScheme::Always,
+                split: false,
+                expected: &["▁Hello▁world"],
+            },
+            // Text which already starts with a space doesn't get another one.
+            Case {
+                text: " Hello",
+                prepend_scheme: PrependScheme::Always,
+                split: true,
+                expected: &["▁Hello"],
+            },
+            Case {
+                text: "",
+                prepend_scheme: PrependScheme::Always,
+                split: true,
+                expected: &[],
+            },
+        ];
+
+        for Case {
+            text,
+            prepend_scheme,
+            split,
+            expected,
+        } in cases
+        {
+            let pre_tokenizer = Metaspace::new('▁', prepend_scheme, split);
+            let pieces = pre_tokenizer.pre_tokenize(text).unwrap();
+            assert_eq!(texts(&pieces), expected);
+        }
+
+        // Offsets map back to the input. The prepended character maps to the
+        // start of the text.
+        let pieces = Metaspace::default().pre_tokenize("ab c").unwrap();
+        assert_eq!(pieces[0].offsets, &[0, 0, 0, 0, 1]);
+        assert_eq!(pieces[1].offsets, &[2, 2, 2, 3]);
+
+        // With `PrependScheme::First`, only text at the start of the input
+        // gets a prefix.
+        let pre_tokenizer = Metaspace::new('▁', PrependScheme::First, true);
+        let pieces = pre_tokenizer
+            .pre_tokenize_chain(vec![PreToken::new("ab", 0), PreToken::new("cd", 5)])
+            .unwrap();
+        assert_eq!(texts(&pieces), &["▁ab", "cd"]);
+    }
+
+    #[test]
+    fn test_sequence() {
+        let pre_tokenizer = Sequence::new(vec![
+            Box::new(Metaspace::new('▁', PrependScheme::Never, false)),
+            Box::new(Metaspace::new('_', PrependScheme::Always, true)),
+        ]);
+        let pieces = pre_tokenizer.pre_tokenize("a b").unwrap();
+        assert_eq!(texts(&pieces), &["_a▁b"]);
+    }
+
+    #[test]
+    fn test_split() {
+        struct Case<'a> {
+            behavior: SplitDelimiterBehavior,
+            expected: &'a [&'a str],
+        }
+
+        let cases = [
+            Case {
+                behavior: SplitDelimiterBehavior::Removed,
+                expected: &["the", "final", "countdown"],
+            },
+            Case {
+                behavior: SplitDelimiterBehavior::Isolated,
+                expected: &["the", "-", "final", "-", "-", "countdown"],
+            },
+            Case {
+                behavior: SplitDelimiterBehavior::MergedWithPrevious,
+                expected: &["the-", "final-", "-", "countdown"],
+            },
+            Case {
+                behavior: SplitDelimiterBehavior::MergedWithNext,
+                expected: &["the", "-final", "-", "-countdown"],
+            },
+            Case {
+                behavior: SplitDelimiterBehavior::Contiguous,
+                expected: &["the", "-", "final", "--", "countdown"],
+            },
+        ];
+
+        for Case { behavior, expected } in cases {
+            let split = Split::new("-", behavior);
+            let pieces = split.pre_tokenize("the-final--countdown").unwrap();
+            assert_eq!(texts(&pieces), expected, "mismatch for {:?}", behavior);
+
+            let split = Split::regex("-", behavior).unwrap();
+            let pieces = split.pre_tokenize("the-final--countdown").unwrap();
+            assert_eq!(texts(&pieces), expected, "mismatch for {:?}", behavior);
+        }
+
+        // With `invert`, the text between matches is the delimiter.
+        let split = Split::regex(r"\w+", SplitDelimiterBehavior::Removed)
+            .unwrap()
+            .with_invert(true);
+        let pieces = split.pre_tokenize("one, two").unwrap();
+        assert_eq!(texts(&pieces), &["one", "two"]);
+        assert_eq!(pieces[1].offsets, &[5, 6, 7]);
+    }
+
+    #[test]
+    fn test_split_lookahead_and_possessive() {
+        struct Case<'a> {
+            pattern: &'a str,
+            text: &'a str,
+            expected: &'a [&'a str],
+        }
+
+        let cases = [
+            // `\s+(?!\S)` leaves the last space to be attached to the next
+            // word.
+            Case {
+                pattern: patterns::GPT2,
+                text: "a   b",
+                expected: &["a", "  ", " b"],
+            },
+            // The `cl100k_base` pattern uses possessive quantifiers.
+            Case {
+                pattern: patterns::CL100K,
+                text: "I'm 12345!!! ok",
+                expected: &["I", "'m", " ", "123", "45", "!!!", " ok"],
+            },
+            Case {
+                pattern: patterns::CL100K,
+                text: "x\n\n  y",
+                expected: &["x", "\n\n", " ", " y"],
+            },
+        ];
+
+        for Case {
+            pattern,
+            text,
+            expected,
+        } in cases
+        {
+            let split = Split::regex(pattern, SplitDelimiterBehavior::Isolated).unwrap();
+            let pieces = split.pre_tokenize(text).unwrap();
+            assert_eq!(texts(&pieces), expected, "mismatch for {:?}", text);
+        }
+    }
+
+    #[test]
+    fn test_unicode_scripts() {
+        struct Case<'a> {
+            text: &'a str,
+            expected: &'a [&'a str],
+        }
+
+        let cases = [
+            Case {
+                text: "Hello world",
+                expected: &["Hello world"],
+            },
+            Case {
+                text: "Hello 世界",
+                expected: &["Hello ", "世界"],
+            },
+            // Kana and kanji are not split.
+            Case {
+                text: "日本語のテキストです",
+                expected: &["日本語のテキストです"],
+            },
+            Case {
+                text: "ラーメンとramen",
+                expected: &["ラーメンと", "ramen"],
+            },
+            // Punctuation and digits are in the "Common" script.
+            Case {
+                text: "abc, 123",
+                expected: &["abc", ", 123"],
+            },
+            Case {
+                text: "Привет, мир",
+                expected: &["Привет", ", ", "мир"],
+            },
+            Case {
+                text: "",
+                expected: &[],
+            },
+        ];
+
+        for Case { text, expected } in cases {
+            let pieces = UnicodeScripts::new().pre_tokenize(text).unwrap();
+            assert_eq!(texts(&pieces), expected, "mismatch for {}", text);
+        }
+
+        let pieces = UnicodeScripts::new().pre_tokenize("ab日本").unwrap();
+        assert_eq!(pieces[1].offsets, &[2, 2, 2, 5, 5, 5]);
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use super::{Encoder, TokenId, TokenizerError};
+use crate::normalizer::Normalizer;
+
+/// Mapping between the token IDs of a tokenizer and those of a version with a
+/// reduced vocabulary.
+///
+/// This is returned by [Tokenizer::prune_vocab](super::Tokenizer::prune_vocab).
+/// Tokens in the reduced vocabulary are numbered from zero, in the same
+/// order as their original IDs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VocabMapping {
+    new_to_old: Vec<TokenId>,
+    old_to_new: HashMap<TokenId, TokenId>,
+}
+
+impl VocabMapping {
+    /// Create a mapping which keeps the tokens in `keep`.
+    pub(crate) fn new(keep: impl IntoIterator<Item = TokenId>) -> VocabMapping {
+        let mut new_to_old: Vec<TokenId> = keep.into_iter().collect();
+        new_to_old.sort_unstable();
+        new_to_old.dedup();
+        let old_to_new = new_to_old
+            .iter()
+            .enumerate()
+            .map(|(new_id, &old_id)| (old_id, new_id as TokenId))
+            .collect();
+        VocabMapping {
+            new_to_old,
+            old_to_new,
+        }
+    }
+
+    /// Return the number of tokens in the reduced vocabulary.
+    pub fn len(&self) -> usize {
+        self.new_to_old.len()
+    }
+
+    /// Return true if the reduced vocabulary is empty.
+    pub fn is_empty(&self) -> bool {
+        self.new_to_old.is_empty()
+    }
+
+    /// Return the original ID of each token in the reduced vocabulary.
+    ///
+    /// These are the indices of the rows to keep when reducing a model's
+    /// embedding or output matrices, eg. using a `Gather` operation.
+    pub fn new_to_old(&self) -> &[TokenId] {
+        &self.new_to_old
+    }
+
+    /// Return the ID in the reduced vocabulary of the token with original ID
+    /// `id`, or `None` if the token was removed.
+    pub fn old_to_new(&self, id: TokenId) -> Option<TokenId> {
+        self.old_to_new.get(&id).copied()
+    }
+
+    /// Return a table which maps original token IDs to IDs in the reduced
+    /// vocabulary, with `-1` for tokens that were removed.
+    ///
+    /// The table has one entry for each ID up to the largest original ID that
+    /// was kept.
+    pub fn old_to_new_table(&self) -> Vec<i32> {
+        let len = self.new_to_old.last().map_or(0, |&id| id as usize + 1);
+        let mut table = vec![-1; len];
+        for (new_id, &old_id) in self.new_to_old.iter().enumerate() {
+            table[old_id as usize] = new_id as i32;
+        }
+        table
+    }
+}
+
+/// Encoder which restricts another encoder to a subset of its vocabulary,
+/// and renumbers the tokens.
+pub(crate) struct PrunedEncoder {
+    inner: Box<dyn Encoder>,
+    mapping: VocabMapping,
+
+    /// ID in the reduced vocabulary of the token used in place of removed
+    /// tokens.
+    unk_id: Option<TokenId>,
+}
+
+impl PrunedEncoder {
+    pub fn new(
+        inner: Box<dyn Encoder>,
+        mapping: VocabMapping,
+        unk_id: Option<TokenId>,
+    ) -> PrunedEncoder {
+        PrunedEncoder {
+            inner,
+            mapping,
+            unk_id,
+        }
+    }
+
+    /// Map a token ID from the inner encoder to the reduced vocabulary.
+    fn map_token(&self, id: TokenId) -> Result<TokenId, TokenizerError> {
+        match self.mapping.old_to_new(id).or(self.unk_id) {
+            Some(new_id) => Ok(new_id),
+            None => Err(TokenizerError::MissingToken(self.inner.get_token_str(id)?)),
+        }
+    }
+
+    /// Map a token ID from the reduced vocabulary to the inner encoder.
+    fn unmap_token(&self, id: TokenId) -> Result<TokenId, TokenizerError> {
+        self.mapping
+            .new_to_old()
+            .get(id as usize)
+            .copied()
+            .ok_or(TokenizerError::InvalidTokenId(id))
+    }
+}
+
+impl Encoder for PrunedEncoder {
+    fn vocab_size(&self) -> usize {
+        self.mapping.len()
+    }
+
+    fn get_token_id(&self, token: &str) -> Result<TokenId, TokenizerError> {
+        let id = self.inner.get_token_id(token)?;
+        self.mapping
+            .old_to_new(id)
+            .ok_or_else(|| TokenizerError::MissingToken(token.to_string()))
+    }
+
+    fn get_token_str(&self, id: TokenId) -> Result<String, TokenizerError> {
+        self.inner.get_token_str(self.unmap_token(id)?)
+    }
+
+    fn encode_with_offsets(
+        &self,
+        text: &str,
+        on_token: &mut dyn FnMut(Range<usize>, TokenId),
+    ) -> Result<(), TokenizerError> {
+        let mut result = Ok(());
+        self.inner.encode_with_offsets(text, &mut |range, id| {
+            if result.is_err() {
+                return;
+            }
+            match self.map_token(id) {
+                Ok(id) => on_token(range, id),
+                Err(err) => result = Err(err),
+            }
+        })?;
+        result
+    }
+
+    fn decode(&self, ids: &[TokenId]) -> Result<String, TokenizerError> {
+        let ids = ids
+            .iter()
+            .map(|&id| self.unmap_token(id))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.inner.decode(&ids)
+    }
+
+    fn normalizer(&self) -> Option<&Normalizer> {
+        self.inner.normalizer()
+    }
+}
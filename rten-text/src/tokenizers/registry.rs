@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+use std::error::Error;
+
+use crate::normalizer::Normalizer;
+
+use super::pre_tokenizers::PreTokenizer;
+
+/// Error returned by a factory registered with a [ComponentRegistry].
+pub type CustomComponentError = Box<dyn Error + Send + Sync>;
+
+type NormalizerFactory =
+    Box<dyn Fn(&serde_json::Value) -> Result<Normalizer, CustomComponentError> + Send + Sync>;
+
+type PreTokenizerFactory = Box<
+    dyn Fn(&serde_json::Value) -> Result<Box<dyn PreTokenizer>, CustomComponentError> + Send + Sync,
+>;
+
+/// Custom normalizers and pre-tokenizers that can be referenced by a
+/// `tokenizer.json` file.
+///
+/// Each component is registered under a name which is matched against the
+/// `type` field of entries in the `normalizer` and `pre_tokenizer` sections
+/// of the file, including entries nested inside a `Sequence`. The factory
+/// receives the JSON configuration of the entry and returns the component.
+///
+/// Registered components take precedence over the built-in types, so they
+/// can also be used to replace the implementation of a built-in type.
+///
+/// Use [Tokenizer::from_json_with_registry](super::Tokenizer::from_json_with_registry)
+/// to load a tokenizer with custom components.
+#[derive(Default)]
+pub struct ComponentRegistry {
+    normalizers: HashMap<String, NormalizerFactory>,
+    pre_tokenizers: HashMap<String, PreTokenizerFactory>,
+}
+
+impl ComponentRegistry {
+    pub fn new() -> ComponentRegistry {
+        ComponentRegistry::default()
+    }
+
+    /// Register a factory for normalizers of type `type_name`.
+    ///
+    /// Custom normalization steps can be implemented using the
+    /// [Normalize](crate::normalizer::Normalize) trait and wrapped with
+    /// [Normalizer::custom].
+    pub fn with_normalizer<F>(mut self, type_name: &str, factory: F) -> ComponentRegistry
+    where
+        F: Fn(&serde_json::Value) -> Result<Normalizer, CustomComponentError>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.normalizers
+            .insert(type_name.to_string(), Box::new(factory));
+        self
+    }
+
+    /// Register a factory for pre-tokenizers of type `type_name`.
+    pub fn with_pre_tokenizer<F>(mut self, type_name: &str, factory: F) -> ComponentRegistry
+    where
+        F: Fn(&serde_json::Value) -> Result<Box<dyn PreTokenizer>, CustomComponentError>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.pre_tokenizers
+            .insert(type_name.to_string(), Box::new(factory));
+        self
+    }
+
+    /// Create a normalizer from its JSON configuration, if its type is
+    /// registered.
+    pub(crate) fn create_normalizer(
+        &self,
+        config: &serde_json::Value,
+    ) -> Option<Result<Normalizer, CustomComponentError>> {
+        let factory = self.normalizers.get(component_type(config)?)?;
+        Some(factory(config))
+    }
+
+    /// Create a pre-tokenizer from its JSON configuration, if its type is
+    /// registered.
+    pub(crate) fn create_pre_tokenizer(
+        &self,
+        config: &serde_json::Value,
+    ) -> Option<Result<Box<dyn PreTokenizer>, CustomComponentError>> {
+        let factory = self.pre_tokenizers.get(component_type(config)?)?;
+        Some(factory(config))
+    }
+}
+
+/// Return the value of the `type` field of a component's configuration.
+fn component_type(config: &serde_json::Value) -> Option<&str> {
+    config.get("type")?.as_str()
+}
@@ -0,0 +1,602 @@
+//! Support for the SentencePiece `.model` format.
+//!
+//! SentencePiece models are serialized as a `ModelProto` Protocol Buffers
+//! message, defined in
+//! [sentencepiece_model.proto](https://github.com/google/sentencepiece/blob/master/src/sentencepiece_model.proto).
+//! This module contains a minimal decoder for the subset of fields that are
+//! needed for tokenization, and the BPE variant used by SentencePiece.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::ops::Range;
+
+use super::unigram::{byte_token_ids, decode_byte_tokens};
+use super::{Encoder, TokenId, TokenizerError};
+use crate::normalizer::{NormalizedText, Normalizer};
+
+/// Value of a field in a serialized Protocol Buffers message.
+enum WireValue<'a> {
+    Varint(u64),
+    Fixed64,
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+/// Reader which iterates over the fields of a serialized Protocol Buffers
+/// message.
+struct ProtoReader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> ProtoReader<'a> {
+    fn new(buf: &'a [u8]) -> ProtoReader<'a> {
+        ProtoReader { buf }
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if len > self.buf.len() {
+            return Err("unexpected end of message".to_string());
+        }
+        let (bytes, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(bytes)
+    }
+
+    fn read_varint(&mut self) -> Result<u64, String> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.read_bytes(1)?[0];
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("varint is too long".to_string())
+    }
+
+    /// Read the next field, returning `(field_number, value)`, or `None` at
+    /// the end of the message.
+    fn next_field(&mut self) -> Result<Option<(u64, WireValue<'a>)>, String> {
+        if self.buf.is_empty() {
+            return Ok(None);
+        }
+        let key = self.read_varint()?;
+        let value = match key & 0x7 {
+            0 => WireValue::Varint(self.read_varint()?),
+            1 => {
+                self.read_bytes(8)?;
+                WireValue::Fixed64
+            }
+            2 => {
+                let len = self.read_varint()? as usize;
+                WireValue::Bytes(self.read_bytes(len)?)
+            }
+            5 => {
+                let bytes = self.read_bytes(4)?;
+                WireValue::Fixed32(u32::from_le_bytes(bytes.try_into().unwrap()))
+            }
+            wire_type => return Err(format!("unsupported wire type {}", wire_type)),
+        };
+        Ok(Some((key >> 3, value)))
+    }
+}
+
+/// Type of a piece in the vocabulary.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum PieceType {
+    Normal,
+    Unknown,
+    Control,
+    UserDefined,
+    Unused,
+    Byte,
+}
+
+/// An entry in the vocabulary.
+pub(crate) struct Piece {
+    pub piece: String,
+    pub score: f32,
+    pub kind: PieceType,
+}
+
+/// The tokenization algorithm used by a model.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ModelType {
+    Unigram,
+    Bpe,
+    Word,
+    Char,
+}
+
+/// Subset of the `TrainerSpec` message.
+pub(crate) struct TrainerSpec {
+    pub model_type: ModelType,
+    pub byte_fallback: bool,
+    pub unk_id: i32,
+    pub bos_id: i32,
+    pub eos_id: i32,
+    pub pad_id: i32,
+}
+
+impl Default for TrainerSpec {
+    fn default() -> TrainerSpec {
+        TrainerSpec {
+            model_type: ModelType::Unigram,
+            byte_fallback: false,
+            unk_id: 0,
+            bos_id: 1,
+            eos_id: 2,
+            pad_id: -1,
+        }
+    }
+}
+
+/// Subset of the `NormalizerSpec` message.
+///
+/// The `precompiled_charsmap` field, which contains Unicode normalization
+/// rules, is not supported.
+pub(crate) struct NormalizerSpec {
+    pub add_dummy_prefix: bool,
+    pub remove_extra_whitespaces: bool,
+    pub escape_whitespaces: bool,
+}
+
+impl Default for NormalizerSpec {
+    fn default() -> NormalizerSpec {
+        NormalizerSpec {
+            add_dummy_prefix: true,
+            remove_extra_whitespaces: true,
+            escape_whitespaces: true,
+        }
+    }
+}
+
+/// Subset of the `ModelProto` message.
+pub(crate) struct ModelProto {
+    pub pieces: Vec<Piece>,
+    pub trainer_spec: TrainerSpec,
+    pub normalizer_spec: NormalizerSpec,
+}
+
+fn parse_string(bytes: &[u8]) -> Result<String, String> {
+    String::from_utf8(bytes.to_vec()).map_err(|_| "invalid UTF-8 string".to_string())
+}
+
+fn parse_piece(buf: &[u8]) -> Result<Piece, String> {
+    let mut piece = Piece {
+        piece: String::new(),
+        score: 0.,
+        kind: PieceType::Normal,
+    };
+    let mut reader = ProtoReader::new(buf);
+    while let Some((field, value)) = reader.next_field()? {
+        match (field, value) {
+            (1, WireValue::Bytes(bytes)) => piece.piece = parse_string(bytes)?,
+            (2, WireValue::Fixed32(bits)) => piece.score = f32::from_bits(bits),
+            (3, WireValue::Varint(kind)) => {
+                piece.kind = match kind {
+                    1 => PieceType::Normal,
+                    2 => PieceType::Unknown,
+                    3 => PieceType::Control,
+                    4 => PieceType::UserDefined,
+                    5 => PieceType::Unused,
+                    6 => PieceType::Byte,
+                    _ => return Err(format!("unknown piece type {}", kind)),
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(piece)
+}
+
+fn parse_trainer_spec(buf: &[u8]) -> Result<TrainerSpec, String> {
+    let mut spec = TrainerSpec::default();
+    let mut reader = ProtoReader::new(buf);
+    while let Some((field, value)) = reader.next_field()? {
+        let WireValue::Varint(value) = value else {
+            continue;
+        };
+        // `int32` fields are sign-extended to 64 bits.
+        let int_value = value as i64 as i32;
+        match field {
+            3 => {
+                spec.model_type = match value {
+                    1 => ModelType::Unigram,
+                    2 => ModelType::Bpe,
+                    3 => ModelType::Word,
+                    4 => ModelType::Char,
+                    _ => return Err(format!("unknown model type {}", value)),
+                }
+            }
+            35 => spec.byte_fallback = value != 0,
+            40 => spec.unk_id = int_value,
+            41 => spec.bos_id = int_value,
+            42 => spec.eos_id = int_value,
+            43 => spec.pad_id = int_value,
+            _ => {}
+        }
+    }
+    Ok(spec)
+}
+
+fn parse_normalizer_spec(buf: &[u8]) -> Result<NormalizerSpec, String> {
+    let mut spec = NormalizerSpec::default();
+    let mut reader = ProtoReader::new(buf);
+    while let Some((field, value)) = reader.next_field()? {
+        match (field, value) {
+            (3, WireValue::Varint(value)) => spec.add_dummy_prefix = value != 0,
+            (4, WireValue::Varint(value)) => spec.remove_extra_whitespaces = value != 0,
+            (5, WireValue::Varint(value)) => spec.escape_whitespaces = value != 0,
+            _ => {}
+        }
+    }
+    Ok(spec)
+}
+
+/// Deserialize a SentencePiece model from the contents of a `.model` file.
+pub(crate) fn from_bytes(buf: &[u8]) -> Result<ModelProto, String> {
+    let mut model = ModelProto {
+        pieces: Vec::new(),
+        trainer_spec: TrainerSpec::default(),
+        normalizer_spec: NormalizerSpec::default(),
+    };
+    let mut reader = ProtoReader::new(buf);
+    while let Some((field, value)) = reader.next_field()? {
+        let WireValue::Bytes(bytes) = value else {
+            continue;
+        };
+        match field {
+            1 => model.pieces.push(parse_piece(bytes)?),
+            2 => model.trainer_spec = parse_trainer_spec(bytes)?,
+            3 => model.normalizer_spec = parse_normalizer_spec(bytes)?,
+            _ => {}
+        }
+    }
+    Ok(model)
+}
+
+/// Candidate merge of two adjacent symbols in [SentencePieceBpe].
+struct Merge {
+    score: f32,
+    left: usize,
+    right: usize,
+
+    /// Combined length of the symbols when the merge was created. Used to
+    /// detect merges that are stale because one of the symbols changed.
+    len: usize,
+}
+
+impl PartialEq for Merge {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Merge {}
+
+impl PartialOrd for Merge {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Merge {
+    /// Order merges by highest score, then leftmost position.
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score
+            .total_cmp(&other.score)
+            .then_with(|| other.left.cmp(&self.left))
+    }
+}
+
+/// Byte Pair Encoding tokenizer used by SentencePiece models such as Llama.
+///
+/// Unlike [Bpe](super::Bpe), this operates on characters rather than bytes,
+/// and there is no merge list. Instead the pair of adjacent symbols which
+/// forms the highest-scoring token in the vocabulary is repeatedly merged.
+/// Characters which are not in the vocabulary are encoded as byte tokens if
+/// byte fallback is enabled, or as the unknown token otherwise.
+pub(crate) struct SentencePieceBpe {
+    normalizer: Option<Normalizer>,
+
+    /// Token strings and scores, indexed by token ID.
+    vocab: Vec<(String, f32)>,
+    token_to_id: HashMap<String, TokenId>,
+    unk_id: Option<TokenId>,
+    byte_fallback: bool,
+}
+
+impl SentencePieceBpe {
+    pub fn new(
+        vocab: Vec<(String, f32)>,
+        normalizer: Option<Normalizer>,
+        unk_id: Option<TokenId>,
+        byte_fallback: bool,
+    ) -> SentencePieceBpe {
+        let token_to_id = vocab
+            .iter()
+            .enumerate()
+            .filter(|(_, (token, _))| !token.is_empty())
+            .map(|(id, (token, _))| (token.clone(), id as TokenId))
+            .collect();
+        SentencePieceBpe {
+            normalizer,
+            vocab,
+            token_to_id,
+            unk_id,
+            byte_fallback,
+        }
+    }
+
+    /// Split normalized text into symbols by repeatedly merging adjacent
+    /// pairs.
+    fn merge(&self, text: &str) -> Vec<Range<usize>> {
+        let mut symbols: Vec<Range<usize>> = text
+            .char_indices()
+            .map(|(i, ch)| i..i + ch.len_utf8())
+            .collect();
+        let n = symbols.len();
+        let mut prev: Vec<Option<usize>> = (0..n).map(|i| i.checked_sub(1)).collect();
+        let mut next: Vec<Option<usize>> = (0..n).map(|i| Some(i + 1).filter(|&j| j < n)).collect();
+
+        let mut merges = BinaryHeap::new();
+        let add_merge = |merges: &mut BinaryHeap<Merge>,
+                         symbols: &[Range<usize>],
+                         left: usize,
+                         right: usize| {
+            let range = symbols[left].start..symbols[right].end;
+            if let Some(&id) = self.token_to_id.get(&text[range.clone()]) {
+                merges.push(Merge {
+                    score: self.vocab[id as usize].1,
+                    left,
+                    right,
+                    len: range.len(),
+                });
+            }
+        };
+        for i in 1..n {
+            add_merge(&mut merges, &symbols, i - 1, i);
+        }
+
+        while let Some(Merge {
+            left, right, len, ..
+        }) = merges.pop()
+        {
+            let is_stale = symbols[left].is_empty()
+                || symbols[right].is_empty()
+                || next[left] != Some(right)
+                || symbols[left].len() + symbols[right].len() != len;
+            if is_stale {
+                continue;
+            }
+
+            symbols[left].end = symbols[right].end;
+            symbols[right] = 0..0;
+            next[left] = next[right];
+            if let Some(next_right) = next[right] {
+                prev[next_right] = Some(left);
+            }
+
+            if let Some(prev_left) = prev[left] {
+                add_merge(&mut merges, &symbols, prev_left, left);
+            }
+            if let Some(next_left) = next[left] {
+                add_merge(&mut merges, &symbols, left, next_left);
+            }
+        }
+
+        symbols.into_iter().filter(|s| !s.is_empty()).collect()
+    }
+}
+
+impl Encoder for SentencePieceBpe {
+    fn encode_with_offsets(
+        &self,
+        text: &str,
+        on_token: &mut dyn FnMut(Range<usize>, TokenId),
+    ) -> Result<(), TokenizerError> {
+        let mut normalized = NormalizedText::new(text);
+        if let Some(normalizer) = &self.normalizer {
+            normalized = normalized.normalize(normalizer);
+        }
+        let text = normalized.normalized();
+
+        let map_offsets = |range: Range<usize>| normalized.original_range(range);
+
+        let symbols = self.merge(text);
+        let mut i = 0;
+        while i < symbols.len() {
+            let range = symbols[i].clone();
+            i += 1;
+
+            if let Some(&id) = self.token_to_id.get(&text[range.clone()]) {
+                on_token(map_offsets(range), id);
+                continue;
+            }
+
+            if self.byte_fallback {
+                if let Some(ids) = byte_token_ids(&self.token_to_id, &text[range.clone()]) {
+                    let range = map_offsets(range);
+                    for id in ids {
+                        on_token(range.clone(), id);
+                    }
+                    continue;
+                }
+            }
+
+            // Merge consecutive unknown characters into one token.
+            let mut unk_range = range;
+            while let Some(range) = symbols
+                .get(i)
+                .filter(|r| !self.token_to_id.contains_key(&text[(*r).clone()]))
+            {
+                unk_range.end = range.end;
+                i += 1;
+            }
+            let Some(unk_id) = self.unk_id else {
+                return Err(TokenizerError::MissingToken(text[unk_range].to_string()));
+            };
+            on_token(map_offsets(unk_range), unk_id);
+        }
+
+        Ok(())
+    }
+
+    fn get_token_str(&self, id: TokenId) -> Result<String, TokenizerError> {
+        self.vocab
+            .get(id as usize)
+            .map(|(token, _score)| token.clone())
+            .ok_or(TokenizerError::InvalidTokenId(id))
+    }
+
+    fn vocab_size(&self) -> usize {
+        self.vocab.len()
+    }
+
+    fn get_token_id(&self, tok: &str) -> Result<TokenId, TokenizerError> {
+        self.token_to_id
+            .get(tok)
+            .copied()
+            .ok_or(TokenizerError::MissingToken(tok.to_string()))
+    }
+
+    fn decode(&self, ids: &[TokenId]) -> Result<String, TokenizerError> {
+        let tokens = self.get_tokens(ids)?;
+        decode_byte_tokens(tokens.iter().map(|t| t.as_str()))
+    }
+
+    fn normalizer(&self) -> Option<&Normalizer> {
+        self.normalizer.as_ref()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::{from_bytes, ModelType, PieceType, SentencePieceBpe};
+    use crate::tokenizers::Encoder;
+
+    /// Writer for serialized Protocol Buffers messages, used to create test
+    /// models.
+    #[derive(Default)]
+    pub struct ProtoWriter {
+        buf: Vec<u8>,
+    }
+
+    impl ProtoWriter {
+        fn varint(&mut self, mut value: u64) {
+            loop {
+                let byte = (value & 0x7f) as u8;
+                value >>= 7;
+                if value == 0 {
+                    self.buf.push(byte);
+                    break;
+                }
+                self.buf.push(byte | 0x80);
+            }
+        }
+
+        pub fn int(&mut self, field: u64, value: i64) -> &mut Self {
+            self.varint(field << 3);
+            self.varint(value as u64);
+            self
+        }
+
+        pub fn float(&mut self, field: u64, value: f32) -> &mut Self {
+            self.varint((field << 3) | 5);
+            self.buf.extend(value.to_bits().to_le_bytes());
+            self
+        }
+
+        pub fn bytes(&mut self, field: u64, value: &[u8]) -> &mut Self {
+            self.varint((field << 3) | 2);
+            self.varint(value.len() as u64);
+            self.buf.extend(value);
+            self
+        }
+
+        pub fn finish(&mut self) -> Vec<u8> {
+            std::mem::take(&mut self.buf)
+        }
+    }
+
+    /// Create a serialized SentencePiece model.
+    ///
+    /// `pieces` is a list of `(piece, score, type)` tuples, where `type` is
+    /// the numeric `SentencePiece.Type` value.
+    pub fn create_model(
+        pieces: &[(&str, f32, i64)],
+        model_type: i64,
+        byte_fallback: bool,
+        add_dummy_prefix: bool,
+    ) -> Vec<u8> {
+        let mut model = ProtoWriter::default();
+        for (piece, score, kind) in pieces {
+            let piece = ProtoWriter::default()
+                .bytes(1, piece.as_bytes())
+                .float(2, *score)
+                .int(3, *kind)
+                .finish();
+            model.bytes(1, &piece);
+        }
+        let trainer_spec = ProtoWriter::default()
+            .int(3, model_type)
+            .int(35, byte_fallback as i64)
+            .int(43, -1)
+            .finish();
+        model.bytes(2, &trainer_spec);
+        let normalizer_spec = ProtoWriter::default()
+            .bytes(1, b"identity")
+            .int(3, add_dummy_prefix as i64)
+            .finish();
+        model.bytes(3, &normalizer_spec);
+        model.finish()
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        let buf = create_model(
+            &[("<unk>", 0., 2), ("<s>", 0., 3), ("▁a", -1.5, 1)],
+            2,
+            true,
+            false,
+        );
+        let model = from_bytes(&buf).unwrap();
+
+        assert_eq!(model.pieces.len(), 3);
+        assert_eq!(model.pieces[0].kind, PieceType::Unknown);
+        assert_eq!(model.pieces[1].kind, PieceType::Control);
+        assert_eq!(model.pieces[2].piece, "▁a");
+        assert_eq!(model.pieces[2].score, -1.5);
+        assert_eq!(model.pieces[2].kind, PieceType::Normal);
+
+        assert_eq!(model.trainer_spec.model_type, ModelType::Bpe);
+        assert!(model.trainer_spec.byte_fallback);
+        assert_eq!(model.trainer_spec.unk_id, 0);
+        assert_eq!(model.trainer_spec.pad_id, -1);
+        assert!(!model.normalizer_spec.add_dummy_prefix);
+        assert!(model.normalizer_spec.escape_whitespaces);
+
+        // Truncated message.
+        assert!(from_bytes(&buf[..buf.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_sentencepiece_bpe() {
+        let vocab = [
+            ("<unk>", 0.),
+            ("a", -1.),
+            ("b", -1.),
+            ("c", -1.),
+            ("ab", -3.),
+            ("bc", -2.),
+        ];
+        let vocab = vocab.iter().map(|(t, s)| (t.to_string(), *s)).collect();
+        let encoder = SentencePieceBpe::new(vocab, None, Some(0), false);
+
+        // "bc" is merged first as it has a higher score than "ab".
+        assert_eq!(encoder.encode("abc").unwrap(), &[1, 5]);
+        assert_eq!(encoder.encode("abab").unwrap(), &[4, 4]);
+        assert_eq!(encoder.encode("axyb").unwrap(), &[1, 0, 2]);
+    }
+}
@@ -0,0 +1,289 @@
+use std::error::Error;
+use std::fmt;
+
+/// Errors that can occur when creating a [Template].
+#[derive(Clone, Debug, PartialEq)]
+pub enum TemplateError {
+    /// The template contains an unknown, repeated or out-of-order sequence
+    /// placeholder.
+    InvalidPlaceholder(String),
+
+    /// The template does not contain the placeholder for a sequence.
+    MissingPlaceholder,
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidPlaceholder(placeholder) => {
+                write!(f, "invalid sequence placeholder {}", placeholder)
+            }
+            Self::MissingPlaceholder => write!(f, "missing sequence placeholder"),
+        }
+    }
+}
+
+impl Error for TemplateError {}
+
+/// Identifies an input sequence in a [Template].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum SequenceId {
+    A,
+    B,
+}
+
+/// Element of a [Template].
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum TemplatePiece {
+    /// Placeholder for the tokens of an input sequence, with the token type
+    /// ID for those tokens.
+    Sequence(SequenceId, usize),
+
+    /// A special token.
+    SpecialToken(TemplateToken),
+}
+
+/// A special token in a [Template].
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct TemplateToken {
+    /// The canonical string for the token.
+    pub token: String,
+
+    /// The token type ID for the token.
+    pub type_id: usize,
+}
+
+impl TemplateToken {
+    pub fn new(token: &str, type_id: usize) -> TemplateToken {
+        TemplateToken {
+            token: token.to_string(),
+            type_id,
+        }
+    }
+}
+
+/// Special tokens which are added before, between and after the input
+/// sequences.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct TemplateParts {
+    /// Tokens before the first sequence.
+    pub prefix: Vec<TemplateToken>,
+
+    /// Tokens between the first and second sequences. This is always empty
+    /// for single-sequence templates.
+    pub middle: Vec<TemplateToken>,
+
+    /// Tokens after the last sequence.
+    pub suffix: Vec<TemplateToken>,
+
+    /// Token type IDs for the first and second sequences.
+    pub sequence_type_ids: [usize; 2],
+}
+
+impl TemplateParts {
+    fn from_pieces(
+        pieces: impl IntoIterator<Item = TemplatePiece>,
+        pair: bool,
+    ) -> Result<TemplateParts, TemplateError> {
+        let mut parts = TemplateParts::default();
+        let mut seen_a = false;
+        let mut seen_b = false;
+
+        for piece in pieces {
+            match piece {
+                TemplatePiece::Sequence(SequenceId::A, type_id) if !seen_a && !seen_b => {
+                    parts.sequence_type_ids[0] = type_id;
+                    seen_a = true;
+                }
+                TemplatePiece::Sequence(SequenceId::B, type_id) if pair && seen_a && !seen_b => {
+                    parts.sequence_type_ids[1] = type_id;
+                    seen_b = true;
+                }
+                TemplatePiece::Sequence(id, _) => {
+                    return Err(TemplateError::InvalidPlaceholder(format!("${:?}", id)));
+                }
+                TemplatePiece::SpecialToken(token) => {
+                    let part = if !seen_a {
+                        &mut parts.prefix
+                    } else if pair && !seen_b {
+                        &mut parts.middle
+                    } else {
+                        &mut parts.suffix
+                    };
+                    part.push(token);
+                }
+            }
+        }
+
+        if !seen_a || (pair && !seen_b) {
+            return Err(TemplateError::MissingPlaceholder);
+        }
+
+        Ok(parts)
+    }
+}
+
+/// Specifies the special tokens that a [Tokenizer](super::Tokenizer) adds
+/// around input sequences.
+///
+/// Templates use the syntax of the `TemplateProcessing` post-processor from
+/// Hugging Face Tokenizers. A template is a space-separated list of special
+/// tokens and the placeholders `$A` and `$B` for the first and second input
+/// sequences. For example BERT models use `[CLS] $A [SEP]` for single
+/// sequences and `[CLS] $A:0 [SEP]:0 $B:1 [SEP]:1` for pairs.
+///
+/// The `:N` suffixes specify the token type IDs returned by
+/// [Encoded::token_type_ids](super::Encoded::token_type_ids). If omitted, the
+/// type ID is zero.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template {
+    pub(crate) single: TemplateParts,
+    pub(crate) pair: TemplateParts,
+}
+
+impl Template {
+    /// Create a template from the layouts for single sequences and pairs of
+    /// sequences.
+    pub fn new(single: &str, pair: &str) -> Result<Template, TemplateError> {
+        Self::from_pieces(parse_template(single)?, parse_template(pair)?)
+    }
+
+    pub(crate) fn from_pieces(
+        single: impl IntoIterator<Item = TemplatePiece>,
+        pair: impl IntoIterator<Item = TemplatePiece>,
+    ) -> Result<Template, TemplateError> {
+        Ok(Template {
+            single: TemplateParts::from_pieces(single, false)?,
+            pair: TemplateParts::from_pieces(pair, true)?,
+        })
+    }
+
+    /// Create a template which adds an optional token before the input and
+    /// an optional token after each sequence.
+    ///
+    /// Tokens from the second sequence, and the token after it, have a type
+    /// ID of 1.
+    pub(crate) fn from_cls_sep(cls_token: Option<&str>, sep_token: Option<&str>) -> Template {
+        let tokens = |token: Option<&str>, type_id| -> Vec<TemplateToken> {
+            token
+                .into_iter()
+                .map(|t| TemplateToken::new(t, type_id))
+                .collect()
+        };
+        Template {
+            single: TemplateParts {
+                prefix: tokens(cls_token, 0),
+                middle: Vec::new(),
+                suffix: tokens(sep_token, 0),
+                sequence_type_ids: [0, 0],
+            },
+            pair: TemplateParts {
+                prefix: tokens(cls_token, 0),
+                middle: tokens(sep_token, 0),
+                suffix: tokens(sep_token, 1),
+                sequence_type_ids: [0, 1],
+            },
+        }
+    }
+}
+
+/// Parse a template string into a list of pieces.
+fn parse_template(template: &str) -> Result<Vec<TemplatePiece>, TemplateError> {
+    template
+        .split_whitespace()
+        .map(|item| {
+            let (name, type_id) = match item.rsplit_once(':') {
+                Some((name, type_id)) if !name.is_empty() => match type_id.parse() {
+                    Ok(type_id) => (name, type_id),
+                    Err(_) => (item, 0),
+                },
+                _ => (item, 0),
+            };
+            match name {
+                "$" | "$A" => Ok(TemplatePiece::Sequence(SequenceId::A, type_id)),
+                "$B" => Ok(TemplatePiece::Sequence(SequenceId::B, type_id)),
+                _ if name.starts_with('$') => {
+                    Err(TemplateError::InvalidPlaceholder(name.to_string()))
+                }
+                _ => Ok(TemplatePiece::SpecialToken(TemplateToken::new(
+                    name, type_id,
+                ))),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Template, TemplateError, TemplateParts, TemplateToken};
+
+    fn parts(
+        prefix: &[(&str, usize)],
+        middle: &[(&str, usize)],
+        suffix: &[(&str, usize)],
+        sequence_type_ids: [usize; 2],
+    ) -> TemplateParts {
+        let to_vec = |tokens: &[(&str, usize)]| {
+            tokens
+                .iter()
+                .map(|(token, type_id)| TemplateToken::new(token, *type_id))
+                .collect()
+        };
+        TemplateParts {
+            prefix: to_vec(prefix),
+            middle: to_vec(middle),
+            suffix: to_vec(suffix),
+            sequence_type_ids,
+        }
+    }
+
+    #[test]
+    fn test_template_new() {
+        let template = Template::new("<s> $A </s>", "<s> $A:0 </s> </s> $B:1 </s>:1").unwrap();
+        assert_eq!(
+            template.single,
+            parts(&[("<s>", 0)], &[], &[("</s>", 0)], [0, 0])
+        );
+        assert_eq!(
+            template.pair,
+            parts(
+                &[("<s>", 0)],
+                &[("</s>", 0), ("</s>", 0)],
+                &[("</s>", 1)],
+                [0, 1]
+            )
+        );
+
+        let template = Template::new("$A", "$A $B:2").unwrap();
+        assert_eq!(template.single, parts(&[], &[], &[], [0, 0]));
+        assert_eq!(template.pair, parts(&[], &[], &[], [0, 2]));
+
+        // Colons which are not followed by a type ID are part of the token.
+        let template = Template::new("<a:b> $A", "$A $B").unwrap();
+        assert_eq!(template.single, parts(&[("<a:b>", 0)], &[], &[], [0, 0]));
+    }
+
+    #[test]
+    fn test_template_invalid() {
+        assert_eq!(
+            Template::new("[CLS] [SEP]", "$A $B"),
+            Err(TemplateError::MissingPlaceholder)
+        );
+        assert_eq!(
+            Template::new("$A", "$A [SEP]"),
+            Err(TemplateError::MissingPlaceholder)
+        );
+        assert_eq!(
+            Template::new("$A $B", "$A $B"),
+            Err(TemplateError::InvalidPlaceholder("$B".to_string()))
+        );
+        assert_eq!(
+            Template::new("$A", "$B $A"),
+            Err(TemplateError::InvalidPlaceholder("$B".to_string()))
+        );
+        assert_eq!(
+            Template::new("$C", "$A $B"),
+            Err(TemplateError::InvalidPlaceholder("$C".to_string()))
+        );
+    }
+}
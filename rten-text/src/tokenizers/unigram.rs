@@ -0,0 +1,793 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::ops::Range;
+use std::sync::Mutex;
+
+use super::decoders::parse_byte_token;
+use super::{Encoder, TokenId, TokenizerError};
+use crate::normalizer::{NormalizedText, Normalizer};
+
+/// Score penalty applied to characters that are not in the vocabulary,
+/// relative to the lowest-scoring token.
+const UNK_PENALTY: f32 = 10.;
+
+/// A segmentation of text into tokens, as a list of `(range, token_id)`
+/// tuples, where `token_id` is `None` for characters that are not in the
+/// vocabulary.
+type Segmentation = Vec<(Range<usize>, Option<TokenId>)>;
+
+/// Unigram language model tokenizer.
+///
+/// Each token in the vocabulary has a score, which is the log probability of
+/// the token. Text is split into the sequence of tokens with the highest total
+/// score, which is found using the Viterbi algorithm. This is the default
+/// algorithm used by SentencePiece [^1] models such as T5 and ALBERT.
+///
+/// For subword regularization, segmentations can instead be sampled from the
+/// distribution of possible segmentations (see [`Unigram::with_sampling`]),
+/// and the `n` highest-scoring segmentations can be obtained using
+/// [`Unigram::encode_nbest`].
+///
+/// [^1]: Kudo, Taku. "Subword regularization: Improving neural network
+///       translation models with multiple subword candidates." arXiv preprint
+///       arXiv:1804.10959 (2018).
+#[derive(Clone)]
+pub struct Unigram {
+    normalizer: Option<Normalizer>,
+
+    /// Token strings and scores, indexed by token ID.
+    vocab: Vec<(String, f32)>,
+    token_to_id: HashMap<String, TokenId>,
+
+    /// Length of the longest token in bytes.
+    max_token_len: usize,
+
+    /// Score assigned to characters which are not in the vocabulary.
+    unk_score: f32,
+    unk_id: Option<TokenId>,
+    byte_fallback: bool,
+
+    /// Settings for sampling segmentations. See [`Unigram::with_sampling`].
+    sampling: Option<Sampling>,
+}
+
+/// Settings and random number generator used for sampling segmentations.
+struct Sampling {
+    nbest_size: i32,
+    alpha: f32,
+    rng: Mutex<fastrand::Rng>,
+}
+
+impl Clone for Sampling {
+    fn clone(&self) -> Self {
+        let rng = self
+            .rng
+            .lock()
+            .map(|rng| rng.clone())
+            .unwrap_or_else(|_| fastrand::Rng::new());
+        Sampling {
+            nbest_size: self.nbest_size,
+            alpha: self.alpha,
+            rng: Mutex::new(rng),
+        }
+    }
+}
+
+/// Partial path through the token lattice, used by [`Unigram::nbest`].
+struct Hypothesis {
+    /// End offset of the last token in the path.
+    end: usize,
+    /// Sum of token scores in the path.
+    score: f64,
+    /// Start offset and ID of the last token in the path.
+    start: usize,
+    token: Option<TokenId>,
+    /// Index of the hypothesis for the rest of the path.
+    prev: Option<usize>,
+}
+
+/// Entry in the priority queue of hypotheses used by [`Unigram::nbest`].
+///
+/// Entries are ordered by priority, then by index, so that earlier
+/// hypotheses are preferred when priorities are equal.
+struct Candidate {
+    priority: f64,
+    index: usize,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .total_cmp(&other.priority)
+            .then_with(|| other.index.cmp(&self.index))
+    }
+}
+
+/// Return `log(sum(exp(x) for x in xs))`, computed in a numerically stable
+/// way.
+fn log_sum_exp(xs: impl Iterator<Item = f64> + Clone) -> f64 {
+    let max = xs.clone().fold(f64::NEG_INFINITY, f64::max);
+    if max == f64::NEG_INFINITY {
+        return max;
+    }
+    max + xs.map(|x| (x - max).exp()).sum::<f64>().ln()
+}
+
+/// Choose an index from `weights` with probability proportional to its
+/// weight.
+fn sample_index(rng: &mut fastrand::Rng, weights: &[f64]) -> usize {
+    let total: f64 = weights.iter().sum();
+    let mut threshold = rng.f64() * total;
+    for (i, weight) in weights.iter().enumerate() {
+        if threshold < *weight {
+            return i;
+        }
+        threshold -= weight;
+    }
+    weights.len() - 1
+}
+
+/// Configuration for a [Unigram] tokenizer.
+#[derive(Debug, Default, Clone)]
+pub struct UnigramOptions {
+    /// The normalizer that handles Unicode normalization, lower-casing the
+    /// input etc.
+    pub normalizer: Option<Normalizer>,
+
+    /// ID of the token used for characters that are not in the vocabulary.
+    /// Consecutive unknown characters are replaced with a single token.
+    ///
+    /// If not set, encoding fails with [TokenizerError::MissingToken] when an
+    /// unknown character is encountered.
+    pub unk_id: Option<TokenId>,
+
+    /// Encode characters that are not in the vocabulary as a sequence of
+    /// tokens of the form `<0xXX>`, one for each byte of the character's UTF-8
+    /// encoding. The unknown token is used if any of the byte tokens are
+    /// missing.
+    pub byte_fallback: bool,
+}
+
+impl Unigram {
+    /// Construct a Unigram tokenizer from a vocabulary.
+    ///
+    /// `vocab` is a list of `(token, score)` tuples, where the index of each
+    /// entry is the token ID.
+    pub fn from_vocab(vocab: Vec<(String, f32)>, options: UnigramOptions) -> Unigram {
+        let token_to_id: HashMap<String, TokenId> = vocab
+            .iter()
+            .enumerate()
+            .filter(|(_, (token, _))| !token.is_empty())
+            .map(|(id, (token, _))| (token.clone(), id as TokenId))
+            .collect();
+        let max_token_len = token_to_id.keys().map(|t| t.len()).max().unwrap_or(0);
+        let min_score = vocab
+            .iter()
+            .map(|(_, score)| *score)
+            .min_by(f32::total_cmp)
+            .unwrap_or(0.);
+
+        Unigram {
+            normalizer: options.normalizer,
+            vocab,
+            token_to_id,
+            max_token_len,
+            unk_score: min_score - UNK_PENALTY,
+            unk_id: options.unk_id,
+            byte_fallback: options.byte_fallback,
+            sampling: None,
+        }
+    }
+
+    /// Sample segmentations when encoding, instead of always choosing the
+    /// highest-scoring one.
+    ///
+    /// This implements subword regularization, and matches the behavior of
+    /// SentencePiece's `sample_encode`:
+    ///
+    /// - If `nbest_size` is 0 or 1, sampling is disabled.
+    /// - If `nbest_size > 1`, a segmentation is sampled from the
+    ///   `nbest_size` highest-scoring segmentations.
+    /// - If `nbest_size < 0`, a segmentation is sampled from all possible
+    ///   segmentations.
+    ///
+    /// The probability of choosing a segmentation is proportional to
+    /// `P(segmentation) ^ alpha`. Smaller values of `alpha` make the
+    /// distribution more uniform. `seed` initializes the random number
+    /// generator, so that encoding a sequence of inputs is reproducible.
+    pub fn with_sampling(self, nbest_size: i32, alpha: f32, seed: u64) -> Unigram {
+        let sampling = (!matches!(nbest_size, 0 | 1)).then(|| Sampling {
+            nbest_size,
+            alpha,
+            rng: Mutex::new(fastrand::Rng::with_seed(seed)),
+        });
+        Unigram { sampling, ..self }
+    }
+
+    /// Return the `n` highest-scoring segmentations of `text`.
+    ///
+    /// Returns a list of `(token_ids, score)` tuples in descending order of
+    /// score, where `score` is the sum of the scores of the tokens. Fewer
+    /// than `n` entries are returned if there are not enough possible
+    /// segmentations. This matches SentencePiece's `nbest_encode`.
+    pub fn encode_nbest(
+        &self,
+        text: &str,
+        n: usize,
+    ) -> Result<Vec<(Vec<TokenId>, f32)>, TokenizerError> {
+        let text = match &self.normalizer {
+            None => text.to_string(),
+            Some(normalizer) => normalizer.normalize(text).0,
+        };
+        self.nbest(&text, n)
+            .into_iter()
+            .map(|(tokens, score)| {
+                let mut ids = Vec::with_capacity(tokens.len());
+                self.emit_tokens(&text, &tokens, &mut |_range, id| ids.push(id))?;
+                Ok((ids, score as f32))
+            })
+            .collect()
+    }
+
+    /// Call `f(end, token_id, score)` for each edge of the token lattice
+    /// which starts at byte offset `start` in `text`.
+    ///
+    /// If there is no token for the character at `start`, an edge with a
+    /// `token_id` of `None` is produced for it.
+    fn for_each_edge(
+        &self,
+        text: &str,
+        start: usize,
+        mut f: impl FnMut(usize, Option<TokenId>, f32),
+    ) {
+        let Some(ch) = text[start..].chars().next() else {
+            return;
+        };
+        let char_end = start + ch.len_utf8();
+
+        let mut found_char = false;
+        let ends = text[start..]
+            .char_indices()
+            .map(|(i, ch)| start + i + ch.len_utf8())
+            .take_while(|end| end - start <= self.max_token_len);
+        for end in ends {
+            if let Some(&id) = self.token_to_id.get(&text[start..end]) {
+                f(end, Some(id), self.vocab[id as usize].1);
+                found_char |= end == char_end;
+            }
+        }
+        if !found_char {
+            f(char_end, None, self.unk_score);
+        }
+    }
+
+    /// Split normalized text into the highest-scoring sequence of tokens.
+    fn viterbi(&self, text: &str) -> Segmentation {
+        #[derive(Clone)]
+        struct Node {
+            score: f64,
+            start: usize,
+            token: Option<TokenId>,
+        }
+
+        // `best[i]` is the best path which ends at byte offset `i`.
+        let mut best: Vec<Option<Node>> = vec![None; text.len() + 1];
+        best[0] = Some(Node {
+            score: 0.,
+            start: 0,
+            token: None,
+        });
+
+        for (start, _) in text.char_indices() {
+            // Every character boundary is reachable, since unknown characters
+            // produce a path of their own.
+            let start_score = best[start].as_ref().map(|n| n.score).unwrap_or(0.);
+            self.for_each_edge(text, start, |end, token, token_score| {
+                let score = start_score + token_score as f64;
+                if best[end].as_ref().is_none_or(|node| score > node.score) {
+                    best[end] = Some(Node {
+                        score,
+                        start,
+                        token,
+                    });
+                }
+            });
+        }
+
+        let mut tokens = Vec::new();
+        let mut end = text.len();
+        while end > 0 {
+            let node = best[end].as_ref().expect("should have path to end");
+            tokens.push((node.start..end, node.token));
+            end = node.start;
+        }
+        tokens.reverse();
+        tokens
+    }
+
+    /// Return the `n` highest-scoring segmentations of normalized text, with
+    /// their scores.
+    ///
+    /// This uses A* search over the token lattice, where the heuristic for a
+    /// partial path is the exact score of the best completion, computed by
+    /// running the Viterbi algorithm backwards.
+    fn nbest(&self, text: &str, n: usize) -> Vec<(Segmentation, f64)> {
+        if n == 0 {
+            return Vec::new();
+        }
+
+        // `best_suffix[i]` is the score of the best path from byte offset `i`
+        // to the end of the text.
+        let mut best_suffix = vec![f64::NEG_INFINITY; text.len() + 1];
+        best_suffix[text.len()] = 0.;
+        for (start, _) in text.char_indices().rev() {
+            let mut best = f64::NEG_INFINITY;
+            self.for_each_edge(text, start, |end, _token, score| {
+                best = best.max(score as f64 + best_suffix[end]);
+            });
+            best_suffix[start] = best;
+        }
+
+        let mut hypotheses = vec![Hypothesis {
+            end: 0,
+            score: 0.,
+            start: 0,
+            token: None,
+            prev: None,
+        }];
+        let mut queue = BinaryHeap::from([Candidate {
+            priority: best_suffix[0],
+            index: 0,
+        }]);
+        let mut results = Vec::new();
+
+        while let Some(Candidate { index, .. }) = queue.pop() {
+            let Hypothesis { end, score, .. } = hypotheses[index];
+
+            if end == text.len() {
+                let mut tokens = Vec::new();
+                let mut node = &hypotheses[index];
+                while let Some(prev) = node.prev {
+                    tokens.push((node.start..node.end, node.token));
+                    node = &hypotheses[prev];
+                }
+                tokens.reverse();
+                results.push((tokens, score));
+                if results.len() == n {
+                    break;
+                }
+                continue;
+            }
+
+            self.for_each_edge(text, end, |next_end, token, token_score| {
+                let next_score = score + token_score as f64;
+                queue.push(Candidate {
+                    priority: next_score + best_suffix[next_end],
+                    index: hypotheses.len(),
+                });
+                hypotheses.push(Hypothesis {
+                    end: next_end,
+                    score: next_score,
+                    start: end,
+                    token,
+                    prev: Some(index),
+                });
+            });
+        }
+
+        results
+    }
+
+    /// Sample a segmentation of normalized text from the distribution of all
+    /// possible segmentations, where the probability of each segmentation is
+    /// proportional to `exp(alpha * score)`.
+    ///
+    /// This uses the forward-filtering backward-sampling algorithm.
+    fn sample(&self, text: &str, alpha: f32, rng: &mut fastrand::Rng) -> Segmentation {
+        let alpha = alpha as f64;
+
+        // `incoming[i]` lists the lattice edges which end at byte offset `i`.
+        let mut incoming: Vec<Vec<(usize, Option<TokenId>, f64)>> =
+            vec![Vec::new(); text.len() + 1];
+        for (start, _) in text.char_indices() {
+            self.for_each_edge(text, start, |end, token, score| {
+                incoming[end].push((start, token, alpha * score as f64));
+            });
+        }
+
+        // `forward[i]` is the log of the sum of the weights of all paths from
+        // the start of the text to byte offset `i`.
+        let mut forward = vec![f64::NEG_INFINITY; text.len() + 1];
+        forward[0] = 0.;
+        for end in 1..=text.len() {
+            forward[end] = log_sum_exp(
+                incoming[end]
+                    .iter()
+                    .map(|(start, _token, score)| forward[*start] + score),
+            );
+        }
+
+        let mut tokens = Vec::new();
+        let mut weights = Vec::new();
+        let mut end = text.len();
+        while end > 0 {
+            let edges = &incoming[end];
+            weights.clear();
+            weights.extend(
+                edges
+                    .iter()
+                    .map(|(start, _token, score)| (forward[*start] + score - forward[end]).exp()),
+            );
+            let (start, token, _score) = edges[sample_index(rng, &weights)];
+            tokens.push((start..end, token));
+            end = start;
+        }
+        tokens.reverse();
+        tokens
+    }
+
+    /// Segment normalized text, using sampling if enabled, or the Viterbi
+    /// algorithm otherwise.
+    fn segment(&self, text: &str) -> Segmentation {
+        let Some(sampling) = &self.sampling else {
+            return self.viterbi(text);
+        };
+        let mut rng = sampling.rng.lock().unwrap_or_else(|err| err.into_inner());
+
+        if sampling.nbest_size < 0 {
+            return self.sample(text, sampling.alpha, &mut rng);
+        }
+
+        let mut nbest = self.nbest(text, sampling.nbest_size as usize);
+        let max_score = nbest
+            .iter()
+            .map(|(_tokens, score)| *score)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let weights: Vec<f64> = nbest
+            .iter()
+            .map(|(_tokens, score)| (sampling.alpha as f64 * (score - max_score)).exp())
+            .collect();
+        let index = sample_index(&mut rng, &weights);
+        nbest.swap_remove(index).0
+    }
+
+    /// Convert a segmentation of normalized text into token IDs, applying
+    /// byte fallback and replacing unknown characters with the unknown token.
+    fn emit_tokens(
+        &self,
+        text: &str,
+        tokens: &[(Range<usize>, Option<TokenId>)],
+        on_token: &mut dyn FnMut(Range<usize>, TokenId),
+    ) -> Result<(), TokenizerError> {
+        let mut i = 0;
+        while i < tokens.len() {
+            let (range, token) = tokens[i].clone();
+            i += 1;
+
+            if let Some(id) = token {
+                on_token(range, id);
+                continue;
+            }
+
+            if self.byte_fallback {
+                if let Some(ids) = self.byte_token_ids(&text[range.clone()]) {
+                    for id in ids {
+                        on_token(range.clone(), id);
+                    }
+                    continue;
+                }
+            }
+
+            // Merge consecutive unknown characters into one token.
+            let mut unk_range = range;
+            while let Some((range, None)) = tokens.get(i) {
+                unk_range.end = range.end;
+                i += 1;
+            }
+            let Some(unk_id) = self.unk_id else {
+                return Err(TokenizerError::MissingToken(text[unk_range].to_string()));
+            };
+            on_token(unk_range, unk_id);
+        }
+        Ok(())
+    }
+
+    /// Return the `<0xXX>` token IDs for the UTF-8 bytes of `text`, or `None`
+    /// if any are missing from the vocabulary.
+    fn byte_token_ids(&self, text: &str) -> Option<Vec<TokenId>> {
+        byte_token_ids(&self.token_to_id, text)
+    }
+}
+
+/// Look up the `<0xXX>` token IDs for the UTF-8 bytes of `text` in `vocab`.
+///
+/// Returns `None` if any of the byte tokens are missing.
+pub(super) fn byte_token_ids(vocab: &HashMap<String, TokenId>, text: &str) -> Option<Vec<TokenId>> {
+    text.bytes()
+        .map(|byte| vocab.get(&format!("<0x{:02X}>", byte)).copied())
+        .collect()
+}
+
+/// Decode token strings produced by a SentencePiece-style tokenizer, where
+/// tokens of the form `<0xXX>` represent individual bytes.
+pub(super) fn decode_byte_tokens<'a>(
+    tokens: impl Iterator<Item = &'a str>,
+) -> Result<String, TokenizerError> {
+    let mut bytes = Vec::new();
+    for token in tokens {
+        match parse_byte_token(token) {
+            Some(byte) => bytes.push(byte),
+            None => bytes.extend(token.as_bytes()),
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| TokenizerError::InvalidUtf8)
+}
+
+impl Encoder for Unigram {
+    fn encode_with_offsets(
+        &self,
+        text: &str,
+        on_token: &mut dyn FnMut(Range<usize>, TokenId),
+    ) -> Result<(), TokenizerError> {
+        let mut normalized = NormalizedText::new(text);
+        if let Some(normalizer) = &self.normalizer {
+            normalized = normalized.normalize(normalizer);
+        }
+        let text = normalized.normalized();
+
+        let map_offsets = |range: Range<usize>| normalized.original_range(range);
+
+        let tokens = self.segment(text);
+        self.emit_tokens(text, &tokens, &mut |range, id| {
+            on_token(map_offsets(range), id)
+        })
+    }
+
+    fn get_token_str(&self, id: TokenId) -> Result<String, TokenizerError> {
+        self.vocab
+            .get(id as usize)
+            .map(|(token, _score)| token.clone())
+            .ok_or(TokenizerError::InvalidTokenId(id))
+    }
+
+    fn vocab_size(&self) -> usize {
+        self.vocab.len()
+    }
+
+    fn get_token_id(&self, tok: &str) -> Result<TokenId, TokenizerError> {
+        self.token_to_id
+            .get(tok)
+            .copied()
+            .ok_or(TokenizerError::MissingToken(tok.to_string()))
+    }
+
+    fn decode(&self, ids: &[TokenId]) -> Result<String, TokenizerError> {
+        let tokens = self.get_tokens(ids)?;
+        decode_byte_tokens(tokens.iter().map(|t| t.as_str()))
+    }
+
+    fn normalizer(&self) -> Option<&Normalizer> {
+        self.normalizer.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::normalizer::{Normalizer, NormalizerOptions};
+    use crate::tokenizers::{
+        EncodeOptions, Encoder, Tokenizer, TokenizerError, TokenizerOptions, Unigram,
+        UnigramOptions,
+    };
+
+    fn create_tokenizer(vocab: &[(&str, f32)], options: UnigramOptions) -> Tokenizer {
+        let vocab = vocab
+            .iter()
+            .map(|(token, score)| (token.to_string(), *score))
+            .collect();
+        let encoder = Unigram::from_vocab(vocab, options);
+        Tokenizer::new(encoder, TokenizerOptions::default())
+    }
+
+    #[test]
+    fn test_unigram_encoder() {
+        struct Case<'a> {
+            text: &'a str,
+            tokens: &'a [&'a str],
+        }
+
+        let vocab = &[
+            ("<unk>", 0.),
+            ("a", -2.),
+            ("b", -2.),
+            ("c", -2.),
+            ("ab", -3.),
+            ("bc", -1.),
+            ("abc", -5.),
+        ];
+        let tokenizer = create_tokenizer(
+            vocab,
+            UnigramOptions {
+                unk_id: Some(0),
+                ..Default::default()
+            },
+        );
+
+        let cases = [
+            // "a" + "bc" (-3) scores higher than "abc" (-5) or "ab" + "c" (-5).
+            Case {
+                text: "abc",
+                tokens: &["a", "bc"],
+            },
+            Case {
+                text: "ab",
+                tokens: &["ab"],
+            },
+            // Consecutive unknown characters are merged into one token.
+            Case {
+                text: "axyzb",
+                tokens: &["a", "<unk>", "b"],
+            },
+            Case {
+                text: "",
+                tokens: &[],
+            },
+        ];
+
+        for Case { text, tokens } in cases {
+            let encoded = tokenizer
+                .encode(text.into(), EncodeOptions::default())
+                .unwrap();
+            assert_eq!(
+                tokenizer.encoder().get_tokens(encoded.token_ids()).unwrap(),
+                tokens
+            );
+        }
+
+        let encoded = tokenizer
+            .encode("axyzb".into(), EncodeOptions::default())
+            .unwrap();
+        assert_eq!(encoded.offset_mapping(), &[(0, 1), (1, 4), (4, 5)]);
+    }
+
+    #[test]
+    fn test_unigram_nbest_and_sampling() {
+        let vocab: Vec<_> = [
+            ("<unk>", 0.),
+            ("a", -2.),
+            ("b", -2.),
+            ("c", -2.),
+            ("ab", -3.),
+            ("bc", -1.),
+            ("abc", -5.),
+        ]
+        .into_iter()
+        .map(|(token, score)| (token.to_string(), score))
+        .collect();
+        let options = UnigramOptions {
+            unk_id: Some(0),
+            ..Default::default()
+        };
+        let encoder = Unigram::from_vocab(vocab, options);
+        let to_tokens = |ids: &[u32]| encoder.get_tokens(ids).unwrap().join(" ");
+
+        // N-best segmentations are returned in descending order of score.
+        let nbest = encoder.encode_nbest("abc", 10).unwrap();
+        let nbest: Vec<_> = nbest
+            .iter()
+            .map(|(ids, score)| (to_tokens(ids), *score))
+            .collect();
+        assert_eq!(nbest.len(), 4);
+        assert_eq!(nbest[0], ("a bc".to_string(), -3.));
+        let mut middle = [nbest[1].clone(), nbest[2].clone()];
+        middle.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            middle,
+            [("ab c".to_string(), -5.), ("abc".to_string(), -5.)]
+        );
+        assert_eq!(nbest[3], ("a b c".to_string(), -6.));
+
+        assert_eq!(encoder.encode_nbest("abc", 1).unwrap().len(), 1);
+        assert!(encoder.encode_nbest("abc", 0).unwrap().is_empty());
+        assert_eq!(
+            encoder.encode_nbest("axb", 1).unwrap(),
+            [(vec![1, 0, 2], -19.)]
+        );
+
+        // An `nbest_size` of 0 or 1 disables sampling.
+        for nbest_size in [0, 1] {
+            let encoder = encoder.clone().with_sampling(nbest_size, 0.1, 1234);
+            for _ in 0..10 {
+                assert_eq!(to_tokens(&encoder.encode("abc").unwrap()), "a bc");
+            }
+        }
+
+        let sample = |nbest_size, alpha, seed| -> HashMap<String, usize> {
+            let encoder = encoder.clone().with_sampling(nbest_size, alpha, seed);
+            let mut counts = HashMap::new();
+            for _ in 0..1000 {
+                let tokens = to_tokens(&encoder.encode("abc").unwrap());
+                *counts.entry(tokens).or_default() += 1;
+            }
+            counts
+        };
+
+        // Sampling from all segmentations. With `alpha = 1`, the probability
+        // of each segmentation is proportional to `exp(score)`.
+        let counts = sample(-1, 1., 1234);
+        assert_eq!(counts.len(), 4);
+        assert!(counts["a bc"] > 700 && counts["a bc"] < 880);
+        assert!(counts["a b c"] < counts["ab c"]);
+        assert!(counts["a b c"] < counts["abc"]);
+        assert_eq!(counts, sample(-1, 1., 1234));
+
+        // Smaller values of `alpha` make the distribution more uniform.
+        let counts = sample(-1, 0.01, 1234);
+        assert!(counts.values().all(|count| *count > 200));
+
+        // Sampling from the n-best segmentations.
+        let counts = sample(2, 1., 1234);
+        assert_eq!(counts.len(), 2);
+        assert!(counts["a bc"] > counts.values().sum::<usize>() / 2);
+    }
+
+    #[test]
+    fn test_unigram_byte_fallback() {
+        let mut vocab = vec![("<unk>", 0.), ("▁", -1.), ("h", -2.), ("i", -2.)];
+        let byte_tokens: Vec<String> = (0..=255u8).map(|b| format!("<0x{:02X}>", b)).collect();
+        vocab.extend(byte_tokens.iter().map(|t| (t.as_str(), 0.)));
+
+        let tokenizer = create_tokenizer(
+            &vocab,
+            UnigramOptions {
+                normalizer: Some(Normalizer::new(NormalizerOptions {
+                    replace_space: Some('▁'),
+                    prepend: Some("▁".to_string()),
+                    ..Default::default()
+                })),
+                unk_id: Some(0),
+                byte_fallback: true,
+            },
+        );
+
+        let encoded = tokenizer
+            .encode("hi é".into(), EncodeOptions::default())
+            .unwrap();
+        assert_eq!(
+            tokenizer.encoder().get_tokens(encoded.token_ids()).unwrap(),
+            &["▁", "h", "i", "▁", "<0xC3>", "<0xA9>"]
+        );
+        assert_eq!(
+            encoded.offset_mapping(),
+            &[(0, 1), (0, 1), (1, 2), (2, 3), (3, 4), (3, 4)]
+        );
+        assert_eq!(
+            tokenizer.encoder().decode(encoded.token_ids()).unwrap(),
+            "▁hi▁é"
+        );
+    }
+
+    #[test]
+    fn test_unigram_missing_unk_token() {
+        let vocab = vec![("a".to_string(), -1.)];
+        let encoder = Unigram::from_vocab(vocab, UnigramOptions::default());
+        let result = encoder.encode("abc");
+        assert!(matches!(result, Err(TokenizerError::MissingToken(tok)) if tok == "bc"));
+        assert_eq!(encoder.encode("aa").unwrap(), &[0, 0]);
+    }
+}
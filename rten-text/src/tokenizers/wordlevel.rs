@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use super::{Encoder, TokenId, TokenizerError};
+use crate::normalizer::{NormalizedText, Normalizer};
+use crate::split::SplitExt;
+
+use unicode_categories::UnicodeCategories;
+
+/// Word-level tokenizer which maps each whole word to a token ID.
+///
+/// The input is split into words at whitespace and punctuation, and each word
+/// is looked up in the vocabulary. Words that are not in the vocabulary are
+/// mapped to the unknown token, if one is configured.
+///
+/// This is the `WordLevel` model from Hugging Face Tokenizers, which is used
+/// by some simple models such as older RNN or CTC models.
+#[derive(Clone)]
+pub struct WordLevel {
+    normalizer: Option<Normalizer>,
+    token_to_id: HashMap<String, TokenId>,
+    id_to_token: HashMap<TokenId, String>,
+    unk_token: Option<String>,
+}
+
+/// Configuration for a [WordLevel] tokenizer.
+#[derive(Debug, Default, Clone)]
+pub struct WordLevelOptions {
+    /// The normalizer that handles Unicode normalization, lower-casing the
+    /// input etc.
+    pub normalizer: Option<Normalizer>,
+
+    /// The token used for words that are not in the vocabulary.
+    ///
+    /// If not set, encoding fails with [TokenizerError::MissingToken] when an
+    /// unknown word is encountered.
+    pub unk_token: Option<String>,
+}
+
+impl WordLevel {
+    /// Construct a WordLevel tokenizer from a vocabulary.
+    ///
+    /// `vocab` is a mapping from word to token ID.
+    pub fn from_vocab(vocab: HashMap<String, TokenId>, options: WordLevelOptions) -> WordLevel {
+        let id_to_token: HashMap<TokenId, String> =
+            vocab.iter().map(|(k, v)| (*v, k.to_string())).collect();
+
+        WordLevel {
+            normalizer: options.normalizer,
+            token_to_id: vocab,
+            id_to_token,
+            unk_token: options.unk_token,
+        }
+    }
+}
+
+impl Encoder for WordLevel {
+    fn encode_with_offsets(
+        &self,
+        text: &str,
+        on_token: &mut dyn FnMut(Range<usize>, TokenId),
+    ) -> Result<(), TokenizerError> {
+        let mut normalized = NormalizedText::new(text);
+        if let Some(normalizer) = &self.normalizer {
+            normalized = normalized.normalize(normalizer);
+        }
+        let text = normalized.normalized();
+
+        let map_offsets = |range: Range<usize>| normalized.original_range(range);
+
+        let is_punc_or_space =
+            |ch: char| ch.is_ascii_punctuation() || ch.is_punctuation() || ch.is_whitespace();
+        let mut offset = 0;
+
+        for word in text.split_keep_delimeters_pure(is_punc_or_space) {
+            if !word.trim().is_empty() {
+                let id = match (self.token_to_id.get(word), &self.unk_token) {
+                    (Some(id), _) => *id,
+                    (None, Some(unk)) => self.get_token_id(unk)?,
+                    (None, None) => return Err(TokenizerError::MissingToken(word.to_string())),
+                };
+                on_token(map_offsets(offset..offset + word.len()), id);
+            }
+            offset += word.len();
+        }
+
+        Ok(())
+    }
+
+    fn get_token_str(&self, id: TokenId) -> Result<String, TokenizerError> {
+        self.id_to_token
+            .get(&id)
+            .cloned()
+            .ok_or(TokenizerError::InvalidTokenId(id))
+    }
+
+    fn vocab_size(&self) -> usize {
+        self.id_to_token
+            .keys()
+            .max()
+            .map_or(0, |&id| id as usize + 1)
+    }
+
+    fn get_token_id(&self, tok: &str) -> Result<TokenId, TokenizerError> {
+        self.token_to_id
+            .get(tok)
+            .copied()
+            .ok_or(TokenizerError::MissingToken(tok.to_string()))
+    }
+
+    fn decode(&self, ids: &[TokenId]) -> Result<String, TokenizerError> {
+        let token_strings = self.get_tokens(ids)?;
+        Ok(token_strings.join(" "))
+    }
+
+    fn normalizer(&self) -> Option<&Normalizer> {
+        self.normalizer.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::normalizer::{Normalizer, NormalizerOptions};
+    use crate::tokenizers::{
+        EncodeOptions, Encoder, Tokenizer, TokenizerError, TokenizerOptions, WordLevel,
+        WordLevelOptions,
+    };
+
+    fn create_tokenizer(vocab: &[&str], options: WordLevelOptions) -> Tokenizer {
+        let vocab: HashMap<_, _> = vocab
+            .iter()
+            .enumerate()
+            .map(|(i, token)| (token.to_string(), i as u32))
+            .collect();
+        let encoder = WordLevel::from_vocab(vocab, options);
+        Tokenizer::new(encoder, TokenizerOptions::default())
+    }
+
+    #[test]
+    fn test_wordlevel_encoder() {
+        struct Case<'a> {
+            text: &'a str,
+            tokens: &'a [&'a str],
+        }
+
+        let vocab = &["<unk>", "the", "cat", "sat", "on", "mat", ".", "Faerûn"];
+        let tokenizer = create_tokenizer(
+            vocab,
+            WordLevelOptions {
+                unk_token: Some("<unk>".to_string()),
+                ..Default::default()
+            },
+        );
+
+        let cases = [
+            Case {
+                text: "the cat sat on the mat.",
+                tokens: &["the", "cat", "sat", "on", "the", "mat", "."],
+            },
+            // Unknown words. Unlike WordPiece, words are not split into
+            // subwords.
+            Case {
+                text: "the dog sat on the cats",
+                tokens: &["the", "<unk>", "sat", "on", "the", "<unk>"],
+            },
+            Case {
+                text: "",
+                tokens: &[],
+            },
+            Case {
+                text: "  Faerûn ",
+                tokens: &["Faerûn"],
+            },
+        ];
+
+        for Case { text, tokens } in cases {
+            let encoded = tokenizer
+                .encode(text.into(), EncodeOptions::default())
+                .unwrap();
+            assert_eq!(
+                tokenizer.encoder().get_tokens(encoded.token_ids()).unwrap(),
+                tokens
+            );
+        }
+    }
+
+    #[test]
+    fn test_wordlevel_offsets() {
+        let vocab = &["<unk>", "the", "cat"];
+        let tokenizer = create_tokenizer(
+            vocab,
+            WordLevelOptions {
+                normalizer: Some(Normalizer::new(NormalizerOptions {
+                    lowercase: true,
+                    ..Default::default()
+                })),
+                unk_token: Some("<unk>".to_string()),
+            },
+        );
+
+        let text = "The  CAT dog";
+        let encoded = tokenizer
+            .encode(text.into(), EncodeOptions::default())
+            .unwrap();
+        assert_eq!(encoded.token_ids(), &[1, 2, 0]);
+        assert_eq!(
+            encoded.text_for_token_range(0..1).map(|t| t.trim()),
+            Some("The")
+        );
+        assert_eq!(
+            encoded.text_for_token_range(1..2).map(|t| t.trim()),
+            Some("CAT")
+        );
+    }
+
+    #[test]
+    fn test_wordlevel_missing_unk_token() {
+        let vocab: HashMap<_, _> = [("the".to_string(), 0)].into();
+
+        // Unknown word with no unknown token configured.
+        let encoder = WordLevel::from_vocab(vocab.clone(), WordLevelOptions::default());
+        let result = encoder.encode("the dog");
+        assert!(matches!(result, Err(TokenizerError::MissingToken(tok)) if tok == "dog"));
+
+        // Unknown token is configured but not in the vocabulary.
+        let encoder = WordLevel::from_vocab(
+            vocab,
+            WordLevelOptions {
+                unk_token: Some("<unk>".to_string()),
+                ..Default::default()
+            },
+        );
+        let result = encoder.encode("the dog");
+        assert!(matches!(result, Err(TokenizerError::MissingToken(tok)) if tok == "<unk>"));
+
+        // Known words don't require an unknown token.
+        assert_eq!(encoder.encode("the").unwrap(), &[0]);
+    }
+}
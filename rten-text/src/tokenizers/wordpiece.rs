@@ -1,7 +1,8 @@
 use std::collections::HashMap;
+use std::ops::Range;
 
 use super::{Encoder, TokenId, TokenizerError};
-use crate::normalizer::Normalizer;
+use crate::normalizer::{NormalizedText, Normalizer};
 use crate::split::SplitExt;
 
 use unicode_categories::UnicodeCategories;
@@ -22,6 +23,7 @@ pub struct WordPiece {
     token_to_id: HashMap<String, TokenId>,
     id_to_token: HashMap<TokenId, String>,
     subword_prefix: String,
+    unk_token: String,
     max_word_len: usize,
 }
 
@@ -32,11 +34,23 @@ pub struct WordPieceOptions {
     /// input etc.
     pub normalizer: Option<Normalizer>,
 
-    /// The maximum length of words that can be tokenized. Any words longer than
-    /// this are tokenized as `[UNK]`.
+    /// The maximum length of words that can be tokenized, in characters. Any
+    /// words longer than this are tokenized as the
+    /// [unknown token](Self::unk_token).
     ///
     /// Defaults to 100.
     pub max_word_len: Option<usize>,
+
+    /// Prefix of tokens which continue a word, rather than starting a new
+    /// one.
+    ///
+    /// Defaults to `##`.
+    pub continuing_subword_prefix: Option<String>,
+
+    /// Token used for words that cannot be tokenized.
+    ///
+    /// Defaults to `[UNK]`.
+    pub unk_token: Option<String>,
 }
 
 impl WordPiece {
@@ -47,12 +61,16 @@ impl WordPiece {
         let id_to_token: HashMap<TokenId, String> =
             vocab.iter().map(|(k, v)| (*v, k.to_string())).collect();
 
-        let subword_prefix = "##".to_string();
+        let subword_prefix = options
+            .continuing_subword_prefix
+            .unwrap_or_else(|| "##".to_string());
+        let unk_token = options.unk_token.unwrap_or_else(|| "[UNK]".to_string());
 
         WordPiece {
             normalizer: options.normalizer,
             token_to_id: vocab,
             subword_prefix,
+            unk_token,
             max_word_len: options.max_word_len.unwrap_or(100),
             id_to_token,
         }
@@ -63,41 +81,30 @@ impl Encoder for WordPiece {
     fn encode_with_offsets(
         &self,
         text: &str,
-        on_token: &mut dyn FnMut(usize, TokenId),
+        on_token: &mut dyn FnMut(Range<usize>, TokenId),
     ) -> Result<(), TokenizerError> {
         let mut tmp_buf = String::with_capacity(self.max_word_len);
 
         // Apply normalization to the input text.
-        let (text, normalized_to_source_offsets) = match &self.normalizer {
-            None => (text.to_string(), None),
-            Some(normalizer) => {
-                let (normalized_text, offsets) = normalizer.normalize(text);
-                (normalized_text, Some(offsets))
-            }
-        };
+        let mut normalized = NormalizedText::new(text);
+        if let Some(normalizer) = &self.normalizer {
+            normalized = normalized.normalize(normalizer);
+        }
+        let text = normalized.normalized();
 
-        // Map an offset into the normalized string into an offset in the source
+        // Map a range in the normalized string into a range in the source
         // string.
-        let map_offset = |offset: usize| {
-            if let Some(mappings) = &normalized_to_source_offsets {
-                mappings
-                    .get(offset)
-                    .copied()
-                    .expect("invalid normalized offset")
-            } else {
-                offset
-            }
-        };
+        let map_offsets = |range: Range<usize>| normalized.original_range(range);
 
         let is_punc_or_space =
             |ch: char| ch.is_ascii_punctuation() || ch.is_punctuation() || ch.is_whitespace();
-        let words = text.split_keep_delimeters(is_punc_or_space);
+        let words = text.split_keep_delimeters_pure(is_punc_or_space);
         let mut offset = 0;
 
         macro_rules! add_unknown_token {
-            () => {
-                let unknown_token = self.get_token_id("[UNK]")?;
-                on_token(map_offset(offset), unknown_token);
+            ($range:expr) => {
+                let unknown_token = self.get_token_id(&self.unk_token)?;
+                on_token(map_offsets($range), unknown_token);
             };
         }
 
@@ -108,7 +115,8 @@ impl Encoder for WordPiece {
             }
 
             if word.chars().count() > self.max_word_len {
-                add_unknown_token!();
+                add_unknown_token!(offset..offset + word.len());
+                offset += word.len();
                 continue;
             }
 
@@ -128,7 +136,8 @@ impl Encoder for WordPiece {
                     };
 
                     if let Some(id) = self.token_to_id.get(prefix) {
-                        on_token(map_offset(offset), *id);
+                        let start = offset + word.len() - remainder.len();
+                        on_token(map_offsets(start..start + len), *id);
                         remainder = remainder.split_at(len).1;
                         word_tokens += 1;
                         break;
@@ -139,7 +148,8 @@ impl Encoder for WordPiece {
                 }
 
                 if len == 0 {
-                    add_unknown_token!();
+                    let start = offset + word.len() - remainder.len();
+                    add_unknown_token!(start..offset + word.len());
                     break;
                 }
             }
@@ -156,6 +166,13 @@ impl Encoder for WordPiece {
             .ok_or(TokenizerError::InvalidTokenId(id))
     }
 
+    fn vocab_size(&self) -> usize {
+        self.id_to_token
+            .keys()
+            .max()
+            .map_or(0, |&id| id as usize + 1)
+    }
+
     fn get_token_id(&self, tok: &str) -> Result<TokenId, TokenizerError> {
         self.token_to_id
             .get(tok)
@@ -167,6 +184,10 @@ impl Encoder for WordPiece {
         let token_strings = self.get_tokens(ids)?;
         Ok(token_strings.join(" "))
     }
+
+    fn normalizer(&self) -> Option<&Normalizer> {
+        self.normalizer.as_ref()
+    }
 }
 
 #[cfg(test)]
@@ -285,6 +306,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_wordpiece_prefix_and_unk_token() {
+        let vocab = &["[CLS]", "[SEP]", "<unk>", "foo", "@@bar", "##baz"];
+        let opts = WordPieceOptions {
+            max_word_len: Some(6),
+            continuing_subword_prefix: Some("@@".to_string()),
+            unk_token: Some("<unk>".to_string()),
+            ..Default::default()
+        };
+        let tokenizer = create_tokenizer(vocab, opts);
+
+        let text = "foobar foobaz foobarbar";
+        let encoded = tokenizer
+            .encode(text.into(), EncodeOptions::default())
+            .unwrap();
+        assert_eq!(
+            tokenizer.encoder().get_tokens(encoded.token_ids()).unwrap(),
+            &["[CLS]", "foo", "@@bar", "foo", "<unk>", "<unk>", "[SEP]"]
+        );
+    }
+
     #[test]
     fn test_wordpiece_encoder_lowercase() {
         struct Case<'a> {
@@ -176,6 +176,11 @@ fn test_bpe_gpt2() -> Result<(), Box<dyn Error>> {
 
         let encoded = tokenizer_from_json.encode(text.as_str().into(), Default::default())?;
         compare_tokens(encoded.token_ids(), &expected.token_ids)?;
+
+        // The `ByteLevel` decoder from `tokenizer.json` should reverse the
+        // encoding.
+        let decoded = tokenizer_from_json.decode(encoded.token_ids())?;
+        assert_eq!(decoded, text);
     }
 
     Ok(())
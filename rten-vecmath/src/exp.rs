@@ -4,7 +4,11 @@
 
 use std::mem::MaybeUninit;
 
-use rten_simd::dispatch::{dispatch_map_op, dispatch_map_op_in_place, SimdUnaryOp};
+use rten_simd::dispatch::{
+    dispatch_map_op, dispatch_map_op_in_place, SimdDispatcher, SimdOp, SimdUnaryOp,
+};
+use rten_simd::functional::simd_map_binary;
+use rten_simd::span::{MutPtrLen, PtrLen};
 use rten_simd::{SimdFloat, SimdInt};
 
 const INV_LOG2: f32 = std::f32::consts::LOG2_E; // aka. 1 / ln2
@@ -218,6 +222,108 @@ pub fn vec_silu_in_place(xs: &mut [f32]) {
     dispatch_map_op_in_place(xs, SimdSilu {});
 }
 
+// `sqrt(2 / pi)`, scaled by 2 so that `0.5 * (1 + tanh(y))` can be computed
+// as `sigmoid(2 * y)`.
+const GELU_TANH_SCALE: f32 = 2. * 0.7978845608;
+const GELU_TANH_CUBIC: f32 = 0.044715;
+
+/// Compute the tanh approximation of GELU.
+///
+/// This computes `0.5 * x * (1 + tanh(sqrt(2 / pi) * (x + 0.044715 * x^3)))`,
+/// using the identity `0.5 * (1 + tanh(y)) = sigmoid(2 * y)`.
+#[inline(always)]
+unsafe fn simd_gelu_tanh<S: SimdFloat>(x: S) -> S {
+    let x_cube = x.mul(x).mul(x);
+    let y = x_cube.mul_add(S::splat(GELU_TANH_CUBIC), x);
+    x.mul(simd_sigmoid(y.mul(S::splat(GELU_TANH_SCALE))))
+}
+
+/// Computes the tanh approximation of the GELU function. See [`vec_gelu_tanh`].
+pub fn gelu_tanh(x: f32) -> f32 {
+    // Safety: f32 is available on all systems
+    unsafe { simd_gelu_tanh(x) }
+}
+
+struct SimdGeluTanh {}
+impl SimdUnaryOp for SimdGeluTanh {
+    #[inline(always)]
+    unsafe fn eval<S: SimdFloat>(&self, x: S) -> S {
+        simd_gelu_tanh(x)
+    }
+}
+
+/// Vectorized tanh approximation of the GELU function.
+///
+/// This computes `0.5 * x * (1 + tanh(sqrt(2 / pi) * (x + 0.044715 * x^3)))`
+/// for each element. This is the variant of GELU used by GPT-2 and related
+/// models, and corresponds to the `approximate="tanh"` mode of the ONNX
+/// [Gelu](https://onnx.ai/onnx/operators/onnx__Gelu.html) operator.
+pub fn vec_gelu_tanh(xs: &[f32], out: &mut [MaybeUninit<f32>]) {
+    dispatch_map_op(xs, out, SimdGeluTanh {});
+}
+
+/// Variant of [vec_gelu_tanh] that modifies elements in-place.
+pub fn vec_gelu_tanh_in_place(xs: &mut [f32]) {
+    dispatch_map_op_in_place(xs, SimdGeluTanh {});
+}
+
+/// Compute `silu(gate) * up` for each pair of elements in `gate` and `up`.
+#[inline(always)]
+unsafe fn simd_swiglu<S: SimdFloat>(
+    gate: PtrLen<f32>,
+    up: PtrLen<f32>,
+    out: MutPtrLen<MaybeUninit<f32>>,
+) {
+    simd_map_binary(
+        gate,
+        up,
+        out,
+        #[inline(always)]
+        |g: S, u: S| simd_silu(g).mul(u),
+        0., /* pad */
+    );
+}
+
+struct SimdSwiGlu {
+    gate: PtrLen<f32>,
+    up: PtrLen<f32>,
+    out: MutPtrLen<MaybeUninit<f32>>,
+}
+
+impl SimdOp for SimdSwiGlu {
+    #[inline(always)]
+    unsafe fn eval<S: SimdFloat>(&self) {
+        simd_swiglu::<S>(self.gate, self.up, self.out)
+    }
+}
+
+/// Computes `silu(gate) * up` for each pair of elements.
+///
+/// This is the gated activation used in the MLP blocks of Llama-style models
+/// ("SwiGLU"). Fusing the SiLU and multiplication avoids making separate passes
+/// over the data for each. `gate`, `up` and `out` must have the same length.
+///
+/// `out` will be fully initialized after this function returns.
+pub fn vec_swiglu(gate: &[f32], up: &[f32], out: &mut [MaybeUninit<f32>]) {
+    let op = SimdSwiGlu {
+        gate: gate.into(),
+        up: up.into(),
+        out: out.into(),
+    };
+    SimdDispatcher::default().dispatch(op);
+}
+
+/// Variant of [vec_swiglu] which writes the result to `gate`.
+pub fn vec_swiglu_in_place(gate: &mut [f32], up: &[f32]) {
+    let out: MutPtrLen<f32> = gate.into();
+    let op = SimdSwiGlu {
+        gate: gate.into(),
+        up: up.into(),
+        out: out.as_uninit(),
+    };
+    SimdDispatcher::default().dispatch(op);
+}
+
 struct SimdExp {}
 impl SimdUnaryOp for SimdExp {
     #[inline(always)]
@@ -247,9 +353,12 @@ mod tests {
     use std::mem::MaybeUninit;
 
     use crate::testing::{
-        arange, benchmark_op, check_f32s_are_equal_ulps, check_with_all_f32s, AsUninit,
+        arange, benchmark_op, check_f32s_are_equal_atol, check_f32s_are_equal_ulps,
+        check_with_all_f32s, triples, AsUninit,
+    };
+    use crate::{
+        exp, vec_exp, vec_gelu_tanh, vec_sigmoid, vec_silu, vec_swiglu, vec_swiglu_in_place,
     };
-    use crate::{exp, vec_exp, vec_sigmoid, vec_silu};
 
     // Maximum error of `vec_expf` compared to Rust standard library
     // implementation.
@@ -259,6 +368,10 @@ mod tests {
     // below.
     const MAX_SIGMOID_ERROR_ULPS: f32 = 4.0;
 
+    // Maximum absolute error of `vec_gelu_tanh` compared to reference
+    // implementation below.
+    const MAX_GELU_TANH_ERROR_ATOL: f32 = 1e-6;
+
     fn reference_sigmoid(x: f32) -> f32 {
         1. / (1. + (-x).exp())
     }
@@ -267,6 +380,14 @@ mod tests {
         x * reference_sigmoid(x)
     }
 
+    // Computed in f64 because `1 + tanh(y)` suffers from cancellation in f32
+    // for negative inputs.
+    fn reference_gelu_tanh(x: f32) -> f32 {
+        let x = x as f64;
+        let y = (2. / std::f64::consts::PI).sqrt() * (x + 0.044715 * x.powi(3));
+        (0.5 * x * (1. + y.tanh())) as f32
+    }
+
     /// Check the results of a SIMD implementation of a unary operator against
     /// a reference implementation.
     fn check_simd_vs_reference<
@@ -372,6 +493,42 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_gelu_tanh() {
+        let input: Vec<_> = arange(-6., 6., 0.001f32).collect();
+        let expected: Vec<_> = input.iter().copied().map(reference_gelu_tanh).collect();
+        let mut actual = vec![0.; input.len()];
+
+        vec_gelu_tanh(&input, actual.as_mut_slice().as_uninit());
+
+        // Relative error is large for very negative inputs, where the result
+        // approaches zero, so use an absolute tolerance.
+        check_f32s_are_equal_atol(
+            triples(&input, &actual, &expected),
+            MAX_GELU_TANH_ERROR_ATOL,
+        );
+    }
+
+    #[test]
+    fn test_swiglu() {
+        // Use a length which is not a multiple of the vector width.
+        let gate: Vec<f32> = arange(-6., 6., 0.07f32).collect();
+        let up: Vec<f32> = (0..gate.len()).map(|i| 0.5 + i as f32 * 0.03).collect();
+        let expected: Vec<f32> = gate
+            .iter()
+            .zip(&up)
+            .map(|(g, u)| reference_silu(*g) * u)
+            .collect();
+
+        let mut actual = vec![0.; gate.len()];
+        vec_swiglu(&gate, &up, actual.as_mut_slice().as_uninit());
+        check_f32s_are_equal_ulps(triples(&gate, &actual, &expected), MAX_SIGMOID_ERROR_ULPS);
+
+        let mut actual = gate.clone();
+        vec_swiglu_in_place(&mut actual, &up);
+        check_f32s_are_equal_ulps(triples(&gate, &actual, &expected), MAX_SIGMOID_ERROR_ULPS);
+    }
+
     #[test]
     #[ignore]
     fn bench_expf() {
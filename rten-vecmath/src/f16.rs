@@ -0,0 +1,406 @@
+//! Conversion between half-precision (f16) and single-precision floats.
+//!
+//! Half-precision values are represented by their IEEE 754 binary16 bit
+//! pattern, stored in a `u16`.
+
+use std::mem::MaybeUninit;
+
+use crate::exp::vec_exp_in_place;
+
+/// Convert a half-precision float, represented by its bits, to an f32.
+///
+/// This conversion is exact.
+pub fn f16_to_f32(x: u16) -> f32 {
+    let sign = ((x & 0x8000) as u32) << 16;
+    let exp = ((x >> 10) & 0x1f) as u32;
+    let mant = (x & 0x3ff) as u32;
+
+    let bits = match exp {
+        // Zero or subnormal. Subnormal values are `mant * 2^-24`, which is
+        // exactly representable as a normal f32.
+        0 => {
+            let abs = mant as f32 * f32::from_bits(0x3380_0000); // 2^-24
+            sign | abs.to_bits()
+        }
+        // Infinity or NaN.
+        0x1f => sign | 0x7f80_0000 | (mant << 13),
+        // Normal value. Re-bias exponent from 15 to 127.
+        _ => sign | ((exp + 112) << 23) | (mant << 13),
+    };
+    f32::from_bits(bits)
+}
+
+/// Convert an f32 to a half-precision float, returned as its bits.
+///
+/// Values are rounded to the nearest representable value, with ties rounded
+/// to even. Values too large for f16 become infinity.
+pub fn f32_to_f16(x: f32) -> u16 {
+    let bits = x.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xff) as i32;
+    let mant = bits & 0x7f_ffff;
+
+    // Infinity or NaN. NaNs are kept quiet.
+    if exp == 0xff {
+        let nan_bits = if mant != 0 {
+            0x200 | (mant >> 13) as u16
+        } else {
+            0
+        };
+        return sign | 0x7c00 | nan_bits;
+    }
+
+    // Re-bias exponent from 127 to 15.
+    let exp = exp - 112;
+
+    // Overflow to infinity.
+    if exp >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    // Result is zero or subnormal.
+    if exp <= 0 {
+        if exp < -10 {
+            return sign;
+        }
+        let mant = mant | 0x80_0000;
+        let shift = (14 - exp) as u32;
+        return sign | round_shift(mant, shift) as u16;
+    }
+
+    // Rounding may carry into the exponent, which correctly produces the
+    // next power of two, or infinity.
+    let rounded = round_shift(((exp as u32) << 23) | mant, 13);
+    sign | rounded as u16
+}
+
+/// Shift `x` right by `shift` bits, rounding to nearest with ties to even.
+fn round_shift(x: u32, shift: u32) -> u32 {
+    let half = 1 << (shift - 1);
+    let rem = x & ((1 << shift) - 1);
+    let y = x >> shift;
+    if rem > half || (rem == half && (y & 1) == 1) {
+        y + 1
+    } else {
+        y
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn is_f16c_supported() -> bool {
+    is_x86_feature_detected!("f16c") && is_x86_feature_detected!("avx")
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+#[target_feature(enable = "f16c")]
+unsafe fn vec_f16_to_f32_f16c(xs: &[u16], out: &mut [MaybeUninit<f32>]) {
+    use std::arch::x86_64::{__m128i, _mm256_cvtph_ps, _mm256_storeu_ps, _mm_loadu_si128};
+
+    let n_full = xs.len() / 8 * 8;
+    for i in (0..n_full).step_by(8) {
+        let x = _mm_loadu_si128(xs.as_ptr().add(i) as *const __m128i);
+        let y = _mm256_cvtph_ps(x);
+        _mm256_storeu_ps(out.as_mut_ptr().add(i) as *mut f32, y);
+    }
+    for i in n_full..xs.len() {
+        out[i].write(f16_to_f32(xs[i]));
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+#[target_feature(enable = "f16c")]
+unsafe fn vec_f32_to_f16_f16c(xs: &[f32], out: &mut [MaybeUninit<u16>]) {
+    use std::arch::x86_64::{
+        __m128i, _mm256_cvtps_ph, _mm256_loadu_ps, _mm_storeu_si128, _MM_FROUND_TO_NEAREST_INT,
+    };
+
+    let n_full = xs.len() / 8 * 8;
+    for i in (0..n_full).step_by(8) {
+        let x = _mm256_loadu_ps(xs.as_ptr().add(i));
+        let y = _mm256_cvtps_ph::<_MM_FROUND_TO_NEAREST_INT>(x);
+        _mm_storeu_si128(out.as_mut_ptr().add(i) as *mut __m128i, y);
+    }
+    for i in n_full..xs.len() {
+        out[i].write(f32_to_f16(xs[i]));
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+unsafe fn vec_f16_to_f32_neon(xs: &[u16], out: &mut [MaybeUninit<f32>]) {
+    use std::arch::aarch64::{float32x4_t, vst1q_f32};
+    use std::arch::asm;
+
+    // The half-precision conversion intrinsics are not stable, so the
+    // instruction is used directly. The input is loaded as a `u64` holding
+    // four f16 values.
+    let n_full = xs.len() / 4 * 4;
+    for i in (0..n_full).step_by(4) {
+        let x = (xs.as_ptr().add(i) as *const u64).read_unaligned();
+        let y: float32x4_t;
+        asm!(
+            "fcvtl {y:v}.4s, {x:v}.4h",
+            x = in(vreg) x,
+            y = out(vreg) y,
+            options(pure, nomem, nostack)
+        );
+        vst1q_f32(out.as_mut_ptr().add(i) as *mut f32, y);
+    }
+    for i in n_full..xs.len() {
+        out[i].write(f16_to_f32(xs[i]));
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+unsafe fn vec_f32_to_f16_neon(xs: &[f32], out: &mut [MaybeUninit<u16>]) {
+    use std::arch::aarch64::vld1q_f32;
+    use std::arch::asm;
+
+    // `fcvtn` rounds using the current rounding mode, which is
+    // round-to-nearest-even by default.
+    let n_full = xs.len() / 4 * 4;
+    for i in (0..n_full).step_by(4) {
+        let x = vld1q_f32(xs.as_ptr().add(i));
+        let y: u64;
+        asm!(
+            "fcvtn {y:v}.4h, {x:v}.4s",
+            x = in(vreg) x,
+            y = out(vreg) y,
+            options(pure, nomem, nostack)
+        );
+        (out.as_mut_ptr().add(i) as *mut u64).write_unaligned(y);
+    }
+    for i in n_full..xs.len() {
+        out[i].write(f32_to_f16(xs[i]));
+    }
+}
+
+/// Convert a slice of half-precision floats to f32.
+///
+/// This uses F16C instructions if available on x86_64 and NEON instructions
+/// on Arm. On other platforms it uses a portable loop which the compiler may
+/// auto-vectorize.
+///
+/// After this function returns, `out` will be fully initialized.
+pub fn vec_f16_to_f32(xs: &[u16], out: &mut [MaybeUninit<f32>]) {
+    assert_eq!(xs.len(), out.len());
+
+    #[cfg(target_arch = "x86_64")]
+    if is_f16c_supported() {
+        // Safety: We checked that the required instructions are available.
+        unsafe { vec_f16_to_f32_f16c(xs, out) };
+        return;
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    {
+        // Safety: NEON and half-precision conversions are always available
+        // on aarch64.
+        unsafe { vec_f16_to_f32_neon(xs, out) };
+    }
+
+    #[cfg(not(target_arch = "aarch64"))]
+    for (x, y) in xs.iter().zip(out.iter_mut()) {
+        y.write(f16_to_f32(*x));
+    }
+}
+
+/// Convert a slice of f32 values to half-precision floats.
+///
+/// See [vec_f16_to_f32] for notes on vectorization and [f32_to_f16] for
+/// rounding behavior.
+///
+/// After this function returns, `out` will be fully initialized.
+pub fn vec_f32_to_f16(xs: &[f32], out: &mut [MaybeUninit<u16>]) {
+    assert_eq!(xs.len(), out.len());
+
+    #[cfg(target_arch = "x86_64")]
+    if is_f16c_supported() {
+        // Safety: We checked that the required instructions are available.
+        unsafe { vec_f32_to_f16_f16c(xs, out) };
+        return;
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    {
+        // Safety: NEON and half-precision conversions are always available
+        // on aarch64.
+        unsafe { vec_f32_to_f16_neon(xs, out) };
+    }
+
+    #[cfg(not(target_arch = "aarch64"))]
+    for (x, y) in xs.iter().zip(out.iter_mut()) {
+        y.write(f32_to_f16(*x));
+    }
+}
+
+/// Compute softmax over a slice of half-precision floats, in-place.
+///
+/// The computation is performed in f32 and the result is rounded back to
+/// f16. The input is converted in fixed-size chunks using a buffer on the
+/// stack, so this does not allocate.
+pub fn vec_softmax_f16_in_place(xs: &mut [u16]) {
+    const CHUNK_SIZE: usize = 256;
+    let mut buf = [0f32; CHUNK_SIZE];
+
+    // Convert a chunk of `xs` to f32, subtract `offset` and return the
+    // converted values.
+    fn load_chunk<'a>(chunk: &[u16], buf: &'a mut [f32], offset: f32) -> &'a mut [f32] {
+        let buf = &mut buf[..chunk.len()];
+        // Safety: `f32` and `MaybeUninit<f32>` have the same layout, and
+        // `vec_f16_to_f32` only writes initialized values.
+        let uninit_buf =
+            unsafe { std::mem::transmute::<&mut [f32], &mut [MaybeUninit<f32>]>(&mut *buf) };
+        vec_f16_to_f32(chunk, uninit_buf);
+        if offset != 0. {
+            for x in buf.iter_mut() {
+                *x -= offset;
+            }
+        }
+        buf
+    }
+
+    let max = xs
+        .chunks(CHUNK_SIZE)
+        .map(|chunk| {
+            load_chunk(chunk, &mut buf, 0.)
+                .iter()
+                .copied()
+                .fold(f32::NEG_INFINITY, f32::max)
+        })
+        .fold(f32::NEG_INFINITY, f32::max);
+
+    let mut sum = 0.;
+    for chunk in xs.chunks(CHUNK_SIZE) {
+        let exps = load_chunk(chunk, &mut buf, max);
+        vec_exp_in_place(exps);
+        sum += exps.iter().sum::<f32>();
+    }
+
+    let scale = 1. / sum;
+    for chunk in xs.chunks_mut(CHUNK_SIZE) {
+        let probs = load_chunk(chunk, &mut buf, max);
+        vec_exp_in_place(probs);
+        for x in probs.iter_mut() {
+            *x *= scale;
+        }
+
+        // Safety: `u16` and `MaybeUninit<u16>` have the same layout.
+        let out = unsafe { std::mem::transmute::<&mut [u16], &mut [MaybeUninit<u16>]>(chunk) };
+        vec_f32_to_f16(probs, out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{f16_to_f32, f32_to_f16, vec_f16_to_f32, vec_f32_to_f16, vec_softmax_f16_in_place};
+    use crate::testing::AsUninit;
+
+    #[test]
+    fn test_f16_to_f32() {
+        let cases = [
+            (0x0000, 0.),
+            (0x8000, -0.),
+            (0x3c00, 1.),
+            (0xc000, -2.),
+            (0x3555, 0.33325195),
+            (0x7bff, 65504.),
+            (0x0001, 5.9604645e-8), // Smallest subnormal
+            (0x03ff, 6.097555e-5),  // Largest subnormal
+            (0x0400, 6.1035156e-5), // Smallest normal
+            (0x7c00, f32::INFINITY),
+            (0xfc00, f32::NEG_INFINITY),
+        ];
+        for (bits, expected) in cases {
+            let actual = f16_to_f32(bits);
+            assert_eq!(actual, expected, "mismatch for {:#06x}", bits);
+            assert_eq!(actual.is_sign_negative(), expected.is_sign_negative());
+        }
+        assert!(f16_to_f32(0x7e00).is_nan());
+    }
+
+    #[test]
+    fn test_f32_to_f16() {
+        let cases = [
+            (0., 0x0000),
+            (-0., 0x8000),
+            (1., 0x3c00),
+            (-2., 0xc000),
+            (65504., 0x7bff),
+            (65520., 0x7c00), // Rounds up to infinity
+            (1e6, 0x7c00),
+            (-1e6, 0xfc00),
+            (5.9604645e-8, 0x0001),
+            (2.9802322e-8, 0x0000), // Tie between 0 and smallest subnormal
+            (1e-9, 0x0000),
+            (f32::INFINITY, 0x7c00),
+            // 1 + 2^-11 is a tie between 1 and the next f16, rounds to even.
+            (1.0004883, 0x3c00),
+            // 1 + 3 * 2^-11 is a tie which rounds up to even.
+            (1.0014648, 0x3c02),
+        ];
+        for (val, expected) in cases {
+            assert_eq!(f32_to_f16(val), expected, "mismatch for {}", val);
+        }
+        assert_eq!(f32_to_f16(f32::NAN) & 0x7e00, 0x7e00);
+    }
+
+    #[test]
+    fn test_f16_round_trip() {
+        for bits in 0..=u16::MAX {
+            let x = f16_to_f32(bits);
+            if x.is_nan() {
+                continue;
+            }
+            assert_eq!(f32_to_f16(x), bits);
+        }
+    }
+
+    #[test]
+    fn test_vec_f16_conversions() {
+        // Use a length which is not a multiple of the vector width.
+        let input: Vec<f32> = (0..37).map(|i| (i as f32 - 18.) * 0.37).collect();
+        let expected_f16: Vec<u16> = input.iter().copied().map(f32_to_f16).collect();
+
+        let mut actual_f16 = vec![0u16; input.len()];
+        vec_f32_to_f16(&input, actual_f16.as_mut_slice().as_uninit());
+        assert_eq!(actual_f16, expected_f16);
+
+        let expected_f32: Vec<f32> = expected_f16.iter().copied().map(f16_to_f32).collect();
+        let mut actual_f32 = vec![0.; input.len()];
+        vec_f16_to_f32(&actual_f16, actual_f32.as_mut_slice().as_uninit());
+        assert_eq!(actual_f32, expected_f32);
+    }
+
+    #[test]
+    fn test_vec_softmax_f16_in_place_chunks() {
+        // Use a length which spans several chunks.
+        let input: Vec<f32> = (0..600).map(|i| (i % 7) as f32 * 0.5).collect();
+        let mut xs: Vec<u16> = input.iter().copied().map(f32_to_f16).collect();
+        vec_softmax_f16_in_place(&mut xs);
+
+        let sum_exp: f32 = input.iter().map(|x| x.exp()).sum();
+        for (x, y) in xs.into_iter().map(f16_to_f32).zip(&input) {
+            let expected = y.exp() / sum_exp;
+            assert!(
+                (x - expected).abs() < expected * 1e-3,
+                "{} != {}",
+                x,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_vec_softmax_f16_in_place() {
+        let mut xs: Vec<u16> = [1., 2., 3., 4.].into_iter().map(f32_to_f16).collect();
+        vec_softmax_f16_in_place(&mut xs);
+
+        let actual: Vec<f32> = xs.into_iter().map(f16_to_f32).collect();
+        let expected = [0.0320586, 0.08714432, 0.23688284, 0.6439143];
+        for (x, y) in actual.iter().zip(expected) {
+            assert!((x - y).abs() < 1e-3, "{} != {}", x, y);
+        }
+    }
+}
@@ -19,8 +19,15 @@
 
 mod erf;
 mod exp;
+mod f16;
+mod log;
+mod norm;
+mod quantize;
+mod sin_cos;
 mod softmax;
+mod sum;
 mod tanh;
+mod topk;
 
 #[cfg(test)]
 mod ulp;
@@ -30,8 +37,22 @@ mod testing;
 
 pub use erf::{erf, gelu, vec_erf, vec_erf_in_place, vec_gelu, vec_gelu_in_place};
 pub use exp::{
-    exp, sigmoid, silu, vec_exp, vec_exp_in_place, vec_sigmoid, vec_sigmoid_in_place, vec_silu,
-    vec_silu_in_place,
+    exp, gelu_tanh, sigmoid, silu, vec_exp, vec_exp_in_place, vec_gelu_tanh,
+    vec_gelu_tanh_in_place, vec_sigmoid, vec_sigmoid_in_place, vec_silu, vec_silu_in_place,
+    vec_swiglu, vec_swiglu_in_place,
 };
-pub use softmax::{vec_softmax, vec_softmax_in_place};
+pub use f16::{f16_to_f32, f32_to_f16, vec_f16_to_f32, vec_f32_to_f16, vec_softmax_f16_in_place};
+pub use log::{log, vec_log, vec_log_in_place};
+pub use norm::{vec_layer_norm, vec_layer_norm_in_place, vec_rms_norm, vec_rms_norm_in_place};
+pub use quantize::{
+    vec_dequantize_u8_to_f32, vec_dequantize_u8_to_f32_per_channel, vec_quantize_f32_to_u8,
+    vec_quantize_f32_to_u8_per_channel,
+};
+pub use sin_cos::{cos, sin, vec_cos, vec_cos_in_place, vec_sin, vec_sin_in_place};
+pub use softmax::{
+    vec_masked_softmax, vec_masked_softmax_in_place, vec_softmax, vec_softmax_in_place,
+    vec_softmax_with_temperature, vec_softmax_with_temperature_in_place,
+};
+pub use sum::{vec_mean_variance, vec_sum, vec_sum_square, Summation};
 pub use tanh::{tanh, vec_tanh, vec_tanh_in_place};
+pub use topk::{argmax, top_k};
@@ -0,0 +1,188 @@
+//! Vectorized natural logarithm.
+
+#![allow(clippy::excessive_precision)]
+
+use std::mem::MaybeUninit;
+
+use rten_simd::dispatch::{dispatch_map_op, dispatch_map_op_in_place, SimdUnaryOp};
+use rten_simd::{SimdFloat, SimdInt};
+
+// `log(2)` split into large and small parts, such that `k * LOG2_HI` is exact
+// for the exponents of all f32 values.
+const LOG2_HI: f32 = 0.693359375;
+const LOG2_LO: f32 = -2.12194440e-4;
+
+// Coefficients of polynomial used to approximate `(log(1 + f) - f + f^2/2) /
+// f^3` in `[sqrt(0.5) - 1, sqrt(2) - 1]`. From the Cephes library.
+const LOG_POLY: [f32; 9] = [
+    3.3333331174e-1,
+    -2.4999993993e-1,
+    2.0000714765e-1,
+    -1.6668057665e-1,
+    1.4249322787e-1,
+    -1.2420140846e-1,
+    1.1676998740e-1,
+    -1.1514610310e-1,
+    7.0376836292e-2,
+];
+
+/// Computes the natural logarithm of `x`. Functionally equivalent to
+/// [f32::ln].
+///
+/// This is a scalar variant of [vec_log] that uses exactly the same algorithm.
+pub fn log(x: f32) -> f32 {
+    // Safety: f32 is available on all systems.
+    unsafe { simd_log(x) }
+}
+
+/// Vectorized implementation of the natural logarithm.
+///
+/// Method outline:
+///
+///  1. Split `x` into an exponent and mantissa such that
+///     `x = 2**k * m` where `k` is an integer and `sqrt(0.5) <= m < sqrt(2)`.
+///     Subnormal inputs are first scaled up to be normal.
+///
+///  2. Compute `log(m)` as `log(1 + f)` where `f = m - 1`, using a
+///     polynomial approximation.
+///
+///  3. Compute the result as `log(x) = k * ln2 + log(m)`.
+///
+/// Negative inputs return NaN, zero returns negative infinity and positive
+/// infinity is returned unchanged.
+///
+/// Safety: The caller must ensure the `SimdFloat` impl is usable on the current system.
+#[inline(always)]
+unsafe fn simd_log<S: SimdFloat>(x: S) -> S {
+    // Scale subnormal inputs by 2^23 so they become normal.
+    let subnormal_mask = x.lt(S::splat(f32::MIN_POSITIVE));
+    let x_norm = x.blend(x.mul(S::splat(8388608.)), subnormal_mask);
+    let k_offset = S::zero().blend(S::splat(23.), subnormal_mask);
+
+    // Split into biased exponent and mantissa in `[1, 2)`. This assumes the
+    // sign bit is clear. Negative inputs are handled below.
+    let bits = x_norm.reinterpret_as_int();
+    let biased_exp = bits.shr::<23>();
+    let m = bits
+        .sub(biased_exp.sub(S::Int::splat(127)).shl::<23>())
+        .reinterpret_as_float();
+
+    // Convert the biased exponent, which is in `[0, 255]`, to a float by
+    // placing it in the mantissa of `2^23`.
+    let k = biased_exp
+        .add(S::Int::splat(0x4b000000))
+        .reinterpret_as_float()
+        .sub(S::splat(8388608. + 127.))
+        .sub(k_offset);
+
+    // Adjust the range of `m` to `[sqrt(0.5), sqrt(2))`, so that `f` is
+    // centered around zero.
+    let m_large = S::splat(std::f32::consts::SQRT_2).le(m);
+    let m = m.blend(m.mul(S::splat(0.5)), m_large);
+    let k = k.blend(k.add(S::one()), m_large);
+
+    let f = m.sub(S::one());
+    let f_sqr = f.mul(f);
+
+    let mut p = S::splat(LOG_POLY[8]);
+    for coeff in LOG_POLY[..8].iter().rev() {
+        p = p.mul_add(f, S::splat(*coeff));
+    }
+    let y = f.mul(f_sqr).mul(p);
+    let y = k.mul_add(S::splat(LOG2_LO), y);
+    let y = f_sqr.mul_add(S::splat(-0.5), y);
+    let y = f.add(y);
+    let y = k.mul_add(S::splat(LOG2_HI), y);
+
+    // Handle special cases. The order of blends matters: `x <= 0` must be
+    // handled before `x >= 0` so that negative values produce NaN.
+    let y = y.blend(S::splat(f32::INFINITY), x.ge(S::splat(f32::INFINITY)));
+    let y = y.blend(S::splat(f32::NEG_INFINITY), x.le(S::zero()));
+
+    // Negative and NaN inputs produce NaN.
+    S::splat(f32::NAN).blend(y, x.ge(S::zero()))
+}
+
+struct SimdLog {}
+impl SimdUnaryOp for SimdLog {
+    #[inline(always)]
+    unsafe fn eval<S: SimdFloat>(&self, x: S) -> S {
+        simd_log(x)
+    }
+}
+
+/// Vectorized natural logarithm.
+///
+/// This is a vectorized version of [log] that computes the function for each
+/// element in `xs` and writes the result to `out`. `xs` and `out` must be
+/// equal in length.
+///
+/// `out` will be fully initialized after this function returns.
+pub fn vec_log(xs: &[f32], out: &mut [MaybeUninit<f32>]) {
+    dispatch_map_op(xs, out, SimdLog {});
+}
+
+/// Variant of [vec_log] that modifies elements in-place.
+pub fn vec_log_in_place(xs: &mut [f32]) {
+    dispatch_map_op_in_place(xs, SimdLog {});
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{log, vec_log, vec_log_in_place};
+    use crate::testing::{
+        arange, benchmark_op, check_f32s_are_equal_ulps, check_with_all_f32s, triples, AsUninit,
+    };
+
+    // Maximum error of `vec_log` compared to `f32::ln`.
+    const MAX_LOG_ERROR_ULPS: f32 = 2.0;
+
+    #[test]
+    fn test_log() {
+        let cases = [
+            (1., 0.),
+            (std::f32::consts::E, 1.),
+            (0., f32::NEG_INFINITY),
+            (-0., f32::NEG_INFINITY),
+            (f32::INFINITY, f32::INFINITY),
+        ];
+        for (x, expected) in cases {
+            assert_eq!(log(x), expected, "mismatch for {}", x);
+        }
+        for x in [-1., f32::NEG_INFINITY, f32::NAN] {
+            assert!(log(x).is_nan());
+        }
+    }
+
+    #[test]
+    fn test_vec_log() {
+        let cases: Vec<f32> = arange(0.001, 10., 0.001f32)
+            .chain(arange(10., 1e6, 7.3))
+            .chain([1e-40, f32::MIN_POSITIVE, 1e-30, 1e30, f32::MAX])
+            .collect();
+        let expected: Vec<f32> = cases.iter().map(|x| x.ln()).collect();
+
+        let mut actual = vec![0.; cases.len()];
+        vec_log(&cases, actual.as_mut_slice().as_uninit());
+        check_f32s_are_equal_ulps(triples(&cases, &actual, &expected), MAX_LOG_ERROR_ULPS);
+
+        let mut actual = cases.clone();
+        vec_log_in_place(&mut actual);
+        check_f32s_are_equal_ulps(triples(&cases, &actual, &expected), MAX_LOG_ERROR_ULPS);
+    }
+
+    #[test]
+    #[ignore] // Ignored by default due to long runtime
+    fn test_log_exhaustive() {
+        check_with_all_f32s(|x| (log(x), x.ln()), MAX_LOG_ERROR_ULPS, "testing log");
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_log() {
+        benchmark_op(
+            |xs, ys| xs.iter().zip(ys.iter_mut()).for_each(|(x, y)| *y = x.ln()),
+            vec_log,
+        );
+    }
+}
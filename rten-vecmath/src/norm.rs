@@ -0,0 +1,267 @@
+use std::mem::MaybeUninit;
+
+use rten_simd::dispatch::{SimdDispatcher, SimdOp};
+use rten_simd::span::{MutPtrLen, PtrLen};
+use rten_simd::{SimdFloat, MAX_LEN};
+
+use crate::sum::{vec_mean_variance, vec_sum_square, Summation};
+
+/// Load a vector from `ptr + offset`, where fewer than `S::LEN` elements may
+/// remain before the end of the buffer of length `len`. Missing elements are
+/// set to `pad`.
+#[inline(always)]
+unsafe fn load_padded<S: SimdFloat>(ptr: *const f32, offset: usize, len: usize, pad: f32) -> S {
+    let remaining = len - offset;
+    if remaining >= S::LEN {
+        S::load(ptr.add(offset))
+    } else {
+        let mut buf = [pad; MAX_LEN];
+        for i in 0..remaining {
+            buf[i] = *ptr.add(offset + i);
+        }
+        S::load(buf.as_ptr())
+    }
+}
+
+/// Compute `(x - mean) * inv_std_dev` for elements of `input`, multiply by
+/// `scale` and add `bias`, if present, and write the results to `output`.
+#[inline(always)]
+unsafe fn simd_normalize<S: SimdFloat>(
+    input: PtrLen<f32>,
+    output: MutPtrLen<MaybeUninit<f32>>,
+    scale: Option<PtrLen<f32>>,
+    bias: Option<PtrLen<f32>>,
+    mean: f32,
+    inv_std_dev: f32,
+) {
+    assert!(S::LEN <= MAX_LEN);
+    assert!(input.len() == output.len());
+    let n = input.len();
+    if let Some(scale) = scale {
+        assert!(scale.len() == n);
+    }
+    if let Some(bias) = bias {
+        assert!(bias.len() == n);
+    }
+
+    let inv_std_dev = S::splat(inv_std_dev);
+    let mean = S::splat(mean);
+
+    let mut tail = [0.; MAX_LEN];
+    for i in (0..n).step_by(S::LEN) {
+        let x = load_padded::<S>(input.ptr(), i, n, 0.);
+        let mut y = x.sub(mean).mul(inv_std_dev);
+        if let Some(scale) = scale {
+            y = y.mul(load_padded(scale.ptr(), i, n, 0.));
+        }
+        if let Some(bias) = bias {
+            y = y.add(load_padded(bias.ptr(), i, n, 0.));
+        }
+
+        let remaining = n - i;
+        if remaining >= S::LEN {
+            y.store(output.ptr().add(i) as *mut f32);
+        } else {
+            y.store(tail.as_mut_ptr());
+            for j in 0..remaining {
+                output.ptr().add(i + j).write(MaybeUninit::new(tail[j]));
+            }
+        }
+    }
+}
+
+struct SimdNormalize {
+    input: PtrLen<f32>,
+    output: MutPtrLen<MaybeUninit<f32>>,
+    scale: Option<PtrLen<f32>>,
+    bias: Option<PtrLen<f32>>,
+    mean: f32,
+    inv_std_dev: f32,
+}
+
+impl SimdOp for SimdNormalize {
+    #[inline(always)]
+    unsafe fn eval<S: SimdFloat>(&self) {
+        simd_normalize::<S>(
+            self.input,
+            self.output,
+            self.scale,
+            self.bias,
+            self.mean,
+            self.inv_std_dev,
+        )
+    }
+}
+
+/// Return the `(mean, 1 / sqrt(variance + epsilon))` statistics used to
+/// normalize `xs`.
+///
+/// If `center` is false, the mean is taken to be zero and the mean square is
+/// used in place of the variance (RMS normalization).
+fn norm_stats(xs: &[f32], center: bool, epsilon: f32) -> (f32, f32) {
+    let (mean, var) = if center {
+        vec_mean_variance(xs, Summation::Fast)
+    } else {
+        (0., vec_sum_square(xs, Summation::Fast) / xs.len() as f32)
+    };
+    (mean, 1. / (var + epsilon).sqrt())
+}
+
+fn dispatch_normalize(
+    input: PtrLen<f32>,
+    output: MutPtrLen<MaybeUninit<f32>>,
+    scale: Option<&[f32]>,
+    bias: Option<&[f32]>,
+    (mean, inv_std_dev): (f32, f32),
+) {
+    let op = SimdNormalize {
+        input,
+        output,
+        scale: scale.map(|s| s.into()),
+        bias: bias.map(|b| b.into()),
+        mean,
+        inv_std_dev,
+    };
+    let dispatcher = SimdDispatcher::default();
+    dispatcher.dispatch(op);
+}
+
+/// Computes [layer normalization][layer_norm] over a slice of floats.
+///
+/// This computes `(x - mean) / sqrt(variance + epsilon) * scale + bias`, where
+/// `scale` and `bias` are optional slices with the same length as `xs`. The
+/// mean and variance are computed using [vec_mean_variance].
+///
+/// `out` will be fully initialized after this function returns.
+///
+/// [layer_norm]: https://arxiv.org/abs/1607.06450
+pub fn vec_layer_norm(
+    xs: &[f32],
+    out: &mut [MaybeUninit<f32>],
+    scale: Option<&[f32]>,
+    bias: Option<&[f32]>,
+    epsilon: f32,
+) {
+    let stats = norm_stats(xs, true, epsilon);
+    dispatch_normalize(xs.into(), out.into(), scale, bias, stats);
+}
+
+/// In-place variant of [vec_layer_norm].
+pub fn vec_layer_norm_in_place(
+    xs: &mut [f32],
+    scale: Option<&[f32]>,
+    bias: Option<&[f32]>,
+    epsilon: f32,
+) {
+    let stats = norm_stats(xs, true, epsilon);
+    let out: MutPtrLen<f32> = xs.into();
+    dispatch_normalize(xs.into(), out.as_uninit(), scale, bias, stats);
+}
+
+/// Computes [RMS normalization][rms_norm] over a slice of floats.
+///
+/// This computes `x / sqrt(mean(x^2) + epsilon) * scale`, where `scale` is an
+/// optional slice with the same length as `xs`.
+///
+/// `out` will be fully initialized after this function returns.
+///
+/// [rms_norm]: https://arxiv.org/abs/1910.07467
+pub fn vec_rms_norm(xs: &[f32], out: &mut [MaybeUninit<f32>], scale: Option<&[f32]>, epsilon: f32) {
+    let stats = norm_stats(xs, false, epsilon);
+    dispatch_normalize(xs.into(), out.into(), scale, None, stats);
+}
+
+/// In-place variant of [vec_rms_norm].
+pub fn vec_rms_norm_in_place(xs: &mut [f32], scale: Option<&[f32]>, epsilon: f32) {
+    let stats = norm_stats(xs, false, epsilon);
+    let out: MutPtrLen<f32> = xs.into();
+    dispatch_normalize(xs.into(), out.as_uninit(), scale, None, stats);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{vec_layer_norm, vec_layer_norm_in_place, vec_rms_norm, vec_rms_norm_in_place};
+    use crate::testing::{check_f32s_are_equal_atol, triples, AsUninit};
+
+    fn reference_layer_norm(
+        xs: &[f32],
+        scale: Option<&[f32]>,
+        bias: Option<&[f32]>,
+        epsilon: f32,
+    ) -> Vec<f32> {
+        let n = xs.len() as f32;
+        let mean = xs.iter().sum::<f32>() / n;
+        let var = xs.iter().map(|x| (x - mean) * (x - mean)).sum::<f32>() / n;
+        let inv_std = 1. / (var + epsilon).sqrt();
+        xs.iter()
+            .enumerate()
+            .map(|(i, x)| {
+                let y = (x - mean) * inv_std * scale.map(|s| s[i]).unwrap_or(1.);
+                y + bias.map(|b| b[i]).unwrap_or(0.)
+            })
+            .collect()
+    }
+
+    fn reference_rms_norm(xs: &[f32], scale: Option<&[f32]>, epsilon: f32) -> Vec<f32> {
+        let n = xs.len() as f32;
+        let mean_sqr = xs.iter().map(|x| x * x).sum::<f32>() / n;
+        let inv_rms = 1. / (mean_sqr + epsilon).sqrt();
+        xs.iter()
+            .enumerate()
+            .map(|(i, x)| x * inv_rms * scale.map(|s| s[i]).unwrap_or(1.))
+            .collect()
+    }
+
+    // Use a length which is not a multiple of the vector width.
+    fn test_inputs() -> (Vec<f32>, Vec<f32>, Vec<f32>) {
+        let xs: Vec<f32> = (0..37)
+            .map(|i| 100. + ((i * 7) % 13) as f32 * 0.1)
+            .collect();
+        let scale: Vec<f32> = (0..xs.len()).map(|i| 0.5 + i as f32 * 0.01).collect();
+        let bias: Vec<f32> = (0..xs.len()).map(|i| i as f32 * -0.02).collect();
+        (xs, scale, bias)
+    }
+
+    #[test]
+    fn test_vec_layer_norm() {
+        let (xs, scale, bias) = test_inputs();
+        let epsilon = 1e-5;
+
+        for (scale, bias) in [(None, None), (Some(&scale[..]), Some(&bias[..]))] {
+            let expected = reference_layer_norm(&xs, scale, bias, epsilon);
+
+            let mut actual = vec![0.; xs.len()];
+            vec_layer_norm(&xs, actual.as_mut_slice().as_uninit(), scale, bias, epsilon);
+            check_f32s_are_equal_atol(triples(&xs, &actual, &expected), 1e-3);
+
+            let mut actual = xs.clone();
+            vec_layer_norm_in_place(&mut actual, scale, bias, epsilon);
+            check_f32s_are_equal_atol(triples(&xs, &actual, &expected), 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_vec_rms_norm() {
+        let (xs, scale, _) = test_inputs();
+        let epsilon = 1e-6;
+
+        for scale in [None, Some(&scale[..])] {
+            let expected = reference_rms_norm(&xs, scale, epsilon);
+
+            let mut actual = vec![0.; xs.len()];
+            vec_rms_norm(&xs, actual.as_mut_slice().as_uninit(), scale, epsilon);
+            check_f32s_are_equal_atol(triples(&xs, &actual, &expected), 1e-5);
+
+            let mut actual = xs.clone();
+            vec_rms_norm_in_place(&mut actual, scale, epsilon);
+            check_f32s_are_equal_atol(triples(&xs, &actual, &expected), 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_vec_norm_empty() {
+        let mut xs: Vec<f32> = Vec::new();
+        vec_layer_norm_in_place(&mut xs, None, None, 1e-5);
+        vec_rms_norm_in_place(&mut xs, None, 1e-5);
+    }
+}
@@ -0,0 +1,335 @@
+//! Conversion between f32 values and linearly quantized u8 values.
+//!
+//! Quantized values are mapped to real values as `(q - zero_point) * scale`,
+//! as in the ONNX `QuantizeLinear` and `DequantizeLinear` operators.
+
+use std::mem::MaybeUninit;
+
+use rten_simd::dispatch::{SimdDispatcher, SimdOp};
+use rten_simd::span::{MutPtrLen, PtrLen};
+use rten_simd::{SimdFloat, SimdInt, SimdInt16, SimdInt8, MAX_LEN};
+
+// Adding an integer in `[0, 2^23)` to the bits of this value, which is
+// `2^23`, produces the float `2^23 + value`.
+const INT_TO_FLOAT_MAGIC: i32 = 0x4b000000;
+
+const ROUNDING_MAGIC: f32 = 12582912.; // 0x3 << 22
+
+// Quantized values are processed as i8 by offsetting them by -128, so that
+// they can be widened and narrowed using signed operations. Adding `i8::MIN`
+// with wrapping maps a u8 value `q` to `q - 128` and back.
+const U8_TO_I8_OFFSET: i8 = i8::MIN;
+
+#[inline(always)]
+unsafe fn simd_dequantize<S: SimdFloat>(
+    input: PtrLen<u8>,
+    output: MutPtrLen<MaybeUninit<f32>>,
+    scale: f32,
+    zero_point: u8,
+) {
+    type Int8<S> = <<S as SimdFloat>::Int as SimdInt>::Int8;
+
+    // Each block of input fills one vector of 8-bit ints, which is widened
+    // into four vectors of 32-bit ints.
+    let block_len = Int8::<S>::LEN;
+    assert!(block_len <= MAX_LEN * 4);
+    assert_eq!(input.len(), output.len());
+    let n = input.len();
+
+    let u8_offset = Int8::<S>::splat(U8_TO_I8_OFFSET);
+    let magic = S::Int::splat(INT_TO_FLOAT_MAGIC - U8_TO_I8_OFFSET as i32);
+    let offset = S::splat(8388608. + zero_point as f32);
+    let scale = S::splat(scale);
+
+    let dequantize_block = |src: *const u8, dst: *mut f32| {
+        let x = Int8::<S>::load(src as *const i8).add(u8_offset);
+        let (lo, hi) = x.widen();
+        let (x0, x1) = lo.widen();
+        let (x2, x3) = hi.widen();
+
+        for (k, x) in [x0, x1, x2, x3].into_iter().enumerate() {
+            // Convert to float and subtract the zero point, both of which are
+            // exact, then scale.
+            let y = x.add(magic).reinterpret_as_float().sub(offset).mul(scale);
+            y.store(dst.add(k * S::LEN));
+        }
+    };
+
+    let mut tmp_in = [0u8; MAX_LEN * 4];
+    let mut tmp_out = [0f32; MAX_LEN * 4];
+    for i in (0..n).step_by(block_len) {
+        let len = block_len.min(n - i);
+        let out_ptr = output.ptr().add(i) as *mut f32;
+        if len == block_len {
+            dequantize_block(input.ptr().add(i), out_ptr);
+        } else {
+            std::ptr::copy_nonoverlapping(input.ptr().add(i), tmp_in.as_mut_ptr(), len);
+            dequantize_block(tmp_in.as_ptr(), tmp_out.as_mut_ptr());
+            std::ptr::copy_nonoverlapping(tmp_out.as_ptr(), out_ptr, len);
+        }
+    }
+}
+
+#[inline(always)]
+unsafe fn simd_quantize<S: SimdFloat>(
+    input: PtrLen<f32>,
+    output: MutPtrLen<MaybeUninit<u8>>,
+    scale: f32,
+    zero_point: u8,
+) {
+    type Int8<S> = <<S as SimdFloat>::Int as SimdInt>::Int8;
+    type Int16<S> = <Int8<S> as SimdInt8>::Int16;
+
+    // Each block of input fills four vectors of 32-bit ints, which are
+    // narrowed into one vector of 8-bit ints.
+    let block_len = Int8::<S>::LEN;
+    assert!(block_len <= MAX_LEN * 4);
+    assert_eq!(input.len(), output.len());
+    let n = input.len();
+
+    let u8_offset = Int8::<S>::splat(U8_TO_I8_OFFSET);
+    let scale = S::splat(scale);
+    let zero_point = S::splat(zero_point as f32 + U8_TO_I8_OFFSET as f32);
+    let rounding_magic = S::splat(ROUNDING_MAGIC);
+    let min = S::splat(i8::MIN as f32);
+    let max = S::splat(i8::MAX as f32);
+
+    let quantize_block = |src: *const f32, dst: *mut u8| {
+        let [y0, y1, y2, y3] = std::array::from_fn(|k| {
+            let x = S::load(src.add(k * S::LEN));
+
+            // Round to nearest, with ties to even. Values are clamped first so
+            // they are within the range where the rounding trick is exact.
+            let y = x.div(scale).max(S::splat(-1024.)).min(S::splat(1024.));
+            let y = y.add(rounding_magic).sub(rounding_magic);
+            y.add(zero_point).max(min).min(max).to_int_trunc()
+        });
+
+        let lo = Int16::<S>::narrow_saturate(y0, y1);
+        let hi = Int16::<S>::narrow_saturate(y2, y3);
+        Int8::<S>::narrow_saturate(lo, hi)
+            .add(u8_offset)
+            .store(dst as *mut i8);
+    };
+
+    let mut tmp_in = [0f32; MAX_LEN * 4];
+    let mut tmp_out = [0u8; MAX_LEN * 4];
+    for i in (0..n).step_by(block_len) {
+        let len = block_len.min(n - i);
+        let out_ptr = output.ptr().add(i) as *mut u8;
+        if len == block_len {
+            quantize_block(input.ptr().add(i), out_ptr);
+        } else {
+            std::ptr::copy_nonoverlapping(input.ptr().add(i), tmp_in.as_mut_ptr(), len);
+            quantize_block(tmp_in.as_ptr(), tmp_out.as_mut_ptr());
+            std::ptr::copy_nonoverlapping(tmp_out.as_ptr(), out_ptr, len);
+        }
+    }
+}
+
+struct SimdDequantize {
+    input: PtrLen<u8>,
+    output: MutPtrLen<MaybeUninit<f32>>,
+    scale: f32,
+    zero_point: u8,
+}
+
+impl SimdOp for SimdDequantize {
+    #[inline(always)]
+    unsafe fn eval<S: SimdFloat>(&self) {
+        simd_dequantize::<S>(self.input, self.output, self.scale, self.zero_point)
+    }
+}
+
+struct SimdQuantize {
+    input: PtrLen<f32>,
+    output: MutPtrLen<MaybeUninit<u8>>,
+    scale: f32,
+    zero_point: u8,
+}
+
+impl SimdOp for SimdQuantize {
+    #[inline(always)]
+    unsafe fn eval<S: SimdFloat>(&self) {
+        simd_quantize::<S>(self.input, self.output, self.scale, self.zero_point)
+    }
+}
+
+/// Dequantize u8 values using a single scale and zero point.
+///
+/// This computes `(x - zero_point) * scale` for each element in `xs` and
+/// writes the result to `out`. `xs` and `out` must be equal in length.
+///
+/// `out` will be fully initialized after this function returns.
+pub fn vec_dequantize_u8_to_f32(
+    xs: &[u8],
+    out: &mut [MaybeUninit<f32>],
+    scale: f32,
+    zero_point: u8,
+) {
+    let op = SimdDequantize {
+        input: xs.into(),
+        output: out.into(),
+        scale,
+        zero_point,
+    };
+    let dispatcher = SimdDispatcher::default();
+    dispatcher.dispatch(op);
+}
+
+/// Quantize f32 values to u8 using a single scale and zero point.
+///
+/// This computes `clamp(round(x / scale) + zero_point, 0, 255)` for each
+/// element in `xs` and writes the result to `out`, where `round` rounds
+/// half-way cases to even. `xs` and `out` must be equal in length.
+///
+/// The result for NaN inputs is unspecified.
+///
+/// `out` will be fully initialized after this function returns.
+pub fn vec_quantize_f32_to_u8(xs: &[f32], out: &mut [MaybeUninit<u8>], scale: f32, zero_point: u8) {
+    let op = SimdQuantize {
+        input: xs.into(),
+        output: out.into(),
+        scale,
+        zero_point,
+    };
+    let dispatcher = SimdDispatcher::default();
+    dispatcher.dispatch(op);
+}
+
+/// Dequantize u8 values using a scale and zero point per channel.
+///
+/// `xs` is divided into `scales.len()` equal-length contiguous channels,
+/// which are dequantized as with [vec_dequantize_u8_to_f32]. `scales` and
+/// `zero_points` must have the same length, which must evenly divide the
+/// length of `xs`.
+pub fn vec_dequantize_u8_to_f32_per_channel(
+    xs: &[u8],
+    out: &mut [MaybeUninit<f32>],
+    scales: &[f32],
+    zero_points: &[u8],
+) {
+    let channel_len = per_channel_len(xs.len(), out.len(), scales.len(), zero_points.len());
+    let channels = xs
+        .chunks(channel_len)
+        .zip(out.chunks_mut(channel_len))
+        .zip(scales.iter().zip(zero_points));
+    for ((xs, out), (&scale, &zero_point)) in channels {
+        vec_dequantize_u8_to_f32(xs, out, scale, zero_point);
+    }
+}
+
+/// Quantize f32 values to u8 using a scale and zero point per channel.
+///
+/// `xs` is divided into channels as with
+/// [vec_dequantize_u8_to_f32_per_channel], which are quantized as with
+/// [vec_quantize_f32_to_u8].
+pub fn vec_quantize_f32_to_u8_per_channel(
+    xs: &[f32],
+    out: &mut [MaybeUninit<u8>],
+    scales: &[f32],
+    zero_points: &[u8],
+) {
+    let channel_len = per_channel_len(xs.len(), out.len(), scales.len(), zero_points.len());
+    let channels = xs
+        .chunks(channel_len)
+        .zip(out.chunks_mut(channel_len))
+        .zip(scales.iter().zip(zero_points));
+    for ((xs, out), (&scale, &zero_point)) in channels {
+        vec_quantize_f32_to_u8(xs, out, scale, zero_point);
+    }
+}
+
+/// Validate the lengths of per-channel quantization inputs and return the
+/// channel length.
+fn per_channel_len(in_len: usize, out_len: usize, n_scales: usize, n_zero_points: usize) -> usize {
+    assert_eq!(in_len, out_len, "Input and output lengths differ");
+    assert_eq!(
+        n_scales, n_zero_points,
+        "Scale and zero point counts differ"
+    );
+    assert!(
+        n_scales > 0 && in_len % n_scales == 0,
+        "Input length is not a multiple of channel count"
+    );
+    // Use a non-zero length for empty inputs, as `chunks` requires it.
+    (in_len / n_scales).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        vec_dequantize_u8_to_f32, vec_dequantize_u8_to_f32_per_channel, vec_quantize_f32_to_u8,
+        vec_quantize_f32_to_u8_per_channel,
+    };
+    use crate::testing::AsUninit;
+
+    fn reference_quantize(x: f32, scale: f32, zero_point: u8) -> u8 {
+        ((x / scale).round_ties_even() + zero_point as f32).clamp(0., 255.) as u8
+    }
+
+    #[test]
+    fn test_vec_dequantize_u8_to_f32() {
+        // Use a length which is not a multiple of the vector width.
+        let xs: Vec<u8> = (0..=255).step_by(7).collect();
+        let (scale, zero_point) = (0.05, 128);
+        let expected: Vec<f32> = xs
+            .iter()
+            .map(|&x| (x as f32 - zero_point as f32) * scale)
+            .collect();
+
+        let mut actual = vec![0.; xs.len()];
+        vec_dequantize_u8_to_f32(&xs, actual.as_mut_slice().as_uninit(), scale, zero_point);
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_vec_quantize_f32_to_u8() {
+        let mut xs: Vec<f32> = (0..37).map(|i| (i as f32 - 18.) * 0.37).collect();
+        // Values which are out of range or exactly half-way between two
+        // quantized values.
+        xs.extend([-1000., 1000., 0.5, 1.5, 2.5, -0.5, -1.5, 1e10, -1e10]);
+        let (scale, zero_point) = (0.1, 10);
+
+        for (scale, zero_point) in [(scale, zero_point), (1., 0), (1., 128)] {
+            let expected: Vec<u8> = xs
+                .iter()
+                .map(|&x| reference_quantize(x, scale, zero_point))
+                .collect();
+            let mut actual = vec![0u8; xs.len()];
+            vec_quantize_f32_to_u8(&xs, actual.as_mut_slice().as_uninit(), scale, zero_point);
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_quantize_per_channel() {
+        let xs: Vec<f32> = (0..30).map(|i| i as f32 * 0.5 - 4.).collect();
+        let scales = [0.1, 0.2, 0.5];
+        let zero_points = [50, 100, 0];
+
+        let mut quantized = vec![0u8; xs.len()];
+        vec_quantize_f32_to_u8_per_channel(
+            &xs,
+            quantized.as_mut_slice().as_uninit(),
+            &scales,
+            &zero_points,
+        );
+        let mut dequantized = vec![0.; xs.len()];
+        vec_dequantize_u8_to_f32_per_channel(
+            &quantized,
+            dequantized.as_mut_slice().as_uninit(),
+            &scales,
+            &zero_points,
+        );
+
+        for (i, (q, y)) in quantized.iter().zip(&dequantized).enumerate() {
+            let channel = i / 10;
+            let (scale, zero_point) = (scales[channel], zero_points[channel]);
+            assert_eq!(*q, reference_quantize(xs[i], scale, zero_point));
+            assert_eq!(*y, (*q as f32 - zero_point as f32) * scale);
+        }
+
+        vec_quantize_f32_to_u8_per_channel(&[], &mut [], &scales, &zero_points);
+    }
+}
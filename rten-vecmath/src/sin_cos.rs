@@ -0,0 +1,224 @@
+//! Vectorized sine and cosine functions.
+
+#![allow(clippy::excessive_precision)]
+
+use std::mem::MaybeUninit;
+
+use rten_simd::dispatch::{dispatch_map_op, dispatch_map_op_in_place, SimdUnaryOp};
+use rten_simd::{SimdFloat, SimdInt};
+
+const INV_PI_2: f32 = std::f32::consts::FRAC_2_PI;
+const ROUNDING_MAGIC: f32 = 12582912.; // 0x3 << 22
+
+// `pi / 2` split into three parts for Cody-Waite range reduction. The first
+// two parts have enough trailing zero bits that `k * PI_2_A` and `k * PI_2_B`
+// are exact for `|k| < 2^15`.
+const PI_2_A: f32 = 1.5703125;
+const PI_2_B: f32 = 4.837512969970703125e-4;
+const PI_2_C: f32 = 7.54978995489188216e-8;
+
+// Coefficients of polynomials used to approximate `sin(r)` and `cos(r)` in
+// `[-pi/4, pi/4]`. From the Cephes library.
+const SIN_POLY_3: f32 = -1.6666654611e-1;
+const SIN_POLY_5: f32 = 8.3321608736e-3;
+const SIN_POLY_7: f32 = -1.9515295891e-4;
+
+const COS_POLY_4: f32 = 4.166664568298827e-2;
+const COS_POLY_6: f32 = -1.388731625493765e-3;
+const COS_POLY_8: f32 = 2.443315711809948e-5;
+
+/// Computes `sin(x)` if `COS` is false or `cos(x)` otherwise.
+///
+/// Method outline:
+///
+///  1. Reduce the range using `x = k * pi/2 + r`, where `k` is an integer and
+///     `|r| <= pi/4`.
+///
+///  2. Compute `sin(r)` or `cos(r)` using polynomial approximations, and
+///     select between them and their negations according to the quadrant
+///     `k mod 4`. `cos(x)` is computed as `sin(x + pi/2)`, by adding one to
+///     the quadrant.
+///
+/// The range reduction is accurate for `|x|` up to about `5e4`. Beyond that
+/// the error grows with the magnitude of `x`. Payne-Hanek reduction would be
+/// needed to support the full range of `f32`.
+///
+/// Safety: The caller must ensure the `SimdFloat` impl is usable on the current system.
+#[inline(always)]
+unsafe fn simd_sin_cos<S: SimdFloat, const COS: bool>(x: S) -> S {
+    // Compute `k = rintf(x * 2/pi)`, `r = x - k * pi/2`.
+    let k = x.mul_add(S::splat(INV_PI_2), S::splat(ROUNDING_MAGIC));
+    let k = k.sub(S::splat(ROUNDING_MAGIC));
+    let r = k.mul_add(S::splat(-PI_2_A), x);
+    let r = k.mul_add(S::splat(-PI_2_B), r);
+    let r = k.mul_add(S::splat(-PI_2_C), r);
+
+    let mut quadrant = k.to_int_trunc();
+    if COS {
+        quadrant = quadrant.add(S::Int::splat(1));
+    }
+
+    let r_sqr = r.mul(r);
+
+    let sin_r = S::splat(SIN_POLY_7);
+    let sin_r = sin_r.mul_add(r_sqr, S::splat(SIN_POLY_5));
+    let sin_r = sin_r.mul_add(r_sqr, S::splat(SIN_POLY_3));
+    let sin_r = sin_r.mul(r_sqr).mul_add(r, r);
+
+    let cos_r = S::splat(COS_POLY_8);
+    let cos_r = cos_r.mul_add(r_sqr, S::splat(COS_POLY_6));
+    let cos_r = cos_r.mul_add(r_sqr, S::splat(COS_POLY_4));
+    let cos_r = cos_r.mul(r_sqr).mul(r_sqr);
+    let cos_r = r_sqr.mul_add(S::splat(-0.5), cos_r).add(S::one());
+
+    // Select `sin(r)`, `cos(r)`, `-sin(r)` or `-cos(r)` for quadrants 0-3.
+    // Bits 0 and 1 of the quadrant are tested by shifting them into the sign
+    // bit.
+    let use_cos = quadrant.shl::<31>().lt(S::Int::zero());
+    let negate = quadrant.shl::<30>().lt(S::Int::zero());
+    let y = sin_r.blend(cos_r, use_cos);
+    y.blend(y.neg(), negate)
+}
+
+/// Computes the sine of `x`.
+///
+/// For `|x| <= 1e4` the absolute error compared to [f32::sin] is at most
+/// `2e-7`, and for `|x| <= 5e4` it is at most `1e-6`. The error grows with
+/// larger inputs, and results are meaningless for `|x|` above about `1e6`.
+///
+/// This is a scalar variant of [vec_sin] that uses exactly the same algorithm.
+pub fn sin(x: f32) -> f32 {
+    // Safety: f32 is available on all systems.
+    unsafe { simd_sin_cos::<_, false>(x) }
+}
+
+/// Computes the cosine of `x`.
+///
+/// See [sin] for the range of inputs where this is accurate.
+///
+/// This is a scalar variant of [vec_cos] that uses exactly the same algorithm.
+pub fn cos(x: f32) -> f32 {
+    // Safety: f32 is available on all systems.
+    unsafe { simd_sin_cos::<_, true>(x) }
+}
+
+struct SimdSin {}
+impl SimdUnaryOp for SimdSin {
+    #[inline(always)]
+    unsafe fn eval<S: SimdFloat>(&self, x: S) -> S {
+        simd_sin_cos::<_, false>(x)
+    }
+}
+
+struct SimdCos {}
+impl SimdUnaryOp for SimdCos {
+    #[inline(always)]
+    unsafe fn eval<S: SimdFloat>(&self, x: S) -> S {
+        simd_sin_cos::<_, true>(x)
+    }
+}
+
+/// Vectorized sine function.
+///
+/// This computes [sin] for each element in `xs` and writes the result to
+/// `out`. `xs` and `out` must be equal in length. See [sin] for the range of
+/// inputs where this is accurate.
+///
+/// `out` will be fully initialized after this function returns.
+pub fn vec_sin(xs: &[f32], out: &mut [MaybeUninit<f32>]) {
+    dispatch_map_op(xs, out, SimdSin {});
+}
+
+/// Variant of [vec_sin] that modifies elements in-place.
+pub fn vec_sin_in_place(xs: &mut [f32]) {
+    dispatch_map_op_in_place(xs, SimdSin {});
+}
+
+/// Vectorized cosine function.
+///
+/// This computes [cos] for each element in `xs` and writes the result to
+/// `out`. `xs` and `out` must be equal in length. See [sin] for the range of
+/// inputs where this is accurate.
+///
+/// `out` will be fully initialized after this function returns.
+pub fn vec_cos(xs: &[f32], out: &mut [MaybeUninit<f32>]) {
+    dispatch_map_op(xs, out, SimdCos {});
+}
+
+/// Variant of [vec_cos] that modifies elements in-place.
+pub fn vec_cos_in_place(xs: &mut [f32]) {
+    dispatch_map_op_in_place(xs, SimdCos {});
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cos, sin, vec_cos, vec_cos_in_place, vec_sin, vec_sin_in_place};
+    use crate::testing::{arange, benchmark_op, check_f32s_are_equal_atol, triples, AsUninit};
+
+    // Maximum absolute error compared to `f32::sin` and `f32::cos`.
+    //
+    // An absolute rather than ULP threshold is used because the relative
+    // error is unbounded near the zeros of the functions.
+    const MAX_SIN_COS_ERROR_ATOL: f32 = 2e-7;
+
+    #[test]
+    fn test_sin_cos() {
+        let cases = [0., -0., 1., -1., 0.5, 3., -100.];
+        for x in cases {
+            assert!((sin(x) - x.sin()).abs() <= MAX_SIN_COS_ERROR_ATOL);
+            assert!((cos(x) - x.cos()).abs() <= MAX_SIN_COS_ERROR_ATOL);
+        }
+        assert_eq!(sin(0.), 0.);
+        assert_eq!(cos(0.), 1.);
+
+        for x in [f32::INFINITY, f32::NEG_INFINITY, f32::NAN] {
+            assert!(sin(x).is_nan());
+            assert!(cos(x).is_nan());
+        }
+    }
+
+    #[test]
+    fn test_sin_cos_large_x() {
+        // The documented bound on the error for `1e4 < |x| <= 5e4`.
+        let max_error = 1e-6;
+        for x in arange(-5e4, 5e4, 0.73) {
+            assert!((sin(x) - x.sin()).abs() <= max_error, "sin({}) error", x);
+            assert!((cos(x) - x.cos()).abs() <= max_error, "cos({}) error", x);
+        }
+    }
+
+    #[test]
+    fn test_vec_sin_cos() {
+        let cases: Vec<f32> = arange(-10., 10., 0.001f32)
+            .chain(arange(-1e4, 1e4, 1.37))
+            .collect();
+
+        for (vec_op, vec_op_in_place, reference) in [
+            (
+                vec_sin as fn(&[f32], &mut [std::mem::MaybeUninit<f32>]),
+                vec_sin_in_place as fn(&mut [f32]),
+                f32::sin as fn(f32) -> f32,
+            ),
+            (vec_cos, vec_cos_in_place, f32::cos),
+        ] {
+            let expected: Vec<f32> = cases.iter().copied().map(reference).collect();
+
+            let mut actual = vec![0.; cases.len()];
+            vec_op(&cases, actual.as_mut_slice().as_uninit());
+            check_f32s_are_equal_atol(triples(&cases, &actual, &expected), MAX_SIN_COS_ERROR_ATOL);
+
+            let mut actual = cases.clone();
+            vec_op_in_place(&mut actual);
+            check_f32s_are_equal_atol(triples(&cases, &actual, &expected), MAX_SIN_COS_ERROR_ATOL);
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_sin() {
+        benchmark_op(
+            |xs, ys| xs.iter().zip(ys.iter_mut()).for_each(|(x, y)| *y = x.sin()),
+            vec_sin,
+        );
+    }
+}
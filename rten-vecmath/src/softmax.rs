@@ -3,7 +3,7 @@ use std::mem::MaybeUninit;
 use rten_simd::dispatch::{SimdDispatcher, SimdOp};
 use rten_simd::functional::{simd_fold, simd_map};
 use rten_simd::span::{MutPtrLen, PtrLen};
-use rten_simd::SimdFloat;
+use rten_simd::{SimdFloat, MAX_LEN};
 
 use crate::exp::simd_exp;
 
@@ -22,7 +22,7 @@ unsafe fn simd_softmax<S: SimdFloat>(input: PtrLen<f32>, out: MutPtrLen<MaybeUni
         |max, x| max.max(x),
         f32::MIN, /* pad */
     );
-    let max_val = max_val.fold_splat(f32::MIN, |max: f32, x: f32| max.max(x));
+    let max_val = S::splat(max_val.reduce_max());
 
     // *x = (*x - max_val).exp()
     let mut exp_sum = S::zero();
@@ -40,7 +40,7 @@ unsafe fn simd_softmax<S: SimdFloat>(input: PtrLen<f32>, out: MutPtrLen<MaybeUni
     );
 
     // *x /= exp_sum
-    let exp_sum = exp_sum.fold_splat(0., |sum, x| sum + x);
+    let exp_sum = S::splat(exp_sum.sum());
     simd_map(
         out.assume_init().into(),
         out,
@@ -89,9 +89,171 @@ pub fn vec_softmax_in_place(xs: &mut [f32]) {
     dispatcher.dispatch(op);
 }
 
+/// Apply the softmax operation over `xs * scale + mask` and write results to
+/// `out`.
+///
+/// Unlike [simd_softmax], this computes the maximum and the sum of
+/// exponentials together using the "online softmax" method from
+/// https://arxiv.org/abs/1805.02867. Together with fusing the scale and mask
+/// into the loads, this needs two passes over the input instead of three.
+#[inline(always)]
+unsafe fn simd_softmax_scaled_masked<S: SimdFloat>(
+    input: PtrLen<f32>,
+    mask: Option<PtrLen<f32>>,
+    out: MutPtrLen<MaybeUninit<f32>>,
+    scale: f32,
+) {
+    assert!(S::LEN <= MAX_LEN);
+    assert!(input.len() == out.len());
+    if let Some(mask) = mask {
+        assert!(mask.len() == input.len());
+    }
+
+    let n = input.len();
+    let scale_vec = S::splat(scale);
+
+    // Load scaled and masked logits from offset `i`. Lanes beyond the end of
+    // the input are set to `-inf`, so they don't contribute to the sum.
+    let load = |i: usize| -> S {
+        let remaining = n - i;
+        let (x, m) = if remaining >= S::LEN {
+            let x = S::load(input.ptr().add(i));
+            let m = mask.map(|mask| S::load(mask.ptr().add(i)));
+            (x, m)
+        } else {
+            let mut x_buf = [f32::NEG_INFINITY; MAX_LEN];
+            let mut m_buf = [0.; MAX_LEN];
+            for j in 0..remaining {
+                x_buf[j] = *input.ptr().add(i + j);
+                if let Some(mask) = mask {
+                    m_buf[j] = *mask.ptr().add(i + j);
+                }
+            }
+            let m = mask.map(|_| S::load(m_buf.as_ptr()));
+            (S::load(x_buf.as_ptr()), m)
+        };
+        let x = x.mul(scale_vec);
+        match m {
+            Some(m) => x.add(m),
+            None => x,
+        }
+    };
+
+    // Compute running per-lane max and sum of `exp(x - max)`. `f32::MIN` is
+    // used as the initial max rather than `-inf` so that `exp(x - max)` is
+    // zero rather than NaN for masked-out elements.
+    let mut max = S::splat(f32::MIN);
+    let mut exp_sum = S::zero();
+    for i in (0..n).step_by(S::LEN) {
+        let x = load(i);
+        let new_max = max.max(x);
+        exp_sum = exp_sum
+            .mul(simd_exp(max.sub(new_max)))
+            .add(simd_exp(x.sub(new_max)));
+        max = new_max;
+    }
+
+    // Combine lanes.
+    let mut lane_max = [f32::MIN; MAX_LEN];
+    let mut lane_sum = [0.; MAX_LEN];
+    max.store(lane_max.as_mut_ptr());
+    exp_sum.store(lane_sum.as_mut_ptr());
+    let max_val = max.reduce_max();
+    let sum_val: f32 = lane_max[..S::LEN]
+        .iter()
+        .zip(&lane_sum[..S::LEN])
+        .map(|(lane_max, lane_sum)| lane_sum * (lane_max - max_val).exp())
+        .sum();
+
+    let max_val = S::splat(max_val);
+    let inv_sum = S::splat(1. / sum_val);
+    let mut tail = [0.; MAX_LEN];
+    for i in (0..n).step_by(S::LEN) {
+        let y = simd_exp(load(i).sub(max_val)).mul(inv_sum);
+        let remaining = n - i;
+        if remaining >= S::LEN {
+            y.store(out.ptr().add(i) as *mut f32);
+        } else {
+            y.store(tail.as_mut_ptr());
+            for j in 0..remaining {
+                out.ptr().add(i + j).write(MaybeUninit::new(tail[j]));
+            }
+        }
+    }
+}
+
+struct SimdScaledMaskedSoftmax {
+    input: PtrLen<f32>,
+    mask: Option<PtrLen<f32>>,
+    output: MutPtrLen<MaybeUninit<f32>>,
+    scale: f32,
+}
+
+impl SimdOp for SimdScaledMaskedSoftmax {
+    #[inline(always)]
+    unsafe fn eval<S: SimdFloat>(&self) {
+        simd_softmax_scaled_masked::<S>(self.input, self.mask, self.output, self.scale)
+    }
+}
+
+fn dispatch_scaled_masked_softmax(
+    input: PtrLen<f32>,
+    mask: Option<&[f32]>,
+    output: MutPtrLen<MaybeUninit<f32>>,
+    scale: f32,
+) {
+    let op = SimdScaledMaskedSoftmax {
+        input,
+        mask: mask.map(|m| m.into()),
+        output,
+        scale,
+    };
+    let dispatcher = SimdDispatcher::default();
+    dispatcher.dispatch(op);
+}
+
+/// Computes the softmax function over `xs / temperature`.
+///
+/// This is equivalent to dividing each element by `temperature` and then
+/// calling [vec_softmax], but avoids a separate pass over the data.
+/// `temperature` must be > 0.
+///
+/// `out` will be fully initialized after this function returns.
+pub fn vec_softmax_with_temperature(xs: &[f32], out: &mut [MaybeUninit<f32>], temperature: f32) {
+    assert!(temperature > 0.);
+    dispatch_scaled_masked_softmax(xs.into(), None, out.into(), 1. / temperature);
+}
+
+/// In-place variant of [vec_softmax_with_temperature].
+pub fn vec_softmax_with_temperature_in_place(xs: &mut [f32], temperature: f32) {
+    assert!(temperature > 0.);
+    let out: MutPtrLen<f32> = xs.into();
+    dispatch_scaled_masked_softmax(xs.into(), None, out.as_uninit(), 1. / temperature);
+}
+
+/// Computes the softmax function over `xs + mask`.
+///
+/// `mask` is an additive mask with the same length as `xs`. Elements can be
+/// excluded by setting the corresponding mask value to `-inf`. If all elements
+/// are masked out, the output is NaN.
+///
+/// `out` will be fully initialized after this function returns.
+pub fn vec_masked_softmax(xs: &[f32], mask: &[f32], out: &mut [MaybeUninit<f32>]) {
+    dispatch_scaled_masked_softmax(xs.into(), Some(mask), out.into(), 1.);
+}
+
+/// In-place variant of [vec_masked_softmax].
+pub fn vec_masked_softmax_in_place(xs: &mut [f32], mask: &[f32]) {
+    let out: MutPtrLen<f32> = xs.into();
+    dispatch_scaled_masked_softmax(xs.into(), Some(mask), out.as_uninit(), 1.);
+}
+
 #[cfg(test)]
 mod tests {
-    use super::vec_softmax;
+    use super::{
+        vec_masked_softmax, vec_masked_softmax_in_place, vec_softmax, vec_softmax_with_temperature,
+        vec_softmax_with_temperature_in_place,
+    };
 
     use crate::testing::{benchmark_op, check_f32s_are_equal_ulps, triples, AsUninit};
 
@@ -122,6 +284,68 @@ mod tests {
         check_f32s_are_equal_ulps(triples(&input, &actual, expected), 0. /* max ULPs */);
     }
 
+    // Maximum error of the fused softmax variants. This is higher than for
+    // `vec_softmax` because the online method rescales partial sums.
+    const MAX_FUSED_SOFTMAX_ERROR_ULPS: f32 = 8.0;
+
+    #[test]
+    fn test_vec_softmax_with_temperature() {
+        // Use a length which is not a multiple of the vector width.
+        let input: Vec<f32> = (0..37).map(|i| ((i * 7) % 11) as f32 - 5.).collect();
+
+        for temperature in [0.5, 1., 2.] {
+            let scaled: Vec<f32> = input.iter().map(|x| x / temperature).collect();
+            let mut expected = vec![0.; input.len()];
+            reference_softmax(&scaled, &mut expected);
+
+            let mut actual = vec![0.; input.len()];
+            vec_softmax_with_temperature(&input, actual.as_mut_slice().as_uninit(), temperature);
+            check_f32s_are_equal_ulps(
+                triples(&input, &actual, &expected),
+                MAX_FUSED_SOFTMAX_ERROR_ULPS,
+            );
+
+            let mut actual = input.clone();
+            vec_softmax_with_temperature_in_place(&mut actual, temperature);
+            check_f32s_are_equal_ulps(
+                triples(&input, &actual, &expected),
+                MAX_FUSED_SOFTMAX_ERROR_ULPS,
+            );
+        }
+    }
+
+    #[test]
+    fn test_vec_masked_softmax() {
+        let input: Vec<f32> = (0..21).map(|i| (i % 5) as f32 * 0.3).collect();
+        let mask: Vec<f32> = (0..input.len())
+            .map(|i| if i % 3 == 0 { f32::NEG_INFINITY } else { 0.5 })
+            .collect();
+
+        let masked: Vec<f32> = input.iter().zip(&mask).map(|(x, m)| x + m).collect();
+        let mut expected = vec![0.; input.len()];
+        reference_softmax(&masked, &mut expected);
+
+        let mut actual = vec![0.; input.len()];
+        vec_masked_softmax(&input, &mask, actual.as_mut_slice().as_uninit());
+        check_f32s_are_equal_ulps(
+            triples(&input, &actual, &expected),
+            MAX_FUSED_SOFTMAX_ERROR_ULPS,
+        );
+
+        for (i, y) in actual.iter().enumerate() {
+            if i % 3 == 0 {
+                assert_eq!(*y, 0.);
+            }
+        }
+
+        let mut actual = input.clone();
+        vec_masked_softmax_in_place(&mut actual, &mask);
+        check_f32s_are_equal_ulps(
+            triples(&input, &actual, &expected),
+            MAX_FUSED_SOFTMAX_ERROR_ULPS,
+        );
+    }
+
     #[test]
     #[ignore]
     fn bench_softmax() {
@@ -0,0 +1,216 @@
+use std::cell::Cell;
+
+use rten_simd::dispatch::{SimdDispatcher, SimdOp};
+use rten_simd::functional::simd_fold;
+use rten_simd::span::PtrLen;
+use rten_simd::{SimdFloat, MAX_LEN};
+
+/// Algorithm used to accumulate values in reductions such as [vec_sum].
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Summation {
+    /// Accumulate values in independent SIMD lanes.
+    ///
+    /// This is the fastest option. The rounding error grows with the length
+    /// of the input, which matters for long reductions of values with a
+    /// large common offset.
+    #[default]
+    Fast,
+
+    /// Accumulate values in independent SIMD lanes using [Kahan
+    /// summation][kahan], then combine the lanes with a compensated sum.
+    ///
+    /// The rounding error is largely independent of the length of the input,
+    /// at the cost of a few extra operations per element.
+    ///
+    /// [kahan]: https://en.wikipedia.org/wiki/Kahan_summation_algorithm
+    Compensated,
+}
+
+/// Sum `f(x - shift)` for each element `x` in `input`.
+///
+/// The input is padded with `shift`, so `f` must map zero to zero.
+#[inline(always)]
+unsafe fn simd_sum<S: SimdFloat, F: Fn(S) -> S>(
+    input: PtrLen<f32>,
+    shift: f32,
+    mode: Summation,
+    f: F,
+) -> f32 {
+    let shift_vec = S::splat(shift);
+
+    match mode {
+        Summation::Fast => simd_fold(
+            input,
+            S::zero(),
+            #[inline(always)]
+            |sum, x| sum.add(f(x.sub(shift_vec))),
+            shift,
+        )
+        .sum(),
+        Summation::Compensated => {
+            assert!(S::LEN <= MAX_LEN);
+
+            // Running compensation for lost low-order bits in each lane.
+            let comp = Cell::new(S::zero());
+            let sum = simd_fold(
+                input,
+                S::zero(),
+                #[inline(always)]
+                |sum, x| {
+                    let y = f(x.sub(shift_vec)).sub(comp.get());
+                    let t = sum.add(y);
+                    comp.set(t.sub(sum).sub(y));
+                    t
+                },
+                shift,
+            );
+
+            let mut sums = [0.; MAX_LEN];
+            let mut comps = [0.; MAX_LEN];
+            sum.store(sums.as_mut_ptr());
+            comp.get().store(comps.as_mut_ptr());
+
+            let mut total = 0f32;
+            let mut total_comp = 0f32;
+            for (sum, comp) in sums.iter().zip(comps).take(S::LEN) {
+                for x in [*sum, -comp] {
+                    let y = x - total_comp;
+                    let t = total + y;
+                    total_comp = (t - total) - y;
+                    total = t;
+                }
+            }
+            total
+        }
+    }
+}
+
+struct SimdSum<'a> {
+    input: PtrLen<f32>,
+    shift: f32,
+    square: bool,
+    mode: Summation,
+    output: &'a Cell<f32>,
+}
+
+impl SimdOp for SimdSum<'_> {
+    #[inline(always)]
+    unsafe fn eval<S: SimdFloat>(&self) {
+        let sum = if self.square {
+            simd_sum::<S, _>(self.input, self.shift, self.mode, |x| x.mul(x))
+        } else {
+            simd_sum::<S, _>(self.input, self.shift, self.mode, |x| x)
+        };
+        self.output.set(sum);
+    }
+}
+
+fn dispatch_sum(xs: &[f32], shift: f32, square: bool, mode: Summation) -> f32 {
+    let output = Cell::new(0.);
+    let op = SimdSum {
+        input: xs.into(),
+        shift,
+        square,
+        mode,
+        output: &output,
+    };
+    let dispatcher = SimdDispatcher::default();
+    dispatcher.dispatch(op);
+    output.get()
+}
+
+/// Return the sum of elements in `xs`.
+///
+/// The order in which elements are accumulated is unspecified, so the result
+/// may differ slightly from a sequential sum. Use [Summation::Compensated] for
+/// long inputs where accuracy matters.
+pub fn vec_sum(xs: &[f32], mode: Summation) -> f32 {
+    dispatch_sum(xs, 0., false, mode)
+}
+
+/// Return the sum of the squares of elements in `xs`.
+///
+/// See [vec_sum] for notes on accuracy.
+pub fn vec_sum_square(xs: &[f32], mode: Summation) -> f32 {
+    dispatch_sum(xs, 0., true, mode)
+}
+
+/// Return the mean and population variance of elements in `xs`.
+///
+/// This uses two passes over the input, first computing the mean and then
+/// the mean of the squared differences from it, which avoids the cancellation
+/// of single-pass methods when the mean is large relative to the variance.
+///
+/// Returns `(NaN, NaN)` if `xs` is empty.
+pub fn vec_mean_variance(xs: &[f32], mode: Summation) -> (f32, f32) {
+    let n = xs.len() as f32;
+    let mean = vec_sum(xs, mode) / n;
+    let variance = dispatch_sum(xs, mean, true, mode) / n;
+    (mean, variance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{vec_mean_variance, vec_sum, vec_sum_square, Summation};
+
+    fn reference_sum(xs: &[f32]) -> f64 {
+        xs.iter().map(|x| *x as f64).sum()
+    }
+
+    fn relative_error(actual: f32, expected: f64) -> f64 {
+        ((actual as f64 - expected) / expected).abs()
+    }
+
+    #[test]
+    fn test_vec_sum() {
+        // Use a length which is not a multiple of the vector width.
+        let xs: Vec<f32> = (0..37).map(|i| i as f32 * 0.5 - 3.).collect();
+        for mode in [Summation::Fast, Summation::Compensated] {
+            assert_eq!(vec_sum(&xs, mode), reference_sum(&xs) as f32);
+            let squares: Vec<f32> = xs.iter().map(|x| x * x).collect();
+            assert_eq!(vec_sum_square(&xs, mode), reference_sum(&squares) as f32);
+            assert_eq!(vec_sum(&[], mode), 0.);
+        }
+    }
+
+    #[test]
+    fn test_vec_sum_compensated_accuracy() {
+        // Summing many values which are not exactly representable
+        // accumulates rounding error in each lane.
+        let xs = vec![0.1f32; 1 << 20];
+        let expected = reference_sum(&xs);
+
+        let fast_error = relative_error(vec_sum(&xs, Summation::Fast), expected);
+        let compensated_error = relative_error(vec_sum(&xs, Summation::Compensated), expected);
+        assert!(compensated_error < 1e-7, "error {}", compensated_error);
+        assert!(compensated_error <= fast_error);
+
+        let squares: Vec<f32> = xs.iter().map(|x| x * x).collect();
+        let expected = reference_sum(&squares);
+        let compensated_error =
+            relative_error(vec_sum_square(&xs, Summation::Compensated), expected);
+        assert!(compensated_error < 1e-7, "error {}", compensated_error);
+    }
+
+    #[test]
+    fn test_vec_mean_variance() {
+        // Values with a large mean relative to their variance.
+        let xs: Vec<f32> = (0..8191).map(|i| 1e4 + (i % 7) as f32 * 0.25).collect();
+
+        let mean = reference_sum(&xs) / xs.len() as f64;
+        let variance = xs.iter().map(|x| (*x as f64 - mean).powi(2)).sum::<f64>() / xs.len() as f64;
+
+        // Fast summation loses precision as the running sums grow, and the
+        // error in the mean then inflates the variance.
+        let (actual_mean, actual_var) = vec_mean_variance(&xs, Summation::Fast);
+        assert!(relative_error(actual_mean, mean) < 1e-5);
+        assert!(relative_error(actual_var, variance) < 5e-2);
+
+        let (actual_mean, actual_var) = vec_mean_variance(&xs, Summation::Compensated);
+        assert!(relative_error(actual_mean, mean) < 1e-7);
+        assert!(relative_error(actual_var, variance) < 1e-5);
+
+        let (mean, var) = vec_mean_variance(&[], Summation::Fast);
+        assert!(mean.is_nan() && var.is_nan());
+    }
+}
@@ -0,0 +1,363 @@
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use rten_simd::dispatch::{SimdDispatcher, SimdOp};
+use rten_simd::functional::simd_fold;
+use rten_simd::span::PtrLen;
+use rten_simd::{SimdFloat, SimdInt, MAX_LEN};
+
+/// Find the index and value of the largest element in `input`.
+///
+/// Each lane tracks the largest value seen at its position and the index
+/// where it was found. The lanes are then combined, preferring the lowest
+/// index when several lanes have the same value. NaN values are ignored.
+#[inline(always)]
+unsafe fn simd_argmax<S: SimdFloat>(input: PtrLen<f32>) -> usize {
+    assert!(S::LEN <= MAX_LEN);
+    let n = input.len();
+    assert!(n <= i32::MAX as usize);
+
+    let mut lane_offsets = [0i32; MAX_LEN];
+    for (i, offset) in lane_offsets.iter_mut().enumerate() {
+        *offset = i as i32;
+    }
+    let mut idx = S::Int::load(lane_offsets.as_ptr());
+    let idx_step = S::Int::splat(S::LEN as i32);
+
+    let mut max_val = S::splat(f32::NEG_INFINITY);
+    // Lanes which never see a value greater than -inf keep an index of -1.
+    let mut max_idx = S::Int::splat(-1);
+
+    let mut tail = [f32::NEG_INFINITY; MAX_LEN];
+    for i in (0..n).step_by(S::LEN) {
+        let remaining = n - i;
+        let x = if remaining >= S::LEN {
+            S::load(input.ptr().add(i))
+        } else {
+            for j in 0..remaining {
+                tail[j] = *input.ptr().add(i + j);
+            }
+            S::load(tail.as_ptr())
+        };
+
+        // Use a strict comparison so that the first occurrence is kept.
+        let gt_mask = max_val.lt(x);
+        max_val = max_val.blend(x, gt_mask);
+        max_idx = max_idx.blend(idx, gt_mask);
+        idx = idx.add(idx_step);
+    }
+
+    let mut lane_vals = [0.; MAX_LEN];
+    let mut lane_idxs = [0i32; MAX_LEN];
+    max_val.store(lane_vals.as_mut_ptr());
+    max_idx.store(lane_idxs.as_mut_ptr());
+
+    let mut best: Option<(usize, f32)> = None;
+    for lane in 0..S::LEN {
+        if lane_idxs[lane] < 0 {
+            continue;
+        }
+        let (idx, val) = (lane_idxs[lane] as usize, lane_vals[lane]);
+        let is_better = match best {
+            Some((best_idx, best_val)) => val > best_val || (val == best_val && idx < best_idx),
+            None => true,
+        };
+        if is_better {
+            best = Some((idx, val));
+        }
+    }
+
+    best.map(|(idx, _)| idx).unwrap_or_else(|| {
+        // All values are -inf or NaN.
+        let xs = std::slice::from_raw_parts(input.ptr(), n);
+        xs.iter().position(|x| !x.is_nan()).unwrap_or(0)
+    })
+}
+
+struct SimdArgMax<'a> {
+    input: PtrLen<f32>,
+    output: &'a Cell<usize>,
+}
+
+impl SimdOp for SimdArgMax<'_> {
+    #[inline(always)]
+    unsafe fn eval<S: SimdFloat>(&self) {
+        self.output.set(simd_argmax::<S>(self.input));
+    }
+}
+
+/// Return the index and value of the largest element in `xs`.
+///
+/// If there are multiple elements with the largest value, the index of the
+/// first is returned. NaN values are ignored, unless all values are NaN, in
+/// which case the first element is returned.
+///
+/// Panics if `xs` is empty.
+pub fn argmax(xs: &[f32]) -> (usize, f32) {
+    assert!(!xs.is_empty(), "argmax of empty slice");
+
+    let output = Cell::new(0);
+    let op = SimdArgMax {
+        input: xs.into(),
+        output: &output,
+    };
+    let dispatcher = SimdDispatcher::default();
+    dispatcher.dispatch(op);
+
+    let idx = output.get();
+    (idx, xs[idx])
+}
+
+/// Entry in the heap used by [`top_k`].
+///
+/// Entries are ordered such that the "greatest" entry in the heap is the one
+/// that should be evicted first, ie. the smallest value, or the highest index
+/// among equal values.
+struct TopKEntry {
+    index: usize,
+    value: f32,
+}
+
+impl PartialEq for TopKEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for TopKEntry {}
+
+impl PartialOrd for TopKEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TopKEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .value
+            .total_cmp(&self.value)
+            .then(self.index.cmp(&other.index))
+    }
+}
+
+// Number of elements in each block that is tested against the current top-k
+// threshold before being scanned.
+const TOPK_BLOCK_SIZE: usize = 256;
+
+/// Find the `k` largest non-NaN elements of `input`.
+///
+/// The input is processed in blocks. Once `k` candidates have been found, the
+/// maximum of each subsequent block is computed using SIMD operations and the
+/// block is skipped entirely if it cannot contain a value that would enter
+/// the top-k. For typical inputs such as logits, only a small fraction of
+/// blocks need to be scanned element by element.
+#[inline(always)]
+unsafe fn simd_top_k<S: SimdFloat>(input: PtrLen<f32>, k: usize) -> Vec<(usize, f32)> {
+    assert!(S::LEN <= MAX_LEN);
+    let xs = std::slice::from_raw_parts(input.ptr(), input.len());
+    let mut heap = BinaryHeap::with_capacity(k + 1);
+
+    for (block_idx, block) in xs.chunks(TOPK_BLOCK_SIZE).enumerate() {
+        if heap.len() == k {
+            let block_max = simd_fold(
+                block.into(),
+                S::splat(f32::NEG_INFINITY),
+                // The operand order means that on architectures where `max`
+                // returns the second operand if either is NaN, NaNs in the
+                // input are skipped. On other architectures NaNs propagate,
+                // so the block is scanned.
+                #[inline(always)]
+                |max, x| x.max(max),
+                f32::NEG_INFINITY, /* pad */
+            )
+            .reduce_max();
+
+            let threshold = heap.peek().map(|e: &TopKEntry| e.value).unwrap();
+            if block_max <= threshold {
+                continue;
+            }
+        }
+
+        let offset = block_idx * TOPK_BLOCK_SIZE;
+        for (i, &value) in block.iter().enumerate() {
+            if value.is_nan() {
+                continue;
+            }
+            if heap.len() < k {
+                heap.push(TopKEntry {
+                    index: offset + i,
+                    value,
+                });
+            } else if value > heap.peek().unwrap().value {
+                heap.pop();
+                heap.push(TopKEntry {
+                    index: offset + i,
+                    value,
+                });
+            }
+        }
+    }
+
+    heap.into_sorted_vec()
+        .into_iter()
+        .map(|e| (e.index, e.value))
+        .collect()
+}
+
+struct SimdTopK<'a> {
+    input: PtrLen<f32>,
+    k: usize,
+    output: &'a RefCell<Vec<(usize, f32)>>,
+}
+
+impl SimdOp for SimdTopK<'_> {
+    #[inline(always)]
+    unsafe fn eval<S: SimdFloat>(&self) {
+        *self.output.borrow_mut() = simd_top_k::<S>(self.input, self.k);
+    }
+}
+
+/// Return the indices and values of the `k` largest elements in `xs`.
+///
+/// The result is sorted in descending order of value. Among elements with
+/// equal values, those with lower indices come first and are preferred when
+/// the top-k is cut off. NaN values are ignored, so the result may contain
+/// fewer than `k` entries if `xs` has fewer than `k` non-NaN elements.
+///
+/// This is a partial selection, so it is much faster than sorting the whole
+/// input when `k` is small relative to `xs.len()`, as when sampling from
+/// logits over a large vocabulary.
+pub fn top_k(xs: &[f32], k: usize) -> Vec<(usize, f32)> {
+    if k == 0 {
+        return Vec::new();
+    }
+
+    let output = RefCell::new(Vec::new());
+    let op = SimdTopK {
+        input: xs.into(),
+        k,
+        output: &output,
+    };
+    let dispatcher = SimdDispatcher::default();
+    dispatcher.dispatch(op);
+    output.into_inner()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{argmax, top_k};
+    use crate::testing::benchmark_op;
+
+    fn reference_top_k(xs: &[f32], k: usize) -> Vec<(usize, f32)> {
+        let mut entries: Vec<_> = xs
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|(_, x)| !x.is_nan())
+            .collect();
+        entries.sort_by(|(ia, a), (ib, b)| b.total_cmp(a).then(ia.cmp(ib)));
+        entries.truncate(k);
+        entries
+    }
+
+    #[test]
+    fn test_argmax() {
+        let cases: &[(&[f32], (usize, f32))] = &[
+            (&[1.], (0, 1.)),
+            (&[1., 3., 2.], (1, 3.)),
+            // Ties are resolved in favor of the first occurrence.
+            (&[5., 1., 5., 5.], (0, 5.)),
+            (&[-1., f32::NAN, -0.5], (2, -0.5)),
+            (
+                &[f32::NEG_INFINITY, f32::NEG_INFINITY],
+                (0, f32::NEG_INFINITY),
+            ),
+            (&[f32::NAN, f32::NEG_INFINITY], (1, f32::NEG_INFINITY)),
+        ];
+        for (xs, expected) in cases {
+            assert_eq!(argmax(xs), *expected);
+        }
+
+        let (idx, val) = argmax(&[f32::NAN, f32::NAN]);
+        assert_eq!(idx, 0);
+        assert!(val.is_nan());
+    }
+
+    #[test]
+    fn test_argmax_long() {
+        // Use lengths which are not a multiple of the vector width, with the
+        // maximum in each position, including the tail.
+        let len = 37;
+        for max_pos in 0..len {
+            let mut xs: Vec<f32> = (0..len).map(|i| (i % 5) as f32).collect();
+            xs[max_pos] = 10.;
+            // Add a later duplicate to check that the first index is returned.
+            if max_pos + 1 < len {
+                xs[len - 1] = 10.;
+            }
+            assert_eq!(argmax(&xs), (max_pos, 10.));
+        }
+    }
+
+    #[test]
+    fn test_top_k() {
+        let mut rng = fastrand::Rng::with_seed(1234);
+        let xs: Vec<f32> = (0..1000).map(|_| rng.f32()).collect();
+
+        for k in [0, 1, 5, 50, 999, 1000, 2000] {
+            assert_eq!(
+                top_k(&xs, k),
+                reference_top_k(&xs, k),
+                "mismatch for k={}",
+                k
+            );
+        }
+    }
+
+    #[test]
+    fn test_top_k_ties_and_nans() {
+        let xs = [1., f32::NAN, 3., 2., 3., 1.];
+        assert_eq!(top_k(&xs, 2), [(2, 3.), (4, 3.)]);
+        assert_eq!(top_k(&xs, 3), [(2, 3.), (4, 3.), (3, 2.)]);
+        assert_eq!(top_k(&xs, 4), [(2, 3.), (4, 3.), (3, 2.), (0, 1.)]);
+        assert_eq!(top_k(&xs, 10), reference_top_k(&xs, 10));
+        assert_eq!(top_k(&[], 3), []);
+    }
+
+    #[test]
+    fn test_top_k_skips_blocks() {
+        // Increasing values mean that each block is scanned, while decreasing
+        // values mean that all blocks after the first are skipped.
+        let increasing: Vec<f32> = (0..2000).map(|i| i as f32).collect();
+        let decreasing: Vec<f32> = increasing.iter().rev().copied().collect();
+        for xs in [increasing, decreasing] {
+            assert_eq!(top_k(&xs, 10), reference_top_k(&xs, 10));
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_argmax() {
+        benchmark_op(
+            |xs, ys| {
+                let (idx, _) =
+                    xs.iter()
+                        .enumerate()
+                        .fold((0, f32::NEG_INFINITY), |(max_i, max), (i, &x)| {
+                            if x > max {
+                                (i, x)
+                            } else {
+                                (max_i, max)
+                            }
+                        });
+                ys[0] = idx as f32;
+            },
+            |xs, ys| {
+                let (idx, _) = argmax(xs);
+                ys[0].write(idx as f32);
+            },
+        );
+    }
+}
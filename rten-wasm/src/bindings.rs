@@ -0,0 +1,103 @@
+use js_sys::Function;
+use wasm_bindgen::prelude::*;
+
+use crate::generator::Generator as GeneratorImpl;
+use crate::tokenizer::Tokenizer as TokenizerImpl;
+
+pub use rten::wasm_api::{Model, NodeInfo, Tensor};
+
+/// Tokenizer which converts between text and token IDs.
+#[wasm_bindgen]
+pub struct Tokenizer {
+    tokenizer: TokenizerImpl,
+}
+
+#[wasm_bindgen]
+impl Tokenizer {
+    /// Create a tokenizer from the contents of a `tokenizer.json` file in the
+    /// Hugging Face Tokenizers format.
+    #[wasm_bindgen(constructor)]
+    pub fn new(json: &str) -> Result<Tokenizer, String> {
+        let tokenizer = TokenizerImpl::from_json(json)?;
+        Ok(Tokenizer { tokenizer })
+    }
+
+    /// Create a tokenizer from the UTF-8 encoded contents of a
+    /// `tokenizer.json` file, such as a `Uint8Array` returned by `fetch`.
+    #[wasm_bindgen(js_name = fromBytes)]
+    pub fn from_bytes(json: &[u8]) -> Result<Tokenizer, String> {
+        let tokenizer = TokenizerImpl::from_bytes(json)?;
+        Ok(Tokenizer { tokenizer })
+    }
+
+    /// Encode text into an array of token IDs.
+    pub fn encode(&self, text: &str) -> Result<Vec<u32>, String> {
+        self.tokenizer.encode(text)
+    }
+
+    /// Decode an array of token IDs into text.
+    pub fn decode(&self, ids: &[u32]) -> Result<String, String> {
+        self.tokenizer.decode(ids)
+    }
+}
+
+/// Runs the generation loop for an auto-regressive transformer model.
+///
+/// Tokens can be generated one at a time using `nextToken`, which allows the
+/// caller to update the UI between steps, or all at once using `generate`.
+#[wasm_bindgen]
+pub struct Generator {
+    generator: GeneratorImpl,
+}
+
+#[wasm_bindgen]
+impl Generator {
+    /// Create a generator which produces tokens following `prompt`.
+    ///
+    /// At most `max_tokens` tokens are generated. Generation stops early when
+    /// a token in `stop_tokens` is generated. If `top_k` is set, tokens are
+    /// sampled from the `top_k` most likely tokens after applying
+    /// `temperature` (default 1.0), otherwise the most likely token is chosen.
+    ///
+    /// The generator keeps the model alive until it is freed.
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        model: &Model,
+        prompt: &[u32],
+        max_tokens: usize,
+        stop_tokens: Vec<u32>,
+        top_k: Option<usize>,
+        temperature: Option<f32>,
+    ) -> Result<Generator, String> {
+        let generator = GeneratorImpl::new(
+            model.shared_model(),
+            prompt,
+            max_tokens,
+            stop_tokens,
+            top_k,
+            temperature,
+        )?;
+        Ok(Generator { generator })
+    }
+
+    /// Run one step of generation and return the next token ID, or
+    /// `undefined` if generation has finished.
+    #[wasm_bindgen(js_name = nextToken)]
+    pub fn next_token(&mut self) -> Result<Option<u32>, String> {
+        self.generator.next_token()
+    }
+
+    /// Run generation to completion and return the generated token IDs,
+    /// excluding the prompt and any stop token.
+    ///
+    /// `on_token` is called with each token ID as it is generated. An
+    /// exception thrown by the callback stops generation.
+    pub fn generate(&mut self, on_token: Option<Function>) -> Result<Vec<u32>, JsValue> {
+        self.generator.generate(|token| {
+            if let Some(callback) = &on_token {
+                callback.call1(&JsValue::NULL, &JsValue::from(token))?;
+            }
+            Ok(())
+        })
+    }
+}
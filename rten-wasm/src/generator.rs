@@ -0,0 +1,137 @@
+use std::sync::Arc;
+
+use rten_generate::generator::GeneratorItem;
+use rten_generate::model::Model;
+use rten_generate::Generator as GeneratorImpl;
+
+/// Runs the generation loop for an auto-regressive transformer model.
+///
+/// This implements the JS `Generator` class. Errors are returned as strings
+/// so that they can be passed to JS unchanged.
+pub struct Generator {
+    tokens: Box<dyn Iterator<Item = GeneratorItem>>,
+}
+
+impl Generator {
+    /// Create a generator which produces tokens following `prompt`.
+    ///
+    /// At most `max_tokens` tokens are generated. Generation stops early when
+    /// a token in `stop_tokens` is generated. If `top_k` is set, tokens are
+    /// sampled from the `top_k` most likely tokens after applying
+    /// `temperature` (default 1.0), otherwise the most likely token is chosen.
+    pub fn new(
+        model: Arc<dyn Model>,
+        prompt: &[u32],
+        max_tokens: usize,
+        stop_tokens: Vec<u32>,
+        top_k: Option<usize>,
+        temperature: Option<f32>,
+    ) -> Result<Generator, String> {
+        let tokens = GeneratorImpl::from_shared_model(model)
+            .map_err(|e| e.to_string())?
+            .with_prompt(prompt)
+            .sample_tokens(max_tokens, top_k, temperature.unwrap_or(1.0), stop_tokens);
+
+        Ok(Generator {
+            tokens: Box::new(tokens),
+        })
+    }
+
+    /// Run one step of generation and return the next token ID, or `None` if
+    /// generation has finished.
+    pub fn next_token(&mut self) -> Result<Option<u32>, String> {
+        self.tokens.next().transpose().map_err(|e| e.to_string())
+    }
+
+    /// Run generation to completion and return the generated token IDs.
+    ///
+    /// `on_token` is called with each token ID as it is generated. If it
+    /// returns an error, generation stops and the error is returned.
+    pub fn generate<E: From<String>>(
+        &mut self,
+        mut on_token: impl FnMut(u32) -> Result<(), E>,
+    ) -> Result<Vec<u32>, E> {
+        let mut tokens = Vec::new();
+        for token in self.tokens.by_ref() {
+            let token = token.map_err(|e| e.to_string())?;
+            tokens.push(token);
+            on_token(token)?;
+        }
+        Ok(tokens)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rten_generate::test_models::{counting_model, relu_model};
+
+    use super::Generator;
+
+    fn load_model(data: Vec<u8>) -> Arc<rten::Model> {
+        Arc::new(rten::Model::load(data).unwrap())
+    }
+
+    #[test]
+    fn test_generator_next_token() {
+        let mut generator = Generator::new(
+            load_model(counting_model(10)),
+            &[1, 2],
+            5,
+            vec![6],
+            None,
+            None,
+        )
+        .unwrap();
+
+        let mut tokens = Vec::new();
+        while let Some(token) = generator.next_token().unwrap() {
+            tokens.push(token);
+        }
+        assert_eq!(tokens, [3, 4, 5]);
+        assert_eq!(generator.next_token(), Ok(None));
+    }
+
+    #[test]
+    fn test_generator_generate() {
+        let mut generator = Generator::new(
+            load_model(counting_model(10)),
+            &[1],
+            3,
+            vec![],
+            Some(1),
+            Some(0.5),
+        )
+        .unwrap();
+        let mut streamed = Vec::new();
+        let tokens = generator
+            .generate(|token| {
+                streamed.push(token);
+                Ok::<_, String>(())
+            })
+            .unwrap();
+        assert_eq!(tokens, [2, 3, 4]);
+        assert_eq!(streamed, tokens);
+
+        // An error from the callback stops generation.
+        let mut generator =
+            Generator::new(load_model(counting_model(10)), &[1], 3, vec![], None, None).unwrap();
+        let result = generator.generate(|token| {
+            if token == 3 {
+                Err("stopped".to_string())
+            } else {
+                Ok(())
+            }
+        });
+        assert_eq!(result, Err("stopped".to_string()));
+        assert_eq!(generator.next_token(), Ok(Some(4)));
+    }
+
+    #[test]
+    fn test_generator_invalid_model() {
+        let model = load_model(relu_model());
+        let result = Generator::new(model, &[1], 3, vec![], None, None);
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,20 @@
+//! WebAssembly bindings for RTen.
+//!
+//! This re-exports the JavaScript API from the `wasm_api` feature of the main
+//! crate and adds tokenization and text generation, so that a complete chat
+//! or completion demo can run in the browser.
+//!
+//! The bindings are only available when compiling for `wasm32` targets. They
+//! wrap the `generator` and `tokenizer` modules, which are also compiled
+//! natively when running tests.
+
+#[cfg(any(target_arch = "wasm32", test))]
+mod generator;
+#[cfg(any(target_arch = "wasm32", test))]
+mod tokenizer;
+
+#[cfg(target_arch = "wasm32")]
+mod bindings;
+
+#[cfg(target_arch = "wasm32")]
+pub use bindings::*;
@@ -0,0 +1,83 @@
+use rten_text::tokenizers::{EncodeOptions, Tokenizer as TokenizerImpl};
+
+/// Tokenizer which converts between text and token IDs.
+///
+/// This implements the JS `Tokenizer` class. Errors are returned as strings
+/// so that they can be passed to JS unchanged.
+pub struct Tokenizer {
+    tokenizer: TokenizerImpl,
+}
+
+impl Tokenizer {
+    /// Create a tokenizer from the contents of a `tokenizer.json` file.
+    pub fn from_json(json: &str) -> Result<Tokenizer, String> {
+        let tokenizer = TokenizerImpl::from_json(json).map_err(|e| e.to_string())?;
+        Ok(Tokenizer { tokenizer })
+    }
+
+    /// Create a tokenizer from the UTF-8 encoded contents of a
+    /// `tokenizer.json` file.
+    pub fn from_bytes(json: &[u8]) -> Result<Tokenizer, String> {
+        let tokenizer = TokenizerImpl::from_bytes(json).map_err(|e| e.to_string())?;
+        Ok(Tokenizer { tokenizer })
+    }
+
+    /// Encode text into a sequence of token IDs.
+    pub fn encode(&self, text: &str) -> Result<Vec<u32>, String> {
+        let encoded = self
+            .tokenizer
+            .encode(text.into(), EncodeOptions::default())
+            .map_err(|e| e.to_string())?;
+        Ok(encoded.token_ids().to_vec())
+    }
+
+    /// Decode a sequence of token IDs into text.
+    pub fn decode(&self, ids: &[u32]) -> Result<String, String> {
+        self.tokenizer.decode(ids).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Tokenizer;
+
+    const TOKENIZER_JSON: &str = r###"{
+        "model": {
+            "type": "WordPiece",
+            "vocab": { "foo": 1, "##bar": 2, "[CLS]": 3, "[SEP]": 4 }
+        }
+    }"###;
+
+    #[test]
+    fn test_tokenizer() {
+        let tokenizer = Tokenizer::from_json(TOKENIZER_JSON).unwrap();
+        let ids = tokenizer.encode("foobar").unwrap();
+        assert_eq!(ids, [3, 1, 2, 4]);
+        assert_eq!(tokenizer.decode(&ids[1..2]).unwrap(), "foo");
+
+        let tokenizer = Tokenizer::from_bytes(TOKENIZER_JSON.as_bytes()).unwrap();
+        assert_eq!(tokenizer.encode("foo").unwrap(), [3, 1, 4]);
+
+        assert!(Tokenizer::from_json("{}").is_err());
+        assert!(Tokenizer::from_bytes(b"\xff").is_err());
+    }
+
+    #[test]
+    fn test_tokenizer_decoder() {
+        let json = r#"{
+            "model": {
+                "type": "WordLevel",
+                "vocab": { "▁hello": 0, "▁world": 1, "<0x21>": 2 }
+            },
+            "decoder": {
+                "type": "Sequence",
+                "decoders": [
+                    { "type": "ByteFallback" },
+                    { "type": "Metaspace", "replacement": "▁", "prepend_scheme": "always" }
+                ]
+            }
+        }"#;
+        let tokenizer = Tokenizer::from_json(json).unwrap();
+        assert_eq!(tokenizer.decode(&[0, 1, 2]).unwrap(), "hello world!");
+    }
+}
@@ -1,6 +1,6 @@
 //! Connectionist Temporal Classification (CTC) sequence decoding tools.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::num::NonZeroU32;
 
 use rten_tensor::prelude::*;
@@ -20,10 +20,138 @@ use crate::Operators;
 /// consider the N most probable paths through the matrix. This may produce more
 /// accurate results, but is significantly slower.
 ///
+/// Beam searches can additionally be guided by a [CtcScorer], such as a
+/// [Lexicon] or a language model, which adjusts the scores of hypotheses as
+/// labels are added to them. See [CtcDecoder::with_scorer].
+///
 /// [^1]: <https://en.wikipedia.org/wiki/Connectionist_temporal_classification>
 ///
 /// [^2]: <https://distill.pub/2017/ctc/>
-pub struct CtcDecoder {}
+pub struct CtcDecoder {
+    scorer: Option<Box<dyn CtcScorer>>,
+}
+
+/// Adjusts the scores of hypotheses during beam search decoding by
+/// [CtcDecoder].
+///
+/// This can be used to implement shallow fusion with a language model, or to
+/// constrain the output to a vocabulary. Scores are log probabilities (or
+/// weighted log probabilities) which are added to the score from the CTC
+/// model when ranking hypotheses.
+pub trait CtcScorer {
+    /// Return the score for extending the label sequence `prefix` with `label`.
+    fn score_extension(&self, prefix: &[DecodeStep], label: u32) -> f32;
+
+    /// Return the score for ending decoding with the label sequence `prefix`.
+    ///
+    /// This is applied to the hypotheses that remain after the final decoding
+    /// step. The default implementation returns zero.
+    fn score_end(&self, _prefix: &[DecodeStep]) -> f32 {
+        0.
+    }
+}
+
+/// A [CtcScorer] which penalizes hypotheses that contain words which are not
+/// in a fixed vocabulary.
+///
+/// Words are sequences of labels separated by labels which correspond to
+/// whitespace characters in the alphabet. A penalty is applied as soon as a
+/// partial word is not the prefix of any word in the vocabulary, or when a
+/// word which is a prefix of vocabulary words, but not a complete word, is
+/// ended.
+pub struct Lexicon {
+    alphabet: Vec<char>,
+    words: HashSet<String>,
+    prefixes: HashSet<String>,
+    penalty: f32,
+}
+
+impl Lexicon {
+    /// Create a lexicon from an alphabet and a list of words.
+    ///
+    /// The label `i` corresponds to the character at index `i - 1` in
+    /// `alphabet`, as in [CtcHypothesis::to_string].
+    pub fn new<'a>(alphabet: &str, words: impl IntoIterator<Item = &'a str>) -> Lexicon {
+        let words: HashSet<String> = words.into_iter().map(|w| w.to_string()).collect();
+        let prefixes = words
+            .iter()
+            .flat_map(|word| {
+                word.char_indices()
+                    .map(|(i, ch)| &word[..i + ch.len_utf8()])
+            })
+            .map(|prefix| prefix.to_string())
+            .collect();
+        Lexicon {
+            alphabet: alphabet.chars().collect(),
+            words,
+            prefixes,
+            penalty: -10.,
+        }
+    }
+
+    /// Set the log-probability penalty applied to out-of-vocabulary words.
+    ///
+    /// The default is -10. Use [f32::NEG_INFINITY] to exclude hypotheses with
+    /// out-of-vocabulary words entirely.
+    pub fn with_penalty(mut self, penalty: f32) -> Lexicon {
+        self.penalty = penalty;
+        self
+    }
+
+    fn label_char(&self, label: u32) -> Option<char> {
+        self.alphabet.get((label as usize).checked_sub(1)?).copied()
+    }
+
+    fn is_separator(&self, label: u32) -> bool {
+        self.label_char(label).is_some_and(|ch| ch.is_whitespace())
+    }
+
+    /// Return the partial word at the end of `prefix`.
+    fn last_word(&self, prefix: &[DecodeStep]) -> String {
+        let start = prefix
+            .iter()
+            .rposition(|step| self.is_separator(step.label))
+            .map(|pos| pos + 1)
+            .unwrap_or(0);
+        prefix[start..]
+            .iter()
+            .map(|step| self.label_char(step.label).unwrap_or('?'))
+            .collect()
+    }
+
+    /// Return the score for ending a word. Words which are not valid prefixes
+    /// have already been penalized by [Lexicon::score_extension].
+    fn score_word_end(&self, word: &str) -> f32 {
+        if word.is_empty() || self.words.contains(word) || !self.prefixes.contains(word) {
+            0.
+        } else {
+            self.penalty
+        }
+    }
+}
+
+impl CtcScorer for Lexicon {
+    fn score_extension(&self, prefix: &[DecodeStep], label: u32) -> f32 {
+        let mut word = self.last_word(prefix);
+        if self.is_separator(label) {
+            return self.score_word_end(&word);
+        }
+
+        // Only penalize the label which first makes the partial word invalid,
+        // so that each out-of-vocabulary word is penalized once.
+        let was_valid = word.is_empty() || self.prefixes.contains(&word);
+        word.push(self.label_char(label).unwrap_or('?'));
+        if was_valid && !self.prefixes.contains(&word) {
+            self.penalty
+        } else {
+            0.
+        }
+    }
+
+    fn score_end(&self, prefix: &[DecodeStep]) -> f32 {
+        self.score_word_end(&self.last_word(prefix))
+    }
+}
 
 /// Item in an output sequence produced by [CtcDecoder].
 #[derive(Clone, Copy, Debug)]
@@ -52,6 +180,16 @@ struct BeamState {
 
     /// Log probability of prefix not followed by a blank.
     prob_no_blank: f32,
+
+    /// Sum of scores for the prefix from the decoder's [CtcScorer].
+    scorer_score: f32,
+}
+
+impl BeamState {
+    /// Return the score used to rank this state.
+    fn total_score(&self) -> f32 {
+        log_sum_exp([self.prob_blank, self.prob_no_blank]) + self.scorer_score
+    }
 }
 
 /// Compute the sum of probabilities in log space.
@@ -97,10 +235,8 @@ impl CtcHypothesis {
     }
 
     fn from_beam_state(state: BeamState) -> CtcHypothesis {
-        Self::new(
-            state.prefix,
-            log_sum_exp([state.prob_blank, state.prob_no_blank]),
-        )
+        let score = state.total_score();
+        Self::new(state.prefix, score)
     }
 
     /// Convert the label sequence to a string, using the given alphabet.
@@ -121,7 +257,8 @@ impl CtcHypothesis {
     /// For hypotheses produced by greedy decoding, this is the product of
     /// probabilities of the most likely label at each time step. For beam
     /// search decoding, this is the sum of probabilities of all paths that
-    /// produce this hypothesis's label sequence.
+    /// produce this hypothesis's label sequence, plus the score from the
+    /// decoder's [CtcScorer], if any.
     ///
     /// This score is not normalized by the input length, so longer input
     /// sequences will tend to lead to lower scores.
@@ -137,7 +274,14 @@ impl CtcHypothesis {
 
 impl CtcDecoder {
     pub fn new() -> CtcDecoder {
-        CtcDecoder {}
+        CtcDecoder { scorer: None }
+    }
+
+    /// Set a scorer which is used to adjust the scores of hypotheses during
+    /// beam search decoding. This has no effect on greedy decoding.
+    pub fn with_scorer<S: CtcScorer + 'static>(mut self, scorer: S) -> CtcDecoder {
+        self.scorer = Some(Box::new(scorer));
+        self
     }
 
     /// Decode sequence using a greedy method.
@@ -223,6 +367,7 @@ impl CtcDecoder {
             prefix: Vec::new(),
             prob_blank: 0.,
             prob_no_blank: f32::NEG_INFINITY,
+            scorer_score: 0.,
         }];
 
         // Probabilities for extensions to beam. The label 0 is used to mean
@@ -243,8 +388,12 @@ impl CtcDecoder {
             /// Label to extend prefix with.
             label: Option<NonZeroU32>,
 
-            /// Probability of new beam state, with this extension.
+            /// Score of new beam state, with this extension. This is the
+            /// probability of the state plus the scorer's score.
             prob: f32,
+
+            /// Sum of scorer scores for the new beam state.
+            scorer_score: f32,
         }
         let mut topk_extensions: Vec<BeamExtension> = Vec::new();
 
@@ -277,6 +426,7 @@ impl CtcDecoder {
                     prefix,
                     prob_blank,
                     prob_no_blank,
+                    ..
                 },
             ) in beam.iter().enumerate()
             {
@@ -330,6 +480,16 @@ impl CtcDecoder {
                         next_prob_blank[[bi, label]],
                         next_prob_no_blank[[bi, label]],
                     ]);
+                    // Only query the scorer for extensions that are possible.
+                    let scorer_score = match (&self.scorer, label) {
+                        (Some(scorer), label) if label > 0 && prob_sum > f32::NEG_INFINITY => {
+                            beam[bi].scorer_score
+                                + scorer.score_extension(&beam[bi].prefix, label as u32)
+                        }
+                        _ => beam[bi].scorer_score,
+                    };
+                    let prob_sum = prob_sum + scorer_score;
+
                     if topk_extensions.len() < beam_size as usize
                         || prob_sum
                             > topk_extensions
@@ -341,6 +501,7 @@ impl CtcDecoder {
                             index: bi as u32,
                             label: NonZeroU32::new(label as u32),
                             prob: prob_sum,
+                            scorer_score,
                         });
 
                         // Sort by probability descending.
@@ -369,11 +530,19 @@ impl CtcDecoder {
                             [[i, ext.label.map(|l| l.get() as usize).unwrap_or(0)]],
                         prob_no_blank: next_prob_no_blank
                             [[i, ext.label.map(|l| l.get() as usize).unwrap_or(0)]],
+                        scorer_score: ext.scorer_score,
                     }
                 })
                 .collect();
         }
 
+        if let Some(scorer) = &self.scorer {
+            for state in beam.iter_mut() {
+                state.scorer_score += scorer.score_end(&state.prefix);
+            }
+            beam.sort_by(|a, b| b.total_score().total_cmp(&a.total_score()));
+        }
+
         beam
     }
 }
@@ -389,7 +558,7 @@ mod tests {
     use rten_tensor::prelude::*;
     use rten_tensor::NdTensor;
 
-    use super::{log_sum_exp, CtcDecoder, CtcHypothesis};
+    use super::{log_sum_exp, CtcDecoder, CtcHypothesis, CtcScorer, DecodeStep, Lexicon};
 
     const ALPHABET: &str = " abcdefghijklmnopqrstuvwxyz";
 
@@ -530,4 +699,73 @@ mod tests {
         let expected_score = log_sum_exp([input[[0, blank_label]] + input[[1, blank_label]]]);
         assert_eq!(beam_output.score(), expected_score);
     }
+
+    #[test]
+    fn test_decode_beam_with_scorer() {
+        // Scorer which strongly penalizes the letter "a".
+        struct NoA;
+        impl CtcScorer for NoA {
+            fn score_extension(&self, _prefix: &[DecodeStep], label: u32) -> f32 {
+                if label == 1 {
+                    -100.
+                } else {
+                    0.
+                }
+            }
+        }
+
+        // Input where beam search without a scorer decodes "a", using the
+        // alphabet "a". See `test_decode_beam_sums_paths`.
+        let mut input = NdTensor::<f32, 2>::zeros([2, 2]);
+        input[[0, 0]] = 0.8;
+        input[[0, 1]] = 0.2;
+        input[[1, 0]] = 0.6;
+        input[[1, 1]] = 0.4;
+        input.apply(|x| x.ln());
+
+        let decoder = CtcDecoder::new();
+        assert_eq!(decoder.decode_beam(input.view(), 10).to_string("a"), "a");
+
+        let decoder = CtcDecoder::new().with_scorer(NoA);
+        let output = decoder.decode_beam(input.view(), 10);
+        assert_eq!(output.to_string("a"), "");
+        assert_eq!(output.score(), input[[0, 0]] + input[[1, 0]]);
+
+        // Greedy decoding ignores the scorer.
+        assert_eq!(decoder.decode_greedy(input.view()).to_string("a"), "");
+    }
+
+    #[test]
+    fn test_decode_beam_with_lexicon() {
+        // Input where the most likely output is "cot sat", but "cat sat" is
+        // the only sequence made up of words in the lexicon.
+        let mut input = onehot_tensor(&encode_str("cot sat", true));
+        let a_label = encode_str("a", false)[0] as usize;
+        let o_label = encode_str("o", false)[0] as usize;
+        input[[1, o_label]] = 0.6f32.ln();
+        input[[1, a_label]] = 0.4f32.ln();
+
+        let decoder = CtcDecoder::new();
+        let output = decoder.decode_beam(input.view(), 10);
+        assert_eq!(output.to_string(ALPHABET), "cot sat");
+
+        let lexicon = Lexicon::new(ALPHABET, ["cat", "sat"]);
+        let decoder = CtcDecoder::new().with_scorer(lexicon);
+        let output = decoder.decode_beam(input.view(), 10);
+        assert_eq!(output.to_string(ALPHABET), "cat sat");
+
+        // A word which is a prefix of a lexicon word, but not a complete word,
+        // is penalized at the end of the sequence.
+        let lexicon = Lexicon::new(ALPHABET, ["ca", "cots"]).with_penalty(-5.);
+        let steps = |s: &str| -> Vec<DecodeStep> {
+            encode_str(s, false)
+                .into_iter()
+                .map(|label| DecodeStep { label, pos: 0 })
+                .collect()
+        };
+        assert_eq!(lexicon.score_end(&steps("ca")), 0.);
+        assert_eq!(lexicon.score_end(&steps("cot")), -5.);
+        assert_eq!(lexicon.score_extension(&steps("co"), a_label as u32), -5.);
+        assert_eq!(lexicon.score_extension(&steps("cox"), a_label as u32), 0.);
+    }
 }
@@ -11,17 +11,26 @@ use std::ops::Range;
 
 use rayon::prelude::*;
 use rten_tensor::prelude::*;
-use rten_tensor::{Alloc, GlobalAlloc, Matrix, MatrixLayout, MatrixMut, NdTensorView};
+use rten_tensor::{Alloc, GlobalAlloc, Matrix, MatrixLayout, MatrixMut, NdTensorView, TensorView};
+use rten_vecmath::{vec_gelu_in_place, vec_silu_in_place};
 
 use crate::iter_util::{range_chunks, MaybeParIter};
-use crate::tensor_pool::ExtractBuffer;
+use crate::ops::broadcast_shapes;
+use crate::tensor_pool::{AutoReturn, ExtractBuffer, TensorPool};
 
+mod int4;
 mod kernels;
 mod packing;
 
+pub use int4::Int4Matrix;
 use kernels::{BaseKernel, Kernel};
 
 /// Left-hand or "A" GEMM input that has been pre-packed.
+///
+/// Packed matrices are created using [GemmExecutor::prepack_a] and can be
+/// re-used across GEMM calls, including from different threads. The packed
+/// layout is specific to the kernel that packed it, so the matrix must be used
+/// with a [GemmExecutor] that uses the same kernel.
 #[derive(Clone)]
 pub struct PackedAMatrix {
     /// Sequence of packed row panels.
@@ -38,9 +47,22 @@ pub struct PackedAMatrix {
 
     /// Number of columns in the unpacked matrix.
     cols: usize,
+
+    /// Name of the kernel used to pack the matrix.
+    kernel_name: &'static str,
 }
 
 impl PackedAMatrix {
+    /// Return the number of rows in the unpacked matrix.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Return the number of columns in the unpacked matrix.
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
     fn block(&self, row_block_idx: usize, depth_block_idx: usize) -> &[f32] {
         let panel_idx = depth_block_idx * self.row_blocks + row_block_idx;
         let offset = panel_idx * self.panel_len;
@@ -57,6 +79,10 @@ impl ExtractBuffer for PackedAMatrix {
 }
 
 /// Right-hand or "B" GEMM input that has been pre-packed.
+///
+/// Packed matrices are created using [GemmExecutor::prepack_b]. This is
+/// typically used for weights which are re-used across many GEMM calls. See
+/// [PackedAMatrix] for restrictions on how packed matrices can be used.
 #[derive(Clone)]
 pub struct PackedBMatrix {
     /// Sequence of packed column panels.
@@ -73,9 +99,29 @@ pub struct PackedBMatrix {
 
     /// Number of columns in the unpacked matrix.
     cols: usize,
+
+    /// Size of blocks that the matrix was divided into along the N dimension.
+    ///
+    /// The column block size depends on the number of threads available when
+    /// the matrix is packed, so it is recorded here rather than recomputed
+    /// when the matrix is used.
+    col_block_size: usize,
+
+    /// Name of the kernel used to pack the matrix.
+    kernel_name: &'static str,
 }
 
 impl PackedBMatrix {
+    /// Return the number of rows in the unpacked matrix.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Return the number of columns in the unpacked matrix.
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
     fn block(&self, col_block_idx: usize, depth_block_idx: usize) -> &[f32] {
         let panel_idx = col_block_idx * self.depth_blocks + depth_block_idx;
         let offset = panel_idx * self.panel_len;
@@ -192,6 +238,39 @@ impl<'a> GemmInputB<'a> {
     }
 }
 
+/// Bias vector which can be added to the output of a GEMM operation.
+#[derive(Copy, Clone)]
+pub enum BiasVector<'a> {
+    /// Column vector with one element per row of the output.
+    Column(&'a [f32]),
+
+    /// Row vector with one element per column of the output.
+    Row(&'a [f32]),
+}
+
+/// Elementwise activation function which can be fused into a GEMM operation.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Activation {
+    Relu,
+    Gelu,
+    Silu,
+}
+
+impl Activation {
+    /// Apply the activation function to each element of `xs`.
+    fn apply(self, xs: &mut [f32]) {
+        match self {
+            Activation::Relu => {
+                for x in xs {
+                    *x = x.max(0.);
+                }
+            }
+            Activation::Gelu => vec_gelu_in_place(xs),
+            Activation::Silu => vec_silu_in_place(xs),
+        }
+    }
+}
+
 /// Perform a General Matrix Multiplication ("gemm").
 ///
 /// This computes `output = alpha * (a @ b) + beta * output` where `@` is
@@ -235,6 +314,12 @@ pub struct GemmExecutor {
     kernel_type: KernelType,
 }
 
+impl Default for GemmExecutor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Arguments for [GemmExecutor::with_kernel] specifying which kernel to use.
 #[derive(Clone, Copy, Debug)]
 pub enum KernelType {
@@ -375,6 +460,7 @@ impl GemmExecutor {
             cols: a.cols(),
             panel_len,
             row_blocks,
+            kernel_name: self.kernel.name(),
         }
     }
 
@@ -434,6 +520,8 @@ impl GemmExecutor {
             cols: b.cols(),
             depth_blocks,
             panel_len,
+            col_block_size: nc,
+            kernel_name: self.kernel.name(),
         }
     }
 
@@ -463,6 +551,7 @@ impl GemmExecutor {
             alpha,
             beta,
             None,
+            None,
         )
     }
 
@@ -507,7 +596,8 @@ impl GemmExecutor {
             b,
             alpha,
             beta,
-            bias,
+            bias.map(BiasVector::Column),
+            None,
         )
     }
 
@@ -526,6 +616,40 @@ impl GemmExecutor {
         b: GemmInputB,
         alpha: f32,
         bias: Option<&[f32]>,
+    ) {
+        gemm_impl(
+            &*self.kernel,
+            // Safety: When beta is zero, we initialize all output elements
+            // and ignore existing values.
+            unsafe { transmute::<&mut [MaybeUninit<f32>], &mut [f32]>(out_data) },
+            out_row_stride,
+            a,
+            b,
+            alpha,
+            0., /* beta */
+            bias.map(BiasVector::Column),
+            None,
+        )
+    }
+
+    /// Perform a matrix multiplication with fused bias addition and
+    /// activation.
+    ///
+    /// This computes `output = activation(alpha * (a @ b) + bias)` where `@`
+    /// is matrix multiplication. The bias and activation are applied to each
+    /// tile of the output as soon as it has been fully computed, while it is
+    /// still in cache, which avoids separate passes over the output.
+    ///
+    /// After this function returns, `out_data` will be fully initialized.
+    pub fn gemm_uninit_fused(
+        &self,
+        out_data: &mut [MaybeUninit<f32>],
+        out_row_stride: usize,
+        a: GemmInputA,
+        b: GemmInputB,
+        alpha: f32,
+        bias: Option<BiasVector>,
+        activation: Option<Activation>,
     ) {
         gemm_impl(
             &*self.kernel,
@@ -538,10 +662,115 @@ impl GemmExecutor {
             alpha,
             0., /* beta */
             bias,
+            activation,
         )
     }
 }
 
+impl GemmExecutor {
+    /// Perform a batched matrix multiplication.
+    ///
+    /// `a` and `b` must have at least 2 dimensions. The last two dimensions
+    /// are the matrix dimensions and any leading dimensions are batch
+    /// dimensions, which are broadcast against each other. `out_data` is a
+    /// contiguous buffer with shape `[batch..., a_rows, b_cols]`, where `batch`
+    /// is the broadcast batch shape.
+    ///
+    /// If one input is re-used across the batch due to broadcasting, it is
+    /// packed only once. The batch and the output tiles of each matrix in the
+    /// batch are processed in parallel together.
+    ///
+    /// `bias` and `activation` are applied to each matrix in the batch, as in
+    /// [`gemm_uninit_fused`](GemmExecutor::gemm_uninit_fused).
+    ///
+    /// After this function returns, `out_data` will be fully initialized.
+    pub fn batched_gemm_uninit(
+        &self,
+        pool: &TensorPool,
+        out_data: &mut [MaybeUninit<f32>],
+        a: TensorView,
+        b: TensorView,
+        alpha: f32,
+        bias: Option<BiasVector>,
+        activation: Option<Activation>,
+    ) {
+        assert!(
+            a.ndim() >= 2 && b.ndim() >= 2,
+            "Inputs must have at least 2 dimensions"
+        );
+
+        let (a_prefix, a_matrix_shape) = a.shape().split_at(a.ndim() - 2);
+        let (b_prefix, b_matrix_shape) = b.shape().split_at(b.ndim() - 2);
+        let [a_rows, a_cols] = [a_matrix_shape[0], a_matrix_shape[1]];
+        let [b_rows, b_cols] = [b_matrix_shape[0], b_matrix_shape[1]];
+        assert!(
+            a_cols == b_rows,
+            "Columns of matrix `a` must match rows of matrix `b`"
+        );
+
+        let out_prefix =
+            broadcast_shapes(a_prefix, b_prefix).expect("Batch dimensions should be broadcastable");
+        let out_matrix_len = a_rows * b_cols;
+        let n_batches: usize = out_prefix.iter().product();
+        assert!(
+            out_data.len() == n_batches * out_matrix_len,
+            "Output buffer length should match output shape"
+        );
+        if out_data.is_empty() {
+            return;
+        }
+
+        let num_a_matrices: usize = a_prefix.iter().product();
+        let num_b_matrices: usize = b_prefix.iter().product();
+
+        // Prepack re-used inputs to amortize packing cost.
+        //
+        // We don't prepack when the "A" matrix is a vector because that uses a
+        // special case vector-matrix algorithm that doesn't benefit from packing.
+        let prepacked_a = (num_a_matrices == 1 && n_batches > 1 && a_rows > 1).then(|| {
+            let a_matrix = a.inner_iter::<2>().next().unwrap();
+            self.prepack_a_in(pool, a_matrix).auto_return(pool)
+        });
+        let prepacked_a = prepacked_a.as_deref();
+
+        let prepacked_b = (num_b_matrices == 1 && n_batches > 1 && a_rows > 1).then(|| {
+            let b_matrix = b.inner_iter::<2>().next().unwrap();
+            self.prepack_b_in(pool, b_matrix).auto_return(pool)
+        });
+        let prepacked_b = prepacked_b.as_deref();
+
+        let a_broadcast_shape = [out_prefix.as_slice(), &[a_rows, a_cols]].concat();
+        let b_broadcast_shape = [out_prefix.as_slice(), &[b_rows, b_cols]].concat();
+        let a_broadcast = a.broadcast(a_broadcast_shape.as_slice());
+        let b_broadcast = b.broadcast(b_broadcast_shape.as_slice());
+        let a_matrices: Vec<Matrix> = a_broadcast.inner_iter::<2>().collect();
+        let b_matrices: Vec<Matrix> = b_broadcast.inner_iter::<2>().collect();
+
+        // Use indexed parallel iterators so that Rayon can split the batch
+        // evenly across threads. Each GEMM call further parallelizes over
+        // blocks of its output, so that idle threads can steal tiles from
+        // other matrices in the batch if the batch is small.
+        out_data
+            .par_chunks_mut(out_matrix_len)
+            .zip(a_matrices.par_iter().zip(b_matrices.par_iter()))
+            .for_each(|(out_mat, (a_mat, b_mat))| {
+                let a_input = if let Some(packed) = prepacked_a {
+                    GemmInputA::Packed(packed)
+                } else {
+                    GemmInputA::Unpacked(*a_mat)
+                };
+
+                let b_input = if let Some(packed) = prepacked_b {
+                    GemmInputB::Packed(packed)
+                } else {
+                    GemmInputB::Unpacked(*b_mat)
+                };
+
+                self.gemm_uninit_fused(out_mat, b_cols, a_input, b_input, alpha, bias, activation);
+            });
+    }
+}
+
 /// Return the block size for the K / depth dimension of a GEMM operation.
 fn depth_block_size(a_cols: usize) -> usize {
     256.min(a_cols)
@@ -645,6 +874,58 @@ impl OutputTiles {
     }
 }
 
+/// Minimum number of multiply-accumulate operations to assign to each thread
+/// in a vector-matrix product. Below this, the cost of dispatching work to
+/// another thread outweighs the benefit.
+const GEMV_MIN_WORK_PER_THREAD: usize = 16 * 1024;
+
+/// Minimum number of columns in each column block of a vector-matrix product.
+const GEMV_MIN_COL_BLOCK: usize = 64;
+
+/// Minimum number of rows of B in each partition when a vector-matrix
+/// product is split along the K dimension.
+const GEMV_MIN_DEPTH_BLOCK: usize = 256;
+
+/// Partitioning of a vector-matrix product into tasks that can be run in
+/// parallel.
+#[derive(Debug, PartialEq)]
+struct GemvPartition {
+    /// Number of columns in each block of the output.
+    col_block_size: usize,
+
+    /// Number of partitions that the K dimension is split into. If greater
+    /// than one, each partition computes a partial result for every column
+    /// block and the partial results are then summed.
+    depth_splits: usize,
+}
+
+impl GemvPartition {
+    /// Choose how to partition a `(1 x K) x (K x N)` product across
+    /// `n_threads` threads.
+    ///
+    /// The number of tasks is limited so that each does a minimum amount of
+    /// work, which makes small products run on a single thread. The N
+    /// dimension is split first, as that requires no extra work to combine
+    /// results. If the N dimension is too small to create a task for every
+    /// thread, as with skinny decoder projections, the K dimension is split
+    /// as well.
+    fn new(k: usize, n: usize, n_threads: usize) -> GemvPartition {
+        let max_tasks = (k * n / GEMV_MIN_WORK_PER_THREAD).clamp(1, n_threads.max(1));
+        let n_col_blocks = n.div_ceil(GEMV_MIN_COL_BLOCK).clamp(1, max_tasks);
+        let depth_splits = (max_tasks / n_col_blocks)
+            .min(k / GEMV_MIN_DEPTH_BLOCK)
+            .max(1);
+        GemvPartition {
+            col_block_size: n.div_ceil(n_col_blocks).max(1),
+            depth_splits,
+        }
+    }
+
+    fn n_tasks(&self, n: usize) -> usize {
+        n.div_ceil(self.col_block_size) * self.depth_splits
+    }
+}
+
 /// Compute a vector-matrix product.
 ///
 /// This operation is called "gemv" in BLAS APIs.
@@ -655,7 +936,8 @@ fn gemv(
     mut output_mat: MatrixMut,
     alpha: f32,
     beta: f32,
-    bias: Option<f32>,
+    bias: Option<BiasVector>,
+    activation: Option<Activation>,
 ) {
     assert!(output_mat.is_contiguous());
 
@@ -666,41 +948,117 @@ fn gemv(
     let a = a.to_contiguous();
     let a_data = a.data().unwrap();
 
-    // The matrix is partitioned into column blocks that are processed in
-    // parallel.
+    // The matrix is partitioned into column blocks, and possibly depth
+    // partitions, that are processed in parallel.
     //
-    // Each column block is partitioned into row blocks for calls to the kernel.
+    // Each task is partitioned into row blocks for calls to the kernel.
     // The kernel internally divides the row blocks into column tiles. The
     // kernel prefers tall row blocks if B has unit row stride, or short row
     // blocks if it has unit column stride.
-    let b_block_size = b_cols.div_ceil(rayon::current_num_threads()).max(128);
+    let partition = GemvPartition::new(a_cols, b_cols, rayon::current_num_threads());
+    let parallel = partition.n_tasks(b_cols) > 1;
+    let b_block_size = partition.col_block_size;
     let k_block_size = if b.row_stride() == 1 { 512 } else { 8 };
 
-    out_data
-        .par_chunks_mut(b_block_size)
-        .enumerate()
-        .for_each(|(col_block_idx, out_chunk)| {
-            let col_block =
-                (col_block_idx * b_block_size)..((col_block_idx + 1) * b_block_size).min(b_cols);
-            let mut effective_beta = beta;
+    // Compute `out_chunk = alpha * (a[k_range] @ b[k_range, col_block]) + beta * out_chunk`.
+    let gemv_block = |out_chunk: &mut [f32], col_block: Range<usize>, k_range: Range<usize>| {
+        let mut effective_beta = beta;
+        for k_block in range_chunks(k_range, k_block_size) {
+            let a_block = &a_data[k_block.clone()];
+            let b_block = b.slice::<2, _>((k_block, col_block.clone()));
+            kernel.gemv_kernel(out_chunk, a_block, b_block, alpha, effective_beta);
+
+            // Reset `beta` so that subsequent updates for each column
+            // accumulate into the first update.
+            effective_beta = 1.0;
+        }
+    };
 
-            for (k_block, a_block) in
-                range_chunks(0..a_cols, k_block_size).zip(a_data.chunks(k_block_size))
-            {
-                let b_block = b.slice::<2, _>((k_block, col_block.clone()));
-                kernel.gemv_kernel(out_chunk, a_block, b_block, alpha, effective_beta);
+    let col_block_range = |col_block_idx: usize| {
+        (col_block_idx * b_block_size)..((col_block_idx + 1) * b_block_size).min(b_cols)
+    };
 
-                // Reset `beta` so that subsequent updates for each column
-                // accumulate into the first update.
-                effective_beta = 1.0;
+    // Temporary buffer holding partial results for each depth partition.
+    //
+    // The buffer is borrowed from the current thread and returned once the
+    // output has been computed, to avoid an allocation on each call.
+    thread_local!(static GEMV_PARTIALS: RefCell<Vec<f32>> = const { RefCell::new(Vec::new()) });
+    let partials = if partition.depth_splits > 1 {
+        let depth_split_size = a_cols.div_ceil(partition.depth_splits);
+        let mut partials = GEMV_PARTIALS.with(|cell| cell.take());
+        partials.clear();
+        partials.resize(partition.depth_splits * b_cols, 0.);
+        partials
+            .par_chunks_mut(b_cols)
+            .zip(range_chunks(0..a_cols, depth_split_size).collect::<Vec<_>>())
+            .for_each(|(partial, k_range)| {
+                partial.par_chunks_mut(b_block_size).enumerate().for_each(
+                    |(col_block_idx, partial_chunk)| {
+                        gemv_block(
+                            partial_chunk,
+                            col_block_range(col_block_idx),
+                            k_range.clone(),
+                        );
+                    },
+                );
+            });
+        Some(partials)
+    } else {
+        None
+    };
+
+    let finish_block = |(col_block_idx, out_chunk): (usize, &mut [f32])| {
+        let col_block = col_block_range(col_block_idx);
+
+        if let Some(partials) = partials.as_ref() {
+            for (i, out) in out_chunk.iter_mut().enumerate() {
+                let col = col_block.start + i;
+                let sum: f32 = partials[col..].iter().step_by(b_cols).sum();
+                let prev = if beta == 0. { 0. } else { beta * *out };
+                *out = prev + sum;
             }
+        } else {
+            gemv_block(out_chunk, col_block.clone(), 0..a_cols);
+        }
 
-            if let Some(bias) = bias {
-                for x in out_chunk {
+        match bias {
+            // nb. The caller checked that the bias length matches the
+            // output shape.
+            Some(BiasVector::Column(bias)) => {
+                for x in out_chunk.iter_mut() {
+                    *x += bias[0];
+                }
+            }
+            Some(BiasVector::Row(bias)) => {
+                for (x, bias) in out_chunk.iter_mut().zip(&bias[col_block]) {
                     *x += bias;
                 }
             }
-        });
+            None => {}
+        }
+
+        if let Some(activation) = activation {
+            activation.apply(out_chunk);
+        }
+    };
+
+    // In a single-threaded context we get better performance by avoiding Rayon
+    // overhead altogether.
+    if parallel {
+        out_data
+            .par_chunks_mut(b_block_size)
+            .enumerate()
+            .for_each(finish_block);
+    } else {
+        out_data
+            .chunks_mut(b_block_size)
+            .enumerate()
+            .for_each(finish_block);
+    }
+
+    if let Some(partials) = partials {
+        GEMV_PARTIALS.with(|cell| cell.replace(partials));
+    }
 }
 
 /// Perform matrix multiplication with a given kernel.
@@ -738,16 +1096,37 @@ fn gemm_impl(
     b: GemmInputB,
     alpha: f32,
     beta: f32,
-    bias: Option<&[f32]>,
+    bias: Option<BiasVector>,
+    activation: Option<Activation>,
 ) {
     assert!(
         a.cols() == b.rows(),
         "Columns of matrix `a` must match rows of matrix `b`"
     );
-    assert!(
-        bias.map(|b| b.len()).unwrap_or(a.rows()) == a.rows(),
-        "Bias vector length must match rows of matrix `a`"
-    );
+    match bias {
+        Some(BiasVector::Column(bias)) => assert!(
+            bias.len() == a.rows(),
+            "Bias vector length must match rows of matrix `a`"
+        ),
+        Some(BiasVector::Row(bias)) => assert!(
+            bias.len() == b.cols(),
+            "Bias vector length must match columns of matrix `b`"
+        ),
+        None => {}
+    }
+
+    if let GemmInputA::Packed(pm) = a {
+        assert!(
+            pm.kernel_name == kernel.name(),
+            "Packed matrix `a` was packed by a different kernel"
+        );
+    }
+    if let GemmInputB::Packed(pm) = b {
+        assert!(
+            pm.kernel_name == kernel.name(),
+            "Packed matrix `b` was packed by a different kernel"
+        );
+    }
 
     // Handle case where output is empty.
     if a.rows() == 0 || b.cols() == 0 {
@@ -757,10 +1136,27 @@ fn gemm_impl(
     // Handle case where depth is zero. We still need to initialize the output
     // in this case.
     if a.cols() == 0 {
-        for x in out_data {
+        for x in out_data.iter_mut() {
             let tmp = if beta == 0. { 0. } else { *x };
             *x = beta * tmp;
         }
+        for (row, out_row) in out_data
+            .chunks_mut(out_row_stride)
+            .take(a.rows())
+            .enumerate()
+        {
+            let out_row = &mut out_row[..b.cols()];
+            match bias {
+                Some(BiasVector::Column(bias)) => out_row.iter_mut().for_each(|x| *x += bias[row]),
+                Some(BiasVector::Row(bias)) => {
+                    out_row.iter_mut().zip(bias).for_each(|(x, b)| *x += b)
+                }
+                None => {}
+            }
+            if let Some(activation) = activation {
+                activation.apply(out_row);
+            }
+        }
         return;
     }
 
@@ -781,8 +1177,8 @@ fn gemm_impl(
             output_mat.view_mut(),
             alpha,
             beta,
-            // nb. We checked above that, if present, the bias length matches `a.rows()`.
-            bias.map(|b| b[0]),
+            bias,
+            activation,
         );
         return;
     }
@@ -794,7 +1190,10 @@ fn gemm_impl(
     // that blocks can fit in specific cache levels. See
     // https://dl.acm.org/doi/pdf/10.1145/2925987 for notes on choosing the
     // values.
-    let nc = col_block_size(b.cols(), kernel.nr());
+    let nc = match b {
+        GemmInputB::Packed(pm) => pm.col_block_size,
+        GemmInputB::Unpacked(_) | GemmInputB::Virtual(_) => col_block_size(b.cols(), kernel.nr()),
+    };
     let mc = row_block_size(a.rows(), kernel.mr());
     let kc = depth_block_size(a.cols());
 
@@ -909,12 +1308,14 @@ fn gemm_impl(
                             col_start / nr..col_end.div_ceil(nr),
                             row_start / mr..row_end.div_ceil(mr),
                             depth_range.start == 0,
+                            depth_range.end == a.cols(),
                             packed_a,
                             packed_b,
                             panel_length,
                             alpha,
                             effective_beta,
                             bias,
+                            activation,
                         );
 
                         if let Some(packed_a) = thread_local_packed_a {
@@ -936,20 +1337,24 @@ fn gemm_impl(
 /// `packed_a` and `packed_b` are the corresponding packed inputs. `panel_length`
 /// is the size of panels along the depth/K dimension.
 ///
-/// `is_first` indicates whether this is the first write to the output tiles
-/// in this block during the current GEMM operation.
+/// `first_update` indicates whether this is the first write to the output
+/// tiles in this block during the current GEMM operation, and `last_update`
+/// whether it is the final write. The bias is added on the first update and
+/// the activation is applied on the last update.
 fn gemm_block(
     kernel: &dyn Kernel,
     output: &OutputTiles,
     col_tiles: Range<usize>,
     row_tiles: Range<usize>,
     first_update: bool,
+    last_update: bool,
     packed_a: &[f32],
     packed_b: &[f32],
     panel_length: usize,
     alpha: f32,
     beta: f32,
-    bias: Option<&[f32]>,
+    bias: Option<BiasVector>,
+    activation: Option<Activation>,
 ) {
     // Maximum tile size of all supported kernels.
     const MAX_MR: usize = 8;
@@ -1039,12 +1444,36 @@ fn gemm_block(
                             //  - Row and column indices are valid for current tile
                             //  - Bias length was checked at start of `gemm_impl`
                             unsafe {
-                                *out_tile.ptr.add(row * out_tile.row_stride + col) +=
-                                    *bias.get_unchecked(row_tile * mr + row);
+                                let bias = match bias {
+                                    BiasVector::Column(bias) => {
+                                        *bias.get_unchecked(row_tile * mr + row)
+                                    }
+                                    BiasVector::Row(bias) => {
+                                        *bias.get_unchecked(col_tile * nr + col)
+                                    }
+                                };
+                                *out_tile.ptr.add(row * out_tile.row_stride + col) += bias;
                             }
                         }
                     }
                 }
+
+                // Apply activation once the tile has been fully computed,
+                // while it is still in cache.
+                if let (Some(activation), true) = (activation, last_update) {
+                    for row in 0..out_tile.used_rows {
+                        // Safety:
+                        //  - Row and column indices are valid for current tile
+                        //  - Tiles are only operated on by one thread at a time
+                        let out_row = unsafe {
+                            std::slice::from_raw_parts_mut(
+                                out_tile.ptr.add(row * out_tile.row_stride),
+                                out_tile.used_cols,
+                            )
+                        };
+                        activation.apply(out_row);
+                    }
+                }
             }
         });
 }
@@ -1059,10 +1488,14 @@ mod tests {
     use rten_bench::run_bench;
     use rten_tensor::prelude::*;
     use rten_tensor::rng::XorShiftRng;
-    use rten_tensor::test_util::expect_equal;
+    use rten_tensor::test_util::{expect_equal, expect_equal_with_tolerance};
     use rten_tensor::{Matrix, MatrixLayout, NdTensor, Tensor};
 
-    use super::{gemm, GemmExecutor, GemmInputA, GemmInputB, KernelType, VirtualMatrix};
+    use super::{
+        gemm, Activation, BiasVector, GemmExecutor, GemmInputA, GemmInputB, GemvPartition,
+        KernelType, VirtualMatrix,
+    };
+    use crate::tensor_pool::TensorPool;
 
     fn reference_matmul_alpha_beta(a: &Tensor, b: &Tensor, alpha: f32, beta: f32) -> Tensor {
         let [a_rows, _a_cols]: [usize; 2] = a.shape().try_into().expect("input should be a matrix");
@@ -1407,6 +1840,77 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_gemm_fused() -> Result<(), Box<dyn Error>> {
+        let mut rng = XorShiftRng::new(1234);
+
+        // Include a vector-matrix product, a zero depth and a depth which
+        // requires multiple blocks, so the activation must wait until the
+        // final block.
+        for (m, n, k) in [
+            (10, 15, 5),
+            (1, 20, 7),
+            (4, 6, 0),
+            (7, 9, DEPTH_BLOCK_SIZE + 10),
+        ] {
+            let a = Tensor::rand(&[m, k], &mut rng).map(|x| x - 0.5);
+            let b = Tensor::rand(&[k, n], &mut rng).map(|x| x - 0.5);
+            let row_bias: Vec<f32> = (0..n).map(|i| i as f32 * 0.1 - 0.5).collect();
+            let col_bias: Vec<f32> = (0..m).map(|i| i as f32 * -0.1 + 0.2).collect();
+
+            for kernel in [None, Some(KernelType::Base)] {
+                let gemm = if let Some(kernel) = kernel {
+                    GemmExecutor::with_kernel(kernel).unwrap()
+                } else {
+                    GemmExecutor::new()
+                };
+
+                for bias in [
+                    None,
+                    Some(BiasVector::Row(&row_bias)),
+                    Some(BiasVector::Column(&col_bias)),
+                ] {
+                    for (activation, act_fn) in [
+                        (None, (|x| x) as fn(f32) -> f32),
+                        (Some(Activation::Relu), |x: f32| x.max(0.)),
+                        (Some(Activation::Gelu), rten_vecmath::gelu),
+                        (Some(Activation::Silu), rten_vecmath::silu),
+                    ] {
+                        let mut expected = reference_matmul(&a, &b);
+                        for r in 0..m {
+                            for c in 0..n {
+                                let bias = match bias {
+                                    Some(BiasVector::Row(bias)) => bias[c],
+                                    Some(BiasVector::Column(bias)) => bias[r],
+                                    None => 0.,
+                                };
+                                expected[[r, c]] = act_fn(expected[[r, c]] + bias);
+                            }
+                        }
+
+                        let mut result = Tensor::uninit(&[m, n]);
+                        gemm.gemm_uninit_fused(
+                            result.data_mut().unwrap(),
+                            n,
+                            GemmInputA::Unpacked(a.nd_view()),
+                            GemmInputB::Unpacked(b.nd_view()),
+                            1.,
+                            bias,
+                            activation,
+                        );
+                        let result = unsafe { result.assume_init() };
+
+                        // The bias is added before accumulating over the K
+                        // dimension, so rounding differs from the reference.
+                        expect_equal_with_tolerance(&result, &expected, 1e-5, 1e-5)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn test_gemm_prepack() -> Result<(), Box<dyn Error>> {
         let mut rng = XorShiftRng::new(1234);
@@ -1482,6 +1986,178 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_gemm_prepack_with_different_thread_count() -> Result<(), Box<dyn Error>> {
+        let mut rng = XorShiftRng::new(1234);
+        let (m, n, k) = (10, COL_BLOCK_SIZE * 2 + COL_BLOCK_SIZE / 2, 5);
+        let a = Tensor::rand(&[m, k], &mut rng);
+        let b = Tensor::rand(&[k, n], &mut rng);
+        let gemm = GemmExecutor::new();
+
+        // The column block size depends on the number of threads, so pack
+        // using a different thread pool than the one the GEMM runs in.
+        let single_thread_pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap();
+        let packed_b = single_thread_pool.install(|| gemm.prepack_b(b.nd_view()));
+        assert_eq!(packed_b.rows(), k);
+        assert_eq!(packed_b.cols(), n);
+
+        let mut result = Tensor::zeros(&[m, n]);
+        let result_row_stride = result.stride(0);
+        gemm.gemm(
+            result.data_mut().unwrap(),
+            result_row_stride,
+            GemmInputA::Unpacked(a.nd_view()),
+            GemmInputB::Packed(&packed_b),
+            1.,
+            0.,
+        );
+
+        let expected = reference_matmul(&a, &b);
+        expect_equal(&result, &expected)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_gemm_prepack_panics_if_kernel_differs() {
+        let gemm = GemmExecutor::new();
+        let base_gemm = GemmExecutor::with_kernel(KernelType::Base).unwrap();
+        if gemm.kernel_name() == base_gemm.kernel_name() {
+            // Only the base kernel is available on this system.
+            return;
+        }
+
+        let a = Tensor::<f32>::zeros(&[4, 5]);
+        let b = Tensor::<f32>::zeros(&[5, 6]);
+        let packed_b = base_gemm.prepack_b(b.nd_view());
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let mut result = Tensor::zeros(&[4, 6]);
+            gemm.gemm(
+                result.data_mut().unwrap(),
+                6,
+                GemmInputA::Unpacked(a.nd_view()),
+                GemmInputB::Packed(&packed_b),
+                1.,
+                0.,
+            );
+        }));
+        let err = result.expect_err("GEMM should panic");
+        assert_eq!(
+            err.downcast_ref::<&str>(),
+            Some(&"Packed matrix `b` was packed by a different kernel")
+        );
+    }
+
+    #[test]
+    fn test_batched_gemm() -> Result<(), Box<dyn Error>> {
+        let mut rng = XorShiftRng::new(1234);
+
+        struct Case<'a> {
+            a_shape: &'a [usize],
+            b_shape: &'a [usize],
+            out_shape: &'a [usize],
+        }
+
+        let cases = [
+            // Same batch size for both inputs.
+            Case {
+                a_shape: &[3, 4, 5],
+                b_shape: &[3, 5, 6],
+                out_shape: &[3, 4, 6],
+            },
+            // "B" broadcast along batch dimension. This uses a pre-packed "B".
+            Case {
+                a_shape: &[3, 4, 5],
+                b_shape: &[5, 6],
+                out_shape: &[3, 4, 6],
+            },
+            // "A" broadcast along batch dimension. This uses a pre-packed "A".
+            Case {
+                a_shape: &[4, 5],
+                b_shape: &[2, 3, 5, 6],
+                out_shape: &[2, 3, 4, 6],
+            },
+            // Both inputs broadcast along different dimensions.
+            Case {
+                a_shape: &[2, 1, 4, 5],
+                b_shape: &[1, 3, 5, 6],
+                out_shape: &[2, 3, 4, 6],
+            },
+            // Vector-matrix products.
+            Case {
+                a_shape: &[3, 1, 5],
+                b_shape: &[5, 6],
+                out_shape: &[3, 1, 6],
+            },
+        ];
+
+        let pool = TensorPool::new();
+        let gemm = GemmExecutor::new();
+
+        for Case {
+            a_shape,
+            b_shape,
+            out_shape,
+        } in cases
+        {
+            let a = Tensor::rand(a_shape, &mut rng);
+            let b = Tensor::rand(b_shape, &mut rng);
+
+            let mut result = Tensor::uninit(out_shape);
+            gemm.batched_gemm_uninit(
+                &pool,
+                result.data_mut().unwrap(),
+                a.view(),
+                b.view(),
+                1.,   /* alpha */
+                None, /* bias */
+                None, /* activation */
+            );
+            let result = unsafe { result.assume_init() };
+
+            let a_broadcast = a.broadcast(
+                [
+                    &out_shape[..out_shape.len() - 2],
+                    &a_shape[a_shape.len() - 2..],
+                ]
+                .concat()
+                .as_slice(),
+            );
+            let b_broadcast = b.broadcast(
+                [
+                    &out_shape[..out_shape.len() - 2],
+                    &b_shape[b_shape.len() - 2..],
+                ]
+                .concat()
+                .as_slice(),
+            );
+            let mut expected = Tensor::zeros(out_shape);
+            for ((mut out_mat, a_mat), b_mat) in expected
+                .inner_iter_mut::<2>()
+                .zip(a_broadcast.inner_iter::<2>())
+                .zip(b_broadcast.inner_iter::<2>())
+            {
+                let out_row_stride = out_mat.stride(0);
+                gemm.gemm(
+                    out_mat.data_mut().unwrap(),
+                    out_row_stride,
+                    GemmInputA::Unpacked(a_mat),
+                    GemmInputB::Unpacked(b_mat),
+                    1., /* alpha */
+                    0., /* beta */
+                );
+            }
+
+            expect_equal(&result, &expected)?;
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn test_gemm_virtual() -> Result<(), Box<dyn Error>> {
         let mut rng = XorShiftRng::new(1234);
@@ -1776,6 +2452,127 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_gemv_partition() {
+        struct Case {
+            k: usize,
+            n: usize,
+            n_threads: usize,
+            expected: GemvPartition,
+        }
+
+        let cases = [
+            // Small problem which runs on a single thread.
+            Case {
+                k: 64,
+                n: 64,
+                n_threads: 8,
+                expected: GemvPartition {
+                    col_block_size: 64,
+                    depth_splits: 1,
+                },
+            },
+            // Wide problem which is split along N only.
+            Case {
+                k: 1024,
+                n: 4096,
+                n_threads: 8,
+                expected: GemvPartition {
+                    col_block_size: 512,
+                    depth_splits: 1,
+                },
+            },
+            // Skinny problem which is split along N and K.
+            Case {
+                k: 4096,
+                n: 128,
+                n_threads: 8,
+                expected: GemvPartition {
+                    col_block_size: 64,
+                    depth_splits: 4,
+                },
+            },
+            // Single thread.
+            Case {
+                k: 4096,
+                n: 4096,
+                n_threads: 1,
+                expected: GemvPartition {
+                    col_block_size: 4096,
+                    depth_splits: 1,
+                },
+            },
+        ];
+
+        for Case {
+            k,
+            n,
+            n_threads,
+            expected,
+        } in cases
+        {
+            assert_eq!(GemvPartition::new(k, n, n_threads), expected);
+        }
+    }
+
+    #[test]
+    fn test_gemv_split_depth() -> Result<(), Box<dyn Error>> {
+        let mut rng = XorShiftRng::new(1234);
+        let thread_pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(8)
+            .build()
+            .unwrap();
+
+        for (k, n, beta) in [(4096, 20, 0.), (4096, 130, 0.5), (1000, 20, 1.)] {
+            let a = Tensor::rand(&[1, k], &mut rng);
+            let b = Tensor::rand(&[k, n], &mut rng);
+            let bias: Vec<f32> = (0..n).map(|i| i as f32).collect();
+            let mut result = Tensor::rand(&[1, n], &mut rng);
+            let mut expected = result.clone();
+
+            thread_pool.install(|| {
+                GemmExecutor::new().gemm_bias(
+                    result.data_mut().unwrap(),
+                    n,
+                    GemmInputA::Unpacked(a.nd_view()),
+                    GemmInputB::Unpacked(b.nd_view()),
+                    1.,
+                    beta,
+                    None,
+                );
+            });
+            reference_gemm(&mut expected, &a, &b, 1., beta, None);
+            expect_equal_with_tolerance(&result, &expected, 1e-3, 1e-5)?;
+
+            let mut result = Tensor::uninit(&[1, n]);
+            thread_pool.install(|| {
+                GemmExecutor::new().gemm_uninit_fused(
+                    result.data_mut().unwrap(),
+                    n,
+                    GemmInputA::Unpacked(a.nd_view()),
+                    GemmInputB::Unpacked(b.nd_view()),
+                    1.,
+                    Some(BiasVector::Row(&bias)),
+                    Some(Activation::Relu),
+                );
+            });
+            let result = unsafe { result.assume_init() };
+            let expected = reference_matmul(&a, &b)
+                .iter()
+                .zip(&bias)
+                .map(|(x, b)| (x + b).max(0.))
+                .collect::<Vec<_>>();
+            expect_equal_with_tolerance(
+                &result,
+                &Tensor::from_data(&[1, n], expected),
+                1e-3,
+                1e-5,
+            )?;
+        }
+
+        Ok(())
+    }
+
     // Run with `cargo test --release bench_gemm -- --nocapture --ignored`
     #[test]
     #[ignore]
@@ -0,0 +1,302 @@
+//! 4-bit group-quantized matrices for use as the "B" input to GEMM.
+
+use std::mem::MaybeUninit;
+use std::ops::Range;
+
+use rten_tensor::{Matrix, MatrixLayout, NdTensor};
+
+use super::VirtualMatrix;
+
+/// A `[K, N]` matrix whose elements are quantized to 4 bits, in groups of
+/// `group_size` consecutive elements along the K dimension.
+///
+/// Each group has its own scale and zero point, and element `(k, n)` is
+/// dequantized as `(q[k, n] - zero_point[g, n]) * scale[g, n]` where
+/// `g = k / group_size`. This is the layout used by GPTQ and AWQ-style
+/// quantized weights for language models.
+///
+/// Quantized values are stored column by column, with two values per byte.
+/// The value for the even row of each pair is stored in the low nibble.
+///
+/// This implements [VirtualMatrix], so it can be passed to the GEMM
+/// implementation as [`GemmInputB::Virtual`](super::GemmInputB::Virtual).
+/// Blocks are dequantized as they are packed, so the full-precision matrix is
+/// never materialized.
+///
+/// Dequantization happens when packing, not in registers in the kernel's
+/// inner loop. Each GEMM call converts the blocks of this matrix that it uses
+/// into the f32 packing buffer, and the multiplication then uses the regular
+/// f32 kernels. The benefit is the reduced memory needed to store the
+/// weights. The arithmetic is the same as for an f32 matrix, plus the cost of
+/// dequantization.
+pub struct Int4Matrix {
+    /// Packed 4-bit values with shape `[cols, rows.div_ceil(2)]`.
+    data: Vec<u8>,
+
+    /// Per-group scales with shape `[cols, n_groups]`.
+    scales: Vec<f32>,
+
+    /// Per-group zero points with shape `[cols, n_groups]`. Each value is in
+    /// the range `[0, 15]`.
+    zero_points: Vec<u8>,
+
+    rows: usize,
+    cols: usize,
+    group_size: usize,
+}
+
+impl Int4Matrix {
+    /// Create a quantized matrix from its packed components.
+    ///
+    /// `data` contains the packed values with shape `[cols, rows.div_ceil(2)]`.
+    /// `scales` and `zero_points` contain the per-group parameters with shape
+    /// `[cols, rows.div_ceil(group_size)]`.
+    ///
+    /// Panics if the lengths of the buffers do not match the shape, or if a
+    /// zero point is outside the range of a 4-bit value.
+    pub fn from_parts(
+        rows: usize,
+        cols: usize,
+        group_size: usize,
+        data: Vec<u8>,
+        scales: Vec<f32>,
+        zero_points: Vec<u8>,
+    ) -> Int4Matrix {
+        assert!(group_size > 0, "Group size must be positive");
+        let n_groups = rows.div_ceil(group_size);
+        assert_eq!(
+            data.len(),
+            cols * rows.div_ceil(2),
+            "Data length does not match shape"
+        );
+        assert_eq!(
+            scales.len(),
+            cols * n_groups,
+            "Scales length does not match shape"
+        );
+        assert_eq!(
+            zero_points.len(),
+            cols * n_groups,
+            "Zero points length does not match shape"
+        );
+        assert!(
+            zero_points.iter().all(|&zp| zp <= 15),
+            "Zero points must be 4-bit values"
+        );
+
+        Int4Matrix {
+            data,
+            scales,
+            zero_points,
+            rows,
+            cols,
+            group_size,
+        }
+    }
+
+    /// Quantize a `[K, N]` matrix using asymmetric min-max quantization of
+    /// each group.
+    pub fn quantize(b: Matrix, group_size: usize) -> Int4Matrix {
+        assert!(group_size > 0, "Group size must be positive");
+        let (rows, cols) = (b.rows(), b.cols());
+        let n_groups = rows.div_ceil(group_size);
+        let col_bytes = rows.div_ceil(2);
+
+        let mut data = vec![0u8; cols * col_bytes];
+        let mut scales = Vec::with_capacity(cols * n_groups);
+        let mut zero_points = Vec::with_capacity(cols * n_groups);
+
+        for col in 0..cols {
+            let col_data = &mut data[col * col_bytes..][..col_bytes];
+            for group_start in (0..rows).step_by(group_size) {
+                let group = group_start..(group_start + group_size).min(rows);
+
+                // Extend the range to include zero, so that zero is exactly
+                // representable.
+                let (min, max) = group
+                    .clone()
+                    .map(|row| b[[row, col]])
+                    .fold((0f32, 0f32), |(min, max), x| (min.min(x), max.max(x)));
+                let scale = if max > min { (max - min) / 15. } else { 1. };
+                let zero_point = (-min / scale).round().clamp(0., 15.) as u8;
+                scales.push(scale);
+                zero_points.push(zero_point);
+
+                for row in group {
+                    let q = (b[[row, col]] / scale + zero_point as f32)
+                        .round()
+                        .clamp(0., 15.) as u8;
+                    col_data[row / 2] |= q << ((row % 2) * 4);
+                }
+            }
+        }
+
+        Int4Matrix {
+            data,
+            scales,
+            zero_points,
+            rows,
+            cols,
+            group_size,
+        }
+    }
+
+    /// Return the number of consecutive elements along the K dimension which
+    /// share a scale and zero point.
+    pub fn group_size(&self) -> usize {
+        self.group_size
+    }
+
+    /// Dequantize the matrix into a full-precision tensor.
+    pub fn dequantize(&self) -> NdTensor<f32, 2> {
+        NdTensor::from_fn([self.rows, self.cols], |[row, col]| self.get(row, col))
+    }
+
+    fn n_groups(&self) -> usize {
+        self.rows.div_ceil(self.group_size)
+    }
+
+    /// Return the dequantized value at a given position.
+    fn get(&self, row: usize, col: usize) -> f32 {
+        let byte = self.data[col * self.rows.div_ceil(2) + row / 2];
+        let q = (byte >> ((row % 2) * 4)) & 0xf;
+        let group_idx = col * self.n_groups() + row / self.group_size;
+        (q as f32 - self.zero_points[group_idx] as f32) * self.scales[group_idx]
+    }
+}
+
+// Safety: `pack_b` initializes the entire buffer passed to it.
+unsafe impl VirtualMatrix for Int4Matrix {
+    fn rows(&self) -> usize {
+        self.rows
+    }
+
+    fn cols(&self) -> usize {
+        self.cols
+    }
+
+    fn pack_b(
+        &self,
+        out: &mut [MaybeUninit<f32>],
+        panel_width: usize,
+        rows: Range<usize>,
+        cols: Range<usize>,
+    ) {
+        let n_panels = cols.len().div_ceil(panel_width);
+        let panel_len = rows.len() * panel_width;
+        assert_eq!(out.len(), n_panels * panel_len);
+
+        let col_bytes = self.rows.div_ceil(2);
+        let n_groups = self.n_groups();
+
+        for (panel_idx, out_panel) in out.chunks_exact_mut(panel_len).enumerate() {
+            let panel_start_col = cols.start + panel_idx * panel_width;
+
+            for panel_col in 0..panel_width {
+                let col = panel_start_col + panel_col;
+                if col >= cols.end {
+                    for row in 0..rows.len() {
+                        out_panel[row * panel_width + panel_col].write(0.);
+                    }
+                    continue;
+                }
+
+                // Dequantize the column into the packing buffer one group at
+                // a time, so that the scale and zero point are loaded once
+                // per group.
+                let col_data = &self.data[col * col_bytes..][..col_bytes];
+                let col_scales = &self.scales[col * n_groups..][..n_groups];
+                let col_zero_points = &self.zero_points[col * n_groups..][..n_groups];
+
+                let mut group_start = rows.start;
+                while group_start < rows.end {
+                    let group_idx = group_start / self.group_size;
+                    let group_end = ((group_idx + 1) * self.group_size).min(rows.end);
+                    let scale = col_scales[group_idx];
+                    let zero_point = col_zero_points[group_idx] as f32;
+
+                    for row in group_start..group_end {
+                        let q = (col_data[row / 2] >> ((row % 2) * 4)) & 0xf;
+                        out_panel[(row - rows.start) * panel_width + panel_col]
+                            .write((q as f32 - zero_point) * scale);
+                    }
+                    group_start = group_end;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use rten_tensor::prelude::*;
+    use rten_tensor::rng::XorShiftRng;
+    use rten_tensor::test_util::expect_equal;
+    use rten_tensor::{NdTensor, Tensor};
+
+    use super::Int4Matrix;
+    use crate::gemm::{GemmExecutor, GemmInputA, GemmInputB};
+
+    #[test]
+    fn test_int4_quantize() {
+        let mut rng = XorShiftRng::new(1234);
+        let b = NdTensor::<f32, 2>::rand([37, 5], &mut rng).map(|x| x * 2. - 1.);
+        let quantized = Int4Matrix::quantize(b.view(), 8);
+        assert_eq!(quantized.group_size(), 8);
+
+        let dequantized = quantized.dequantize();
+        assert_eq!(dequantized.shape(), b.shape());
+
+        // Values in each group span at most 2, divided into 15 steps.
+        let max_error = 2. / 15. / 2.;
+        for (x, y) in b.iter().zip(dequantized.iter()) {
+            assert!((x - y).abs() <= max_error + 1e-6, "{} != {}", x, y);
+        }
+
+        // Zero is exactly representable.
+        let zeros = NdTensor::<f32, 2>::zeros([4, 3]);
+        let dequantized = Int4Matrix::quantize(zeros.view(), 4).dequantize();
+        assert!(dequantized.iter().all(|&x| x == 0.));
+    }
+
+    #[test]
+    fn test_gemm_int4() -> Result<(), Box<dyn Error>> {
+        let mut rng = XorShiftRng::new(1234);
+        let gemm = GemmExecutor::new();
+
+        // Include sizes which are not multiples of the group size, panel
+        // width and depth block size.
+        for (m, n, k, group_size) in [(5, 17, 33, 8), (1, 20, 300, 32), (10, 40, 64, 64)] {
+            let a = NdTensor::<f32, 2>::rand([m, k], &mut rng);
+            let b = NdTensor::<f32, 2>::rand([k, n], &mut rng);
+            let quantized = Int4Matrix::quantize(b.view(), group_size);
+            let dequantized = quantized.dequantize();
+
+            let mut result = Tensor::zeros(&[m, n]);
+            gemm.gemm(
+                result.data_mut().unwrap(),
+                n,
+                GemmInputA::Unpacked(a.view()),
+                GemmInputB::Virtual(&quantized),
+                1.,
+                0.,
+            );
+
+            let mut expected = Tensor::zeros(&[m, n]);
+            gemm.gemm(
+                expected.data_mut().unwrap(),
+                n,
+                GemmInputA::Unpacked(a.view()),
+                GemmInputB::Unpacked(dequantized.view()),
+                1.,
+                0.,
+            );
+
+            expect_equal(&result, &expected)?;
+        }
+
+        Ok(())
+    }
+}
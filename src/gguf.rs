@@ -0,0 +1,577 @@
+//! Support for loading models from GGUF files.
+//!
+//! [GGUF](https://github.com/ggerganov/ggml/blob/master/docs/gguf.md) is the
+//! file format used by llama.cpp and related projects. A GGUF file contains
+//! metadata key-value pairs, which describe the model architecture and
+//! hyperparameters, plus a set of (usually quantized) weight tensors. Unlike
+//! `.rten` files, it does not contain a computation graph.
+//!
+//! [`GgufFile`] parses a GGUF file and provides access to its metadata and
+//! tensors. [`load_llama`] uses these to construct an RTen [`Model`](crate::Model) for
+//! Llama-family models. The resulting model follows the input and output
+//! naming conventions of models exported by Hugging Face's Optimum tool, so
+//! it can be used with the `rten-generate` crate.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+
+use rten_tensor::Tensor;
+
+use crate::model::ModelLoadError;
+
+mod llama;
+mod quant;
+
+pub use llama::{load_llama, LlamaConfig};
+use quant::TensorType;
+
+/// Errors that occur when reading a GGUF file or constructing a model from
+/// it.
+#[derive(Debug)]
+pub enum GgufError {
+    /// An error occurred reading the file from disk.
+    ReadFailed(std::io::Error),
+
+    /// The file is not a valid GGUF file.
+    InvalidFile(String),
+
+    /// The file uses a GGUF version that is not supported.
+    UnsupportedVersion(u32),
+
+    /// A tensor uses a data type that is not supported.
+    UnsupportedType(String),
+
+    /// The model architecture is not supported.
+    UnsupportedArchitecture(String),
+
+    /// A required metadata key is missing or has the wrong type.
+    MissingMetadata(String),
+
+    /// A metadata value is present but invalid, such as head counts which
+    /// are inconsistent with the embedding size.
+    InvalidMetadata(String),
+
+    /// A required tensor is missing.
+    MissingTensor(String),
+
+    /// A tensor has a shape which is inconsistent with the model
+    /// configuration.
+    ShapeMismatch(String, Vec<usize>),
+
+    /// An error occurred loading the generated model.
+    ModelLoadFailed(ModelLoadError),
+}
+
+impl fmt::Display for GgufError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GgufError::ReadFailed(err) => write!(f, "read error: {}", err),
+            GgufError::InvalidFile(err) => write!(f, "invalid GGUF file: {}", err),
+            GgufError::UnsupportedVersion(version) => {
+                write!(f, "unsupported GGUF version {}", version)
+            }
+            GgufError::UnsupportedType(dtype) => write!(f, "unsupported tensor type {}", dtype),
+            GgufError::UnsupportedArchitecture(arch) => {
+                write!(f, "unsupported model architecture \"{}\"", arch)
+            }
+            GgufError::MissingMetadata(key) => write!(f, "missing or invalid metadata \"{}\"", key),
+            GgufError::InvalidMetadata(err) => write!(f, "invalid metadata: {}", err),
+            GgufError::MissingTensor(name) => write!(f, "missing tensor \"{}\"", name),
+            GgufError::ShapeMismatch(name, shape) => {
+                write!(f, "tensor \"{}\" has unexpected shape {:?}", name, shape)
+            }
+            GgufError::ModelLoadFailed(err) => write!(f, "failed to load model: {}", err),
+        }
+    }
+}
+
+impl Error for GgufError {}
+
+/// Value of a metadata entry in a GGUF file.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MetadataValue {
+    U8(u8),
+    I8(i8),
+    U16(u16),
+    I16(i16),
+    U32(u32),
+    I32(i32),
+    U64(u64),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    Bool(bool),
+    String(String),
+    Array(Vec<MetadataValue>),
+}
+
+impl MetadataValue {
+    /// Return the value as an unsigned integer, if it is a non-negative
+    /// integer.
+    pub fn as_usize(&self) -> Option<usize> {
+        let val = match *self {
+            MetadataValue::U8(x) => x as u64,
+            MetadataValue::U16(x) => x as u64,
+            MetadataValue::U32(x) => x as u64,
+            MetadataValue::U64(x) => x,
+            MetadataValue::I8(x) => u64::try_from(x).ok()?,
+            MetadataValue::I16(x) => u64::try_from(x).ok()?,
+            MetadataValue::I32(x) => u64::try_from(x).ok()?,
+            MetadataValue::I64(x) => u64::try_from(x).ok()?,
+            _ => return None,
+        };
+        usize::try_from(val).ok()
+    }
+
+    /// Return the value as a float, if it is a number.
+    pub fn as_f32(&self) -> Option<f32> {
+        match *self {
+            MetadataValue::F32(x) => Some(x),
+            MetadataValue::F64(x) => Some(x as f32),
+            _ => self.as_usize().map(|x| x as f32),
+        }
+    }
+
+    /// Return the value as a string slice, if it is a string.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            MetadataValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Return the value as a slice of values, if it is an array.
+    pub fn as_array(&self) -> Option<&[MetadataValue]> {
+        match self {
+            MetadataValue::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+/// Description of a tensor in a GGUF file.
+#[derive(Clone, Debug)]
+struct TensorInfo {
+    name: String,
+
+    /// Shape of the tensor in row-major order (ie. the reverse of the order
+    /// stored in the file).
+    shape: Vec<usize>,
+
+    dtype: TensorType,
+
+    /// Offset of the tensor data relative to the start of the data section.
+    offset: usize,
+}
+
+/// Sequential reader for the little-endian values in a GGUF file.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], GgufError> {
+        let bytes = self
+            .pos
+            .checked_add(len)
+            .and_then(|end| self.data.get(self.pos..end))
+            .ok_or_else(|| GgufError::InvalidFile("unexpected end of file".into()))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], GgufError> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+
+    fn u32(&mut self) -> Result<u32, GgufError> {
+        self.array().map(u32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> Result<u64, GgufError> {
+        self.array().map(u64::from_le_bytes)
+    }
+
+    fn len(&mut self) -> Result<usize, GgufError> {
+        usize::try_from(self.u64()?).map_err(|_| GgufError::InvalidFile("length too large".into()))
+    }
+
+    fn string(&mut self) -> Result<String, GgufError> {
+        let len = self.len()?;
+        let bytes = self.bytes(len)?;
+        String::from_utf8(bytes.to_vec())
+            .map_err(|_| GgufError::InvalidFile("string is not valid UTF-8".into()))
+    }
+
+    fn metadata_value(&mut self, value_type: u32) -> Result<MetadataValue, GgufError> {
+        let value = match value_type {
+            0 => MetadataValue::U8(self.array::<1>()?[0]),
+            1 => MetadataValue::I8(self.array::<1>()?[0] as i8),
+            2 => MetadataValue::U16(self.array().map(u16::from_le_bytes)?),
+            3 => MetadataValue::I16(self.array().map(i16::from_le_bytes)?),
+            4 => MetadataValue::U32(self.u32()?),
+            5 => MetadataValue::I32(self.array().map(i32::from_le_bytes)?),
+            6 => MetadataValue::F32(self.array().map(f32::from_le_bytes)?),
+            7 => MetadataValue::Bool(self.array::<1>()?[0] != 0),
+            8 => MetadataValue::String(self.string()?),
+            9 => {
+                let item_type = self.u32()?;
+                let len = self.len()?;
+                let mut items = Vec::new();
+                for _ in 0..len {
+                    items.push(self.metadata_value(item_type)?);
+                }
+                MetadataValue::Array(items)
+            }
+            10 => MetadataValue::U64(self.u64()?),
+            11 => MetadataValue::I64(self.array().map(i64::from_le_bytes)?),
+            12 => MetadataValue::F64(self.array().map(f64::from_le_bytes)?),
+            _ => {
+                return Err(GgufError::InvalidFile(format!(
+                    "unknown metadata type {}",
+                    value_type
+                )))
+            }
+        };
+        Ok(value)
+    }
+}
+
+/// A parsed GGUF file.
+///
+/// Tensor data is kept in its original, possibly quantized, format and
+/// converted to `f32` when accessed via [`GgufFile::tensor`].
+pub struct GgufFile {
+    data: Vec<u8>,
+    metadata: HashMap<String, MetadataValue>,
+    tensors: Vec<TensorInfo>,
+
+    /// Offset of the tensor data section in `data`.
+    data_offset: usize,
+}
+
+impl GgufFile {
+    /// Read and parse a GGUF file.
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<GgufFile, GgufError> {
+        let data = std::fs::read(path).map_err(GgufError::ReadFailed)?;
+        Self::parse(data)
+    }
+
+    /// Parse a GGUF file from a buffer.
+    pub fn parse(data: Vec<u8>) -> Result<GgufFile, GgufError> {
+        let mut reader = Reader {
+            data: &data,
+            pos: 0,
+        };
+
+        if reader.bytes(4)? != b"GGUF" {
+            return Err(GgufError::InvalidFile("incorrect magic number".into()));
+        }
+        let version = reader.u32()?;
+        if !(2..=3).contains(&version) {
+            return Err(GgufError::UnsupportedVersion(version));
+        }
+        let tensor_count = reader.len()?;
+        let metadata_count = reader.len()?;
+
+        let mut metadata = HashMap::new();
+        for _ in 0..metadata_count {
+            let key = reader.string()?;
+            let value_type = reader.u32()?;
+            let value = reader.metadata_value(value_type)?;
+            metadata.insert(key, value);
+        }
+
+        let mut tensors = Vec::new();
+        for _ in 0..tensor_count {
+            let name = reader.string()?;
+            let ndim = reader.u32()?;
+            let mut shape = Vec::new();
+            for _ in 0..ndim {
+                shape.push(reader.len()?);
+            }
+            // GGUF lists dimensions starting with the innermost one.
+            shape.reverse();
+            let dtype = TensorType::from_id(reader.u32()?)?;
+            let offset = reader.len()?;
+            tensors.push(TensorInfo {
+                name,
+                shape,
+                dtype,
+                offset,
+            });
+        }
+
+        let alignment = metadata
+            .get("general.alignment")
+            .and_then(|val| val.as_usize())
+            .unwrap_or(32)
+            .max(1);
+        let data_offset = reader
+            .pos
+            .checked_next_multiple_of(alignment)
+            .ok_or_else(|| GgufError::InvalidFile("alignment too large".into()))?;
+
+        let file = GgufFile {
+            data,
+            metadata,
+            tensors,
+            data_offset,
+        };
+
+        for info in &file.tensors {
+            file.tensor_data(info)?;
+        }
+
+        Ok(file)
+    }
+
+    /// Return the value of a metadata entry.
+    pub fn metadata(&self, key: &str) -> Option<&MetadataValue> {
+        self.metadata.get(key)
+    }
+
+    /// Return an iterator over the names of metadata entries.
+    pub fn metadata_keys(&self) -> impl Iterator<Item = &str> {
+        self.metadata.keys().map(|key| key.as_str())
+    }
+
+    /// Return an iterator over the names of tensors in the file.
+    pub fn tensor_names(&self) -> impl Iterator<Item = &str> {
+        self.tensors.iter().map(|info| info.name.as_str())
+    }
+
+    /// Return the shape of a tensor, in row-major order.
+    ///
+    /// Note that GGUF files list dimensions in the reverse order.
+    pub fn tensor_shape(&self, name: &str) -> Option<&[usize]> {
+        self.tensor_info(name).map(|info| info.shape.as_slice())
+    }
+
+    /// Read a tensor and convert it to `f32`, dequantizing it if necessary.
+    pub fn tensor(&self, name: &str) -> Result<Tensor<f32>, GgufError> {
+        let info = self
+            .tensor_info(name)
+            .ok_or_else(|| GgufError::MissingTensor(name.to_string()))?;
+        let data = self.tensor_data(info)?;
+        let elements = info.dtype.dequantize(data);
+        Ok(Tensor::from_data(&info.shape, elements))
+    }
+
+    fn tensor_info(&self, name: &str) -> Option<&TensorInfo> {
+        self.tensors.iter().find(|info| info.name == name)
+    }
+
+    /// Return the raw bytes of a tensor's data.
+    fn tensor_data(&self, info: &TensorInfo) -> Result<&[u8], GgufError> {
+        let out_of_bounds = || {
+            GgufError::InvalidFile(format!(
+                "data for tensor \"{}\" is out of bounds",
+                info.name
+            ))
+        };
+
+        let len = info
+            .shape
+            .iter()
+            .try_fold(1usize, |len, &dim| len.checked_mul(dim))
+            .ok_or_else(out_of_bounds)?;
+        let (block_len, block_size) = info.dtype.block_layout();
+        if len % block_len != 0 {
+            return Err(GgufError::InvalidFile(format!(
+                "size of tensor \"{}\" is not a multiple of the block size",
+                info.name
+            )));
+        }
+        let size = (len / block_len)
+            .checked_mul(block_size)
+            .ok_or_else(out_of_bounds)?;
+        let start = self
+            .data_offset
+            .checked_add(info.offset)
+            .ok_or_else(out_of_bounds)?;
+        let end = start.checked_add(size).ok_or_else(out_of_bounds)?;
+        self.data.get(start..end).ok_or_else(out_of_bounds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rten_tensor::prelude::*;
+
+    use super::{GgufError, GgufFile, MetadataValue};
+
+    /// Builder for GGUF files used in tests.
+    #[derive(Default)]
+    pub struct GgufBuilder {
+        metadata: Vec<(String, MetadataValue)>,
+        tensors: Vec<(String, Vec<usize>, u32, Vec<u8>)>,
+    }
+
+    impl GgufBuilder {
+        pub fn add_metadata(&mut self, key: &str, value: MetadataValue) {
+            self.metadata.push((key.to_string(), value));
+        }
+
+        /// Add a tensor with a row-major `shape` and `data` in the format
+        /// indicated by the GGML type ID `dtype`.
+        pub fn add_tensor(&mut self, name: &str, shape: &[usize], dtype: u32, data: Vec<u8>) {
+            self.tensors
+                .push((name.to_string(), shape.to_vec(), dtype, data));
+        }
+
+        pub fn add_f32_tensor(&mut self, name: &str, shape: &[usize], data: &[f32]) {
+            let data = data.iter().flat_map(|x| x.to_le_bytes()).collect();
+            self.add_tensor(name, shape, 0, data);
+        }
+
+        fn write_string(buf: &mut Vec<u8>, s: &str) {
+            buf.extend((s.len() as u64).to_le_bytes());
+            buf.extend(s.as_bytes());
+        }
+
+        fn write_value(buf: &mut Vec<u8>, value: &MetadataValue) {
+            match value {
+                MetadataValue::U32(x) => buf.extend(x.to_le_bytes()),
+                MetadataValue::F32(x) => buf.extend(x.to_le_bytes()),
+                MetadataValue::String(s) => Self::write_string(buf, s),
+                MetadataValue::Array(items) => {
+                    buf.extend(Self::type_id(&items[0]).to_le_bytes());
+                    buf.extend((items.len() as u64).to_le_bytes());
+                    for item in items {
+                        Self::write_value(buf, item);
+                    }
+                }
+                _ => unimplemented!("unsupported metadata type in test"),
+            }
+        }
+
+        fn type_id(value: &MetadataValue) -> u32 {
+            match value {
+                MetadataValue::U32(_) => 4,
+                MetadataValue::F32(_) => 6,
+                MetadataValue::String(_) => 8,
+                MetadataValue::Array(_) => 9,
+                _ => unimplemented!("unsupported metadata type in test"),
+            }
+        }
+
+        pub fn finish(self) -> Vec<u8> {
+            let alignment = 32;
+            let mut buf = Vec::new();
+            buf.extend(b"GGUF");
+            buf.extend(3u32.to_le_bytes());
+            buf.extend((self.tensors.len() as u64).to_le_bytes());
+            buf.extend((self.metadata.len() as u64).to_le_bytes());
+
+            for (key, value) in &self.metadata {
+                Self::write_string(&mut buf, key);
+                buf.extend(Self::type_id(value).to_le_bytes());
+                Self::write_value(&mut buf, value);
+            }
+
+            let mut data = Vec::new();
+            for (name, shape, dtype, tensor_data) in &self.tensors {
+                Self::write_string(&mut buf, name);
+                buf.extend((shape.len() as u32).to_le_bytes());
+                for &dim in shape.iter().rev() {
+                    buf.extend((dim as u64).to_le_bytes());
+                }
+                buf.extend(dtype.to_le_bytes());
+                data.resize(data.len().next_multiple_of(alignment), 0);
+                buf.extend((data.len() as u64).to_le_bytes());
+                data.extend(tensor_data);
+            }
+
+            buf.resize(buf.len().next_multiple_of(alignment), 0);
+            buf.extend(data);
+            buf
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let mut builder = GgufBuilder::default();
+        builder.add_metadata(
+            "general.architecture",
+            MetadataValue::String("llama".into()),
+        );
+        builder.add_metadata("llama.block_count", MetadataValue::U32(2));
+        builder.add_metadata(
+            "tokenizer.ggml.tokens",
+            MetadataValue::Array(vec![
+                MetadataValue::String("a".into()),
+                MetadataValue::String("b".into()),
+            ]),
+        );
+        builder.add_f32_tensor("foo", &[2, 3], &[1., 2., 3., 4., 5., 6.]);
+        builder.add_f32_tensor("bar", &[2], &[7., 8.]);
+        let gguf = GgufFile::parse(builder.finish()).unwrap();
+
+        assert_eq!(
+            gguf.metadata("general.architecture")
+                .and_then(|v| v.as_str()),
+            Some("llama")
+        );
+        assert_eq!(
+            gguf.metadata("llama.block_count")
+                .and_then(|v| v.as_usize()),
+            Some(2)
+        );
+        assert_eq!(
+            gguf.metadata("tokenizer.ggml.tokens")
+                .and_then(|v| v.as_array())
+                .map(|v| v.len()),
+            Some(2)
+        );
+
+        let mut names: Vec<_> = gguf.tensor_names().collect();
+        names.sort();
+        assert_eq!(names, ["bar", "foo"]);
+        assert_eq!(gguf.tensor_shape("foo"), Some([2, 3].as_slice()));
+
+        let foo = gguf.tensor("foo").unwrap();
+        assert_eq!(foo.shape(), [2, 3]);
+        assert_eq!(foo.to_vec(), [1., 2., 3., 4., 5., 6.]);
+        let bar = gguf.tensor("bar").unwrap();
+        assert_eq!(bar.to_vec(), [7., 8.]);
+
+        assert!(matches!(
+            gguf.tensor("baz"),
+            Err(GgufError::MissingTensor(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        let err = GgufFile::parse(b"GGML".to_vec()).err().unwrap();
+        assert_eq!(err.to_string(), "invalid GGUF file: incorrect magic number");
+
+        let mut buf = b"GGUF".to_vec();
+        buf.extend(1u32.to_le_bytes());
+        let err = GgufFile::parse(buf).err().unwrap();
+        assert_eq!(err.to_string(), "unsupported GGUF version 1");
+
+        let mut builder = GgufBuilder::default();
+        builder.add_f32_tensor("foo", &[2, 3], &[1., 2., 3., 4., 5., 6.]);
+        let mut buf = builder.finish();
+        buf.truncate(buf.len() - 4);
+        let err = GgufFile::parse(buf).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "invalid GGUF file: data for tensor \"foo\" is out of bounds"
+        );
+
+        // Shape whose element count overflows.
+        let mut builder = GgufBuilder::default();
+        builder.add_tensor("foo", &[usize::MAX, 2], 0, vec![0; 8]);
+        let err = GgufFile::parse(builder.finish()).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "invalid GGUF file: data for tensor \"foo\" is out of bounds"
+        );
+    }
+}
@@ -0,0 +1,907 @@
+//! Construction of Llama-family transformer decoders from GGUF files.
+
+use rten_tensor::prelude::*;
+use rten_tensor::{Tensor, TensorView};
+
+use super::{GgufError, GgufFile};
+use crate::graph::Dimension;
+use crate::model::Model;
+use crate::model_builder::{GraphBuilder, ModelBuilder, ModelFormat, OpType};
+use crate::ops::{Cast, Concat, DataType, Gather, ReduceMean, Reshape, Softmax, Transpose};
+
+/// Hyperparameters of a Llama-family model.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LlamaConfig {
+    /// Number of transformer layers.
+    pub n_layers: usize,
+
+    /// Size of the hidden state.
+    pub n_embd: usize,
+
+    /// Number of query heads.
+    pub n_heads: usize,
+
+    /// Number of key and value heads. This is less than `n_heads` for models
+    /// which use grouped-query attention.
+    pub n_kv_heads: usize,
+
+    /// Epsilon used in RMS normalization layers.
+    pub rms_norm_eps: f32,
+
+    /// Base frequency for rotary position embeddings.
+    pub rope_freq_base: f32,
+
+    /// Factor by which positions are divided when computing rotary position
+    /// embeddings. This is 1 unless the model uses linear RoPE scaling.
+    pub rope_scale: f32,
+}
+
+impl LlamaConfig {
+    /// Read the model configuration from GGUF metadata.
+    pub fn from_gguf(gguf: &GgufFile) -> Result<LlamaConfig, GgufError> {
+        let arch = gguf
+            .metadata("general.architecture")
+            .and_then(|val| val.as_str())
+            .ok_or_else(|| GgufError::MissingMetadata("general.architecture".into()))?;
+        if arch != "llama" {
+            return Err(GgufError::UnsupportedArchitecture(arch.to_string()));
+        }
+
+        let get_usize = |key: &str| {
+            gguf.metadata(key)
+                .and_then(|val| val.as_usize())
+                .ok_or_else(|| GgufError::MissingMetadata(key.to_string()))
+        };
+        let get_f32 = |key: &str, default: f32| match gguf.metadata(key) {
+            Some(val) => val
+                .as_f32()
+                .ok_or_else(|| GgufError::MissingMetadata(key.to_string())),
+            None => Ok(default),
+        };
+
+        let n_heads = get_usize("llama.attention.head_count")?;
+        let n_kv_heads = match gguf.metadata("llama.attention.head_count_kv") {
+            Some(_) => get_usize("llama.attention.head_count_kv")?,
+            None => n_heads,
+        };
+        let n_embd = get_usize("llama.embedding_length")?;
+
+        if n_heads == 0 || n_kv_heads == 0 || n_heads % n_kv_heads != 0 || n_embd % n_heads != 0 {
+            return Err(GgufError::InvalidMetadata(format!(
+                "head counts {} and {} are incompatible with embedding size {}",
+                n_heads, n_kv_heads, n_embd
+            )));
+        }
+
+        let head_dim = n_embd / n_heads;
+        if let Some(rope_dims) = gguf.metadata("llama.rope.dimension_count") {
+            if rope_dims.as_usize() != Some(head_dim) {
+                return Err(GgufError::UnsupportedArchitecture(
+                    "llama with partial rotary embeddings".into(),
+                ));
+            }
+        }
+
+        let rope_scale = match gguf
+            .metadata("llama.rope.scaling.type")
+            .and_then(|val| val.as_str())
+        {
+            None | Some("none") => 1.,
+            Some("linear") => get_f32("llama.rope.scaling.factor", 1.)?,
+            Some(scaling) => {
+                return Err(GgufError::UnsupportedArchitecture(format!(
+                    "llama with \"{}\" RoPE scaling",
+                    scaling
+                )))
+            }
+        };
+
+        Ok(LlamaConfig {
+            n_layers: get_usize("llama.block_count")?,
+            n_embd,
+            n_heads,
+            n_kv_heads,
+            rms_norm_eps: get_f32("llama.attention.layer_norm_rms_epsilon", 1e-5)?,
+            rope_freq_base: get_f32("llama.rope.freq_base", 10_000.)?,
+            rope_scale,
+        })
+    }
+
+    fn head_dim(&self) -> usize {
+        self.n_embd / self.n_heads
+    }
+}
+
+/// Wrapper around [`GraphBuilder`] which generates names for intermediate
+/// values.
+struct LlamaGraphBuilder<'mb, 'a> {
+    graph: GraphBuilder<'mb, 'a>,
+}
+
+impl<'mb, 'a> LlamaGraphBuilder<'mb, 'a> {
+    /// Add an operator with a single output and return the output's ID.
+    fn op(&mut self, name: &str, op: OpType, inputs: &[u32]) -> u32 {
+        let output = self.graph.add_value(&format!("{}_out", name), None);
+        self.op_with_output(name, op, inputs, output);
+        output
+    }
+
+    fn op_with_output(&mut self, name: &str, op: OpType, inputs: &[u32], output: u32) {
+        let inputs: Vec<_> = inputs.iter().copied().map(Some).collect();
+        self.graph.add_operator(name, op, &inputs, &[output]);
+    }
+
+    fn constant(&mut self, tensor: TensorView<f32>) -> u32 {
+        self.graph.add_constant(tensor)
+    }
+
+    fn int_constant(&mut self, values: &[i32]) -> u32 {
+        self.graph
+            .add_constant(TensorView::from_data(&[values.len()], values))
+    }
+
+    fn scalar(&mut self, value: f32) -> u32 {
+        self.graph.add_constant(Tensor::from_scalar(value).view())
+    }
+
+    fn int_scalar(&mut self, value: i32) -> u32 {
+        self.graph.add_constant(Tensor::from_scalar(value).view())
+    }
+
+    /// Add a `x @ weight` matrix multiplication, where `weight` has shape
+    /// `(in_features, out_features)`.
+    fn linear(&mut self, name: &str, x: u32, weight: TensorView<f32>) -> u32 {
+        let weight = self.constant(weight);
+        self.op(name, OpType::MatMul, &[x, weight])
+    }
+
+    fn rms_norm(&mut self, name: &str, x: u32, weight: TensorView<f32>, eps: f32) -> u32 {
+        let square = self.op(&format!("{}.square", name), OpType::Mul, &[x, x]);
+        let mean = self.op(
+            &format!("{}.mean", name),
+            OpType::ReduceMean(ReduceMean {
+                axes: Some(vec![-1]),
+                keep_dims: true,
+            }),
+            &[square],
+        );
+        let eps = self.scalar(eps);
+        let mean_eps = self.op(&format!("{}.add_eps", name), OpType::Add, &[mean, eps]);
+        let rms = self.op(&format!("{}.sqrt", name), OpType::Sqrt, &[mean_eps]);
+        let normalized = self.op(&format!("{}.div", name), OpType::Div, &[x, rms]);
+        let weight = self.constant(weight);
+        self.op(
+            &format!("{}.scale", name),
+            OpType::Mul,
+            &[normalized, weight],
+        )
+    }
+
+    fn reshape(&mut self, name: &str, x: u32, shape: &[i32]) -> u32 {
+        let shape = self.int_constant(shape);
+        self.op(
+            name,
+            OpType::Reshape(Reshape { allow_zero: false }),
+            &[x, shape],
+        )
+    }
+
+    fn transpose(&mut self, name: &str, x: u32, perm: &[usize]) -> u32 {
+        self.op(
+            name,
+            OpType::Transpose(Transpose {
+                perm: Some(perm.to_vec()),
+            }),
+            &[x],
+        )
+    }
+
+    fn unsqueeze(&mut self, name: &str, x: u32, axes: &[i32]) -> u32 {
+        let axes = self.int_constant(axes);
+        self.op(name, OpType::Unsqueeze, &[x, axes])
+    }
+
+    /// Apply rotary position embeddings to a `(batch, heads, seq, head_dim)`
+    /// tensor, using the "rotate half" formulation.
+    fn rope(&mut self, name: &str, x: u32, cos: u32, sin: u32, head_dim: usize) -> u32 {
+        let half = head_dim as i32 / 2;
+        let axes = self.int_constant(&[3]);
+        let zero = self.int_constant(&[0]);
+        let mid = self.int_constant(&[half]);
+        let end = self.int_constant(&[head_dim as i32]);
+        let x1 = self.op(
+            &format!("{}.x1", name),
+            OpType::Slice,
+            &[x, zero, mid, axes],
+        );
+        let x2 = self.op(&format!("{}.x2", name), OpType::Slice, &[x, mid, end, axes]);
+        let neg_x2 = self.op(&format!("{}.neg", name), OpType::Neg, &[x2]);
+        let rotated = self.op(
+            &format!("{}.rotate", name),
+            OpType::Concat(Concat { axis: 3 }),
+            &[neg_x2, x1],
+        );
+        let x_cos = self.op(&format!("{}.mul_cos", name), OpType::Mul, &[x, cos]);
+        let rot_sin = self.op(&format!("{}.mul_sin", name), OpType::Mul, &[rotated, sin]);
+        self.op(&format!("{}.add", name), OpType::Add, &[x_cos, rot_sin])
+    }
+}
+
+/// Weights of a linear layer, transposed into `(in_features, out_features)`
+/// order.
+fn linear_weight(gguf: &GgufFile, name: &str) -> Result<Tensor<f32>, GgufError> {
+    let weight = gguf.tensor(name)?;
+    if weight.ndim() != 2 {
+        return Err(GgufError::ShapeMismatch(
+            name.to_string(),
+            weight.shape().to_vec(),
+        ));
+    }
+    Ok(weight.transposed().to_tensor())
+}
+
+/// Load the weights for the query or key projection of an attention layer.
+///
+/// llama.cpp's conversion scripts permute the rows of these weights so that
+/// rotary embeddings are applied to adjacent pairs of channels in each head,
+/// matching Meta's original implementation. This undoes that permutation, so
+/// that embeddings can instead be applied to the two halves of each head, as
+/// in the Hugging Face implementation.
+fn rope_linear_weight(
+    gguf: &GgufFile,
+    name: &str,
+    n_heads: usize,
+) -> Result<Tensor<f32>, GgufError> {
+    let weight = gguf.tensor(name)?;
+    let shape_mismatch = || GgufError::ShapeMismatch(name.to_string(), weight.shape().to_vec());
+    let &[out_features, in_features] = weight.shape() else {
+        return Err(shape_mismatch());
+    };
+    if out_features % (n_heads * 2) != 0 {
+        return Err(shape_mismatch());
+    }
+    let head_dim = out_features / n_heads;
+    let mut unpermuted = weight
+        .reshaped([n_heads, head_dim / 2, 2, in_features].as_slice())
+        .permuted([0, 2, 1, 3].as_slice())
+        .to_tensor();
+    unpermuted.reshape(&[out_features, in_features]);
+    Ok(unpermuted.transposed().to_tensor())
+}
+
+/// Return a tensor from the GGUF file, checking that it has the expected
+/// shape.
+fn tensor_with_shape(
+    gguf: &GgufFile,
+    name: &str,
+    shape: &[usize],
+) -> Result<Tensor<f32>, GgufError> {
+    let tensor = gguf.tensor(name)?;
+    if tensor.shape() != shape {
+        return Err(GgufError::ShapeMismatch(
+            name.to_string(),
+            tensor.shape().to_vec(),
+        ));
+    }
+    Ok(tensor)
+}
+
+/// Construct a model from a GGUF file containing a Llama-family model.
+///
+/// This supports models whose `general.architecture` metadata is `llama`,
+/// which includes Llama 1-3, Mistral, TinyLlama and other models with the
+/// same structure. Weights are converted to `f32` at load time, so the
+/// memory required is about 4 bytes per parameter regardless of the
+/// quantization used in the file.
+///
+/// The model has the following inputs and outputs:
+///
+/// - `input_ids` - `(batch, seq)` token IDs
+/// - `attention_mask` - `(batch, past_seq + seq)` mask which is 1 for tokens
+///   that should be attended to and 0 for padding
+/// - `position_ids` - `(batch, seq)` positions of tokens in the sequence
+/// - `past_key_values.{layer}.{key, value}` - `(batch, kv_heads, past_seq,
+///   head_dim)` key-value cache inputs
+/// - `logits` - `(batch, seq, n_vocab)` output logits
+/// - `present.{layer}.{key, value}` - Updated key-value cache outputs
+///
+/// These follow the conventions expected by the `rten-generate` crate.
+pub fn load_llama(gguf: &GgufFile) -> Result<Model, GgufError> {
+    let config = LlamaConfig::from_gguf(gguf)?;
+    let buf = build_llama(gguf, &config)?;
+    Model::load(buf).map_err(GgufError::ModelLoadFailed)
+}
+
+/// Serialize a Llama model in the RTen model format.
+fn build_llama(gguf: &GgufFile, config: &LlamaConfig) -> Result<Vec<u8>, GgufError> {
+    let n_embd = config.n_embd;
+    let head_dim = config.head_dim();
+    let n_heads = config.n_heads;
+    let n_kv_heads = config.n_kv_heads;
+    let n_rep = n_heads / n_kv_heads;
+    let eps = config.rms_norm_eps;
+
+    let mut model_builder = ModelBuilder::new(ModelFormat::V2);
+    let mut b = LlamaGraphBuilder {
+        graph: model_builder.graph_builder(),
+    };
+
+    let batch = Dimension::Symbolic("batch".into());
+    let seq = Dimension::Symbolic("sequence".into());
+    let input_ids = b
+        .graph
+        .add_value("input_ids", Some(&[batch.clone(), seq.clone()]));
+    let attention_mask = b.graph.add_value(
+        "attention_mask",
+        Some(&[batch.clone(), Dimension::Symbolic("total_sequence".into())]),
+    );
+    let position_ids = b
+        .graph
+        .add_value("position_ids", Some(&[batch.clone(), seq.clone()]));
+    for input in [input_ids, attention_mask, position_ids] {
+        b.graph.add_input(input);
+    }
+
+    // Token embeddings.
+    let token_embd = gguf.tensor("token_embd.weight")?;
+    if !matches!(token_embd.shape(), &[_, embd_dim] if embd_dim == n_embd) {
+        return Err(GgufError::ShapeMismatch(
+            "token_embd.weight".into(),
+            token_embd.shape().to_vec(),
+        ));
+    }
+    let embd_const = b.constant(token_embd.view());
+    let mut hidden = b.op(
+        "embed_tokens",
+        OpType::Gather(Gather { axis: 0 }),
+        &[embd_const, input_ids],
+    );
+
+    // Rotary embedding tables with shape `(batch, 1, seq, head_dim)`.
+    let mut inv_freq: Vec<f32> = (0..head_dim / 2)
+        .map(|i| {
+            1. / config.rope_freq_base.powf((2 * i) as f32 / head_dim as f32) / config.rope_scale
+        })
+        .collect();
+    if gguf.tensor_shape("rope_freqs.weight").is_some() {
+        // Per-frequency scaling factors used by Llama 3.1 and later.
+        let factors = tensor_with_shape(gguf, "rope_freqs.weight", &[head_dim / 2])?;
+        for (freq, factor) in inv_freq.iter_mut().zip(factors.iter()) {
+            *freq /= factor;
+        }
+    }
+    let inv_freq = b.constant(TensorView::from_data(&[head_dim / 2], inv_freq.as_slice()));
+    let positions = b.op(
+        "rope.positions",
+        OpType::Cast(Cast {
+            to: DataType::Float,
+        }),
+        &[position_ids],
+    );
+    let positions = b.unsqueeze("rope.positions_unsqueeze", positions, &[2]);
+    let freqs = b.op("rope.freqs", OpType::Mul, &[positions, inv_freq]);
+    let freqs = b.op(
+        "rope.freqs_concat",
+        OpType::Concat(Concat { axis: -1 }),
+        &[freqs, freqs],
+    );
+    let cos = b.op("rope.cos", OpType::Cos, &[freqs]);
+    let cos = b.unsqueeze("rope.cos_unsqueeze", cos, &[1]);
+    let sin = b.op("rope.sin", OpType::Sin, &[freqs]);
+    let sin = b.unsqueeze("rope.sin_unsqueeze", sin, &[1]);
+
+    // Attention mask with shape `(batch, 1, 1, seq, total_seq)`, which
+    // combines causal masking and the padding mask from `attention_mask`.
+    let mask_shape = b.op("mask.shape", OpType::Shape, &[attention_mask]);
+    let one = b.int_scalar(1);
+    let total_seq = b.op(
+        "mask.total_seq",
+        OpType::Gather(Gather { axis: 0 }),
+        &[mask_shape, one],
+    );
+    let zero = b.int_scalar(0);
+    let key_positions = b.op("mask.key_positions", OpType::Range, &[zero, total_seq, one]);
+    let query_positions = b.unsqueeze("mask.query_positions", position_ids, &[2]);
+    let causal = b.op(
+        "mask.causal",
+        OpType::Greater,
+        &[key_positions, query_positions],
+    );
+    let padding = b.op("mask.padding", OpType::Equal, &[attention_mask, zero]);
+    let padding = b.unsqueeze("mask.padding_unsqueeze", padding, &[1]);
+    let masked = b.op("mask.or", OpType::Or, &[causal, padding]);
+    let neg_inf = b.scalar(f32::NEG_INFINITY);
+    let zero_f = b.scalar(0.);
+    let mask = b.op("mask.where", OpType::Where, &[masked, neg_inf, zero_f]);
+    let mask = b.unsqueeze("mask.unsqueeze", mask, &[1, 2]);
+
+    let attn_scale = b.scalar(1. / (head_dim as f32).sqrt());
+    let kv_cache_shape = [
+        batch.clone(),
+        Dimension::Fixed(n_kv_heads),
+        Dimension::Symbolic("past_sequence".into()),
+        Dimension::Fixed(head_dim),
+    ];
+
+    for layer in 0..config.n_layers {
+        let p = format!("blk.{}", layer);
+        let name = |op: &str| format!("layers.{}.{}", layer, op);
+
+        // Self-attention.
+        let attn_norm = tensor_with_shape(gguf, &format!("{}.attn_norm.weight", p), &[n_embd])?;
+        let normed = b.rms_norm(&name("attn_norm"), hidden, attn_norm.view(), eps);
+
+        let wq = rope_linear_weight(gguf, &format!("{}.attn_q.weight", p), n_heads)?;
+        let wk = rope_linear_weight(gguf, &format!("{}.attn_k.weight", p), n_kv_heads)?;
+        let wv = linear_weight(gguf, &format!("{}.attn_v.weight", p))?;
+        let q = b.linear(&name("q_proj"), normed, wq.view());
+        let k = b.linear(&name("k_proj"), normed, wk.view());
+        let v = b.linear(&name("v_proj"), normed, wv.view());
+
+        // (batch, seq, heads * head_dim) => (batch, heads, seq, head_dim)
+        let q = b.reshape(
+            &name("q_reshape"),
+            q,
+            &[0, 0, n_heads as i32, head_dim as i32],
+        );
+        let q = b.transpose(&name("q_transpose"), q, &[0, 2, 1, 3]);
+        let k = b.reshape(
+            &name("k_reshape"),
+            k,
+            &[0, 0, n_kv_heads as i32, head_dim as i32],
+        );
+        let k = b.transpose(&name("k_transpose"), k, &[0, 2, 1, 3]);
+        let v = b.reshape(
+            &name("v_reshape"),
+            v,
+            &[0, 0, n_kv_heads as i32, head_dim as i32],
+        );
+        let v = b.transpose(&name("v_transpose"), v, &[0, 2, 1, 3]);
+
+        let q = b.rope(&name("q_rope"), q, cos, sin, head_dim);
+        let k = b.rope(&name("k_rope"), k, cos, sin, head_dim);
+        let q = b.op(&name("q_scale"), OpType::Mul, &[q, attn_scale]);
+
+        // Append new keys and values to the cache.
+        let past_key = b.graph.add_value(
+            &format!("past_key_values.{}.key", layer),
+            Some(&kv_cache_shape),
+        );
+        let past_value = b.graph.add_value(
+            &format!("past_key_values.{}.value", layer),
+            Some(&kv_cache_shape),
+        );
+        b.graph.add_input(past_key);
+        b.graph.add_input(past_value);
+        let present_key = b.graph.add_value(&format!("present.{}.key", layer), None);
+        let present_value = b.graph.add_value(&format!("present.{}.value", layer), None);
+        b.graph.add_output(present_key);
+        b.graph.add_output(present_value);
+        b.op_with_output(
+            &name("key_concat"),
+            OpType::Concat(Concat { axis: 2 }),
+            &[past_key, k],
+            present_key,
+        );
+        b.op_with_output(
+            &name("value_concat"),
+            OpType::Concat(Concat { axis: 2 }),
+            &[past_value, v],
+            present_value,
+        );
+
+        // Grouped-query attention. Queries are grouped by the key-value head
+        // they share, to avoid copying the keys and values for each query
+        // head.
+        //
+        // (batch, heads, seq, head_dim) => (batch, kv_heads, n_rep, seq, head_dim)
+        let q = b.reshape(
+            &name("q_group"),
+            q,
+            &[0, n_kv_heads as i32, n_rep as i32, -1, head_dim as i32],
+        );
+        let k = b.unsqueeze(&name("k_unsqueeze"), present_key, &[2]);
+        let k = b.transpose(&name("k_t"), k, &[0, 1, 2, 4, 3]);
+        let v = b.unsqueeze(&name("v_unsqueeze"), present_value, &[2]);
+
+        let scores = b.op(&name("scores"), OpType::MatMul, &[q, k]);
+        let scores = b.op(&name("scores_mask"), OpType::Add, &[scores, mask]);
+        let probs = b.op(
+            &name("softmax"),
+            OpType::Softmax(Softmax { axis: -1 }),
+            &[scores],
+        );
+        let attn = b.op(&name("attn"), OpType::MatMul, &[probs, v]);
+
+        // (batch, kv_heads, n_rep, seq, head_dim) => (batch, seq, heads * head_dim)
+        let attn = b.reshape(
+            &name("attn_ungroup"),
+            attn,
+            &[0, n_heads as i32, -1, head_dim as i32],
+        );
+        let attn = b.transpose(&name("attn_transpose"), attn, &[0, 2, 1, 3]);
+        let attn = b.reshape(&name("attn_reshape"), attn, &[0, 0, n_embd as i32]);
+
+        let wo = linear_weight(gguf, &format!("{}.attn_output.weight", p))?;
+        let attn_out = b.linear(&name("o_proj"), attn, wo.view());
+        hidden = b.op(&name("attn_residual"), OpType::Add, &[hidden, attn_out]);
+
+        // SwiGLU feed-forward network.
+        let ffn_norm = tensor_with_shape(gguf, &format!("{}.ffn_norm.weight", p), &[n_embd])?;
+        let normed = b.rms_norm(&name("ffn_norm"), hidden, ffn_norm.view(), eps);
+
+        let w_gate = linear_weight(gguf, &format!("{}.ffn_gate.weight", p))?;
+        let w_up = linear_weight(gguf, &format!("{}.ffn_up.weight", p))?;
+        let w_down = linear_weight(gguf, &format!("{}.ffn_down.weight", p))?;
+        let gate = b.linear(&name("gate_proj"), normed, w_gate.view());
+        let gate_sigmoid = b.op(&name("gate_sigmoid"), OpType::Sigmoid, &[gate]);
+        let gate = b.op(&name("gate_silu"), OpType::Mul, &[gate, gate_sigmoid]);
+        let up = b.linear(&name("up_proj"), normed, w_up.view());
+        let gated = b.op(&name("gate_up"), OpType::Mul, &[gate, up]);
+        let ffn_out = b.linear(&name("down_proj"), gated, w_down.view());
+        hidden = b.op(&name("ffn_residual"), OpType::Add, &[hidden, ffn_out]);
+    }
+
+    let output_norm = tensor_with_shape(gguf, "output_norm.weight", &[n_embd])?;
+    let normed = b.rms_norm("norm", hidden, output_norm.view(), eps);
+
+    // Models with tied embeddings omit the output projection.
+    let output_weight = if gguf.tensor_shape("output.weight").is_some() {
+        linear_weight(gguf, "output.weight")?
+    } else {
+        token_embd.transposed().to_tensor()
+    };
+    let logits = b.graph.add_value("logits", None);
+    let output_weight = b.constant(output_weight.view());
+    b.op_with_output("lm_head", OpType::MatMul, &[normed, output_weight], logits);
+    b.graph.add_output(logits);
+
+    let graph = b.graph.finish();
+    model_builder.set_graph(graph);
+
+    Ok(model_builder.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rten_tensor::prelude::*;
+    use rten_tensor::rng::XorShiftRng;
+    use rten_tensor::{NdTensor, Tensor};
+
+    use super::{load_llama, LlamaConfig};
+    use crate::gguf::tests::GgufBuilder;
+    use crate::gguf::{GgufFile, MetadataValue};
+    use crate::model::Model;
+    use crate::ops::{InputOrOutput, Output};
+
+    const N_LAYERS: usize = 2;
+    const N_EMBD: usize = 8;
+    const N_HEADS: usize = 2;
+    const N_KV_HEADS: usize = 1;
+    const HEAD_DIM: usize = N_EMBD / N_HEADS;
+    const N_FF: usize = 12;
+    const N_VOCAB: usize = 10;
+    const EPS: f32 = 1e-5;
+    const ROPE_BASE: f32 = 100.;
+
+    /// Generate random weights for a small Llama model, with row-major
+    /// shapes as used by GGUF.
+    fn random_weights() -> HashMap<String, Tensor<f32>> {
+        let mut rng = XorShiftRng::new(1234);
+        let mut weights = HashMap::new();
+        let mut add = |name: String, shape: &[usize]| {
+            let tensor = Tensor::<f32>::rand(shape, &mut rng).map(|x| x - 0.5);
+            weights.insert(name, tensor);
+        };
+
+        add("token_embd.weight".into(), &[N_VOCAB, N_EMBD]);
+        for layer in 0..N_LAYERS {
+            let p = format!("blk.{}", layer);
+            add(format!("{}.attn_norm.weight", p), &[N_EMBD]);
+            add(format!("{}.attn_q.weight", p), &[N_EMBD, N_EMBD]);
+            add(
+                format!("{}.attn_k.weight", p),
+                &[N_KV_HEADS * HEAD_DIM, N_EMBD],
+            );
+            add(
+                format!("{}.attn_v.weight", p),
+                &[N_KV_HEADS * HEAD_DIM, N_EMBD],
+            );
+            add(format!("{}.attn_output.weight", p), &[N_EMBD, N_EMBD]);
+            add(format!("{}.ffn_norm.weight", p), &[N_EMBD]);
+            add(format!("{}.ffn_gate.weight", p), &[N_FF, N_EMBD]);
+            add(format!("{}.ffn_up.weight", p), &[N_FF, N_EMBD]);
+            add(format!("{}.ffn_down.weight", p), &[N_EMBD, N_FF]);
+        }
+        add("output_norm.weight".into(), &[N_EMBD]);
+        add("output.weight".into(), &[N_VOCAB, N_EMBD]);
+        weights
+    }
+
+    fn build_gguf(weights: &HashMap<String, Tensor<f32>>) -> GgufFile {
+        let mut builder = GgufBuilder::default();
+        builder.add_metadata(
+            "general.architecture",
+            MetadataValue::String("llama".into()),
+        );
+        builder.add_metadata("llama.block_count", MetadataValue::U32(N_LAYERS as u32));
+        builder.add_metadata("llama.embedding_length", MetadataValue::U32(N_EMBD as u32));
+        builder.add_metadata(
+            "llama.attention.head_count",
+            MetadataValue::U32(N_HEADS as u32),
+        );
+        builder.add_metadata(
+            "llama.attention.head_count_kv",
+            MetadataValue::U32(N_KV_HEADS as u32),
+        );
+        builder.add_metadata(
+            "llama.attention.layer_norm_rms_epsilon",
+            MetadataValue::F32(EPS),
+        );
+        builder.add_metadata("llama.rope.freq_base", MetadataValue::F32(ROPE_BASE));
+        for (name, tensor) in weights {
+            builder.add_f32_tensor(name, tensor.shape(), &tensor.to_vec());
+        }
+        GgufFile::parse(builder.finish()).unwrap()
+    }
+
+    fn rms_norm(x: &[f32], weight: &Tensor<f32>) -> Vec<f32> {
+        let rms = (x.iter().map(|x| x * x).sum::<f32>() / x.len() as f32 + EPS).sqrt();
+        x.iter()
+            .zip(weight.iter())
+            .map(|(x, w)| x / rms * w)
+            .collect()
+    }
+
+    /// Compute `weight @ x` where `weight` has shape `(out, in)`.
+    fn matvec(weight: &Tensor<f32>, x: &[f32]) -> Vec<f32> {
+        weight
+            .axis_iter(0)
+            .map(|row| row.iter().zip(x).map(|(w, x)| w * x).sum())
+            .collect()
+    }
+
+    /// Apply rotary embeddings to adjacent pairs of channels in each head, as
+    /// llama.cpp does with GGUF weights.
+    fn rope_interleaved(x: &mut [f32], pos: usize) {
+        for head in x.chunks_mut(HEAD_DIM) {
+            for i in 0..HEAD_DIM / 2 {
+                let theta = pos as f32 * ROPE_BASE.powf(-((2 * i) as f32) / HEAD_DIM as f32);
+                let (x0, x1) = (head[2 * i], head[2 * i + 1]);
+                head[2 * i] = x0 * theta.cos() - x1 * theta.sin();
+                head[2 * i + 1] = x0 * theta.sin() + x1 * theta.cos();
+            }
+        }
+    }
+
+    /// Straightforward reference implementation of the Llama forward pass,
+    /// which returns the logits for each position in `tokens`.
+    fn reference_forward(weights: &HashMap<String, Tensor<f32>>, tokens: &[u32]) -> Vec<Vec<f32>> {
+        let w = |name: &str| &weights[name];
+        let mut key_cache: Vec<Vec<Vec<f32>>> = vec![Vec::new(); N_LAYERS];
+        let mut value_cache: Vec<Vec<Vec<f32>>> = vec![Vec::new(); N_LAYERS];
+        let mut all_logits = Vec::new();
+
+        for (pos, &token) in tokens.iter().enumerate() {
+            let mut x = w("token_embd.weight")
+                .slice::<1, _>(token as usize)
+                .to_vec();
+
+            for layer in 0..N_LAYERS {
+                let p = format!("blk.{}", layer);
+                let lw = |suffix: &str| w(&format!("{}.{}", p, suffix));
+
+                let normed = rms_norm(&x, lw("attn_norm.weight"));
+                let mut q = matvec(lw("attn_q.weight"), &normed);
+                let mut k = matvec(lw("attn_k.weight"), &normed);
+                let v = matvec(lw("attn_v.weight"), &normed);
+                rope_interleaved(&mut q, pos);
+                rope_interleaved(&mut k, pos);
+                key_cache[layer].push(k);
+                value_cache[layer].push(v);
+
+                let mut attn = vec![0.; N_EMBD];
+                for h in 0..N_HEADS {
+                    let kv_h = h / (N_HEADS / N_KV_HEADS);
+                    let q_h = &q[h * HEAD_DIM..(h + 1) * HEAD_DIM];
+                    let scores: Vec<f32> = key_cache[layer]
+                        .iter()
+                        .map(|k| {
+                            let k_h = &k[kv_h * HEAD_DIM..(kv_h + 1) * HEAD_DIM];
+                            q_h.iter().zip(k_h).map(|(q, k)| q * k).sum::<f32>()
+                                / (HEAD_DIM as f32).sqrt()
+                        })
+                        .collect();
+                    let max = scores.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+                    let exps: Vec<f32> = scores.iter().map(|s| (s - max).exp()).collect();
+                    let sum: f32 = exps.iter().sum();
+                    for (p, v) in exps.iter().zip(&value_cache[layer]) {
+                        for c in 0..HEAD_DIM {
+                            attn[h * HEAD_DIM + c] += p / sum * v[kv_h * HEAD_DIM + c];
+                        }
+                    }
+                }
+                let attn_out = matvec(lw("attn_output.weight"), &attn);
+                x.iter_mut().zip(attn_out).for_each(|(x, y)| *x += y);
+
+                let normed = rms_norm(&x, lw("ffn_norm.weight"));
+                let gate = matvec(lw("ffn_gate.weight"), &normed);
+                let up = matvec(lw("ffn_up.weight"), &normed);
+                let gated: Vec<f32> = gate
+                    .iter()
+                    .zip(up)
+                    .map(|(g, u)| g / (1. + (-g).exp()) * u)
+                    .collect();
+                let ffn_out = matvec(lw("ffn_down.weight"), &gated);
+                x.iter_mut().zip(ffn_out).for_each(|(x, y)| *x += y);
+            }
+
+            let normed = rms_norm(&x, w("output_norm.weight"));
+            all_logits.push(matvec(w("output.weight"), &normed));
+        }
+
+        all_logits
+    }
+
+    /// Run the model on `tokens`, starting at position `past_len`, and return
+    /// the logits and updated KV cache.
+    fn run_model(
+        model: &Model,
+        tokens: &[u32],
+        past_len: usize,
+        kv_cache: Vec<Output>,
+    ) -> (NdTensor<f32, 3>, Vec<Output>) {
+        let seq = tokens.len();
+        let input_ids = NdTensor::from_fn([1, seq], |[_, i]| tokens[i] as i32);
+        let attention_mask = NdTensor::full([1, past_len + seq], 1i32);
+        let position_ids = NdTensor::from_fn([1, seq], |[_, i]| (past_len + i) as i32);
+
+        let mut inputs: Vec<(usize, InputOrOutput)> = vec![
+            (
+                model.find_node("input_ids").unwrap(),
+                input_ids.view().into(),
+            ),
+            (
+                model.find_node("attention_mask").unwrap(),
+                attention_mask.view().into(),
+            ),
+            (
+                model.find_node("position_ids").unwrap(),
+                position_ids.view().into(),
+            ),
+        ];
+        let mut outputs = vec![model.find_node("logits").unwrap()];
+        for layer in 0..N_LAYERS {
+            for kind in ["key", "value"] {
+                outputs.push(
+                    model
+                        .find_node(&format!("present.{}.{}", layer, kind))
+                        .unwrap(),
+                );
+            }
+        }
+
+        let empty_cache: Vec<Output> = (0..N_LAYERS * 2)
+            .map(|_| {
+                NdTensor::<f32, 4>::zeros([1, N_KV_HEADS, 0, HEAD_DIM])
+                    .into_dyn()
+                    .into()
+            })
+            .collect();
+        let kv_cache = if kv_cache.is_empty() {
+            empty_cache
+        } else {
+            kv_cache
+        };
+        for layer in 0..N_LAYERS {
+            for (i, kind) in ["key", "value"].iter().enumerate() {
+                let id = model
+                    .find_node(&format!("past_key_values.{}.{}", layer, kind))
+                    .unwrap();
+                inputs.push((id, (&kv_cache[layer * 2 + i]).into()));
+            }
+        }
+
+        let mut results = model.run(inputs, &outputs, None).unwrap();
+        let logits: NdTensor<f32, 3> = results.remove(0).try_into().unwrap();
+        (logits, results)
+    }
+
+    fn assert_close(actual: &[f32], expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-4, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_config_from_gguf() {
+        let gguf = build_gguf(&random_weights());
+        let config = LlamaConfig::from_gguf(&gguf).unwrap();
+        assert_eq!(
+            config,
+            LlamaConfig {
+                n_layers: N_LAYERS,
+                n_embd: N_EMBD,
+                n_heads: N_HEADS,
+                n_kv_heads: N_KV_HEADS,
+                rms_norm_eps: EPS,
+                rope_freq_base: ROPE_BASE,
+                rope_scale: 1.,
+            }
+        );
+    }
+
+    #[test]
+    fn test_load_llama() {
+        let weights = random_weights();
+        let gguf = build_gguf(&weights);
+        let model = load_llama(&gguf).unwrap();
+
+        let tokens = [1, 5, 2, 9, 3];
+        let expected = reference_forward(&weights, &tokens);
+
+        // Process the whole sequence at once.
+        let (logits, _) = run_model(&model, &tokens, 0, Vec::new());
+        assert_eq!(logits.shape(), [1, tokens.len(), N_VOCAB]);
+        for (pos, expected) in expected.iter().enumerate() {
+            assert_close(&logits.slice::<1, _>((0, pos)).to_vec(), expected);
+        }
+
+        // Process the sequence incrementally using the KV cache.
+        let (logits, kv_cache) = run_model(&model, &tokens[..3], 0, Vec::new());
+        assert_close(&logits.slice::<1, _>((0, 2)).to_vec(), &expected[2]);
+        let (logits, kv_cache) = run_model(&model, &tokens[3..4], 3, kv_cache);
+        assert_close(&logits.slice::<1, _>((0, 0)).to_vec(), &expected[3]);
+        let (logits, _) = run_model(&model, &tokens[4..], 4, kv_cache);
+        assert_close(&logits.slice::<1, _>((0, 0)).to_vec(), &expected[4]);
+    }
+
+    #[test]
+    fn test_load_invalid_head_count() {
+        let mut builder = GgufBuilder::default();
+        builder.add_metadata(
+            "general.architecture",
+            MetadataValue::String("llama".into()),
+        );
+        builder.add_metadata("llama.block_count", MetadataValue::U32(1));
+        builder.add_metadata("llama.embedding_length", MetadataValue::U32(8));
+        builder.add_metadata("llama.attention.head_count", MetadataValue::U32(3));
+        let gguf = GgufFile::parse(builder.finish()).unwrap();
+        let err = LlamaConfig::from_gguf(&gguf).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "invalid metadata: head counts 3 and 3 are incompatible with embedding size 8"
+        );
+    }
+
+    #[test]
+    fn test_load_shape_mismatch() {
+        let mut weights = random_weights();
+        weights.insert(
+            "token_embd.weight".into(),
+            Tensor::zeros(&[N_VOCAB, N_EMBD + 1]),
+        );
+        let gguf = build_gguf(&weights);
+        let err = load_llama(&gguf).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "tensor \"token_embd.weight\" has unexpected shape [{}, {}]",
+                N_VOCAB,
+                N_EMBD + 1
+            )
+        );
+    }
+
+    #[test]
+    fn test_load_unsupported_architecture() {
+        let mut builder = GgufBuilder::default();
+        builder.add_metadata("general.architecture", MetadataValue::String("gpt2".into()));
+        let gguf = GgufFile::parse(builder.finish()).unwrap();
+        let err = load_llama(&gguf).err().unwrap();
+        assert_eq!(err.to_string(), "unsupported model architecture \"gpt2\"");
+    }
+}
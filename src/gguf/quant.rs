@@ -0,0 +1,308 @@
+//! Dequantization of GGML tensor data types.
+//!
+//! The block layouts follow the reference implementations in `ggml-quants.c`
+//! from the ggml project.
+
+use rten_vecmath::f16_to_f32;
+
+use super::GgufError;
+
+/// Number of elements in a "K-quant" super-block.
+const QK_K: usize = 256;
+
+/// Data types of tensors in GGUF files which can be converted to `f32`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TensorType {
+    F32,
+    F16,
+    BF16,
+    Q4_0,
+    Q4_1,
+    Q8_0,
+    Q4K,
+    Q6K,
+}
+
+impl TensorType {
+    /// Return the tensor type corresponding to a GGML type ID.
+    pub fn from_id(id: u32) -> Result<TensorType, GgufError> {
+        let dtype = match id {
+            0 => TensorType::F32,
+            1 => TensorType::F16,
+            2 => TensorType::Q4_0,
+            3 => TensorType::Q4_1,
+            8 => TensorType::Q8_0,
+            12 => TensorType::Q4K,
+            14 => TensorType::Q6K,
+            30 => TensorType::BF16,
+            _ => {
+                return Err(GgufError::UnsupportedType(format!(
+                    "with GGML type ID {}",
+                    id
+                )))
+            }
+        };
+        Ok(dtype)
+    }
+
+    /// Return the number of elements in each block and the size of each
+    /// block in bytes.
+    pub fn block_layout(self) -> (usize, usize) {
+        match self {
+            TensorType::F32 => (1, 4),
+            TensorType::F16 | TensorType::BF16 => (1, 2),
+            // f16 scale, 16 bytes of 4-bit values.
+            TensorType::Q4_0 => (32, 2 + 16),
+            // f16 scale and min, 16 bytes of 4-bit values.
+            TensorType::Q4_1 => (32, 2 + 2 + 16),
+            // f16 scale, 32 bytes of 8-bit values.
+            TensorType::Q8_0 => (32, 2 + 32),
+            // f16 scale and min, 12 bytes of 6-bit sub-block scales and mins,
+            // 128 bytes of 4-bit values.
+            TensorType::Q4K => (QK_K, 2 + 2 + 12 + QK_K / 2),
+            // 128 bytes of low 4 bits, 64 bytes of high 2 bits, 16 sub-block
+            // scales, f16 scale.
+            TensorType::Q6K => (QK_K, QK_K / 2 + QK_K / 4 + QK_K / 16 + 2),
+        }
+    }
+
+    /// Convert the raw data for a tensor to `f32`.
+    ///
+    /// `data` must contain a whole number of blocks.
+    pub fn dequantize(self, data: &[u8]) -> Vec<f32> {
+        let (block_len, block_size) = self.block_layout();
+        let mut out = Vec::with_capacity(data.len() / block_size * block_len);
+        for block in data.chunks_exact(block_size) {
+            match self {
+                TensorType::F32 => out.push(f32::from_le_bytes(block.try_into().unwrap())),
+                TensorType::F16 => out.push(f16_to_f32(read_u16(block, 0))),
+                TensorType::BF16 => out.push(f32::from_bits((read_u16(block, 0) as u32) << 16)),
+                TensorType::Q4_0 => dequantize_q4_0(block, &mut out),
+                TensorType::Q4_1 => dequantize_q4_1(block, &mut out),
+                TensorType::Q8_0 => dequantize_q8_0(block, &mut out),
+                TensorType::Q4K => dequantize_q4_k(block, &mut out),
+                TensorType::Q6K => dequantize_q6_k(block, &mut out),
+            }
+        }
+        out
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_f16(data: &[u8], offset: usize) -> f32 {
+    f16_to_f32(read_u16(data, offset))
+}
+
+fn dequantize_q4_0(block: &[u8], out: &mut Vec<f32>) {
+    let d = read_f16(block, 0);
+    let qs = &block[2..];
+    out.extend(qs.iter().map(|q| ((q & 0xF) as i32 - 8) as f32 * d));
+    out.extend(qs.iter().map(|q| ((q >> 4) as i32 - 8) as f32 * d));
+}
+
+fn dequantize_q4_1(block: &[u8], out: &mut Vec<f32>) {
+    let d = read_f16(block, 0);
+    let m = read_f16(block, 2);
+    let qs = &block[4..];
+    out.extend(qs.iter().map(|q| (q & 0xF) as f32 * d + m));
+    out.extend(qs.iter().map(|q| (q >> 4) as f32 * d + m));
+}
+
+fn dequantize_q8_0(block: &[u8], out: &mut Vec<f32>) {
+    let d = read_f16(block, 0);
+    out.extend(block[2..].iter().map(|&q| q as i8 as f32 * d));
+}
+
+/// Extract the 6-bit scale and min for sub-block `j` of a Q4_K block.
+fn q4_k_scale_min(j: usize, scales: &[u8]) -> (u8, u8) {
+    if j < 4 {
+        (scales[j] & 63, scales[j + 4] & 63)
+    } else {
+        (
+            (scales[j + 4] & 0xF) | ((scales[j - 4] >> 6) << 4),
+            (scales[j + 4] >> 4) | ((scales[j] >> 6) << 4),
+        )
+    }
+}
+
+fn dequantize_q4_k(block: &[u8], out: &mut Vec<f32>) {
+    let d = read_f16(block, 0);
+    let dmin = read_f16(block, 2);
+    let scales = &block[4..16];
+    let qs = &block[16..];
+
+    // Each 32-byte chunk of `qs` holds two sub-blocks of 32 values, in the
+    // low and high nibbles respectively.
+    for (i, q) in qs.chunks_exact(32).enumerate() {
+        let (sc, m) = q4_k_scale_min(2 * i, scales);
+        let (d1, m1) = (d * sc as f32, dmin * m as f32);
+        out.extend(q.iter().map(|q| d1 * (q & 0xF) as f32 - m1));
+
+        let (sc, m) = q4_k_scale_min(2 * i + 1, scales);
+        let (d2, m2) = (d * sc as f32, dmin * m as f32);
+        out.extend(q.iter().map(|q| d2 * (q >> 4) as f32 - m2));
+    }
+}
+
+fn dequantize_q6_k(block: &[u8], out: &mut Vec<f32>) {
+    let ql = &block[..QK_K / 2];
+    let qh = &block[QK_K / 2..QK_K / 2 + QK_K / 4];
+    let scales = &block[QK_K / 2 + QK_K / 4..QK_K / 2 + QK_K / 4 + QK_K / 16];
+    let d = read_f16(block, QK_K / 2 + QK_K / 4 + QK_K / 16);
+
+    // Values are processed in two halves of 128, each of which uses 64 bytes
+    // of `ql`, 32 bytes of `qh` and 8 scales.
+    for half in 0..2 {
+        let ql = &ql[half * 64..];
+        let qh = &qh[half * 32..];
+        let sc = &scales[half * 8..];
+
+        let start = out.len();
+        out.resize(start + 128, 0.);
+        let y = &mut out[start..];
+
+        for l in 0..32 {
+            let is = l / 16;
+            let q1 = ((ql[l] & 0xF) | ((qh[l] & 3) << 4)) as i32 - 32;
+            let q2 = ((ql[l + 32] & 0xF) | (((qh[l] >> 2) & 3) << 4)) as i32 - 32;
+            let q3 = ((ql[l] >> 4) | (((qh[l] >> 4) & 3) << 4)) as i32 - 32;
+            let q4 = ((ql[l + 32] >> 4) | (((qh[l] >> 6) & 3) << 4)) as i32 - 32;
+            y[l] = d * (sc[is] as i8) as f32 * q1 as f32;
+            y[l + 32] = d * (sc[is + 2] as i8) as f32 * q2 as f32;
+            y[l + 64] = d * (sc[is + 4] as i8) as f32 * q3 as f32;
+            y[l + 96] = d * (sc[is + 6] as i8) as f32 * q4 as f32;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rten_vecmath::f32_to_f16;
+
+    use super::{TensorType, QK_K};
+
+    #[test]
+    fn test_dequantize_float() {
+        let data: Vec<u8> = [1.5f32, -2.].iter().flat_map(|x| x.to_le_bytes()).collect();
+        assert_eq!(TensorType::F32.dequantize(&data), [1.5, -2.]);
+
+        let data: Vec<u8> = [1.5f32, -2.]
+            .iter()
+            .flat_map(|&x| f32_to_f16(x).to_le_bytes())
+            .collect();
+        assert_eq!(TensorType::F16.dequantize(&data), [1.5, -2.]);
+
+        let data: Vec<u8> = [1.5f32, -2.]
+            .iter()
+            .flat_map(|x| ((x.to_bits() >> 16) as u16).to_le_bytes())
+            .collect();
+        assert_eq!(TensorType::BF16.dequantize(&data), [1.5, -2.]);
+    }
+
+    #[test]
+    fn test_dequantize_q4_0() {
+        // Values `q - 8` for q in 0..16, then reversed.
+        let mut block = f32_to_f16(0.5).to_le_bytes().to_vec();
+        block.extend((0..16u8).map(|i| i | ((15 - i) << 4)));
+
+        let expected: Vec<f32> = (0..16)
+            .map(|q| (q - 8) as f32 * 0.5)
+            .chain((0..16).map(|q| (7 - q) as f32 * 0.5))
+            .collect();
+        assert_eq!(TensorType::Q4_0.dequantize(&block), expected);
+    }
+
+    #[test]
+    fn test_dequantize_q4_1() {
+        let mut block = f32_to_f16(0.5).to_le_bytes().to_vec();
+        block.extend(f32_to_f16(-1.).to_le_bytes());
+        block.extend((0..16u8).map(|i| i | (i << 4)));
+
+        let expected: Vec<f32> = (0..32).map(|q| (q % 16) as f32 * 0.5 - 1.).collect();
+        assert_eq!(TensorType::Q4_1.dequantize(&block), expected);
+    }
+
+    #[test]
+    fn test_dequantize_q8_0() {
+        let mut block = f32_to_f16(0.25).to_le_bytes().to_vec();
+        block.extend((0..32i8).map(|i| (i - 16) as u8));
+
+        let expected: Vec<f32> = (0..32).map(|q| (q - 16) as f32 * 0.25).collect();
+        assert_eq!(TensorType::Q8_0.dequantize(&block), expected);
+    }
+
+    #[test]
+    fn test_dequantize_q4_k() {
+        let mut block = f32_to_f16(2.).to_le_bytes().to_vec();
+        block.extend(f32_to_f16(0.5).to_le_bytes());
+
+        // Sub-block `j` has scale `j + 1` and min `j`. The first four use the
+        // low 6 bits of bytes 0..8. The last four use nibbles of bytes 8..12,
+        // with the high 2 bits stored in the top bits of bytes 0..8 (which
+        // are zero here since all values are < 16).
+        let mut scales = [0u8; 12];
+        for j in 0..4 {
+            scales[j] = j as u8 + 1;
+            scales[j + 4] = j as u8;
+        }
+        for j in 4..8 {
+            scales[j + 4] = (j as u8 + 1) | ((j as u8) << 4);
+        }
+        block.extend(scales);
+        block.extend((0..QK_K / 2).map(|i| (i % 16) as u8 | (((i + 1) % 16) << 4) as u8));
+
+        let values = TensorType::Q4K.dequantize(&block);
+        assert_eq!(values.len(), QK_K);
+        for (i, &x) in values.iter().enumerate() {
+            let sub_block = i / 32;
+            let l = i % 32;
+            let byte = (sub_block / 2) * 32 + l;
+            let q = if sub_block % 2 == 0 {
+                byte % 16
+            } else {
+                (byte + 1) % 16
+            };
+            let expected = 2. * (sub_block + 1) as f32 * q as f32 - 0.5 * sub_block as f32;
+            assert_eq!(x, expected, "mismatch at {}", i);
+        }
+    }
+
+    #[test]
+    fn test_dequantize_q6_k() {
+        // Use 6-bit values which are `i % 64` for output index `i`, stored
+        // across `ql` (low 4 bits) and `qh` (high 2 bits).
+        let mut ql = [0u8; QK_K / 2];
+        let mut qh = [0u8; QK_K / 4];
+        for i in 0..QK_K {
+            let q = (i % 64) as u8;
+            let half = i / 128;
+            let j = i % 128;
+            let l = j % 32;
+            let group = j / 32;
+            let ql_index = half * 64 + l + if group % 2 == 1 { 32 } else { 0 };
+            if group < 2 {
+                ql[ql_index] |= q & 0xF;
+            } else {
+                ql[ql_index] |= (q & 0xF) << 4;
+            }
+            qh[half * 32 + l] |= (q >> 4) << (2 * group);
+        }
+        let scales: Vec<u8> = (0..16).map(|i| (i as i8 - 8) as u8).collect();
+
+        let mut block = ql.to_vec();
+        block.extend(qh);
+        block.extend(&scales);
+        block.extend(f32_to_f16(0.5).to_le_bytes());
+
+        let values = TensorType::Q6K.dequantize(&block);
+        assert_eq!(values.len(), QK_K);
+        for (i, &x) in values.iter().enumerate() {
+            let scale = (i / 16) as i32 - 8;
+            let q = (i % 64) as i32 - 32;
+            assert_eq!(x, 0.5 * scale as f32 * q as f32, "mismatch at {}", i);
+        }
+    }
+}
@@ -125,6 +125,11 @@ impl<T> ConstantNode<T> {
         }
     }
 
+    /// Replace the data for this constant.
+    pub fn set_data(&mut self, data: impl Into<ConstantNodeData<T>>) {
+        self.data = data.into();
+    }
+
     fn layout(&self) -> &DynLayout {
         match &self.data {
             ConstantNodeData::Owned(data) => data.layout(),
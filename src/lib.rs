@@ -96,7 +96,6 @@ use rten_tensor::{NdTensor, Tensor};
 mod constant_storage;
 mod downcast;
 mod env;
-mod gemm;
 mod graph;
 mod header;
 mod iter_util;
@@ -111,15 +110,19 @@ mod threading;
 mod timing;
 
 #[cfg(feature = "wasm_api")]
-mod wasm_api;
+pub mod wasm_api;
 
 // Temporarily included in this crate. These functions should be moved into
 // a separate crate in future.
 pub mod ctc;
 
+pub mod gemm;
+pub mod gguf;
+pub mod lora;
 pub mod ops;
 
 pub use graph::{Dimension, NodeId, RunError, RunOptions};
+pub use lora::{LoraAdapter, LoraError};
 pub use model::{Model, ModelLoadError, ModelOptions, NodeInfo};
 pub use model_metadata::ModelMetadata;
 pub use op_registry::{OpRegistry, ReadOp, ReadOpError};
@@ -0,0 +1,411 @@
+//! Support for applying LoRA adapters to model weights.
+//!
+//! [LoRA](https://arxiv.org/abs/2106.09685) fine-tunes a model by learning a
+//! low-rank update `B @ A` for selected weight matrices, where `A` has shape
+//! `(rank, in_features)` and `B` has shape `(out_features, rank)`. Adapters
+//! are usually distributed as safetensors files containing only these
+//! factors.
+//!
+//! A [`LoraAdapter`] is applied to a model by passing it to
+//! [`ModelOptions::add_lora`](crate::ModelOptions::add_lora). The update is
+//! merged into the base model's weights at load time, so inference runs at
+//! the same speed as the base model.
+
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+
+use rten_tensor::prelude::*;
+use rten_tensor::Tensor;
+
+use crate::downcast::DowncastDyn;
+use crate::graph::{Constant, Graph, Node, NodeId};
+use crate::ops::{matmul, Gemm};
+use crate::tensor_pool::TensorPool;
+
+mod safetensors;
+
+use safetensors::SafeTensors;
+
+/// Errors that occur when loading or applying a LoRA adapter.
+#[derive(Debug)]
+pub enum LoraError {
+    /// An error occurred reading the adapter file.
+    ReadFailed(std::io::Error),
+
+    /// The adapter file is not a valid safetensors file.
+    InvalidFile(String),
+
+    /// One of the pair of low-rank factors for a weight is missing.
+    MissingTensor(String),
+
+    /// The model's main graph does not contain a float constant with the
+    /// name of a weight that the adapter modifies.
+    TargetNotFound(String),
+
+    /// The shape of the adapter's update does not match the weight.
+    ShapeMismatch(String),
+}
+
+impl fmt::Display for LoraError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoraError::ReadFailed(err) => write!(f, "read error: {}", err),
+            LoraError::InvalidFile(err) => write!(f, "invalid adapter file: {}", err),
+            LoraError::MissingTensor(name) => write!(f, "missing adapter tensor \"{}\"", name),
+            LoraError::TargetNotFound(name) => {
+                write!(f, "model does not contain weight \"{}\"", name)
+            }
+            LoraError::ShapeMismatch(name) => {
+                write!(f, "adapter shape does not match weight \"{}\"", name)
+            }
+        }
+    }
+}
+
+impl Error for LoraError {}
+
+/// Low-rank update for a single weight matrix.
+struct LoraLayer {
+    /// Name of the adapter layer, without the `lora_A` / `lora_B` suffix.
+    name: String,
+
+    /// Name of the weight in the base model.
+    target: String,
+
+    /// Down-projection with shape `(rank, in_features)`.
+    a: Tensor<f32>,
+
+    /// Up-projection with shape `(out_features, rank)`.
+    b: Tensor<f32>,
+
+    /// Per-layer scaling numerator, if stored in the adapter file.
+    alpha: Option<f32>,
+}
+
+/// Pairs of suffixes used to name the low-rank factors in adapter files.
+///
+/// The first pair is used by Hugging Face PEFT. The second is used by
+/// kohya-ss and other Stable Diffusion tooling.
+const FACTOR_NAMES: [(&str, &str); 2] = [(".lora_A", ".lora_B"), (".lora_down", ".lora_up")];
+
+/// A LoRA adapter loaded from a safetensors file.
+///
+/// The weights which the adapter modifies are identified by name. By
+/// default the name of each weight is derived from the adapter's tensor
+/// names by removing the `base_model.model.` prefix and `lora_A` / `lora_B`
+/// suffixes added by PEFT, so that eg. the update stored in
+/// `base_model.model.model.layers.0.self_attn.q_proj.lora_{A, B}.weight` is
+/// applied to the constant `model.layers.0.self_attn.q_proj.weight`. Use
+/// [`with_target`](LoraAdapter::with_target) for models where weights have
+/// different names.
+///
+/// Only weights in the model's main graph can be modified. Weights inside the
+/// subgraphs of control flow operators such as `If` and `Loop` are not
+/// searched, and targeting them fails with [`LoraError::TargetNotFound`].
+pub struct LoraAdapter {
+    layers: Vec<LoraLayer>,
+    alpha: Option<f32>,
+}
+
+impl LoraAdapter {
+    /// Load an adapter from a `.safetensors` file.
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<LoraAdapter, LoraError> {
+        let data = std::fs::read(path).map_err(LoraError::ReadFailed)?;
+        Self::load(data)
+    }
+
+    /// Load an adapter from the contents of a `.safetensors` file.
+    pub fn load(data: Vec<u8>) -> Result<LoraAdapter, LoraError> {
+        let tensors = SafeTensors::parse(data).map_err(LoraError::InvalidFile)?;
+
+        let mut names: Vec<&str> = tensors.names().collect();
+        names.sort();
+
+        let mut layers = Vec::new();
+        for name in names {
+            let Some((a_suffix, b_suffix, pos)) = FACTOR_NAMES
+                .iter()
+                .find_map(|&(a, b)| name.find(a).map(|pos| (a, b, pos)))
+            else {
+                continue;
+            };
+
+            // PEFT may insert the adapter name before the `.weight` suffix,
+            // eg. `lora_A.default.weight`.
+            let prefix = &name[..pos];
+            let b_name = name.replacen(a_suffix, b_suffix, 1);
+            let a = tensors.tensor(name).unwrap();
+            let b = tensors
+                .tensor(&b_name)
+                .ok_or_else(|| LoraError::MissingTensor(b_name.clone()))?;
+
+            if a.ndim() != 2 || b.ndim() != 2 || a.size(0) != b.size(1) {
+                return Err(LoraError::ShapeMismatch(prefix.to_string()));
+            }
+
+            let alpha = tensors
+                .tensor(&format!("{}.alpha", prefix))
+                .and_then(|alpha| alpha.iter().next().copied());
+            let target = format!(
+                "{}.weight",
+                prefix.strip_prefix("base_model.model.").unwrap_or(prefix)
+            );
+
+            layers.push(LoraLayer {
+                name: prefix.to_string(),
+                target,
+                a,
+                b,
+                alpha,
+            });
+        }
+
+        // Check for B factors without a corresponding A factor.
+        for name in tensors.names() {
+            for (a_suffix, b_suffix) in FACTOR_NAMES {
+                if name.contains(b_suffix) {
+                    let a_name = name.replacen(b_suffix, a_suffix, 1);
+                    if !tensors.names().any(|name| name == a_name) {
+                        return Err(LoraError::MissingTensor(a_name));
+                    }
+                }
+            }
+        }
+
+        Ok(LoraAdapter {
+            layers,
+            alpha: None,
+        })
+    }
+
+    /// Set the scaling numerator used for layers which don't specify one.
+    ///
+    /// The update for each weight is scaled by `alpha / rank`. PEFT stores
+    /// alpha as `lora_alpha` in `adapter_config.json` rather than in the
+    /// weights file, so it needs to be passed here. If not set, alpha defaults
+    /// to the rank, giving a scale of 1.
+    pub fn with_alpha(mut self, alpha: f32) -> Self {
+        self.alpha = Some(alpha);
+        self
+    }
+
+    /// Set the name of the model weight that an adapter layer is applied to.
+    ///
+    /// `layer` is the name of the adapter layer, as returned by
+    /// [`layers`](LoraAdapter::layers).
+    pub fn with_target(mut self, layer: &str, target: &str) -> Self {
+        if let Some(layer) = self.layers.iter_mut().find(|l| l.name == layer) {
+            layer.target = target.to_string();
+        }
+        self
+    }
+
+    /// Return an iterator over `(layer_name, target_weight_name)` pairs for
+    /// the weights modified by this adapter.
+    pub fn layers(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.layers
+            .iter()
+            .map(|layer| (layer.name.as_str(), layer.target.as_str()))
+    }
+
+    /// Add the adapter's updates to the weights in `graph`.
+    pub(crate) fn merge(&self, graph: &mut Graph) -> Result<(), LoraError> {
+        let pool = TensorPool::new();
+
+        for layer in &self.layers {
+            let target_not_found = || LoraError::TargetNotFound(layer.target.clone());
+            let shape_mismatch = || LoraError::ShapeMismatch(layer.target.clone());
+
+            let id = graph
+                .get_node_id(&layer.target)
+                .ok_or_else(target_not_found)?;
+            let transposed = weight_is_transposed(graph, id);
+            let Some(Node::Constant(Constant::Float(weight))) = graph.get_node_mut(id) else {
+                return Err(target_not_found());
+            };
+
+            let rank = layer.a.size(0);
+            let scale = layer.alpha.or(self.alpha).unwrap_or(rank as f32) / rank as f32;
+            let (out_features, in_features) = (layer.b.size(0), layer.a.size(1));
+
+            // Determine whether the weight is stored as `(out, in)`, as in
+            // PyTorch, or `(in, out)`, as used by ONNX `MatMul`.
+            let shape = weight.view().shape().to_vec();
+            let transposed = match transposed {
+                Some(transposed) => transposed,
+                None => shape != [out_features, in_features],
+            };
+            let expected_shape = if transposed {
+                [in_features, out_features]
+            } else {
+                [out_features, in_features]
+            };
+            if shape != expected_shape {
+                return Err(shape_mismatch());
+            }
+
+            let delta =
+                matmul(&pool, layer.b.view(), layer.a.view()).map_err(|_| shape_mismatch())?;
+            let delta = if transposed {
+                delta.transposed()
+            } else {
+                delta.view()
+            };
+
+            let mut merged = weight.view().to_tensor();
+            for (w, d) in merged.iter_mut().zip(delta.iter()) {
+                *w += scale * d;
+            }
+            weight.set_data(merged);
+        }
+
+        Ok(())
+    }
+}
+
+/// Determine whether a weight with ID `id` is used in `(in_features,
+/// out_features)` order, by examining the operators that use it.
+///
+/// Returns `None` if this cannot be determined from the graph.
+fn weight_is_transposed(graph: &Graph, id: NodeId) -> Option<bool> {
+    graph.iter().find_map(|(_, node)| {
+        let Node::Operator(op) = node else {
+            return None;
+        };
+        if op.input_ids().get(1) != Some(&Some(id)) {
+            return None;
+        }
+        match op.operator().name() {
+            "MatMul" => Some(true),
+            "Gemm" => op
+                .operator()
+                .downcast_ref::<Gemm>()
+                .map(|gemm| !gemm.transpose_b),
+            _ => None,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rten_tensor::prelude::*;
+    use rten_tensor::rng::XorShiftRng;
+    use rten_tensor::test_util::expect_equal;
+    use rten_tensor::Tensor;
+
+    use super::safetensors::tests::write_safetensors;
+    use super::{LoraAdapter, LoraError};
+    use crate::graph::{Graph, Node, TypedConstant};
+    use crate::ops::{Gemm, MatMul};
+
+    /// Compute the expected merged weight in `(out, in)` order.
+    fn merged_weight(
+        weight: &Tensor<f32>,
+        a: &Tensor<f32>,
+        b: &Tensor<f32>,
+        scale: f32,
+    ) -> Tensor<f32> {
+        let mut merged = weight.clone();
+        let rank = a.size(0);
+        for i in 0..merged.size(0) {
+            for j in 0..merged.size(1) {
+                let delta: f32 = (0..rank).map(|k| b[[i, k]] * a[[k, j]]).sum();
+                merged[[i, j]] += scale * delta;
+            }
+        }
+        merged
+    }
+
+    #[test]
+    fn test_load_adapter() {
+        let mut rng = XorShiftRng::new(1234);
+        let a = Tensor::<f32>::rand(&[2, 4], &mut rng);
+        let b = Tensor::<f32>::rand(&[3, 2], &mut rng);
+        let buf = write_safetensors(&[
+            ("base_model.model.layers.0.q_proj.lora_A.weight", &a),
+            ("base_model.model.layers.0.q_proj.lora_B.weight", &b),
+            ("unet.down.lora_down.weight", &a),
+            ("unet.down.lora_up.weight", &b),
+        ]);
+        let adapter = LoraAdapter::load(buf)
+            .unwrap()
+            .with_target("unet.down", "down_proj");
+        let layers: Vec<_> = adapter.layers().collect();
+        assert_eq!(
+            layers,
+            [
+                ("base_model.model.layers.0.q_proj", "layers.0.q_proj.weight"),
+                ("unet.down", "down_proj")
+            ]
+        );
+
+        let buf = write_safetensors(&[("layers.0.q_proj.lora_B.weight", &b)]);
+        let err = LoraAdapter::load(buf).err().unwrap();
+        assert!(
+            matches!(err, LoraError::MissingTensor(name) if name == "layers.0.q_proj.lora_A.weight")
+        );
+    }
+
+    #[test]
+    fn test_merge_adapter() {
+        let mut rng = XorShiftRng::new(1234);
+        let (in_features, out_features, rank) = (4, 4, 2);
+        let weight = Tensor::<f32>::rand(&[out_features, in_features], &mut rng);
+        let a = Tensor::<f32>::rand(&[rank, in_features], &mut rng);
+        let b = Tensor::<f32>::rand(&[out_features, rank], &mut rng);
+        let alpha = 8.;
+        let expected = merged_weight(&weight, &a, &b, alpha / rank as f32);
+
+        let buf = write_safetensors(&[
+            ("base_model.model.gemm_proj.lora_A.weight", &a),
+            ("base_model.model.gemm_proj.lora_B.weight", &b),
+            ("base_model.model.matmul_proj.lora_A.weight", &a),
+            ("base_model.model.matmul_proj.lora_B.weight", &b),
+        ]);
+        let adapter = LoraAdapter::load(buf).unwrap().with_alpha(alpha);
+
+        // Create a graph which uses one copy of the weight with `Gemm` in
+        // `(out, in)` order, and one with `MatMul` in `(in, out)` order.
+        let mut graph = Graph::new();
+        let input = graph.add_value(Some("input"), None);
+        let gemm_weight = graph.add_constant(Some("gemm_proj.weight"), weight.clone());
+        graph.add_simple_op(
+            "gemm",
+            Gemm {
+                alpha: 1.,
+                beta: 1.,
+                transpose_a: false,
+                transpose_b: true,
+            },
+            &[input, gemm_weight],
+        );
+        let matmul_weight =
+            graph.add_constant(Some("matmul_proj.weight"), weight.transposed().to_tensor());
+        graph.add_simple_op("matmul", MatMul {}, &[input, matmul_weight]);
+
+        adapter.merge(&mut graph).unwrap();
+
+        let merged = |id| -> Tensor<f32> {
+            let Some(Node::Constant(constant)) = graph.get_node(id) else {
+                panic!("expected constant");
+            };
+            constant.as_view().unwrap().to_tensor()
+        };
+        expect_equal(&merged(gemm_weight), &expected).unwrap();
+        expect_equal(&merged(matmul_weight), &expected.transposed().to_tensor()).unwrap();
+
+        // Adapters which modify weights that the model doesn't have should
+        // fail.
+        let buf = write_safetensors(&[
+            ("missing_proj.lora_A.weight", &a),
+            ("missing_proj.lora_B.weight", &b),
+        ]);
+        let adapter = LoraAdapter::load(buf).unwrap();
+        let err = adapter.merge(&mut graph).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "model does not contain weight \"missing_proj.weight\""
+        );
+    }
+}
@@ -0,0 +1,443 @@
+//! Minimal reader for the [safetensors](https://github.com/huggingface/safetensors)
+//! format.
+
+use std::collections::HashMap;
+
+use rten_tensor::Tensor;
+use rten_vecmath::f16_to_f32;
+
+/// Subset of JSON values which can appear in a safetensors header.
+#[derive(Debug, PartialEq)]
+enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    fn get(&self, key: &str) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Object(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn as_usize(&self) -> Option<usize> {
+        match *self {
+            JsonValue::Number(n) if n >= 0. && n.fract() == 0. => Some(n as usize),
+            _ => None,
+        }
+    }
+}
+
+/// Maximum nesting depth of arrays and objects in the JSON header.
+///
+/// Safetensors headers are only two levels deep, so this is a generous limit
+/// which prevents malicious inputs from overflowing the stack.
+const MAX_JSON_DEPTH: usize = 32;
+
+/// Recursive-descent parser for the JSON header.
+struct JsonParser<'a> {
+    input: &'a [u8],
+    pos: usize,
+
+    /// Number of arrays and objects enclosing the current position.
+    depth: usize,
+}
+
+impl<'a> JsonParser<'a> {
+    fn new(input: &'a [u8]) -> Self {
+        JsonParser {
+            input,
+            pos: 0,
+            depth: 0,
+        }
+    }
+
+    /// Parse a nested array or object using `parse`.
+    fn nested(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<JsonValue, String>,
+    ) -> Result<JsonValue, String> {
+        if self.depth >= MAX_JSON_DEPTH {
+            return Err(format!("header nesting is too deep at offset {}", self.pos));
+        }
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .input
+            .get(self.pos)
+            .is_some_and(|c| c.is_ascii_whitespace())
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.input.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), String> {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(format!("expected '{}' at offset {}", c as char, self.pos))
+        }
+    }
+
+    fn keyword(&mut self, word: &str, value: JsonValue) -> Result<JsonValue, String> {
+        if self.input[self.pos..].starts_with(word.as_bytes()) {
+            self.pos += word.len();
+            Ok(value)
+        } else {
+            Err(format!("invalid token at offset {}", self.pos))
+        }
+    }
+
+    fn value(&mut self) -> Result<JsonValue, String> {
+        match self.peek() {
+            Some(b'{') => self.nested(Self::object),
+            Some(b'[') => self.nested(Self::array),
+            Some(b'"') => self.string().map(JsonValue::String),
+            Some(b't') => self.keyword("true", JsonValue::Bool(true)),
+            Some(b'f') => self.keyword("false", JsonValue::Bool(false)),
+            Some(b'n') => self.keyword("null", JsonValue::Null),
+            Some(_) => self.number(),
+            None => Err("unexpected end of header".into()),
+        }
+    }
+
+    fn object(&mut self) -> Result<JsonValue, String> {
+        self.expect(b'{')?;
+        let mut entries = Vec::new();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(JsonValue::Object(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(b':')?;
+            entries.push((key, self.value()?));
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                _ => break,
+            }
+        }
+        self.expect(b'}')?;
+        Ok(JsonValue::Object(entries))
+    }
+
+    fn array(&mut self) -> Result<JsonValue, String> {
+        self.expect(b'[')?;
+        let mut items = Vec::new();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(JsonValue::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                _ => break,
+            }
+        }
+        self.expect(b']')?;
+        Ok(JsonValue::Array(items))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect(b'"')?;
+        let mut bytes = Vec::new();
+        loop {
+            let c = *self
+                .input
+                .get(self.pos)
+                .ok_or("unterminated string in header")?;
+            self.pos += 1;
+            match c {
+                b'"' => break,
+                b'\\' => {
+                    let escaped = *self
+                        .input
+                        .get(self.pos)
+                        .ok_or("unterminated string in header")?;
+                    self.pos += 1;
+                    match escaped {
+                        b'n' => bytes.push(b'\n'),
+                        b't' => bytes.push(b'\t'),
+                        b'r' => bytes.push(b'\r'),
+                        b'b' => bytes.push(0x08),
+                        b'f' => bytes.push(0x0c),
+                        b'u' => {
+                            let hex = self
+                                .input
+                                .get(self.pos..self.pos + 4)
+                                .and_then(|hex| std::str::from_utf8(hex).ok())
+                                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                                .ok_or("invalid unicode escape in header")?;
+                            self.pos += 4;
+                            let ch = char::from_u32(hex).unwrap_or(char::REPLACEMENT_CHARACTER);
+                            let mut buf = [0; 4];
+                            bytes.extend(ch.encode_utf8(&mut buf).as_bytes());
+                        }
+                        other => bytes.push(other),
+                    }
+                }
+                _ => bytes.push(c),
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| "header is not valid UTF-8".into())
+    }
+
+    fn number(&mut self) -> Result<JsonValue, String> {
+        let start = self.pos;
+        while self
+            .input
+            .get(self.pos)
+            .is_some_and(|c| c.is_ascii_digit() || b"+-.eE".contains(c))
+        {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.input[start..self.pos])
+            .ok()
+            .and_then(|num| num.parse().ok())
+            .map(JsonValue::Number)
+            .ok_or_else(|| format!("invalid number at offset {}", start))
+    }
+}
+
+/// Element types supported in safetensors files.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum DType {
+    F32,
+    F16,
+    BF16,
+}
+
+impl DType {
+    fn size(self) -> usize {
+        match self {
+            DType::F32 => 4,
+            DType::F16 | DType::BF16 => 2,
+        }
+    }
+}
+
+struct TensorInfo {
+    dtype: DType,
+    shape: Vec<usize>,
+    data_start: usize,
+}
+
+/// A parsed safetensors file.
+pub struct SafeTensors {
+    data: Vec<u8>,
+    tensors: HashMap<String, TensorInfo>,
+}
+
+impl SafeTensors {
+    /// Parse the contents of a `.safetensors` file.
+    pub fn parse(data: Vec<u8>) -> Result<SafeTensors, String> {
+        let header_len = data
+            .get(..8)
+            .map(|len| u64::from_le_bytes(len.try_into().unwrap()) as usize)
+            .ok_or("file is too small")?;
+        let header = data
+            .get(8..8usize.saturating_add(header_len))
+            .ok_or("header is out of bounds")?;
+        let header = JsonParser::new(header).value()?;
+        let JsonValue::Object(entries) = header else {
+            return Err("header is not an object".into());
+        };
+
+        let data_start = 8 + header_len;
+        let mut tensors = HashMap::new();
+        for (name, info) in entries {
+            if name == "__metadata__" {
+                continue;
+            }
+            let invalid = || format!("invalid header entry for tensor \"{}\"", name);
+
+            let dtype = match info.get("dtype") {
+                Some(JsonValue::String(dtype)) => match dtype.as_str() {
+                    "F32" => DType::F32,
+                    "F16" => DType::F16,
+                    "BF16" => DType::BF16,
+                    _ => {
+                        return Err(format!(
+                            "unsupported dtype {} for tensor \"{}\"",
+                            dtype, name
+                        ))
+                    }
+                },
+                _ => return Err(invalid()),
+            };
+            let shape = match info.get("shape") {
+                Some(JsonValue::Array(dims)) => dims
+                    .iter()
+                    .map(|dim| dim.as_usize())
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(invalid)?,
+                _ => return Err(invalid()),
+            };
+            let (start, end) = match info.get("data_offsets") {
+                Some(JsonValue::Array(offsets)) => match offsets.as_slice() {
+                    [start, end] => (
+                        start.as_usize().ok_or_else(invalid)?,
+                        end.as_usize().ok_or_else(invalid)?,
+                    ),
+                    _ => return Err(invalid()),
+                },
+                _ => return Err(invalid()),
+            };
+
+            let len = shape.iter().product::<usize>() * dtype.size();
+            if end.checked_sub(start) != Some(len) || data_start + end > data.len() {
+                return Err(format!("data for tensor \"{}\" is out of bounds", name));
+            }
+
+            tensors.insert(
+                name,
+                TensorInfo {
+                    dtype,
+                    shape,
+                    data_start: data_start + start,
+                },
+            );
+        }
+
+        Ok(SafeTensors { data, tensors })
+    }
+
+    /// Return an iterator over the names of tensors in the file.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.tensors.keys().map(|name| name.as_str())
+    }
+
+    /// Read a tensor and convert it to `f32`.
+    pub fn tensor(&self, name: &str) -> Option<Tensor<f32>> {
+        let info = self.tensors.get(name)?;
+        let len: usize = info.shape.iter().product();
+        let bytes = &self.data[info.data_start..info.data_start + len * info.dtype.size()];
+        let elements: Vec<f32> = match info.dtype {
+            DType::F32 => bytes
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+                .collect(),
+            DType::F16 => bytes
+                .chunks_exact(2)
+                .map(|b| f16_to_f32(u16::from_le_bytes(b.try_into().unwrap())))
+                .collect(),
+            DType::BF16 => bytes
+                .chunks_exact(2)
+                .map(|b| f32::from_bits((u16::from_le_bytes(b.try_into().unwrap()) as u32) << 16))
+                .collect(),
+        };
+        Some(Tensor::from_data(&info.shape, elements))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use rten_tensor::prelude::*;
+    use rten_tensor::Tensor;
+
+    use super::{JsonParser, JsonValue, SafeTensors};
+
+    /// Serialize `f32` tensors in safetensors format.
+    pub fn write_safetensors(tensors: &[(&str, &Tensor<f32>)]) -> Vec<u8> {
+        let mut header = String::from("{\"__metadata__\":{\"format\":\"pt\"}");
+        let mut data = Vec::new();
+        for (name, tensor) in tensors {
+            let shape: Vec<String> = tensor.shape().iter().map(|d| d.to_string()).collect();
+            let start = data.len();
+            data.extend(tensor.iter().flat_map(|x| x.to_le_bytes()));
+            header += &format!(
+                ",\"{}\":{{\"dtype\":\"F32\",\"shape\":[{}],\"data_offsets\":[{},{}]}}",
+                name,
+                shape.join(","),
+                start,
+                data.len()
+            );
+        }
+        header.push('}');
+
+        let mut buf = (header.len() as u64).to_le_bytes().to_vec();
+        buf.extend(header.as_bytes());
+        buf.extend(data);
+        buf
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let json =
+            r#" {"a": [1, 2.5, -3e2], "b": {"c": "x\"é"}, "d": [true, false, null], "e": []} "#;
+        let value = JsonParser::new(json.as_bytes()).value().unwrap();
+        assert_eq!(
+            value,
+            JsonValue::Object(vec![
+                (
+                    "a".into(),
+                    JsonValue::Array(vec![
+                        JsonValue::Number(1.),
+                        JsonValue::Number(2.5),
+                        JsonValue::Number(-300.)
+                    ])
+                ),
+                (
+                    "b".into(),
+                    JsonValue::Object(vec![("c".into(), JsonValue::String("x\"é".into()))])
+                ),
+                (
+                    "d".into(),
+                    JsonValue::Array(vec![
+                        JsonValue::Bool(true),
+                        JsonValue::Bool(false),
+                        JsonValue::Null
+                    ])
+                ),
+                ("e".into(), JsonValue::Array(vec![])),
+            ])
+        );
+
+        let nested = |depth| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(JsonParser::new(nested(32).as_bytes()).value().is_ok());
+        let err = JsonParser::new(nested(100_000).as_bytes())
+            .value()
+            .err()
+            .unwrap();
+        assert_eq!(err, "header nesting is too deep at offset 32");
+    }
+
+    #[test]
+    fn test_parse_safetensors() {
+        let x = Tensor::from_data(&[2, 3], vec![1., 2., 3., 4., 5., 6.]);
+        let y = Tensor::from_data(&[1], vec![7.]);
+        let buf = write_safetensors(&[("x", &x), ("y", &y)]);
+
+        let st = SafeTensors::parse(buf.clone()).unwrap();
+        let mut names: Vec<_> = st.names().collect();
+        names.sort();
+        assert_eq!(names, ["x", "y"]);
+        assert_eq!(st.tensor("x"), Some(x));
+        assert_eq!(st.tensor("y"), Some(y));
+        assert_eq!(st.tensor("z"), None);
+
+        let err = SafeTensors::parse(buf[..buf.len() - 1].to_vec()).err();
+        assert_eq!(
+            err.as_deref(),
+            Some("data for tensor \"y\" is out of bounds")
+        );
+    }
+}
@@ -17,6 +17,7 @@ use crate::constant_storage::{ArcSlice, ArcTensorView, ConstantStorage};
 use crate::env::str_as_bool;
 use crate::graph::{ConstantNodeData, Dimension, Graph, Node, NodeId, RunError, RunOptions};
 use crate::header::{Header, HeaderError};
+use crate::lora::{LoraAdapter, LoraError};
 use crate::model_metadata::ModelMetadata;
 use crate::number::{LeBytes, Pod};
 use crate::op_registry::{OpLoadContext, OpRegistry, ReadOpError};
@@ -155,6 +156,7 @@ fn parse_timing_config(config: &str, opts: &mut RunOptions) {
 pub struct ModelOptions {
     registry: OpRegistry,
     optimize: bool,
+    adapters: Vec<LoraAdapter>,
 }
 
 impl ModelOptions {
@@ -171,6 +173,7 @@ impl ModelOptions {
         ModelOptions {
             registry: ops,
             optimize: true,
+            adapters: Vec::new(),
         }
     }
 
@@ -180,6 +183,16 @@ impl ModelOptions {
         self
     }
 
+    /// Merge a LoRA adapter into the model's weights when it is loaded.
+    ///
+    /// Adapters are applied in the order they are added. See
+    /// [`LoraAdapter`] for details of how the adapter's layers are matched
+    /// to the model's weights.
+    pub fn add_lora(&mut self, adapter: LoraAdapter) -> &mut Self {
+        self.adapters.push(adapter);
+        self
+    }
+
     /// Load the model from a file. See [`Model::load_file`].
     pub fn load_file<P: AsRef<Path>>(&self, path: P) -> Result<Model, ModelLoadError> {
         let data = std::fs::read(path).map_err(ModelLoadError::ReadFailed)?;
@@ -297,6 +310,7 @@ impl Model {
             storage.clone(),
             tensor_data_offset,
             options.optimize,
+            &options.adapters,
         )?;
 
         let metadata = model
@@ -314,6 +328,7 @@ impl Model {
         storage: Arc<ConstantStorage>,
         tensor_data_offset: Option<u64>,
         optimize: bool,
+        adapters: &[LoraAdapter],
     ) -> Result<Graph, ModelLoadError> {
         let node_count = serialized_graph.nodes().map(|ns| ns.len()).unwrap_or(0);
 
@@ -339,8 +354,17 @@ impl Model {
             graph.set_captures(&captures);
         }
 
+        // Adapters are only applied to the main graph. See notes on
+        // `LoraAdapter`.
         let load_subgraph = |g: sg::Graph| -> Result<Graph, ModelLoadError> {
-            Self::load_graph(g, registry, storage.clone(), tensor_data_offset, optimize)
+            Self::load_graph(
+                g,
+                registry,
+                storage.clone(),
+                tensor_data_offset,
+                optimize,
+                &[],
+            )
         };
 
         if let Some(nodes) = serialized_graph.nodes() {
@@ -371,6 +395,14 @@ impl Model {
             }
         }
 
+        // Adapters are merged before optimization, as that may fuse or rename
+        // the weights that the adapter modifies.
+        for adapter in adapters {
+            adapter
+                .merge(&mut graph)
+                .map_err(ModelLoadError::AdapterError)?;
+        }
+
         if optimize {
             let optimizer = GraphOptimizer::new();
             optimizer
@@ -672,6 +704,10 @@ pub enum ModelLoadError {
 
     /// The file's header is invalid.
     InvalidHeader(Box<dyn Error + Send + Sync>),
+
+    /// An error occurred while merging a LoRA adapter into the model's
+    /// weights.
+    AdapterError(LoraError),
 }
 
 impl Display for ModelLoadError {
@@ -684,6 +720,7 @@ impl Display for ModelLoadError {
             ModelLoadError::GraphError(e) => write!(f, "graph error: {e}"),
             ModelLoadError::OptimizeError(e) => write!(f, "graph optimization error: {e}"),
             ModelLoadError::InvalidHeader(e) => write!(f, "invalid header: {e}"),
+            ModelLoadError::AdapterError(e) => write!(f, "adapter error: {e}"),
         }
     }
 }
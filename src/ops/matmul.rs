@@ -1,10 +1,8 @@
-use rayon::prelude::*;
-
 use rten_tensor::prelude::*;
 use rten_tensor::{Tensor, TensorView};
 
 use crate::check_dims;
-use crate::gemm::{GemmExecutor, GemmInputA, GemmInputB};
+use crate::gemm::{Activation, BiasVector, GemmExecutor, GemmInputA, GemmInputB};
 use crate::ops::binary_elementwise::broadcast_shapes;
 use crate::ops::layout::expand_to;
 use crate::ops::{InputList, IntoOpResult, OpError, Operator, OutputList};
@@ -117,7 +115,32 @@ enum MatmulStrategy {
 }
 
 pub fn matmul(pool: &TensorPool, a: TensorView, b: TensorView) -> Result<Tensor, OpError> {
-    matmul_impl(pool, a, b, MatmulStrategy::Auto)
+    matmul_impl(pool, a, b, MatmulStrategy::Auto, None, None)
+}
+
+/// Compute `activation(matmul(a, b) + bias)`, applying the bias and
+/// activation to the output of the matrix multiplication as it is produced.
+///
+/// `bias`, if present, must be a vector whose length matches the number of
+/// columns in `b`.
+pub fn fused_matmul(
+    pool: &TensorPool,
+    a: TensorView,
+    b: TensorView,
+    bias: Option<TensorView>,
+    activation: Option<Activation>,
+) -> Result<Tensor, OpError> {
+    let Some(bias) = bias else {
+        return matmul_impl(pool, a, b, MatmulStrategy::Auto, None, activation);
+    };
+
+    if bias.ndim() != 1 || b.ndim() < 2 || bias.size(0) != b.size(b.ndim() - 1) {
+        return Err(OpError::IncompatibleInputShapes(
+            "Bias length does not match columns of second matrix",
+        ));
+    }
+    let bias = bias.to_contiguous_in(pool).auto_return(pool);
+    matmul_impl(pool, a, b, MatmulStrategy::Auto, bias.data(), activation)
 }
 
 fn matmul_impl(
@@ -125,6 +148,8 @@ fn matmul_impl(
     a: TensorView,
     b: TensorView,
     strategy: MatmulStrategy,
+    bias: Option<&[f32]>,
+    activation: Option<Activation>,
 ) -> Result<Tensor, OpError> {
     if a.ndim() < 2 || b.ndim() < 2 {
         return Err(OpError::InvalidValue("Inputs must have >= 2 dimensions"));
@@ -163,7 +188,7 @@ fn matmul_impl(
         // nb. We assume `a` is likely already contiguous, so this will be cheap.
         let a_contig = a.to_contiguous_in(pool).auto_return(pool);
         let a_matrix = a_contig.reshaped([num_a_matrices * a_rows, a_cols].as_slice());
-        let mut output = matmul(pool, a_matrix, b.clone())?;
+        let mut output = matmul_impl(pool, a_matrix, b.clone(), strategy, bias, activation)?;
         output.reshape(out_shape);
         return Ok(output);
     }
@@ -175,64 +200,18 @@ fn matmul_impl(
         return Ok(Tensor::zeros(out_shape));
     }
 
-    let a_broadcast_shape = [out_prefix.as_slice(), &[a_rows, a_cols]].concat();
-    let b_broadcast_shape = [out_prefix.as_slice(), &[b_rows, b_cols]].concat();
-
-    let a_broadcast = a.broadcast(a_broadcast_shape.as_slice());
-    let b_broadcast = b.broadcast(b_broadcast_shape.as_slice());
-
-    let out_row_stride = output.stride(output.ndim() - 2);
-    let out_batches = output
-        .data_mut()
-        .unwrap()
-        .chunks_mut(out_row_stride * a_rows);
-
     let gemm = GemmExecutor::new();
-
-    // Prepack re-used inputs to amortize packing cost.
-    //
-    // We don't prepack when the "A" matrix is a vector because that uses a
-    // special case vector-matrix algorithm that doesn't benefit from packing.
-    let prepacked_a = (num_a_matrices == 1 && num_b_matrices > 1 && a_rows > 1).then(|| {
-        let a_matrix = a.inner_iter::<2>().next().unwrap();
-        gemm.prepack_a_in(pool, a_matrix).auto_return(pool)
-    });
-    let prepacked_a = prepacked_a.as_deref();
-
-    let prepacked_b = (num_a_matrices > 1 && num_b_matrices == 1 && a_rows > 1).then(|| {
-        let b_matrix = b.inner_iter::<2>().next().unwrap();
-        gemm.prepack_b_in(pool, b_matrix).auto_return(pool)
-    });
-    let prepacked_b = prepacked_b.as_deref();
-
-    a_broadcast
-        .inner_iter::<2>()
-        .zip(b_broadcast.inner_iter::<2>())
-        .zip(out_batches)
-        .par_bridge()
-        .for_each(|((a_mat, b_mat), out_mat)| {
-            let a_input = if let Some(packed) = prepacked_a {
-                GemmInputA::Packed(packed)
-            } else {
-                GemmInputA::Unpacked(a_mat)
-            };
-
-            let b_input = if let Some(packed) = prepacked_b {
-                GemmInputB::Packed(packed)
-            } else {
-                GemmInputB::Unpacked(b_mat)
-            };
-
-            gemm.gemm_uninit(
-                out_mat,
-                out_row_stride,
-                a_input,
-                b_input,
-                1., // alpha
-            );
-        });
-
-    // Safety: Loop above initialized all output elements.
+    gemm.batched_gemm_uninit(
+        pool,
+        output.data_mut().unwrap(),
+        a,
+        b,
+        1., /* alpha */
+        bias.map(BiasVector::Row),
+        activation,
+    );
+
+    // Safety: `batched_gemm_uninit` initialized all output elements.
     let output = unsafe { output.assume_init() };
 
     Ok(output)
@@ -253,6 +232,30 @@ impl Operator for MatMul {
     }
 }
 
+/// MatMul with a fused bias addition and activation.
+///
+/// This computes `activation(MatMul(A, B) + bias)` where the optional `bias`
+/// input is a vector with one element per column of `B`. It is not an ONNX
+/// operator, but is created by the graph optimizer from subgraphs with this
+/// structure.
+#[derive(Clone, Debug)]
+pub struct FusedMatMul {
+    pub activation: Option<Activation>,
+}
+
+impl Operator for FusedMatMul {
+    fn name(&self) -> &str {
+        "FusedMatMul"
+    }
+
+    fn run(&self, pool: &TensorPool, inputs: InputList) -> Result<OutputList, OpError> {
+        let a = inputs.require_as(0)?;
+        let b = inputs.require_as(1)?;
+        let bias = inputs.get_as(2)?;
+        fused_matmul(pool, a, b, bias, self.activation).into_op_result()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::error::Error;
@@ -263,11 +266,12 @@ mod tests {
     use rten_tensor::test_util::expect_equal;
     use rten_tensor::{Tensor, TensorView, TensorViewMut};
 
-    use crate::gemm::gemm;
+    use crate::gemm::{gemm, Activation};
+    use crate::ops::add;
     use crate::ops::tests::new_pool;
     use crate::tensor_pool::AutoReturn;
 
-    use super::{gemm_op, matmul, matmul_impl, MatmulStrategy, OpError};
+    use super::{fused_matmul, gemm_op, matmul, matmul_impl, MatmulStrategy, OpError};
 
     fn gemm_tensors(c: &mut Tensor, a: &Tensor, b: &Tensor, alpha: f32, beta: f32) {
         c.make_contiguous();
@@ -532,6 +536,56 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_fused_matmul() -> Result<(), Box<dyn Error>> {
+        let pool = new_pool();
+        let mut rng = XorShiftRng::new(1234);
+
+        // Cover the flattened batch, batched and vector-matrix paths.
+        for (a_shape, b_shape) in [
+            (&[2, 3, 10][..], &[10, 8][..]),
+            (&[3, 10][..], &[2, 10, 8][..]),
+            (&[1, 10][..], &[10, 8][..]),
+        ] {
+            let a = Tensor::rand(a_shape, &mut rng).map(|x| x - 0.5);
+            let b = Tensor::rand(b_shape, &mut rng).map(|x| x - 0.5);
+            let bias = Tensor::rand(&[8], &mut rng).map(|x| x - 0.5);
+
+            let matmul_out = matmul(&pool, a.view(), b.view())?;
+
+            let result = fused_matmul(&pool, a.view(), b.view(), Some(bias.view()), None)?;
+            let expected = add(&pool, matmul_out.view(), bias.view())?;
+            expect_equal(&result, &expected)?;
+
+            let result = fused_matmul(
+                &pool,
+                a.view(),
+                b.view(),
+                Some(bias.view()),
+                Some(Activation::Relu),
+            )?;
+            let expected = expected.map(|x| x.max(0.));
+            expect_equal(&result, &expected)?;
+
+            let result = fused_matmul(&pool, a.view(), b.view(), None, Some(Activation::Relu))?;
+            let expected = matmul_out.map(|x| x.max(0.));
+            expect_equal(&result, &expected)?;
+        }
+
+        let a = Tensor::rand(&[3, 10], &mut rng);
+        let b = Tensor::rand(&[10, 8], &mut rng);
+        let bias = Tensor::rand(&[7], &mut rng);
+        let result = fused_matmul(&pool, a.view(), b.view(), Some(bias.view()), None);
+        assert_eq!(
+            result.err(),
+            Some(OpError::IncompatibleInputShapes(
+                "Bias length does not match columns of second matrix"
+            ))
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_matmul_zero_sized_dim() {
         struct Case {
@@ -603,7 +657,7 @@ mod tests {
                 );
                 let pool = new_pool();
                 run_bench(trials, Some(&desc), || {
-                    matmul_impl(&pool, a.view(), b.view(), strategy)
+                    matmul_impl(&pool, a.view(), b.view(), strategy, None, None)
                         .unwrap()
                         .auto_return(&pool);
                 });
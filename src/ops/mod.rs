@@ -81,7 +81,7 @@ pub use layout::{
     expand, flatten, reshape, squeeze, squeeze_in_place, Expand, Flatten, Reshape, Shape, Size,
     Squeeze, Transpose, Unsqueeze,
 };
-pub use matmul::{gemm_op, matmul, Gemm, MatMul};
+pub use matmul::{fused_matmul, gemm_op, matmul, FusedMatMul, Gemm, MatMul};
 pub use non_max_suppression::{non_max_suppression, BoxOrder, NonMaxSuppression};
 pub use norm::{
     batch_norm, batch_norm_in_place, instance_normalization, layer_normalization, log_softmax,
@@ -121,6 +121,8 @@ pub use unary_elementwise::{
 };
 pub use variadic_elementwise::{max, mean, min, sum, Max, Mean, Min, Sum};
 
+pub(crate) use binary_elementwise::broadcast_shapes;
+
 mod operators;
 pub use operators::{FloatOperators, Operators};
 
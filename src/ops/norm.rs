@@ -2,7 +2,7 @@ use rayon::prelude::*;
 
 use rten_tensor::prelude::*;
 use rten_tensor::{NdTensorView, Tensor, TensorView};
-use rten_vecmath::vec_softmax_in_place;
+use rten_vecmath::{vec_layer_norm_in_place, vec_softmax_in_place};
 use smallvec::SmallVec;
 
 use crate::ops::reduce::reduce_inverse_rms;
@@ -265,6 +265,28 @@ pub fn layer_normalization(
 
     let epsilon = epsilon.unwrap_or(1e-5);
     let resolved_axis = resolve_axis(input.ndim(), axis)?;
+
+    // Fast path for the common case of normalizing over the last axis, with
+    // per-element scale and bias.
+    if !input.is_empty() && resolved_axis == input.ndim() - 1 {
+        let lane_size = input.size(resolved_axis);
+        let is_lane_vec = |x: &TensorView| {
+            x.len() == lane_size && x.ndim() > 0 && x.size(x.ndim() - 1) == lane_size
+        };
+        if is_lane_vec(&scale) && bias.as_ref().map(is_lane_vec).unwrap_or(true) {
+            let scale = scale.to_contiguous_in(pool).auto_return(pool);
+            let scale = scale.data();
+            let bias = bias.map(|b| b.to_contiguous_in(pool).auto_return(pool));
+            let bias = bias.as_ref().and_then(|b| b.data());
+
+            let mut output = input.to_tensor_in(pool);
+            softmax_lanes(&mut output, axis, |lane| {
+                vec_layer_norm_in_place(lane, scale, bias, epsilon)
+            })?;
+            return Ok(output);
+        }
+    }
+
     let normalized_axes: SmallVec<[i32; 5]> = (resolved_axis..input.ndim())
         .map(|axis| axis as i32)
         .collect();
@@ -665,6 +687,21 @@ mod tests {
         ]]);
         expect_eq_1e4(&result, &expected)?;
 
+        // Scale and bias that need broadcasting use the general path. The
+        // result should match the fast path.
+        let scale_bcast = Tensor::from([[0.0751, 0.6952]; 5]);
+        let bias_bcast = Tensor::from([[0.9993, 0.7632]; 5]);
+        let result = layer_normalization(
+            &pool,
+            input.view(),
+            scale_bcast.view(),
+            Some(bias_bcast.view()),
+            -1,   /* axis */
+            None, /* epsilon */
+        )
+        .unwrap();
+        expect_eq_1e4(&result, &expected)?;
+
         Ok(())
     }
 
@@ -1,20 +1,24 @@
 use std::error::Error;
 use std::fmt::{Display, Formatter};
 
+use rten_tensor::prelude::*;
 use rten_tensor::Tensor;
 use rustc_hash::FxHashMap;
 
 use crate::downcast::DowncastDyn;
+use crate::gemm::Activation;
 use crate::graph::{
     Constant, ConstantNode, Graph, Node, NodeId, OperatorNode, RunError, TypedConstant,
 };
 use crate::ops::fused::FusedTranspose;
-use crate::ops::{Gelu, LayerNormalization, Operator, ReduceMean, Silu, Transpose};
+use crate::ops::{
+    FusedMatMul, Gelu, Input, LayerNormalization, Operator, ReduceMean, Silu, Transpose,
+};
 use crate::Output;
 
 mod pattern_matcher;
 
-use pattern_matcher::{binary_op, const_symbol, symbol, unary_op, unary_op_key};
+use pattern_matcher::{binary_op, const_symbol, operator, symbol, unary_op, unary_op_key};
 
 /// Errors that occur while applying graph optimizations.
 #[derive(Debug, PartialEq)]
@@ -292,6 +296,7 @@ impl GraphOptimizer {
         self.fuse_silu(&mut graph_mut)?;
         self.fuse_gelu(&mut graph_mut)?;
         self.fuse_layer_norm(&mut graph_mut)?;
+        self.fuse_matmul_epilogue(&mut graph_mut)?;
 
         Ok(graph_mut.finalize_graph())
     }
@@ -516,6 +521,102 @@ impl GraphOptimizer {
 
         Ok(())
     }
+
+    /// Fuse `Activation(MatMul(A, B) + bias)` into `FusedMatMul(A, B, bias)`.
+    ///
+    /// Subgraphs with only the bias addition or only the activation are also
+    /// fused. This allows the bias and activation to be applied to the
+    /// output of the matrix multiplication while it is in cache, instead of
+    /// in separate passes.
+    fn fuse_matmul_epilogue(&self, graph: &mut GraphMutator) -> Result<(), OptimizeError> {
+        let matmul_pat = operator("MatMul", [symbol("a"), symbol("b")], Some("matmul"));
+        let matmul_bias_pat = matmul_pat.clone() + const_symbol("bias");
+
+        // Return the activation corresponding to an operator, if it is one
+        // that can be fused.
+        let activation = |op_node: &OperatorNode| match op_node.operator().name() {
+            "Relu" => Some(Activation::Relu),
+            "Gelu" => Some(Activation::Gelu),
+            "Silu" => Some(Activation::Silu),
+            _ => None,
+        };
+
+        // Test if a value is used only as an input to a single operator, so
+        // that it doesn't need to be computed if that operator is fused.
+        let is_internal_value = |graph: &GraphMutator, value_id| {
+            graph.find_operator_with_input(value_id).is_some()
+                && !graph.output_ids().contains(&value_id)
+        };
+
+        // Test if `bias` is a vector which matches the number of columns of a
+        // constant `b` matrix. In other cases `Add` may broadcast differently
+        // than the fused operator.
+        let is_valid_bias = |graph: &GraphMutator, bias, b| {
+            let const_shape = |id| match graph.graph().get_node(id) {
+                Some(Node::Constant(val)) => match val.as_input() {
+                    Input::FloatTensor(t) => Some(t.shape().to_vec()),
+                    Input::IntTensor(_) => None,
+                },
+                _ => None,
+            };
+            match (const_shape(bias).as_deref(), const_shape(b).as_deref()) {
+                (Some(&[bias_len]), Some([.., _, b_cols])) => bias_len == *b_cols,
+                _ => false,
+            }
+        };
+
+        graph.apply_fusion(|graph, op_node_id, op_node| {
+            let op_output = op_node.output_id()?;
+
+            // Match either an activation with a `MatMul(A, B) + bias` or
+            // `MatMul(A, B)` input, or a `MatMul(A, B) + bias` subgraph whose
+            // output is not followed by an activation.
+            let (activation, pat_match) = if let Some(activation) = activation(op_node) {
+                let [Some(act_input)] = op_node.input_ids() else {
+                    return None;
+                };
+                if !is_internal_value(graph, *act_input) {
+                    return None;
+                }
+                let pat_match = matmul_bias_pat
+                    .test(*act_input, graph.graph())
+                    .or_else(|| matmul_pat.test(*act_input, graph.graph()))?;
+                (Some(activation), pat_match)
+            } else {
+                let pat_match = matmul_bias_pat.test(op_node_id, graph.graph())?;
+                let followed_by_activation = graph
+                    .find_operator_with_input(op_output)
+                    .is_some_and(|next_op| activation(next_op).is_some());
+                if followed_by_activation {
+                    return None;
+                }
+                (None, pat_match)
+            };
+
+            let a = pat_match.resolved_symbol("a").unwrap();
+            let b = pat_match.resolved_symbol("b").unwrap();
+            let bias = pat_match.resolved_symbol("bias");
+            let matmul_output = match graph.graph().get_node(pat_match.resolved_symbol("matmul")?) {
+                Some(Node::Operator(matmul_op)) => matmul_op.output_id(),
+                _ => None,
+            }?;
+
+            if let Some(bias) = bias {
+                if !is_internal_value(graph, matmul_output) || !is_valid_bias(graph, bias, b) {
+                    return None;
+                }
+            }
+
+            Some(Fusion::from_op(
+                op_node.name(),
+                FusedMatMul { activation },
+                vec![Some(a), Some(b), bias],
+                op_output,
+            ))
+        });
+
+        Ok(())
+    }
 }
 
 impl Default for GraphOptimizer {
@@ -532,10 +633,11 @@ mod tests {
 
     use super::{GraphOptimizer, OptimizeError};
     use crate::downcast::DowncastDyn;
+    use crate::gemm::Activation;
     use crate::graph::{Constant, Graph, Node};
     use crate::ops::{
-        Add, Div, Erf, LayerNormalization, MatMul, Mul, Pow, ReduceMean, Sigmoid, Sqrt, Sub,
-        Transpose,
+        Add, Div, Erf, FusedMatMul, LayerNormalization, MatMul, Mul, Pow, ReduceMean, Relu,
+        Sigmoid, Sqrt, Sub, Transpose,
     };
 
     fn optimize_graph(graph: Graph) -> Result<Graph, OptimizeError> {
@@ -660,6 +762,67 @@ mod tests {
         assert_eq!(op.name(), Some("mul_half"));
     }
 
+    #[test]
+    fn test_fuse_matmul_epilogue() {
+        let weights = Tensor::from([[1., 2.], [3., 4.], [5., 6.]]);
+        let bias_vec = Tensor::from([0.5, -0.5]);
+
+        // Bias and activation.
+        let mut graph = Graph::new();
+        let input = graph.add_value(None, None);
+        let weights_id = graph.add_constant(None, weights.clone());
+        let bias = graph.add_constant(None, bias_vec.clone());
+        let (_, matmul_out) = graph.add_simple_op("matmul", MatMul {}, &[input, weights_id]);
+        let (_, add_out) = graph.add_simple_op("add", Add {}, &[bias, matmul_out]);
+        let (_, relu_out) = graph.add_simple_op("relu", Relu {}, &[add_out]);
+        graph.set_input_ids(&[input]);
+        graph.set_output_ids(&[relu_out]);
+
+        let graph = optimize_graph(graph).unwrap();
+        let (_, op) = graph.get_source_node(graph.output_ids()[0]).unwrap();
+        assert_eq!(op.operator().name(), "FusedMatMul");
+        assert_eq!(op.name(), Some("relu"));
+        assert_eq!(op.input_ids(), &[Some(input), Some(weights_id), Some(bias)]);
+        let fused_op = op.operator().downcast_ref::<FusedMatMul>().unwrap();
+        assert_eq!(fused_op.activation, Some(Activation::Relu));
+
+        // Bias only.
+        let mut graph = Graph::new();
+        let input = graph.add_value(None, None);
+        let weights_id = graph.add_constant(None, weights.clone());
+        let bias = graph.add_constant(None, bias_vec.clone());
+        let (_, matmul_out) = graph.add_simple_op("matmul", MatMul {}, &[input, weights_id]);
+        let (_, add_out) = graph.add_simple_op("add", Add {}, &[matmul_out, bias]);
+        graph.set_input_ids(&[input]);
+        graph.set_output_ids(&[add_out]);
+
+        let graph = optimize_graph(graph).unwrap();
+        let (_, op) = graph.get_source_node(graph.output_ids()[0]).unwrap();
+        assert_eq!(op.operator().name(), "FusedMatMul");
+        let fused_op = op.operator().downcast_ref::<FusedMatMul>().unwrap();
+        assert_eq!(fused_op.activation, None);
+
+        // Bias which doesn't match the weights, and MatMul output which is
+        // used elsewhere. These should not be fused.
+        let mut graph = Graph::new();
+        let input = graph.add_value(None, None);
+        let weights_id = graph.add_constant(None, weights.clone());
+        let scalar_bias = graph.add_constant(None, Tensor::from([0.5]));
+        let (_, matmul_out) = graph.add_simple_op("matmul", MatMul {}, &[input, weights_id]);
+        let (_, add_out) = graph.add_simple_op("add", Add {}, &[matmul_out, scalar_bias]);
+        let (_, matmul_2_out) = graph.add_simple_op("matmul_2", MatMul {}, &[input, weights_id]);
+        let bias = graph.add_constant(None, bias_vec);
+        let (_, add_2_out) = graph.add_simple_op("add_2", Add {}, &[matmul_2_out, bias]);
+        graph.set_input_ids(&[input]);
+        graph.set_output_ids(&[add_out, add_2_out, matmul_2_out]);
+
+        let graph = optimize_graph(graph).unwrap();
+        for output in graph.output_ids() {
+            let (_, op) = graph.get_source_node(*output).unwrap();
+            assert_ne!(op.operator().name(), "FusedMatMul");
+        }
+    }
+
     fn layer_norm_graph() -> Graph {
         let mut graph = Graph::new();
         let input = graph.add_value(None, None);
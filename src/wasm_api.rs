@@ -1,6 +1,9 @@
+//! JavaScript API for WebAssembly builds, generated using wasm-bindgen.
+
 use std::borrow::Borrow;
 use std::iter::zip;
 use std::rc::Rc;
+use std::sync::Arc;
 
 use rten_tensor::prelude::*;
 use rten_tensor::rng::XorShiftRng;
@@ -13,7 +16,17 @@ use crate::tensor_pool::TensorPool;
 
 #[wasm_bindgen]
 pub struct Model {
-    model: model::Model,
+    model: Arc<model::Model>,
+}
+
+impl Model {
+    /// Return a shared reference to the underlying model.
+    ///
+    /// This allows other crates which add WebAssembly bindings, such as
+    /// rten-wasm, to accept a `Model` created from JS.
+    pub fn shared_model(&self) -> Arc<model::Model> {
+        self.model.clone()
+    }
 }
 
 #[wasm_bindgen]
@@ -22,7 +35,9 @@ impl Model {
     #[wasm_bindgen(constructor)]
     pub fn new(model_data: Vec<u8>) -> Result<Model, String> {
         let model = model::Model::load(model_data).map_err(|e| e.to_string())?;
-        Ok(Model { model })
+        Ok(Model {
+            model: Arc::new(model),
+        })
     }
 
     /// Find the ID of a node in the graph from its name.